@@ -0,0 +1,383 @@
+//! Client for MDBY databases served over HTTP (`mdby serve`)
+//!
+//! [`Client`] runs MDQL on a remote database with the calls of
+//! `mdby::Database`, so code can move between a local database and a server:
+//! [`execute`](Client::execute) sends one statement to `POST /query`, and
+//! [`execute_script`](Client::execute_script) and
+//! [`execute_batch`](Client::execute_batch) send several to `POST /batch`,
+//! which runs them as one transaction.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! let client = mdby_client::Client::new("http://127.0.0.1:7300");
+//! if let mdby_client::QueryResult::Documents(docs) =
+//!     client.execute("SELECT * FROM todos WHERE done = false").await?
+//! {
+//!     for doc in docs {
+//!         println!("{}: {}", doc.id, doc.fields["title"]);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Results have the variants of `mdby::QueryResult`, with JSON in place of
+//! the database's own types (values, DESCRIBE and EXPLAIN output). The server
+//! names the kind of each result, since some share their JSON: an empty list
+//! could be documents, collections or views. Errors the server reports are
+//! [`Error::Server`], with its status and hint.
+
+use serde_json::{Map, Value};
+
+/// Response header naming the kind of a `POST /query` result
+const RESULT_KIND: &str = "mdby-result";
+
+/// Errors from a remote database
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The server refused the request, or the statement failed
+    #[error("{message}")]
+    Server {
+        status: u16,
+        message: String,
+        hint: Option<String>,
+    },
+
+    #[error("Can't reach {url}: {message}")]
+    Unreachable { url: String, message: String },
+
+    #[error("Unexpected response from {url}: {message}")]
+    InvalidResponse { url: String, message: String },
+}
+
+impl Error {
+    /// The server's hint on how to fix the error, if it gave one
+    pub fn suggestion(&self) -> Option<&str> {
+        match self {
+            Error::Server { hint, .. } => hint.as_deref(),
+            Error::Unreachable { .. } => Some("Check the URL, and that `mdby serve` is running there"),
+            Error::InvalidResponse { .. } => None,
+        }
+    }
+}
+
+/// Result of executing a query, as `mdby::QueryResult`
+#[derive(Debug)]
+pub enum QueryResult {
+    /// Documents returned from a SELECT
+    Documents(Vec<Document>),
+    /// The one value of a SELECT that only counts (`SELECT COUNT(*) FROM todos`)
+    Scalar(Value),
+    /// Number of affected documents
+    Affected(usize),
+    /// View created/updated
+    ViewCreated(String),
+    /// Collection created
+    CollectionCreated(String),
+    /// List of collection names (from SHOW COLLECTIONS)
+    Collections(Vec<String>),
+    /// List of view names (from SHOW VIEWS)
+    Views(Vec<String>),
+    /// MDQL that recreates a collection or view (from SHOW CREATE)
+    Definition(String),
+    /// Summary of a collection (from DESCRIBE), as the server's JSON
+    Description(Value),
+    /// How a SELECT would run (from EXPLAIN), as the server's JSON
+    Plan(Value),
+    /// Outcome of each statement of a multi-statement query
+    Batch(BatchResult),
+}
+
+/// A document returned by the server
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Document {
+    pub id: String,
+    /// Frontmatter fields
+    pub fields: Map<String, Value>,
+    /// Markdown body
+    pub body: String,
+}
+
+impl Document {
+    /// A document from its JSON: `id`, the fields, and the body as `_body`
+    fn from_json(json: Value) -> Option<Self> {
+        let Value::Object(mut fields) = json else {
+            return None;
+        };
+        let id = fields.shift_remove("id")?.as_str()?.to_string();
+        let body = match fields.shift_remove("_body") {
+            Some(Value::String(body)) => body,
+            _ => String::new(),
+        };
+        Some(Document { id, fields, body })
+    }
+
+    /// Get a field's value
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        self.fields.get(field)
+    }
+}
+
+/// Outcomes of the statements of a batch, in order
+#[derive(Debug)]
+pub struct BatchResult {
+    pub statements: Vec<StatementOutcome>,
+}
+
+/// One statement of a batch and its result
+#[derive(Debug)]
+pub struct StatementOutcome {
+    /// Line of the script the statement starts on, counting from 1 (for
+    /// [`Client::execute_batch`], its position in the list)
+    pub line: usize,
+    /// The statement, as MDQL
+    pub statement: String,
+    /// The result, or the error the server reported ([`Error::Server`])
+    pub result: anyhow::Result<QueryResult>,
+}
+
+impl BatchResult {
+    /// Number of statements that succeeded
+    pub fn succeeded(&self) -> usize {
+        self.statements.iter().filter(|s| s.result.is_ok()).count()
+    }
+
+    /// Number of statements that failed
+    pub fn failed(&self) -> usize {
+        self.statements.len() - self.succeeded()
+    }
+
+    /// Total documents affected by the statements that succeeded
+    pub fn affected(&self) -> usize {
+        self.statements
+            .iter()
+            .map(|s| match &s.result {
+                Ok(QueryResult::Affected(n)) => *n,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+/// A remote database, served by `mdby serve`
+#[derive(Debug, Clone)]
+pub struct Client {
+    url: String,
+    agent: ureq::Agent,
+}
+
+/// A response: its status, result kind, and JSON body
+struct Reply {
+    status: u16,
+    kind: Option<String>,
+    json: Value,
+}
+
+impl Client {
+    /// A client for the server at `url` (`http://127.0.0.1:7300`)
+    pub fn new(url: impl Into<String>) -> Self {
+        let url = url.into().trim_end_matches('/').to_string();
+        Client { url, agent: ureq::Agent::new() }
+    }
+
+    /// The server's URL
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Execute an MDQL query on the server
+    ///
+    /// As with a local database, a query of several statements runs them in
+    /// order and returns [`QueryResult::Batch`].
+    pub async fn execute(&self, query: &str) -> anyhow::Result<QueryResult> {
+        let reply = self.post("/query", serde_json::json!({ "query": query })).await?;
+        if reply.status >= 400 {
+            return Err(self.error(&reply).into());
+        }
+        let Some(kind) = &reply.kind else {
+            return Err(self.invalid("no MDBY-Result header").into());
+        };
+        Ok(self.parse(kind, reply.json)?)
+    }
+
+    /// Execute a script of statements separated by semicolons as one
+    /// transaction
+    ///
+    /// If a statement fails, nothing the script did is kept, and the
+    /// outcomes end with the failure. A script that doesn't parse runs
+    /// nothing, and is an error.
+    pub async fn execute_script(&self, script: &str) -> anyhow::Result<BatchResult> {
+        let reply = self.post("/batch", serde_json::json!({ "script": script })).await?;
+        Ok(self.batch(reply)?)
+    }
+
+    /// Execute a list of statements as one transaction, like
+    /// [`execute_script`](Self::execute_script)
+    ///
+    /// Each outcome's `line` is the statement's position in the list,
+    /// counting from 1. If any statement doesn't parse, none run.
+    pub async fn execute_batch(&self, statements: &[String]) -> anyhow::Result<BatchResult> {
+        let reply = self.post("/batch", serde_json::json!({ "statements": statements })).await?;
+        Ok(self.batch(reply)?)
+    }
+
+    /// Send a JSON request to `path`, off the async runtime's threads
+    ///
+    /// The server only runs statements sent as JSON (or with a header naming
+    /// them), which pages on other sites can't send without its consent.
+    async fn post(&self, path: &str, body: Value) -> Result<Reply, Error> {
+        let url = format!("{}{}", self.url, path);
+        let request = self
+            .agent
+            .post(&url)
+            .set("Content-Type", "application/json")
+            .set("Accept", "application/json");
+        let body = body.to_string();
+        let response = tokio::task::spawn_blocking(move || request.send_string(&body).map_err(Box::new))
+            .await
+            .map_err(|e| self.unreachable(e))?;
+        let response = match response.map_err(|e| *e) {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => return Err(self.unreachable(e)),
+        };
+        let status = response.status();
+        let kind = response.header(RESULT_KIND).map(str::to_string);
+        let body = response.into_string().map_err(|e| self.unreachable(e))?;
+        let json = serde_json::from_str(&body).map_err(|e| self.invalid(e))?;
+        Ok(Reply { status, kind, json })
+    }
+
+    /// The outcomes of `POST /batch`, which lists them even when a statement
+    /// failed
+    fn batch(&self, reply: Reply) -> Result<BatchResult, Error> {
+        let Some(list) = reply.json.get("statements").and_then(Value::as_array) else {
+            return Err(self.error(&reply));
+        };
+        let mut statements = Vec::new();
+        for outcome in list {
+            let line = outcome.get("line").or_else(|| outcome.get("index")).and_then(Value::as_u64);
+            let statement = outcome.get("statement").and_then(Value::as_str);
+            let (Some(line), Some(statement)) = (line, statement) else {
+                return Err(self.invalid("a statement without its position"));
+            };
+            let result = match (outcome.get("error"), outcome.get("kind").and_then(Value::as_str)) {
+                (Some(error), _) => Err(Error::Server {
+                    status: reply.status,
+                    message: error.as_str().unwrap_or_default().to_string(),
+                    hint: reply.json.get("hint").and_then(Value::as_str).map(str::to_string),
+                }
+                .into()),
+                (None, Some(kind)) => Ok(self.parse(kind, outcome.get("result").cloned().unwrap_or_default())?),
+                (None, None) => return Err(self.invalid("a result without its kind")),
+            };
+            statements.push(StatementOutcome { line: line as usize, statement: statement.to_string(), result });
+        }
+        Ok(BatchResult { statements })
+    }
+
+    /// A result of `kind` from its JSON
+    fn parse(&self, kind: &str, json: Value) -> Result<QueryResult, Error> {
+        let strings = |json: &Value| -> Option<Vec<String>> {
+            json.as_array()?.iter().map(|s| s.as_str().map(str::to_string)).collect()
+        };
+        let string = |json: &Value, key: &str| json.get(key).and_then(Value::as_str).map(str::to_string);
+        let result = match kind {
+            "documents" => match json {
+                Value::Array(docs) => docs
+                    .into_iter()
+                    .map(Document::from_json)
+                    .collect::<Option<Vec<_>>>()
+                    .map(QueryResult::Documents),
+                _ => None,
+            },
+            "scalar" => Some(QueryResult::Scalar(json)),
+            "affected" => json
+                .get("affected")
+                .and_then(Value::as_u64)
+                .map(|n| QueryResult::Affected(n as usize)),
+            "view-created" => string(&json, "name").map(QueryResult::ViewCreated),
+            "collection-created" => string(&json, "name").map(QueryResult::CollectionCreated),
+            "collections" => strings(&json).map(QueryResult::Collections),
+            "views" => strings(&json).map(QueryResult::Views),
+            "definition" => string(&json, "statement").map(QueryResult::Definition),
+            "description" => Some(QueryResult::Description(json)),
+            "plan" => Some(QueryResult::Plan(json)),
+            "batch" => {
+                let reply = Reply { status: 200, kind: None, json };
+                return self.batch(reply).map(QueryResult::Batch);
+            }
+            _ => return Err(self.invalid(format!("unknown result kind '{}'", kind))),
+        };
+        result.ok_or_else(|| self.invalid(format!("a {} result in the wrong shape", kind)))
+    }
+
+    /// The error a failed response reports
+    fn error(&self, reply: &Reply) -> Error {
+        match reply.json.get("error").and_then(Value::as_str) {
+            Some(message) => Error::Server {
+                status: reply.status,
+                message: message.to_string(),
+                hint: reply.json.get("hint").and_then(Value::as_str).map(str::to_string),
+            },
+            None => self.invalid(format!("status {} without an error", reply.status)),
+        }
+    }
+
+    fn unreachable(&self, message: impl std::fmt::Display) -> Error {
+        Error::Unreachable { url: self.url.clone(), message: message.to_string() }
+    }
+
+    fn invalid(&self, message: impl std::fmt::Display) -> Error {
+        Error::InvalidResponse { url: self.url.clone(), message: message.to_string() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_results() {
+        let client = Client::new("http://127.0.0.1:7300/");
+        assert_eq!(client.url(), "http://127.0.0.1:7300");
+        let docs = client
+            .parse("documents", json!([{"id": "a", "title": "First", "_body": "Notes"}, {"id": "b"}]))
+            .unwrap();
+        let QueryResult::Documents(docs) = docs else { panic!("{:?}", docs) };
+        assert_eq!((docs[0].id.as_str(), docs[0].get("title"), docs[0].body.as_str()), ("a", Some(&json!("First")), "Notes"));
+        assert!(docs[1].fields.is_empty());
+
+        // The same JSON is a different result depending on its kind
+        assert!(matches!(client.parse("documents", json!([])), Ok(QueryResult::Documents(docs)) if docs.is_empty()));
+        assert!(matches!(client.parse("views", json!([])), Ok(QueryResult::Views(views)) if views.is_empty()));
+        assert!(matches!(client.parse("affected", json!({"affected": 2})), Ok(QueryResult::Affected(2))));
+        assert!(matches!(
+            client.parse("collection-created", json!({"created": "collection", "name": "todos"})),
+            Ok(QueryResult::CollectionCreated(name)) if name == "todos"
+        ));
+        assert!(matches!(client.parse("affected", json!([])), Err(Error::InvalidResponse { .. })));
+        assert!(matches!(client.parse("rows", json!([])), Err(Error::InvalidResponse { .. })));
+    }
+
+    #[test]
+    fn test_parse_batches() {
+        let client = Client::new("http://127.0.0.1:7300");
+        let json = json!({
+            "statements": [
+                {"index": 1, "statement": "DELETE FROM todos", "kind": "affected", "result": {"affected": 3}},
+                {"index": 2, "statement": "INSERT INTO todos (id) VALUES ('b')", "error": "Missing title"},
+            ],
+            "error": "Statement 2 failed, so none were kept: Missing title",
+        });
+        let batch = client.batch(Reply { status: 400, kind: None, json }).unwrap();
+        assert_eq!((batch.succeeded(), batch.failed(), batch.affected()), (1, 1, 3));
+        let error = batch.statements[1].result.as_ref().unwrap_err();
+        assert!(matches!(error.downcast_ref::<Error>(), Some(Error::Server { status: 400, .. })));
+        assert_eq!(error.to_string(), "Missing title");
+
+        let json = json!({"error": "Statement 1: Parse error", "hint": null});
+        let error = client.batch(Reply { status: 400, kind: None, json }).unwrap_err();
+        assert!(matches!(error, Error::Server { status: 400, ref message, hint: None } if message == "Statement 1: Parse error"));
+    }
+}
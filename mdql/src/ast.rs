@@ -3,6 +3,9 @@
 use serde::{Deserialize, Serialize};
 
 /// A complete MDQL statement
+// SELECT dwarfs the other statements, but statements are parsed one at a time
+// and never stored in bulk, so boxing it would buy nothing
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Statement {
     Select(SelectStmt),
@@ -10,11 +13,20 @@ pub enum Statement {
     Update(UpdateStmt),
     Delete(DeleteStmt),
     CreateCollection(CreateCollectionStmt),
+    AlterCollection(AlterCollectionStmt),
     CreateView(CreateViewStmt),
     DropCollection(String),
     DropView(String),
     ShowCollections,
     ShowViews,
+    /// SHOW CREATE COLLECTION name
+    ShowCreateCollection(String),
+    /// SHOW CREATE VIEW name
+    ShowCreateView(String),
+    /// DESCRIBE [COLLECTION] name
+    Describe(String),
+    /// EXPLAIN SELECT ...: the query's plan, without running it
+    Explain(Box<SelectStmt>),
 }
 
 /// SELECT statement
@@ -22,14 +34,30 @@ pub enum Statement {
 pub struct SelectStmt {
     /// Columns to select (empty = *)
     pub columns: Vec<Column>,
-    /// Collection to select from
+    /// Collection to select from (`@name` for a system collection,
+    /// `view:name` for a saved view)
     pub from: String,
     /// Optional alias for the from collection
     pub from_alias: Option<String>,
+    /// AS OF: read from this git revision, or the last commit at this time
+    #[serde(default)]
+    pub as_of: Option<String>,
     /// JOIN clauses
     pub joins: Vec<JoinClause>,
+    /// RELATED TO: rank by similarity to this document
+    #[serde(default)]
+    pub related_to: Option<String>,
+    /// SEMANTIC_SEARCH: rank by embedding similarity to this text
+    #[serde(default)]
+    pub semantic_search: Option<String>,
     /// Optional WHERE clause
     pub where_clause: Option<Expr>,
+    /// GROUP BY columns (field or `table.field`)
+    #[serde(default)]
+    pub group_by: Vec<String>,
+    /// HAVING clause, filtering groups
+    #[serde(default)]
+    pub having: Option<Expr>,
     /// ORDER BY clauses
     pub order_by: Vec<OrderBy>,
     /// LIMIT clause
@@ -69,9 +97,10 @@ pub enum Column {
     Star,
     /// Named field
     Field(String),
-    /// Qualified field (collection.field or alias.field)
+    /// Qualified field (collection.field or alias.field), or a path into an
+    /// object field (`metadata.author`, with `metadata` as the table)
     Qualified { table: String, field: String },
-    /// Special fields (@body, @id, @path)
+    /// Special fields (@body, @id, @path, @author)
     Special(SpecialField),
     /// Expression with alias
     Expr { expr: Box<Expr>, alias: Option<String> },
@@ -90,6 +119,8 @@ pub enum SpecialField {
     Modified,
     /// @created - creation time (from git)
     Created,
+    /// @author - author of the last commit touching the document (from git)
+    Author,
 }
 
 /// ORDER BY clause
@@ -99,18 +130,13 @@ pub struct OrderBy {
     pub direction: OrderDirection,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum OrderDirection {
+    #[default]
     Asc,
     Desc,
 }
 
-impl Default for OrderDirection {
-    fn default() -> Self {
-        Self::Asc
-    }
-}
-
 /// INSERT statement
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct InsertStmt {
@@ -118,10 +144,32 @@ pub struct InsertStmt {
     pub into: String,
     /// Column names
     pub columns: Vec<String>,
-    /// Values to insert
-    pub values: Vec<Literal>,
+    /// Rows of values to insert, one document per row
+    pub values: Vec<Vec<Literal>>,
     /// Body content (optional)
-    pub body: Option<String>,
+    pub body: Option<InsertBody>,
+    /// ON CONFLICT: what to do when a document with the ID already exists
+    #[serde(default)]
+    pub on_conflict: Option<OnConflict>,
+}
+
+/// Action for an INSERT whose document ID is taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OnConflict {
+    /// `ON CONFLICT UPDATE` (or `UPSERT INTO`) - set the given columns, and
+    /// the body if one is given, on the existing document
+    Update,
+    /// `ON CONFLICT DO NOTHING` - leave the existing document alone
+    Nothing,
+}
+
+/// Source of an INSERT body
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum InsertBody {
+    /// `BODY '...'` - content given inline
+    Inline(String),
+    /// `BODY FROM FILE './draft.md'` - content read from a file at execution time
+    File(String),
 }
 
 /// UPDATE statement
@@ -156,9 +204,34 @@ pub struct DeleteStmt {
 pub struct CreateCollectionStmt {
     pub name: String,
     pub columns: Vec<ColumnDef>,
+    /// `UNIQUE (a, b)`: combinations of columns no two documents may share
+    #[serde(default)]
+    pub unique: Vec<Vec<String>>,
     pub if_not_exists: bool,
 }
 
+/// ALTER COLLECTION statement
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlterCollectionStmt {
+    pub name: String,
+    pub action: AlterAction,
+    /// MIGRATE: rewrite existing documents to match the new schema
+    pub migrate: bool,
+}
+
+/// Schema change made by ALTER COLLECTION
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AlterAction {
+    /// ADD COLUMN def
+    AddColumn(ColumnDef),
+    /// DROP COLUMN name
+    DropColumn(String),
+    /// RENAME COLUMN from TO to
+    RenameColumn { from: String, to: String },
+    /// ALTER COLUMN name TYPE data_type
+    AlterType { column: String, data_type: DataType },
+}
+
 /// Column definition in CREATE COLLECTION
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ColumnDef {
@@ -188,17 +261,173 @@ pub enum Constraint {
     Unique,
     Default(Literal),
     Indexed,
+    /// What deleting a referenced document does to a REF column
+    OnDelete(OnDelete),
+}
+
+/// `ON DELETE` action of a REF column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OnDelete {
+    /// Delete the referencing documents too
+    Cascade,
+    /// Clear the reference
+    SetNull,
+    /// Refuse to delete a referenced document
+    Restrict,
 }
 
 /// CREATE VIEW statement
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CreateViewStmt {
     pub name: String,
+    /// Parameters the query refers to as `:name`, given when the view is rendered
+    #[serde(default)]
+    pub params: Vec<ViewParam>,
     pub query: Box<SelectStmt>,
     pub template: Option<String>,
+    /// Files the view is rendered to (`FORMATS ('html', 'csv')`); empty for
+    /// the default, HTML and JSON
+    #[serde(default)]
+    pub formats: Vec<ViewFormat>,
+    /// Fields the RSS or Atom feed takes entries from (`FEED (date = published)`)
+    #[serde(default)]
+    pub feed: Option<FeedFields>,
+    /// Built-in rendering extensions (`WITH MERMAID, MATH`)
+    #[serde(default)]
+    pub features: Vec<ViewFeature>,
+    /// Render hook (declared in the database config) that bodies are piped through
+    pub render_hook: Option<String>,
+    /// Where and when the rendered view is sent (`DELIVER TO target SCHEDULE '...'`)
+    #[serde(default)]
+    pub delivery: Option<Delivery>,
     pub if_not_exists: bool,
 }
 
+/// A parameter of a view (`CREATE VIEW by_tag(tag STRING) AS ...`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ViewParam {
+    pub name: String,
+    pub data_type: DataType,
+}
+
+/// Delivery of a rendered view to a target declared in the database config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Delivery {
+    pub target: String,
+    /// When to deliver, e.g. `daily 07:00`; without one, the view is only
+    /// delivered on demand
+    #[serde(default)]
+    pub schedule: Option<String>,
+}
+
+/// A file format a view is rendered to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ViewFormat {
+    #[default]
+    Html,
+    Json,
+    Markdown,
+    Csv,
+    /// RSS 2.0 feed, written to `feed.xml`
+    Rss,
+    /// Atom feed, written to `feed.xml`
+    Atom,
+}
+
+impl ViewFormat {
+    pub const ALL: [ViewFormat; 6] = [
+        ViewFormat::Html,
+        ViewFormat::Json,
+        ViewFormat::Markdown,
+        ViewFormat::Csv,
+        ViewFormat::Rss,
+        ViewFormat::Atom,
+    ];
+
+    /// Name as written in `FORMATS (...)`
+    pub fn name(&self) -> &'static str {
+        match self {
+            ViewFormat::Html => "html",
+            ViewFormat::Json => "json",
+            ViewFormat::Markdown => "markdown",
+            ViewFormat::Csv => "csv",
+            ViewFormat::Rss => "rss",
+            ViewFormat::Atom => "atom",
+        }
+    }
+
+    /// File extension of the format's output
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ViewFormat::Markdown => "md",
+            ViewFormat::Rss | ViewFormat::Atom => "xml",
+            other => other.name(),
+        }
+    }
+
+    /// Name of the file the format's output is written to in the view's
+    /// directory: `feed.xml` for feeds, `index.{extension}` otherwise
+    pub fn file_name(&self) -> String {
+        if self.is_feed() {
+            "feed.xml".to_string()
+        } else {
+            format!("index.{}", self.extension())
+        }
+    }
+
+    /// Whether the format is a feed (RSS or Atom)
+    pub fn is_feed(&self) -> bool {
+        matches!(self, ViewFormat::Rss | ViewFormat::Atom)
+    }
+
+    /// A format by name or extension, case-insensitively (`'md'` is
+    /// Markdown); feeds share `xml`, so they go by name only
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| {
+            format.name().eq_ignore_ascii_case(name)
+                || (!format.is_feed() && format.extension().eq_ignore_ascii_case(name))
+        })
+    }
+}
+
+/// Fields a view's feed takes each entry's parts from, where they aren't
+/// the defaults: `title` (else the ID), `date`, the document body, and no
+/// link of its own
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeedFields {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+}
+
+impl FeedFields {
+    /// `(key, field)` for each field that is set, in the order `FEED` lists them
+    pub fn entries(&self) -> Vec<(&'static str, &str)> {
+        [("title", &self.title), ("date", &self.date), ("body", &self.body), ("link", &self.link)]
+            .into_iter()
+            .filter_map(|(key, field)| field.as_deref().map(|field| (key, field)))
+            .collect()
+    }
+}
+
+/// Built-in rendering extension for a view's markdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ViewFeature {
+    /// ```mermaid fences rendered as diagrams
+    Mermaid,
+    /// `$...$` and `$$...$$` rendered with KaTeX
+    Math,
+    /// Each document's most similar documents as `related` in the template
+    Related,
+}
+
 /// Expression in WHERE clause or elsewhere
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Expr {
@@ -206,6 +435,8 @@ pub enum Expr {
     Literal(Literal),
     /// Column reference
     Column(Column),
+    /// View parameter (`:tag`), replaced by its value when the view is rendered
+    Param(String),
     /// Binary operation
     BinaryOp {
         left: Box<Expr>,
@@ -222,6 +453,11 @@ pub enum Expr {
         name: String,
         args: Vec<Expr>,
     },
+    /// Aggregate over the documents of a group; `arg` is `None` for `COUNT(*)`
+    Aggregate {
+        function: AggregateFunction,
+        arg: Option<Box<Expr>>,
+    },
     /// IN expression: column IN (values...)
     In {
         expr: Box<Expr>,
@@ -242,6 +478,9 @@ pub enum Expr {
     HasTag {
         tag: String,
         column: Option<String>,
+        /// View parameter giving the tag (`HAS TAG :tag`), in place of `tag`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        param: Option<String>,
     },
     /// IS NULL / IS NOT NULL
     IsNull {
@@ -257,6 +496,16 @@ pub enum Expr {
     },
 }
 
+/// Aggregate functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
 /// Literal values
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Literal {
@@ -298,17 +547,43 @@ pub enum UnaryOp {
     Neg,
 }
 
+/// The view a SELECT reads from, for a `view:name` source
+pub fn view_source(from: &str) -> Option<&str> {
+    from.strip_prefix("view:")
+}
+
 impl SelectStmt {
     pub fn new(from: impl Into<String>) -> Self {
         Self {
             columns: vec![Column::Star],
             from: from.into(),
             from_alias: None,
+            as_of: None,
             joins: vec![],
+            related_to: None,
+            semantic_search: None,
             where_clause: None,
+            group_by: vec![],
+            having: None,
             order_by: vec![],
             limit: None,
             offset: None,
         }
     }
 }
+
+impl Statement {
+    /// Whether the statement only reads (SELECT, SHOW, DESCRIBE, EXPLAIN)
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Statement::Select(_)
+                | Statement::ShowCollections
+                | Statement::ShowViews
+                | Statement::ShowCreateCollection(_)
+                | Statement::ShowCreateView(_)
+                | Statement::Describe(_)
+                | Statement::Explain(_)
+        )
+    }
+}
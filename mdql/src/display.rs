@@ -0,0 +1,603 @@
+//! Rendering AST nodes back to MDQL
+//!
+//! `Display` output is valid MDQL that parses back to an equivalent AST, so
+//! stored definitions can be shown as executable statements (`SHOW CREATE`).
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::ast::*;
+
+impl Display for Statement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Select(s) => write!(f, "{}", s),
+            Statement::Insert(s) => write!(f, "{}", s),
+            Statement::Update(s) => write!(f, "{}", s),
+            Statement::Delete(s) => write!(f, "{}", s),
+            Statement::CreateCollection(s) => write!(f, "{}", s),
+            Statement::AlterCollection(s) => write!(f, "{}", s),
+            Statement::CreateView(s) => write!(f, "{}", s),
+            Statement::DropCollection(name) => write!(f, "DROP COLLECTION {}", name),
+            Statement::DropView(name) => write!(f, "DROP VIEW {}", name),
+            Statement::ShowCollections => write!(f, "SHOW COLLECTIONS"),
+            Statement::ShowViews => write!(f, "SHOW VIEWS"),
+            Statement::ShowCreateCollection(name) => write!(f, "SHOW CREATE COLLECTION {}", name),
+            Statement::ShowCreateView(name) => write!(f, "SHOW CREATE VIEW {}", name),
+            Statement::Describe(name) => write!(f, "DESCRIBE COLLECTION {}", name),
+            Statement::Explain(s) => write!(f, "EXPLAIN {}", s),
+        }
+    }
+}
+
+impl Display for SelectStmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "SELECT ")?;
+        if self.columns.is_empty() {
+            write!(f, "*")?;
+        } else {
+            write_list(f, &self.columns)?;
+        }
+
+        write!(f, " FROM {}", self.from)?;
+        if let Some(revision) = &self.as_of {
+            write!(f, " AS OF {}", Quoted(revision))?;
+        }
+        if let Some(alias) = &self.from_alias {
+            write!(f, " AS {}", alias)?;
+        }
+        for join in &self.joins {
+            write!(f, " {}", join)?;
+        }
+        if let Some(id) = &self.related_to {
+            write!(f, " RELATED TO {}", Quoted(id))?;
+        }
+        if let Some(text) = &self.semantic_search {
+            write!(f, " SEMANTIC_SEARCH({})", Quoted(text))?;
+        }
+        if let Some(where_clause) = &self.where_clause {
+            write!(f, " WHERE {}", where_clause)?;
+        }
+        if !self.group_by.is_empty() {
+            write!(f, " GROUP BY {}", self.group_by.join(", "))?;
+        }
+        if let Some(having) = &self.having {
+            write!(f, " HAVING {}", having)?;
+        }
+        if !self.order_by.is_empty() {
+            write!(f, " ORDER BY ")?;
+            write_list(f, &self.order_by)?;
+        }
+        if let Some(limit) = self.limit {
+            write!(f, " LIMIT {}", limit)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " OFFSET {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for JoinClause {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let keyword = match self.join_type {
+            JoinType::Inner => "JOIN",
+            JoinType::Left => "LEFT JOIN",
+            JoinType::Right => "RIGHT JOIN",
+        };
+        write!(f, "{} {}", keyword, self.collection)?;
+        if let Some(alias) = &self.alias {
+            write!(f, " AS {}", alias)?;
+        }
+        write!(f, " ON {}", self.on)
+    }
+}
+
+impl Display for OrderBy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.direction {
+            OrderDirection::Asc => write!(f, "{}", self.column),
+            OrderDirection::Desc => write!(f, "{} DESC", self.column),
+        }
+    }
+}
+
+impl Display for Column {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Column::Star => write!(f, "*"),
+            Column::Field(name) => write!(f, "{}", name),
+            Column::Qualified { table, field } => write!(f, "{}.{}", table, field),
+            Column::Special(special) => write!(f, "{}", special),
+            Column::Expr { expr, alias } => {
+                write!(f, "{}", expr)?;
+                match alias {
+                    Some(alias) => write!(f, " AS {}", alias),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl Display for SpecialField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SpecialField::Id => "@id",
+            SpecialField::Body => "@body",
+            SpecialField::Path => "@path",
+            SpecialField::Modified => "@modified",
+            SpecialField::Created => "@created",
+            SpecialField::Author => "@author",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for InsertStmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "INSERT INTO {} (", self.into)?;
+        write_list(f, &self.columns)?;
+        write!(f, ") VALUES ")?;
+        for (i, row) in self.values.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "(")?;
+            write_list(f, row)?;
+            write!(f, ")")?;
+        }
+        match &self.body {
+            Some(InsertBody::Inline(body)) => write!(f, " BODY {}", Quoted(body)),
+            Some(InsertBody::File(path)) => write!(f, " BODY FROM FILE {}", Quoted(path)),
+            None => Ok(()),
+        }?;
+        match self.on_conflict {
+            Some(OnConflict::Update) => write!(f, " ON CONFLICT UPDATE"),
+            Some(OnConflict::Nothing) => write!(f, " ON CONFLICT DO NOTHING"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for UpdateStmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "UPDATE {} SET ", self.collection)?;
+        write_list(f, &self.set)?;
+        if let Some(where_clause) = &self.where_clause {
+            write!(f, " WHERE {}", where_clause)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SetClause {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {}", self.column, self.value)
+    }
+}
+
+impl Display for DeleteStmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "DELETE FROM {}", self.from)?;
+        if let Some(where_clause) = &self.where_clause {
+            write!(f, " WHERE {}", where_clause)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for CreateCollectionStmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "CREATE ")?;
+        if self.if_not_exists {
+            write!(f, "IF NOT EXISTS ")?;
+        }
+        write!(f, "COLLECTION {}", self.name)?;
+        if !self.columns.is_empty() || !self.unique.is_empty() {
+            write!(f, " (")?;
+            for (i, column) in self.columns.iter().enumerate() {
+                let sep = if i + 1 < self.columns.len() || !self.unique.is_empty() { "," } else { "" };
+                write!(f, "\n    {}{}", column, sep)?;
+            }
+            for (i, fields) in self.unique.iter().enumerate() {
+                let sep = if i + 1 < self.unique.len() { "," } else { "" };
+                write!(f, "\n    UNIQUE ({}){}", fields.join(", "), sep)?;
+            }
+            write!(f, "\n)")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for AlterCollectionStmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "ALTER COLLECTION {} ", self.name)?;
+        match &self.action {
+            AlterAction::AddColumn(column) => write!(f, "ADD COLUMN {}", column)?,
+            AlterAction::DropColumn(name) => write!(f, "DROP COLUMN {}", name)?,
+            AlterAction::RenameColumn { from, to } => write!(f, "RENAME COLUMN {} TO {}", from, to)?,
+            AlterAction::AlterType { column, data_type } => write!(f, "ALTER COLUMN {} TYPE {}", column, data_type)?,
+        }
+        if self.migrate {
+            write!(f, " MIGRATE")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ColumnDef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.data_type)?;
+        for constraint in &self.constraints {
+            write!(f, " {}", constraint)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ViewFeature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ViewFeature::Mermaid => write!(f, "MERMAID"),
+            ViewFeature::Math => write!(f, "MATH"),
+            ViewFeature::Related => write!(f, "RELATED"),
+        }
+    }
+}
+
+impl Display for DataType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DataType::String => write!(f, "STRING"),
+            DataType::Int => write!(f, "INT"),
+            DataType::Float => write!(f, "FLOAT"),
+            DataType::Bool => write!(f, "BOOL"),
+            DataType::Date => write!(f, "DATE"),
+            DataType::DateTime => write!(f, "DATETIME"),
+            DataType::Object => write!(f, "OBJECT"),
+            DataType::Array(inner) => write!(f, "ARRAY<{}>", inner),
+            DataType::Ref(collection) => write!(f, "REF<{}>", collection),
+        }
+    }
+}
+
+impl Display for Constraint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::Required => write!(f, "REQUIRED"),
+            Constraint::Unique => write!(f, "UNIQUE"),
+            Constraint::Indexed => write!(f, "INDEXED"),
+            Constraint::Default(lit) => write!(f, "DEFAULT {}", lit),
+            Constraint::OnDelete(action) => write!(f, "ON DELETE {}", action),
+        }
+    }
+}
+
+impl Display for OnDelete {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            OnDelete::Cascade => write!(f, "CASCADE"),
+            OnDelete::SetNull => write!(f, "SET NULL"),
+            OnDelete::Restrict => write!(f, "RESTRICT"),
+        }
+    }
+}
+
+impl Display for CreateViewStmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "CREATE ")?;
+        if self.if_not_exists {
+            write!(f, "IF NOT EXISTS ")?;
+        }
+        write!(f, "VIEW {}", self.name)?;
+        if !self.params.is_empty() {
+            write!(f, "(")?;
+            for (i, param) in self.params.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{} {}", param.name, param.data_type)?;
+            }
+            write!(f, ")")?;
+        }
+        write!(f, " AS {}", self.query)?;
+        if let Some(template) = &self.template {
+            write!(f, " TEMPLATE {}", Quoted(template))?;
+        }
+        if !self.formats.is_empty() {
+            let formats: Vec<String> = self.formats.iter().map(|format| Quoted(format.name()).to_string()).collect();
+            write!(f, " FORMATS ({})", formats.join(", "))?;
+        }
+        if let Some(feed) = &self.feed {
+            let parts: Vec<String> = feed.entries().iter().map(|(key, field)| format!("{} = {}", key, field)).collect();
+            write!(f, " FEED ({})", parts.join(", "))?;
+        }
+        if !self.features.is_empty() {
+            write!(f, " WITH ")?;
+            write_list(f, &self.features)?;
+        }
+        if let Some(hook) = &self.render_hook {
+            write!(f, " RENDER WITH {}", hook)?;
+        }
+        if let Some(delivery) = &self.delivery {
+            write!(f, " DELIVER TO {}", delivery.target)?;
+            if let Some(schedule) = &delivery.schedule {
+                write!(f, " SCHEDULE {}", Quoted(schedule))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal(lit) => write!(f, "{}", lit),
+            Expr::Column(col) => write!(f, "{}", col),
+            Expr::Param(name) => write!(f, ":{}", name),
+            Expr::BinaryOp { left, op, right } => {
+                let prec = self.precedence();
+                // Comparisons don't chain, so an equal-precedence operand needs parens too;
+                // arithmetic is left-associative, so only its right operand does
+                let min = if op.is_logical() { prec } else { prec + 1 };
+                write_operand(f, left, if op.is_arithmetic() { prec } else { min })?;
+                write!(f, " {} ", op)?;
+                write_operand(f, right, min)
+            }
+            Expr::UnaryOp { op: UnaryOp::Not, expr } => {
+                write!(f, "NOT ")?;
+                write_operand(f, expr, self.precedence())
+            }
+            Expr::UnaryOp { op: UnaryOp::Neg, expr } => {
+                write!(f, "-")?;
+                write_operand(f, expr, PRIMARY)
+            }
+            Expr::Function { name, args } => {
+                write!(f, "{}(", name)?;
+                write_list(f, args)?;
+                write!(f, ")")
+            }
+            Expr::Aggregate { function, arg } => match arg {
+                Some(arg) => write!(f, "{}({})", function, arg),
+                None => write!(f, "{}(*)", function),
+            },
+            Expr::In { expr, values, negated } => {
+                write_operand(f, expr, PRIMARY)?;
+                write!(f, "{} IN (", if *negated { " NOT" } else { "" })?;
+                write_list(f, values)?;
+                write!(f, ")")
+            }
+            Expr::Like { expr, pattern, negated } => {
+                write_operand(f, expr, PRIMARY)?;
+                write!(f, "{} LIKE {}", if *negated { " NOT" } else { "" }, Quoted(pattern))
+            }
+            Expr::Contains { text } => write!(f, "CONTAINS({})", Quoted(text)),
+            Expr::HasTag { tag, column, param } => {
+                match param {
+                    Some(param) => write!(f, "HAS TAG :{}", param)?,
+                    None => write!(f, "HAS TAG {}", Quoted(tag))?,
+                }
+                match column {
+                    Some(column) => write!(f, " IN {}", column),
+                    None => Ok(()),
+                }
+            }
+            Expr::IsNull { expr, negated } => {
+                write_operand(f, expr, PRIMARY)?;
+                write!(f, " IS {}NULL", if *negated { "NOT " } else { "" })
+            }
+            Expr::Between { expr, low, high, negated } => {
+                write_operand(f, expr, PRIMARY)?;
+                write!(f, "{} BETWEEN ", if *negated { " NOT" } else { "" })?;
+                write_operand(f, low, PRIMARY)?;
+                write!(f, " AND ")?;
+                write_operand(f, high, PRIMARY)
+            }
+        }
+    }
+}
+
+/// Precedence of expressions that need no parentheses in any position
+const PRIMARY: u8 = 10;
+
+impl Expr {
+    /// Binding strength when rendered; lower binds looser
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::BinaryOp { op: BinaryOp::Or, .. } => 1,
+            Expr::BinaryOp { op: BinaryOp::And, .. } => 2,
+            Expr::UnaryOp { op: UnaryOp::Not, .. } => 3,
+            Expr::BinaryOp { op: BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod, .. } => 6,
+            Expr::BinaryOp { op: BinaryOp::Add | BinaryOp::Sub | BinaryOp::Concat, .. } => 5,
+            Expr::BinaryOp { .. }
+            | Expr::In { .. }
+            | Expr::Like { .. }
+            | Expr::IsNull { .. }
+            | Expr::Between { .. }
+            | Expr::Contains { .. }
+            | Expr::HasTag { .. } => 4,
+            _ => PRIMARY,
+        }
+    }
+}
+
+impl Display for AggregateFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AggregateFunction::Count => "COUNT",
+            AggregateFunction::Sum => "SUM",
+            AggregateFunction::Avg => "AVG",
+            AggregateFunction::Min => "MIN",
+            AggregateFunction::Max => "MAX",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl BinaryOp {
+    fn is_logical(&self) -> bool {
+        matches!(self, BinaryOp::And | BinaryOp::Or)
+    }
+
+    fn is_arithmetic(&self) -> bool {
+        matches!(self, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod | BinaryOp::Concat)
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            BinaryOp::Eq => "=",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::And => "AND",
+            BinaryOp::Or => "OR",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::Concat => "||",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl Display for Literal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Null => write!(f, "NULL"),
+            Literal::Bool(b) => write!(f, "{}", b),
+            Literal::Int(i) => write!(f, "{}", i),
+            // Debug keeps the decimal point (`1.0`) so the value stays a float
+            Literal::Float(x) => write!(f, "{:?}", x),
+            Literal::String(s) => write!(f, "{}", Quoted(s)),
+            Literal::Array(items) => {
+                write!(f, "[")?;
+                write_list(f, items)?;
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// A single-quoted string literal with MDQL escapes applied
+struct Quoted<'a>(&'a str);
+
+impl Display for Quoted<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "'")?;
+        for c in self.0.chars() {
+            match c {
+                '\'' => write!(f, "''")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\t' => write!(f, "\\t")?,
+                '\r' => write!(f, "\\r")?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        write!(f, "'")
+    }
+}
+
+fn write_operand(f: &mut Formatter<'_>, expr: &Expr, min_precedence: u8) -> fmt::Result {
+    if expr.precedence() < min_precedence {
+        write!(f, "({})", expr)
+    } else {
+        write!(f, "{}", expr)
+    }
+}
+
+fn write_list<T: Display>(f: &mut Formatter<'_>, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+
+    fn assert_roundtrip(query: &str) {
+        let stmt = parse(query).unwrap();
+        let rendered = stmt.to_string();
+        let reparsed = parse(&rendered).unwrap_or_else(|e| panic!("failed to reparse {:?}: {}", rendered, e));
+        assert_eq!(stmt, reparsed, "rendered as {:?}", rendered);
+    }
+
+    #[test]
+    fn test_select_roundtrip() {
+        assert_roundtrip("SELECT * FROM todos");
+        assert_roundtrip("SELECT title, @id, t.done FROM todos AS t LEFT JOIN users AS u ON t.user_id = u.id");
+        assert_roundtrip("SELECT o.total, c.name FROM orders AS o JOIN customers AS c ON o.customer = c.@id ORDER BY c.name");
+        assert_roundtrip("SELECT * FROM notes WHERE @created > '2024-01-01' ORDER BY @modified DESC");
+        assert_roundtrip("SELECT title, priority * 2 AS weighted, @id FROM todos WHERE (a + b) * c > a - b - c");
+        assert_roundtrip("SELECT first || ' ' || last AS name, done = false AS open FROM people");
+        assert_roundtrip("SELECT * FROM todos AS OF 'abc123' AS t WHERE t.done = true");
+        assert_roundtrip("SELECT status, COUNT(*) AS n, AVG(points) FROM todos WHERE done = false GROUP BY status HAVING COUNT(*) > 1 ORDER BY n DESC");
+        assert_roundtrip("SELECT * FROM todos WHERE (done = false OR priority > 3) AND NOT title LIKE '%x%'");
+        assert_roundtrip("SELECT * FROM todos WHERE NOT (a = 1 AND b = 2)");
+        assert_roundtrip("SELECT * FROM todos WHERE n NOT BETWEEN -1.5 AND 1e3 AND tag NOT IN ('a', 'b')");
+        assert_roundtrip("SELECT * FROM notes RELATED TO 'rust-intro' WHERE draft = false LIMIT 5");
+        assert_roundtrip("SELECT * FROM notes SEMANTIC_SEARCH('how do branches work') LIMIT 10");
+        assert_roundtrip("SELECT * FROM todos WHERE CONTAINS('it''s') OR HAS TAG 'x' IN labels OR due IS NOT NULL");
+        assert_roundtrip("SELECT * FROM todos ORDER BY priority DESC, title LIMIT 5 OFFSET 10");
+        assert_roundtrip("SELECT hash, message FROM @commits WHERE date >= '2024-03-18'");
+        assert_roundtrip("SELECT title, @author FROM todos WHERE @author = 'ally'");
+        assert_roundtrip("SELECT UPPER(title) AS loud, LENGTH(tags) FROM todos WHERE LOWER(title) LIKE '%rust%' AND DATE(due) < DATE()");
+    }
+
+    #[test]
+    fn test_create_roundtrip() {
+        assert_roundtrip(
+            "CREATE COLLECTION todos (title STRING REQUIRED UNIQUE, done BOOL DEFAULT false, \
+             tags ARRAY<STRING>, owner REF<users>, due DATETIME INDEXED, note STRING DEFAULT 'a\\nb')",
+        );
+        assert_roundtrip(
+            "CREATE COLLECTION comments (post REF<posts> REQUIRED ON DELETE CASCADE, \
+             author REF<users> ON DELETE SET NULL, parent REF<comments> ON DELETE RESTRICT)",
+        );
+        assert_roundtrip("CREATE IF NOT EXISTS COLLECTION todos");
+        assert_roundtrip("CREATE COLLECTION pages (project STRING, slug STRING, UNIQUE (project, slug), UNIQUE (slug))");
+        assert_roundtrip("CREATE VIEW active AS SELECT * FROM todos WHERE done = false TEMPLATE 'list.html'");
+        assert_roundtrip("CREATE VIEW posts AS SELECT * FROM posts TEMPLATE 'post.html' RENDER WITH pandoc");
+        assert_roundtrip("CREATE VIEW overdue AS SELECT * FROM todos WHERE done = false DELIVER TO team_email SCHEDULE 'daily 07:00'");
+        assert_roundtrip("CREATE VIEW digest AS SELECT * FROM notes DELIVER TO chat");
+        assert_roundtrip("CREATE VIEW notes AS SELECT * FROM notes WITH MERMAID, MATH");
+        assert_roundtrip("CREATE VIEW notes AS SELECT * FROM notes WITH RELATED");
+        assert_roundtrip("CREATE VIEW report AS SELECT * FROM todos TEMPLATE 'r.html' FORMATS ('markdown', 'csv') WITH MATH");
+        assert_roundtrip("CREATE VIEW blog AS SELECT * FROM posts FORMATS ('html', 'rss') FEED (title = headline, date = published)");
+        assert_roundtrip("CREATE VIEW by_tag(tag STRING, since DATE) AS SELECT * FROM notes WHERE HAS TAG :tag AND created >= :since");
+    }
+
+    #[test]
+    fn test_alter_roundtrip() {
+        assert_roundtrip("ALTER COLLECTION todos ADD COLUMN due_date DATE REQUIRED DEFAULT '2024-01-01' MIGRATE");
+        assert_roundtrip("ALTER COLLECTION todos DROP COLUMN notes");
+        assert_roundtrip("ALTER COLLECTION todos RENAME COLUMN due TO due_date MIGRATE");
+        assert_roundtrip("ALTER COLLECTION todos ALTER COLUMN tags TYPE ARRAY<STRING>");
+    }
+
+    #[test]
+    fn test_mutation_roundtrip() {
+        assert_roundtrip("INSERT INTO todos (id, title, tags) VALUES ('t1', 'Don''t', ['a', 'b']) BODY 'Line\\nTwo'");
+        assert_roundtrip("INSERT INTO todos (id, done) VALUES ('t1', true) ON CONFLICT UPDATE");
+        assert_roundtrip("INSERT INTO todos (id, title) VALUES ('a', 'A'), ('b', 'B'), ('c', 'C')");
+        assert_roundtrip("INSERT INTO todos (id) VALUES ('t1') BODY 'x' ON CONFLICT DO NOTHING");
+        assert_roundtrip("UPDATE todos SET done = true, priority = 2 WHERE id = 't1'");
+        assert_roundtrip("DELETE FROM todos WHERE done = true");
+    }
+
+    #[test]
+    fn test_describe_roundtrip() {
+        assert_roundtrip("DESCRIBE COLLECTION todos");
+    }
+}
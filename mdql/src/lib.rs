@@ -43,6 +43,7 @@
 //! - `HAS TAG` - Check array membership
 
 mod ast;
+mod display;
 mod parser;
 mod error;
 
@@ -59,6 +60,23 @@ pub fn parse_multi(input: &str) -> Result<Vec<Statement>, ParseError> {
     parser::parse_statements(input)
 }
 
+/// Parse a script of MDQL statements (separated by semicolons), each with
+/// the line it starts on
+pub fn parse_script(input: &str) -> Result<Vec<(usize, Statement)>, ParseError> {
+    parser::parse_script(input)
+}
+
+/// Parse a lone MDQL expression, such as a WHERE condition (`done = false
+/// AND priority > 2`)
+pub fn parse_expression(input: &str) -> Result<Expr, ParseError> {
+    parser::parse_expression(input)
+}
+
+/// Parse an ORDER BY list (`priority DESC, title`)
+pub fn parse_order_by(input: &str) -> Result<Vec<OrderBy>, ParseError> {
+    parser::parse_order_by(input)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -5,10 +5,10 @@
 use nom::{
     IResult,
     branch::alt,
-    bytes::complete::{tag, tag_no_case, take_while1},
-    character::complete::{char, multispace0, multispace1, digit1, none_of},
-    combinator::{map, opt, value},
-    multi::{separated_list0, separated_list1, many0},
+    bytes::complete::{tag, tag_no_case, take_till, take_until, take_while, take_while1, take_while_m_n},
+    character::complete::{anychar, char, multispace1, digit1, none_of, one_of, satisfy},
+    combinator::{map, map_opt, map_res, not, opt, recognize, value},
+    multi::{separated_list0, separated_list1, many0, many0_count, many1_count},
     sequence::{delimited, preceded, terminated, tuple},
 };
 
@@ -17,11 +17,11 @@ use crate::error::ParseError;
 
 /// Parse a complete statement
 pub fn parse_statement(input: &str) -> Result<Statement, ParseError> {
-    let input = input.trim();
+    let input = skip_separators(input);
     let (remaining, stmt) = statement(input)?;
 
-    // Check for trailing content (ignoring whitespace and semicolons)
-    let remaining = remaining.trim().trim_end_matches(';').trim();
+    // Check for trailing content (ignoring whitespace, comments and semicolons)
+    let remaining = skip_separators(remaining);
     if !remaining.is_empty() {
         return Err(ParseError::new(format!("Unexpected trailing content: {}", remaining)));
     }
@@ -32,23 +32,106 @@ pub fn parse_statement(input: &str) -> Result<Statement, ParseError> {
 /// Parse multiple statements separated by semicolons
 pub fn parse_statements(input: &str) -> Result<Vec<Statement>, ParseError> {
     let mut statements = Vec::new();
-    let mut remaining = input.trim();
+    // Skip leading whitespace, comments and empty statements
+    let mut remaining = skip_separators(input);
 
     while !remaining.is_empty() {
-        // Skip leading whitespace and empty statements
-        remaining = remaining.trim().trim_start_matches(';').trim();
-        if remaining.is_empty() {
-            break;
-        }
-
         let (rest, stmt) = statement(remaining)?;
         statements.push(stmt);
-        remaining = rest.trim().trim_start_matches(';').trim();
+        remaining = skip_separators(rest);
+    }
+
+    Ok(statements)
+}
+
+/// Parse a script of statements separated by semicolons, each with the line
+/// it starts on (counting from 1)
+///
+/// A parse error is located at the line and column where parsing stopped.
+pub fn parse_script(input: &str) -> Result<Vec<(usize, Statement)>, ParseError> {
+    let mut statements = Vec::new();
+    let mut remaining = skip_separators(input);
+
+    while !remaining.is_empty() {
+        let start = input.len() - remaining.len();
+        let (rest, stmt) = statement(remaining).map_err(|e| {
+            let stopped = match &e {
+                nom::Err::Error(e) | nom::Err::Failure(e) => input.len() - e.input.len(),
+                nom::Err::Incomplete(_) => start,
+            };
+            let (line, column) = location(input, stopped);
+            ParseError::from(e).with_location(line, column)
+        })?;
+        statements.push((location(input, start).0, stmt));
+        remaining = skip_separators(rest);
     }
 
     Ok(statements)
 }
 
+/// Parse a lone expression, such as a WHERE condition
+pub fn parse_expression(input: &str) -> Result<Expr, ParseError> {
+    parse_complete(input, expr)
+}
+
+/// Parse an ORDER BY list (`priority DESC, title`)
+pub fn parse_order_by(input: &str) -> Result<Vec<OrderBy>, ParseError> {
+    parse_complete(input, order_by_list)
+}
+
+/// Parse all of `input` (but surrounding whitespace and comments) with `parser`
+fn parse_complete<'a, T>(input: &'a str, mut parser: impl FnMut(&'a str) -> IResult<&'a str, T>) -> Result<T, ParseError> {
+    let (input, _) = ws0(input)?;
+    let (remaining, parsed) = parser(input)?;
+    let (remaining, _) = ws0(remaining)?;
+    if !remaining.is_empty() {
+        return Err(ParseError::new(format!("Unexpected trailing content: {}", remaining)));
+    }
+    Ok(parsed)
+}
+
+/// Line and column (both from 1) of a byte offset into `input`
+fn location(input: &str, offset: usize) -> (usize, usize) {
+    let before = &input[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+/// Skip any mix of whitespace, comments and semicolons between statements
+fn skip_separators(mut input: &str) -> &str {
+    loop {
+        let (rest, _) = ws0(input).unwrap_or((input, ""));
+        match rest.strip_prefix(';') {
+            Some(rest) => input = rest,
+            None => return rest,
+        }
+    }
+}
+
+// ============================================================================
+// Whitespace and Comments
+// ============================================================================
+
+/// `-- comment` running to the end of the line
+fn line_comment(input: &str) -> IResult<&str, &str> {
+    recognize(tuple((tag("--"), take_till(|c| c == '\n'))))(input)
+}
+
+/// `/* comment */`, possibly spanning several lines
+fn block_comment(input: &str) -> IResult<&str, &str> {
+    recognize(tuple((tag("/*"), take_until("*/"), tag("*/"))))(input)
+}
+
+/// Optional whitespace; comments count as whitespace
+fn ws0(input: &str) -> IResult<&str, &str> {
+    recognize(many0_count(alt((multispace1, line_comment, block_comment))))(input)
+}
+
+/// Required whitespace; comments count as whitespace
+fn ws1(input: &str) -> IResult<&str, &str> {
+    recognize(many1_count(alt((multispace1, line_comment, block_comment))))(input)
+}
+
 // ============================================================================
 // Statement Parsers
 // ============================================================================
@@ -60,54 +143,112 @@ fn statement(input: &str) -> IResult<&str, Statement> {
         map(update_stmt, Statement::Update),
         map(delete_stmt, Statement::Delete),
         map(create_collection_stmt, Statement::CreateCollection),
+        map(alter_collection_stmt, Statement::AlterCollection),
         map(create_view_stmt, Statement::CreateView),
         map(drop_collection_stmt, Statement::DropCollection),
         map(drop_view_stmt, Statement::DropView),
         show_stmt,
+        describe_stmt,
+        explain_stmt,
     ))(input)
 }
 
 // ============================================================================
-// SHOW
+// SHOW / DESCRIBE
 // ============================================================================
 
 fn show_stmt(input: &str) -> IResult<&str, Statement> {
     let (input, _) = tag_no_case("SHOW")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     alt((
         map(tag_no_case("COLLECTIONS"), |_| Statement::ShowCollections),
         map(tag_no_case("VIEWS"), |_| Statement::ShowViews),
+        map(
+            preceded(tuple((tag_no_case("CREATE"), ws1, tag_no_case("COLLECTION"), ws1)), identifier),
+            |name| Statement::ShowCreateCollection(name.to_string()),
+        ),
+        map(
+            preceded(tuple((tag_no_case("CREATE"), ws1, tag_no_case("VIEW"), ws1)), identifier),
+            |name| Statement::ShowCreateView(name.to_string()),
+        ),
     ))(input)
 }
 
+/// `DESCRIBE [COLLECTION] name`
+fn describe_stmt(input: &str) -> IResult<&str, Statement> {
+    let (input, _) = tag_no_case("DESCRIBE")(input)?;
+    let (input, _) = ws1(input)?;
+    let (input, _) = opt(tuple((tag_no_case("COLLECTION"), ws1)))(input)?;
+    let (input, name) = identifier(input)?;
+    Ok((input, Statement::Describe(name.to_string())))
+}
+
+/// `EXPLAIN SELECT ...`
+fn explain_stmt(input: &str) -> IResult<&str, Statement> {
+    let (input, _) = tag_no_case("EXPLAIN")(input)?;
+    let (input, _) = ws1(input)?;
+    let (input, select) = select_stmt(input)?;
+    Ok((input, Statement::Explain(Box::new(select))))
+}
+
 // ============================================================================
 // SELECT
 // ============================================================================
 
 fn select_stmt(input: &str) -> IResult<&str, SelectStmt> {
     let (input, _) = tag_no_case("SELECT")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, columns) = select_columns(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, _) = tag_no_case("FROM")(input)?;
-    let (input, _) = multispace1(input)?;
-    let (input, from) = identifier(input)?;
+    let (input, _) = ws1(input)?;
+    let (input, from) = source_name(input)?;
+    let (input, as_of) = opt(preceded(
+        tuple((ws1, tag_no_case("AS"), ws1, tag_no_case("OF"), ws1)),
+        string_literal,
+    ))(input)?;
     let (input, from_alias) = opt(table_alias)(input)?;
     let (input, joins) = many0(join_clause)(input)?;
+    let (input, ranking) = opt(preceded(
+        ws1,
+        alt((
+            map(
+                preceded(tuple((tag_no_case("RELATED"), ws1, tag_no_case("TO"), ws1)), string_literal),
+                |id| (Some(id), None),
+            ),
+            map(
+                delimited(
+                    tuple((tag_no_case("SEMANTIC_SEARCH"), ws0, char('('), ws0)),
+                    string_literal,
+                    tuple((ws0, char(')'))),
+                ),
+                |text| (None, Some(text)),
+            ),
+        )),
+    ))(input)?;
+    let (related_to, semantic_search) = ranking.unwrap_or_default();
     let (input, where_clause) = opt(preceded(
-        tuple((multispace1, tag_no_case("WHERE"), multispace1)),
+        tuple((ws1, tag_no_case("WHERE"), ws1)),
+        expr,
+    ))(input)?;
+    let (input, group_by) = opt(preceded(
+        tuple((ws1, tag_no_case("GROUP"), ws1, tag_no_case("BY"), ws1)),
+        separated_list1(tuple((ws0, char(','), ws0)), map(column_name, String::from)),
+    ))(input)?;
+    let (input, having) = opt(preceded(
+        tuple((ws1, tag_no_case("HAVING"), ws1)),
         expr,
     ))(input)?;
     let (input, order_by) = opt(preceded(
-        tuple((multispace1, tag_no_case("ORDER"), multispace1, tag_no_case("BY"), multispace1)),
+        tuple((ws1, tag_no_case("ORDER"), ws1, tag_no_case("BY"), ws1)),
         order_by_list,
     ))(input)?;
     let (input, limit) = opt(preceded(
-        tuple((multispace1, tag_no_case("LIMIT"), multispace1)),
+        tuple((ws1, tag_no_case("LIMIT"), ws1)),
         map(digit1, |s: &str| s.parse::<usize>().unwrap_or(0)),
     ))(input)?;
     let (input, offset) = opt(preceded(
-        tuple((multispace1, tag_no_case("OFFSET"), multispace1)),
+        tuple((ws1, tag_no_case("OFFSET"), ws1)),
         map(digit1, |s: &str| s.parse::<usize>().unwrap_or(0)),
     ))(input)?;
 
@@ -115,8 +256,13 @@ fn select_stmt(input: &str) -> IResult<&str, SelectStmt> {
         columns,
         from: from.to_string(),
         from_alias: from_alias.map(String::from),
+        as_of,
         joins,
+        related_to,
+        semantic_search,
         where_clause,
+        group_by: group_by.unwrap_or_default(),
+        having,
         order_by: order_by.unwrap_or_default(),
         limit,
         offset,
@@ -126,27 +272,27 @@ fn select_stmt(input: &str) -> IResult<&str, SelectStmt> {
 /// Parse a table alias - must use AS keyword to avoid ambiguity with WHERE/JOIN/etc.
 fn table_alias(input: &str) -> IResult<&str, &str> {
     preceded(
-        tuple((multispace1, tag_no_case("AS"), multispace1)),
+        tuple((ws1, tag_no_case("AS"), ws1)),
         identifier,
     )(input)
 }
 
 fn join_clause(input: &str) -> IResult<&str, JoinClause> {
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, join_type) = alt((
-        value(JoinType::Inner, tuple((tag_no_case("INNER"), multispace1, tag_no_case("JOIN")))),
-        value(JoinType::Left, tuple((tag_no_case("LEFT"), multispace1, tag_no_case("JOIN")))),
-        value(JoinType::Left, tuple((tag_no_case("LEFT"), multispace1, tag_no_case("OUTER"), multispace1, tag_no_case("JOIN")))),
-        value(JoinType::Right, tuple((tag_no_case("RIGHT"), multispace1, tag_no_case("JOIN")))),
-        value(JoinType::Right, tuple((tag_no_case("RIGHT"), multispace1, tag_no_case("OUTER"), multispace1, tag_no_case("JOIN")))),
+        value(JoinType::Inner, tuple((tag_no_case("INNER"), ws1, tag_no_case("JOIN")))),
+        value(JoinType::Left, tuple((tag_no_case("LEFT"), ws1, tag_no_case("JOIN")))),
+        value(JoinType::Left, tuple((tag_no_case("LEFT"), ws1, tag_no_case("OUTER"), ws1, tag_no_case("JOIN")))),
+        value(JoinType::Right, tuple((tag_no_case("RIGHT"), ws1, tag_no_case("JOIN")))),
+        value(JoinType::Right, tuple((tag_no_case("RIGHT"), ws1, tag_no_case("OUTER"), ws1, tag_no_case("JOIN")))),
         value(JoinType::Inner, tag_no_case("JOIN")),
     ))(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, collection) = identifier(input)?;
     let (input, alias) = opt(table_alias)(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, _) = tag_no_case("ON")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, on) = expr(input)?;
 
     Ok((input, JoinClause {
@@ -158,28 +304,36 @@ fn join_clause(input: &str) -> IResult<&str, JoinClause> {
 }
 
 fn select_columns(input: &str) -> IResult<&str, Vec<Column>> {
-    alt((
-        map(char('*'), |_| vec![Column::Star]),
-        separated_list1(
-            tuple((multispace0, char(','), multispace0)),
-            column,
-        ),
-    ))(input)
+    separated_list1(
+        tuple((ws0, char(','), ws0)),
+        column,
+    )(input)
 }
 
+/// A selected column: `*`, a field, or any expression with an optional alias
 fn column(input: &str) -> IResult<&str, Column> {
     alt((
         map(char('*'), |_| Column::Star),
-        map(special_field, Column::Special),
-        qualified_column,
-        map(identifier, |s| Column::Field(s.to_string())),
+        map(
+            tuple((expr, opt(preceded(tuple((ws1, tag_no_case("AS"), ws1)), identifier)))),
+            |(expr, alias)| match (expr, alias) {
+                // A bare field stays a plain column
+                (Expr::Column(column), None) => column,
+                (expr, alias) => Column::Expr { expr: Box::new(expr), alias: alias.map(String::from) },
+            },
+        ),
     ))(input)
 }
 
+/// `table.field`, `table.@id` and friends for a special field, or a path
+/// into an object field (`metadata.author.name`)
 fn qualified_column(input: &str) -> IResult<&str, Column> {
     let (input, table) = identifier(input)?;
     let (input, _) = char('.')(input)?;
-    let (input, field) = identifier(input)?;
+    let (input, field) = alt((
+        recognize(preceded(char('@'), identifier)),
+        recognize(tuple((identifier, many0_count(preceded(char('.'), identifier))))),
+    ))(input)?;
     Ok((input, Column::Qualified {
         table: table.to_string(),
         field: field.to_string(),
@@ -195,21 +349,27 @@ fn special_field(input: &str) -> IResult<&str, SpecialField> {
             value(SpecialField::Path, tag_no_case("path")),
             value(SpecialField::Modified, tag_no_case("modified")),
             value(SpecialField::Created, tag_no_case("created")),
+            value(SpecialField::Author, tag_no_case("author")),
         )),
     )(input)
 }
 
 fn order_by_list(input: &str) -> IResult<&str, Vec<OrderBy>> {
     separated_list1(
-        tuple((multispace0, char(','), multispace0)),
+        tuple((ws0, char(','), ws0)),
         order_by_item,
     )(input)
 }
 
+/// A field, `table.field` in a joined query, or a path into an object field
+fn column_name(input: &str) -> IResult<&str, &str> {
+    recognize(tuple((identifier, many0_count(tuple((char('.'), identifier))))))(input)
+}
+
 fn order_by_item(input: &str) -> IResult<&str, OrderBy> {
-    let (input, col) = identifier(input)?;
+    let (input, col) = alt((map(special_field, |field| field.to_string()), map(column_name, String::from)))(input)?;
     let (input, dir) = opt(preceded(
-        multispace1,
+        ws1,
         alt((
             value(OrderDirection::Asc, tag_no_case("ASC")),
             value(OrderDirection::Desc, tag_no_case("DESC")),
@@ -217,7 +377,7 @@ fn order_by_item(input: &str) -> IResult<&str, OrderBy> {
     ))(input)?;
 
     Ok((input, OrderBy {
-        column: col.to_string(),
+        column: col,
         direction: dir.unwrap_or_default(),
     }))
 }
@@ -227,35 +387,56 @@ fn order_by_item(input: &str) -> IResult<&str, OrderBy> {
 // ============================================================================
 
 fn insert_stmt(input: &str) -> IResult<&str, InsertStmt> {
-    let (input, _) = tag_no_case("INSERT")(input)?;
-    let (input, _) = multispace1(input)?;
+    // UPSERT INTO is INSERT INTO ... ON CONFLICT UPDATE
+    let (input, upsert) = alt((
+        value(false, tag_no_case("INSERT")),
+        value(true, tag_no_case("UPSERT")),
+    ))(input)?;
+    let (input, _) = ws1(input)?;
     let (input, _) = tag_no_case("INTO")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, into) = identifier(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, columns) = delimited(
         char('('),
-        separated_list1(tuple((multispace0, char(','), multispace0)), identifier),
+        separated_list1(tuple((ws0, char(','), ws0)), identifier),
         char(')'),
     )(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, _) = tag_no_case("VALUES")(input)?;
-    let (input, _) = multispace0(input)?;
-    let (input, values) = delimited(
-        char('('),
-        separated_list1(tuple((multispace0, char(','), multispace0)), literal),
-        char(')'),
+    let (input, _) = ws0(input)?;
+    let (input, values) = separated_list1(
+        tuple((ws0, char(','), ws0)),
+        delimited(char('('), separated_list1(tuple((ws0, char(','), ws0)), literal), char(')')),
     )(input)?;
     let (input, body) = opt(preceded(
-        tuple((multispace1, tag_no_case("BODY"), multispace1)),
-        string_literal,
+        tuple((ws1, tag_no_case("BODY"), ws1)),
+        alt((
+            map(
+                preceded(tuple((tag_no_case("FROM"), ws1, tag_no_case("FILE"), ws1)), string_literal),
+                InsertBody::File,
+            ),
+            map(string_literal, InsertBody::Inline),
+        )),
     ))(input)?;
+    let (input, on_conflict) = if upsert {
+        (input, Some(OnConflict::Update))
+    } else {
+        opt(preceded(
+            tuple((ws1, tag_no_case("ON"), ws1, tag_no_case("CONFLICT"), ws1)),
+            alt((
+                value(OnConflict::Update, preceded(opt(tuple((tag_no_case("DO"), ws1))), tag_no_case("UPDATE"))),
+                value(OnConflict::Nothing, tuple((tag_no_case("DO"), ws1, tag_no_case("NOTHING")))),
+            )),
+        ))(input)?
+    };
 
     Ok((input, InsertStmt {
         into: into.to_string(),
         columns: columns.into_iter().map(String::from).collect(),
         values,
         body,
+        on_conflict,
     }))
 }
 
@@ -265,17 +446,17 @@ fn insert_stmt(input: &str) -> IResult<&str, InsertStmt> {
 
 fn update_stmt(input: &str) -> IResult<&str, UpdateStmt> {
     let (input, _) = tag_no_case("UPDATE")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, collection) = identifier(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, _) = tag_no_case("SET")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, set) = separated_list1(
-        tuple((multispace0, char(','), multispace0)),
+        tuple((ws0, char(','), ws0)),
         set_clause,
     )(input)?;
     let (input, where_clause) = opt(preceded(
-        tuple((multispace1, tag_no_case("WHERE"), multispace1)),
+        tuple((ws1, tag_no_case("WHERE"), ws1)),
         expr,
     ))(input)?;
 
@@ -288,9 +469,9 @@ fn update_stmt(input: &str) -> IResult<&str, UpdateStmt> {
 
 fn set_clause(input: &str) -> IResult<&str, SetClause> {
     let (input, col) = identifier(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, _) = char('=')(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, val) = expr(input)?;
 
     Ok((input, SetClause {
@@ -305,12 +486,12 @@ fn set_clause(input: &str) -> IResult<&str, SetClause> {
 
 fn delete_stmt(input: &str) -> IResult<&str, DeleteStmt> {
     let (input, _) = tag_no_case("DELETE")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, _) = tag_no_case("FROM")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, from) = identifier(input)?;
     let (input, where_clause) = opt(preceded(
-        tuple((multispace1, tag_no_case("WHERE"), multispace1)),
+        tuple((ws1, tag_no_case("WHERE"), ws1)),
         expr,
     ))(input)?;
 
@@ -326,37 +507,68 @@ fn delete_stmt(input: &str) -> IResult<&str, DeleteStmt> {
 
 fn create_collection_stmt(input: &str) -> IResult<&str, CreateCollectionStmt> {
     let (input, _) = tag_no_case("CREATE")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, if_not_exists) = opt(tuple((
         tag_no_case("IF"),
-        multispace1,
+        ws1,
         tag_no_case("NOT"),
-        multispace1,
+        ws1,
         tag_no_case("EXISTS"),
-        multispace1,
+        ws1,
     )))(input)?;
     let (input, _) = tag_no_case("COLLECTION")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, name) = identifier(input)?;
-    let (input, _) = multispace0(input)?;
-    let (input, columns) = opt(delimited(
-        char('('),
-        separated_list0(tuple((multispace0, char(','), multispace0)), column_def),
-        char(')'),
+    let (input, _) = ws0(input)?;
+    let (input, elements) = opt(delimited(
+        tuple((char('('), ws0)),
+        separated_list0(
+            tuple((ws0, char(','), ws0)),
+            alt((map(unique_constraint, ColumnListItem::Unique), map(column_def, ColumnListItem::Column))),
+        ),
+        tuple((ws0, char(')'))),
     ))(input)?;
 
+    // Columns and UNIQUE (...) constraints may be listed in any order
+    let mut columns = Vec::new();
+    let mut unique = Vec::new();
+    for element in elements.unwrap_or_default() {
+        match element {
+            ColumnListItem::Column(column) => columns.push(column),
+            ColumnListItem::Unique(fields) => unique.push(fields),
+        }
+    }
+
     Ok((input, CreateCollectionStmt {
         name: name.to_string(),
-        columns: columns.unwrap_or_default(),
+        columns,
+        unique,
         if_not_exists: if_not_exists.is_some(),
     }))
 }
 
+/// An entry in CREATE COLLECTION's column list
+enum ColumnListItem {
+    Column(ColumnDef),
+    Unique(Vec<String>),
+}
+
+/// `UNIQUE (a, b)` in a column list
+fn unique_constraint(input: &str) -> IResult<&str, Vec<String>> {
+    preceded(
+        tuple((tag_no_case("UNIQUE"), ws0, char('('), ws0)),
+        terminated(
+            separated_list1(tuple((ws0, char(','), ws0)), map(identifier, String::from)),
+            tuple((ws0, char(')'))),
+        ),
+    )(input)
+}
+
 fn column_def(input: &str) -> IResult<&str, ColumnDef> {
     let (input, name) = identifier(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, data_type) = data_type(input)?;
-    let (input, constraints) = many0(preceded(multispace1, constraint))(input)?;
+    let (input, constraints) = many0(preceded(ws1, constraint))(input)?;
 
     Ok((input, ColumnDef {
         name: name.to_string(),
@@ -371,18 +583,19 @@ fn data_type(input: &str) -> IResult<&str, DataType> {
         value(DataType::Int, tag_no_case("INT")),
         value(DataType::Float, tag_no_case("FLOAT")),
         value(DataType::Bool, tag_no_case("BOOL")),
-        value(DataType::Date, tag_no_case("DATE")),
+        // DATETIME must be tried before its prefix DATE
         value(DataType::DateTime, tag_no_case("DATETIME")),
+        value(DataType::Date, tag_no_case("DATE")),
         value(DataType::Object, tag_no_case("OBJECT")),
         map(
             preceded(
-                tuple((tag_no_case("ARRAY"), multispace0, char('<'))),
+                tuple((tag_no_case("ARRAY"), ws0, char('<'))),
                 terminated(data_type, char('>')),
             ),
             |inner| DataType::Array(Box::new(inner)),
         ),
         map(
-            preceded(tuple((tag_no_case("REF"), multispace0, char('<'))),
+            preceded(tuple((tag_no_case("REF"), ws0, char('<'))),
                      terminated(identifier, char('>'))),
             |name| DataType::Ref(name.to_string()),
         ),
@@ -395,9 +608,66 @@ fn constraint(input: &str) -> IResult<&str, Constraint> {
         value(Constraint::Unique, tag_no_case("UNIQUE")),
         value(Constraint::Indexed, tag_no_case("INDEXED")),
         map(
-            preceded(tuple((tag_no_case("DEFAULT"), multispace1)), literal),
+            preceded(tuple((tag_no_case("DEFAULT"), ws1)), literal),
             Constraint::Default,
         ),
+        map(
+            preceded(
+                tuple((tag_no_case("ON"), ws1, tag_no_case("DELETE"), ws1)),
+                alt((
+                    value(OnDelete::Cascade, tag_no_case("CASCADE")),
+                    value(OnDelete::SetNull, tuple((tag_no_case("SET"), ws1, tag_no_case("NULL")))),
+                    value(OnDelete::Restrict, tag_no_case("RESTRICT")),
+                )),
+            ),
+            Constraint::OnDelete,
+        ),
+    ))(input)
+}
+
+// ============================================================================
+// ALTER COLLECTION
+// ============================================================================
+
+fn alter_collection_stmt(input: &str) -> IResult<&str, AlterCollectionStmt> {
+    let (input, _) = tuple((tag_no_case("ALTER"), ws1, tag_no_case("COLLECTION"), ws1))(input)?;
+    let (input, name) = identifier(input)?;
+    let (input, _) = ws1(input)?;
+    let (input, action) = alter_action(input)?;
+    let (input, migrate) = opt(preceded(ws1, tag_no_case("MIGRATE")))(input)?;
+
+    Ok((input, AlterCollectionStmt {
+        name: name.to_string(),
+        action,
+        migrate: migrate.is_some(),
+    }))
+}
+
+fn alter_action(input: &str) -> IResult<&str, AlterAction> {
+    let column = |i| tuple((tag_no_case("COLUMN"), ws1))(i);
+    alt((
+        map(
+            preceded(tuple((tag_no_case("ADD"), ws1, column)), column_def),
+            AlterAction::AddColumn,
+        ),
+        map(
+            preceded(tuple((tag_no_case("DROP"), ws1, column)), identifier),
+            |name| AlterAction::DropColumn(name.to_string()),
+        ),
+        map(
+            tuple((
+                preceded(tuple((tag_no_case("RENAME"), ws1, column)), identifier),
+                preceded(tuple((ws1, tag_no_case("TO"), ws1)), identifier),
+            )),
+            |(from, to)| AlterAction::RenameColumn { from: from.to_string(), to: to.to_string() },
+        ),
+        map(
+            tuple((
+                preceded(tuple((tag_no_case("ALTER"), ws1, column)), identifier),
+                preceded(tuple((ws1, tag_no_case("TYPE"), ws1)), data_type),
+            )),
+            |(column, data_type)| AlterAction::AlterType { column: column.to_string(), data_type },
+        ),
     ))(input)
 }
 
@@ -407,53 +677,140 @@ fn constraint(input: &str) -> IResult<&str, Constraint> {
 
 fn create_view_stmt(input: &str) -> IResult<&str, CreateViewStmt> {
     let (input, _) = tag_no_case("CREATE")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, if_not_exists) = opt(tuple((
         tag_no_case("IF"),
-        multispace1,
+        ws1,
         tag_no_case("NOT"),
-        multispace1,
+        ws1,
         tag_no_case("EXISTS"),
-        multispace1,
+        ws1,
     )))(input)?;
     let (input, _) = tag_no_case("VIEW")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, name) = identifier(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, params) = opt(delimited(
+        tuple((ws0, char('('), ws0)),
+        separated_list0(tuple((ws0, char(','), ws0)), view_param),
+        tuple((ws0, char(')'))),
+    ))(input)?;
+    let (input, _) = ws1(input)?;
     let (input, _) = tag_no_case("AS")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, query) = select_stmt(input)?;
     let (input, template) = opt(preceded(
-        tuple((multispace1, tag_no_case("TEMPLATE"), multispace1)),
+        tuple((ws1, tag_no_case("TEMPLATE"), ws1)),
         string_literal,
     ))(input)?;
+    let (input, formats) = opt(preceded(
+        tuple((ws1, tag_no_case("FORMATS"), ws0)),
+        delimited(
+            tuple((char('('), ws0)),
+            separated_list1(tuple((ws0, char(','), ws0)), map_opt(string_literal, |name| ViewFormat::from_name(&name))),
+            tuple((ws0, char(')'))),
+        ),
+    ))(input)?;
+    let (input, feed) = opt(preceded(tuple((ws1, tag_no_case("FEED"), ws0)), feed_fields))(input)?;
+    let (input, features) = opt(preceded(
+        tuple((ws1, tag_no_case("WITH"), ws1)),
+        separated_list1(tuple((ws0, char(','), ws0)), view_feature),
+    ))(input)?;
+    let (input, render_hook) = opt(preceded(
+        tuple((ws1, tag_no_case("RENDER"), ws1, tag_no_case("WITH"), ws1)),
+        identifier,
+    ))(input)?;
+    let (input, delivery) = opt(preceded(ws1, delivery))(input)?;
 
     Ok((input, CreateViewStmt {
         name: name.to_string(),
+        params: params.unwrap_or_default(),
         query: Box::new(query),
         template,
+        formats: formats.unwrap_or_default(),
+        feed,
+        features: features.unwrap_or_default(),
+        render_hook: render_hook.map(str::to_string),
+        delivery,
         if_not_exists: if_not_exists.is_some(),
     }))
 }
 
+/// `name TYPE`, a parameter of a view
+fn view_param(input: &str) -> IResult<&str, ViewParam> {
+    let (input, name) = identifier(input)?;
+    let (input, _) = ws1(input)?;
+    let (input, data_type) = data_type(input)?;
+    Ok((input, ViewParam { name: name.to_string(), data_type }))
+}
+
+/// `(title = headline, date = published)`, each part at most once
+fn feed_fields(input: &str) -> IResult<&str, FeedFields> {
+    let part = tuple((
+        alt((tag_no_case("title"), tag_no_case("date"), tag_no_case("body"), tag_no_case("link"))),
+        tuple((ws0, char('='), ws0)),
+        identifier,
+    ));
+    map_opt(
+        delimited(
+            tuple((char('('), ws0)),
+            separated_list1(tuple((ws0, char(','), ws0)), part),
+            tuple((ws0, char(')'))),
+        ),
+        |parts| {
+            let mut fields = FeedFields::default();
+            for (key, _, field) in parts {
+                let slot = match key.to_lowercase().as_str() {
+                    "title" => &mut fields.title,
+                    "date" => &mut fields.date,
+                    "body" => &mut fields.body,
+                    _ => &mut fields.link,
+                };
+                if slot.replace(field.to_string()).is_some() {
+                    return None;
+                }
+            }
+            Some(fields)
+        },
+    )(input)
+}
+
+/// `DELIVER TO target [SCHEDULE 'spec']`
+fn delivery(input: &str) -> IResult<&str, Delivery> {
+    let (input, _) = tuple((tag_no_case("DELIVER"), ws1, tag_no_case("TO"), ws1))(input)?;
+    let (input, target) = identifier(input)?;
+    let (input, schedule) = opt(preceded(
+        tuple((ws1, tag_no_case("SCHEDULE"), ws1)),
+        string_literal,
+    ))(input)?;
+    Ok((input, Delivery { target: target.to_string(), schedule }))
+}
+
+fn view_feature(input: &str) -> IResult<&str, ViewFeature> {
+    alt((
+        value(ViewFeature::Mermaid, tag_no_case("MERMAID")),
+        value(ViewFeature::Math, tag_no_case("MATH")),
+        value(ViewFeature::Related, tag_no_case("RELATED")),
+    ))(input)
+}
+
 // ============================================================================
 // DROP
 // ============================================================================
 
 fn drop_collection_stmt(input: &str) -> IResult<&str, String> {
     let (input, _) = tag_no_case("DROP")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, _) = tag_no_case("COLLECTION")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, name) = identifier(input)?;
     Ok((input, name.to_string()))
 }
 
 fn drop_view_stmt(input: &str) -> IResult<&str, String> {
     let (input, _) = tag_no_case("DROP")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, _) = tag_no_case("VIEW")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, name) = identifier(input)?;
     Ok((input, name.to_string()))
 }
@@ -469,7 +826,7 @@ fn expr(input: &str) -> IResult<&str, Expr> {
 fn or_expr(input: &str) -> IResult<&str, Expr> {
     let (input, first) = and_expr(input)?;
     let (input, rest) = many0(preceded(
-        tuple((multispace1, tag_no_case("OR"), multispace1)),
+        tuple((ws1, tag_no_case("OR"), ws1)),
         and_expr,
     ))(input)?;
 
@@ -483,7 +840,7 @@ fn or_expr(input: &str) -> IResult<&str, Expr> {
 fn and_expr(input: &str) -> IResult<&str, Expr> {
     let (input, first) = not_expr(input)?;
     let (input, rest) = many0(preceded(
-        tuple((multispace1, tag_no_case("AND"), multispace1)),
+        tuple((ws1, tag_no_case("AND"), ws1)),
         not_expr,
     ))(input)?;
 
@@ -497,7 +854,7 @@ fn and_expr(input: &str) -> IResult<&str, Expr> {
 fn not_expr(input: &str) -> IResult<&str, Expr> {
     alt((
         map(
-            preceded(tuple((tag_no_case("NOT"), multispace1)), not_expr),
+            preceded(tuple((tag_no_case("NOT"), ws1)), not_expr),
             |e| Expr::UnaryOp { op: UnaryOp::Not, expr: Box::new(e) },
         ),
         comparison_expr,
@@ -517,9 +874,9 @@ fn comparison_expr(input: &str) -> IResult<&str, Expr> {
 }
 
 fn binary_comparison(input: &str) -> IResult<&str, Expr> {
-    let (input, left) = primary_expr(input)?;
+    let (input, left) = additive_expr(input)?;
     let (input, rest) = opt(tuple((
-        multispace0,
+        ws0,
         alt((
             value(BinaryOp::Eq, tag("=")),
             value(BinaryOp::Ne, alt((tag("!="), tag("<>")))),
@@ -528,8 +885,8 @@ fn binary_comparison(input: &str) -> IResult<&str, Expr> {
             value(BinaryOp::Ge, tag(">=")),
             value(BinaryOp::Gt, tag(">")),
         )),
-        multispace0,
-        primary_expr,
+        ws0,
+        additive_expr,
     )))(input)?;
 
     match rest {
@@ -542,13 +899,47 @@ fn binary_comparison(input: &str) -> IResult<&str, Expr> {
     }
 }
 
+/// `+`, `-` and `||`, left to right
+///
+/// Identifiers may contain `-`, so subtraction needs a space before the `-`.
+fn additive_expr(input: &str) -> IResult<&str, Expr> {
+    let op = alt((
+        value(BinaryOp::Concat, tag("||")),
+        value(BinaryOp::Add, tag("+")),
+        value(BinaryOp::Sub, tag("-")),
+    ));
+    let (input, first) = multiplicative_expr(input)?;
+    let (input, rest) = many0(tuple((preceded(ws0, op), preceded(ws0, multiplicative_expr))))(input)?;
+    Ok((input, fold_binary(first, rest)))
+}
+
+/// `*`, `/` and `%`, left to right
+fn multiplicative_expr(input: &str) -> IResult<&str, Expr> {
+    let op = alt((
+        value(BinaryOp::Mul, char('*')),
+        value(BinaryOp::Div, char('/')),
+        value(BinaryOp::Mod, char('%')),
+    ));
+    let (input, first) = primary_expr(input)?;
+    let (input, rest) = many0(tuple((preceded(ws0, op), preceded(ws0, primary_expr))))(input)?;
+    Ok((input, fold_binary(first, rest)))
+}
+
+fn fold_binary(first: Expr, rest: Vec<(BinaryOp, Expr)>) -> Expr {
+    rest.into_iter().fold(first, |acc, (op, e)| Expr::BinaryOp {
+        left: Box::new(acc),
+        op,
+        right: Box::new(e),
+    })
+}
+
 fn contains_expr(input: &str) -> IResult<&str, Expr> {
     let (input, _) = tag_no_case("CONTAINS")(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, _) = char('(')(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, text) = string_literal(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, _) = char(')')(input)?;
 
     Ok((input, Expr::Contains { text }))
@@ -556,27 +947,31 @@ fn contains_expr(input: &str) -> IResult<&str, Expr> {
 
 fn has_tag_expr(input: &str) -> IResult<&str, Expr> {
     let (input, _) = tag_no_case("HAS")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, _) = tag_no_case("TAG")(input)?;
-    let (input, _) = multispace1(input)?;
-    let (input, tag_val) = string_literal(input)?;
+    let (input, _) = ws1(input)?;
+    let (input, (tag_val, param)) = alt((
+        map(string_literal, |tag| (tag, None)),
+        map(param, |name| (String::new(), Some(name.to_string()))),
+    ))(input)?;
     let (input, column) = opt(preceded(
-        tuple((multispace1, tag_no_case("IN"), multispace1)),
+        tuple((ws1, tag_no_case("IN"), ws1)),
         identifier,
     ))(input)?;
 
     Ok((input, Expr::HasTag {
         tag: tag_val,
         column: column.map(String::from),
+        param,
     }))
 }
 
 fn is_null_expr(input: &str) -> IResult<&str, Expr> {
     let (input, e) = primary_expr(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, _) = tag_no_case("IS")(input)?;
-    let (input, _) = multispace1(input)?;
-    let (input, negated) = opt(tuple((tag_no_case("NOT"), multispace1)))(input)?;
+    let (input, _) = ws1(input)?;
+    let (input, negated) = opt(tuple((tag_no_case("NOT"), ws1)))(input)?;
     let (input, _) = tag_no_case("NULL")(input)?;
 
     Ok((input, Expr::IsNull {
@@ -587,10 +982,10 @@ fn is_null_expr(input: &str) -> IResult<&str, Expr> {
 
 fn like_expr(input: &str) -> IResult<&str, Expr> {
     let (input, e) = primary_expr(input)?;
-    let (input, _) = multispace1(input)?;
-    let (input, negated) = opt(tuple((tag_no_case("NOT"), multispace1)))(input)?;
+    let (input, _) = ws1(input)?;
+    let (input, negated) = opt(tuple((tag_no_case("NOT"), ws1)))(input)?;
     let (input, _) = tag_no_case("LIKE")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, pattern) = string_literal(input)?;
 
     Ok((input, Expr::Like {
@@ -602,14 +997,14 @@ fn like_expr(input: &str) -> IResult<&str, Expr> {
 
 fn in_expr(input: &str) -> IResult<&str, Expr> {
     let (input, e) = primary_expr(input)?;
-    let (input, _) = multispace1(input)?;
-    let (input, negated) = opt(tuple((tag_no_case("NOT"), multispace1)))(input)?;
+    let (input, _) = ws1(input)?;
+    let (input, negated) = opt(tuple((tag_no_case("NOT"), ws1)))(input)?;
     let (input, _) = tag_no_case("IN")(input)?;
-    let (input, _) = multispace0(input)?;
+    let (input, _) = ws0(input)?;
     let (input, values) = delimited(
         char('('),
         separated_list1(
-            tuple((multispace0, char(','), multispace0)),
+            tuple((ws0, char(','), ws0)),
             map(literal, Expr::Literal),
         ),
         char(')'),
@@ -624,14 +1019,14 @@ fn in_expr(input: &str) -> IResult<&str, Expr> {
 
 fn between_expr(input: &str) -> IResult<&str, Expr> {
     let (input, e) = primary_expr(input)?;
-    let (input, _) = multispace1(input)?;
-    let (input, negated) = opt(tuple((tag_no_case("NOT"), multispace1)))(input)?;
+    let (input, _) = ws1(input)?;
+    let (input, negated) = opt(tuple((tag_no_case("NOT"), ws1)))(input)?;
     let (input, _) = tag_no_case("BETWEEN")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, low) = primary_expr(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, _) = tag_no_case("AND")(input)?;
-    let (input, _) = multispace1(input)?;
+    let (input, _) = ws1(input)?;
     let (input, high) = primary_expr(input)?;
 
     Ok((input, Expr::Between {
@@ -642,13 +1037,51 @@ fn between_expr(input: &str) -> IResult<&str, Expr> {
     }))
 }
 
+/// `COUNT(*)`, or `COUNT`/`SUM`/`AVG`/`MIN`/`MAX` of an expression
+fn aggregate_expr(input: &str) -> IResult<&str, Expr> {
+    let function = alt((
+        value(AggregateFunction::Count, tag_no_case("COUNT")),
+        value(AggregateFunction::Sum, tag_no_case("SUM")),
+        value(AggregateFunction::Avg, tag_no_case("AVG")),
+        value(AggregateFunction::Min, tag_no_case("MIN")),
+        value(AggregateFunction::Max, tag_no_case("MAX")),
+    ));
+    alt((
+        map(
+            tuple((tag_no_case("COUNT"), ws0, char('('), ws0, char('*'), ws0, char(')'))),
+            |_| Expr::Aggregate { function: AggregateFunction::Count, arg: None },
+        ),
+        map(
+            tuple((function, delimited(tuple((ws0, char('('), ws0)), expr, tuple((ws0, char(')')))))),
+            |(function, arg)| Expr::Aggregate { function, arg: Some(Box::new(arg)) },
+        ),
+    ))(input)
+}
+
+/// A call to a built-in function, such as `LOWER(title)` or `NOW()`
+///
+/// Names are case-insensitive and kept uppercase; the executor checks them.
+fn function_call(input: &str) -> IResult<&str, Expr> {
+    let (input, name) = identifier(input)?;
+    let (input, args) = delimited(
+        tuple((ws0, char('('), ws0)),
+        separated_list0(tuple((ws0, char(','), ws0)), expr),
+        tuple((ws0, char(')'))),
+    )(input)?;
+
+    Ok((input, Expr::Function { name: name.to_uppercase(), args }))
+}
+
 fn primary_expr(input: &str) -> IResult<&str, Expr> {
     alt((
         delimited(
-            tuple((char('('), multispace0)),
+            tuple((char('('), ws0)),
             expr,
-            tuple((multispace0, char(')'))),
+            tuple((ws0, char(')'))),
         ),
+        aggregate_expr,
+        function_call,
+        map(param, |name| Expr::Param(name.to_string())),
         map(literal, Expr::Literal),
         map(special_field, |sf| Expr::Column(Column::Special(sf))),
         map(qualified_column, Expr::Column),
@@ -664,11 +1097,25 @@ fn identifier(input: &str) -> IResult<&str, &str> {
     take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '-')(input)
 }
 
+/// A view parameter, `:name`, returning the name
+fn param(input: &str) -> IResult<&str, &str> {
+    preceded(char(':'), identifier)(input)
+}
+
+/// Collection to read from: a name, a system collection such as `@commits`,
+/// or a saved view such as `view:active_todos`
+fn source_name(input: &str) -> IResult<&str, &str> {
+    alt((
+        recognize(preceded(tag("view:"), identifier)),
+        recognize(preceded(opt(char('@')), identifier)),
+    ))(input)
+}
+
 fn literal(input: &str) -> IResult<&str, Literal> {
     alt((
-        value(Literal::Null, tag_no_case("NULL")),
-        value(Literal::Bool(true), tag_no_case("true")),
-        value(Literal::Bool(false), tag_no_case("false")),
+        value(Literal::Null, keyword("NULL")),
+        value(Literal::Bool(true), keyword("true")),
+        value(Literal::Bool(false), keyword("false")),
         map(float_literal, Literal::Float),
         map(integer_literal, Literal::Int),
         map(string_literal, Literal::String),
@@ -676,20 +1123,34 @@ fn literal(input: &str) -> IResult<&str, Literal> {
     ))(input)
 }
 
+/// A keyword that isn't the start of a longer identifier, so `nullable` is a field
+fn keyword(word: &'static str) -> impl Fn(&str) -> IResult<&str, &str> {
+    move |input| terminated(tag_no_case(word), not(satisfy(|c: char| c.is_alphanumeric() || c == '_' || c == '-')))(input)
+}
+
 fn integer_literal(input: &str) -> IResult<&str, i64> {
-    let (input, neg) = opt(char('-'))(input)?;
-    let (input, digits) = digit1(input)?;
-    let val: i64 = digits.parse().unwrap_or(0);
-    Ok((input, if neg.is_some() { -val } else { val }))
+    map_res(
+        recognize(tuple((opt(one_of("+-")), digit1))),
+        |s: &str| s.parse::<i64>(),
+    )(input)
 }
 
+/// Float literal: `1.5`, `.5`, `-2.0`, `+3.25`, `1e6`, `2.5E-3`
+///
+/// Requires a decimal point or an exponent so plain integers stay `Int`.
 fn float_literal(input: &str) -> IResult<&str, f64> {
-    let (input, neg) = opt(char('-'))(input)?;
-    let (input, int_part) = digit1(input)?;
-    let (input, _) = char('.')(input)?;
-    let (input, frac_part) = digit1(input)?;
-    let val: f64 = format!("{}.{}", int_part, frac_part).parse().unwrap_or(0.0);
-    Ok((input, if neg.is_some() { -val } else { val }))
+    let exponent = |i| recognize(tuple((one_of("eE"), opt(one_of("+-")), digit1)))(i);
+    map_res(
+        recognize(tuple((
+            opt(one_of("+-")),
+            alt((
+                recognize(tuple((digit1, char('.'), digit1, opt(exponent)))),
+                recognize(tuple((char('.'), digit1, opt(exponent)))),
+                recognize(tuple((digit1, exponent))),
+            )),
+        ))),
+        |s: &str| s.parse::<f64>(),
+    )(input)
 }
 
 fn string_literal(input: &str) -> IResult<&str, String> {
@@ -699,7 +1160,8 @@ fn string_literal(input: &str) -> IResult<&str, String> {
             map(
                 many0(alt((
                     map(tag("''"), |_| "'".to_string()),
-                    map(none_of("'"), |c| c.to_string()),
+                    escape_sequence,
+                    map(none_of("'\\"), |c| c.to_string()),
                 ))),
                 |v| v.join(""),
             ),
@@ -709,24 +1171,68 @@ fn string_literal(input: &str) -> IResult<&str, String> {
             char('"'),
             map(
                 many0(alt((
-                    map(tag("\\\""), |_| "\"".to_string()),
-                    map(tag("\\n"), |_| "\n".to_string()),
-                    map(tag("\\t"), |_| "\t".to_string()),
-                    map(tag("\\\\"), |_| "\\".to_string()),
+                    map(tag("\"\""), |_| "\"".to_string()),
+                    escape_sequence,
                     map(none_of("\"\\"), |c| c.to_string()),
                 ))),
                 |v| v.join(""),
             ),
             char('"'),
         ),
+        dollar_quoted_literal,
     ))(input)
 }
 
+/// Backslash escape shared by both quote styles
+///
+/// Supports `\n`, `\t`, `\r`, `\\`, `\'`, `\"` and `\u{1F600}`. Any other
+/// backslash is kept as-is so Windows paths and regexes survive unchanged.
+fn escape_sequence(input: &str) -> IResult<&str, String> {
+    preceded(
+        char('\\'),
+        alt((
+            value("\n".to_string(), char('n')),
+            value("\t".to_string(), char('t')),
+            value("\r".to_string(), char('r')),
+            value("\\".to_string(), char('\\')),
+            value("'".to_string(), char('\'')),
+            value("\"".to_string(), char('"')),
+            map_opt(
+                delimited(tag("u{"), take_while_m_n(1, 6, |c: char| c.is_ascii_hexdigit()), char('}')),
+                |hex: &str| u32::from_str_radix(hex, 16).ok().and_then(char::from_u32).map(String::from),
+            ),
+            map(anychar, |c| format!("\\{}", c)),
+        )),
+    )(input)
+}
+
+/// Heredoc-style literal: `$$ ... $$` or `$tag$ ... $tag$`
+///
+/// The content is taken verbatim - no escapes - so multi-paragraph markdown
+/// can be pasted in as-is. A newline directly after the opening delimiter is
+/// dropped. Use a tag when the content itself contains `$$`.
+fn dollar_quoted_literal(input: &str) -> IResult<&str, String> {
+    let (input, label) = delimited(
+        char('$'),
+        take_while(|c: char| c.is_alphanumeric() || c == '_'),
+        char('$'),
+    )(input)?;
+    let delimiter = format!("${}$", label);
+    let (input, content) = take_until(delimiter.as_str())(input)?;
+    let (input, _) = tag(delimiter.as_str())(input)?;
+
+    let content = content
+        .strip_prefix("\r\n")
+        .or_else(|| content.strip_prefix('\n'))
+        .unwrap_or(content);
+    Ok((input, content.to_string()))
+}
+
 fn array_literal(input: &str) -> IResult<&str, Vec<Literal>> {
     delimited(
         char('['),
         separated_list0(
-            tuple((multispace0, char(','), multispace0)),
+            tuple((ws0, char(','), ws0)),
             literal,
         ),
         char(']'),
@@ -759,18 +1265,108 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_select_expressions() {
+        let stmt = parse_statement("SELECT title, priority * 2 + 1 AS weighted, @id FROM todos").unwrap();
+        let Statement::Select(s) = stmt else { panic!("Expected Select") };
+        assert_eq!(s.columns[0], Column::Field("title".into()));
+        assert_eq!(s.columns[2], Column::Special(SpecialField::Id));
+        let Column::Expr { expr, alias } = &s.columns[1] else { panic!("Expected Expr") };
+        assert_eq!(alias.as_deref(), Some("weighted"));
+        assert!(matches!(expr.as_ref(), Expr::BinaryOp { op: BinaryOp::Add, left, .. }
+            if matches!(left.as_ref(), Expr::BinaryOp { op: BinaryOp::Mul, .. })));
+
+        // Hyphens belong to identifiers; keywords don't swallow longer names
+        let stmt = parse_statement("SELECT due-date, nullable FROM todos WHERE a - 1 > 0").unwrap();
+        let Statement::Select(s) = stmt else { panic!("Expected Select") };
+        assert_eq!(s.columns, vec![Column::Field("due-date".into()), Column::Field("nullable".into())]);
+    }
+
+    #[test]
+    fn test_parse_select_as_of() {
+        let stmt = parse_statement("SELECT * FROM todos AS OF '2024-03-01' WHERE done = false").unwrap();
+        assert!(matches!(stmt, Statement::Select(s) if s.as_of.as_deref() == Some("2024-03-01") && s.from_alias.is_none()));
+
+        // A plain alias still works
+        let stmt = parse_statement("SELECT * FROM todos AS t").unwrap();
+        assert!(matches!(stmt, Statement::Select(s) if s.as_of.is_none() && s.from_alias.as_deref() == Some("t")));
+    }
+
+    #[test]
+    fn test_parse_select_related_to() {
+        let stmt = parse_statement("SELECT * FROM notes RELATED TO 'rust-intro' LIMIT 5").unwrap();
+        assert!(matches!(stmt, Statement::Select(s) if s.related_to.as_deref() == Some("rust-intro") && s.limit == Some(5)));
+    }
+
+    #[test]
+    fn test_parse_select_semantic_search() {
+        let stmt = parse_statement("SELECT * FROM notes SEMANTIC_SEARCH('git internals') LIMIT 10").unwrap();
+        assert!(matches!(stmt, Statement::Select(s) if s.semantic_search.as_deref() == Some("git internals") && s.related_to.is_none()));
+
+        // One ranking per query
+        assert!(parse_statement("SELECT * FROM notes RELATED TO 'a' SEMANTIC_SEARCH('b')").is_err());
+    }
+
+    #[test]
+    fn test_parse_select_system_collection() {
+        let stmt = parse_statement("SELECT * FROM @commits WHERE author = 'ada'").unwrap();
+        assert!(matches!(stmt, Statement::Select(s) if s.from == "@commits"));
+
+        // System collections are read-only
+        assert!(parse_statement("INSERT INTO @commits (id) VALUES ('x')").is_err());
+    }
+
+    #[test]
+    fn test_parse_select_from_view() {
+        let stmt = parse_statement("SELECT * FROM view:active_todos WHERE priority > 3").unwrap();
+        assert!(matches!(&stmt, Statement::Select(s) if s.from == "view:active_todos" && s.where_clause.is_some()));
+        assert_eq!(stmt.to_string(), "SELECT * FROM view:active_todos WHERE priority > 3");
+        assert_eq!(view_source("view:active_todos"), Some("active_todos"));
+        assert_eq!(view_source("todos"), None);
+
+        // Views are read-only
+        assert!(parse_statement("INSERT INTO view:active_todos (id) VALUES ('x')").is_err());
+    }
+
     #[test]
     fn test_parse_insert() {
         let stmt = parse_statement("INSERT INTO todos (id, title, done) VALUES ('task-1', 'Buy milk', false)").unwrap();
         if let Statement::Insert(i) = stmt {
             assert_eq!(i.into, "todos");
             assert_eq!(i.columns.len(), 3);
-            assert_eq!(i.values.len(), 3);
+            assert_eq!(i.values, vec![vec![
+                Literal::String("task-1".into()),
+                Literal::String("Buy milk".into()),
+                Literal::Bool(false),
+            ]]);
         } else {
             panic!("Expected Insert");
         }
     }
 
+    #[test]
+    fn test_parse_insert_many_rows() {
+        let stmt = parse_statement("INSERT INTO todos (id, title) VALUES ('a','A'), ('b', 'B') ,('c', 'C') BODY 'x'").unwrap();
+        let Statement::Insert(i) = stmt else { panic!("Expected Insert") };
+        assert_eq!(i.values.len(), 3);
+        assert_eq!(i.values[2], vec![Literal::String("c".into()), Literal::String("C".into())]);
+        assert!(i.body.is_some());
+    }
+
+    #[test]
+    fn test_parse_insert_on_conflict() {
+        let on_conflict = |query| match parse_statement(query).unwrap() {
+            Statement::Insert(i) => i.on_conflict,
+            _ => panic!("Expected Insert"),
+        };
+        assert_eq!(on_conflict("INSERT INTO todos (id) VALUES ('a')"), None);
+        assert_eq!(on_conflict("INSERT INTO todos (id) VALUES ('a') ON CONFLICT UPDATE"), Some(OnConflict::Update));
+        assert_eq!(on_conflict("INSERT INTO todos (id) VALUES ('a') on conflict do update"), Some(OnConflict::Update));
+        assert_eq!(on_conflict("INSERT INTO todos (id) VALUES ('a') BODY 'x' ON CONFLICT DO NOTHING"), Some(OnConflict::Nothing));
+        assert_eq!(on_conflict("UPSERT INTO todos (id, title) VALUES ('a', 'A')"), Some(OnConflict::Update));
+        assert!(parse_statement("UPSERT INTO todos (id) VALUES ('a') ON CONFLICT DO NOTHING").is_err());
+    }
+
     #[test]
     fn test_parse_create_collection() {
         let stmt = parse_statement("CREATE COLLECTION todos (title STRING REQUIRED, done BOOL DEFAULT false)").unwrap();
@@ -782,12 +1378,157 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_on_delete() {
+        let stmt = parse_statement(
+            "CREATE COLLECTION comments (post REF<posts> REQUIRED ON DELETE CASCADE, author REF<users> on delete set null)",
+        )
+        .unwrap();
+        let Statement::CreateCollection(c) = stmt else { panic!("Expected CreateCollection") };
+        assert_eq!(c.columns[0].constraints, vec![Constraint::Required, Constraint::OnDelete(OnDelete::Cascade)]);
+        assert_eq!(c.columns[1].constraints, vec![Constraint::OnDelete(OnDelete::SetNull)]);
+        assert!(parse_statement("CREATE COLLECTION c (post REF<posts> ON DELETE NOTHING)").is_err());
+    }
+
+    #[test]
+    fn test_parse_composite_unique() {
+        let stmt = parse_statement(
+            "CREATE COLLECTION pages (project STRING, UNIQUE (project, slug), slug STRING REQUIRED, unique(lang))",
+        )
+        .unwrap();
+        let Statement::CreateCollection(c) = stmt else { panic!("Expected CreateCollection") };
+        assert_eq!(c.columns.len(), 2);
+        assert_eq!(c.unique, vec![vec!["project".to_string(), "slug".to_string()], vec!["lang".to_string()]]);
+        assert!(parse_statement("CREATE COLLECTION pages (UNIQUE ())").is_err());
+    }
+
+    #[test]
+    fn test_parse_alter_collection() {
+        let stmt = parse_statement("ALTER COLLECTION todos ADD COLUMN due_date DATE DEFAULT '2024-01-01' MIGRATE").unwrap();
+        if let Statement::AlterCollection(a) = stmt {
+            assert_eq!(a.name, "todos");
+            assert!(a.migrate);
+            match a.action {
+                AlterAction::AddColumn(column) => {
+                    assert_eq!(column.data_type, DataType::Date);
+                    assert_eq!(column.constraints, vec![Constraint::Default(Literal::String("2024-01-01".into()))]);
+                }
+                other => panic!("Expected AddColumn, got {:?}", other),
+            }
+        } else {
+            panic!("Expected AlterCollection");
+        }
+
+        let stmt = parse_statement("alter collection todos rename column due to due_date").unwrap();
+        assert_eq!(stmt, Statement::AlterCollection(AlterCollectionStmt {
+            name: "todos".into(),
+            action: AlterAction::RenameColumn { from: "due".into(), to: "due_date".into() },
+            migrate: false,
+        }));
+        let stmt = parse_statement("ALTER COLLECTION todos ALTER COLUMN priority TYPE INT MIGRATE").unwrap();
+        assert!(matches!(stmt, Statement::AlterCollection(AlterCollectionStmt {
+            action: AlterAction::AlterType { data_type: DataType::Int, .. },
+            migrate: true,
+            ..
+        })));
+        assert!(parse_statement("ALTER COLLECTION todos DROP COLUMN notes").is_ok());
+        assert!(parse_statement("ALTER COLLECTION todos DROP notes").is_err());
+    }
+
     #[test]
     fn test_parse_create_view() {
         let stmt = parse_statement("CREATE VIEW active AS SELECT * FROM todos WHERE done = false TEMPLATE 'list.html'").unwrap();
         if let Statement::CreateView(v) = stmt {
             assert_eq!(v.name, "active");
             assert_eq!(v.template, Some("list.html".to_string()));
+            assert_eq!(v.render_hook, None);
+        } else {
+            panic!("Expected CreateView");
+        }
+    }
+
+    #[test]
+    fn test_parse_create_view_params() {
+        let stmt = parse_statement("CREATE VIEW by_tag (tag STRING, min INT) AS SELECT * FROM notes WHERE HAS TAG :tag IN labels AND priority >= :min").unwrap();
+        let Statement::CreateView(v) = stmt else {
+            panic!("Expected CreateView");
+        };
+        assert_eq!(
+            v.params,
+            vec![
+                ViewParam { name: "tag".into(), data_type: DataType::String },
+                ViewParam { name: "min".into(), data_type: DataType::Int },
+            ]
+        );
+        let Some(Expr::BinaryOp { left, right, .. }) = v.query.where_clause else {
+            panic!("Expected AND");
+        };
+        assert_eq!(*left, Expr::HasTag { tag: String::new(), column: Some("labels".into()), param: Some("tag".into()) });
+        let Expr::BinaryOp { right, .. } = *right else {
+            panic!("Expected >=");
+        };
+        assert_eq!(*right, Expr::Param("min".into()));
+    }
+
+    #[test]
+    fn test_parse_create_view_delivery() {
+        let stmt = parse_statement("CREATE VIEW overdue AS SELECT * FROM todos DELIVER TO team_email SCHEDULE 'daily 07:00'").unwrap();
+        if let Statement::CreateView(v) = stmt {
+            let delivery = v.delivery.unwrap();
+            assert_eq!(delivery.target, "team_email");
+            assert_eq!(delivery.schedule, Some("daily 07:00".to_string()));
+        } else {
+            panic!("Expected CreateView");
+        }
+    }
+
+    #[test]
+    fn test_parse_create_view_formats() {
+        let stmt = parse_statement("CREATE VIEW report AS SELECT * FROM todos TEMPLATE 'r.html' FORMATS ('html', 'CSV', 'md') WITH MATH").unwrap();
+        let Statement::CreateView(v) = stmt else {
+            panic!("Expected CreateView");
+        };
+        assert_eq!(v.formats, vec![ViewFormat::Html, ViewFormat::Csv, ViewFormat::Markdown]);
+        assert_eq!(v.features, vec![ViewFeature::Math]);
+        assert!(parse_statement("CREATE VIEW report AS SELECT * FROM todos FORMATS ('pdf')").is_err());
+    }
+
+    #[test]
+    fn test_parse_create_view_feed() {
+        let stmt = parse_statement(
+            "CREATE VIEW blog AS SELECT * FROM posts FORMATS ('html', 'Atom') FEED (Title = headline, body=summary)",
+        )
+        .unwrap();
+        let Statement::CreateView(v) = stmt else {
+            panic!("Expected CreateView");
+        };
+        assert_eq!(v.formats, vec![ViewFormat::Html, ViewFormat::Atom]);
+        let feed = v.feed.unwrap();
+        assert_eq!(feed.title.as_deref(), Some("headline"));
+        assert_eq!(feed.body.as_deref(), Some("summary"));
+        assert_eq!(feed.date, None);
+        assert!(parse_statement("CREATE VIEW blog AS SELECT * FROM posts FEED (title = a, title = b)").is_err());
+        assert!(parse_statement("CREATE VIEW blog AS SELECT * FROM posts FEED (author = a)").is_err());
+        assert!(parse_statement("CREATE VIEW blog AS SELECT * FROM posts FORMATS ('xml')").is_err());
+    }
+
+    #[test]
+    fn test_parse_create_view_render_hook() {
+        let stmt = parse_statement("CREATE VIEW diagrams AS SELECT * FROM notes RENDER WITH mermaid").unwrap();
+        if let Statement::CreateView(v) = stmt {
+            assert_eq!(v.template, None);
+            assert_eq!(v.render_hook, Some("mermaid".to_string()));
+        } else {
+            panic!("Expected CreateView");
+        }
+    }
+
+    #[test]
+    fn test_parse_create_view_features() {
+        let stmt = parse_statement("CREATE VIEW notes AS SELECT * FROM notes WITH mermaid, MATH RENDER WITH pandoc").unwrap();
+        if let Statement::CreateView(v) = stmt {
+            assert_eq!(v.features, vec![ViewFeature::Mermaid, ViewFeature::Math]);
+            assert_eq!(v.render_hook, Some("pandoc".to_string()));
         } else {
             panic!("Expected CreateView");
         }
@@ -872,4 +1613,288 @@ mod tests {
             panic!("Expected Select");
         }
     }
+
+    #[test]
+    fn test_parse_nested_path() {
+        let stmt = parse_statement("SELECT meta.author.name FROM notes WHERE meta.author.name = 'marie' ORDER BY meta.year").unwrap();
+        let Statement::Select(s) = stmt else { panic!("Expected Select") };
+        let path = Column::Qualified { table: "meta".into(), field: "author.name".into() };
+        assert_eq!(s.columns, vec![path.clone()]);
+        assert!(matches!(&s.where_clause, Some(Expr::BinaryOp { left, .. }) if **left == Expr::Column(path.clone())));
+        assert_eq!(s.order_by[0].column, "meta.year");
+    }
+
+    #[test]
+    fn test_parse_join_on_special_field() {
+        let stmt = parse_statement(
+            "SELECT o.total, c.name FROM orders AS o JOIN customers AS c ON o.customer = c.@id ORDER BY c.name DESC",
+        )
+        .unwrap();
+        if let Statement::Select(s) = stmt {
+            assert!(matches!(
+                &s.joins[0].on,
+                Expr::BinaryOp { right, .. }
+                    if matches!(right.as_ref(), Expr::Column(Column::Qualified { table, field }) if table == "c" && field == "@id")
+            ));
+            assert_eq!(s.order_by[0].column, "c.name");
+        } else {
+            panic!("Expected Select");
+        }
+    }
+
+    #[test]
+    fn test_parse_group_by() {
+        let stmt = parse_statement(
+            "SELECT status, count(*) AS n, SUM(points) FROM todos GROUP BY status HAVING COUNT(*) >= 2 ORDER BY n DESC",
+        )
+        .unwrap();
+        if let Statement::Select(s) = stmt {
+            assert_eq!(s.columns.len(), 3);
+            assert!(matches!(
+                &s.columns[1],
+                Column::Expr { expr, alias: Some(alias) }
+                    if alias == "n" && matches!(expr.as_ref(), Expr::Aggregate { function: AggregateFunction::Count, arg: None })
+            ));
+            assert_eq!(s.group_by, vec!["status"]);
+            assert!(matches!(s.having, Some(Expr::BinaryOp { op: BinaryOp::Ge, .. })));
+        } else {
+            panic!("Expected Select");
+        }
+        assert!(parse_statement("SELECT SUM(*) FROM todos").is_err());
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        let stmt = parse_statement(
+            "-- active tasks\nSELECT /* everything */ * FROM todos -- trailing\nWHERE done = false /* end */",
+        ).unwrap();
+        if let Statement::Select(s) = stmt {
+            assert_eq!(s.from, "todos");
+            assert!(s.where_clause.is_some());
+        } else {
+            panic!("Expected Select");
+        }
+    }
+
+    #[test]
+    fn test_parse_statements_with_comments() {
+        let stmts = parse_statements(
+            "/* seed;\n data */\nCREATE COLLECTION todos; -- schema-less; for now\nINSERT INTO todos (id, title) VALUES ('t1', '-- not a comment');\n-- done\n",
+        ).unwrap();
+        assert_eq!(stmts.len(), 2);
+        if let Statement::Insert(insert) = &stmts[1] {
+            assert_eq!(insert.values[0][1], Literal::String("-- not a comment".into()));
+        } else {
+            panic!("Expected Insert");
+        }
+    }
+
+    #[test]
+    fn test_parse_script_lines() {
+        let script = "-- setup\nCREATE COLLECTION todos;\n\nINSERT INTO todos (id)\n  VALUES ('t1'); SELECT * FROM todos\n";
+        let lines: Vec<usize> = parse_script(script).unwrap().into_iter().map(|(line, _)| line).collect();
+        assert_eq!(lines, vec![2, 4, 5]);
+
+        let err = parse_script("SELECT * FROM todos;\nSELECT * FROM todos;\n  SELEC * FROM todos").unwrap_err();
+        assert_eq!((err.line, err.column), (Some(3), Some(3)));
+    }
+
+    #[test]
+    fn test_parse_expression_and_order_by() {
+        let expr = parse_expression(" done = false AND priority > 2 ").unwrap();
+        assert!(matches!(expr, Expr::BinaryOp { op: BinaryOp::And, .. }));
+        assert!(parse_expression("done = false; DROP COLLECTION todos").is_err());
+        assert!(parse_expression("").is_err());
+
+        let order = parse_order_by("priority DESC, title").unwrap();
+        assert_eq!(order.len(), 2);
+        assert_eq!(order[0].direction, OrderDirection::Desc);
+        assert!(parse_order_by("priority SIDEWAYS").is_err());
+    }
+
+    #[test]
+    fn test_parse_unterminated_block_comment() {
+        assert!(parse_statement("SELECT * FROM todos /* oops").is_err());
+    }
+
+    #[test]
+    fn test_parse_heredoc_body() {
+        let stmt = parse_statement(
+            "INSERT INTO notes (id) VALUES ('n1') BODY $$\n# Title\n\nIt's a 'quoted' -- body\n$$",
+        ).unwrap();
+        if let Statement::Insert(insert) = stmt {
+            assert_eq!(insert.body, Some(InsertBody::Inline("# Title\n\nIt's a 'quoted' -- body\n".into())));
+        } else {
+            panic!("Expected Insert");
+        }
+    }
+
+    #[test]
+    fn test_parse_tagged_heredoc() {
+        let stmt = parse_statement("INSERT INTO notes (id) VALUES ('n1') BODY $md$Costs $$5$md$").unwrap();
+        if let Statement::Insert(insert) = stmt {
+            assert_eq!(insert.body, Some(InsertBody::Inline("Costs $$5".into())));
+        } else {
+            panic!("Expected Insert");
+        }
+    }
+
+    #[test]
+    fn test_parse_body_from_file() {
+        let stmt = parse_statement("INSERT INTO notes (id, title) VALUES ('n1', 'Draft') BODY FROM FILE './draft.md'").unwrap();
+        if let Statement::Insert(insert) = stmt {
+            assert_eq!(insert.body, Some(InsertBody::File("./draft.md".into())));
+        } else {
+            panic!("Expected Insert");
+        }
+    }
+
+    #[test]
+    fn test_parse_numeric_literals() {
+        let cases = [
+            ("1e6", Literal::Float(1e6)),
+            ("2.5E-3", Literal::Float(2.5e-3)),
+            (".5", Literal::Float(0.5)),
+            ("-.5", Literal::Float(-0.5)),
+            ("+3", Literal::Int(3)),
+            ("-42", Literal::Int(-42)),
+            ("+1.25", Literal::Float(1.25)),
+        ];
+        for (text, expected) in cases {
+            let (rest, lit) = literal(text).unwrap();
+            assert!(rest.is_empty(), "unparsed input for {}", text);
+            assert_eq!(lit, expected, "parsing {}", text);
+        }
+
+        // Out-of-range integers are rejected rather than silently zeroed
+        assert!(integer_literal("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn test_parse_negative_between_and_in() {
+        let stmt = parse_statement("SELECT * FROM t WHERE n BETWEEN -10 AND -1.5").unwrap();
+        if let Statement::Select(s) = stmt {
+            match s.where_clause {
+                Some(Expr::Between { low, high, .. }) => {
+                    assert_eq!(*low, Expr::Literal(Literal::Int(-10)));
+                    assert_eq!(*high, Expr::Literal(Literal::Float(-1.5)));
+                }
+                other => panic!("Expected Between, got {:?}", other),
+            }
+        } else {
+            panic!("Expected Select");
+        }
+
+        let stmt = parse_statement("SELECT * FROM t WHERE n IN (-1, +2, .5, 1e3)").unwrap();
+        if let Statement::Select(s) = stmt {
+            match s.where_clause {
+                Some(Expr::In { values, .. }) => assert_eq!(values, vec![
+                    Expr::Literal(Literal::Int(-1)),
+                    Expr::Literal(Literal::Int(2)),
+                    Expr::Literal(Literal::Float(0.5)),
+                    Expr::Literal(Literal::Float(1000.0)),
+                ]),
+                other => panic!("Expected In, got {:?}", other),
+            }
+        } else {
+            panic!("Expected Select");
+        }
+    }
+
+    #[test]
+    fn test_parse_string_escapes() {
+        let cases = [
+            (r"'line\none'", "line\none"),
+            (r#""line\none""#, "line\none"),
+            (r"'tab\there'", "tab\there"),
+            (r"'it''s'", "it's"),
+            (r"'it\'s'", "it's"),
+            (r#""say \"hi\"""#, "say \"hi\""),
+            (r#""say ""hi""""#, "say \"hi\""),
+            (r"'caf\u{e9} \u{1F600}'", "café 😀"),
+            (r#""caf\u{E9}""#, "café"),
+            ("'naïve ✓'", "naïve ✓"),
+            (r"'C:\temp\dir'", "C:\temp\\dir"),
+            (r"'C:\\temp\dir'", "C:\\temp\\dir"),
+            (r"'C:\Users\me'", "C:\\Users\\me"),
+            (r"$$C:\temp$$", "C:\\temp"),
+            (r"'back\\slash'", "back\\slash"),
+        ];
+        for (text, expected) in cases {
+            let (rest, s) = string_literal(text).unwrap();
+            assert!(rest.is_empty(), "unparsed input for {}", text);
+            assert_eq!(s, expected, "parsing {}", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_show_create() {
+        assert_eq!(
+            parse_statement("SHOW CREATE COLLECTION todos").unwrap(),
+            Statement::ShowCreateCollection("todos".into())
+        );
+        assert_eq!(
+            parse_statement("show create view active").unwrap(),
+            Statement::ShowCreateView("active".into())
+        );
+    }
+
+    #[test]
+    fn test_parse_describe() {
+        assert_eq!(parse_statement("DESCRIBE todos").unwrap(), Statement::Describe("todos".into()));
+        assert_eq!(
+            parse_statement("describe collection todos").unwrap(),
+            Statement::Describe("todos".into())
+        );
+        // A collection may itself be called "collection"
+        assert_eq!(
+            parse_statement("DESCRIBE collection").unwrap(),
+            Statement::Describe("collection".into())
+        );
+    }
+
+    #[test]
+    fn test_parse_explain() {
+        let stmt = parse_statement("EXPLAIN SELECT * FROM todos WHERE done = false LIMIT 5").unwrap();
+        let Statement::Select(select) = parse_statement("SELECT * FROM todos WHERE done = false LIMIT 5").unwrap() else {
+            panic!("Expected Select");
+        };
+        assert_eq!(stmt, Statement::Explain(Box::new(select)));
+        assert!(stmt.is_read_only());
+        assert!(parse_statement("EXPLAIN DELETE FROM todos").is_err());
+    }
+
+    #[test]
+    fn test_parse_function_call() {
+        let stmt = parse_statement("UPDATE todos SET updated_at = now(), title = COALESCE(title , 'Untitled') WHERE lower( title ) LIKE '%rust%'").unwrap();
+        let Statement::Update(update) = stmt else {
+            panic!("Expected Update");
+        };
+        assert_eq!(update.set[0].value, Expr::Function { name: "NOW".into(), args: vec![] });
+        assert_eq!(
+            update.set[1].value,
+            Expr::Function {
+                name: "COALESCE".into(),
+                args: vec![
+                    Expr::Column(Column::Field("title".into())),
+                    Expr::Literal(Literal::String("Untitled".into())),
+                ],
+            }
+        );
+        let Some(Expr::Like { expr, .. }) = update.where_clause else {
+            panic!("Expected LIKE");
+        };
+        assert_eq!(*expr, Expr::Function { name: "LOWER".into(), args: vec![Expr::Column(Column::Field("title".into()))] });
+    }
+
+    #[test]
+    fn test_parse_multiline_collection_schema() {
+        let stmt = parse_statement("CREATE COLLECTION todos (\n    title STRING,\n    due DATETIME\n)").unwrap();
+        if let Statement::CreateCollection(c) = stmt {
+            assert_eq!(c.columns.len(), 2);
+            assert_eq!(c.columns[1].data_type, DataType::DateTime);
+        } else {
+            panic!("Expected CreateCollection");
+        }
+    }
 }
@@ -0,0 +1,377 @@
+//! Database configuration
+//!
+//! Settings are stored in `/.mdby/config.yaml`. Every field is optional, so a
+//! missing or partial file falls back to defaults.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::git::ConflictResolution;
+use crate::schema::TimeZone;
+use crate::storage::document::{Document, Value};
+
+/// Database-wide settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Human-readable database name
+    #[serde(default)]
+    pub name: Option<String>,
+    /// What the database is for
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Starter template the database was created from (`mdby init --template`)
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Locale of field labels in table headers and views (`fr`, `pt-BR`);
+    /// see `labels` in the schema
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Zone DATETIME values are stored in: `UTC` (the default) or an offset
+    /// like `+02:00` (see [`crate::schema::timezone`])
+    #[serde(default, skip_serializing_if = "TimeZone::is_utc")]
+    pub timezone: TimeZone,
+    /// Public URL the `views/` directory is published at, for links in
+    /// RSS and Atom feeds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site_url: Option<String>,
+    /// Maximum number of views regenerated at once (default: number of CPUs)
+    #[serde(default)]
+    pub view_parallelism: Option<usize>,
+    /// Regenerate the views reading a collection whenever a statement
+    /// changes it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto_regenerate_views: bool,
+    /// Size limits enforced when documents are written
+    #[serde(default)]
+    pub limits: Limits,
+    /// External commands views can pipe bodies through (`RENDER WITH name`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub render_hooks: BTreeMap<String, RenderHook>,
+    /// Where views can be delivered (`DELIVER TO name`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub delivery_targets: BTreeMap<String, DeliveryTarget>,
+    /// Sign every commit the database makes (checked by `mdby verify`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing: Option<Signing>,
+    /// Command computing embeddings for `SEMANTIC_SEARCH`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embeddings: Option<EmbeddingCommand>,
+    /// How `mdby sync` resolves conflicts and authenticates
+    #[serde(default, skip_serializing_if = "SyncSettings::is_default")]
+    pub sync: SyncSettings,
+    /// Settings for the HTTP API (`mdby serve`)
+    #[serde(default, skip_serializing_if = "ServeSettings::is_default")]
+    pub serve: ServeSettings,
+    /// Log executed statements to `.mdby/logs/queries.log` (`mdby slowlog`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub query_log: bool,
+    /// The CLI asks before a DELETE without WHERE removes more than this
+    /// many documents (default: 0, so it always asks)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub confirm_delete_above: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// Settings for `mdby sync`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncSettings {
+    /// How documents changed both locally and on the remote are combined
+    #[serde(default)]
+    pub strategy: ConflictResolution,
+    /// Private key for SSH remotes, tried after the SSH agent
+    /// (default: `~/.ssh/id_ed25519`, then `~/.ssh/id_rsa`)
+    #[serde(default)]
+    pub ssh_key: Option<String>,
+}
+
+impl SyncSettings {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Settings for `mdby serve`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServeSettings {
+    /// Origins whose pages may call the API from a browser
+    /// (`https://app.example.com`, or `*` for any); without any, only pages
+    /// served by the API's own origin can read its responses
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cors_origins: Vec<String>,
+}
+
+impl ServeSettings {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Commit signing settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signing {
+    /// Signature format
+    #[serde(default)]
+    pub format: SigningFormat,
+    /// GPG key ID, or path to the SSH key (private, or public with an agent)
+    pub key: String,
+    /// Program to sign and verify with (default: `gpg` or `ssh-keygen`)
+    #[serde(default)]
+    pub program: Option<String>,
+    /// SSH allowed signers file; without one, SSH signatures are only
+    /// checked for integrity, not against trusted keys
+    #[serde(default)]
+    pub allowed_signers: Option<PathBuf>,
+}
+
+/// How commits are signed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SigningFormat {
+    /// OpenPGP signatures made with `gpg`
+    #[default]
+    Gpg,
+    /// SSH signatures made with `ssh-keygen -Y sign`
+    Ssh,
+}
+
+impl Signing {
+    /// Program that makes and checks signatures in this format
+    pub fn program(&self) -> &str {
+        self.program.as_deref().unwrap_or(match self.format {
+            SigningFormat::Gpg => "gpg",
+            SigningFormat::Ssh => "ssh-keygen",
+        })
+    }
+}
+
+/// An external command that turns a markdown body into HTML
+///
+/// The body is written to the command's stdin; its stdout is used as the
+/// rendered HTML.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderHook {
+    /// Program to run (looked up on `PATH`)
+    pub command: String,
+    /// Arguments passed to the program
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Where a delivered view's HTML is sent
+///
+/// A webhook receives it as the body of a POST request. A command gets it on
+/// stdin; with `to`, mail headers come first, so `sendmail -t` sends the view
+/// as an email.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DeliveryTarget {
+    Webhook {
+        /// URL to POST to
+        webhook: String,
+    },
+    Command {
+        /// Program to run (looked up on `PATH`), in the database root
+        command: String,
+        /// Arguments passed to the program
+        #[serde(default)]
+        args: Vec<String>,
+        /// Recipient for the `To:` header
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        to: Option<String>,
+    },
+}
+
+/// An external command that embeds text
+///
+/// The text is written to the command's stdin; it prints the embedding as a
+/// JSON array of numbers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddingCommand {
+    /// Program to run (looked up on `PATH`)
+    pub command: String,
+    /// Arguments passed to the program
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Model name stored with each embedding; changing it recomputes them
+    /// (default: the command line)
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Per-document size limits, checked on INSERT and UPDATE
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    /// Maximum body size in bytes
+    pub max_body_bytes: usize,
+    /// Maximum number of frontmatter fields
+    pub max_fields: usize,
+    /// Maximum number of elements in any array value, including nested ones
+    pub max_array_len: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: 1024 * 1024,
+            max_fields: 256,
+            max_array_len: 10_000,
+        }
+    }
+}
+
+impl Limits {
+    /// Check a document against the limits
+    pub fn check(&self, doc: &Document) -> crate::Result<()> {
+        let exceeded = |limit, actual, max| crate::Error::LimitExceeded {
+            id: doc.id.clone(),
+            limit,
+            actual,
+            max,
+        };
+
+        if doc.body.len() > self.max_body_bytes {
+            return Err(exceeded("body size (bytes)", doc.body.len(), self.max_body_bytes));
+        }
+        if doc.fields.len() > self.max_fields {
+            return Err(exceeded("field count", doc.fields.len(), self.max_fields));
+        }
+        let longest = doc.fields.values().map(longest_array).max().unwrap_or(0);
+        if longest > self.max_array_len {
+            return Err(exceeded("array length", longest, self.max_array_len));
+        }
+
+        Ok(())
+    }
+}
+
+/// Length of the longest array within a value
+fn longest_array(value: &Value) -> usize {
+    match value {
+        Value::Array(items) => items.iter().map(longest_array).max().unwrap_or(0).max(items.len()),
+        Value::Object(obj) => obj.values().map(longest_array).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+impl Config {
+    /// Path of the config file for a database root
+    pub fn path(root: &Path) -> PathBuf {
+        root.join(".mdby").join("config.yaml")
+    }
+
+    /// Load the config, returning defaults if the file doesn't exist
+    pub fn load(root: &Path) -> anyhow::Result<Self> {
+        let path = Self::path(root);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        serde_yaml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid config {:?}: {}", path, e))
+    }
+
+    /// Number of views to regenerate concurrently
+    pub fn view_parallelism(&self) -> usize {
+        self.view_parallelism
+            .filter(|n| *n > 0)
+            .unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1))
+    }
+
+    /// Write the config file
+    pub fn save(&self, root: &Path) -> anyhow::Result<()> {
+        let path = Self::path(root);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_config_uses_defaults() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config = Config::load(tmp.path()).unwrap();
+        assert!(config.name.is_none());
+        assert!(config.view_parallelism() >= 1);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            name: Some("notes".into()),
+            template: Some("zettelkasten".into()),
+            ..Default::default()
+        };
+        config.save(tmp.path()).unwrap();
+
+        let loaded = Config::load(tmp.path()).unwrap();
+        assert_eq!(loaded.name.as_deref(), Some("notes"));
+        assert_eq!(loaded.template.as_deref(), Some("zettelkasten"));
+    }
+
+    #[test]
+    fn test_partial_limits_keep_defaults() {
+        let config: Config = serde_yaml::from_str("limits:\n  max_fields: 3\n").unwrap();
+        assert_eq!(config.limits.max_fields, 3);
+        assert_eq!(config.limits.max_body_bytes, Limits::default().max_body_bytes);
+    }
+
+    #[test]
+    fn test_signing() {
+        let yaml = "signing:\n  format: ssh\n  key: ~/.ssh/id_ed25519.pub\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let signing = config.signing.unwrap();
+        assert_eq!(signing.format, SigningFormat::Ssh);
+        assert_eq!(signing.program(), "ssh-keygen");
+
+        let config: Config = serde_yaml::from_str("signing:\n  key: ABCD1234\n").unwrap();
+        assert_eq!(config.signing.unwrap().program(), "gpg");
+    }
+
+    #[test]
+    fn test_render_hooks() {
+        let yaml = "render_hooks:\n  pandoc:\n    command: pandoc\n    args: [-f, markdown, -t, html]\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let hook = &config.render_hooks["pandoc"];
+        assert_eq!(hook.command, "pandoc");
+        assert_eq!(hook.args, ["-f", "markdown", "-t", "html"]);
+    }
+
+    #[test]
+    fn test_sync_settings() {
+        let config: Config = serde_yaml::from_str("sync:\n  strategy: theirs\n").unwrap();
+        assert_eq!(config.sync.strategy, ConflictResolution::Theirs);
+        assert_eq!(config.sync.ssh_key, None);
+
+        // Defaults are left out of the saved file
+        let yaml = serde_yaml::to_string(&Config::default()).unwrap();
+        assert!(!yaml.contains("sync"));
+    }
+
+    #[test]
+    fn test_limits_check_nested_arrays() {
+        let limits = Limits { max_array_len: 2, ..Default::default() };
+        let mut doc = Document::new("a");
+        doc.set("tags", Value::Array(vec![Value::Int(1), Value::Int(2)]));
+        assert!(limits.check(&doc).is_ok());
+
+        let nested = Value::Array(vec![Value::Array(vec![Value::Int(1), Value::Int(2), Value::Int(3)])]);
+        doc.set("matrix", nested);
+        assert!(matches!(
+            limits.check(&doc),
+            Err(crate::Error::LimitExceeded { actual: 3, max: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,203 @@
+//! Duplicate detection (`mdby dedupe`)
+//!
+//! Each document's fields and body are flattened to one text. Documents whose
+//! texts are equal after normalizing case and whitespace are exact duplicates,
+//! found by hash. Other pairs are compared by the Jaccard similarity of their
+//! word 3-grams, and reported when it reaches the threshold.
+//!
+//! A pair is resolved by merging one document into the other: the kept
+//! document gains the fields it lacks, arrays are combined, and the other
+//! document is deleted.
+
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+
+use crate::storage::document::{Document, Value};
+
+/// Default similarity threshold for near-duplicates
+pub const DEFAULT_THRESHOLD: f64 = 0.9;
+
+/// Words per shingle
+const SHINGLE_WORDS: usize = 3;
+
+/// Two documents that look like duplicates
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct DuplicatePair {
+    /// Collection both documents belong to
+    pub collection: String,
+    /// ID of the first document (the smaller ID)
+    pub first: String,
+    /// ID of the second document
+    pub second: String,
+    /// Jaccard similarity of the documents' shingles, from 0 to 1
+    pub similarity: f64,
+    /// Whether the normalized contents are identical
+    pub exact: bool,
+}
+
+/// Pairs of documents at or above `threshold` similarity, most similar first
+pub fn find_duplicates(collection: &str, docs: &[Document], threshold: f64) -> Vec<DuplicatePair> {
+    let mut docs: Vec<&Document> = docs.iter().collect();
+    docs.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let texts: Vec<String> = docs.iter().map(|doc| normalized_text(doc)).collect();
+    let hashes: Vec<[u8; 32]> = texts.iter().map(|text| Sha256::digest(text.as_bytes()).into()).collect();
+    let shingles: Vec<HashSet<&str>> = texts.iter().map(|text| shingles(text)).collect();
+
+    let mut pairs = Vec::new();
+    for i in 0..docs.len() {
+        for j in i + 1..docs.len() {
+            let exact = hashes[i] == hashes[j];
+            let (a, b) = (&shingles[i], &shingles[j]);
+            // Jaccard can't exceed the ratio of the set sizes
+            let bound = a.len().min(b.len()) as f64 / a.len().max(b.len()).max(1) as f64;
+            if !exact && bound < threshold {
+                continue;
+            }
+
+            let similarity = if exact { 1.0 } else { jaccard(a, b) };
+            if similarity >= threshold {
+                pairs.push(DuplicatePair {
+                    collection: collection.to_string(),
+                    first: docs[i].id.clone(),
+                    second: docs[j].id.clone(),
+                    similarity,
+                    exact,
+                });
+            }
+        }
+    }
+
+    pairs.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+    pairs
+}
+
+/// Merge `other` into `keep`
+///
+/// Fields missing from `keep` are copied from `other`, arrays present in both
+/// are combined without repeats, and `other`'s body is used only if `keep`'s
+/// is empty. Fields both documents set to different scalar values keep
+/// `keep`'s value.
+pub fn merge(keep: &Document, other: &Document) -> Document {
+    let mut merged = keep.clone();
+    for (name, value) in &other.fields {
+        match (merged.fields.get_mut(name), value) {
+            (None, _) => {
+                merged.fields.insert(name.clone(), value.clone());
+            }
+            (Some(Value::Array(items)), Value::Array(extra)) => {
+                for item in extra {
+                    if !items.contains(item) {
+                        items.push(item.clone());
+                    }
+                }
+            }
+            (Some(Value::Null), _) => {
+                merged.fields.insert(name.clone(), value.clone());
+            }
+            _ => {}
+        }
+    }
+    if merged.body.trim().is_empty() {
+        merged.body = other.body.clone();
+    }
+    merged
+}
+
+/// Fields as `name: value` lines followed by the body, lowercased, with
+/// whitespace collapsed
+fn normalized_text(doc: &Document) -> String {
+    let mut text = String::new();
+    for (name, value) in &doc.fields {
+        text.push_str(name);
+        text.push_str(": ");
+        text.push_str(&serde_json::to_string(value).unwrap_or_default());
+        text.push('\n');
+    }
+    text.push_str(&doc.body);
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Word 3-grams of a normalized text (the whole text if it's shorter)
+fn shingles(text: &str) -> HashSet<&str> {
+    let starts: Vec<usize> = text
+        .char_indices()
+        .filter(|&(i, c)| c != ' ' && (i == 0 || text.as_bytes()[i - 1] == b' '))
+        .map(|(i, _)| i)
+        .collect();
+    if starts.len() <= SHINGLE_WORDS {
+        return HashSet::from([text]);
+    }
+
+    let mut set = HashSet::new();
+    for (n, &start) in starts.iter().enumerate() {
+        let Some(&next) = starts.get(n + SHINGLE_WORDS) else {
+            set.insert(&text[start..]);
+            break;
+        };
+        set.insert(text[start..next].trim_end());
+    }
+    set
+}
+
+fn jaccard(a: &HashSet<&str>, b: &HashSet<&str>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(id: &str, title: &str, body: &str) -> Document {
+        let mut doc = Document::new(id);
+        doc.set("title", title);
+        doc.body = body.to_string();
+        doc
+    }
+
+    #[test]
+    fn test_exact_and_near_duplicates() {
+        let long = "the quick brown fox jumps over the lazy dog while the cat watches from the fence";
+        let docs = vec![
+            doc("a", "Fox", long),
+            doc("b", "fox", &format!("  {}\n", long.to_uppercase())),
+            doc("c", "Fox", &format!("{} quietly", long)),
+            doc("d", "Other", "something else entirely"),
+        ];
+
+        let pairs = find_duplicates("notes", &docs, 0.8);
+        assert_eq!(pairs[0].first, "a");
+        assert_eq!(pairs[0].second, "b");
+        assert!(pairs[0].exact);
+        assert!(pairs.iter().any(|p| p.first == "a" && p.second == "c" && !p.exact && p.similarity >= 0.8));
+        assert!(pairs.iter().all(|p| p.first != "d" && p.second != "d"));
+
+        assert!(find_duplicates("notes", &docs, 1.0).iter().all(|p| p.exact));
+    }
+
+    #[test]
+    fn test_merge_fills_missing_fields() {
+        let mut keep = doc("a", "Keep", "");
+        keep.set("tags", Value::Array(vec![Value::String("x".into())]));
+        let mut other = doc("b", "Other", "Body from b");
+        other.set("tags", Value::Array(vec![Value::String("x".into()), Value::String("y".into())]));
+        other.set("source", "import");
+
+        let merged = merge(&keep, &other);
+        assert_eq!(merged.id, "a");
+        assert_eq!(merged.get("title").and_then(|v| v.as_str()), Some("Keep"));
+        assert_eq!(merged.get("source").and_then(|v| v.as_str()), Some("import"));
+        assert_eq!(merged.get("tags").and_then(|v| v.as_array()).map(Vec::len), Some(2));
+        assert_eq!(merged.body, "Body from b");
+    }
+
+    #[test]
+    fn test_shingles() {
+        assert_eq!(shingles("one two"), HashSet::from(["one two"]));
+        assert_eq!(shingles("a b c d"), HashSet::from(["a b c", "b c d"]));
+    }
+}
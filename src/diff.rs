@@ -0,0 +1,108 @@
+//! Result set diffing (`mdby diff-query`)
+//!
+//! Two result sets are compared by document ID: documents only in the second
+//! are added, documents only in the first are removed, and documents in both
+//! whose fields or body differ are changed. The result sets can come from two
+//! different queries, or from one query run at two revisions of the database.
+
+use crate::storage::document::Document;
+
+/// Differences between two result sets
+#[derive(Debug, Clone, Default)]
+pub struct ResultDiff {
+    /// Documents only in the second result set
+    pub added: Vec<Document>,
+    /// Documents only in the first result set
+    pub removed: Vec<Document>,
+    /// Documents in both result sets with different contents
+    pub changed: Vec<ChangedDocument>,
+}
+
+impl ResultDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// A document whose contents differ between the result sets
+#[derive(Debug, Clone)]
+pub struct ChangedDocument {
+    pub before: Document,
+    pub after: Document,
+    /// Fields that were added, removed or changed, plus `body` if the body changed
+    pub fields: Vec<String>,
+}
+
+/// Compare two result sets by document ID
+///
+/// Added and changed documents keep the order of `after`, removed documents
+/// the order of `before`.
+pub fn diff(before: &[Document], after: &[Document]) -> ResultDiff {
+    let mut result = ResultDiff::default();
+
+    for doc in after {
+        match before.iter().find(|other| other.id == doc.id) {
+            None => result.added.push(doc.clone()),
+            Some(old) => {
+                let fields = changed_fields(old, doc);
+                if !fields.is_empty() {
+                    result.changed.push(ChangedDocument { before: old.clone(), after: doc.clone(), fields });
+                }
+            }
+        }
+    }
+    for doc in before {
+        if !after.iter().any(|other| other.id == doc.id) {
+            result.removed.push(doc.clone());
+        }
+    }
+    result
+}
+
+/// Names of the fields that differ between two versions of a document
+fn changed_fields(before: &Document, after: &Document) -> Vec<String> {
+    let mut fields: Vec<String> = after
+        .fields
+        .iter()
+        .filter(|(key, value)| before.fields.get(*key) != Some(value))
+        .map(|(key, _)| key.clone())
+        .collect();
+    fields.extend(before.fields.keys().filter(|key| !after.fields.contains_key(*key)).cloned());
+    if before.body != after.body {
+        fields.push("body".to_string());
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn todo(id: &str, status: &str) -> Document {
+        let mut doc = Document::new(id);
+        doc.set("status", status);
+        doc
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = vec![todo("a", "open"), todo("b", "open"), todo("c", "open")];
+        let mut c = todo("c", "open").with_body("Notes");
+        c.set("owner", "ada");
+        let after = vec![todo("d", "open"), todo("b", "done"), c, todo("a", "open")];
+
+        let result = diff(&before, &after);
+        let ids = |docs: &[Document]| docs.iter().map(|d| d.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&result.added), vec!["d"]);
+        assert!(result.removed.is_empty());
+        assert_eq!(result.changed.len(), 2);
+        assert_eq!(result.changed[0].after.id, "b");
+        assert_eq!(result.changed[0].fields, vec!["status"]);
+        assert_eq!(result.changed[1].fields, vec!["owner", "body"]);
+
+        let result = diff(&after, &before);
+        assert_eq!(ids(&result.removed), vec!["d"]);
+        assert_eq!(result.changed[1].fields, vec!["owner", "body"]);
+        assert!(diff(&before, &before).is_empty());
+    }
+}
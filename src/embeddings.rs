@@ -0,0 +1,234 @@
+//! Semantic search over embeddings (`SEMANTIC_SEARCH('text')`)
+//!
+//! An [`EmbeddingProvider`] turns text into a vector. The provider is either
+//! set in code with [`crate::Database::set_embedding_provider`] or declared
+//! under `embeddings` in `/.mdby/config.yaml` as a command that reads text on
+//! stdin and prints a JSON array of numbers.
+//!
+//! Each document's embedding is stored in
+//! `/.mdby/embeddings/{collection}/{id}.json` with the model name and a hash
+//! of the embedded text, and is only recomputed when either changes. The
+//! directory ignores itself in git: embeddings can always be recomputed.
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::EmbeddingCommand;
+use crate::query::related::document_text;
+use crate::storage::document::Document;
+
+/// Turns text into embedding vectors
+pub trait EmbeddingProvider: Send + Sync {
+    /// Name of the model; stored embeddings from another model are recomputed
+    fn model(&self) -> String;
+
+    /// Embedding of one text
+    fn embed(&self, text: &str) -> anyhow::Result<Vec<f32>>;
+}
+
+/// Provider running the command declared under `embeddings` in the config
+pub struct CommandProvider {
+    command: EmbeddingCommand,
+}
+
+impl CommandProvider {
+    pub fn new(command: EmbeddingCommand) -> Self {
+        Self { command }
+    }
+}
+
+impl EmbeddingProvider for CommandProvider {
+    fn model(&self) -> String {
+        self.command.model.clone().unwrap_or_else(|| {
+            std::iter::once(self.command.command.as_str())
+                .chain(self.command.args.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+    }
+
+    fn embed(&self, text: &str) -> anyhow::Result<Vec<f32>> {
+        let mut child = Command::new(&self.command.command)
+            .args(&self.command.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Could not run {}: {}", self.command.command, e))?;
+
+        let mut stdin = child.stdin.take().ok_or_else(|| anyhow::anyhow!("stdin unavailable"))?;
+        stdin.write_all(text.as_bytes())?;
+        drop(stdin);
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("{} failed: {} ({})", self.command.command, stderr.trim(), output.status);
+        }
+        serde_json::from_slice(&output.stdout)
+            .map_err(|e| anyhow::anyhow!("{} did not print a JSON array of numbers: {}", self.command.command, e))
+    }
+}
+
+/// A document's embedding as stored on disk
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoredEmbedding {
+    model: String,
+    /// Hex SHA-256 of the embedded text
+    hash: String,
+    vector: Vec<f32>,
+}
+
+/// Directory holding the stored embeddings of every collection
+pub fn embeddings_dir(root: &Path) -> PathBuf {
+    root.join(".mdby").join("embeddings")
+}
+
+/// Cosine similarity of each document to `query`, keyed by document ID
+///
+/// `docs` is the whole collection: missing or outdated embeddings are
+/// computed, and stored ones of documents that no longer exist are removed.
+/// With `store` off (read-only replicas), nothing is written and embeddings
+/// that aren't stored yet are computed for this search only.
+pub fn search(
+    root: &Path,
+    provider: &dyn EmbeddingProvider,
+    collection: &str,
+    docs: &[Document],
+    query: &str,
+    store: bool,
+) -> anyhow::Result<HashMap<String, f64>> {
+    let model = provider.model();
+    let dir = embeddings_dir(root).join(collection);
+    if store {
+        std::fs::create_dir_all(&dir)?;
+        let gitignore = embeddings_dir(root).join(".gitignore");
+        if !gitignore.exists() {
+            std::fs::write(&gitignore, "*\n")?;
+        }
+        remove_stale(&dir, docs)?;
+    }
+
+    let query = provider.embed(query)?;
+    let mut scores = HashMap::new();
+    for doc in docs {
+        let text = document_text(doc);
+        let hash = format!("{:x}", Sha256::digest(text.as_bytes()));
+        let path = dir.join(format!("{}.json", doc.id));
+
+        let stored = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<StoredEmbedding>(&content).ok())
+            .filter(|stored| stored.model == model && stored.hash == hash);
+        let vector = match stored {
+            Some(stored) => stored.vector,
+            None => {
+                let vector = provider
+                    .embed(&text)
+                    .map_err(|e| anyhow::anyhow!("Embedding '{}' failed: {}", doc.id, e))?;
+                if store {
+                    let stored = StoredEmbedding { model: model.clone(), hash, vector };
+                    std::fs::write(&path, serde_json::to_string(&stored)?)?;
+                    stored.vector
+                } else {
+                    vector
+                }
+            }
+        };
+
+        if vector.len() != query.len() {
+            anyhow::bail!(
+                "Embedding of '{}' has {} dimensions but the query's has {}",
+                doc.id,
+                vector.len(),
+                query.len()
+            );
+        }
+        scores.insert(doc.id.clone(), cosine(&query, &vector));
+    }
+
+    Ok(scores)
+}
+
+/// Remove stored embeddings of documents not in `docs`
+fn remove_stale(dir: &Path, docs: &[Document]) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(id) = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix(".json")) else {
+            continue;
+        };
+        if !docs.iter().any(|doc| doc.id == id) {
+            std::fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| f64::from(*x) * f64::from(*y)).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| f64::from(*x).powi(2)).sum::<f64>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempfile::TempDir;
+
+    /// Counts of a few words, and how many texts it embedded
+    struct WordCounts {
+        calls: AtomicUsize,
+    }
+
+    impl EmbeddingProvider for WordCounts {
+        fn model(&self) -> String {
+            "word-counts".to_string()
+        }
+
+        fn embed(&self, text: &str) -> anyhow::Result<Vec<f32>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let text = text.to_lowercase();
+            Ok(["cat", "dog", "car"].iter().map(|w| text.matches(w).count() as f32).collect())
+        }
+    }
+
+    fn doc(id: &str, body: &str) -> Document {
+        let mut doc = Document::new(id);
+        doc.body = body.to_string();
+        doc
+    }
+
+    #[test]
+    fn test_search_stores_and_reuses_embeddings() {
+        let tmp = TempDir::new().unwrap();
+        let provider = WordCounts { calls: AtomicUsize::new(0) };
+        let docs = vec![doc("pets", "cat and dog"), doc("cars", "car car"), doc("kitten", "cat")];
+
+        let scores = search(tmp.path(), &provider, "notes", &docs, "a cat", true).unwrap();
+        assert!(scores["kitten"] > scores["pets"] && scores["pets"] > scores["cars"]);
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 4);
+        assert!(tmp.path().join(".mdby/embeddings/notes/pets.json").exists());
+
+        // Only the query and the changed document are embedded again
+        let docs = vec![doc("pets", "dog"), doc("cars", "car car")];
+        search(tmp.path(), &provider, "notes", &docs, "a cat", true).unwrap();
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 6);
+        assert!(!tmp.path().join(".mdby/embeddings/notes/kitten.json").exists());
+    }
+
+    #[test]
+    fn test_cosine() {
+        assert!((cosine(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-9);
+        assert_eq!(cosine(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+    }
+}
@@ -37,6 +37,32 @@ pub enum Error {
     #[error("INSERT requires an 'id' column")]
     MissingDocumentId,
 
+    #[error("Document '{id}' exceeds the {limit} limit: {actual} > {max}")]
+    LimitExceeded {
+        id: String,
+        limit: &'static str,
+        actual: usize,
+        max: usize,
+    },
+
+    #[error("Cannot delete document '{id}' from collection '{collection}': referenced by {referrer}")]
+    DocumentReferenced {
+        collection: String,
+        id: String,
+        /// `collection/id` of a document pointing at it
+        referrer: String,
+    },
+
+    #[error("Document '{id}' in collection '{collection}' has changed since the version given")]
+    DocumentChanged { collection: String, id: String },
+
+    #[error("Document '{id}' in collection '{collection}' failed lint rules: {message}")]
+    LintFailed {
+        collection: String,
+        id: String,
+        message: String,
+    },
+
     // ==========================================================================
     // View Errors
     // ==========================================================================
@@ -84,6 +110,15 @@ pub enum Error {
     #[error("Query execution error: {message}")]
     QueryError { message: String },
 
+    #[error("{statement} is not allowed by the execution policy")]
+    StatementDenied { statement: &'static str },
+
+    #[error("{statement} on collection '{collection}' is not allowed by the execution policy")]
+    CollectionDenied {
+        statement: &'static str,
+        collection: String,
+    },
+
     // ==========================================================================
     // Git Errors
     // ==========================================================================
@@ -221,6 +256,11 @@ impl From<crate::schema::ValidationError> for Error {
                 collection: String::new(),
                 message: format!("Unique constraint violated for field: {}", field),
             },
+            err @ (crate::schema::ValidationError::UniqueCombinationViolation(_)
+            | crate::schema::ValidationError::MissingReference { .. }) => Error::SchemaValidation {
+                collection: String::new(),
+                message: err.to_string(),
+            },
         }
     }
 }
@@ -248,6 +288,18 @@ impl Error {
             Error::MissingRequiredField { .. } => {
                 Some("Add the required field to your INSERT statement")
             }
+            Error::LimitExceeded { .. } => {
+                Some("Raise the limit under 'limits:' in .mdby/config.yaml if this is intended")
+            }
+            Error::DocumentReferenced { .. } => {
+                Some("Delete or update the referencing documents first (the REF field is ON DELETE RESTRICT)")
+            }
+            Error::DocumentChanged { .. } => {
+                Some("Read the document again and retry with its current version")
+            }
+            Error::LintFailed { .. } => {
+                Some("Fix the document, or set 'enforce: false' for the collection in .mdby/lint.yaml")
+            }
             _ => None,
         }
     }
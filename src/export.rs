@@ -0,0 +1,305 @@
+//! Database export (`mdby export`)
+//!
+//! Copies a database's documents, schemas, views, templates and settings into
+//! a new directory that `mdby` can open. With scrubbing, each field's `scrub`
+//! rule and the schema's `scrub_body` rule are applied on the way out:
+//!
+//! ```yaml
+//! fields:
+//!   email:
+//!     type: string
+//!     scrub: hash          # a salted hash; emails keep an `@example.invalid` domain
+//!   phone:
+//!     scrub: drop          # left out
+//! scrub_body:
+//!   truncate: 200          # first 200 characters
+//! ```
+//!
+//! Hashes are salted per export, so equal values stay equal within an export
+//! but can't be looked up from a list of known values. Document IDs are kept,
+//! so a `REF` field with a `hash` rule no longer resolves. A scrubbed export leaves out
+//! `.mdby/config.yaml`, which can hold webhook URLs and key paths.
+//!
+//! A single collection can also be written as CSV or JSON, in the layouts
+//! [`crate::import`] reads back.
+
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::schema::{Schema, Scrub};
+use crate::storage::collection::Collection;
+use crate::storage::document::{Document, Value};
+use crate::validation::validate_collection_name;
+use crate::Database;
+
+/// Files and directories under `.mdby` that are exported
+const DEFINITIONS: &[&str] = &["schemas", "views", "templates", "counters", "lint.yaml", "config.yaml"];
+
+/// What an export wrote
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportSummary {
+    pub collections: usize,
+    pub documents: usize,
+    /// Field values and bodies changed or removed by scrub rules
+    pub scrubbed: usize,
+}
+
+/// Export the database into `dest`, which must be empty or not exist
+pub async fn export(db: &Database, dest: &Path, scrub: bool) -> anyhow::Result<ExportSummary> {
+    if dest.exists() && std::fs::read_dir(dest)?.next().is_some() {
+        anyhow::bail!("Export destination {:?} is not empty", dest);
+    }
+
+    let source = db.root.join(".mdby");
+    for name in DEFINITIONS {
+        if scrub && *name == "config.yaml" {
+            continue;
+        }
+        copy(&source.join(name), &dest.join(".mdby").join(name))?;
+    }
+
+    let salt = uuid::Uuid::new_v4().to_string();
+    let mut summary = ExportSummary::default();
+    let collections_path = db.root.join("collections");
+    let mut names = Vec::new();
+    if collections_path.exists() {
+        for entry in std::fs::read_dir(&collections_path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                names.extend(entry.file_name().to_str().map(String::from));
+            }
+        }
+    }
+    names.sort();
+
+    for name in names {
+        let schema = db.schema.get(&name);
+        let target = Collection::open(&name, dest);
+        let target = match schema {
+            Some(schema) => target.with_field_order(schema.field_order()),
+            None => target,
+        };
+        target.ensure_exists().await?;
+
+        for mut doc in Collection::open(&name, &db.root).list().await? {
+            if let (true, Some(schema)) = (scrub, schema) {
+                summary.scrubbed += scrub_document(schema, &mut doc, &salt);
+            }
+            target.insert(&doc).await?;
+            summary.documents += 1;
+        }
+        summary.collections += 1;
+    }
+
+    Ok(summary)
+}
+
+/// Write a collection as CSV, returning the number of documents
+///
+/// Columns are `id`, the schema's fields in order, any other fields in
+/// alphabetical order, then `body`. Array items are joined with `;` and
+/// objects written as JSON, so [`crate::import::import_csv`] reads the file
+/// back into the same documents.
+pub async fn to_csv(db: &Database, name: &str, output: impl Write) -> anyhow::Result<usize> {
+    validate_collection_name(name)?;
+    let collection = Collection::open(name, &db.root);
+    if !collection.exists().await {
+        anyhow::bail!("Collection '{}' does not exist", name);
+    }
+    let mut docs = collection.list().await?;
+    docs.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let fields = db.schema.get(name).map(|schema| schema.field_order()).unwrap_or_default();
+    write_csv(&docs, fields, output)?;
+    Ok(docs.len())
+}
+
+/// Write documents as CSV: `id`, the `fields` given, any other fields in
+/// alphabetical order, then `body`
+pub(crate) fn write_csv(docs: &[Document], mut fields: Vec<String>, output: impl Write) -> anyhow::Result<()> {
+    let others: BTreeSet<&String> = docs
+        .iter()
+        .flat_map(|doc| doc.fields.keys())
+        .filter(|field| !fields.contains(field))
+        .collect();
+    fields.extend(others.into_iter().cloned());
+
+    let mut writer = csv::Writer::from_writer(output);
+    writer.write_record(std::iter::once("id").chain(fields.iter().map(String::as_str)).chain(["body"]))?;
+    for doc in docs {
+        let mut record = vec![doc.id.clone()];
+        record.extend(fields.iter().map(|field| doc.fields.get(field).map(csv_cell).unwrap_or_default()));
+        record.push(doc.body.clone());
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write a collection as JSON, returning the number of documents
+///
+/// Documents are objects shaped like the server returns them: `id`, the
+/// fields, and `_body` unless the body is empty. They are written as an
+/// array, or with `lines` one per line as JSON Lines.
+pub async fn to_json(db: &Database, name: &str, mut output: impl Write, lines: bool) -> anyhow::Result<usize> {
+    validate_collection_name(name)?;
+    let collection = Collection::open(name, &db.root);
+    if !collection.exists().await {
+        anyhow::bail!("Collection '{}' does not exist", name);
+    }
+    let mut docs = collection.list().await?;
+    docs.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let objects: Vec<serde_json::Value> = docs.iter().map(crate::server::document_json).collect();
+    if lines {
+        for object in &objects {
+            serde_json::to_writer(&mut output, object)?;
+            writeln!(output)?;
+        }
+    } else {
+        serde_json::to_writer_pretty(&mut output, &objects)?;
+        writeln!(output)?;
+    }
+    output.flush()?;
+
+    Ok(docs.len())
+}
+
+/// A value as CSV cell text
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Bool(b) => b.to_string(),
+        Value::Int(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items
+            .iter()
+            .map(csv_cell)
+            .collect::<Vec<_>>()
+            .join(&crate::import::CSV_ARRAY_SEPARATOR.to_string()),
+        Value::Object(_) => serde_json::to_string(value).unwrap_or_default(),
+    }
+}
+
+/// Apply a schema's scrub rules to a document, returning the number of
+/// values changed or removed
+pub fn scrub_document(schema: &Schema, doc: &mut Document, salt: &str) -> usize {
+    let mut scrubbed = 0;
+    for (name, def) in &schema.fields {
+        let (Some(rule), Some(value)) = (def.scrub, doc.fields.get(name)) else {
+            continue;
+        };
+        match scrub_value(rule, value, salt) {
+            Some(new) if new == *value => {}
+            Some(new) => {
+                doc.fields.insert(name.clone(), new);
+                scrubbed += 1;
+            }
+            None => {
+                doc.fields.remove(name);
+                scrubbed += 1;
+            }
+        }
+    }
+
+    if let Some(rule) = schema.scrub_body.filter(|_| !doc.body.is_empty()) {
+        let body = match rule {
+            Scrub::Drop => String::new(),
+            Scrub::Hash => hash(&doc.body, salt),
+            Scrub::Truncate(n) => doc.body.chars().take(n).collect(),
+        };
+        if body != doc.body {
+            doc.body = body;
+            scrubbed += 1;
+        }
+    }
+    scrubbed
+}
+
+/// A scrubbed value, or `None` to leave the field out
+fn scrub_value(rule: Scrub, value: &Value, salt: &str) -> Option<Value> {
+    match (rule, value) {
+        (Scrub::Drop, _) => None,
+        (_, Value::Array(items)) => Some(Value::Array(items.iter().filter_map(|v| scrub_value(rule, v, salt)).collect())),
+        (_, Value::Null) => Some(Value::Null),
+        (Scrub::Hash, Value::String(s)) => Some(Value::String(hash(s, salt))),
+        (Scrub::Hash, other) => Some(Value::String(hash(&serde_json::to_string(other).unwrap_or_default(), salt))),
+        (Scrub::Truncate(n), Value::String(s)) => Some(Value::String(s.chars().take(n).collect())),
+        (Scrub::Truncate(_), other) => Some(other.clone()),
+    }
+}
+
+/// First 12 hex digits of the salted SHA-256 of `text`; emails keep an email shape
+fn hash(text: &str, salt: &str) -> String {
+    let digest = Sha256::new().chain_update(salt).chain_update(text).finalize();
+    let hex: String = digest.iter().take(6).map(|b| format!("{:02x}", b)).collect();
+    if text.contains('@') {
+        format!("{}@example.invalid", hex)
+    } else {
+        hex
+    }
+}
+
+/// Copy a file or directory tree, if it exists
+fn copy(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if from.is_file() {
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(from, to)?;
+        return Ok(());
+    }
+    if !from.is_dir() {
+        return Ok(());
+    }
+    for entry in WalkDir::new(from).into_iter().filter_map(|e| e.ok()) {
+        let target = to.join(entry.path().strip_prefix(from)?);
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::FieldDef;
+
+    #[test]
+    fn test_scrub_document() {
+        let rule = |scrub| FieldDef { scrub: Some(scrub), ..Default::default() };
+        let mut schema = Schema::new("people")
+            .field("email", rule(Scrub::Hash))
+            .field("phone", rule(Scrub::Drop))
+            .field("aliases", rule(Scrub::Truncate(2)))
+            .field("name", FieldDef::default());
+        schema.scrub_body = Some(Scrub::Truncate(5));
+
+        let mut doc = Document::new("ada").with_body("Private notes");
+        doc.set("email", "ada@example.com");
+        doc.set("phone", "555-0100");
+        doc.set("name", "Ada");
+        doc.fields.insert("aliases".into(), Value::Array(vec!["Countess".into(), "AL".into()]));
+        let mut other = doc.clone();
+
+        assert_eq!(scrub_document(&schema, &mut doc, "salt"), 4);
+        let email = doc.fields["email"].as_str().unwrap();
+        assert!(email.ends_with("@example.invalid") && !email.contains("ada"));
+        assert!(!doc.fields.contains_key("phone"));
+        assert_eq!(doc.fields["aliases"], Value::Array(vec!["Co".into(), "AL".into()]));
+        assert_eq!(doc.fields["name"], Value::from("Ada"));
+        assert_eq!(doc.body, "Priva");
+
+        // Same salt, same hash; another salt, another hash
+        scrub_document(&schema, &mut other, "salt");
+        assert_eq!(other.fields["email"], doc.fields["email"]);
+        assert_ne!(hash("ada@example.com", "pepper"), email);
+    }
+}
@@ -0,0 +1,140 @@
+//! Fuzzy document finder (`mdby find`)
+//!
+//! Each whitespace-separated term of the pattern must match a document's ID
+//! or title the way fzf matches: the term's characters appear in order,
+//! ignoring case. A match scores higher when its characters are consecutive,
+//! start a word or start the text, and lower for every character skipped
+//! between them. A document's score is the sum of its terms' best scores.
+
+use crate::Database;
+
+/// Points for each matched character
+const MATCH: i64 = 16;
+/// Extra points for a character right after the previous match
+const CONSECUTIVE: i64 = 12;
+/// Extra points for a character starting a word (after a space, `-`, `_`, `/` or `.`)
+const WORD_START: i64 = 8;
+/// Extra points for matching the first character of the text
+const TEXT_START: i64 = 4;
+
+/// A document matching a pattern
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct FindMatch {
+    /// Collection the document belongs to
+    pub collection: String,
+    /// Document ID
+    pub id: String,
+    /// The document's title, if it has one
+    pub title: Option<String>,
+    /// Higher is better
+    pub score: i64,
+}
+
+/// Documents in every collection matching `pattern`, best first, at most
+/// `limit` of them
+///
+/// Titles come from each collection's lint `title_field`, `title` by default.
+pub async fn find(db: &Database, pattern: &str, limit: usize) -> anyhow::Result<Vec<FindMatch>> {
+    let terms: Vec<String> = pattern.split_whitespace().map(str::to_lowercase).collect();
+    if terms.is_empty() {
+        anyhow::bail!("Give some text to search for");
+    }
+
+    let mut matches = Vec::new();
+    for (collection, docs) in db.all_documents().await? {
+        let title_field = db
+            .lint
+            .rules(&collection)
+            .and_then(|rules| rules.title_field.clone())
+            .unwrap_or_else(|| "title".to_string());
+        for doc in docs {
+            let title = doc.get(&title_field).and_then(|v| v.as_str()).map(str::to_string);
+            // Each term's best score over the ID and title, with the length
+            // of the text it matched in for breaking ties
+            let total = terms.iter().try_fold((0, 0), |(total, length), term| {
+                let in_id = score(term, &doc.id).map(|score| (score, -(doc.id.len() as i64)));
+                let in_title = title.as_deref().and_then(|title| Some((score(term, title)?, -(title.len() as i64))));
+                let (best, negated_length) = in_id.max(in_title)?;
+                Some((total + best, length - negated_length))
+            });
+            if let Some((score, length)) = total {
+                matches.push((length, FindMatch { collection: collection.clone(), id: doc.id, title, score }));
+            }
+        }
+    }
+
+    // Best first; among equals, a match in shorter text is likelier to be what was meant
+    matches.sort_by(|(a_length, a), (b_length, b)| {
+        b.score
+            .cmp(&a.score)
+            .then(a_length.cmp(b_length))
+            .then_with(|| (&a.collection, &a.id).cmp(&(&b.collection, &b.id)))
+    });
+    Ok(matches.into_iter().take(limit).map(|(_, m)| m).collect())
+}
+
+/// Score of the best match of `term` (lowercase) in `text`, or `None` if its
+/// characters don't all appear in order
+pub fn score(term: &str, text: &str) -> Option<i64> {
+    let term: Vec<char> = term.chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    if term.is_empty() || term.len() > text.len() {
+        return None;
+    }
+
+    let bonus = |j: usize| {
+        let start = match j {
+            0 => TEXT_START + WORD_START,
+            _ if matches!(text[j - 1], ' ' | '-' | '_' | '/' | '.') => WORD_START,
+            _ => 0,
+        };
+        MATCH + start
+    };
+
+    // best[j]: best score for the terms so far with the last one matched at j
+    let mut best: Vec<Option<i64>> = text.iter().enumerate().map(|(j, &c)| (c == term[0]).then(|| bonus(j))).collect();
+    for &c in &term[1..] {
+        let mut next = vec![None; text.len()];
+        // Best of best[k] + k over k < j - 1, so a gap of j - k - 1 costs one point per character
+        let mut gapped: Option<i64> = None;
+        for j in 1..text.len() {
+            if j >= 2 {
+                if let Some(score) = best[j - 2] {
+                    gapped = Some(gapped.map_or(score + j as i64 - 2, |g| g.max(score + j as i64 - 2)));
+                }
+            }
+            if text[j] != c {
+                continue;
+            }
+            let after_gap = gapped.map(|g| g - (j as i64 - 1));
+            let consecutive = best[j - 1].map(|score| score + CONSECUTIVE);
+            next[j] = after_gap.max(consecutive).map(|score| score + bonus(j));
+        }
+        best = next;
+    }
+
+    best.into_iter().flatten().max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_needs_characters_in_order() {
+        assert!(score("rst", "rust-intro").is_some());
+        assert!(score("tsr", "rust-intro").is_none());
+        assert!(score("RuSt", "Rust").is_none()); // terms are lowercased by the caller
+        assert!(score("rust", "RUST").is_some());
+    }
+
+    #[test]
+    fn test_score_prefers_consecutive_and_word_starts() {
+        // Consecutive beats scattered
+        assert!(score("intro", "rust-intro") > score("intro", "i-n-t-r-o"));
+        // A word start beats the middle of a word
+        assert!(score("in", "rust-intro") > score("in", "rusting"));
+        // Fewer skipped characters is better
+        assert!(score("ri", "rust-intro") > score("ri", "rust-long-intro"));
+    }
+}
@@ -3,21 +3,29 @@
 //! When concurrent edits create conflicts, MDBY resolves them using
 //! document-aware merge strategies.
 //!
-//! Note: These functions are currently unused but will be integrated
-//! when git sync (push/pull) is implemented.
+//! Every automatic resolution is recorded in `/.mdby/conflicts/{id}-{timestamp}.md`
+//! with the versions that went into it, so a bad merge can be reviewed and
+//! reverted by hand.
+//!
+//! `ours` is always the local version and `theirs` the incoming one: the
+//! remote's history during `mdby sync`, or the proposal being applied.
 
-#![allow(dead_code)]
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use crate::storage::document::Document;
+use crate::storage::frontmatter;
 
 /// Strategy for resolving conflicts
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ConflictResolution {
     /// Keep the local version
     Ours,
     /// Keep the remote version
     Theirs,
     /// Merge fields individually, preferring newer values
+    #[default]
     MergeFields,
     /// Concatenate body content with conflict markers
     ConcatenateBody,
@@ -25,12 +33,140 @@ pub enum ConflictResolution {
     Manual,
 }
 
-impl Default for ConflictResolution {
-    fn default() -> Self {
-        Self::MergeFields
+impl ConflictResolution {
+    /// Name used in conflict records
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConflictResolution::Ours => "ours",
+            ConflictResolution::Theirs => "theirs",
+            ConflictResolution::MergeFields => "merge_fields",
+            ConflictResolution::ConcatenateBody => "concatenate_body",
+            ConflictResolution::Manual => "manual",
+        }
+    }
+}
+
+impl FromStr for ConflictResolution {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        [
+            ConflictResolution::Ours,
+            ConflictResolution::Theirs,
+            ConflictResolution::MergeFields,
+            ConflictResolution::ConcatenateBody,
+            ConflictResolution::Manual,
+        ]
+        .into_iter()
+        .find(|strategy| strategy.name() == s)
+        .ok_or_else(|| {
+            anyhow::anyhow!("Unknown conflict strategy '{}' (ours, theirs, merge_fields, concatenate_body, manual)", s)
+        })
     }
 }
 
+/// An automatically resolved conflict, as saved under `.mdby/conflicts/`
+#[derive(Debug, Clone)]
+pub struct ConflictRecord {
+    /// Collection of the conflicted document
+    pub collection: String,
+    /// Document ID
+    pub id: String,
+    /// Strategy that produced the merged version
+    pub strategy: ConflictResolution,
+    /// Record file, relative to the database root
+    pub path: PathBuf,
+}
+
+/// Resolve a conflict and record the versions that went into it
+pub fn resolve_recorded(
+    root: &Path,
+    collection: &str,
+    base: Option<&Document>,
+    ours: &Document,
+    theirs: &Document,
+    strategy: ConflictResolution,
+) -> anyhow::Result<(Document, ConflictRecord)> {
+    let merged = resolve(base, ours, theirs, strategy)?;
+    let record = record(root, collection, base, ours, theirs, &merged, strategy)?;
+    Ok((merged, record))
+}
+
+/// Write the pre-merge and merged versions of a document to a conflict record
+fn record(
+    root: &Path,
+    collection: &str,
+    base: Option<&Document>,
+    ours: &Document,
+    theirs: &Document,
+    merged: &Document,
+    strategy: ConflictResolution,
+) -> anyhow::Result<ConflictRecord> {
+    let dir = Path::new(".mdby").join("conflicts");
+    std::fs::create_dir_all(root.join(&dir))?;
+
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+    let time = chrono::DateTime::from_timestamp(secs, 0)
+        .ok_or_else(|| anyhow::anyhow!("System time out of range"))?;
+    let stamp = time.format("%Y%m%dT%H%M%SZ").to_string();
+
+    // Two conflicts on the same ID within a second get distinct files
+    let mut path = dir.join(format!("{}-{}.md", ours.id, stamp));
+    let mut n = 2;
+    while root.join(&path).exists() {
+        path = dir.join(format!("{}-{}-{}.md", ours.id, stamp, n));
+        n += 1;
+    }
+
+    let mut fields = crate::storage::document::Fields::new();
+    fields.insert("collection".into(), collection.into());
+    fields.insert("id".into(), ours.id.as_str().into());
+    fields.insert("strategy".into(), strategy.name().into());
+    fields.insert(
+        "resolved_at".into(),
+        time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true).into(),
+    );
+
+    let mut body = format!(
+        "# Conflict in {collection}/{id}\n\n\
+         Resolved automatically with `{strategy}`. To undo the merge, copy one of\n\
+         the versions below back to `collections/{collection}/{id}.md`.\n",
+        collection = collection,
+        id = ours.id,
+        strategy = strategy.name(),
+    );
+    let mut versions = vec![("Ours", ours), ("Theirs", theirs)];
+    if let Some(base) = base {
+        versions.push(("Base", base));
+    }
+    versions.push(("Merged", merged));
+    for (title, doc) in versions {
+        body.push_str(&format!("\n## {}\n\n{}", title, fenced(&doc.render())));
+    }
+
+    std::fs::write(root.join(&path), frontmatter::render(&fields, &body))?;
+
+    Ok(ConflictRecord {
+        collection: collection.to_string(),
+        id: ours.id.clone(),
+        strategy,
+        path,
+    })
+}
+
+/// A markdown code block whose fence is longer than any backtick run in `content`
+fn fenced(content: &str) -> String {
+    let longest = content
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{}markdown\n{}\n{}\n", fence, content.trim_end(), fence)
+}
+
 /// Resolve a conflict between two document versions
 pub fn resolve(
     base: Option<&Document>,
@@ -91,11 +227,10 @@ fn merge_fields(
         }
     }
 
-    // For body, prefer theirs if different (last-write-wins)
-    result.body = if ours.body == theirs.body {
-        ours.body.clone()
-    } else {
-        theirs.body.clone()
+    // For body, keep the side that changed it; if both did, prefer theirs
+    result.body = match base {
+        Some(base) if theirs.body == base.body => ours.body.clone(),
+        _ => theirs.body.clone(),
     };
 
     Ok(result)
@@ -156,4 +291,56 @@ mod tests {
         // Theirs wins in true conflicts
         assert_eq!(result.get("title"), Some(&Value::String("Their Title".into())));
     }
+
+    #[test]
+    fn test_merge_fields_keeps_changed_body() {
+        let mut base = Document::new("test");
+        base.set("title", "Base");
+        base.body = "Original".into();
+
+        let mut ours = base.clone();
+        ours.body = "Edited locally".into();
+        let mut theirs = base.clone();
+        theirs.set("title", "Renamed");
+
+        let result = merge_fields(Some(&base), &ours, &theirs).unwrap();
+        assert_eq!(result.body, "Edited locally");
+        assert_eq!(result.get("title"), Some(&Value::String("Renamed".into())));
+    }
+
+    #[test]
+    fn test_strategy_names() {
+        assert_eq!("concatenate_body".parse::<ConflictResolution>().unwrap(), ConflictResolution::ConcatenateBody);
+        assert!("newest".parse::<ConflictResolution>().is_err());
+        let yaml = serde_yaml::to_string(&ConflictResolution::MergeFields).unwrap();
+        assert_eq!(yaml.trim(), "merge_fields");
+    }
+
+    #[test]
+    fn test_resolve_recorded_saves_versions() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let mut ours = Document::new("task-1");
+        ours.set("title", "Ours");
+        ours.body = "```\ncode\n```".into();
+        let mut theirs = Document::new("task-1");
+        theirs.set("title", "Theirs");
+
+        let (merged, record) =
+            resolve_recorded(tmp.path(), "todos", None, &ours, &theirs, ConflictResolution::Theirs).unwrap();
+        assert_eq!(merged.get("title"), theirs.get("title"));
+        assert!(record.path.starts_with(".mdby/conflicts"));
+        assert!(record.path.file_name().unwrap().to_str().unwrap().starts_with("task-1-"));
+
+        let saved = std::fs::read_to_string(tmp.path().join(&record.path)).unwrap();
+        let (fields, body) = frontmatter::parse(&saved).unwrap();
+        assert_eq!(fields.get("strategy"), Some(&Value::String("theirs".into())));
+        assert!(body.contains("## Ours\n\n````markdown\n---\ntitle: Ours"));
+        assert!(body.contains("## Merged"));
+        assert!(!body.contains("## Base"));
+
+        // A second conflict in the same second doesn't overwrite the first
+        let (_, second) =
+            resolve_recorded(tmp.path(), "todos", None, &ours, &theirs, ConflictResolution::Ours).unwrap();
+        assert_ne!(record.path, second.path);
+    }
 }
@@ -0,0 +1,132 @@
+//! Credentials for fetching from and pushing to remotes
+//!
+//! SSH remotes are tried with the SSH agent, then with the key set as
+//! `sync.ssh_key` in `/.mdby/config.yaml` (or `~/.ssh/id_ed25519` and
+//! `~/.ssh/id_rsa`), unlocked with `MDBY_SSH_PASSPHRASE` if it's set. HTTPS
+//! remotes use the token in `MDBY_GIT_TOKEN` (with `MDBY_GIT_USERNAME`), then
+//! git's configured credential helper. Each method is tried once, so wrong
+//! credentials fail the sync instead of retrying forever.
+
+use git2::{Cred, CredentialType, ErrorClass, ErrorCode, FetchOptions, PushOptions, RemoteCallbacks};
+use std::path::PathBuf;
+
+use super::signing::expand_home;
+use super::Repository;
+
+/// Default SSH keys, relative to the home directory
+const DEFAULT_KEYS: &[&str] = &["~/.ssh/id_ed25519", "~/.ssh/id_rsa"];
+
+/// Methods already tried for one connection
+#[derive(Default)]
+struct Attempts {
+    agent: bool,
+    keys: usize,
+    token: bool,
+    helper: bool,
+    default: bool,
+}
+
+impl Repository {
+    /// Use this private key for SSH remotes when the agent has none
+    pub fn set_ssh_key(&mut self, key: Option<String>) {
+        self.ssh_key = key;
+    }
+
+    /// Fetch options that authenticate with the remote
+    pub(super) fn fetch_options(&self) -> FetchOptions<'_> {
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(self.remote_callbacks());
+        options
+    }
+
+    /// Push options that authenticate with the remote
+    pub(super) fn push_options<'a>(&'a self, mut callbacks: RemoteCallbacks<'a>) -> PushOptions<'a> {
+        self.add_credentials(&mut callbacks);
+        let mut options = PushOptions::new();
+        options.remote_callbacks(callbacks);
+        options
+    }
+
+    fn remote_callbacks(&self) -> RemoteCallbacks<'_> {
+        let mut callbacks = RemoteCallbacks::new();
+        self.add_credentials(&mut callbacks);
+        callbacks
+    }
+
+    fn add_credentials<'a>(&'a self, callbacks: &mut RemoteCallbacks<'a>) {
+        let keys = self.ssh_keys();
+        let mut tried = Attempts::default();
+        callbacks.credentials(move |url, username, allowed| {
+            let user = username.unwrap_or("git");
+
+            if allowed.contains(CredentialType::USERNAME) {
+                return Cred::username(user);
+            }
+
+            if allowed.contains(CredentialType::SSH_KEY) {
+                if !tried.agent && std::env::var_os("SSH_AUTH_SOCK").is_some() {
+                    tried.agent = true;
+                    return Cred::ssh_key_from_agent(user);
+                }
+                if let Some(key) = keys.get(tried.keys) {
+                    tried.keys += 1;
+                    let passphrase = std::env::var("MDBY_SSH_PASSPHRASE").ok();
+                    return Cred::ssh_key(user, None, key, passphrase.as_deref());
+                }
+            }
+
+            if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+                if !tried.token {
+                    tried.token = true;
+                    if let Ok(token) = std::env::var("MDBY_GIT_TOKEN") {
+                        let name = std::env::var("MDBY_GIT_USERNAME").unwrap_or_else(|_| user.to_string());
+                        return Cred::userpass_plaintext(&name, &token);
+                    }
+                }
+                if !tried.helper {
+                    tried.helper = true;
+                    if let Ok(cred) = self.inner.config().and_then(|config| Cred::credential_helper(&config, url, username)) {
+                        return Ok(cred);
+                    }
+                }
+            }
+
+            if allowed.contains(CredentialType::DEFAULT) && !tried.default {
+                tried.default = true;
+                return Cred::default();
+            }
+
+            Err(git2::Error::new(
+                ErrorCode::Auth,
+                ErrorClass::Callback,
+                format!("Authentication failed for {}", url),
+            ))
+        });
+    }
+
+    /// SSH keys to try, in order: the configured one, or the defaults that exist
+    fn ssh_keys(&self) -> Vec<PathBuf> {
+        match &self.ssh_key {
+            Some(key) => vec![PathBuf::from(expand_home(key))],
+            None => DEFAULT_KEYS
+                .iter()
+                .map(|key| PathBuf::from(expand_home(key)))
+                .filter(|path| path.exists())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_configured_ssh_key_replaces_defaults() {
+        let tmp = TempDir::new().unwrap();
+        let mut repo = Repository::open_or_init(tmp.path()).unwrap();
+        repo.set_ssh_key(Some("/keys/deploy".to_string()));
+        assert_eq!(repo.ssh_keys(), vec![PathBuf::from("/keys/deploy")]);
+    }
+}
@@ -0,0 +1,299 @@
+//! Document-aware merging of branches
+//!
+//! Branches are merged with git's three-way merge. Where both sides changed
+//! the same document, the versions are parsed and combined field by field
+//! (see [`super::conflict`]), and the versions that went in are recorded under
+//! `/.mdby/conflicts/`. Conflicts in anything other than a document, or where
+//! one side deleted the document, abort the merge and leave the branch as it was.
+
+use git2::{build::CheckoutBuilder, Index, IndexEntry, IndexTime, Oid};
+use std::path::{Component, Path};
+
+use super::conflict::{resolve_recorded, ConflictRecord, ConflictResolution};
+use super::Repository;
+use crate::storage::document::Document;
+
+/// Result of merging a branch into the current one
+#[derive(Debug, Clone)]
+pub struct MergeOutcome {
+    /// New HEAD commit (the merge commit, or the fast-forward target)
+    pub commit: String,
+    /// Whether the branch was merged by moving HEAD forward
+    pub fast_forward: bool,
+    /// Documents changed on both sides and merged field by field
+    pub conflicts: Vec<ConflictRecord>,
+}
+
+impl Repository {
+    /// Merge `theirs` into the current branch
+    ///
+    /// The working tree must be clean. Returns `None` if `theirs` is already
+    /// part of the current branch.
+    pub fn merge_documents(&self, theirs: Oid, message: &str) -> anyhow::Result<Option<MergeOutcome>> {
+        if self.has_changes()? {
+            anyhow::bail!("Uncommitted changes in the working tree; commit or propose them before merging");
+        }
+
+        let head = self.inner.head()?.peel_to_commit()?;
+        if head.id() == theirs || self.inner.graph_descendant_of(head.id(), theirs)? {
+            return Ok(None);
+        }
+
+        let branch = self.inner.head()?.name().unwrap_or("HEAD").to_string();
+        if self.inner.graph_descendant_of(theirs, head.id())? {
+            let target = self.inner.find_commit(theirs)?;
+            self.inner
+                .checkout_tree(target.as_object(), Some(CheckoutBuilder::new().safe()))?;
+            self.inner.reference(&branch, theirs, true, message)?;
+            return Ok(Some(MergeOutcome {
+                commit: theirs.to_string(),
+                fast_forward: true,
+                conflicts: Vec::new(),
+            }));
+        }
+
+        let annotated = self.inner.find_annotated_commit(theirs)?;
+        self.inner.merge(&[&annotated], None, None)?;
+
+        match self.resolve_merge_conflicts() {
+            Ok(conflicts) => {
+                let mut index = self.inner.index()?;
+                index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+                index.write()?;
+                let tree = self.inner.find_tree(index.write_tree()?)?;
+                let sig = self.signature()?;
+                let their_commit = self.inner.find_commit(theirs)?;
+                let oid = self.create_commit(true, &sig, message, &tree, &[&head, &their_commit])?;
+                self.inner.cleanup_state()?;
+
+                Ok(Some(MergeOutcome {
+                    commit: oid.to_string(),
+                    fast_forward: false,
+                    conflicts,
+                }))
+            }
+            Err(e) => {
+                self.inner.cleanup_state()?;
+                self.inner.reset(head.as_object(), git2::ResetType::Hard, None)?;
+                // Conflict records written before the failure aren't part of any commit
+                let mut checkout = CheckoutBuilder::new();
+                checkout.force().remove_untracked(true);
+                self.inner.checkout_head(Some(&mut checkout))?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Merge every conflicted document in the index field by field
+    fn resolve_merge_conflicts(&self) -> anyhow::Result<Vec<ConflictRecord>> {
+        let mut index = self.inner.index()?;
+        let records = self.resolve_index_conflicts(&mut index, ConflictResolution::MergeFields, false)?;
+        index.write()?;
+
+        // Replace the conflict markers in the working tree with the merged documents
+        let mut checkout = CheckoutBuilder::new();
+        checkout.force();
+        for record in &records {
+            checkout.path(format!("collections/{}/{}.md", record.collection, record.id));
+        }
+        if !records.is_empty() {
+            self.inner.checkout_index(Some(&mut index), Some(&mut checkout))?;
+        }
+
+        Ok(records)
+    }
+
+    /// Resolve every conflicted document in `index` with `strategy`
+    ///
+    /// Each merged document and its conflict record are staged in `index`.
+    /// When replaying local commits onto remote ones, the index's "their"
+    /// side is the local version; `local_is_theirs` swaps the sides so that
+    /// `ours` always means local.
+    pub(super) fn resolve_index_conflicts(
+        &self,
+        index: &mut Index,
+        strategy: ConflictResolution,
+        local_is_theirs: bool,
+    ) -> anyhow::Result<Vec<ConflictRecord>> {
+        if !index.has_conflicts() {
+            return Ok(Vec::new());
+        }
+        let root = self
+            .inner
+            .workdir()
+            .ok_or_else(|| anyhow::anyhow!("Cannot merge in a bare repository"))?
+            .to_path_buf();
+
+        let mut records = Vec::new();
+        let conflicts: Vec<_> = index.conflicts()?.collect::<Result<_, _>>()?;
+        for conflict in conflicts {
+            let (local, incoming) = if local_is_theirs {
+                (conflict.their, conflict.our)
+            } else {
+                (conflict.our, conflict.their)
+            };
+            let entry = local
+                .as_ref()
+                .or(incoming.as_ref())
+                .or(conflict.ancestor.as_ref())
+                .ok_or_else(|| anyhow::anyhow!("Empty conflict entry"))?;
+            let path = String::from_utf8_lossy(&entry.path).into_owned();
+
+            let (collection, id) = document_path(Path::new(&path))
+                .ok_or_else(|| anyhow::anyhow!("Conflict in '{}' must be resolved manually", path))?;
+            let (Some(local), Some(incoming)) = (local, incoming) else {
+                anyhow::bail!("'{}' was deleted on one side and changed on the other; resolve manually", path);
+            };
+
+            let ours = self.document_at(&id, local.id)?;
+            let theirs = self.document_at(&id, incoming.id)?;
+            let base = match &conflict.ancestor {
+                Some(entry) => Some(self.document_at(&id, entry.id)?),
+                None => None,
+            };
+
+            let (merged, record) = resolve_recorded(&root, &collection, base.as_ref(), &ours, &theirs, strategy)?;
+            index.remove_path(Path::new(&path))?;
+            self.stage(index, local, merged.render().as_bytes())?;
+            let content = std::fs::read(root.join(&record.path))?;
+            self.stage(index, new_entry(&record.path), &content)?;
+            records.push(record);
+        }
+
+        Ok(records)
+    }
+
+    /// Add `content` to `index` at the entry's path
+    fn stage(&self, index: &mut Index, entry: IndexEntry, content: &[u8]) -> anyhow::Result<()> {
+        index.add(&IndexEntry {
+            id: self.inner.blob(content)?,
+            file_size: u32::try_from(content.len()).unwrap_or(u32::MAX),
+            // Clears the conflict stage
+            flags: 0,
+            ..entry
+        })?;
+        Ok(())
+    }
+
+    fn document_at(&self, id: &str, blob: Oid) -> anyhow::Result<Document> {
+        let blob = self.inner.find_blob(blob)?;
+        Document::parse(id, &String::from_utf8_lossy(blob.content()))
+    }
+}
+
+/// Index entry for a new regular file
+fn new_entry(path: &Path) -> IndexEntry {
+    IndexEntry {
+        ctime: IndexTime::new(0, 0),
+        mtime: IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode: 0o100644,
+        uid: 0,
+        gid: 0,
+        file_size: 0,
+        id: Oid::zero(),
+        flags: 0,
+        flags_extended: 0,
+        path: path.to_string_lossy().into_owned().into_bytes(),
+    }
+}
+
+/// Collection and ID for `collections/{collection}/{id}.md`
+fn document_path(path: &Path) -> Option<(String, String)> {
+    let parts: Vec<_> = path.components().collect();
+    match parts.as_slice() {
+        [Component::Normal(dir), Component::Normal(collection), Component::Normal(_)]
+            if *dir == "collections" && path.extension().is_some_and(|e| e == "md") =>
+        {
+            let id = path.file_stem()?.to_str()?.to_string();
+            Some((collection.to_str()?.to_string(), id))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(repo: &Repository, path: &str, content: &str) {
+        let full = repo.inner().workdir().unwrap().join(path);
+        std::fs::create_dir_all(full.parent().unwrap()).unwrap();
+        std::fs::write(full, content).unwrap();
+    }
+
+    /// A repository with a `side` branch diverging from the checked-out one at `path`
+    fn diverged(path: &str, base: &str, ours: &str, theirs: &str) -> (TempDir, Repository, Oid) {
+        let tmp = TempDir::new().unwrap();
+        let repo = Repository::open_or_init(tmp.path()).unwrap();
+        write(&repo, path, base);
+        let base = repo.commit("Base").unwrap();
+
+        write(&repo, path, theirs);
+        let side = repo.commit("Theirs").unwrap();
+
+        let base = repo.inner().find_object(base, None).unwrap();
+        repo.inner().reset(&base, git2::ResetType::Hard, None).unwrap();
+        drop(base);
+        write(&repo, path, ours);
+        repo.commit("Ours").unwrap();
+
+        (tmp, repo, side)
+    }
+
+    #[test]
+    fn test_document_conflict_merged_by_field() {
+        let (tmp, repo, side) = diverged(
+            "collections/todos/task-1.md",
+            "---\ntitle: Base\npriority: 1\n---\nBody\n",
+            "---\ntitle: Ours\npriority: 1\n---\nBody\n",
+            "---\ntitle: Base\npriority: 5\n---\nBody\n",
+        );
+
+        let outcome = repo.merge_documents(side, "Merge side").unwrap().unwrap();
+        assert!(!outcome.fast_forward);
+        assert_eq!(outcome.conflicts.len(), 1);
+        assert!(tmp.path().join(&outcome.conflicts[0].path).exists());
+
+        let merged = std::fs::read_to_string(tmp.path().join("collections/todos/task-1.md")).unwrap();
+        let doc = Document::parse("task-1", &merged).unwrap();
+        assert_eq!(doc.get("title").and_then(|v| v.as_str()), Some("Ours"));
+        assert_eq!(doc.get("priority").and_then(|v| v.as_i64()), Some(5));
+        assert!(!repo.has_changes().unwrap());
+        assert_eq!(repo.inner().head().unwrap().peel_to_commit().unwrap().parent_count(), 2);
+
+        // Merging again is a no-op
+        assert!(repo.merge_documents(side, "Merge side").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_non_document_conflict_aborts() {
+        let (tmp, repo, side) = diverged(".mdby/schemas/todos.yaml", "a: 1\n", "a: 2\n", "a: 3\n");
+        let before = repo.head_hash().unwrap();
+
+        let err = repo.merge_documents(side, "Merge side").unwrap_err();
+        assert!(err.to_string().contains("resolved manually"));
+        assert_eq!(repo.head_hash().unwrap(), before);
+        assert!(!repo.has_changes().unwrap());
+        assert_eq!(std::fs::read_to_string(tmp.path().join(".mdby/schemas/todos.yaml")).unwrap(), "a: 2\n");
+    }
+
+    #[test]
+    fn test_dirty_tree_refused() {
+        let (tmp, repo, side) = diverged("collections/todos/a.md", "a", "b", "c");
+        std::fs::write(tmp.path().join("collections/todos/a.md"), "edited").unwrap();
+        assert!(repo.merge_documents(side, "Merge side").is_err());
+    }
+
+    #[test]
+    fn test_document_path() {
+        assert_eq!(
+            document_path(Path::new("collections/todos/task-1.md")),
+            Some(("todos".into(), "task-1".into()))
+        );
+        assert_eq!(document_path(Path::new(".mdby/schemas/todos.yaml")), None);
+        assert_eq!(document_path(Path::new("collections/todos/README.txt")), None);
+    }
+}
@@ -15,17 +15,59 @@
 //! Each database operation creates a git commit. Transactions can span
 //! multiple operations and are committed atomically.
 
-use git2::{Repository as Git2Repo, Signature};
-use std::path::Path;
+use git2::{Oid, Repository as Git2Repo, Signature};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::config::Signing;
+use crate::storage::tree::TreeReader;
 
 mod conflict;
+mod credentials;
+mod merge;
+mod proposals;
+mod signing;
 mod sync;
 
-pub use conflict::ConflictResolution;
+pub use conflict::{resolve_recorded, ConflictRecord, ConflictResolution};
+pub use merge::MergeOutcome;
+pub use proposals::Proposal;
+pub use signing::{CommitVerification, SignatureStatus};
+pub use sync::RebasedCommit;
 
 /// Git repository wrapper for MDBY
 pub struct Repository {
     inner: Git2Repo,
+    history: Mutex<HistoryIndex>,
+    signing: Option<Signing>,
+    /// Revision read instead of HEAD; set for read-only replicas
+    reference: Option<String>,
+    /// Private key for SSH remotes
+    ssh_key: Option<String>,
+    /// Messages of the commits held back ([`Repository::hold_commits`])
+    held: Mutex<Option<Vec<String>>>,
+}
+
+/// Commit history of every path, as of one HEAD commit
+#[derive(Default)]
+struct HistoryIndex {
+    head: Option<Oid>,
+    files: HashMap<PathBuf, FileHistory>,
+}
+
+/// When a path was first and last committed, and by whom, as returned by
+/// [`Repository::file_history`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileHistory {
+    /// ID of the last commit touching the path
+    pub commit: String,
+    /// Author of the last commit touching the path
+    pub author: String,
+    /// Time of the commit that added the path, in seconds since the Unix epoch
+    pub created: i64,
+    /// Time of the last commit touching the path, in seconds since the Unix epoch
+    pub modified: i64,
 }
 
 impl Repository {
@@ -44,7 +86,85 @@ impl Repository {
             }
         };
 
-        Ok(Self { inner })
+        Ok(Self {
+            inner,
+            history: Mutex::default(),
+            signing: None,
+            reference: None,
+            ssh_key: None,
+            held: Mutex::default(),
+        })
+    }
+
+    /// Open an existing repository
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            inner: Git2Repo::open(path)?,
+            history: Mutex::default(),
+            signing: None,
+            reference: None,
+            ssh_key: None,
+            held: Mutex::default(),
+        })
+    }
+
+    /// A repository with no commits and nothing on disk, for a database
+    /// whose documents aren't kept in git (an archive)
+    pub fn empty() -> anyhow::Result<Self> {
+        Ok(Self {
+            inner: Git2Repo::from_odb(git2::Odb::new()?)?,
+            history: Mutex::default(),
+            signing: None,
+            reference: None,
+            ssh_key: None,
+            held: Mutex::default(),
+        })
+    }
+
+    /// Open a bare repository as a read-only replica of `reference`
+    ///
+    /// Reads come from the commit `reference` (a branch, tag or commit hash)
+    /// points at, resolved again on every read so pushes to the bare
+    /// repository are picked up. Committing fails.
+    pub fn open_bare(path: &Path, reference: &str) -> anyhow::Result<Self> {
+        let repo = Self {
+            inner: Git2Repo::open_bare(path)?,
+            history: Mutex::default(),
+            signing: None,
+            reference: Some(reference.to_string()),
+            ssh_key: None,
+            held: Mutex::default(),
+        };
+        repo.head_commit()?;
+        Ok(repo)
+    }
+
+    /// Whether this is a read-only replica opened with [`Repository::open_bare`]
+    pub fn is_read_only(&self) -> bool {
+        self.reference.is_some()
+    }
+
+    /// The commit reads come from: HEAD, or the replica's reference
+    pub fn head_commit(&self) -> anyhow::Result<git2::Commit<'_>> {
+        match &self.reference {
+            Some(reference) => self
+                .inner
+                .revparse_single(reference)
+                .and_then(|object| object.peel_to_commit())
+                .map_err(|_| anyhow::anyhow!("Reference '{}' not found", reference)),
+            None => Ok(self.inner.head()?.peel_to_commit()?),
+        }
+    }
+
+    /// Collections and files as of [`Repository::head_commit`], for replicas
+    ///
+    /// Returns `None` for a repository with a working tree, which is read
+    /// from disk instead.
+    pub fn snapshot(&self) -> anyhow::Result<Option<TreeReader<'_>>> {
+        if !self.is_read_only() {
+            return Ok(None);
+        }
+        TreeReader::new(&self.inner, &self.head_commit()?).map(Some)
     }
 
     /// Create the initial commit for a new repository
@@ -59,7 +179,14 @@ impl Repository {
     }
 
     /// Commit current changes with a message
+    ///
+    /// While commits are held back, the message is recorded instead and HEAD
+    /// is returned.
     pub fn commit(&self, message: &str) -> anyhow::Result<git2::Oid> {
+        if let Some(held) = self.held.lock().map_err(|_| anyhow::anyhow!("Commit lock poisoned"))?.as_mut() {
+            held.push(message.to_string());
+            return Ok(self.head_commit()?.id());
+        }
         let sig = self.signature()?;
         let mut index = self.inner.index()?;
 
@@ -70,32 +197,232 @@ impl Repository {
         let tree_id = index.write_tree()?;
         let tree = self.inner.find_tree(tree_id)?;
 
-        let head = self.inner.head()?;
-        let parent = head.peel_to_commit()?;
-
-        let oid = self.inner.commit(
-            Some("HEAD"),
-            &sig,
-            &sig,
-            message,
-            &tree,
-            &[&parent],
-        )?;
+        let parent = self.inner.head()?.peel_to_commit()?;
+        let oid = self.create_commit(true, &sig, message, &tree, &[&parent])?;
 
         Ok(oid)
     }
 
+    /// Hold back commits until [`Repository::release_commits`], recording
+    /// their messages, so that several statements make one commit
+    pub fn hold_commits(&self) {
+        if let Ok(mut held) = self.held.lock() {
+            held.get_or_insert_with(Vec::new);
+        }
+    }
+
+    /// Whether commits are being held back
+    pub fn is_holding_commits(&self) -> bool {
+        self.held.lock().is_ok_and(|held| held.is_some())
+    }
+
+    /// Stop holding back commits; the messages of those that were, in order
+    pub fn release_commits(&self) -> Vec<String> {
+        self.held.lock().ok().and_then(|mut held| held.take()).unwrap_or_default()
+    }
+
     /// Get the current HEAD commit hash
     pub fn head_hash(&self) -> anyhow::Result<String> {
-        let head = self.inner.head()?;
-        let commit = head.peel_to_commit()?;
-        Ok(commit.id().to_string())
+        Ok(self.head_commit()?.id().to_string())
+    }
+
+    /// Most recent commits reachable from HEAD, newest first
+    pub fn log(&self, limit: usize) -> anyhow::Result<Vec<CommitInfo>> {
+        let mut revwalk = self.inner.revwalk()?;
+        revwalk.push(self.head_commit()?.id())?;
+
+        let mut commits = Vec::new();
+        for oid in revwalk.take(limit) {
+            let commit = self.inner.find_commit(oid?)?;
+            commits.push(CommitInfo {
+                id: commit.id().to_string(),
+                summary: commit.summary().unwrap_or_default().to_string(),
+                author: commit.author().name().unwrap_or_default().to_string(),
+                time: commit.time().seconds(),
+            });
+        }
+
+        Ok(commits)
+    }
+
+    /// Hash of the commit `revision` names: a git revision (branch, tag, hash),
+    /// or a time (`2024-03-01`, `2024-03-01 09:30:00` or RFC 3339), meaning the
+    /// last commit reachable from HEAD made at or before it
+    ///
+    /// A date without a time is midnight UTC at its start.
+    pub fn resolve_revision(&self, revision: &str) -> anyhow::Result<String> {
+        let Some(time) = parse_time(revision) else {
+            return self
+                .inner
+                .revparse_single(revision)
+                .and_then(|object| object.peel_to_commit())
+                .map(|commit| commit.id().to_string())
+                .map_err(|_| anyhow::anyhow!("Unknown revision '{}'", revision));
+        };
+
+        let mut revwalk = self.inner.revwalk()?;
+        revwalk.set_sorting(git2::Sort::TIME)?;
+        revwalk.push(self.head_commit()?.id())?;
+        for oid in revwalk {
+            let commit = self.inner.find_commit(oid?)?;
+            if commit.time().seconds() <= time {
+                return Ok(commit.id().to_string());
+            }
+        }
+        anyhow::bail!("No commits at or before {}", revision)
+    }
+
+    /// Every version of `path` (relative to the repository root) in HEAD's
+    /// history, newest first: the commit that wrote it and its contents, or
+    /// `None` for a commit that deleted it
+    pub fn file_versions(&self, path: &Path) -> anyhow::Result<Vec<(CommitInfo, Option<String>)>> {
+        let blob_at = |commit: &git2::Commit| -> anyhow::Result<Option<Oid>> {
+            Ok(commit.tree()?.get_path(path).ok().map(|entry| entry.id()))
+        };
+
+        let mut revwalk = self.inner.revwalk()?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+        revwalk.push(self.head_commit()?.id())?;
+
+        let mut versions = Vec::new();
+        for oid in revwalk {
+            let commit = self.inner.find_commit(oid?)?;
+            let blob = blob_at(&commit)?;
+            let before = match commit.parents().next() {
+                Some(parent) => blob_at(&parent)?,
+                None => None,
+            };
+            if blob == before {
+                continue;
+            }
+            let content = match blob {
+                Some(id) => Some(String::from_utf8_lossy(self.inner.find_blob(id)?.content()).into_owned()),
+                None => None,
+            };
+            versions.push((
+                CommitInfo {
+                    id: commit.id().to_string(),
+                    summary: commit.summary().unwrap_or_default().to_string(),
+                    author: commit.author().name().unwrap_or_default().to_string(),
+                    time: commit.time().seconds(),
+                },
+                content,
+            ));
+        }
+        Ok(versions)
+    }
+
+    /// Every commit reachable from HEAD with the files it touched, newest first
+    pub fn history(&self) -> anyhow::Result<Vec<CommitDetails>> {
+        let mut revwalk = self.inner.revwalk()?;
+        revwalk.push(self.head_commit()?.id())?;
+
+        let mut commits = Vec::new();
+        for oid in revwalk {
+            let commit = self.inner.find_commit(oid?)?;
+            let files = self
+                .changed_files(&commit)?
+                .iter()
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect();
+
+            let author = commit.author();
+            commits.push(CommitDetails {
+                id: commit.id().to_string(),
+                author: author.name().unwrap_or_default().to_string(),
+                email: author.email().unwrap_or_default().to_string(),
+                time: commit.time().seconds(),
+                message: commit.message().unwrap_or_default().trim_end().to_string(),
+                files,
+            });
+        }
+
+        Ok(commits)
+    }
+
+    /// Author of the last commit that touched `path` (relative to the repository root)
+    pub fn last_author(&self, path: &Path) -> anyhow::Result<Option<String>> {
+        Ok(self.file_history(path)?.map(|history| history.author))
+    }
+
+    /// First and last commit touching `path` (relative to the repository root),
+    /// or `None` if it isn't in HEAD's history
+    ///
+    /// The answer comes from an index of every path's history, which is built
+    /// on first use and brought up to date with the commits added since
+    /// whenever HEAD moves forward. A path that was deleted and added again
+    /// counts as created by the commit that added it back.
+    pub fn file_history(&self, path: &Path) -> anyhow::Result<Option<FileHistory>> {
+        let head = self.head_commit()?.id();
+        let mut index = self
+            .history
+            .lock()
+            .map_err(|_| anyhow::anyhow!("History index lock poisoned"))?;
+
+        if index.head != Some(head) {
+            let mut revwalk = self.inner.revwalk()?;
+            revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+            revwalk.push(head)?;
+            match index.head {
+                Some(old) if self.inner.graph_descendant_of(head, old)? => revwalk.hide(old)?,
+                _ => index.files.clear(),
+            }
+
+            // Oldest first, so later commits overwrite earlier authors and times
+            for oid in revwalk {
+                let commit = self.inner.find_commit(oid?)?;
+                let author = commit.author().name().unwrap_or_default().to_string();
+                let time = commit.time().seconds();
+                let id = commit.id().to_string();
+                let tree = commit.tree()?;
+                for file in self.changed_files(&commit)? {
+                    if tree.get_path(&file).is_err() {
+                        index.files.remove(&file);
+                        continue;
+                    }
+                    let created = index.files.get(&file).map_or(time, |history| history.created);
+                    index.files.insert(file, FileHistory { commit: id.clone(), author: author.clone(), created, modified: time });
+                }
+            }
+            index.head = Some(head);
+        }
+
+        Ok(index.files.get(path).cloned())
+    }
+
+    /// Paths a commit added, modified or deleted, compared with its first parent
+    fn changed_files(&self, commit: &git2::Commit) -> anyhow::Result<Vec<PathBuf>> {
+        let tree = commit.tree()?;
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+
+        let diff = self.inner.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+        Ok(diff_paths(&diff))
     }
 
-    /// Check if there are uncommitted changes
+    /// Paths that differ between a commit and HEAD, relative to the
+    /// repository root
+    pub fn changed_since(&self, commit: &str) -> anyhow::Result<Vec<PathBuf>> {
+        let old = self.inner.find_commit(git2::Oid::from_str(commit)?)?.tree()?;
+        let new = self.head_commit()?.tree()?;
+        let diff = self.inner.diff_tree_to_tree(Some(&old), Some(&new), None)?;
+        Ok(diff_paths(&diff))
+    }
+
+    /// Check if there are uncommitted changes (ignored files don't count)
     pub fn has_changes(&self) -> anyhow::Result<bool> {
-        let statuses = self.inner.statuses(None)?;
-        Ok(!statuses.is_empty())
+        Ok(!self.uncommitted()?.is_empty())
+    }
+
+    /// Paths with uncommitted changes, relative to the repository root
+    /// (ignored files don't count)
+    pub fn uncommitted(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut options = git2::StatusOptions::new();
+        options.include_untracked(true).recurse_untracked_dirs(true).include_ignored(false);
+        let statuses = self.inner.statuses(Some(&mut options))?;
+        Ok(statuses.iter().filter_map(|entry| entry.path().map(PathBuf::from)).collect())
     }
 
     /// Get a signature for commits
@@ -107,22 +434,63 @@ impl Repository {
             .map_err(Into::into)
     }
 
-    /// Sync with remote (stub - to be implemented)
-    pub async fn sync(&mut self) -> anyhow::Result<crate::SyncResult> {
-        // TODO: Implement push/pull with conflict resolution
-        Ok(crate::SyncResult {
-            pulled: 0,
-            pushed: 0,
-            conflicts_resolved: vec![],
-        })
-    }
-
     /// Get the underlying git2 repository (for advanced operations)
     pub fn inner(&self) -> &Git2Repo {
         &self.inner
     }
 }
 
+/// Seconds since the Unix epoch for an RFC 3339 time, `YYYY-MM-DD HH:MM:SS`
+/// (UTC) or `YYYY-MM-DD` (midnight UTC)
+fn parse_time(text: &str) -> Option<i64> {
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(text) {
+        return Some(time.timestamp());
+    }
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| chrono::NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))
+        .map(|time| time.and_utc().timestamp())
+}
+
+/// Summary of a commit, as returned by [`Repository::log`]
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CommitInfo {
+    /// Full commit hash
+    pub id: String,
+    /// First line of the commit message
+    pub summary: String,
+    /// Author name
+    pub author: String,
+    /// Commit time in seconds since the Unix epoch
+    pub time: i64,
+}
+
+/// A commit with its full message and changed files, as returned by [`Repository::history`]
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CommitDetails {
+    /// Full commit hash
+    pub id: String,
+    /// Author name
+    pub author: String,
+    /// Author email
+    pub email: String,
+    /// Commit time in seconds since the Unix epoch
+    pub time: i64,
+    /// Full commit message
+    pub message: String,
+    /// Paths added, modified or deleted, relative to the repository root
+    pub files: Vec<String>,
+}
+
+/// Paths a diff added, modified or deleted
+fn diff_paths(diff: &git2::Diff) -> Vec<PathBuf> {
+    diff.deltas()
+        .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+        .map(Path::to_path_buf)
+        .collect()
+}
+
 /// A database transaction that will be committed atomically
 pub struct Transaction<'a> {
     repo: &'a Repository,
@@ -196,4 +564,86 @@ mod tests {
         let oid = repo.commit("Add test file").unwrap();
         assert!(!oid.is_zero());
     }
+
+    #[test]
+    fn test_log_newest_first() {
+        let tmp = TempDir::new().unwrap();
+        let repo = Repository::open_or_init(tmp.path()).unwrap();
+
+        std::fs::write(tmp.path().join("test.md"), "# Test").unwrap();
+        repo.commit("Add test file").unwrap();
+
+        let log = repo.log(10).unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].summary, "Add test file");
+        assert_eq!(log[1].summary, "Initialize MDBY database");
+        assert_eq!(repo.log(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_history_lists_changed_files() {
+        let tmp = TempDir::new().unwrap();
+        let repo = Repository::open_or_init(tmp.path()).unwrap();
+
+        std::fs::write(tmp.path().join("a.md"), "# A").unwrap();
+        std::fs::write(tmp.path().join("b.md"), "# B").unwrap();
+        repo.commit("Add files\n\nWith a body").unwrap();
+        std::fs::write(tmp.path().join("b.md"), "# B2").unwrap();
+        repo.commit("Edit b").unwrap();
+
+        let history = repo.history().unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].files, vec!["b.md"]);
+        assert_eq!(history[1].message, "Add files\n\nWith a body");
+        assert_eq!(history[1].files, vec!["a.md", "b.md"]);
+        assert!(history[2].files.is_empty());
+    }
+
+    #[test]
+    fn test_last_author_follows_new_commits() {
+        let tmp = TempDir::new().unwrap();
+        let repo = Repository::open_or_init(tmp.path()).unwrap();
+        let commit_as = |name: &str, file: &str| {
+            repo.inner().config().unwrap().set_str("user.name", name).unwrap();
+            repo.inner().config().unwrap().set_str("user.email", "x@example.com").unwrap();
+            std::fs::write(tmp.path().join(file), name).unwrap();
+            repo.commit(&format!("{} edits {}", name, file)).unwrap();
+        };
+
+        commit_as("ada", "a.md");
+        commit_as("bob", "b.md");
+        assert_eq!(repo.last_author(Path::new("a.md")).unwrap().as_deref(), Some("ada"));
+        assert_eq!(repo.last_author(Path::new("b.md")).unwrap().as_deref(), Some("bob"));
+        assert_eq!(repo.last_author(Path::new("missing.md")).unwrap(), None);
+
+        commit_as("bob", "a.md");
+        assert_eq!(repo.last_author(Path::new("a.md")).unwrap().as_deref(), Some("bob"));
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("1970-01-02"), Some(86_400));
+        assert_eq!(parse_time("1970-01-01 00:01:00"), Some(60));
+        assert_eq!(parse_time("1970-01-01T01:00:00+01:00"), Some(0));
+        assert_eq!(parse_time("abc123"), None);
+    }
+
+    #[test]
+    fn test_file_history_forgets_deleted_paths() {
+        let tmp = TempDir::new().unwrap();
+        let repo = Repository::open_or_init(tmp.path()).unwrap();
+        std::fs::write(tmp.path().join("a.md"), "# A").unwrap();
+        repo.commit("Add a").unwrap();
+        std::fs::write(tmp.path().join("a.md"), "# A2").unwrap();
+        repo.commit("Edit a").unwrap();
+
+        let history = repo.file_history(Path::new("a.md")).unwrap().unwrap();
+        let times: Vec<i64> = repo.history().unwrap().iter().map(|commit| commit.time).collect();
+        assert_eq!(history.modified, times[0]);
+        assert_eq!(history.created, times[1]);
+
+        std::fs::remove_file(tmp.path().join("a.md")).unwrap();
+        repo.commit("Remove a").unwrap();
+        assert_eq!(repo.file_history(Path::new("a.md")).unwrap(), None);
+    }
 }
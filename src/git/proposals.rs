@@ -0,0 +1,264 @@
+//! Proposals: changes shared on a branch for review
+//!
+//! `mdby propose "<message>"` commits the uncommitted changes to a new
+//! `proposals/{slug}-{hash}` branch instead of the current one, restores the
+//! working tree to the current branch and pushes the new branch. `mdby
+//! proposals list` shows proposals, local or fetched from the remote, that
+//! aren't merged yet, and `mdby proposals apply <name>` merges one with the
+//! document-aware merger.
+
+use git2::{build::CheckoutBuilder, BranchType, ErrorCode, Oid};
+use std::collections::BTreeMap;
+
+use super::merge::MergeOutcome;
+use super::sync::is_unreachable;
+use super::Repository;
+use crate::slug::{slugify_with, SlugOptions};
+
+/// Branch name prefix for proposals
+pub const PREFIX: &str = "proposals/";
+
+/// A proposed change waiting for review
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Proposal {
+    /// Name without the `proposals/` prefix
+    pub name: String,
+    /// Tip commit hash
+    pub commit: String,
+    /// Author of the tip commit
+    pub author: String,
+    /// Commit time in seconds since the Unix epoch
+    pub time: i64,
+    /// First line of the commit message
+    pub summary: String,
+    /// Paths changed relative to the current branch
+    pub files: Vec<String>,
+    /// Whether the branch is on the remote
+    pub pushed: bool,
+}
+
+impl Repository {
+    /// Move the uncommitted changes onto a new proposal branch and push it
+    ///
+    /// The branch is kept locally if the remote doesn't exist or can't be
+    /// reached; `pushed` is false in that case.
+    pub fn propose(&self, message: &str, remote: &str) -> anyhow::Result<Proposal> {
+        if !self.has_changes()? {
+            anyhow::bail!("No uncommitted changes to propose");
+        }
+
+        let head = self.inner.head()?.peel_to_commit()?;
+        let mut index = self.inner.index()?;
+        index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+        let tree = self.inner.find_tree(index.write_tree()?)?;
+        let sig = self.signature()?;
+        let oid = self.create_commit(false, &sig, message, &tree, &[&head])?;
+
+        let name = format!("{}-{}", slug(message), &oid.to_string()[..7]);
+        let branch = format!("{}{}", PREFIX, name);
+        self.inner.branch(&branch, &self.inner.find_commit(oid)?, false)?;
+
+        // The changes now live on the proposal branch only. New files are
+        // removed one by one: a forced checkout would also prune the empty
+        // directories of collections that have no committed documents.
+        let added: Vec<_> = self
+            .inner
+            .diff_tree_to_tree(Some(&head.tree()?), Some(&tree), None)?
+            .deltas()
+            .filter(|delta| delta.status() == git2::Delta::Added)
+            .filter_map(|delta| delta.new_file().path().map(|p| p.to_path_buf()))
+            .collect();
+        self.inner.reset(head.as_object(), git2::ResetType::Mixed, None)?;
+        self.inner.checkout_head(Some(CheckoutBuilder::new().force()))?;
+        let workdir = self.inner.workdir().unwrap_or_else(|| self.inner.path());
+        for path in added {
+            std::fs::remove_file(workdir.join(path))?;
+        }
+
+        let pushed = match self.inner.find_remote(remote) {
+            Ok(_) => match self.push_branch(remote, &branch) {
+                Ok(()) => true,
+                Err(e) if is_unreachable(&e) => {
+                    tracing::warn!("Remote '{}' is unreachable; proposal kept locally: {}", remote, e.message());
+                    false
+                }
+                Err(e) => return Err(e.into()),
+            },
+            Err(_) => false,
+        };
+
+        let mut proposal = self.proposal(&name, oid, head.id())?;
+        proposal.pushed = pushed;
+        Ok(proposal)
+    }
+
+    /// Proposals not yet merged into the current branch, by name
+    ///
+    /// Fetches proposal branches from the remote first when it can be reached.
+    pub fn proposals(&self, remote: &str) -> anyhow::Result<Vec<Proposal>> {
+        self.fetch_proposals(remote)?;
+        let head = self.inner.head()?.peel_to_commit()?.id();
+        let local = format!("refs/heads/{}", PREFIX);
+        let tracking = format!("refs/remotes/{}/{}", remote, PREFIX);
+
+        let mut found: BTreeMap<String, Proposal> = BTreeMap::new();
+        for (base, pushed) in [(&local, false), (&tracking, true)] {
+            for reference in self.inner.references_glob(&format!("{}*", base))? {
+                let reference = reference?;
+                let Some(name) = reference.name().and_then(|n| n.strip_prefix(base.as_str())) else {
+                    continue;
+                };
+                let oid = reference.peel_to_commit()?.id();
+                if oid == head || self.inner.graph_descendant_of(head, oid)? {
+                    continue;
+                }
+
+                match found.get_mut(name) {
+                    Some(existing) => existing.pushed |= pushed,
+                    None => {
+                        let mut proposal = self.proposal(name, oid, head)?;
+                        proposal.pushed = pushed;
+                        found.insert(name.to_string(), proposal);
+                    }
+                }
+            }
+        }
+
+        Ok(found.into_values().collect())
+    }
+
+    /// Merge a proposal into the current branch and delete its local branch
+    ///
+    /// Returns `None` if the proposal was already merged.
+    pub fn apply_proposal(&self, name: &str, remote: &str) -> anyhow::Result<Option<MergeOutcome>> {
+        let name = name.strip_prefix(PREFIX).unwrap_or(name);
+        self.fetch_proposals(remote)?;
+
+        let refs = [
+            format!("refs/heads/{}{}", PREFIX, name),
+            format!("refs/remotes/{}/{}{}", remote, PREFIX, name),
+        ];
+        let oid = refs
+            .iter()
+            .find_map(|r| self.inner.refname_to_id(r).ok())
+            .ok_or_else(|| anyhow::anyhow!("Proposal '{}' not found", name))?;
+
+        let summary = self.inner.find_commit(oid)?.summary().unwrap_or_default().to_string();
+        let outcome = self.merge_documents(oid, &format!("Apply proposal {}: {}", name, summary))?;
+
+        match self.inner.find_branch(&format!("{}{}", PREFIX, name), BranchType::Local) {
+            Ok(mut branch) => branch.delete()?,
+            Err(e) if e.code() == ErrorCode::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(outcome)
+    }
+
+    /// Fetch proposal branches, skipping a missing or unreachable remote
+    fn fetch_proposals(&self, remote: &str) -> anyhow::Result<()> {
+        let Ok(mut handle) = self.inner.find_remote(remote) else {
+            return Ok(());
+        };
+        let refspec = format!("+refs/heads/{}*:refs/remotes/{}/{}*", PREFIX, remote, PREFIX);
+        match handle.fetch(&[refspec], Some(&mut self.fetch_options()), None) {
+            Ok(()) => Ok(()),
+            Err(e) if is_unreachable(&e) => {
+                tracing::warn!("Remote '{}' is unreachable; listing local proposals: {}", remote, e.message());
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn proposal(&self, name: &str, oid: Oid, head: Oid) -> anyhow::Result<Proposal> {
+        let commit = self.inner.find_commit(oid)?;
+        let base = self.inner.merge_base(head, oid)?;
+        let base_tree = self.inner.find_commit(base)?.tree()?;
+        let diff = self.inner.diff_tree_to_tree(Some(&base_tree), Some(&commit.tree()?), None)?;
+        let files = diff
+            .deltas()
+            .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+
+        let author = commit.author().name().unwrap_or_default().to_string();
+        Ok(Proposal {
+            name: name.to_string(),
+            commit: oid.to_string(),
+            author,
+            time: commit.time().seconds(),
+            summary: commit.summary().unwrap_or_default().to_string(),
+            files,
+            pushed: false,
+        })
+    }
+}
+
+/// Branch-safe slug of a message, at most 40 characters
+fn slug(message: &str) -> String {
+    let slug = slugify_with(message, &SlugOptions::default().with_max_length(Some(40)));
+    if slug.is_empty() {
+        return "change".to_string();
+    }
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(repo: &Repository, path: &str, content: &str) {
+        let full = repo.inner().workdir().unwrap().join(path);
+        std::fs::create_dir_all(full.parent().unwrap()).unwrap();
+        std::fs::write(full, content).unwrap();
+    }
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(slug("Fix typo in README!"), "fix-typo-in-readme");
+        assert_eq!(slug("日本"), "ri-ben");
+        assert_eq!(slug("!!!"), "change");
+        assert!(slug(&"word ".repeat(20)).len() <= 40);
+    }
+
+    #[test]
+    fn test_propose_list_apply() {
+        let tmp = TempDir::new().unwrap();
+        let bare = tmp.path().join("remote.git");
+        git2::Repository::init_bare(&bare).unwrap();
+        let repo = Repository::open_or_init(&tmp.path().join("db")).unwrap();
+        repo.inner().remote("origin", bare.to_str().unwrap()).unwrap();
+
+        write(&repo, "collections/notes/idea.md", "---\ntitle: Idea\n---\n");
+        let proposal = repo.propose("Add an idea", "origin").unwrap();
+        assert!(proposal.name.starts_with("add-an-idea-"));
+        assert!(proposal.pushed);
+        assert_eq!(proposal.files, vec!["collections/notes/idea.md"]);
+
+        // The change left the working tree and waits on its branch
+        assert!(!tmp.path().join("db/collections/notes/idea.md").exists());
+        assert!(!repo.has_changes().unwrap());
+        let listed = repo.proposals("origin").unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, proposal.name);
+
+        let outcome = repo.apply_proposal(&proposal.name, "origin").unwrap().unwrap();
+        assert!(outcome.fast_forward);
+        assert!(tmp.path().join("db/collections/notes/idea.md").exists());
+        assert!(repo.proposals("origin").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_propose_without_changes_or_remote() {
+        let tmp = TempDir::new().unwrap();
+        let repo = Repository::open_or_init(tmp.path()).unwrap();
+        assert!(repo.propose("Nothing", "origin").is_err());
+
+        write(&repo, "a.md", "a");
+        let proposal = repo.propose("Local only", "origin").unwrap();
+        assert!(!proposal.pushed);
+        assert!(repo.apply_proposal("missing", "origin").is_err());
+    }
+}
@@ -0,0 +1,324 @@
+//! Commit signing and verification
+//!
+//! With `signing` set in `/.mdby/config.yaml`, every commit the database makes
+//! is signed: the commit is built as a buffer, signed by `gpg` or
+//! `ssh-keygen -Y sign`, and written with the signature in its `gpgsig`
+//! header, the same way `git commit -S` does. `mdby verify` walks the history
+//! and checks each signature with the matching program, flagging commits that
+//! are unsigned or whose signature doesn't match their content.
+
+use git2::{ErrorCode, Oid, Signature, Tree};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use super::Repository;
+use crate::config::{Signing, SigningFormat};
+
+/// Signature state of one commit
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum SignatureStatus {
+    /// Signature matches the commit
+    Valid {
+        /// Key owner (GPG) or key fingerprint (SSH)
+        signer: String,
+    },
+    /// Commit has no signature
+    Unsigned,
+    /// Signature doesn't verify: the commit was altered or the key isn't trusted
+    Invalid {
+        /// Output of the verifying program
+        reason: String,
+    },
+}
+
+/// A commit and the state of its signature, as returned by [`Repository::verify`]
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CommitVerification {
+    /// Full commit hash
+    pub id: String,
+    /// First line of the commit message
+    pub summary: String,
+    /// Author name
+    pub author: String,
+    /// Commit time in seconds since the Unix epoch
+    pub time: i64,
+    /// Signature state
+    #[serde(flatten)]
+    pub signature: SignatureStatus,
+}
+
+impl CommitVerification {
+    /// Whether the commit is unsigned or its signature is invalid
+    pub fn flagged(&self) -> bool {
+        !matches!(self.signature, SignatureStatus::Valid { .. })
+    }
+}
+
+impl Repository {
+    /// Use these settings to sign new commits, or stop signing with `None`
+    pub fn set_signing(&mut self, signing: Option<Signing>) {
+        self.signing = signing;
+    }
+
+    /// Write a commit, signed when signing is configured
+    ///
+    /// The committer is the repository's signature. With `update_head`, the
+    /// checked-out branch (or a detached HEAD) moves to the new commit.
+    pub(super) fn create_commit(
+        &self,
+        update_head: bool,
+        author: &Signature<'_>,
+        message: &str,
+        tree: &Tree<'_>,
+        parents: &[&git2::Commit<'_>],
+    ) -> anyhow::Result<Oid> {
+        if self.is_read_only() {
+            anyhow::bail!("Cannot commit to a read-only replica");
+        }
+        let committer = self.signature()?;
+        let Some(signing) = &self.signing else {
+            let update_ref = update_head.then_some("HEAD");
+            return Ok(self.inner.commit(update_ref, author, &committer, message, tree, parents)?);
+        };
+
+        let buffer = self.inner.commit_create_buffer(author, &committer, message, tree, parents)?;
+        let content = buffer
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Commit content is not valid UTF-8"))?;
+        let signature = sign(signing, content)?;
+        let oid = self.inner.commit_signed(content, &signature, None)?;
+
+        if update_head {
+            let head = self.inner.head()?;
+            let log = format!("commit: {}", message.lines().next().unwrap_or_default());
+            match head.name() {
+                Some(name) if head.is_branch() => {
+                    self.inner.reference(name, oid, true, &log)?;
+                }
+                _ => self.inner.set_head_detached(oid)?,
+            }
+        }
+
+        Ok(oid)
+    }
+
+    /// Check the signature of every commit reachable from HEAD, newest first
+    ///
+    /// With `since`, commits reachable from that revision are skipped, so
+    /// history from before signing was turned on can be left out.
+    pub fn verify(&self, since: Option<&str>) -> anyhow::Result<Vec<CommitVerification>> {
+        let mut revwalk = self.inner.revwalk()?;
+        revwalk.push(self.head_commit()?.id())?;
+        if let Some(since) = since {
+            let commit = self
+                .inner
+                .revparse_single(since)
+                .and_then(|object| object.peel_to_commit())
+                .map_err(|_| anyhow::anyhow!("Unknown revision '{}'", since))?;
+            revwalk.hide(commit.id())?;
+        }
+
+        let mut results = Vec::new();
+        for oid in revwalk {
+            let oid = oid?;
+            let commit = self.inner.find_commit(oid)?;
+            let author = commit.author();
+            results.push(CommitVerification {
+                id: oid.to_string(),
+                summary: commit.summary().unwrap_or_default().to_string(),
+                author: author.name().unwrap_or_default().to_string(),
+                time: commit.time().seconds(),
+                signature: self.check_signature(oid, author.email().unwrap_or_default())?,
+            });
+        }
+
+        Ok(results)
+    }
+
+    fn check_signature(&self, oid: Oid, email: &str) -> anyhow::Result<SignatureStatus> {
+        let (signature, content) = match self.inner.extract_signature(&oid, None) {
+            Ok(extracted) => extracted,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(SignatureStatus::Unsigned),
+            Err(e) => return Err(e.into()),
+        };
+        let signature = signature
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Signature of {} is not valid UTF-8", oid))?;
+
+        let format = if signature.contains("BEGIN SSH SIGNATURE") {
+            SigningFormat::Ssh
+        } else {
+            SigningFormat::Gpg
+        };
+        // Verify with the configured program when the format matches
+        let configured = self.signing.as_ref().filter(|s| s.format == format);
+        let program = configured.map(Signing::program).unwrap_or(match format {
+            SigningFormat::Gpg => "gpg",
+            SigningFormat::Ssh => "ssh-keygen",
+        });
+
+        // The verifying programs read the signature from a file
+        let sig_path = self.inner.path().join(format!("mdby-verify-{}.sig", oid));
+        std::fs::write(&sig_path, signature)?;
+        let sig_file = sig_path.to_string_lossy();
+        let result = match format {
+            SigningFormat::Gpg => run(program, &["--status-fd=1", "--verify", &sig_file, "-"], &content),
+            SigningFormat::Ssh => match configured.and_then(|s| s.allowed_signers.as_deref()) {
+                Some(allowed) => {
+                    let allowed = allowed.to_string_lossy();
+                    run(program, &["-Y", "verify", "-f", &allowed, "-I", email, "-n", "git", "-s", &sig_file], &content)
+                }
+                None => run(program, &["-Y", "check-novalidate", "-n", "git", "-s", &sig_file], &content),
+            },
+        };
+        std::fs::remove_file(&sig_path)?;
+
+        Ok(match result? {
+            Ok(stdout) => SignatureStatus::Valid { signer: signer(format, &stdout) },
+            Err(reason) => SignatureStatus::Invalid { reason },
+        })
+    }
+}
+
+/// Detached, armored signature of a commit buffer
+fn sign(signing: &Signing, content: &str) -> anyhow::Result<String> {
+    let result = match signing.format {
+        SigningFormat::Gpg => run(signing.program(), &["--status-fd=2", "-bsau", &signing.key], content.as_bytes()),
+        SigningFormat::Ssh => {
+            let key = expand_home(&signing.key);
+            run(signing.program(), &["-Y", "sign", "-n", "git", "-f", &key], content.as_bytes())
+        }
+    };
+
+    match result? {
+        Ok(signature) if !signature.trim().is_empty() => Ok(signature),
+        Ok(_) => anyhow::bail!("{} produced no signature", signing.program()),
+        Err(reason) => anyhow::bail!("Signing the commit with {} failed: {}", signing.program(), reason),
+    }
+}
+
+/// Run a program with `input` on stdin
+///
+/// The outer error is a failure to run the program at all; the inner result
+/// is its stdout on success, or its stderr when it exits unsuccessfully.
+fn run(program: &str, args: &[&str], input: &[u8]) -> anyhow::Result<Result<String, String>> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Could not run {}: {}", program, e))?;
+
+    let mut stdin = child.stdin.take().ok_or_else(|| anyhow::anyhow!("stdin unavailable"))?;
+    stdin.write_all(input)?;
+    drop(stdin);
+
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        Ok(Ok(String::from_utf8_lossy(&output.stdout).into_owned()))
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Ok(Err(format!("{} ({})", stderr.trim(), output.status)))
+    }
+}
+
+/// Who made a valid signature, from the verifying program's output
+fn signer(format: SigningFormat, stdout: &str) -> String {
+    match format {
+        // [GNUPG:] GOODSIG <long key id> <user id>
+        SigningFormat::Gpg => stdout
+            .lines()
+            .find_map(|line| line.strip_prefix("[GNUPG:] GOODSIG "))
+            .and_then(|rest| rest.split_once(' '))
+            .map(|(_, user)| user.to_string())
+            .unwrap_or_default(),
+        // Good "git" signature [for <identity>] with <type> key <fingerprint>
+        SigningFormat::Ssh => stdout.split_whitespace().last().unwrap_or_default().to_string(),
+    }
+}
+
+/// Expand a leading `~/` to the home directory
+pub(super) fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest).to_string_lossy().into_owned(),
+        _ => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A repository signing with a fresh SSH key
+    fn signing_repo(tmp: &TempDir) -> Repository {
+        let key = tmp.path().join("key");
+        let status = Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-C", "test", "-f"])
+            .arg(&key)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let mut repo = Repository::open_or_init(&tmp.path().join("db")).unwrap();
+        repo.set_signing(Some(Signing {
+            format: SigningFormat::Ssh,
+            key: key.to_string_lossy().into_owned(),
+            program: None,
+            allowed_signers: None,
+        }));
+        repo
+    }
+
+    #[test]
+    fn test_signed_commits_verify() {
+        let tmp = TempDir::new().unwrap();
+        let repo = signing_repo(&tmp);
+        std::fs::write(tmp.path().join("db/a.md"), "a").unwrap();
+        let oid = repo.commit("Signed change").unwrap();
+        assert_eq!(repo.head_hash().unwrap(), oid.to_string());
+
+        let results = repo.verify(None).unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(&results[0].signature, SignatureStatus::Valid { signer } if signer.starts_with("SHA256:")));
+        // The initial commit predates the signing config
+        assert_eq!(results[1].signature, SignatureStatus::Unsigned);
+        assert!(results[1].flagged());
+
+        let initial = results[1].id.clone();
+        assert_eq!(repo.verify(Some(&initial)).unwrap().len(), 1);
+        assert!(repo.verify(Some("no-such-rev")).is_err());
+    }
+
+    #[test]
+    fn test_tampered_commit_is_invalid() {
+        let tmp = TempDir::new().unwrap();
+        let repo = signing_repo(&tmp);
+        std::fs::write(tmp.path().join("db/a.md"), "a").unwrap();
+        let oid = repo.commit("Original message").unwrap();
+
+        // Reuse the signature on a commit with a different message
+        let (signature, content) = repo.inner().extract_signature(&oid, None).unwrap();
+        let forged = content.as_str().unwrap().replace("Original message", "Forged message");
+        let forged = repo
+            .inner()
+            .commit_signed(&forged, signature.as_str().unwrap(), None)
+            .unwrap();
+        repo.inner().set_head_detached(forged).unwrap();
+
+        let results = repo.verify(None).unwrap();
+        assert_eq!(results[0].summary, "Forged message");
+        assert!(matches!(results[0].signature, SignatureStatus::Invalid { .. }));
+    }
+
+    #[test]
+    fn test_signer_from_output() {
+        let gpg = "[GNUPG:] NEWSIG\n[GNUPG:] GOODSIG 0123456789ABCDEF Ada <ada@example.com>\n";
+        assert_eq!(signer(SigningFormat::Gpg, gpg), "Ada <ada@example.com>");
+        let ssh = "Good \"git\" signature with ED25519 key SHA256:abc\n";
+        assert_eq!(signer(SigningFormat::Ssh, ssh), "SHA256:abc");
+    }
+}
@@ -1,38 +1,384 @@
 //! Git sync operations for MDBY
 //!
-//! Handles push/pull with remote repositories and conflict resolution.
+//! `mdby sync` fetches the current branch from a remote, replays local
+//! commits on top of any new remote history, and pushes the result.
+//!
+//! # Conflicts
+//!
+//! When a replayed commit changed a document the remote changed too, the two
+//! versions are combined with the configured [`ConflictResolution`] strategy
+//! (`sync.strategy` in `/.mdby/config.yaml`, field-by-field by default). The
+//! merged document and a record of the versions that went into it, under
+//! `/.mdby/conflicts/`, become part of the replayed commit. Conflicts the
+//! strategy can't settle (`manual`, a document deleted on one side, or a file
+//! that isn't a document) stop the sync before anything is changed.
+//!
+//! # Offline Changes
+//!
+//! Every operation is already a local commit, so the commits ahead of the
+//! remote-tracking branch are the queue of changes waiting to be sent. When
+//! the remote can't be reached, sync reports how many are queued and leaves
+//! them in place. The next successful sync rebases them onto whatever the
+//! remote gained in the meantime and reports each rebased commit.
 
-// Sync implementation will be added here
-// For now, this is a placeholder for the sync logic
+use git2::{build::CheckoutBuilder, ErrorClass, ErrorCode, Oid, RemoteCallbacks, Sort};
 
+use super::conflict::{ConflictRecord, ConflictResolution};
 use super::Repository;
 use crate::SyncResult;
 
+/// A local commit replayed onto new remote history
+#[derive(Debug, Clone)]
+pub struct RebasedCommit {
+    /// Hash before the rebase
+    pub original: String,
+    /// Hash after the rebase
+    pub rebased: String,
+    /// First line of the commit message
+    pub summary: String,
+}
+
 impl Repository {
-    /// Pull changes from remote
-    pub async fn pull(&mut self, _remote: &str) -> anyhow::Result<usize> {
-        // TODO: Implement pull with libgit2
-        // 1. Fetch from remote
-        // 2. Merge/rebase
-        // 3. Resolve conflicts using document-aware merge
-        Ok(0)
+    /// Sync the current branch with a remote: fetch, rebase local commits, push
+    ///
+    /// Uncommitted changes are committed first so they are synced too.
+    /// Documents changed on both sides are combined with `strategy`.
+    pub async fn sync(&mut self, remote: &str, strategy: ConflictResolution) -> anyhow::Result<SyncResult> {
+        if self.has_changes()? {
+            self.commit("Commit local changes before sync")?;
+        }
+
+        let branch = self.branch_name()?;
+        if let Err(e) = self.fetch(remote, &branch) {
+            return self.offline(remote, e);
+        }
+
+        let mut result = SyncResult::default();
+        if let Some(upstream) = self.tracking(remote, &branch)? {
+            let head = self.inner.head()?.peel_to_commit()?.id();
+            let (ahead, behind) = self.inner.graph_ahead_behind(head, upstream)?;
+            result.pulled = behind;
+            if behind > 0 && ahead == 0 {
+                self.fast_forward(&branch, upstream)?;
+            } else if behind > 0 {
+                let (rebased, conflicts) = self.rebase_onto(&branch, upstream, strategy)?;
+                result.conflicts_resolved = conflicts
+                    .iter()
+                    .map(|record| format!("collections/{}/{}.md", record.collection, record.id))
+                    .collect();
+                result.rebased = rebased;
+                result.conflicts = conflicts;
+            }
+        }
+
+        let pending = self.pending(remote)?;
+        if pending > 0 {
+            if let Err(e) = self.push_branch(remote, &branch) {
+                let mut offline = self.offline(remote, e)?;
+                offline.pulled = result.pulled;
+                offline.rebased = result.rebased;
+                offline.conflicts_resolved = result.conflicts_resolved;
+                offline.conflicts = result.conflicts;
+                return Ok(offline);
+            }
+            result.pushed = pending;
+        }
+
+        Ok(result)
+    }
+
+    /// Number of local commits not yet on the remote
+    ///
+    /// Before the first sync every commit counts as pending.
+    pub fn pending(&self, remote: &str) -> anyhow::Result<usize> {
+        let mut revwalk = self.inner.revwalk()?;
+        revwalk.push_head()?;
+        if let Some(upstream) = self.tracking(remote, &self.branch_name()?)? {
+            revwalk.hide(upstream)?;
+        }
+        Ok(revwalk.count())
+    }
+
+    /// Name of the checked-out branch
+    fn branch_name(&self) -> anyhow::Result<String> {
+        let head = self.inner.head()?;
+        if !head.is_branch() {
+            anyhow::bail!("Cannot sync a detached HEAD; check out a branch first");
+        }
+        Ok(head.shorthand().unwrap_or_default().to_string())
+    }
+
+    /// Commit the remote-tracking branch points at, if it has been fetched
+    fn tracking(&self, remote: &str, branch: &str) -> anyhow::Result<Option<Oid>> {
+        match self.inner.refname_to_id(&format!("refs/remotes/{}/{}", remote, branch)) {
+            Ok(oid) => Ok(Some(oid)),
+            Err(e) if e.code() == ErrorCode::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn fetch(&self, remote: &str, branch: &str) -> Result<(), git2::Error> {
+        let refspec = format!("+refs/heads/{}:refs/remotes/{}/{}", branch, remote, branch);
+        self.inner
+            .find_remote(remote)?
+            .fetch(&[refspec], Some(&mut self.fetch_options()), None)
+    }
+
+    /// Push a local branch and update its remote-tracking ref
+    pub(super) fn push_branch(&self, remote: &str, branch: &str) -> Result<(), git2::Error> {
+        let mut rejected = None;
+        {
+            let mut callbacks = RemoteCallbacks::new();
+            callbacks.push_update_reference(|_, status| {
+                rejected = status.map(String::from);
+                Ok(())
+            });
+            let mut options = self.push_options(callbacks);
+
+            let refspec = format!("refs/heads/{}:refs/heads/{}", branch, branch);
+            self.inner.find_remote(remote)?.push(&[refspec], Some(&mut options))?;
+        }
+        if let Some(reason) = rejected {
+            return Err(git2::Error::from_str(&format!("Push of '{}' rejected: {}", branch, reason)));
+        }
+
+        let head = self.inner.head()?.peel_to_commit()?.id();
+        self.inner
+            .reference(&format!("refs/remotes/{}/{}", remote, branch), head, true, "sync: push")?;
+        Ok(())
     }
 
-    /// Push changes to remote
-    pub async fn push(&mut self, _remote: &str) -> anyhow::Result<usize> {
-        // TODO: Implement push with libgit2
-        Ok(0)
+    /// Move the branch forward to `upstream` and update the working tree
+    fn fast_forward(&self, branch: &str, upstream: Oid) -> anyhow::Result<()> {
+        let commit = self.inner.find_commit(upstream)?;
+        self.inner
+            .checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe()))?;
+        self.inner
+            .reference(&format!("refs/heads/{}", branch), upstream, true, "sync: fast-forward")?;
+        Ok(())
+    }
+
+    /// Replay local commits onto `upstream`
+    ///
+    /// Each commit is cherry-picked in memory and written with its original
+    /// author, so replayed commits are signed like any other. Documents both
+    /// sides changed are merged with `strategy`; a conflict it can't resolve
+    /// stops the rebase before anything is changed.
+    fn rebase_onto(
+        &self,
+        branch: &str,
+        upstream: Oid,
+        strategy: ConflictResolution,
+    ) -> anyhow::Result<(Vec<RebasedCommit>, Vec<ConflictRecord>)> {
+        let mut conflicts = Vec::new();
+        let replayed = self.replay(upstream, strategy, &mut conflicts);
+
+        // Conflict records are checked out from the rebased commits, or
+        // discarded along with a failed rebase
+        if let Some(root) = self.inner.workdir() {
+            for record in &conflicts {
+                std::fs::remove_file(root.join(&record.path))?;
+            }
+        }
+
+        let (onto, rebased) = replayed?;
+        self.fast_forward(branch, onto)?;
+        Ok((rebased, conflicts))
     }
 
-    /// Full sync: pull, resolve conflicts, push
-    pub async fn full_sync(&mut self, remote: &str) -> anyhow::Result<SyncResult> {
-        let pulled = self.pull(remote).await?;
-        let pushed = self.push(remote).await?;
+    /// Write the local commits onto `upstream`, returning the new tip
+    fn replay(
+        &self,
+        upstream: Oid,
+        strategy: ConflictResolution,
+        conflicts: &mut Vec<ConflictRecord>,
+    ) -> anyhow::Result<(Oid, Vec<RebasedCommit>)> {
+        let mut revwalk = self.inner.revwalk()?;
+        revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+        revwalk.push_head()?;
+        revwalk.hide(upstream)?;
 
+        let mut onto = self.inner.find_commit(upstream)?;
+        let mut rebased = Vec::new();
+        for oid in revwalk {
+            let original = self.inner.find_commit(oid?)?;
+            let summary = original.summary().unwrap_or_default().to_string();
+
+            // A merge is replayed as its changes relative to the first parent
+            let mainline = if original.parent_count() > 1 { 1 } else { 0 };
+            let mut index = self.inner.cherrypick_commit(&original, &onto, mainline, None)?;
+            // The commit being replayed is the index's "their" side
+            let records = self.resolve_index_conflicts(&mut index, strategy, true).map_err(|e| {
+                anyhow::anyhow!(
+                    "Local commit {} ({}) conflicts with remote changes; nothing was synced: {}",
+                    &original.id().to_string()[..7],
+                    summary,
+                    e
+                )
+            })?;
+            conflicts.extend(records);
+
+            let tree = self.inner.find_tree(index.write_tree_to(&self.inner)?)?;
+            // The remote already has this change
+            if tree.id() == onto.tree_id() {
+                continue;
+            }
+
+            let author = original.author();
+            let message = original.message().unwrap_or_default();
+            let oid = self.create_commit(false, &author, message, &tree, &[&onto])?;
+            onto = self.inner.find_commit(oid)?;
+            rebased.push(RebasedCommit {
+                original: original.id().to_string(),
+                rebased: oid.to_string(),
+                summary,
+            });
+        }
+
+        Ok((onto.id(), rebased))
+    }
+
+    /// Result for an unreachable remote, or the error if it's something else
+    fn offline(&self, remote: &str, error: git2::Error) -> anyhow::Result<SyncResult> {
+        if !is_unreachable(&error) {
+            return Err(error.into());
+        }
+
+        tracing::warn!("Remote '{}' is unreachable: {}", remote, error.message());
         Ok(SyncResult {
-            pulled,
-            pushed,
-            conflicts_resolved: vec![],
+            offline: true,
+            queued: self.pending(remote)?,
+            ..SyncResult::default()
         })
     }
 }
+
+/// Whether a fetch or push failed because the remote can't be reached
+///
+/// Transport errors count, including `Os`, which is how refused connections
+/// and missing local remotes surface. Rejected credentials don't: queueing
+/// commits wouldn't help.
+pub(super) fn is_unreachable(error: &git2::Error) -> bool {
+    error.code() != ErrorCode::Auth
+        && matches!(error.class(), ErrorClass::Net | ErrorClass::Http | ErrorClass::Ssh | ErrorClass::Os)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A bare remote and two clones of it
+    fn setup() -> (TempDir, Repository, Repository) {
+        let tmp = TempDir::new().unwrap();
+        let bare = tmp.path().join("remote.git");
+        git2::Repository::init_bare(&bare).unwrap();
+
+        let mut a = Repository::open_or_init(&tmp.path().join("a")).unwrap();
+        a.inner().remote("origin", bare.to_str().unwrap()).unwrap();
+        sync(&mut a, "origin");
+
+        git2::Repository::clone(bare.to_str().unwrap(), tmp.path().join("b")).unwrap();
+        let b = Repository::open(&tmp.path().join("b")).unwrap();
+        (tmp, a, b)
+    }
+
+    fn sync(repo: &mut Repository, remote: &str) -> SyncResult {
+        try_sync(repo, remote).unwrap()
+    }
+
+    fn try_sync(repo: &mut Repository, remote: &str) -> anyhow::Result<SyncResult> {
+        sync_with(repo, remote, ConflictResolution::default())
+    }
+
+    fn sync_with(repo: &mut Repository, remote: &str, strategy: ConflictResolution) -> anyhow::Result<SyncResult> {
+        tokio::runtime::Runtime::new().unwrap().block_on(repo.sync(remote, strategy))
+    }
+
+    fn write_commit(repo: &Repository, file: &str, content: &str) {
+        let path = repo.inner().workdir().unwrap().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+        repo.commit(&format!("Write {}", file)).unwrap();
+    }
+
+    fn read(repo: &Repository, file: &str) -> String {
+        std::fs::read_to_string(repo.inner().workdir().unwrap().join(file)).unwrap()
+    }
+
+    #[test]
+    fn test_diverged_commits_are_rebased() {
+        let (_tmp, mut a, mut b) = setup();
+
+        write_commit(&a, "a.md", "from a");
+        assert_eq!(sync(&mut a, "origin").pushed, 1);
+
+        write_commit(&b, "b.md", "from b");
+        let result = sync(&mut b, "origin");
+        assert_eq!((result.pulled, result.pushed), (1, 1));
+        assert_eq!(result.rebased.len(), 1);
+        assert_eq!(result.rebased[0].summary, "Write b.md");
+        assert_eq!(read(&b, "a.md"), "from a");
+        assert_eq!(b.pending("origin").unwrap(), 0);
+
+        // A fast-forwards to the rebased history
+        let result = sync(&mut a, "origin");
+        assert_eq!((result.pulled, result.pushed), (1, 0));
+        assert!(result.rebased.is_empty());
+        assert_eq!(read(&a, "b.md"), "from b");
+    }
+
+    #[test]
+    fn test_conflicting_rebase_is_aborted() {
+        let (_tmp, mut a, mut b) = setup();
+
+        write_commit(&a, "same.md", "from a");
+        sync(&mut a, "origin");
+        write_commit(&b, "same.md", "from b");
+        let before = b.head_hash().unwrap();
+
+        let err = try_sync(&mut b, "origin").unwrap_err();
+        assert!(err.to_string().contains("conflicts with remote changes"));
+        assert_eq!(b.head_hash().unwrap(), before);
+        assert_eq!(read(&b, "same.md"), "from b");
+    }
+
+    #[test]
+    fn test_document_conflict_resolved_by_strategy() {
+        let (_tmp, mut a, mut b) = setup();
+        let doc = "collections/notes/n.md";
+
+        write_commit(&a, doc, "---\ntitle: Base\n---\nBody\n");
+        sync(&mut a, "origin");
+        sync(&mut b, "origin");
+        write_commit(&a, doc, "---\ntitle: Remote\n---\nBody\n");
+        sync(&mut a, "origin");
+        write_commit(&b, doc, "---\ntitle: Local\n---\nBody\n");
+        let before = b.head_hash().unwrap();
+
+        // Manual resolution stops the sync
+        let err = sync_with(&mut b, "origin", ConflictResolution::Manual).unwrap_err();
+        assert!(err.to_string().contains("Manual conflict resolution required"));
+        assert_eq!(b.head_hash().unwrap(), before);
+        assert!(!b.has_changes().unwrap());
+
+        let result = sync_with(&mut b, "origin", ConflictResolution::Ours).unwrap();
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(result.conflicts[0].strategy, ConflictResolution::Ours);
+        assert!(read(&b, doc).contains("title: Local"));
+        assert!(!b.has_changes().unwrap());
+        assert_eq!(b.pending("origin").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_unreachable_remote_queues_commits() {
+        let (_tmp, mut a, _b) = setup();
+        a.inner().remote("offline", "http://127.0.0.1:9/remote.git").unwrap();
+
+        write_commit(&a, "a.md", "queued");
+        let result = sync(&mut a, "offline");
+        assert!(result.offline);
+        assert!(result.queued >= 1);
+        assert_eq!(a.pending("origin").unwrap(), 1);
+    }
+}
@@ -0,0 +1,207 @@
+//! Document reference graph (`mdby graph`)
+//!
+//! Nodes are documents and edges are the links between them: `REF` field
+//! values and `[[wikilinks]]` in bodies, as checked by [`crate::links`]. Only
+//! links between documents that are in the graph become edges, so filtering
+//! by collection or tag gives the subgraph of those documents and broken
+//! links are left out.
+//!
+//! The graph renders as Graphviz DOT, with a cluster per collection, or
+//! serializes to JSON as `nodes` and `edges` with `source` and `target`, the
+//! shape force-directed graph views expect.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use crate::links::{extract_links, ref_values};
+use crate::storage::document::Value;
+use crate::Database;
+
+/// Which documents to include
+#[derive(Debug, Clone, Default)]
+pub struct GraphFilter {
+    /// Only documents in these collections; empty means all
+    pub collections: Vec<String>,
+    /// Only documents with at least one of these tags; empty means all
+    pub tags: Vec<String>,
+}
+
+/// A document in the graph
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Node {
+    /// `collection/id`
+    pub id: String,
+    pub collection: String,
+    pub document: String,
+    /// The document's title, if it has one
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// How one document links to another
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum EdgeKind {
+    /// A `REF` value in this field
+    Ref { field: String },
+    /// A `[[wikilink]]` in the body
+    Wikilink,
+}
+
+/// A link from one document to another
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Edge {
+    /// `collection/id` of the linking document
+    pub source: String,
+    /// `collection/id` of the linked document
+    pub target: String,
+    #[serde(flatten)]
+    pub kind: EdgeKind,
+}
+
+/// Documents and the links between them
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Graph {
+    /// Sorted by collection, then ID
+    pub nodes: Vec<Node>,
+    /// Sorted and without duplicates
+    pub edges: Vec<Edge>,
+}
+
+/// Build the graph of the documents matching `filter`
+///
+/// Titles and tags come from each collection's lint `title_field` and
+/// `tags_field`, `title` and `tags` by default.
+pub async fn build(db: &Database, filter: &GraphFilter) -> anyhow::Result<Graph> {
+    let mut graph = Graph::default();
+    let mut included = Vec::new();
+
+    for (collection, mut docs) in db.all_documents().await? {
+        if !filter.collections.is_empty() && !filter.collections.contains(&collection) {
+            continue;
+        }
+        let rules = db.lint.rules(&collection);
+        let title_field = rules.and_then(|r| r.title_field.as_deref()).unwrap_or("title");
+        let tags_field = rules.and_then(|r| r.tags_field.as_deref()).unwrap_or("tags");
+
+        docs.sort_by(|a, b| a.id.cmp(&b.id));
+        for doc in docs {
+            let tags: Vec<String> = match doc.get(tags_field) {
+                Some(Value::Array(values)) => values.iter().filter_map(|v| v.as_str()).map(str::to_string).collect(),
+                _ => Vec::new(),
+            };
+            if !filter.tags.is_empty() && !tags.iter().any(|tag| filter.tags.contains(tag)) {
+                continue;
+            }
+            graph.nodes.push(Node {
+                id: format!("{}/{}", collection, doc.id),
+                collection: collection.clone(),
+                document: doc.id.clone(),
+                title: doc.get(title_field).and_then(|v| v.as_str()).map(str::to_string),
+                tags,
+            });
+            included.push((collection.clone(), doc));
+        }
+    }
+
+    let nodes: BTreeSet<&str> = graph.nodes.iter().map(|node| node.id.as_str()).collect();
+    let mut edges = BTreeSet::new();
+    for (collection, doc) in &included {
+        let source = format!("{}/{}", collection, doc.id);
+        let refs = db.schema.get(collection).map(|schema| ref_values(schema, doc)).unwrap_or_default();
+        let refs = refs
+            .into_iter()
+            .map(|(field, target, id)| (format!("{}/{}", target, id), EdgeKind::Ref { field }));
+        let wikilinks = extract_links(&doc.body).wikilinks.into_iter().map(|target| {
+            let target = if target.contains('/') { target } else { format!("{}/{}", collection, target) };
+            (target, EdgeKind::Wikilink)
+        });
+
+        for (target, kind) in refs.chain(wikilinks) {
+            if target != source && nodes.contains(target.as_str()) {
+                edges.insert(Edge { source: source.clone(), target, kind });
+            }
+        }
+    }
+    graph.edges = edges.into_iter().collect();
+
+    Ok(graph)
+}
+
+impl Graph {
+    /// Graphviz DOT, with a cluster per collection; REF edges are labeled
+    /// with their field and wikilinks are dashed
+    pub fn to_dot(&self) -> String {
+        let mut by_collection: BTreeMap<&str, Vec<&Node>> = BTreeMap::new();
+        for node in &self.nodes {
+            by_collection.entry(&node.collection).or_default().push(node);
+        }
+
+        let mut dot = String::from("digraph mdby {\n    node [shape=box];\n");
+        for (collection, nodes) in by_collection {
+            dot.push_str(&format!("    subgraph {} {{\n", quote(&format!("cluster_{}", collection))));
+            dot.push_str(&format!("        label={};\n", quote(collection)));
+            for node in nodes {
+                let label = node.title.as_deref().unwrap_or(&node.document);
+                dot.push_str(&format!("        {} [label={}];\n", quote(&node.id), quote(label)));
+            }
+            dot.push_str("    }\n");
+        }
+        for edge in &self.edges {
+            let attributes = match &edge.kind {
+                EdgeKind::Ref { field } => format!("label={}", quote(field)),
+                EdgeKind::Wikilink => "style=dashed".to_string(),
+            };
+            dot.push_str(&format!("    {} -> {} [{}];\n", quote(&edge.source), quote(&edge.target), attributes));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// A DOT quoted string
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_dot() {
+        let node = |collection: &str, document: &str, title: Option<&str>| Node {
+            id: format!("{}/{}", collection, document),
+            collection: collection.into(),
+            document: document.into(),
+            title: title.map(str::to_string),
+            tags: Vec::new(),
+        };
+        let graph = Graph {
+            nodes: vec![node("posts", "hello", Some("Say \"hi\"")), node("users", "ally", None)],
+            edges: vec![
+                Edge { source: "posts/hello".into(), target: "users/ally".into(), kind: EdgeKind::Ref { field: "author".into() } },
+                Edge { source: "users/ally".into(), target: "posts/hello".into(), kind: EdgeKind::Wikilink },
+            ],
+        };
+
+        assert_eq!(
+            graph.to_dot(),
+            r#"digraph mdby {
+    node [shape=box];
+    subgraph "cluster_posts" {
+        label="posts";
+        "posts/hello" [label="Say \"hi\""];
+    }
+    subgraph "cluster_users" {
+        label="users";
+        "users/ally" [label="ally"];
+    }
+    "posts/hello" -> "users/ally" [label="author"];
+    "users/ally" -> "posts/hello" [style=dashed];
+}
+"#
+        );
+    }
+}
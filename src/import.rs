@@ -0,0 +1,223 @@
+//! Importing documents from other formats (`mdby import`)
+//!
+//! CSV: the header row names the fields. One column holds the document ID
+//! (`id` unless another is given), a `body` column holds the markdown body,
+//! and every other column becomes a frontmatter field, with empty cells left
+//! out. Cells are converted to the schema's field types: numbers, booleans
+//! and dates as an INSERT would coerce them, arrays from `;`-separated
+//! items, and objects from JSON. Without a schema, values stay strings.
+//!
+//! JSON Lines: one object per line, shaped like `mdby export json` writes
+//! documents: `id`, the fields, and `_body` for the markdown body. Values keep
+//! their JSON types, with the same coercion towards the schema as an INSERT.
+//! Blank lines are skipped.
+//!
+//! Rows without an ID get one from the schema's `id_strategy`. Every row is
+//! checked before anything is written, and the import is a single commit.
+
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read};
+
+use crate::schema::{coerce_value, FieldType, IdStrategy, Schema};
+use crate::slug::SlugOptions;
+use crate::storage::document::{Document, Value};
+use crate::validation::{validate_collection_name, validate_document_id};
+use crate::Database;
+
+/// Separator between the items of an array field in a CSV cell
+pub const CSV_ARRAY_SEPARATOR: char = ';';
+
+/// Import the rows of a CSV file into a collection, creating it if needed
+///
+/// Returns the IDs of the new documents, in row order.
+pub async fn import_csv(db: &Database, name: &str, input: impl Read, id_column: &str) -> anyhow::Result<Vec<String>> {
+    validate_collection_name(name)?;
+    let schema = db.schema.get(name);
+
+    let mut reader = csv::Reader::from_reader(input);
+    let headers = reader.headers()?.clone();
+    let id_index = headers.iter().position(|header| header == id_column);
+    let strategy = schema.map(|s| s.id_strategy.clone()).unwrap_or_default();
+    if id_index.is_none() && matches!(strategy, IdStrategy::Manual) {
+        anyhow::bail!(
+            "The CSV has no '{}' column and collection '{}' has no id_strategy to generate IDs",
+            id_column,
+            name
+        );
+    }
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map_or(0, |position| position.line());
+
+        let mut doc = Document::new(String::new());
+        for (i, (header, cell)) in headers.iter().zip(record.iter()).enumerate() {
+            if Some(i) == id_index {
+                doc.id = cell.trim().to_string();
+            } else if header == "body" {
+                doc.body = cell.to_string();
+            } else if !cell.is_empty() {
+                let field_type = schema.and_then(|s| s.fields.get(header)).map(|def| &def.field_type);
+                doc.fields.insert(header.to_string(), cell_value(field_type, cell));
+            }
+        }
+        rows.push((line, doc));
+    }
+
+    insert_rows(db, name, rows, id_column).await
+}
+
+/// Import the objects of a JSON Lines file into a collection, creating it if
+/// needed
+///
+/// Returns the IDs of the new documents, in line order.
+pub async fn import_jsonl(db: &Database, name: &str, input: impl Read) -> anyhow::Result<Vec<String>> {
+    validate_collection_name(name)?;
+    let schema = db.schema.get(name);
+
+    let mut rows = Vec::new();
+    for (i, line) in BufReader::new(input).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let number = i as u64 + 1;
+        let doc = json_document(&line).map_err(|e| anyhow::anyhow!("Line {}: {}", number, e))?;
+        rows.push((number, doc));
+    }
+    if let Some(schema) = schema {
+        for (_, doc) in &mut rows {
+            schema.coerce(doc);
+        }
+    }
+
+    insert_rows(db, name, rows, "id").await
+}
+
+/// A document from one JSON Lines object
+fn json_document(line: &str) -> anyhow::Result<Document> {
+    let serde_json::Value::Object(object) = serde_json::from_str(line)? else {
+        anyhow::bail!("Expected a JSON object");
+    };
+
+    let mut doc = Document::new(String::new());
+    for (key, value) in object {
+        match (key.as_str(), value) {
+            ("id", serde_json::Value::String(id)) => doc.id = id.trim().to_string(),
+            ("id", _) => anyhow::bail!("'id' must be a string"),
+            ("_body", serde_json::Value::String(body)) => doc.body = body,
+            ("_body", _) => anyhow::bail!("'_body' must be a string"),
+            (_, serde_json::Value::Null) => {}
+            (_, value) => {
+                doc.fields.insert(key, serde_json::from_value(value)?);
+            }
+        }
+    }
+    Ok(doc)
+}
+
+/// Give each row an ID, check them all, then write them in one commit
+///
+/// `id_key` names where IDs come from, for errors. Rows are numbered by the
+/// line they start on.
+async fn insert_rows(db: &Database, name: &str, rows: Vec<(u64, Document)>, id_key: &str) -> anyhow::Result<Vec<String>> {
+    let schema = db.schema.get(name);
+    let strategy = schema.map(|s| s.id_strategy.clone()).unwrap_or_default();
+    let format = schema.map(|s| s.id_format.clone()).unwrap_or_default();
+    let stored: HashSet<String> = db.store.ids(name).await?.into_iter().collect();
+
+    let mut docs: Vec<Document> = Vec::new();
+    for (line, mut doc) in rows {
+        let row_error = |e: anyhow::Error| anyhow::anyhow!("Line {}: {}", line, e);
+
+        if doc.id.is_empty() {
+            let base = crate::schema::ids::generate(&strategy, &format, &db.root, name, &doc, |id| stored.contains(id))
+                .await
+                .map_err(row_error)?
+                .ok_or_else(|| row_error(anyhow::anyhow!("Empty '{}' and no id_strategy", id_key)))?;
+            // Derived IDs can repeat within one import; suffix them like taken ones
+            doc.id = crate::slug::unique(&base, &SlugOptions::default(), |id| docs.iter().any(|other| other.id == id));
+        }
+        validate_document_id(&doc.id).map_err(|e| row_error(e.into()))?;
+        if docs.iter().any(|other| other.id == doc.id) {
+            return Err(row_error(anyhow::anyhow!("Document '{}' appears more than once", doc.id)));
+        }
+        crate::query::check_insertable(db, name, &doc.id).await.map_err(row_error)?;
+        if let Some(schema) = schema {
+            schema.normalize_datetimes(&mut doc, db.config.timezone);
+        }
+
+        check_document(db, name, schema, &doc).map_err(row_error)?;
+        docs.push(doc);
+    }
+    crate::query::check_unique(db, name, &docs).await?;
+    crate::query::check_refs(db, name, &docs).await?;
+
+    let layout = crate::query::layout(db, name);
+    db.store.create_collection(name).await?;
+    for doc in &docs {
+        db.store.put(name, doc, &layout).await?;
+    }
+    crate::query::update_indexes(db, name, &docs, &[])?;
+    if !docs.is_empty() {
+        db.git.commit(&format!("IMPORT into {}: {} document(s)", name, docs.len()))?;
+    }
+
+    Ok(docs.into_iter().map(|doc| doc.id).collect())
+}
+
+/// Schema, limit and lint checks for one imported document
+fn check_document(db: &Database, name: &str, schema: Option<&Schema>, doc: &Document) -> anyhow::Result<()> {
+    if let Some(schema) = schema {
+        schema.validate(doc)?;
+    }
+    db.config.limits.check(doc)?;
+    db.lint.enforce(name, doc)?;
+    Ok(())
+}
+
+/// A CSV cell as a value of the field's type, or a string if it doesn't
+/// convert (for validation to report)
+fn cell_value(field_type: Option<&FieldType>, cell: &str) -> Value {
+    let text = Value::String(cell.to_string());
+    match field_type {
+        None => text,
+        Some(FieldType::Array(inner)) => Value::Array(
+            cell.split(CSV_ARRAY_SEPARATOR)
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| cell_value(Some(inner), item))
+                .collect(),
+        ),
+        Some(FieldType::Object) => serde_json::from_str(cell).unwrap_or(text),
+        Some(field_type) => coerce_value(field_type, &text).unwrap_or(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_document() {
+        let doc = json_document(r#"{"id": "t1", "title": "Milk", "priority": 2, "note": null, "_body": "Oat"}"#).unwrap();
+        assert_eq!(doc.id, "t1");
+        assert_eq!(doc.body, "Oat");
+        assert_eq!(doc.get("priority"), Some(&Value::Int(2)));
+        assert_eq!(doc.get("note"), None);
+        assert!(json_document("[1, 2]").is_err());
+        assert!(json_document(r#"{"id": 7}"#).is_err());
+    }
+
+    #[test]
+    fn test_cell_value() {
+        let array = FieldType::Array(Box::new(FieldType::Int));
+        assert_eq!(cell_value(Some(&array), "1; 2;;3"), Value::Array(vec![Value::Int(1), Value::Int(2), Value::Int(3)]));
+        assert_eq!(cell_value(Some(&FieldType::Bool), "TRUE"), Value::Bool(true));
+        assert_eq!(cell_value(Some(&FieldType::Int), "many"), Value::String("many".into()));
+        assert_eq!(cell_value(None, "42"), Value::String("42".into()));
+        let object = cell_value(Some(&FieldType::Object), r#"{"a": 1}"#);
+        assert!(matches!(object, Value::Object(map) if map.get("a") == Some(&Value::Int(1))));
+    }
+}
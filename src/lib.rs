@@ -42,19 +42,43 @@
 //! └─────────────────────────────────────────────────────────────────┘
 //! ```
 
+pub mod config;
+pub mod dedupe;
+pub mod diff;
+pub mod embeddings;
 pub mod error;
+pub mod export;
+pub mod find;
 pub mod git;
+pub mod graph;
+pub mod import;
+pub mod links;
+pub mod lint;
+pub mod openapi;
+pub mod policy;
 pub mod query;
+pub mod query_log;
+pub mod remote;
+pub mod repl;
 pub mod schema;
+pub mod seed;
+pub mod server;
+pub mod slug;
+pub mod starter;
 pub mod storage;
+pub mod system;
 pub mod validation;
 pub mod views;
 
 pub use error::{Error, Result};
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 pub use storage::document::Document;
+use storage::document::Value;
+use storage::store::Layout;
 pub use storage::collection::Collection;
 pub use schema::Schema;
 
@@ -66,37 +90,988 @@ pub struct Database {
     pub git: git::Repository,
     /// Schema registry
     pub(crate) schema: schema::SchemaRegistry,
+    /// Settings from `.mdby/config.yaml`
+    pub config: config::Config,
+    /// Lint rules from `.mdby/lint.yaml`
+    pub lint: lint::LintConfig,
+    /// Where documents are kept; markdown files unless replaced with
+    /// [`Database::set_store`]
+    pub(crate) store: Arc<dyn storage::store::Store>,
+    /// Embedding provider set with [`Database::set_embedding_provider`]
+    embedder: Option<Arc<dyn embeddings::EmbeddingProvider>>,
+    /// Statements this handle may execute
+    policy: policy::ExecutionPolicy,
+    /// Documents read per collection by the statement being executed, for
+    /// the query log
+    scans: std::sync::Mutex<BTreeMap<String, usize>>,
 }
 
 impl Database {
     /// Open or create a database at the given path
+    ///
+    /// A path to a file opens it as an archive (see [`Database::open_archive`]).
     pub async fn open(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
         let root = path.into();
-        let git = git::Repository::open_or_init(&root)?;
+        if root.is_file() {
+            return Self::open_archive(root).await;
+        }
+        let mut git = git::Repository::open_or_init(&root)?;
+        // Finish a statement a crash interrupted before anything reads the files
+        storage::journal::recover(&root, git.head_hash().ok())?;
         let schema = schema::SchemaRegistry::load(&root)?;
+        let config = config::Config::load(&root)?;
+        let lint = lint::LintConfig::load(&root)?;
+        git.set_signing(config.signing.clone());
+        git.set_ssh_key(config.sync.ssh_key.clone());
 
-        Ok(Self { root, git, schema })
+        let store = Arc::new(storage::store::MarkdownStore::new(&root));
+        Ok(Self { root, git, schema, config, lint, store, embedder: None, policy: policy::ExecutionPolicy::trusted(), scans: Default::default() })
+    }
+
+    /// Open a bare git repository as a read-only replica of `reference`
+    ///
+    /// Queries read documents straight from the commit `reference` (a branch,
+    /// tag or commit hash) points at, without checking anything out, so one
+    /// server can answer queries for many databases. The reference is resolved
+    /// again for each query; schemas and settings are read once, here.
+    /// Statements that write fail.
+    pub async fn open_bare(path: impl Into<PathBuf>, reference: &str) -> anyhow::Result<Self> {
+        let root = path.into();
+        let git = git::Repository::open_bare(&root, reference)?;
+
+        let (schema, config, lint) = {
+            let tree = git
+                .snapshot()?
+                .ok_or_else(|| anyhow::anyhow!("{:?} is not a read-only replica", root))?;
+            let schema_dir = std::path::Path::new(".mdby").join("schemas");
+            let mut schemas = Vec::new();
+            for file in tree.files(&schema_dir)? {
+                if file.ends_with(".yaml") {
+                    let content = tree.read_file(schema_dir.join(&file))?.unwrap_or_default();
+                    schemas.push(serde_yaml::from_str(&content)?);
+                }
+            }
+
+            let config = match tree.read_file(".mdby/config.yaml")? {
+                Some(content) => serde_yaml::from_str(&content)
+                    .map_err(|e| anyhow::anyhow!("Invalid config in {}: {}", reference, e))?,
+                None => config::Config::default(),
+            };
+            let lint = match tree.read_file(".mdby/lint.yaml")? {
+                Some(content) => serde_yaml::from_str(&content)
+                    .map_err(|e| anyhow::anyhow!("Invalid lint rules in {}: {}", reference, e))?,
+                None => lint::LintConfig::default(),
+            };
+            (schema::SchemaRegistry::from_schemas(&root, schemas), config, lint)
+        };
+
+        let store = Arc::new(storage::store::MarkdownStore::new(&root));
+        Ok(Self { root, git, schema, config, lint, store, embedder: None, policy: policy::ExecutionPolicy::trusted(), scans: Default::default() })
+    }
+
+    /// Open a `.zip`, `.tar` or `.tar.gz` snapshot of a database directory,
+    /// read-only and without extracting it
+    ///
+    /// The archive is read into memory once; its documents are served by an
+    /// [`storage::archive::ArchiveStore`] and its schemas and settings come
+    /// from the `.mdby/` inside it. There is no git history, so `@commits`
+    /// and the history fields are unavailable. Statements that write fail.
+    pub async fn open_archive(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let root = path.into();
+        let archive = storage::archive::ArchiveStore::open(&root)?;
+
+        let mut schemas = Vec::new();
+        for file in archive.files(".mdby/schemas") {
+            if file.ends_with(".yaml") {
+                let content = archive.read_file(&format!(".mdby/schemas/{}", file)).unwrap_or_default();
+                schemas.push(serde_yaml::from_str(&content)?);
+            }
+        }
+        let config = match archive.read_file(".mdby/config.yaml") {
+            Some(content) => serde_yaml::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("Invalid config in {:?}: {}", root, e))?,
+            None => config::Config::default(),
+        };
+        let lint = match archive.read_file(".mdby/lint.yaml") {
+            Some(content) => serde_yaml::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("Invalid lint rules in {:?}: {}", root, e))?,
+            None => lint::LintConfig::default(),
+        };
+
+        let schema = schema::SchemaRegistry::from_schemas(&root, schemas);
+        let git = git::Repository::empty()?;
+        let store = Arc::new(archive);
+        Ok(Self { root, git, schema, config, lint, store, embedder: None, policy: policy::ExecutionPolicy::trusted(), scans: Default::default() })
+    }
+
+    /// A read-only handle on this database as of `reference` (a branch, tag,
+    /// commit hash or any other git revision), with the same policy and
+    /// embedding provider
+    pub async fn at_revision(&self, reference: &str) -> anyhow::Result<Self> {
+        let mut db = Self::open_bare(self.git.inner().path(), reference).await?;
+        db.root = self.root.clone();
+        db.embedder = self.embedder.clone();
+        db.policy = self.policy.clone();
+        Ok(db)
+    }
+
+    /// Whether the database is a read-only replica opened with
+    /// [`Database::open_bare`], or its store can't be written (an archive)
+    pub fn is_read_only(&self) -> bool {
+        self.git.is_read_only() || self.store.is_read_only()
+    }
+
+    /// Restrict the statements [`Database::execute`] accepts
+    ///
+    /// This replaces the [trusted](policy::ExecutionPolicy::trusted) policy a
+    /// handle starts with, so `BODY FROM FILE` is refused unless `policy`
+    /// allows it.
+    pub fn set_policy(&mut self, policy: policy::ExecutionPolicy) {
+        self.policy = policy;
+    }
+
+    /// Statements this handle may execute
+    pub fn policy(&self) -> &policy::ExecutionPolicy {
+        &self.policy
+    }
+
+    /// Keep documents in `store` instead of the markdown files under
+    /// `/collections/` (see [`storage::store`])
+    ///
+    /// Schemas, views and settings stay in `/.mdby/`.
+    pub fn set_store(&mut self, store: impl storage::store::Store + 'static) {
+        self.store = Arc::new(store);
+    }
+
+    /// Use `provider` for `SEMANTIC_SEARCH` instead of the `embeddings`
+    /// command in `.mdby/config.yaml`
+    pub fn set_embedding_provider(&mut self, provider: impl embeddings::EmbeddingProvider + 'static) {
+        self.embedder = Some(Arc::new(provider));
+    }
+
+    /// Provider for `SEMANTIC_SEARCH`: the one set in code, or else the
+    /// command from the config
+    pub fn embedding_provider(&self) -> Option<Arc<dyn embeddings::EmbeddingProvider>> {
+        self.embedder.clone().or_else(|| {
+            let command = self.config.embeddings.clone()?;
+            Some(Arc::new(embeddings::CommandProvider::new(command)) as Arc<dyn embeddings::EmbeddingProvider>)
+        })
     }
 
     /// Execute an MDQL query
+    ///
+    /// A query of several statements separated by semicolons runs them all,
+    /// in order, even after one fails, and returns a [`QueryResult::Batch`]
+    /// with each statement's outcome. A batch that doesn't parse runs nothing.
+    ///
+    /// With `query_log` set in the config, each statement and its timing are
+    /// appended to the query log.
     pub async fn execute(&mut self, query: &str) -> anyhow::Result<QueryResult> {
-        let parsed = mdql::parse(query)?;
-        self.execute_ast(parsed).await
+        let started = std::time::SystemTime::now();
+        let timer = std::time::Instant::now();
+        let parsed = mdql::parse_script(query).and_then(|statements| match statements.is_empty() {
+            // Reports the error for an empty query
+            true => mdql::parse(query).map(|statement| vec![(1, statement)]),
+            false => Ok(statements),
+        });
+        let mut statements = match parsed {
+            Ok(statements) => statements,
+            Err(e) => {
+                self.log_query(query, started, timer, Some(e.to_string()));
+                return Err(e.into());
+            }
+        };
+
+        if statements.len() == 1 {
+            return self.execute_logged(query, statements.remove(0).1).await;
+        }
+
+        let mut outcomes = Vec::with_capacity(statements.len());
+        for (line, statement) in statements {
+            let text = statement.to_string();
+            let result = self.execute_logged(&text, statement).await;
+            outcomes.push(StatementOutcome { line, statement: text, result });
+        }
+        Ok(QueryResult::Batch(BatchResult { statements: outcomes }))
+    }
+
+    /// Execute a script of statements separated by semicolons as one
+    /// transaction
+    ///
+    /// Statements run in order until one fails. If none does, their changes
+    /// are a single commit listing what each statement did; if one does,
+    /// everything the script changed is undone and nothing is committed, so
+    /// the returned outcomes end with the failure. A script that doesn't
+    /// parse runs nothing, and the error names the line.
+    pub async fn execute_script(&mut self, script: &str) -> anyhow::Result<BatchResult> {
+        let statements = mdql::parse_script(script)?;
+        self.execute_transaction(script, "SCRIPT", statements).await
+    }
+
+    /// Execute a list of statements as one transaction, like
+    /// [`execute_script`](Self::execute_script)
+    ///
+    /// Each outcome's `line` is the statement's position in the list,
+    /// counting from 1. If any statement doesn't parse, none run.
+    pub async fn execute_batch(&mut self, statements: &[String]) -> anyhow::Result<BatchResult> {
+        let parsed = statements
+            .iter()
+            .enumerate()
+            .map(|(i, statement)| match mdql::parse(statement) {
+                Ok(parsed) => Ok((i + 1, parsed)),
+                Err(e) => Err(anyhow::anyhow!("Statement {}: {}", i + 1, e)),
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        self.execute_transaction(&statements.join(";\n"), "BATCH", parsed).await
+    }
+
+    /// Execute statements until one fails, committing their changes together
+    /// under `label` if none does and undoing them all if one does
+    async fn execute_transaction(
+        &mut self,
+        text: &str,
+        label: &str,
+        statements: Vec<(usize, mdql::Statement)>,
+    ) -> anyhow::Result<BatchResult> {
+        if self.is_read_only() || statements.iter().all(|(_, statement)| statement.is_read_only()) {
+            return Ok(self.execute_statements(statements).await);
+        }
+
+        let head = self.git.head_hash().ok();
+        storage::journal::begin(&self.root, text, head.clone())?;
+        self.git.hold_commits();
+        let batch = self.execute_statements(statements).await;
+        let held = self.git.release_commits();
+
+        if batch.failed() > 0 {
+            storage::journal::rollback(&self.root)?;
+            self.schema = schema::SchemaRegistry::load(&self.root)?;
+            return Ok(batch);
+        }
+        if !held.is_empty() {
+            let mut transaction = git::Transaction::begin(&self.git, format!("{}: {} statement(s)", label, held.len()));
+            for message in held {
+                transaction.record(message);
+            }
+            transaction.commit()?;
+        }
+        storage::journal::clear(&self.root)?;
+        if let (Some(head), true) = (&head, self.config.auto_regenerate_views) {
+            self.regenerate_changed_views(head).await;
+        }
+        Ok(batch)
+    }
+
+    /// Execute statements in order, stopping after the first that fails
+    async fn execute_statements(&mut self, statements: Vec<(usize, mdql::Statement)>) -> BatchResult {
+        let mut outcomes = Vec::with_capacity(statements.len());
+        for (line, statement) in statements {
+            let text = statement.to_string();
+            let result = self.execute_logged(&text, statement).await;
+            let failed = result.is_err();
+            outcomes.push(StatementOutcome { line, statement: text, result });
+            if failed {
+                break;
+            }
+        }
+        BatchResult { statements: outcomes }
+    }
+
+    /// Execute one parsed statement, logging it as `query`
+    async fn execute_logged(&mut self, query: &str, statement: mdql::Statement) -> anyhow::Result<QueryResult> {
+        if !self.config.query_log {
+            return self.execute_ast(statement).await;
+        }
+
+        self.take_scans();
+        let started = std::time::SystemTime::now();
+        let timer = std::time::Instant::now();
+        let result = self.execute_ast(statement).await;
+        self.log_query(query, started, timer, result.as_ref().err().map(|e| e.to_string()));
+        result
+    }
+
+    /// Append a statement to the query log, if it is enabled
+    fn log_query(&self, query: &str, started: std::time::SystemTime, timer: std::time::Instant, error: Option<String>) {
+        if !self.config.query_log {
+            return;
+        }
+        let mut entry = query_log::QueryLogEntry::new(query, started, timer.elapsed().as_secs_f64() * 1000.0);
+        entry.scanned = self.take_scans();
+        entry.error = error;
+        if let Err(e) = query_log::append(&self.root, &entry) {
+            tracing::warn!("Failed to write the query log: {}", e);
+        }
+    }
+
+    /// Files a statement would remove, relative to the database root, e.g. to
+    /// confirm a DELETE or DROP before running it
+    pub async fn removals(&self, statement: &mdql::Statement) -> anyhow::Result<Vec<PathBuf>> {
+        query::removals(self, statement).await
+    }
+
+    /// Compare the results of two SELECT statements
+    ///
+    /// `before` runs at revision `from` and `after` at revision `to`; a
+    /// revision of `None` means the database as it is now.
+    pub async fn diff_queries(
+        &mut self,
+        before: &str,
+        from: Option<&str>,
+        after: &str,
+        to: Option<&str>,
+    ) -> anyhow::Result<diff::ResultDiff> {
+        let before = self.select_at(before, from).await?;
+        let after = self.select_at(after, to).await?;
+        Ok(diff::diff(&before, &after))
+    }
+
+    /// The last commit that wrote a document, or `None` if it isn't committed
+    ///
+    /// This is the document's version for optimistic concurrency: a writer
+    /// that read the document at one version can check it is still current
+    /// before changing it (as `mdby serve` does for `If-Match`). A body kept
+    /// compressed counts as part of the document.
+    pub fn document_version(&self, collection: &str, id: &str) -> anyhow::Result<Option<String>> {
+        validation::validate_collection_name(collection)?;
+        validation::validate_document_id(id)?;
+        let dir = std::path::Path::new("collections").join(collection);
+        let file = format!("{}.md", id);
+        let Some(document) = self.git.file_history(&dir.join(&file))? else {
+            return Ok(None);
+        };
+        let body = self.git.file_history(&dir.join(storage::compression::body_file(&file)))?;
+        Ok(Some(match body {
+            Some(body) if body.modified > document.modified => body.commit,
+            _ => document.commit,
+        }))
+    }
+
+    /// Every version of a document, newest first, each with the commit that
+    /// wrote it; `document` is `None` where a commit deleted it
+    pub async fn history(&self, collection: &str, id: &str) -> anyhow::Result<Vec<DocumentVersion>> {
+        validation::validate_collection_name(collection)?;
+        validation::validate_document_id(id)?;
+        let path = std::path::Path::new("collections").join(collection).join(format!("{}.md", id));
+        self.git
+            .file_versions(&path)?
+            .into_iter()
+            .map(|(commit, content)| {
+                let document = content.map(|content| Document::parse(id, &content)).transpose()?;
+                Ok(DocumentVersion { commit, document })
+            })
+            .collect()
+    }
+
+    async fn select_at(&mut self, query: &str, revision: Option<&str>) -> anyhow::Result<Vec<Document>> {
+        let result = match revision {
+            Some(revision) => self.at_revision(revision).await?.execute(query).await?,
+            None => self.execute(query).await?,
+        };
+        match result {
+            QueryResult::Documents(docs) => Ok(docs),
+            _ => anyhow::bail!("Only SELECT results can be compared: {}", query),
+        }
+    }
+
+    /// Count documents read from a collection, for the query log
+    pub(crate) fn record_scan(&self, collection: &str, documents: usize) {
+        if let Ok(mut scans) = self.scans.lock() {
+            *scans.entry(collection.to_string()).or_default() += documents;
+        }
+    }
+
+    fn take_scans(&self) -> BTreeMap<String, usize> {
+        self.scans.lock().map(|mut scans| std::mem::take(&mut *scans)).unwrap_or_default()
     }
 
     /// Execute a parsed AST
+    ///
+    /// Statements that write are journaled (see [`storage::journal`]): one
+    /// that fails before committing leaves its files as they were. With
+    /// `auto_regenerate_views` set, the views reading the collections a
+    /// statement changed are regenerated before it returns.
     async fn execute_ast(&mut self, ast: mdql::Statement) -> anyhow::Result<QueryResult> {
-        query::execute(self, ast).await
+        // A script journals and commits its statements together
+        if ast.is_read_only() || self.is_read_only() || self.git.is_holding_commits() {
+            return query::execute(self, ast).await;
+        }
+
+        let head = self.git.head_hash().ok();
+        storage::journal::begin(&self.root, &ast.to_string(), head.clone())?;
+        let result = query::execute(self, ast).await;
+        if result.is_err() && self.git.head_hash().ok() == head {
+            storage::journal::rollback(&self.root)?;
+            // The schema files may have been put back
+            self.schema = schema::SchemaRegistry::load(&self.root)?;
+        } else {
+            storage::journal::clear(&self.root)?;
+        }
+        if let (Ok(_), Some(head), true) = (&result, &head, self.config.auto_regenerate_views) {
+            self.regenerate_changed_views(head).await;
+        }
+        result
+    }
+
+    /// Regenerate the views reading collections changed since `commit`
+    ///
+    /// The statement has already committed, so failures are only logged.
+    async fn regenerate_changed_views(&self, commit: &str) {
+        let changed = match self.git.changed_since(commit) {
+            Ok(changed) => changed,
+            Err(e) => {
+                tracing::error!("Failed to find changed collections: {}", e);
+                return;
+            }
+        };
+        let collections: BTreeSet<String> = changed
+            .iter()
+            .filter_map(|path| match path.strip_prefix("collections").ok()?.components().next()? {
+                std::path::Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        if let Err(e) = views::regenerate_reading(self, &collections).await {
+            tracing::error!("Failed to regenerate views: {}", e);
+        }
+    }
+
+    /// Validate every document in a collection against its schema
+    ///
+    /// Returns one report per document that has at least one violation.
+    /// Collections without a schema always validate cleanly.
+    pub async fn validate_collection(&self, name: &str) -> anyhow::Result<Vec<schema::ValidationReport>> {
+        validation::validate_collection_name(name)?;
+        let collection = Collection::open(name, &self.root);
+        if !collection.exists().await {
+            anyhow::bail!("Collection '{}' does not exist", name);
+        }
+
+        let Some(schema) = self.schema.get(name) else {
+            return Ok(Vec::new());
+        };
+
+        let mut docs = collection.list().await?;
+        docs.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(docs
+            .iter()
+            .filter_map(|doc| {
+                let errors = schema.validate_all(doc);
+                (!errors.is_empty()).then(|| schema::ValidationReport {
+                    collection: name.to_string(),
+                    id: doc.id.clone(),
+                    errors,
+                })
+            })
+            .collect())
+    }
+
+    /// Check every document in a collection for links that don't resolve
+    ///
+    /// Covers wikilinks, `REF` fields and relative links; external URLs are
+    /// only requested when `external` is set. Returns one report per
+    /// document with at least one broken link.
+    pub async fn check_links(&self, name: &str, external: bool) -> anyhow::Result<Vec<links::LinkReport>> {
+        validation::validate_collection_name(name)?;
+        if !Collection::open(name, &self.root).exists().await {
+            anyhow::bail!("Collection '{}' does not exist", name);
+        }
+
+        links::check_collection(&self.root, name, self.schema.get(name), external).await
+    }
+
+    /// Check every document in a collection against its lint rules
+    ///
+    /// Returns one report per document that has at least one violation.
+    /// Collections without lint rules always pass.
+    pub async fn lint_collection(&self, name: &str) -> anyhow::Result<Vec<lint::LintReport>> {
+        validation::validate_collection_name(name)?;
+        let collection = Collection::open(name, &self.root);
+        if !collection.exists().await {
+            anyhow::bail!("Collection '{}' does not exist", name);
+        }
+
+        let Some(rules) = self.lint.rules(name) else {
+            return Ok(Vec::new());
+        };
+
+        let mut docs = collection.list().await?;
+        docs.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(docs
+            .iter()
+            .filter_map(|doc| {
+                let violations = rules.check(doc);
+                (!violations.is_empty()).then(|| lint::LintReport {
+                    collection: name.to_string(),
+                    id: doc.id.clone(),
+                    violations,
+                })
+            })
+            .collect())
+    }
+
+    /// Rebuild every index of a collection from its documents, returning the
+    /// fields reindexed
+    ///
+    /// Index files of fields that are no longer indexed are removed.
+    pub async fn reindex(&self, name: &str) -> anyhow::Result<Vec<String>> {
+        validation::validate_collection_name(name)?;
+        let collection = Collection::open(name, &self.root);
+        if !collection.exists().await {
+            anyhow::bail!("Collection '{}' does not exist", name);
+        }
+
+        let indexes = storage::index::IndexManager::new(&self.root);
+        indexes.remove_collection(name)?;
+        let fields = query::indexed_fields(self, name);
+        for field in &fields {
+            indexes.rebuild(&collection, field).await?;
+        }
+        Ok(fields)
+    }
+
+    /// Compare a collection's indexes with its documents
+    ///
+    /// Returns one report per index that disagrees with them. Indexes that
+    /// haven't been built yet have nothing to disagree about.
+    pub async fn check_indexes(&self, name: &str) -> anyhow::Result<Vec<storage::index::IndexReport>> {
+        validation::validate_collection_name(name)?;
+        let collection = Collection::open(name, &self.root);
+        if !collection.exists().await {
+            anyhow::bail!("Collection '{}' does not exist", name);
+        }
+
+        let indexes = storage::index::IndexManager::new(&self.root);
+        let mut reports = Vec::new();
+        for field in query::indexed_fields(self, name) {
+            if let Some(drift) = indexes.check(&collection, &field).await? {
+                if !drift.is_empty() {
+                    reports.push(storage::index::IndexReport { collection: name.to_string(), field, drift });
+                }
+            }
+        }
+        Ok(reports)
+    }
+
+    /// Flip a boolean field of one document, returning its new value
+    ///
+    /// A missing field counts as false. The change runs as an UPDATE
+    /// statement, so policies, validation and the query log apply as usual.
+    pub async fn toggle(&mut self, collection: &str, id: &str, field: &str) -> anyhow::Result<bool> {
+        let doc = self.document(collection, id).await?;
+        let value = match doc.get(field) {
+            None | Some(Value::Null) => true,
+            Some(Value::Bool(value)) => !value,
+            Some(_) => anyhow::bail!("Field '{}' of '{}' is not a boolean", field, id),
+        };
+        self.update_field(collection, id, field, mdql::Literal::Bool(value)).await?;
+        Ok(value)
+    }
+
+    /// Add and remove tags in an array field of one document, returning the
+    /// tags afterwards
+    ///
+    /// New tags go at the end and tags already present keep their place. Like
+    /// [`Database::toggle`], this runs an UPDATE statement.
+    pub async fn tag(
+        &mut self,
+        collection: &str,
+        id: &str,
+        field: &str,
+        add: &[String],
+        remove: &[String],
+    ) -> anyhow::Result<Vec<String>> {
+        let doc = self.document(collection, id).await?;
+        let mut tags: Vec<String> = match doc.get(field) {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(values)) => values
+                .iter()
+                .map(|value| match value {
+                    Value::String(tag) => Ok(tag.clone()),
+                    _ => Err(anyhow::anyhow!("Field '{}' of '{}' holds a non-string tag", field, id)),
+                })
+                .collect::<anyhow::Result<_>>()?,
+            Some(_) => anyhow::bail!("Field '{}' of '{}' is not an array", field, id),
+        };
+        tags.retain(|tag| !remove.contains(tag));
+        for tag in add {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+
+        let literal = mdql::Literal::Array(tags.iter().cloned().map(mdql::Literal::String).collect());
+        self.update_field(collection, id, field, literal).await?;
+        Ok(tags)
+    }
+
+    /// Documents in every collection whose ID or title fuzzily matches `text`,
+    /// best first; see [`find`]
+    pub async fn find(&self, text: &str, limit: usize) -> anyhow::Result<Vec<find::FindMatch>> {
+        find::find(self, text, limit).await
+    }
+
+    /// Link graph of the documents matching `filter`; see [`graph`]
+    pub async fn graph(&self, filter: &graph::GraphFilter) -> anyhow::Result<graph::Graph> {
+        graph::build(self, filter).await
+    }
+
+    /// Every collection's documents, by collection name, from the working
+    /// tree or a replica's commit
+    pub(crate) async fn all_documents(&self) -> anyhow::Result<Vec<(String, Vec<Document>)>> {
+        if let Some(tree) = self.git.snapshot()? {
+            return tree
+                .collections()?
+                .into_iter()
+                .map(|name| {
+                    let docs = tree.documents(&name)?;
+                    Ok((name, docs))
+                })
+                .collect();
+        }
+
+        let dir = self.root.join("collections");
+        let mut names = Vec::new();
+        if dir.exists() {
+            for entry in std::fs::read_dir(&dir)? {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    names.push(entry.file_name().to_string_lossy().into_owned());
+                }
+            }
+        }
+        names.sort();
+
+        let mut all = Vec::new();
+        for name in names {
+            let docs = Collection::open(&name, &self.root).list().await?;
+            all.push((name, docs));
+        }
+        Ok(all)
+    }
+
+    /// Overwrite an existing document's fields and body, as after editing its
+    /// file by hand
+    ///
+    /// The document goes through the same schema, limit, lint, UNIQUE and
+    /// REF checks as an UPDATE. Returns false, writing nothing, when the
+    /// document is unchanged.
+    pub async fn replace(&self, name: &str, doc: &Document) -> anyhow::Result<bool> {
+        let current = self.document(name, &doc.id).await?;
+        if current.fields == doc.fields && current.body == doc.body {
+            return Ok(false);
+        }
+
+        if let Some(schema) = self.schema.get(name) {
+            schema.validate(doc)?;
+        }
+        self.config.limits.check(doc)?;
+        self.lint.enforce(name, doc)?;
+        let docs = std::slice::from_ref(doc);
+        query::check_unique(self, name, docs).await?;
+        query::check_refs(self, name, docs).await?;
+
+        self.store.put(name, doc, &query::layout(self, name)).await?;
+        query::update_indexes(self, name, docs, &[])?;
+        self.git.commit(&format!("EDIT {}: {}", name, doc.id))?;
+        Ok(true)
+    }
+
+    async fn document(&self, collection: &str, id: &str) -> anyhow::Result<Document> {
+        validation::validate_collection_name(collection)?;
+        validation::validate_document_id(id)?;
+        self.store.get(collection, id).await?.ok_or_else(|| {
+            Error::DocumentNotFound { collection: collection.to_string(), id: id.to_string() }.into()
+        })
+    }
+
+    /// Run `UPDATE collection SET field = value WHERE @id = 'id'`
+    async fn update_field(&mut self, collection: &str, id: &str, field: &str, value: mdql::Literal) -> anyhow::Result<()> {
+        let update = mdql::Statement::Update(mdql::UpdateStmt {
+            collection: collection.to_string(),
+            set: vec![mdql::SetClause { column: field.to_string(), value: mdql::Expr::Literal(value) }],
+            where_clause: Some(mdql::Expr::BinaryOp {
+                left: Box::new(mdql::Expr::Column(mdql::Column::Special(mdql::SpecialField::Id))),
+                op: mdql::BinaryOp::Eq,
+                right: Box::new(mdql::Expr::Literal(mdql::Literal::String(id.to_string()))),
+            }),
+        });
+        self.execute(&update.to_string()).await?;
+        Ok(())
+    }
+
+    /// Write schema default values into documents that are missing those fields
+    ///
+    /// Returns the IDs of the documents that changed, in ID order. All changes
+    /// are committed together.
+    pub async fn apply_defaults(&self, name: &str) -> anyhow::Result<Vec<String>> {
+        validation::validate_collection_name(name)?;
+        let Some(schema) = self.schema.get(name) else {
+            anyhow::bail!("Collection '{}' has no schema", name);
+        };
+        let collection = Collection::open(name, &self.root).with_layout(&Layout::of(Some(schema)));
+        if !collection.exists().await {
+            anyhow::bail!("Collection '{}' does not exist", name);
+        }
+
+        let mut docs = collection.list().await?;
+        docs.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut changed = Vec::new();
+        for mut doc in docs {
+            if !schema.apply_defaults(&mut doc).is_empty() {
+                self.config.limits.check(&doc)?;
+                self.lint.enforce(name, &doc)?;
+                changed.push(doc);
+            }
+        }
+
+        let mut updated = Vec::new();
+        for doc in changed {
+            collection.update(&doc).await?;
+            updated.push(doc.id);
+        }
+
+        if !updated.is_empty() {
+            self.git.commit(&format!("APPLY DEFAULTS to {}: {} document(s)", name, updated.len()))?;
+        }
+
+        Ok(updated)
+    }
+
+    /// Pairs of documents in a collection that look like duplicates
+    ///
+    /// `threshold` is the minimum similarity, from 0 to 1; see [`dedupe`].
+    pub async fn find_duplicates(&self, name: &str, threshold: f64) -> anyhow::Result<Vec<dedupe::DuplicatePair>> {
+        validation::validate_collection_name(name)?;
+        if !(0.0..=1.0).contains(&threshold) {
+            anyhow::bail!("Similarity threshold must be between 0 and 1, got {}", threshold);
+        }
+        let collection = Collection::open(name, &self.root);
+        if !collection.exists().await {
+            anyhow::bail!("Collection '{}' does not exist", name);
+        }
+
+        Ok(dedupe::find_duplicates(name, &collection.list().await?, threshold))
+    }
+
+    /// Merge the document `remove` into `keep` and delete it, in one commit
+    ///
+    /// Returns the merged document.
+    pub async fn merge_duplicates(&self, name: &str, keep: &str, remove: &str) -> anyhow::Result<Document> {
+        validation::validate_collection_name(name)?;
+        validation::validate_document_id(keep)?;
+        validation::validate_document_id(remove)?;
+        if keep == remove {
+            anyhow::bail!("Cannot merge document '{}' into itself", keep);
+        }
+
+        let collection = Collection::open(name, &self.root).with_layout(&Layout::of(self.schema.get(name)));
+        let missing = |id: &str| anyhow::anyhow!("Document '{}' not found in collection '{}'", id, name);
+        let kept = collection.get(keep).await?.ok_or_else(|| missing(keep))?;
+        let removed = collection.get(remove).await?.ok_or_else(|| missing(remove))?;
+
+        let merged = dedupe::merge(&kept, &removed);
+        if let Some(schema) = self.schema.get(name) {
+            schema.validate(&merged)?;
+        }
+        self.config.limits.check(&merged)?;
+        self.lint.enforce(name, &merged)?;
+
+        collection.update(&merged).await?;
+        collection.delete(remove).await?;
+        self.git.commit(&format!("DEDUPE {}: merged {} into {}", name, remove, keep))?;
+
+        Ok(merged)
+    }
+
+    /// Copy the database into `dest`, applying the schemas' scrub rules when
+    /// `scrub` is set (see [`export`])
+    pub async fn export(&self, dest: &std::path::Path, scrub: bool) -> anyhow::Result<export::ExportSummary> {
+        export::export(self, dest, scrub).await
     }
 
-    /// Regenerate all views (async)
+    /// Write a collection as CSV, returning the number of documents (see
+    /// [`export::to_csv`])
+    pub async fn export_csv(&self, name: &str, output: impl std::io::Write) -> anyhow::Result<usize> {
+        export::to_csv(self, name, output).await
+    }
+
+    /// Add a document for each row of a CSV file, taking IDs from
+    /// `id_column`, in one commit; returns the new IDs (see [`import`])
+    pub async fn import_csv(&self, name: &str, input: impl std::io::Read, id_column: &str) -> anyhow::Result<Vec<String>> {
+        import::import_csv(self, name, input, id_column).await
+    }
+
+    /// Write a collection as a JSON array, or JSON Lines with `lines`,
+    /// returning the number of documents (see [`export::to_json`])
+    pub async fn export_json(&self, name: &str, output: impl std::io::Write, lines: bool) -> anyhow::Result<usize> {
+        export::to_json(self, name, output, lines).await
+    }
+
+    /// Add a document for each object of a JSON Lines file, in one commit;
+    /// returns the new IDs (see [`import`])
+    pub async fn import_jsonl(&self, name: &str, input: impl std::io::Read) -> anyhow::Result<Vec<String>> {
+        import::import_jsonl(self, name, input).await
+    }
+
+    /// Propose a schema for a collection from its documents' frontmatter,
+    /// marking fields present in at least `required_ratio` of them required
+    /// (see [`schema::infer`])
+    pub async fn infer_schema(&self, name: &str, required_ratio: f64) -> anyhow::Result<schema::infer::SchemaInference> {
+        validation::validate_collection_name(name)?;
+        let collection = Collection::open(name, &self.root);
+        if !collection.exists().await {
+            anyhow::bail!("Collection '{}' does not exist", name);
+        }
+        let docs = collection.list().await?;
+        Ok(schema::infer::infer(name, &docs, self.schema.get(name), required_ratio))
+    }
+
+    /// Save a collection's schema to `.mdby/schemas/`, replacing any it had,
+    /// and commit it
+    pub fn save_schema(&mut self, schema: Schema) -> anyhow::Result<()> {
+        if self.is_read_only() {
+            anyhow::bail!("Database is a read-only replica");
+        }
+        let name = schema.name.clone();
+        validation::validate_collection_name(&name)?;
+        self.schema.register(schema)?;
+        self.git.commit(&format!("SAVE SCHEMA {}", name))?;
+        Ok(())
+    }
+
+    /// Display names of a collection's fields in the configured `locale`, for
+    /// those whose schema gives one a label
+    pub fn field_labels(&self, collection: &str) -> BTreeMap<String, String> {
+        self.schema
+            .get(collection)
+            .map(|schema| schema.labels(self.config.locale.as_deref()))
+            .unwrap_or_default()
+    }
+
+    /// Add `count` generated documents to a collection, returning their IDs
+    /// (see [`seed`])
+    ///
+    /// With `from_schema`, values come from the schema alone rather than from
+    /// existing documents; `rng_seed` makes the output repeatable.
+    pub async fn seed(&self, name: &str, count: usize, from_schema: bool, rng_seed: Option<u64>) -> anyhow::Result<Vec<String>> {
+        validation::validate_collection_name(name)?;
+        seed::seed(self, name, count, from_schema, rng_seed).await
+    }
+
+    /// Regenerate the views that are out of date with their documents,
+    /// definitions or templates (async)
     pub async fn regenerate_views(&self) -> anyhow::Result<()> {
         views::regenerate_all(self).await
     }
 
-    /// Sync with remote (push/pull with conflict resolution)
-    pub async fn sync(&mut self) -> anyhow::Result<SyncResult> {
-        self.git.sync().await
+    /// Regenerate every view, whether or not it is out of date (async)
+    pub async fn rebuild_views(&self) -> anyhow::Result<()> {
+        views::rebuild_all(self).await
+    }
+
+    /// Render a view with values for its parameters (`CREATE VIEW by_tag(tag
+    /// STRING) AS ...`), returning its HTML and JSON without writing them
+    pub async fn render_view(&self, name: &str, params: &BTreeMap<String, String>) -> anyhow::Result<views::RenderedView> {
+        views::render_view(self, name, params).await
+    }
+
+    /// Templates in `.mdby/templates/`, with the views and templates using
+    /// them
+    pub fn templates(&self) -> anyhow::Result<Vec<views::TemplateInfo>> {
+        views::list_templates(self)
+    }
+
+    /// Add a template to `.mdby/templates/` and commit it; `replace` allows
+    /// overwriting one with the same name
+    pub fn add_template(&self, name: &str, content: &str, replace: bool) -> anyhow::Result<()> {
+        views::add_template(self, name, content, replace)
+    }
+
+    /// Remove a template no view or other template uses, and commit it
+    pub fn remove_template(&self, name: &str) -> anyhow::Result<()> {
+        views::remove_template(self, name)
+    }
+
+    /// Render a template with the results of a SELECT, returning the HTML a
+    /// view using it would have
+    pub async fn preview_template(&self, name: &str, query: &str) -> anyhow::Result<String> {
+        views::preview_template(self, name, query).await
+    }
+
+    /// Regenerate a view and send it to its `DELIVER TO` target
+    pub async fn deliver_view(&self, name: &str) -> anyhow::Result<()> {
+        views::delivery::deliver(self, name).await
+    }
+
+    /// Deliver the scheduled views that are due at `now` (seconds since the
+    /// epoch), returning each view attempted and its outcome
+    pub async fn deliver_due_views(&self, now: i64) -> anyhow::Result<Vec<(String, anyhow::Result<()>)>> {
+        views::delivery::deliver_due(self, now).await
+    }
+
+    /// Generate the HTML reference for this database, returning its path
+    pub async fn generate_docs(&self) -> anyhow::Result<PathBuf> {
+        views::generate_docs(self).await
+    }
+
+    /// Generate the built-in `@dashboard` view, returning the path of its
+    /// HTML page
+    pub async fn generate_dashboard(&self) -> anyhow::Result<PathBuf> {
+        views::generate_dashboard(self).await
+    }
+
+    /// Move uncommitted changes onto a new proposal branch and push it to `remote`
+    pub fn propose(&self, message: &str, remote: &str) -> anyhow::Result<git::Proposal> {
+        self.git.propose(message, remote)
+    }
+
+    /// Proposals (local or on `remote`) not yet merged into the current branch
+    pub fn proposals(&self, remote: &str) -> anyhow::Result<Vec<git::Proposal>> {
+        self.git.proposals(remote)
+    }
+
+    /// Merge a proposal with the document-aware merger
+    ///
+    /// Schemas, settings and lint rules are reloaded, since the proposal may
+    /// have changed them. Returns `None` if the proposal was already merged.
+    pub fn apply_proposal(&mut self, name: &str, remote: &str) -> anyhow::Result<Option<git::MergeOutcome>> {
+        let outcome = self.git.apply_proposal(name, remote)?;
+        if outcome.is_some() {
+            self.reload()?;
+        }
+        Ok(outcome)
+    }
+
+    /// Reload schemas, settings and lint rules after history changed under them
+    fn reload(&mut self) -> anyhow::Result<()> {
+        self.schema = schema::SchemaRegistry::load(&self.root)?;
+        self.config = config::Config::load(&self.root)?;
+        self.lint = lint::LintConfig::load(&self.root)?;
+        self.git.set_signing(self.config.signing.clone());
+        self.git.set_ssh_key(self.config.sync.ssh_key.clone());
+        Ok(())
+    }
+
+    /// Check commit signatures from HEAD back to `since` (or the first commit)
+    pub fn verify(&self, since: Option<&str>) -> anyhow::Result<Vec<git::CommitVerification>> {
+        self.git.verify(since)
+    }
+
+    /// Sync with a remote: fetch, rebase local commits onto new remote history, push
+    ///
+    /// Documents changed both locally and on the remote are combined with
+    /// `strategy`, or with `sync.strategy` from the config when it's `None`.
+    pub async fn sync(&mut self, remote: &str, strategy: Option<git::ConflictResolution>) -> anyhow::Result<SyncResult> {
+        let strategy = strategy.unwrap_or(self.config.sync.strategy);
+        let result = self.git.sync(remote, strategy).await?;
+        if result.pulled > 0 {
+            self.reload()?;
+        }
+        Ok(result)
     }
 }
 
@@ -105,6 +1080,8 @@ impl Database {
 pub enum QueryResult {
     /// Documents returned from a SELECT
     Documents(Vec<Document>),
+    /// The one value of a SELECT that only counts (`SELECT COUNT(*) FROM todos`)
+    Scalar(storage::document::Value),
     /// Number of affected documents
     Affected(usize),
     /// View created/updated
@@ -115,12 +1092,105 @@ pub enum QueryResult {
     Collections(Vec<String>),
     /// List of view names (from SHOW VIEWS)
     Views(Vec<String>),
+    /// MDQL that recreates a collection or view (from SHOW CREATE)
+    Definition(String),
+    /// Summary of a collection (from DESCRIBE)
+    Description(CollectionDescription),
+    /// How a SELECT would run (from EXPLAIN)
+    Plan(query::plan::QueryPlan),
+    /// Outcome of each statement of a multi-statement query
+    Batch(BatchResult),
 }
 
-/// Result of a sync operation
+/// Outcomes of the statements of a multi-statement query, in order
+#[derive(Debug)]
+pub struct BatchResult {
+    pub statements: Vec<StatementOutcome>,
+}
+
+/// One statement of a batch and its result
 #[derive(Debug)]
+pub struct StatementOutcome {
+    /// Line of the query the statement starts on, counting from 1 (for
+    /// [`Database::execute_batch`], its position in the list)
+    pub line: usize,
+    /// The statement, as MDQL
+    pub statement: String,
+    pub result: anyhow::Result<QueryResult>,
+}
+
+impl BatchResult {
+    /// Number of statements that ran without error
+    pub fn succeeded(&self) -> usize {
+        self.statements.iter().filter(|s| s.result.is_ok()).count()
+    }
+
+    /// Number of statements that failed
+    pub fn failed(&self) -> usize {
+        self.statements.len() - self.succeeded()
+    }
+
+    /// Documents affected by INSERT, UPDATE, DELETE and ALTER statements
+    pub fn affected(&self) -> usize {
+        self.statements
+            .iter()
+            .map(|s| match s.result {
+                Ok(QueryResult::Affected(n)) => n,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+/// One version of a document, as returned by [`Database::history`]
+#[derive(Debug)]
+pub struct DocumentVersion {
+    /// Commit that wrote this version
+    pub commit: git::CommitInfo,
+    /// The document as of that commit, or `None` if the commit deleted it
+    pub document: Option<Document>,
+}
+
+/// A collection's size, schema and metadata file (from DESCRIBE)
+#[derive(Debug)]
+pub struct CollectionDescription {
+    /// Collection name
+    pub name: String,
+    /// Number of documents
+    pub documents: usize,
+    /// Schema description, if any
+    pub description: Option<String>,
+    /// Contents of the collection's `_meta.md` or `README.md`
+    pub meta: Option<storage::collection::CollectionMeta>,
+    /// Schema fields in declaration order (empty without a schema)
+    pub fields: Vec<FieldDescription>,
+}
+
+/// One schema field in a [`CollectionDescription`]
+#[derive(Debug)]
+pub struct FieldDescription {
+    pub name: String,
+    /// MDQL type, e.g. `ARRAY<STRING>`
+    pub field_type: String,
+    /// MDQL constraints, e.g. `REQUIRED`, `DEFAULT false`
+    pub constraints: Vec<String>,
+    pub description: Option<String>,
+    /// Display name in the configured locale, if the schema gives one
+    pub label: Option<String>,
+}
+
+/// Result of a sync operation
+#[derive(Debug, Default)]
 pub struct SyncResult {
     pub pulled: usize,
     pub pushed: usize,
     pub conflicts_resolved: Vec<String>,
+    /// Pre-merge versions saved for each automatically resolved conflict
+    pub conflicts: Vec<git::ConflictRecord>,
+    /// Local commits replayed onto new remote history
+    pub rebased: Vec<git::RebasedCommit>,
+    /// The remote couldn't be reached; local commits stay queued
+    pub offline: bool,
+    /// Local commits waiting for the remote, when offline
+    pub queued: usize,
 }
@@ -0,0 +1,310 @@
+//! Link checking (`mdby validate --links`)
+//!
+//! Each document is checked for links that don't resolve:
+//! - Wikilinks in the body: `[[id]]` (same collection) or `[[collection/id]]`,
+//!   optionally followed by `#section` or `|label`
+//! - `REF(collection)` field values, including arrays of references
+//! - Relative markdown links and images, resolved against the collection
+//!   directory (or the database root when they start with `/`)
+//!
+//! External `http(s)` links need the network, so they are only requested
+//! when asked for. A link is broken if the request fails or returns a 4xx or
+//! 5xx status. Links inside code spans and code blocks are ignored.
+
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use regex::Regex;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::schema::Schema;
+use crate::storage::collection::Collection;
+use crate::storage::document::{Document, Value};
+use crate::validation::{validate_collection_name, validate_document_id};
+
+/// Timeout for each external link request
+const EXTERNAL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum number of external links requested at once
+const EXTERNAL_PARALLELISM: usize = 8;
+
+/// A link that doesn't resolve
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BrokenLink {
+    #[error("Wikilink [[{0}]] does not resolve to a document")]
+    Wikilink(String),
+    #[error("Field '{field}' references missing document {collection}/{id}")]
+    Ref {
+        field: String,
+        collection: String,
+        id: String,
+    },
+    #[error("Link target '{0}' does not exist")]
+    Asset(String),
+    #[error("External link {url} failed: {reason}")]
+    External { url: String, reason: String },
+}
+
+/// Broken links for one document
+#[derive(Debug, Clone)]
+pub struct LinkReport {
+    /// Collection the document belongs to
+    pub collection: String,
+    /// Document ID
+    pub id: String,
+    /// Broken links, in the order they were checked
+    pub broken: Vec<BrokenLink>,
+}
+
+/// Links found in a markdown body
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BodyLinks {
+    /// Wikilink targets (`id` or `collection/id`)
+    pub wikilinks: Vec<String>,
+    /// Relative link and image destinations
+    pub relative: Vec<String>,
+    /// `http(s)` link and image destinations
+    pub external: Vec<String>,
+}
+
+/// Extract the links from a markdown body
+pub fn extract_links(body: &str) -> BodyLinks {
+    static WIKILINK: OnceLock<Regex> = OnceLock::new();
+    static SCHEME: OnceLock<Regex> = OnceLock::new();
+    let wikilink = WIKILINK.get_or_init(|| Regex::new(r"\[\[([^\[\]|#]+)(?:#[^\[\]|]*)?(?:\|[^\[\]]*)?\]\]").unwrap());
+    let scheme = SCHEME.get_or_init(|| Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").unwrap());
+
+    let mut links = BodyLinks::default();
+    let mut text = String::new();
+    let mut in_code_block = false;
+
+    for event in Parser::new(body) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(TagEnd::CodeBlock) => in_code_block = false,
+            Event::Text(t) if !in_code_block => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak | Event::End(TagEnd::Paragraph) => text.push('\n'),
+            Event::Start(Tag::Link { dest_url, .. }) | Event::Start(Tag::Image { dest_url, .. }) => {
+                let dest = dest_url.trim();
+                if dest.is_empty() || dest.starts_with('#') {
+                    continue;
+                }
+                if dest.starts_with("http://") || dest.starts_with("https://") {
+                    links.external.push(dest.to_string());
+                } else if dest.starts_with("//") {
+                    links.external.push(format!("https:{}", dest));
+                } else if !scheme.is_match(dest) {
+                    links.relative.push(dest.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    links.wikilinks = wikilink
+        .captures_iter(&text)
+        .map(|c| c[1].trim().to_string())
+        .collect();
+
+    links
+}
+
+/// Check every document in a collection for broken links
+pub async fn check_collection(
+    root: &Path,
+    name: &str,
+    schema: Option<&Schema>,
+    external: bool,
+) -> anyhow::Result<Vec<LinkReport>> {
+    let collection = Collection::open(name, root);
+    let collection_dir = root.join("collections").join(name);
+
+    let mut docs = collection.list().await?;
+    docs.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let body_links: Vec<BodyLinks> = docs.iter().map(|doc| extract_links(&doc.body)).collect();
+
+    let external_results = if external {
+        let urls: BTreeSet<String> = body_links.iter().flat_map(|l| l.external.iter().cloned()).collect();
+        check_external(urls).await?
+    } else {
+        HashMap::new()
+    };
+
+    let mut documents = DocumentLookup::new(root);
+    let mut reports = Vec::new();
+
+    for (doc, links) in docs.iter().zip(&body_links) {
+        let mut broken = Vec::new();
+
+        for target in &links.wikilinks {
+            let (target_collection, id) = match target.split_once('/') {
+                Some((c, id)) => (c, id),
+                None => (name, target.as_str()),
+            };
+            if !documents.exists(target_collection, id).await? {
+                broken.push(BrokenLink::Wikilink(target.clone()));
+            }
+        }
+
+        if let Some(schema) = schema {
+            for (field, collection, id) in ref_values(schema, doc) {
+                if !documents.exists(&collection, &id).await? {
+                    broken.push(BrokenLink::Ref { field, collection, id });
+                }
+            }
+        }
+
+        for dest in &links.relative {
+            let path = dest.split(['#', '?']).next().unwrap_or_default().replace("%20", " ");
+            if path.is_empty() {
+                continue;
+            }
+            let resolved = match path.strip_prefix('/') {
+                Some(rooted) => root.join(rooted),
+                None => collection_dir.join(&path),
+            };
+            if !resolved.exists() {
+                broken.push(BrokenLink::Asset(dest.clone()));
+            }
+        }
+
+        for url in &links.external {
+            if let Some(Some(reason)) = external_results.get(url) {
+                broken.push(BrokenLink::External {
+                    url: url.clone(),
+                    reason: reason.clone(),
+                });
+            }
+        }
+
+        if !broken.is_empty() {
+            reports.push(LinkReport {
+                collection: name.to_string(),
+                id: doc.id.clone(),
+                broken,
+            });
+        }
+    }
+
+    Ok(reports)
+}
+
+/// (field, collection, id) for every `REF` value in a document
+pub(crate) fn ref_values(schema: &Schema, doc: &Document) -> Vec<(String, String, String)> {
+    let mut refs = Vec::new();
+
+    for (field, def) in &schema.fields {
+        let Some(target) = def.field_type.ref_target() else {
+            continue;
+        };
+
+        let ids: Vec<&str> = match doc.get(field) {
+            Some(Value::String(id)) => vec![id.as_str()],
+            Some(Value::Array(items)) => items.iter().filter_map(|v| v.as_str()).collect(),
+            _ => continue,
+        };
+        for id in ids {
+            refs.push((field.clone(), target.to_string(), id.to_string()));
+        }
+    }
+
+    refs
+}
+
+/// Cached document existence checks
+struct DocumentLookup<'a> {
+    root: &'a Path,
+    known: HashMap<(String, String), bool>,
+}
+
+impl<'a> DocumentLookup<'a> {
+    fn new(root: &'a Path) -> Self {
+        Self {
+            root,
+            known: HashMap::new(),
+        }
+    }
+
+    async fn exists(&mut self, collection: &str, id: &str) -> anyhow::Result<bool> {
+        if validate_collection_name(collection).is_err() || validate_document_id(id).is_err() {
+            return Ok(false);
+        }
+
+        let key = (collection.to_string(), id.to_string());
+        if let Some(exists) = self.known.get(&key) {
+            return Ok(*exists);
+        }
+
+        let exists = Collection::open(collection, self.root).get(id).await?.is_some();
+        self.known.insert(key, exists);
+        Ok(exists)
+    }
+}
+
+/// Request each URL, returning the failure reason for broken ones
+async fn check_external(urls: BTreeSet<String>) -> anyhow::Result<HashMap<String, Option<String>>> {
+    let agent = ureq::AgentBuilder::new().timeout(EXTERNAL_TIMEOUT).build();
+    let semaphore = Arc::new(Semaphore::new(EXTERNAL_PARALLELISM));
+    let mut tasks = JoinSet::new();
+
+    for url in urls {
+        let agent = agent.clone();
+        let permit = semaphore.clone().acquire_owned().await?;
+        tasks.spawn_blocking(move || {
+            let _permit = permit;
+            let result = request(&agent, &url);
+            (url, result)
+        });
+    }
+
+    let mut results = HashMap::new();
+    while let Some(joined) = tasks.join_next().await {
+        let (url, result) = joined?;
+        results.insert(url, result);
+    }
+    Ok(results)
+}
+
+/// HEAD the URL, falling back to GET for servers that don't allow HEAD
+fn request(agent: &ureq::Agent, url: &str) -> Option<String> {
+    let result = match agent.head(url).call() {
+        Err(ureq::Error::Status(405, _)) => agent.get(url).call(),
+        other => other,
+    };
+
+    match result {
+        Ok(_) => None,
+        Err(ureq::Error::Status(code, _)) => Some(format!("HTTP {}", code)),
+        Err(e) => Some(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_links() {
+        let body = "See [[task-2]] and [[notes/idea#intro|the idea]].\n\n\
+                    ![diagram](assets/diagram.png) [docs](https://example.com/docs)\n\
+                    [top](#top) [mail](mailto:a@b.c) [cdn](//cdn.example.com/x.js)\n\n\
+                    ```\n[[not-a-link]] [x](missing.png)\n```\n\
+                    `[[inline]]`\n";
+
+        let links = extract_links(body);
+        assert_eq!(links.wikilinks, vec!["task-2", "notes/idea"]);
+        assert_eq!(links.relative, vec!["assets/diagram.png"]);
+        assert_eq!(links.external, vec!["https://example.com/docs", "https://cdn.example.com/x.js"]);
+    }
+
+    #[test]
+    fn test_wikilink_split_across_text_events() {
+        // pulldown-cmark may emit brackets as separate text events
+        let links = extract_links("A [[b]] c [[d|e]] [not] [[f]]");
+        assert_eq!(links.wikilinks, vec!["b", "d", "f"]);
+    }
+}
@@ -0,0 +1,305 @@
+//! Document lint rules (`mdby lint`)
+//!
+//! Lint rules check document content rather than types: heading structure,
+//! title length, wording and tag taxonomy. They are defined per collection in
+//! `/.mdby/lint.yaml`:
+//!
+//! ```yaml
+//! collections:
+//!   posts:
+//!     enforce: true              # reject INSERT/UPDATE that break a rule
+//!     required_headings: ["## Summary", "## Details"]
+//!     max_title_length: 80
+//!     forbidden_words: [TODO, lorem]
+//!     allowed_tags: [rust, databases, git]
+//! ```
+//!
+//! Required headings must appear in the body in the listed order; a leading
+//! run of `#` fixes the level, otherwise any level matches. Forbidden words
+//! match whole words, case-insensitively, in the title and body.
+
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::storage::document::{Document, Value};
+
+/// Lint rules for every collection
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LintConfig {
+    /// Rules keyed by collection name
+    #[serde(default)]
+    pub collections: HashMap<String, CollectionRules>,
+}
+
+/// Lint rules for one collection
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollectionRules {
+    /// Reject INSERT and UPDATE statements that produce violations
+    pub enforce: bool,
+    /// Headings that must appear in the body, in order (e.g. `## Summary`)
+    pub required_headings: Vec<String>,
+    /// Maximum length of the title field, in characters
+    pub max_title_length: Option<usize>,
+    /// Field holding the title (default: `title`)
+    pub title_field: Option<String>,
+    /// Words that may not appear in the title or body
+    pub forbidden_words: Vec<String>,
+    /// Tag taxonomy; tags outside this list are violations
+    pub allowed_tags: Option<Vec<String>>,
+    /// Field holding the tags (default: `tags`)
+    pub tags_field: Option<String>,
+}
+
+/// A single rule violation
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LintViolation {
+    #[error("Missing heading: {0}")]
+    MissingHeading(String),
+    #[error("Title is {length} characters, over the maximum of {max}")]
+    TitleTooLong { length: usize, max: usize },
+    #[error("Forbidden word '{word}' in {location}")]
+    ForbiddenWord { word: String, location: String },
+    #[error("Tag '{0}' is not in the allowed tag list")]
+    UnknownTag(String),
+}
+
+/// Lint violations for one document
+#[derive(Debug, Clone)]
+pub struct LintReport {
+    /// Collection the document belongs to
+    pub collection: String,
+    /// Document ID
+    pub id: String,
+    /// All violations, in rule order
+    pub violations: Vec<LintViolation>,
+}
+
+impl LintConfig {
+    /// Path of the lint file for a database root
+    pub fn path(root: &Path) -> PathBuf {
+        root.join(".mdby").join("lint.yaml")
+    }
+
+    /// Load the lint rules, returning no rules if the file doesn't exist
+    pub fn load(root: &Path) -> anyhow::Result<Self> {
+        let path = Self::path(root);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        serde_yaml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid lint rules {:?}: {}", path, e))
+    }
+
+    /// Rules for a collection, if any
+    pub fn rules(&self, collection: &str) -> Option<&CollectionRules> {
+        self.collections.get(collection)
+    }
+
+    /// Check a document before it is written, if its collection enforces lint rules
+    pub fn enforce(&self, collection: &str, doc: &Document) -> crate::Result<()> {
+        let Some(rules) = self.rules(collection).filter(|r| r.enforce) else {
+            return Ok(());
+        };
+
+        let violations = rules.check(doc);
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        let messages: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+        Err(crate::Error::LintFailed {
+            collection: collection.to_string(),
+            id: doc.id.clone(),
+            message: messages.join("; "),
+        })
+    }
+}
+
+impl CollectionRules {
+    /// Check a document against every rule
+    pub fn check(&self, doc: &Document) -> Vec<LintViolation> {
+        let mut violations = Vec::new();
+        let title = doc
+            .get(self.title_field.as_deref().unwrap_or("title"))
+            .and_then(|v| v.as_str());
+
+        if !self.required_headings.is_empty() {
+            let headings = body_headings(&doc.body);
+            let mut remaining = headings.iter();
+            for required in &self.required_headings {
+                let (level, text) = parse_heading_spec(required);
+                let mut rest = remaining.clone();
+                let found = rest.any(|(l, t)| {
+                    t.eq_ignore_ascii_case(text) && level.map(|level| level == *l).unwrap_or(true)
+                });
+                if found {
+                    remaining = rest;
+                } else {
+                    violations.push(LintViolation::MissingHeading(required.clone()));
+                }
+            }
+        }
+
+        if let (Some(max), Some(title)) = (self.max_title_length, title) {
+            let length = title.chars().count();
+            if length > max {
+                violations.push(LintViolation::TitleTooLong { length, max });
+            }
+        }
+
+        for word in &self.forbidden_words {
+            let pattern = format!(r"(?i)\b{}\b", regex::escape(word));
+            let Ok(re) = Regex::new(&pattern) else { continue };
+            for (location, text) in [("title", title.unwrap_or("")), ("body", doc.body.as_str())] {
+                if re.is_match(text) {
+                    violations.push(LintViolation::ForbiddenWord {
+                        word: word.clone(),
+                        location: location.to_string(),
+                    });
+                }
+            }
+        }
+
+        if let Some(allowed) = &self.allowed_tags {
+            let tags = match doc.get(self.tags_field.as_deref().unwrap_or("tags")) {
+                Some(Value::Array(items)) => items.iter().filter_map(|v| v.as_str()).collect(),
+                Some(Value::String(s)) => vec![s.as_str()],
+                _ => Vec::new(),
+            };
+            for tag in tags {
+                if !allowed.iter().any(|a| a == tag) {
+                    violations.push(LintViolation::UnknownTag(tag.to_string()));
+                }
+            }
+        }
+
+        violations
+    }
+}
+
+/// Split `## Summary` into (Some(2), "Summary"); a bare `Summary` matches any level
+fn parse_heading_spec(spec: &str) -> (Option<usize>, &str) {
+    let spec = spec.trim();
+    let level = spec.chars().take_while(|c| *c == '#').count();
+    let text = spec[level..].trim();
+    ((level > 0).then_some(level), text)
+}
+
+/// Headings in a markdown body, as (level, text)
+fn body_headings(body: &str) -> Vec<(usize, String)> {
+    let mut headings = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+
+    for event in Parser::new(body) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                current = Some((heading_level(level), String::new()));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, buf)) = current.as_mut() {
+                    buf.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some((level, text)) = current.take() {
+                    headings.push((level, text.trim().to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    headings
+}
+
+fn heading_level(level: HeadingLevel) -> usize {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(title: &str, body: &str) -> Document {
+        let mut doc = Document::new("post-1");
+        doc.set("title", Value::String(title.into()));
+        doc.body = body.into();
+        doc
+    }
+
+    #[test]
+    fn test_required_headings_in_order() {
+        let rules = CollectionRules {
+            required_headings: vec!["## Summary".into(), "Details".into()],
+            ..Default::default()
+        };
+
+        let ok = doc("Post", "# Post\n\n## Summary\n\ntext\n\n### Details\n");
+        assert!(rules.check(&ok).is_empty());
+
+        let wrong_level = doc("Post", "# Summary\n\n## Details\n");
+        assert_eq!(
+            rules.check(&wrong_level),
+            vec![LintViolation::MissingHeading("## Summary".into())]
+        );
+
+        let in_code = doc("Post", "```\n## Summary\n```\n## Details\n");
+        assert_eq!(rules.check(&in_code).len(), 1);
+    }
+
+    #[test]
+    fn test_title_and_forbidden_words() {
+        let rules = CollectionRules {
+            max_title_length: Some(10),
+            forbidden_words: vec!["todo".into()],
+            ..Default::default()
+        };
+
+        assert!(rules.check(&doc("Short", "Done with todos")).is_empty());
+
+        let violations = rules.check(&doc("A much longer title", "TODO: write this"));
+        assert_eq!(violations.len(), 2);
+        assert!(matches!(violations[0], LintViolation::TitleTooLong { length: 19, max: 10 }));
+        assert!(matches!(&violations[1], LintViolation::ForbiddenWord { location, .. } if location == "body"));
+    }
+
+    #[test]
+    fn test_allowed_tags() {
+        let rules = CollectionRules {
+            allowed_tags: Some(vec!["rust".into(), "git".into()]),
+            ..Default::default()
+        };
+
+        let mut d = doc("Post", "");
+        d.set("tags", Value::Array(vec![Value::String("rust".into()), Value::String("java".into())]));
+        assert_eq!(rules.check(&d), vec![LintViolation::UnknownTag("java".into())]);
+    }
+
+    #[test]
+    fn test_enforce_only_when_enabled() {
+        let mut config: LintConfig =
+            serde_yaml::from_str("collections:\n  posts:\n    max_title_length: 3\n").unwrap();
+        let long = doc("Too long", "");
+        assert!(config.enforce("posts", &long).is_ok());
+
+        config.collections.get_mut("posts").unwrap().enforce = true;
+        assert!(matches!(
+            config.enforce("posts", &long),
+            Err(crate::Error::LintFailed { .. })
+        ));
+        assert!(config.enforce("other", &long).is_ok());
+    }
+}
@@ -1,23 +1,39 @@
 //! MDBY CLI - Markdown Database
 
+use clap::builder::TypedValueParser;
 use clap::{Parser, Subcommand, ValueEnum};
-use mdby::{Database, Document, QueryResult};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use mdby::config::Config;
+use mdby::git::{ConflictResolution, SignatureStatus};
+use mdby::starter::Starter;
+use mdby::query::plan::{Access, QueryPlan, SortStrategy};
+use mdby::{Collection, CollectionDescription, Database, Document, FieldDescription, QueryResult};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[command(name = "mdby")]
 #[command(about = "A markdown-based git-backed database", long_about = None)]
 #[command(version)]
 struct Cli {
-    /// Database directory (defaults to current directory)
+    /// Database directory, or a .zip or .tar.gz archive of one to read
+    /// (defaults to current directory)
     #[arg(short, long, default_value = ".")]
     database: PathBuf,
 
+    /// Run `query`, `exec` and `repl` on the server at this URL (`mdby
+    /// serve`) instead of a database directory
+    #[arg(long, global = true)]
+    url: Option<String>,
+
     /// Output format
     #[arg(short, long, default_value = "table", global = true)]
     format: OutputFormat,
 
+    /// Don't ask before DROP and DELETE statements remove documents
+    #[arg(short, long, global = true)]
+    yes: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -35,7 +51,12 @@ enum OutputFormat {
 #[derive(Subcommand)]
 enum Commands {
     /// Initialize a new MDBY database
-    Init,
+    Init {
+        /// Starter template with example collections, documents and views
+        #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(Starter::ALL.map(|s| s.name()))
+            .try_map(|s| s.parse::<Starter>()))]
+        template: Option<Starter>,
+    },
 
     /// Execute an MDQL query
     Query {
@@ -43,27 +64,444 @@ enum Commands {
         query: String,
     },
 
+    /// Run a file of MDQL statements as one commit, undoing them all if one
+    /// fails
+    Exec {
+        /// The script, statements separated by semicolons (`-` for standard input)
+        file: PathBuf,
+    },
+
     /// Start interactive REPL mode
     Repl,
 
-    /// Regenerate all views
-    Regenerate,
+    /// Regenerate the views that are out of date
+    Regenerate {
+        /// Regenerate every view, even those that are up to date
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Generate an HTML reference of collections, schemas, views and recent activity
+    Docs,
+
+    /// Generate the built-in @dashboard view: counts, recent changes, overdue documents and tags
+    Dashboard,
+
+    /// Export the graph of REF fields and wikilinks between documents, as
+    /// Graphviz DOT (or JSON with --format json)
+    Graph {
+        /// Only documents in this collection (repeatable)
+        #[arg(long)]
+        collection: Vec<String>,
+
+        /// Only documents with this tag (repeatable; any of them matches)
+        #[arg(long)]
+        tag: Vec<String>,
+    },
 
     /// Sync with remote git repository
     Sync {
         /// Remote name (default: origin)
         #[arg(default_value = "origin")]
         remote: String,
+
+        /// How to combine documents changed on both sides: ours, theirs,
+        /// merge_fields, concatenate_body or manual (default: sync.strategy
+        /// from the config, or merge_fields)
+        #[arg(long)]
+        strategy: Option<ConflictResolution>,
+    },
+
+    /// Move uncommitted changes onto a proposal branch and push it for review
+    Propose {
+        /// Description of the change
+        message: String,
+
+        /// Remote to push the proposal to
+        #[arg(long, default_value = "origin")]
+        remote: String,
+    },
+
+    /// Review and merge proposed changes
+    Proposals {
+        #[command(subcommand)]
+        action: ProposalAction,
+    },
+
+    /// Check commit signatures and flag unsigned or tampered commits
+    Verify {
+        /// Only check commits made after this revision
+        #[arg(long)]
+        since: Option<String>,
     },
 
     /// Show database status
     Status,
 
-    /// List collections
+    /// List collections with document counts, sizes and recent activity
     Collections,
 
     /// List views
     Views,
+
+    /// Work with one view
+    View {
+        #[command(subcommand)]
+        action: ViewAction,
+    },
+
+    /// Manage view templates in .mdby/templates/
+    Template {
+        #[command(subcommand)]
+        action: TemplateAction,
+    },
+
+    /// Validate documents against their collection schemas
+    Validate {
+        /// Collection to validate (default: all collections)
+        collection: Option<String>,
+
+        /// Also check wikilinks, REF fields and relative links
+        #[arg(long)]
+        links: bool,
+
+        /// With --links, also request external http(s) links
+        #[arg(long, requires = "links")]
+        external: bool,
+    },
+
+    /// Check documents against the lint rules in .mdby/lint.yaml
+    Lint {
+        /// Collection to lint (default: all collections)
+        collection: Option<String>,
+    },
+
+    /// Rebuild indexes from scratch, e.g. after files were edited outside MDBY
+    Reindex {
+        /// Collection to reindex (default: all collections)
+        collection: Option<String>,
+
+        /// Only compare the indexes with the documents, and fail if they disagree
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Flip a boolean field of one document (a missing field counts as false)
+    Toggle {
+        /// Collection name
+        collection: String,
+
+        /// Document ID
+        id: String,
+
+        /// Field to flip
+        field: String,
+    },
+
+    /// Add (+tag or tag) and remove (-tag) tags on one document
+    Tag {
+        /// Collection name
+        collection: String,
+
+        /// Document ID
+        id: String,
+
+        /// Changes, e.g. `+urgent -someday`; options go before them
+        #[arg(required = true, allow_hyphen_values = true)]
+        changes: Vec<String>,
+
+        /// Array field holding the tags
+        #[arg(long, default_value = "tags")]
+        field: String,
+    },
+
+    /// Fuzzy-find documents by ID or title across all collections
+    Find {
+        /// Text to look for; every word must match
+        text: String,
+
+        /// Maximum number of matches
+        #[arg(long, default_value = "10")]
+        limit: usize,
+
+        /// Open the best match in $EDITOR
+        #[arg(long)]
+        edit: bool,
+    },
+
+    /// Edit a document in $VISUAL or $EDITOR and commit the result
+    Edit {
+        /// Document as `collection/id`
+        target: String,
+    },
+
+    /// Write schema default values into documents missing those fields
+    ApplyDefaults {
+        /// Collection to update
+        collection: String,
+    },
+
+    /// Work with collection schemas
+    Schema {
+        #[command(subcommand)]
+        action: SchemaAction,
+    },
+
+    /// Add generated documents to a collection, e.g. for demos and performance tests
+    Seed {
+        /// Collection to add documents to
+        collection: String,
+
+        /// Number of documents to generate
+        #[arg(long, default_value_t = 10)]
+        count: usize,
+
+        /// Generate every value from the schema instead of reusing values of
+        /// existing documents
+        #[arg(long)]
+        from_schema: bool,
+
+        /// Random seed, for repeatable output
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+
+    /// Find documents with identical or near-identical content
+    Dedupe {
+        /// Collection to check (default: all collections)
+        collection: Option<String>,
+
+        /// Minimum similarity, from 0 to 1, for near-duplicates
+        #[arg(long, default_value_t = mdby::dedupe::DEFAULT_THRESHOLD)]
+        threshold: f64,
+
+        /// Go through each pair and choose which document to keep
+        #[arg(long)]
+        merge: bool,
+    },
+
+    /// Show documents added, removed or changed between two SELECT results:
+    /// two queries, or one query at two revisions
+    DiffQuery {
+        /// Query giving the earlier result set
+        query: String,
+
+        /// Query giving the later result set (default: the same query)
+        other: Option<String>,
+
+        /// Revision to run the first query at, e.g. a commit, tag or `main@{monday}`
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Revision to run the second query at (default: the working tree)
+        #[arg(long)]
+        to: Option<String>,
+    },
+
+    /// Show every committed version of a document, newest first
+    History {
+        /// Collection name
+        collection: String,
+
+        /// Document ID
+        id: String,
+    },
+
+    /// Copy the database into a new directory, e.g. to share it in a bug
+    /// report, or write one collection in another format
+    #[command(args_conflicts_with_subcommands = true)]
+    Export {
+        #[command(subcommand)]
+        format: Option<ExportFormat>,
+
+        /// Directory to create (must be empty or not exist)
+        dest: Option<PathBuf>,
+
+        /// Anonymize fields and bodies using the schemas' scrub rules
+        #[arg(long)]
+        scrub: bool,
+    },
+
+    /// Add documents to a collection from another format
+    Import {
+        #[command(subcommand)]
+        format: ImportFormat,
+    },
+
+    /// Summarize the query log: slowest statements and most-read collections
+    Slowlog {
+        /// Number of statements and collections to show
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+
+    /// Deliver a view to its DELIVER TO target, or run scheduled deliveries
+    Deliver {
+        /// View to deliver now
+        view: Option<String>,
+
+        /// Keep running and deliver each view on its SCHEDULE
+        #[arg(long, conflicts_with = "view")]
+        daemon: bool,
+    },
+
+    /// Serve the database over HTTP (POST /query, /collections/{name}/{id}, GET /openapi.json, ...)
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:7300")]
+        addr: std::net::SocketAddr,
+
+        /// Only accept SELECT, SHOW and DESCRIBE statements
+        #[arg(long)]
+        read_only: bool,
+
+        /// Let pages on this origin call the API from a browser (`*` for
+        /// any); adds to `serve.cors_origins` in .mdby/config.yaml
+        #[arg(long = "cors-origin", value_name = "ORIGIN")]
+        cors_origins: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExportFormat {
+    /// Write a collection as CSV: id, fields, then body
+    Csv {
+        /// Collection name
+        collection: String,
+
+        /// File to write (defaults to stdout)
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Write a collection as JSON: id, fields and _body per document
+    Json {
+        /// Collection name
+        collection: String,
+
+        /// File to write (defaults to stdout)
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+
+        /// One document per line (JSON Lines) instead of an array
+        #[arg(long)]
+        lines: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ImportFormat {
+    /// Add a document per CSV row; the header names the fields, `body` is the body
+    Csv {
+        /// CSV file (`-` for stdin)
+        file: PathBuf,
+
+        /// Collection to import into (created if missing)
+        #[arg(long)]
+        collection: String,
+
+        /// Column holding document IDs; without it, the schema's id_strategy generates them
+        #[arg(long, default_value = "id")]
+        id_column: String,
+    },
+
+    /// Add a document per line of a JSON Lines file, as `export json --lines` writes
+    Jsonl {
+        /// Collection to import into (created if missing)
+        collection: String,
+
+        /// JSON Lines file (`-` for stdin)
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum SchemaAction {
+    /// Propose a schema from a collection's frontmatter and write it after confirmation
+    Infer {
+        /// Collection to scan
+        collection: String,
+
+        /// Share of documents, from 0 to 1, a field must appear in to be required
+        #[arg(long, default_value_t = mdby::schema::infer::DEFAULT_REQUIRED_RATIO)]
+        required_ratio: f64,
+    },
+}
+
+#[derive(Subcommand)]
+enum ViewAction {
+    /// Render a view with values for its parameters; prints the HTML (JSON with --format json)
+    Render {
+        /// View name
+        name: String,
+
+        /// A parameter value as name=value (repeatable)
+        #[arg(long = "param", short)]
+        params: Vec<String>,
+
+        /// Directory to write the view's files to, instead of printing
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TemplateAction {
+    /// Copy a template file into .mdby/templates/ and commit it
+    Add {
+        /// Template file
+        file: PathBuf,
+
+        /// Name to give it, e.g. `partials/header.html` (default: the file name)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Replace a template with the same name
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// List templates with the views and templates using them
+    List,
+
+    /// Remove a template that no view or other template uses
+    Remove {
+        /// Template name
+        name: String,
+    },
+
+    /// Render a template with a query's results and print the HTML
+    Preview {
+        /// Template name
+        name: String,
+
+        /// SELECT query giving the documents
+        query: String,
+
+        /// File to write the HTML to, instead of printing it
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProposalAction {
+    /// List proposals not yet merged into the current branch
+    List {
+        /// Remote to fetch proposals from
+        #[arg(long, default_value = "origin")]
+        remote: String,
+    },
+
+    /// Merge a proposal into the current branch
+    Apply {
+        /// Proposal name, as shown by `mdby proposals list`
+        name: String,
+
+        /// Remote to fetch the proposal from
+        #[arg(long, default_value = "origin")]
+        remote: String,
+    },
 }
 
 #[tokio::main]
@@ -74,16 +512,104 @@ async fn main() -> anyhow::Result<()> {
     }
 
     let cli = Cli::parse();
+    let url = cli.url.as_deref();
+    let runs_on_servers = matches!(cli.command, Commands::Query { .. } | Commands::Exec { .. } | Commands::Repl);
 
     let result = match cli.command {
-        Commands::Init => init_database(&cli.database).await,
-        Commands::Query { query } => execute_query(&cli.database, &query, cli.format).await,
-        Commands::Repl => run_repl(&cli.database).await,
-        Commands::Regenerate => regenerate_views(&cli.database).await,
-        Commands::Sync { remote } => sync_database(&cli.database, &remote).await,
+        _ if url.is_some() && !runs_on_servers => {
+            Err(anyhow::anyhow!("Only query, exec and repl run on a server; leave out --url to use a database directory"))
+        }
+        Commands::Init { template } => init_database(&cli.database, template).await,
+        Commands::Query { query } => execute_query(&cli.database, url, &query, cli.format, cli.yes).await,
+        Commands::Exec { file } => execute_script(&cli.database, url, &file, cli.format, cli.yes).await,
+        Commands::Repl => run_repl(&cli.database, url, cli.yes).await,
+        Commands::Regenerate { force } => regenerate_views(&cli.database, force).await,
+        Commands::Docs => generate_docs(&cli.database).await,
+        Commands::Dashboard => generate_dashboard(&cli.database).await,
+        Commands::Graph { collection, tag } => export_graph(&cli.database, collection, tag, cli.format).await,
+        Commands::Sync { remote, strategy } => sync_database(&cli.database, &remote, strategy).await,
+        Commands::Propose { message, remote } => propose_changes(&cli.database, &message, &remote, cli.format).await,
+        Commands::Proposals { action } => match action {
+            ProposalAction::List { remote } => list_proposals(&cli.database, &remote, cli.format).await,
+            ProposalAction::Apply { name, remote } => apply_proposal(&cli.database, &name, &remote, cli.format).await,
+        },
+        Commands::Verify { since } => verify_signatures(&cli.database, since.as_deref(), cli.format).await,
         Commands::Status => show_status(&cli.database).await,
         Commands::Collections => list_collections(&cli.database, cli.format).await,
         Commands::Views => list_views(&cli.database, cli.format).await,
+        Commands::View { action } => match action {
+            ViewAction::Render { name, params, output } => {
+                render_view(&cli.database, &name, &params, output.as_deref(), cli.format).await
+            }
+        },
+        Commands::Template { action } => match action {
+            TemplateAction::Add { file, name, force } => add_template(&cli.database, &file, name.as_deref(), force).await,
+            TemplateAction::List => list_templates(&cli.database, cli.format).await,
+            TemplateAction::Remove { name } => remove_template(&cli.database, &name).await,
+            TemplateAction::Preview { name, query, output } => {
+                preview_template(&cli.database, &name, &query, output.as_deref()).await
+            }
+        },
+        Commands::Validate { collection, links, external } => {
+            let links = links.then_some(external);
+            validate_documents(&cli.database, collection.as_deref(), links, cli.format).await
+        }
+        Commands::Lint { collection } => {
+            lint_documents(&cli.database, collection.as_deref(), cli.format).await
+        }
+        Commands::Reindex { collection, check } => {
+            reindex_collections(&cli.database, collection.as_deref(), check, cli.format).await
+        }
+        Commands::Toggle { collection, id, field } => {
+            toggle_field(&cli.database, &collection, &id, &field, cli.format).await
+        }
+        Commands::Tag { collection, id, changes, field } => {
+            tag_document(&cli.database, &collection, &id, &field, &changes, cli.format).await
+        }
+        Commands::Find { text, limit, edit } => find_documents(&cli.database, &text, limit, edit, cli.format).await,
+        Commands::Edit { target } => match target.split_once('/') {
+            Some((collection, id)) => edit_document(&cli.database, collection, id).await,
+            None => Err(anyhow::anyhow!("Give the document as collection/id, got '{}'", target)),
+        },
+        Commands::ApplyDefaults { collection } => {
+            apply_defaults(&cli.database, &collection, cli.format).await
+        }
+        Commands::Schema { action } => match action {
+            SchemaAction::Infer { collection, required_ratio } => {
+                infer_schema(&cli.database, &collection, required_ratio, cli.yes, cli.format).await
+            }
+        },
+        Commands::Seed { collection, count, from_schema, seed } => {
+            seed_collection(&cli.database, &collection, count, from_schema, seed).await
+        }
+        Commands::Dedupe { collection, threshold, merge } => {
+            dedupe_documents(&cli.database, collection.as_deref(), threshold, merge, cli.format).await
+        }
+        Commands::DiffQuery { query, other, from, to } => {
+            diff_query(&cli.database, &query, other.as_deref(), from.as_deref(), to.as_deref(), cli.format).await
+        }
+        Commands::History { collection, id } => show_history(&cli.database, &collection, &id, cli.format).await,
+        Commands::Export { format, dest, scrub } => match (format, dest) {
+            (Some(ExportFormat::Csv { collection, output }), _) => {
+                export_csv(&cli.database, &collection, output.as_deref()).await
+            }
+            (Some(ExportFormat::Json { collection, output, lines }), _) => {
+                export_json(&cli.database, &collection, output.as_deref(), lines).await
+            }
+            (None, Some(dest)) => export_database(&cli.database, &dest, scrub).await,
+            (None, None) => Err(anyhow::anyhow!("Give a directory to export to")),
+        },
+        Commands::Import { format } => match format {
+            ImportFormat::Csv { file, collection, id_column } => {
+                import_csv(&cli.database, &file, &collection, &id_column, cli.format).await
+            }
+            ImportFormat::Jsonl { collection, file } => import_jsonl(&cli.database, &collection, &file, cli.format).await,
+        },
+        Commands::Slowlog { limit } => show_slowlog(&cli.database, limit, cli.format),
+        Commands::Deliver { view, daemon } => deliver_views(&cli.database, view.as_deref(), daemon).await,
+        Commands::Serve { addr, read_only, cors_origins } => {
+            serve_database(&cli.database, addr, read_only, cors_origins).await
+        }
     };
 
     if let Err(e) = result {
@@ -91,10 +617,8 @@ async fn main() -> anyhow::Result<()> {
         eprintln!("Error: {}", e);
 
         // Check if we have an MDBY error with a suggestion
-        if let Some(mdby_err) = e.downcast_ref::<mdby::Error>() {
-            if let Some(suggestion) = mdby_err.suggestion() {
-                eprintln!("Hint: {}", suggestion);
-            }
+        if let Some(suggestion) = suggestion(&e) {
+            eprintln!("Hint: {}", suggestion);
         }
 
         std::process::exit(1);
@@ -103,11 +627,77 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn init_database(path: &PathBuf) -> anyhow::Result<()> {
+/// How to fix an error, if it is one of MDBY's (or a server's, with `--url`)
+fn suggestion(e: &anyhow::Error) -> Option<&str> {
+    match (e.downcast_ref::<mdby::Error>(), e.downcast_ref::<mdby_client::Error>()) {
+        (Some(mdby_err), _) => mdby_err.suggestion(),
+        (None, Some(client_err)) => client_err.suggestion(),
+        (None, None) => None,
+    }
+}
+
+/// Where statements run: a database directory, or a server (`--url`)
+enum Target {
+    Local(Box<Database>),
+    Remote(mdby::remote::Remote),
+}
+
+impl Target {
+    async fn open(path: &PathBuf, url: Option<&str>) -> anyhow::Result<Self> {
+        Ok(match url {
+            Some(url) => Target::Remote(mdby::remote::Remote::new(url)),
+            None => Target::Local(Box::new(Database::open(path).await?)),
+        })
+    }
+
+    async fn execute(&mut self, query: &str) -> anyhow::Result<QueryResult> {
+        match self {
+            Target::Local(db) => db.execute(query).await,
+            Target::Remote(remote) => remote.execute(query).await,
+        }
+    }
+
+    async fn execute_script(&mut self, script: &str) -> anyhow::Result<mdby::BatchResult> {
+        match self {
+            Target::Local(db) => db.execute_script(script).await,
+            Target::Remote(remote) => remote.execute_script(script).await,
+        }
+    }
+
+    /// Ask before statements that remove documents, as [`confirm_removals`]
+    ///
+    /// A server's files can't be listed, so statements that would list them
+    /// ask without.
+    async fn confirm(&self, query: &str, yes: bool) -> anyhow::Result<bool> {
+        match self {
+            Target::Local(db) => confirm_removals(db, query, yes).await,
+            Target::Remote(remote) => confirm_remote_removals(remote.url(), query, yes),
+        }
+    }
+
+    /// Column labels of a query's result, as [`column_labels`]; a server's
+    /// results keep their field names
+    fn labels(&self, query: &str) -> BTreeMap<String, String> {
+        match self {
+            Target::Local(db) => column_labels(db, query),
+            Target::Remote(_) => BTreeMap::new(),
+        }
+    }
+
+    /// Reload the names the REPL completes
+    async fn refresh(&self, helper: &mut mdby::repl::ReplHelper) {
+        match self {
+            Target::Local(db) => helper.refresh(db).await,
+            Target::Remote(remote) => helper.set_names(remote.names().await.unwrap_or_default()),
+        }
+    }
+}
+
+async fn init_database(path: &PathBuf, template: Option<Starter>) -> anyhow::Result<()> {
     println!("Initializing MDBY database at {:?}...", path);
 
     // Create the database (this will init git if needed)
-    let _db = Database::open(path).await?;
+    let mut db = Database::open(path).await?;
 
     // Create standard directories
     tokio::fs::create_dir_all(path.join("collections")).await?;
@@ -116,108 +706,428 @@ async fn init_database(path: &PathBuf) -> anyhow::Result<()> {
     tokio::fs::create_dir_all(path.join(".mdby/views")).await?;
     tokio::fs::create_dir_all(path.join(".mdby/templates")).await?;
 
+    // Starter config, named after the database directory
+    if !Config::path(path).exists() {
+        let name = path
+            .canonicalize()
+            .ok()
+            .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()));
+        let config = Config { name, ..Default::default() };
+        config.save(path)?;
+        db.config = config;
+    }
+
+    if let Some(starter) = template {
+        println!("Applying '{}' template...", starter);
+        mdby::starter::apply(&mut db, starter).await?;
+    }
+
+    if db.git.has_changes()? {
+        db.git.commit("Add database config")?;
+    }
+
     println!("Database initialized successfully!");
     println!();
     println!("Directory structure:");
-    println!("  collections/     - Your data collections");
-    println!("  views/           - Generated view outputs");
-    println!("  .mdby/schemas/   - Collection schemas");
-    println!("  .mdby/views/     - View definitions");
-    println!("  .mdby/templates/ - HTML templates for views");
+    println!("  collections/       - Your data collections");
+    println!("  views/             - Generated view outputs");
+    println!("  .mdby/config.yaml  - Database settings");
+    println!("  .mdby/schemas/     - Collection schemas");
+    println!("  .mdby/views/       - View definitions");
+    println!("  .mdby/templates/   - HTML templates for views");
     println!();
     println!("Get started:");
-    println!("  mdby query \"CREATE COLLECTION todos (title STRING REQUIRED, done BOOL DEFAULT false)\"");
-    println!("  mdby query \"INSERT INTO todos (id, title) VALUES ('task-1', 'Hello MDBY!')\"");
-    println!("  mdby query \"SELECT * FROM todos\"");
+    match template {
+        Some(starter) => {
+            println!("  mdby query \"SHOW COLLECTIONS\"");
+            println!("  mdby query \"{}\"", starter.example_query());
+        }
+        None => {
+            println!("  mdby query \"CREATE COLLECTION todos (title STRING REQUIRED, done BOOL DEFAULT false)\"");
+            println!("  mdby query \"INSERT INTO todos (id, title) VALUES ('task-1', 'Hello MDBY!')\"");
+            println!("  mdby query \"SELECT * FROM todos\"");
+        }
+    }
 
     Ok(())
 }
 
-async fn execute_query(path: &PathBuf, query: &str, format: OutputFormat) -> anyhow::Result<()> {
-    let mut db = Database::open(path).await?;
-    let result = db.execute(query).await?;
+async fn execute_query(path: &PathBuf, url: Option<&str>, query: &str, format: OutputFormat, yes: bool) -> anyhow::Result<()> {
+    let mut target = Target::open(path, url).await?;
+    if !target.confirm(query, yes).await? {
+        anyhow::bail!("Cancelled; nothing was changed");
+    }
+    let labels = target.labels(query);
+    let result = target.execute(query).await?;
+
+    // A script exits non-zero if any of its statements failed
+    let failed = match &result {
+        QueryResult::Batch(batch) => (batch.failed(), batch.statements.len()),
+        _ => (0, 1),
+    };
+    print_result(&mut std::io::stdout(), result, format, &labels)?;
+    if failed.0 > 0 {
+        anyhow::bail!("{} of {} statements failed", failed.0, failed.1);
+    }
+    Ok(())
+}
+
+async fn execute_script(path: &PathBuf, url: Option<&str>, file: &Path, format: OutputFormat, yes: bool) -> anyhow::Result<()> {
+    let script = if file == Path::new("-") {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        std::fs::read_to_string(file).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", file.display(), e))?
+    };
+    let mut target = Target::open(path, url).await?;
+    if !target.confirm(&script, yes).await? {
+        anyhow::bail!("Cancelled; nothing was changed");
+    }
 
+    let batch = target.execute_script(&script).await?;
+    let failed = batch.statements.iter().find(|outcome| outcome.result.is_err()).map(|outcome| outcome.line);
+    print_batch(&mut std::io::stdout(), batch, format)?;
+    if let Some(line) = failed {
+        anyhow::bail!("The statement on line {} failed; none of the script's changes were kept", line);
+    }
+    Ok(())
+}
+
+/// Show what DROP COLLECTION, DROP VIEW and DELETE statements would remove
+/// and ask before running them; false if the user declines
+///
+/// A DELETE with a WHERE clause, or one removing no more than
+/// `confirm_delete_above` documents, runs without asking. Without a terminal
+/// to ask on, removals need `--yes`, which skips the questions.
+async fn confirm_removals(db: &Database, query: &str, yes: bool) -> anyhow::Result<bool> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    if yes {
+        return Ok(true);
+    }
+
+    // A query that doesn't parse fails in execute() with the parse error
+    let Ok(statements) = mdql::parse_multi(query) else {
+        return Ok(true);
+    };
+
+    for statement in &statements {
+        let ask = match statement {
+            mdql::Statement::DropCollection(_) | mdql::Statement::DropView(_) => true,
+            mdql::Statement::Delete(delete) => delete.where_clause.is_none(),
+            _ => false,
+        };
+        if !ask {
+            continue;
+        }
+        let Ok(paths) = db.removals(statement).await else {
+            // Let the statement itself report the problem
+            continue;
+        };
+        let documents = paths.iter().filter(|p| p.extension().is_some_and(|e| e == "md")).count();
+        if matches!(statement, mdql::Statement::Delete(_)) && documents <= db.config.confirm_delete_above {
+            continue;
+        }
+
+        let stdin = std::io::stdin();
+        if !stdin.is_terminal() {
+            anyhow::bail!("'{}' would remove {} file(s); pass --yes to run it without a terminal", statement, paths.len());
+        }
+        println!("{} will remove:", statement);
+        for path in &paths {
+            println!("  {}", path.display());
+        }
+        print!("Remove {} file(s)? [y/N] ", paths.len());
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        stdin.lock().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Ask before DROP COLLECTION, DROP VIEW and DELETE without WHERE run on the
+/// server at `url`; false if the user declines
+fn confirm_remote_removals(url: &str, query: &str, yes: bool) -> anyhow::Result<bool> {
+    use std::io::{BufRead, IsTerminal};
+
+    if yes {
+        return Ok(true);
+    }
+    let Ok(statements) = mdql::parse_multi(query) else {
+        return Ok(true);
+    };
+    for statement in &statements {
+        let ask = match statement {
+            mdql::Statement::DropCollection(_) | mdql::Statement::DropView(_) => true,
+            mdql::Statement::Delete(delete) => delete.where_clause.is_none(),
+            _ => false,
+        };
+        if !ask {
+            continue;
+        }
+        let stdin = std::io::stdin();
+        if !stdin.is_terminal() {
+            anyhow::bail!("'{}' would remove files on {}; pass --yes to run it without a terminal", statement, url);
+        }
+        print!("'{}' will remove files on {}. Run it? [y/N] ", statement, url);
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        stdin.lock().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Display names of the fields a single SELECT returns, for table headers:
+/// the labels of its collection's schema in the configured locale
+fn column_labels(db: &Database, query: &str) -> BTreeMap<String, String> {
+    match mdql::parse(query) {
+        Ok(mdql::Statement::Select(select)) if select.joins.is_empty() => db.field_labels(&select.from),
+        _ => BTreeMap::new(),
+    }
+}
+
+fn print_result(out: &mut dyn Write, result: QueryResult, format: OutputFormat, labels: &BTreeMap<String, String>) -> std::io::Result<()> {
     match result {
         QueryResult::Documents(docs) => {
-            print_documents(&docs, format);
+            print_documents(out, &docs, format, labels)?;
         }
+        QueryResult::Scalar(value) => match format {
+            OutputFormat::Json => writeln!(out, "{}", serde_json::json!(value))?,
+            _ => writeln!(out, "{}", format_value(&value))?,
+        },
         QueryResult::Affected(count) => {
             match format {
                 OutputFormat::Json => {
-                    println!("{}", serde_json::json!({"affected": count}));
+                    writeln!(out, "{}", serde_json::json!({"affected": count}))?;
                 }
                 _ => {
-                    println!("{} document(s) affected.", count);
+                    writeln!(out, "{} document(s) affected.", count)?;
                 }
             }
         }
         QueryResult::CollectionCreated(name) => {
             match format {
                 OutputFormat::Json => {
-                    println!("{}", serde_json::json!({"created": "collection", "name": name}));
+                    writeln!(out, "{}", serde_json::json!({"created": "collection", "name": name}))?;
                 }
                 _ => {
-                    println!("Collection '{}' created.", name);
+                    writeln!(out, "Collection '{}' created.", name)?;
                 }
             }
         }
         QueryResult::ViewCreated(name) => {
             match format {
                 OutputFormat::Json => {
-                    println!("{}", serde_json::json!({"created": "view", "name": name}));
+                    writeln!(out, "{}", serde_json::json!({"created": "view", "name": name}))?;
                 }
                 _ => {
-                    println!("View '{}' created.", name);
+                    writeln!(out, "View '{}' created.", name)?;
                 }
             }
         }
         QueryResult::Collections(names) => {
-            print_list("Collections", &names, format);
+            print_list(out, "Collections", &names, format)?;
         }
         QueryResult::Views(names) => {
-            print_list("Views", &names, format);
+            print_list(out, "Views", &names, format)?;
+        }
+        QueryResult::Definition(statement) => {
+            match format {
+                OutputFormat::Json => {
+                    writeln!(out, "{}", serde_json::json!({"statement": statement}))?;
+                }
+                _ => {
+                    writeln!(out, "{};", statement)?;
+                }
+            }
         }
+        QueryResult::Description(description) => {
+            print_description(out, &description, format)?;
+        }
+        QueryResult::Plan(plan) => print_plan(out, &plan, format)?,
+        QueryResult::Batch(batch) => print_batch(out, batch, format)?,
+    }
+    Ok(())
+}
+
+/// Each statement's result, then how many succeeded
+fn print_batch(out: &mut dyn Write, batch: mdby::BatchResult, format: OutputFormat) -> std::io::Result<()> {
+    if let OutputFormat::Json = format {
+        let json = mdby::server::result_json(&QueryResult::Batch(batch));
+        writeln!(out, "{}", serde_json::to_string_pretty(&json).unwrap_or_default())?;
+        return Ok(());
     }
 
+    let (total, succeeded, failed, affected) =
+        (batch.statements.len(), batch.succeeded(), batch.failed(), batch.affected());
+    for outcome in batch.statements {
+        if let OutputFormat::Table = format {
+            writeln!(out, "-- line {}: {}", outcome.line, outcome.statement)?;
+        }
+        match outcome.result {
+            Ok(result) => print_result(out, result, format, &BTreeMap::new())?,
+            Err(e) => eprintln!("Error on line {}: {}", outcome.line, e),
+        }
+        if let OutputFormat::Table = format {
+            writeln!(out)?;
+        }
+    }
+    if let OutputFormat::Table = format {
+        writeln!(
+            out,
+            "{} statements: {} succeeded, {} failed, {} document(s) affected.",
+            total, succeeded, failed, affected
+        )?;
+    }
     Ok(())
 }
 
-fn print_list(label: &str, items: &[String], format: OutputFormat) {
+fn print_description(out: &mut dyn Write, description: &CollectionDescription, format: OutputFormat) -> std::io::Result<()> {
     match format {
         OutputFormat::Json => {
-            println!("{}", serde_json::to_string_pretty(&items).unwrap_or_default());
+            let json = mdby::server::description_json(description);
+            writeln!(out, "{}", serde_json::to_string_pretty(&json).unwrap_or_default())?;
         }
         OutputFormat::Table => {
-            if items.is_empty() {
-                println!("No {} found.", label.to_lowercase());
+            writeln!(out, "Collection: {}", description.name)?;
+            writeln!(out, "Documents:  {}", description.documents)?;
+            if let Some(text) = &description.description {
+                writeln!(out, "About:      {}", text)?;
+            }
+            if let Some(meta) = &description.meta {
+                writeln!(out, "\n{}:", meta.file)?;
+                for line in meta.body.trim().lines() {
+                    writeln!(out, "  {}", line)?;
+                }
+            }
+            if description.fields.is_empty() {
+                writeln!(out, "\nNo schema.")?;
             } else {
-                println!("{}:", label);
-                for name in items {
-                    println!("  {}", name);
+                writeln!(out, "\nFields:")?;
+                let name = |field: &FieldDescription| match &field.label {
+                    Some(label) => format!("{} ({})", field.name, label),
+                    None => field.name.clone(),
+                };
+                let name_width = description.fields.iter().map(|f| name(f).chars().count()).max().unwrap_or(0);
+                let type_width = description.fields.iter().map(|f| f.field_type.len()).max().unwrap_or(0);
+                for field in &description.fields {
+                    let line = format!(
+                        "  {:name_width$}  {:type_width$}  {}",
+                        name(field),
+                        field.field_type,
+                        field.constraints.join(" "),
+                    );
+                    match &field.description {
+                        Some(text) => writeln!(out, "{}  -- {}", line.trim_end(), text)?,
+                        None => writeln!(out, "{}", line.trim_end())?,
+                    }
                 }
-                println!("\n({} total)", items.len());
             }
         }
         OutputFormat::Minimal => {
-            for name in items {
-                println!("{}", name);
+            for field in &description.fields {
+                writeln!(out, "{}\t{}", field.name, field.field_type)?;
             }
         }
     }
+    Ok(())
 }
 
-fn print_documents(docs: &[Document], format: OutputFormat) {
+fn print_plan(out: &mut dyn Write, plan: &QueryPlan, format: OutputFormat) -> std::io::Result<()> {
+    let access = match &plan.access {
+        Access::FullScan => "full scan".to_string(),
+        Access::Stream => "streaming scan, stopping at LIMIT".to_string(),
+        Access::IndexLookup { fields } => format!("index lookup on {}", fields.join(", ")),
+        Access::Snapshot => "full scan of a git snapshot".to_string(),
+        Access::System => "system collection".to_string(),
+        Access::View => "rows of a view's query".to_string(),
+        Access::Count => "count of document files, none read".to_string(),
+    };
     match format {
         OutputFormat::Json => {
-            let json_docs: Vec<serde_json::Value> = docs.iter().map(doc_to_json).collect();
-            println!("{}", serde_json::to_string_pretty(&json_docs).unwrap_or_default());
+            writeln!(out, "{}", serde_json::to_string_pretty(plan).unwrap_or_default())?;
         }
         OutputFormat::Table => {
-            if docs.is_empty() {
-                println!("No documents found.");
-                return;
+            writeln!(out, "Collection: {} ({} document(s))", plan.collection, plan.documents)?;
+            writeln!(out, "Access:     {}", access)?;
+            for (i, filter) in plan.filters.iter().enumerate() {
+                let label = if i == 0 { "Filter:" } else { "" };
+                match &filter.index {
+                    Some(field) => writeln!(out, "{:11} {}  [index on {}]", label, filter.condition, field)?,
+                    None => writeln!(out, "{:11} {}", label, filter.condition)?,
+                }
             }
-
+            for join in &plan.joins {
+                writeln!(out, "Join:       {} ({} document(s))", join.join, join.documents)?;
+            }
+            if plan.aggregate {
+                if plan.group_by.is_empty() {
+                    writeln!(out, "Group:      all rows into one")?;
+                } else {
+                    writeln!(out, "Group:      by {}", plan.group_by.join(", "))?;
+                }
+            }
+            match &plan.sort {
+                SortStrategy::None => {}
+                SortStrategy::Relevance => writeln!(out, "Sort:       by relevance")?,
+                SortStrategy::InMemory { keys } => writeln!(out, "Sort:       in memory by {}", keys.join(", "))?,
+            }
+            if let Some(offset) = plan.offset {
+                writeln!(out, "Offset:     {}", offset)?;
+            }
+            if let Some(limit) = plan.limit {
+                writeln!(out, "Limit:      {}", limit)?;
+            }
+            writeln!(out, "Scanned:    at most {} document(s)", plan.estimated_scanned)?;
+        }
+        OutputFormat::Minimal => writeln!(out, "{}\t{}", access, plan.estimated_scanned)?,
+    }
+    Ok(())
+}
+
+fn print_list(out: &mut dyn Write, label: &str, items: &[String], format: OutputFormat) -> std::io::Result<()> {
+    match format {
+        OutputFormat::Json => {
+            writeln!(out, "{}", serde_json::to_string_pretty(&items).unwrap_or_default())?;
+        }
+        OutputFormat::Table => {
+            if items.is_empty() {
+                writeln!(out, "No {} found.", label.to_lowercase())?;
+            } else {
+                writeln!(out, "{}:", label)?;
+                for name in items {
+                    writeln!(out, "  {}", name)?;
+                }
+                writeln!(out, "\n({} total)", items.len())?;
+            }
+        }
+        OutputFormat::Minimal => {
+            for name in items {
+                writeln!(out, "{}", name)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Documents in `format`; table headers show the fields' `labels`, if any
+fn print_documents(out: &mut dyn Write, docs: &[Document], format: OutputFormat, labels: &BTreeMap<String, String>) -> std::io::Result<()> {
+    match format {
+        OutputFormat::Json => {
+            let json_docs: Vec<serde_json::Value> = docs.iter().map(mdby::server::document_json).collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&json_docs).unwrap_or_default())?;
+        }
+        OutputFormat::Table => {
+            if docs.is_empty() {
+                writeln!(out, "No documents found.")?;
+                return Ok(());
+            }
+
             // Collect all field names
             let mut all_fields: Vec<String> = vec!["id".to_string()];
             for doc in docs {
@@ -229,9 +1139,10 @@ fn print_documents(docs: &[Document], format: OutputFormat) {
             }
 
             // Calculate column widths
+            let heading = |field: &str| labels.get(field).map_or(field, String::as_str).to_string();
             let mut widths: HashMap<&str, usize> = HashMap::new();
             for field in &all_fields {
-                widths.insert(field, field.len());
+                widths.insert(field, heading(field).chars().count());
             }
             for doc in docs {
                 let id_len = doc.id.len();
@@ -250,16 +1161,16 @@ fn print_documents(docs: &[Document], format: OutputFormat) {
             // Print header
             let header: Vec<String> = all_fields
                 .iter()
-                .map(|f| format!("{:width$}", f, width = widths.get(f.as_str()).unwrap_or(&0)))
+                .map(|f| format!("{:width$}", heading(f), width = widths.get(f.as_str()).unwrap_or(&0)))
                 .collect();
-            println!("{}", header.join(" | "));
+            writeln!(out, "{}", header.join(" | "))?;
 
             // Print separator
             let sep: Vec<String> = all_fields
                 .iter()
                 .map(|f| "-".repeat(*widths.get(f.as_str()).unwrap_or(&0)))
                 .collect();
-            println!("{}", sep.join("-+-"));
+            writeln!(out, "{}", sep.join("-+-"))?;
 
             // Print rows
             for doc in docs {
@@ -274,17 +1185,18 @@ fn print_documents(docs: &[Document], format: OutputFormat) {
                         format!("{:width$}", val, width = widths.get(f.as_str()).unwrap_or(&0))
                     })
                     .collect();
-                println!("{}", row.join(" | "));
+                writeln!(out, "{}", row.join(" | "))?;
             }
 
-            println!("\n({} row(s))", docs.len());
+            writeln!(out, "\n({} row(s))", docs.len())?;
         }
         OutputFormat::Minimal => {
             for doc in docs {
-                println!("{}", doc.id);
+                writeln!(out, "{}", doc.id)?;
             }
         }
     }
+    Ok(())
 }
 
 fn format_value(value: &mdby::storage::document::Value) -> String {
@@ -303,232 +1215,339 @@ fn format_value(value: &mdby::storage::document::Value) -> String {
     }
 }
 
-fn doc_to_json(doc: &Document) -> serde_json::Value {
-    use mdby::storage::document::Value;
+async fn show_history(path: &Path, collection: &str, id: &str, format: OutputFormat) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let versions = db.history(collection, id).await?;
+    let date = |time: i64| {
+        chrono::DateTime::from_timestamp(time, 0)
+            .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+            .unwrap_or_default()
+    };
 
-    fn value_to_json(v: &Value) -> serde_json::Value {
-        match v {
-            Value::Null => serde_json::Value::Null,
-            Value::Bool(b) => serde_json::Value::Bool(*b),
-            Value::Int(i) => serde_json::json!(i),
-            Value::Float(f) => serde_json::json!(f),
-            Value::String(s) => serde_json::Value::String(s.clone()),
-            Value::Array(arr) => serde_json::Value::Array(arr.iter().map(value_to_json).collect()),
-            Value::Object(obj) => {
-                let map: serde_json::Map<String, serde_json::Value> =
-                    obj.iter().map(|(k, v)| (k.clone(), value_to_json(v))).collect();
-                serde_json::Value::Object(map)
+    match format {
+        OutputFormat::Json => {
+            let json: Vec<serde_json::Value> = versions
+                .iter()
+                .map(|version| {
+                    serde_json::json!({
+                        "commit": version.commit.id,
+                        "summary": version.commit.summary,
+                        "author": version.commit.author,
+                        "date": date(version.commit.time),
+                        "document": version.document.as_ref().map(mdby::server::document_json),
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        }
+        OutputFormat::Table => {
+            if versions.is_empty() {
+                println!("No history for '{}' in {}.", id, collection);
+                return Ok(());
+            }
+            for (i, version) in versions.iter().enumerate() {
+                // Versions are newest first, so the one before this is next
+                let change = match (&version.document, versions.get(i + 1).and_then(|v| v.document.as_ref())) {
+                    (None, _) => "deleted".to_string(),
+                    (Some(_), None) => "created".to_string(),
+                    (Some(after), Some(before)) => {
+                        let diff = mdby::diff::diff(std::slice::from_ref(before), std::slice::from_ref(after));
+                        diff.changed.first().map(|c| c.fields.join(", ")).unwrap_or_else(|| "no changes".into())
+                    }
+                };
+                println!(
+                    "{}  {}  {}  {} ({})",
+                    &version.commit.id[..7.min(version.commit.id.len())],
+                    date(version.commit.time),
+                    version.commit.author,
+                    version.commit.summary,
+                    change
+                );
+            }
+        }
+        OutputFormat::Minimal => {
+            for version in &versions {
+                println!("{}", version.commit.id);
             }
         }
     }
 
-    let mut obj = serde_json::Map::new();
-    obj.insert("id".to_string(), serde_json::Value::String(doc.id.clone()));
+    Ok(())
+}
 
-    for (key, value) in &doc.fields {
-        obj.insert(key.clone(), value_to_json(value));
+async fn diff_query(
+    path: &Path,
+    query: &str,
+    other: Option<&str>,
+    from: Option<&str>,
+    to: Option<&str>,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    if other.is_none() && from.is_none() && to.is_none() {
+        anyhow::bail!("Give a second query, or --from/--to revisions to run the query at");
     }
 
-    if !doc.body.is_empty() {
-        obj.insert("_body".to_string(), serde_json::Value::String(doc.body.clone()));
+    let mut db = Database::open(path).await?;
+    let diff = db.diff_queries(query, from, other.unwrap_or(query), to).await?;
+
+    match format {
+        OutputFormat::Json => {
+            let changed: Vec<serde_json::Value> = diff
+                .changed
+                .iter()
+                .map(|c| {
+                    serde_json::json!({
+                        "id": c.after.id,
+                        "fields": c.fields,
+                        "before": mdby::server::document_json(&c.before),
+                        "after": mdby::server::document_json(&c.after),
+                    })
+                })
+                .collect();
+            let json = serde_json::json!({
+                "added": diff.added.iter().map(mdby::server::document_json).collect::<Vec<_>>(),
+                "removed": diff.removed.iter().map(mdby::server::document_json).collect::<Vec<_>>(),
+                "changed": changed,
+            });
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        }
+        OutputFormat::Table | OutputFormat::Minimal => {
+            for doc in &diff.added {
+                println!("+ {}", doc.id);
+            }
+            for doc in &diff.removed {
+                println!("- {}", doc.id);
+            }
+            for changed in &diff.changed {
+                match format {
+                    OutputFormat::Table => println!("~ {} ({})", changed.after.id, changed.fields.join(", ")),
+                    _ => println!("~ {}", changed.after.id),
+                }
+            }
+            if matches!(format, OutputFormat::Table) {
+                if diff.is_empty() {
+                    println!("No differences.");
+                } else {
+                    println!();
+                    println!(
+                        "{} added, {} removed, {} changed",
+                        diff.added.len(),
+                        diff.removed.len(),
+                        diff.changed.len()
+                    );
+                }
+            }
+        }
     }
 
-    serde_json::Value::Object(obj)
+    Ok(())
 }
 
-async fn run_repl(path: &PathBuf) -> anyhow::Result<()> {
-    use std::io::{self, BufRead, Write};
-
-    println!("MDBY Interactive Shell");
-    println!("Type 'help' for commands, 'exit' to quit.");
-    println!();
+/// Show the schema inferred for a collection and write it once confirmed
+///
+/// Without a terminal to ask on, the schema is only written with `--yes`.
+async fn infer_schema(path: &Path, collection: &str, required_ratio: f64, yes: bool, format: OutputFormat) -> anyhow::Result<()> {
+    use std::io::{BufRead, IsTerminal, Write};
 
+    if !(0.0..=1.0).contains(&required_ratio) {
+        anyhow::bail!("--required-ratio must be between 0 and 1, got {}", required_ratio);
+    }
     let mut db = Database::open(path).await?;
+    let inference = db.infer_schema(collection, required_ratio).await?;
+    let schema_file = Path::new(".mdby").join("schemas").join(format!("{}.yaml", collection));
 
-    let stdin = io::stdin();
-    let mut stdout = io::stdout();
-
-    loop {
-        print!("mdql> ");
-        stdout.flush()?;
-
-        let mut line = String::new();
-        if stdin.lock().read_line(&mut line)? == 0 {
-            break;
-        }
-
-        let line = line.trim();
-        if line.is_empty() {
-            continue;
-        }
-
-        match line.to_lowercase().as_str() {
-            "exit" | "quit" | "\\q" => break,
-            "help" | "\\h" => {
-                println!("Commands:");
-                println!("  SELECT * FROM <collection>    - Query documents");
-                println!("  INSERT INTO <collection> ...  - Insert a document");
-                println!("  UPDATE <collection> SET ...   - Update documents");
-                println!("  DELETE FROM <collection> ...  - Delete documents");
-                println!("  CREATE COLLECTION <name> ...  - Create a collection");
-                println!("  CREATE VIEW <name> AS ...     - Create a view");
-                println!();
-                println!("Special:");
-                println!("  help, \\h  - Show this help");
-                println!("  exit, \\q  - Exit the shell");
-                continue;
-            }
-            _ => {}
-        }
-
-        match db.execute(line).await {
-            Ok(result) => match result {
-                QueryResult::Documents(docs) => {
-                    print_documents(&docs, OutputFormat::Table);
-                }
-                QueryResult::Affected(n) => println!("({} row(s) affected)", n),
-                QueryResult::CollectionCreated(name) => println!("Collection '{}' created", name),
-                QueryResult::ViewCreated(name) => println!("View '{}' created", name),
-                QueryResult::Collections(names) => {
-                    print_list("Collections", &names, OutputFormat::Table);
-                }
-                QueryResult::Views(names) => {
-                    print_list("Views", &names, OutputFormat::Table);
-                }
-            },
-            Err(e) => {
-                eprintln!("Error: {}", e);
-                if let Some(mdby_err) = e.downcast_ref::<mdby::Error>() {
-                    if let Some(suggestion) = mdby_err.suggestion() {
-                        eprintln!("Hint: {}", suggestion);
-                    }
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&inference)?),
+        _ => {
+            println!("Scanned {} document(s) in {}:", inference.documents, collection);
+            let width = inference.fields.iter().map(|field| field.name.len()).max().unwrap_or(0);
+            for field in &inference.fields {
+                let required = if field.required { "  required" } else { "" };
+                println!(
+                    "  {:width$}  {:14}  {}/{}{}",
+                    field.name,
+                    field.field_type,
+                    field.present,
+                    inference.documents,
+                    required,
+                    width = width
+                );
+                if !field.mismatched.is_empty() {
+                    let seen: Vec<String> = field.conflicts.iter().map(|(name, count)| format!("{} {}", name, count)).collect();
+                    println!("    conflict: {}; not {}: {}", seen.join(", "), field.field_type, field.mismatched.join(", "));
                 }
             }
+            println!();
+            println!("Proposed {}:", schema_file.display());
+            print!("{}", serde_yaml::to_string(&inference.schema)?);
         }
-        println!();
+    }
+    if inference.has_conflicts() {
+        eprintln!("Documents listed under a conflict won't validate against this schema until they are fixed.");
     }
 
-    println!("Goodbye!");
+    if !yes {
+        let stdin = std::io::stdin();
+        if !stdin.is_terminal() || matches!(format, OutputFormat::Json) {
+            eprintln!("Pass --yes to write {}", schema_file.display());
+            return Ok(());
+        }
+        let replacing = if path.join(&schema_file).exists() { " (replacing the current schema)" } else { "" };
+        print!("Write {}{}? [y/N] ", schema_file.display(), replacing);
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        stdin.lock().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            return Ok(());
+        }
+    }
+    db.save_schema(inference.schema)?;
+    println!("Wrote {}", schema_file.display());
     Ok(())
 }
 
-async fn regenerate_views(path: &PathBuf) -> anyhow::Result<()> {
+async fn seed_collection(
+    path: &Path,
+    collection: &str,
+    count: usize,
+    from_schema: bool,
+    seed: Option<u64>,
+) -> anyhow::Result<()> {
     let db = Database::open(path).await?;
-    println!("Regenerating views...");
-    db.regenerate_views().await?;
-    println!("Done!");
+    let ids = db.seed(collection, count, from_schema, seed).await?;
+    println!("Added {} documents to {}", ids.len(), collection);
     Ok(())
 }
 
-async fn sync_database(path: &PathBuf, remote: &str) -> anyhow::Result<()> {
-    let mut db = Database::open(path).await?;
-    println!("Syncing with {}...", remote);
-    let result = db.sync().await?;
-    println!("Pulled: {} commits", result.pulled);
-    println!("Pushed: {} commits", result.pushed);
-    if !result.conflicts_resolved.is_empty() {
-        println!("Resolved conflicts:");
-        for path in &result.conflicts_resolved {
-            println!("  - {}", path);
-        }
+async fn export_database(path: &PathBuf, dest: &Path, scrub: bool) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let summary = db.export(dest, scrub).await?;
+    println!(
+        "Exported {} documents in {} collections to {:?}",
+        summary.documents, summary.collections, dest
+    );
+    if scrub {
+        println!("Scrubbed {} values", summary.scrubbed);
     }
     Ok(())
 }
 
-async fn show_status(path: &PathBuf) -> anyhow::Result<()> {
+async fn export_csv(path: &Path, collection: &str, output: Option<&Path>) -> anyhow::Result<()> {
     let db = Database::open(path).await?;
-
-    println!("MDBY Database Status");
-    println!("====================");
-    println!("Path: {:?}", db.root);
-    println!();
-
-    // Count collections
-    let collections_path = path.join("collections");
-    if collections_path.exists() {
-        let mut count = 0;
-        let mut entries = tokio::fs::read_dir(&collections_path).await?;
-        while let Some(entry) = entries.next_entry().await? {
-            if entry.path().is_dir() {
-                count += 1;
-            }
+    match output {
+        Some(output) => {
+            let count = db.export_csv(collection, std::fs::File::create(output)?).await?;
+            println!("Exported {} documents to {:?}", count, output);
+        }
+        None => {
+            db.export_csv(collection, std::io::stdout().lock()).await?;
         }
-        println!("Collections: {}", count);
-    } else {
-        println!("Collections: 0");
     }
+    Ok(())
+}
 
-    // Count views
-    let views_path = path.join(".mdby/views");
-    if views_path.exists() {
-        let mut count = 0;
-        let mut entries = tokio::fs::read_dir(&views_path).await?;
-        while let Some(entry) = entries.next_entry().await? {
-            if entry.path().extension().map(|e| e == "yaml").unwrap_or(false) {
-                count += 1;
-            }
+async fn export_json(path: &Path, collection: &str, output: Option<&Path>, lines: bool) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    match output {
+        Some(output) => {
+            let count = db.export_json(collection, std::fs::File::create(output)?, lines).await?;
+            println!("Exported {} documents to {:?}", count, output);
+        }
+        None => {
+            db.export_json(collection, std::io::stdout().lock(), lines).await?;
         }
-        println!("Views: {}", count);
-    } else {
-        println!("Views: 0");
     }
+    Ok(())
+}
 
-    // Git status
-    if db.git.has_changes()? {
-        println!("\nUncommitted changes detected.");
+async fn import_csv(path: &Path, file: &Path, collection: &str, id_column: &str, format: OutputFormat) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let ids = if file == Path::new("-") {
+        db.import_csv(collection, std::io::stdin().lock(), id_column).await?
     } else {
-        println!("\nNo uncommitted changes.");
-    }
-
+        db.import_csv(collection, open_input(file)?, id_column).await?
+    };
+    print_imported(collection, &ids, format);
     Ok(())
 }
 
-async fn list_collections(path: &PathBuf, format: OutputFormat) -> anyhow::Result<()> {
-    let collections_path = path.join("collections");
-
-    if !collections_path.exists() {
-        match format {
-            OutputFormat::Json => println!("[]"),
-            _ => println!("No collections found."),
-        }
-        return Ok(());
-    }
+async fn import_jsonl(path: &Path, collection: &str, file: &Path, format: OutputFormat) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let ids = if file == Path::new("-") {
+        db.import_jsonl(collection, std::io::stdin().lock()).await?
+    } else {
+        db.import_jsonl(collection, open_input(file)?).await?
+    };
+    print_imported(collection, &ids, format);
+    Ok(())
+}
 
-    let mut collections = Vec::new();
-    let mut entries = tokio::fs::read_dir(&collections_path).await?;
+fn open_input(file: &Path) -> anyhow::Result<std::fs::File> {
+    std::fs::File::open(file).map_err(|e| anyhow::anyhow!("Cannot read {:?}: {}", file, e))
+}
 
-    while let Some(entry) = entries.next_entry().await? {
-        if entry.path().is_dir() {
-            let name = entry.file_name().to_string_lossy().to_string();
-            // Count documents
-            let mut doc_count = 0;
-            let mut docs = tokio::fs::read_dir(entry.path()).await?;
-            while let Some(doc) = docs.next_entry().await? {
-                if doc.path().extension().map(|e| e == "md").unwrap_or(false) {
-                    doc_count += 1;
-                }
+fn print_imported(collection: &str, ids: &[String], format: OutputFormat) {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::json!({"affected": ids.len(), "ids": ids})),
+        OutputFormat::Table => println!("Imported {} documents into {}", ids.len(), collection),
+        OutputFormat::Minimal => {
+            for id in ids {
+                println!("{}", id);
             }
-            collections.push((name, doc_count));
         }
     }
+}
+
+fn show_slowlog(path: &Path, limit: usize, format: OutputFormat) -> anyhow::Result<()> {
+    let entries = mdby::query_log::read(path)?;
+    let report = mdby::query_log::report(&entries, limit);
 
     match format {
         OutputFormat::Json => {
-            let json: Vec<serde_json::Value> = collections
+            let slowest: Vec<serde_json::Value> = report
+                .slowest
                 .iter()
-                .map(|(name, count)| serde_json::json!({"name": name, "documents": count}))
+                .map(|s| {
+                    serde_json::json!({
+                        "statement": s.statement,
+                        "count": s.count,
+                        "max_ms": s.max_ms,
+                        "avg_ms": s.total_ms / s.count as f64,
+                    })
+                })
+                .collect();
+            let most_scanned: Vec<serde_json::Value> = report
+                .most_scanned
+                .iter()
+                .map(|c| serde_json::json!({"collection": c.collection, "queries": c.queries, "documents": c.documents}))
                 .collect();
+            let json = serde_json::json!({"entries": report.entries, "slowest": slowest, "most_scanned": most_scanned});
             println!("{}", serde_json::to_string_pretty(&json)?);
         }
         OutputFormat::Table => {
-            println!("Collections:");
-            for (name, count) in &collections {
-                println!("  {} ({} documents)", name, count);
+            if report.entries == 0 {
+                println!("The query log is empty. Set 'query_log: true' in .mdby/config.yaml to record queries.");
+                return Ok(());
+            }
+            println!("Slowest statements ({} logged):", report.entries);
+            for s in &report.slowest {
+                println!(
+                    "  {:>9.1} ms max {:>9.1} ms avg {:>5}x  {}",
+                    s.max_ms,
+                    s.total_ms / s.count as f64,
+                    s.count,
+                    s.statement
+                );
+            }
+            println!();
+            println!("Most scanned collections:");
+            for c in &report.most_scanned {
+                println!("  {:<24} {:>9} document(s) in {} statement(s)", c.collection, c.documents, c.queries);
             }
         }
         OutputFormat::Minimal => {
-            for (name, _) in &collections {
-                println!("{}", name);
+            for s in &report.slowest {
+                println!("{:.1}\t{}", s.max_ms, s.statement);
             }
         }
     }
@@ -536,51 +1555,1179 @@ async fn list_collections(path: &PathBuf, format: OutputFormat) -> anyhow::Resul
     Ok(())
 }
 
-async fn list_views(path: &PathBuf, format: OutputFormat) -> anyhow::Result<()> {
-    let views_path = path.join(".mdby/views");
+async fn deliver_views(path: &PathBuf, view: Option<&str>, daemon: bool) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
 
-    if !views_path.exists() {
-        match format {
-            OutputFormat::Json => println!("[]"),
-            _ => println!("No views found."),
-        }
+    if let Some(view) = view {
+        db.deliver_view(view).await?;
+        println!("Delivered view '{}'", view);
         return Ok(());
     }
 
-    let mut views = Vec::new();
-    let mut entries = tokio::fs::read_dir(&views_path).await?;
-
-    while let Some(entry) = entries.next_entry().await? {
-        if entry.path().extension().map(|e| e == "yaml").unwrap_or(false) {
-            let name = entry
-                .path()
-                .file_stem()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or_default();
-            views.push(name);
+    loop {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        for (view, result) in db.deliver_due_views(now).await? {
+            match result {
+                Ok(()) => println!("Delivered view '{}'", view),
+                Err(e) => eprintln!("Error: {}", e),
+            }
         }
+        if !daemon {
+            return Ok(());
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(30)).await;
     }
+}
 
-    match format {
-        OutputFormat::Json => {
-            let json: Vec<serde_json::Value> = views
-                .iter()
-                .map(|name| serde_json::json!({"name": name}))
-                .collect();
-            println!("{}", serde_json::to_string_pretty(&json)?);
-        }
-        OutputFormat::Table => {
-            println!("Views:");
-            for name in &views {
-                println!("  {}", name);
-            }
+async fn serve_database(
+    path: &PathBuf,
+    addr: std::net::SocketAddr,
+    read_only: bool,
+    cors_origins: Vec<String>,
+) -> anyhow::Result<()> {
+    let mut db = Database::open(path).await?;
+    if read_only {
+        db.set_policy(mdby::policy::ExecutionPolicy::read_only());
+    }
+    db.config.serve.cors_origins.extend(cors_origins);
+    println!("Serving {:?} on http://{}", path, addr);
+    mdby::server::serve(db, addr).await
+}
+
+async fn run_repl(path: &PathBuf, url: Option<&str>, yes: bool) -> anyhow::Result<()> {
+    use mdby::repl::MetaCommand;
+    use rustyline::error::ReadlineError;
+
+    println!("MDBY Interactive Shell");
+    println!("Type 'help' for commands, 'exit' to quit. End statements with ';'.");
+    if let Some(url) = url {
+        println!("Connected to {}.", url);
+    }
+    println!();
+
+    let mut target = Target::open(path, url).await?;
+
+    let mut editor = rustyline::Editor::<mdby::repl::ReplHelper, rustyline::history::FileHistory>::new()?;
+    let mut helper = mdby::repl::ReplHelper::default();
+    target.refresh(&mut helper).await;
+    editor.set_helper(Some(helper));
+    let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".mdby_history"));
+    if let Some(history) = &history {
+        // There is none on the first run
+        let _ = editor.load_history(history);
+    }
+
+    let mut format = OutputFormat::Table;
+    let mut timing = false;
+    // Results go to the terminal, or to the file given with \o
+    let mut output: Option<std::fs::File> = None;
+    let format_name = |format: OutputFormat| format.to_possible_value().map(|value| value.get_name().to_string());
+
+    loop {
+        let input = match editor.readline("mdql> ") {
+            Ok(input) => input,
+            // Ctrl-C drops the statement being typed
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        let line = input.trim();
+        if line.is_empty() {
+            continue;
         }
-        OutputFormat::Minimal => {
-            for name in &views {
-                println!("{}", name);
+        editor.add_history_entry(line)?;
+        if let Some(history) = &history {
+            if let Err(e) = editor.append_history(history) {
+                tracing::warn!("Failed to save the REPL history: {}", e);
             }
         }
-    }
 
-    Ok(())
+        let statement = match MetaCommand::parse(line) {
+            Ok(None) => line.to_string(),
+            Ok(Some(MetaCommand::Quit)) => break,
+            Ok(Some(MetaCommand::Help)) => {
+                print_repl_help();
+                continue;
+            }
+            Ok(Some(MetaCommand::ListCollections)) => "SHOW COLLECTIONS".to_string(),
+            Ok(Some(MetaCommand::Describe(name))) => format!("DESCRIBE COLLECTION {}", name),
+            Ok(Some(MetaCommand::Timing(on))) => {
+                timing = on.unwrap_or(!timing);
+                println!("Timing is {}.", if timing { "on" } else { "off" });
+                println!();
+                continue;
+            }
+            Ok(Some(MetaCommand::Format(name))) => {
+                if let Some(name) = name {
+                    match <OutputFormat as ValueEnum>::from_str(&name, true) {
+                        Ok(chosen) => format = chosen,
+                        Err(_) => eprintln!("Unknown format '{}': expected table, json or minimal", name),
+                    }
+                }
+                println!("Output format is {}.", format_name(format).unwrap_or_default());
+                println!();
+                continue;
+            }
+            Ok(Some(MetaCommand::Output(file))) => {
+                match file {
+                    Some(file) => match std::fs::File::create(&file) {
+                        Ok(opened) => {
+                            output = Some(opened);
+                            println!("Writing results to {}.", file);
+                        }
+                        Err(e) => eprintln!("Error: Failed to open {}: {}", file, e),
+                    },
+                    None => {
+                        output = None;
+                        println!("Writing results to the terminal.");
+                    }
+                }
+                println!();
+                continue;
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                println!();
+                continue;
+            }
+        };
+
+        match target.confirm(&statement, yes).await {
+            Ok(true) => {}
+            Ok(false) => {
+                println!("Cancelled.");
+                println!();
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                println!();
+                continue;
+            }
+        }
+
+        let labels = target.labels(&statement);
+        let timer = std::time::Instant::now();
+        let result = target.execute(&statement).await;
+        let elapsed = timer.elapsed();
+        match result {
+            Ok(result) => {
+                let written = match &mut output {
+                    Some(file) => print_result(file, result, format, &labels).and_then(|_| writeln!(file)),
+                    None => print_result(&mut std::io::stdout(), result, format, &labels),
+                };
+                if let Err(e) = written {
+                    eprintln!("Error: Failed to write the result: {}", e);
+                }
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                if let Some(suggestion) = suggestion(&e) {
+                    eprintln!("Hint: {}", suggestion);
+                }
+            }
+        }
+        if timing {
+            println!("Time: {:.3} ms", elapsed.as_secs_f64() * 1000.0);
+        }
+        // The statement may have created or altered collections
+        if let Some(helper) = editor.helper_mut() {
+            target.refresh(helper).await;
+        }
+        println!();
+    }
+
+    println!("Goodbye!");
+    Ok(())
+}
+
+fn print_repl_help() {
+    println!("Commands:");
+    println!("  SELECT * FROM <collection>    - Query documents");
+    println!("  INSERT INTO <collection> ...  - Insert a document");
+    println!("  UPDATE <collection> SET ...   - Update documents");
+    println!("  DELETE FROM <collection> ...  - Delete documents");
+    println!("  CREATE COLLECTION <name> ...  - Create a collection");
+    println!("  CREATE VIEW <name> AS ...     - Create a view");
+    println!("  DESCRIBE <collection>         - Show schema and description");
+    println!();
+    println!("Statements may span several lines and run once they end with ';'.");
+    println!("Tab completes keywords, collection names and field names.");
+    println!();
+    println!("Special:");
+    println!("  \\dt                        - List collections");
+    println!("  \\d <collection>            - Show a collection's schema");
+    println!("  \\timing [on|off]           - Show how long each statement takes");
+    println!("  \\format [table|json|minimal] - Set the output format");
+    println!("  \\o [file]                  - Write results to a file, or back to the terminal");
+    println!("  help, \\h                   - Show this help");
+    println!("  exit, \\q                   - Exit the shell");
+}
+
+async fn regenerate_views(path: &PathBuf, force: bool) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    println!("Regenerating views...");
+    if force {
+        db.rebuild_views().await?;
+    } else {
+        db.regenerate_views().await?;
+    }
+    println!("Done!");
+    Ok(())
+}
+
+async fn generate_docs(path: &PathBuf) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let docs = db.generate_docs().await?;
+    println!("Docs written to {}", docs.display());
+    println!("They are refreshed whenever views are regenerated.");
+    Ok(())
+}
+
+async fn export_graph(path: &Path, collections: Vec<String>, tags: Vec<String>, format: OutputFormat) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let graph = db.graph(&mdby::graph::GraphFilter { collections, tags }).await?;
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&graph)?),
+        OutputFormat::Table | OutputFormat::Minimal => print!("{}", graph.to_dot()),
+    }
+    Ok(())
+}
+
+async fn generate_dashboard(path: &Path) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let dashboard = db.generate_dashboard().await?;
+    println!("Dashboard written to {}", dashboard.display());
+    println!("It is refreshed whenever views are regenerated.");
+    Ok(())
+}
+
+async fn sync_database(path: &PathBuf, remote: &str, strategy: Option<ConflictResolution>) -> anyhow::Result<()> {
+    let mut db = Database::open(path).await?;
+    println!("Syncing with {}...", remote);
+    let result = db.sync(remote, strategy).await?;
+    if result.offline {
+        println!("Remote '{}' is unreachable; {} local commit(s) queued for the next sync.", remote, result.queued);
+        return Ok(());
+    }
+    println!("Pulled: {} commits", result.pulled);
+    println!("Pushed: {} commits", result.pushed);
+    if !result.conflicts_resolved.is_empty() {
+        println!("Resolved conflicts:");
+        for path in &result.conflicts_resolved {
+            println!("  - {}", path);
+        }
+    }
+    if !result.rebased.is_empty() {
+        println!("Rebased onto remote changes:");
+        for commit in &result.rebased {
+            println!("  - {} -> {} {}", &commit.original[..7], &commit.rebased[..7], commit.summary);
+        }
+    }
+    if !result.conflicts.is_empty() {
+        println!("Pre-merge versions saved for review:");
+        for record in &result.conflicts {
+            println!(
+                "  - {}/{} ({}): {}",
+                record.collection,
+                record.id,
+                record.strategy.name(),
+                record.path.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn propose_changes(path: &Path, message: &str, remote: &str, format: OutputFormat) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let proposal = db.propose(message, remote)?;
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&proposal)?),
+        OutputFormat::Table => {
+            println!("Proposal '{}' created with {} changed file(s).", proposal.name, proposal.files.len());
+            if proposal.pushed {
+                println!("Pushed to {}.", remote);
+            } else {
+                println!("Not pushed ({} is missing or unreachable); it is kept locally.", remote);
+            }
+        }
+        OutputFormat::Minimal => println!("{}", proposal.name),
+    }
+    Ok(())
+}
+
+async fn list_proposals(path: &Path, remote: &str, format: OutputFormat) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let proposals = db.proposals(remote)?;
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&proposals)?),
+        OutputFormat::Table => {
+            if proposals.is_empty() {
+                println!("No open proposals.");
+            }
+            for proposal in &proposals {
+                let date = chrono::DateTime::from_timestamp(proposal.time, 0)
+                    .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default();
+                let location = if proposal.pushed { "" } else { " (local)" };
+                println!("{}{}", proposal.name, location);
+                println!("  {} by {} on {}", proposal.summary, proposal.author, date);
+                for file in &proposal.files {
+                    println!("  - {}", file);
+                }
+            }
+        }
+        OutputFormat::Minimal => {
+            for proposal in &proposals {
+                println!("{}", proposal.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn apply_proposal(path: &Path, name: &str, remote: &str, format: OutputFormat) -> anyhow::Result<()> {
+    let mut db = Database::open(path).await?;
+    let outcome = db.apply_proposal(name, remote)?;
+    if outcome.is_some() {
+        db.regenerate_views().await?;
+    }
+
+    match (format, outcome) {
+        (OutputFormat::Json, outcome) => {
+            let conflicts: Vec<String> = outcome
+                .iter()
+                .flat_map(|o| o.conflicts.iter().map(|c| c.path.display().to_string()))
+                .collect();
+            println!(
+                "{}",
+                serde_json::json!({
+                    "applied": outcome.is_some(),
+                    "commit": outcome.as_ref().map(|o| o.commit.clone()),
+                    "conflicts": conflicts,
+                })
+            );
+        }
+        (_, None) => println!("Proposal '{}' is already merged.", name),
+        (_, Some(outcome)) => {
+            println!("Applied proposal '{}' ({}).", name, &outcome.commit[..7]);
+            if !outcome.conflicts.is_empty() {
+                println!("Merged field by field; pre-merge versions saved for review:");
+                for record in &outcome.conflicts {
+                    println!("  - {}/{}: {}", record.collection, record.id, record.path.display());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn verify_signatures(path: &Path, since: Option<&str>, format: OutputFormat) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let results = db.verify(since)?;
+    let flagged: Vec<_> = results.iter().filter(|r| r.flagged()).collect();
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&results)?),
+        OutputFormat::Table => {
+            for result in &flagged {
+                let status = match &result.signature {
+                    SignatureStatus::Invalid { reason } => format!("INVALID: {}", reason),
+                    _ => "UNSIGNED".to_string(),
+                };
+                println!("{} {} ({})", &result.id[..7], result.summary, result.author);
+                println!("  {}", status);
+            }
+            println!("{} commit(s) checked, {} flagged.", results.len(), flagged.len());
+        }
+        OutputFormat::Minimal => {
+            for result in &flagged {
+                println!("{}", result.id);
+            }
+        }
+    }
+
+    if !flagged.is_empty() {
+        anyhow::bail!("{} commit(s) are unsigned or have invalid signatures", flagged.len());
+    }
+    Ok(())
+}
+
+async fn show_status(path: &PathBuf) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+
+    println!("MDBY Database Status");
+    println!("====================");
+    println!("Path: {:?}", db.root);
+    if let Some(name) = &db.config.name {
+        println!("Name: {}", name);
+    }
+    if let Some(template) = &db.config.template {
+        println!("Template: {}", template);
+    }
+    println!();
+
+    // Count collections
+    let collections_path = path.join("collections");
+    if collections_path.exists() {
+        let mut count = 0;
+        let mut entries = tokio::fs::read_dir(&collections_path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.path().is_dir() {
+                count += 1;
+            }
+        }
+        println!("Collections: {}", count);
+    } else {
+        println!("Collections: 0");
+    }
+
+    // Count views
+    let views_path = path.join(".mdby/views");
+    if views_path.exists() {
+        let mut count = 0;
+        let mut entries = tokio::fs::read_dir(&views_path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.path().extension().map(|e| e == "yaml").unwrap_or(false) {
+                count += 1;
+            }
+        }
+        println!("Views: {}", count);
+    } else {
+        println!("Views: 0");
+    }
+
+    // Git status
+    if db.git.has_changes()? {
+        println!("\nUncommitted changes detected.");
+    } else {
+        println!("\nNo uncommitted changes.");
+    }
+    if db.git.inner().find_remote("origin").is_ok() {
+        println!("{} commit(s) waiting to sync with origin.", db.git.pending("origin")?);
+    }
+
+    Ok(())
+}
+
+async fn list_collections(path: &Path, format: OutputFormat) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let collections = mdby::system::list(&db.git, mdby::system::COLLECTIONS)?;
+
+    match format {
+        OutputFormat::Json => {
+            let json: Vec<serde_json::Value> = collections.iter().map(mdby::server::document_json).collect();
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        }
+        OutputFormat::Table => {
+            if collections.is_empty() {
+                println!("No collections found.");
+                return Ok(());
+            }
+            let int = |doc: &Document, field: &str| doc.get(field).and_then(|v| v.as_i64()).unwrap_or_default();
+            let width = collections.iter().map(|doc| doc.id.len()).max().unwrap_or_default().max(4);
+            println!(
+                "{:<width$}  {:>9}  {:>9}  {:<6}  {:<20}  ACTIVITY ({} weeks)",
+                "NAME",
+                "DOCUMENTS",
+                "SIZE",
+                "SCHEMA",
+                "LAST MODIFIED",
+                mdby::system::ACTIVITY_WEEKS
+            );
+            for doc in &collections {
+                let activity: Vec<i64> = match doc.get("activity") {
+                    Some(mdby::storage::document::Value::Array(weeks)) => weeks.iter().filter_map(|v| v.as_i64()).collect(),
+                    _ => Vec::new(),
+                };
+                println!(
+                    "{:<width$}  {:>9}  {:>9}  {:<6}  {:<20}  {}",
+                    doc.id,
+                    int(doc, "documents"),
+                    human_bytes(int(doc, "bytes") as u64),
+                    if doc.get("schema").and_then(|v| v.as_bool()).unwrap_or(false) { "yes" } else { "no" },
+                    doc.get("modified").and_then(|v| v.as_str()).unwrap_or("-"),
+                    sparkline(&activity)
+                );
+            }
+        }
+        OutputFormat::Minimal => {
+            for doc in &collections {
+                println!("{}", doc.id);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A byte count in B, KiB, MiB or GiB
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Counts as block characters scaled to the largest, e.g. `▁▁▃█`
+fn sparkline(counts: &[i64]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = counts.iter().copied().max().unwrap_or_default();
+    counts
+        .iter()
+        .map(|&count| match count {
+            0 => ' ',
+            _ => BARS[((count * (BARS.len() as i64 - 1)) / max.max(1)) as usize],
+        })
+        .collect()
+}
+
+async fn render_view(path: &Path, name: &str, params: &[String], output: Option<&Path>, format: OutputFormat) -> anyhow::Result<()> {
+    let params = params
+        .iter()
+        .map(|param| match param.split_once('=') {
+            Some((key, value)) => Ok((key.trim().to_string(), value.to_string())),
+            None => Err(anyhow::anyhow!("Give parameters as name=value, got '{}'", param)),
+        })
+        .collect::<anyhow::Result<BTreeMap<_, _>>>()?;
+
+    let db = Database::open(path).await?;
+    let rendered = db.render_view(name, &params).await?;
+    if let Some(output) = output {
+        rendered.write_to(output).await?;
+        println!("Rendered view '{}' to {:?}", name, output);
+        return Ok(());
+    }
+    // The view's first format, or its JSON with --format json
+    let text = match format {
+        OutputFormat::Json => rendered.get(mdby::views::OutputFormat::Json),
+        _ => rendered.outputs.first().map(|(_, text)| text.as_str()),
+    };
+    let text = text.ok_or_else(|| anyhow::anyhow!("View '{}' isn't rendered to JSON; add 'json' to its FORMATS", name))?;
+    println!("{}", text);
+    Ok(())
+}
+
+async fn add_template(path: &Path, file: &Path, name: Option<&str>, force: bool) -> anyhow::Result<()> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Give the template a name with --name"))?
+            .to_string(),
+    };
+    let content = std::fs::read_to_string(file).map_err(|e| anyhow::anyhow!("Cannot read {:?}: {}", file, e))?;
+    let db = Database::open(path).await?;
+    db.add_template(&name, &content, force)?;
+    println!("Added template '{}'", name);
+    Ok(())
+}
+
+async fn list_templates(path: &Path, format: OutputFormat) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let templates = db.templates()?;
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&templates)?),
+        OutputFormat::Minimal => {
+            for template in &templates {
+                println!("{}", template.name);
+            }
+        }
+        OutputFormat::Table => {
+            if templates.is_empty() {
+                println!("No templates found.");
+            }
+            for template in &templates {
+                println!("{}", template.name);
+                if let Some(parent) = &template.extends {
+                    println!("  extends: {}", parent);
+                }
+                if !template.includes.is_empty() {
+                    println!("  includes: {}", template.includes.join(", "));
+                }
+                if !template.views.is_empty() {
+                    println!("  views: {}", template.views.join(", "));
+                }
+                if !template.used_by.is_empty() {
+                    println!("  used by: {}", template.used_by.join(", "));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn remove_template(path: &Path, name: &str) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    db.remove_template(name)?;
+    println!("Removed template '{}'", name);
+    Ok(())
+}
+
+async fn preview_template(path: &Path, name: &str, query: &str, output: Option<&Path>) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let html = db.preview_template(name, query).await?;
+    match output {
+        Some(output) => {
+            std::fs::write(output, html)?;
+            println!("Rendered template '{}' to {:?}", name, output);
+        }
+        None => println!("{}", html),
+    }
+    Ok(())
+}
+
+async fn list_views(path: &Path, format: OutputFormat) -> anyhow::Result<()> {
+    let views_path = path.join(".mdby/views");
+
+    if !views_path.exists() {
+        match format {
+            OutputFormat::Json => println!("[]"),
+            _ => println!("No views found."),
+        }
+        return Ok(());
+    }
+
+    let mut views = Vec::new();
+    let mut entries = tokio::fs::read_dir(&views_path).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.path().extension().map(|e| e == "yaml").unwrap_or(false) {
+            let name = entry
+                .path()
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            views.push(name);
+        }
+    }
+
+    match format {
+        OutputFormat::Json => {
+            let json: Vec<serde_json::Value> = views
+                .iter()
+                .map(|name| serde_json::json!({"name": name}))
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        }
+        OutputFormat::Table => {
+            println!("Views:");
+            for name in &views {
+                println!("  {}", name);
+            }
+        }
+        OutputFormat::Minimal => {
+            for name in &views {
+                println!("{}", name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate documents; `links` is `Some(external)` when link checking is on
+async fn validate_documents(
+    path: &Path,
+    collection: Option<&str>,
+    links: Option<bool>,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    let mut db = Database::open(path).await?;
+
+    let names = match collection {
+        Some(name) => vec![name.to_string()],
+        None => match db.execute("SHOW COLLECTIONS").await? {
+            QueryResult::Collections(names) => names,
+            _ => Vec::new(),
+        },
+    };
+
+    // Schema errors and broken links, grouped per document
+    let mut problems: BTreeMap<(String, String), (Vec<String>, Vec<String>)> = BTreeMap::new();
+    for name in &names {
+        for report in db.validate_collection(name).await? {
+            let entry = problems.entry((report.collection, report.id)).or_default();
+            entry.0.extend(report.errors.iter().map(|e| e.to_string()));
+        }
+        if let Some(external) = links {
+            for report in db.check_links(name, external).await? {
+                let entry = problems.entry((report.collection, report.id)).or_default();
+                entry.1.extend(report.broken.iter().map(|b| b.to_string()));
+            }
+        }
+    }
+
+    match format {
+        OutputFormat::Json => {
+            let json: Vec<serde_json::Value> = problems
+                .iter()
+                .map(|((collection, id), (errors, broken))| {
+                    let mut entry = serde_json::json!({"collection": collection, "id": id, "errors": errors});
+                    if links.is_some() {
+                        entry["links"] = serde_json::json!(broken);
+                    }
+                    entry
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        }
+        OutputFormat::Table => {
+            for ((collection, id), (errors, broken)) in &problems {
+                println!("{}/{}:", collection, id);
+                for err in errors.iter().chain(broken) {
+                    println!("  - {}", err);
+                }
+            }
+            if problems.is_empty() {
+                println!("All documents are valid.");
+            }
+        }
+        OutputFormat::Minimal => {
+            for (collection, id) in problems.keys() {
+                println!("{}/{}", collection, id);
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        anyhow::bail!("{} document(s) failed validation", problems.len());
+    }
+
+    Ok(())
+}
+
+async fn lint_documents(path: &Path, collection: Option<&str>, format: OutputFormat) -> anyhow::Result<()> {
+    let mut db = Database::open(path).await?;
+
+    let names = match collection {
+        Some(name) => vec![name.to_string()],
+        None => match db.execute("SHOW COLLECTIONS").await? {
+            QueryResult::Collections(names) => names,
+            _ => Vec::new(),
+        },
+    };
+
+    let mut reports = Vec::new();
+    for name in &names {
+        reports.extend(db.lint_collection(name).await?);
+    }
+
+    match format {
+        OutputFormat::Json => {
+            let json: Vec<serde_json::Value> = reports
+                .iter()
+                .map(|r| {
+                    let violations: Vec<String> = r.violations.iter().map(|v| v.to_string()).collect();
+                    serde_json::json!({"collection": r.collection, "id": r.id, "violations": violations})
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        }
+        OutputFormat::Table => {
+            for report in &reports {
+                println!("{}/{}:", report.collection, report.id);
+                for violation in &report.violations {
+                    println!("  - {}", violation);
+                }
+            }
+            if reports.is_empty() {
+                println!("No lint violations.");
+            }
+        }
+        OutputFormat::Minimal => {
+            for report in &reports {
+                println!("{}/{}", report.collection, report.id);
+            }
+        }
+    }
+
+    if !reports.is_empty() {
+        anyhow::bail!("{} document(s) failed lint rules", reports.len());
+    }
+
+    Ok(())
+}
+
+async fn reindex_collections(path: &Path, collection: Option<&str>, check: bool, format: OutputFormat) -> anyhow::Result<()> {
+    let mut db = Database::open(path).await?;
+
+    let names = match collection {
+        Some(name) => vec![name.to_string()],
+        None => match db.execute("SHOW COLLECTIONS").await? {
+            QueryResult::Collections(names) => names,
+            _ => Vec::new(),
+        },
+    };
+
+    if !check {
+        let mut rebuilt = Vec::new();
+        for name in &names {
+            rebuilt.extend(db.reindex(name).await?.into_iter().map(|field| (name.clone(), field)));
+        }
+        match format {
+            OutputFormat::Json => {
+                let json: Vec<serde_json::Value> = rebuilt
+                    .iter()
+                    .map(|(collection, field)| serde_json::json!({"collection": collection, "field": field}))
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&json)?);
+            }
+            OutputFormat::Table => println!("Rebuilt {} index(es) in {} collection(s).", rebuilt.len(), names.len()),
+            OutputFormat::Minimal => {
+                for (collection, field) in &rebuilt {
+                    println!("{}.{}", collection, field);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    let mut reports = Vec::new();
+    for name in &names {
+        reports.extend(db.check_indexes(name).await?);
+    }
+
+    match format {
+        OutputFormat::Json => {
+            let json: Vec<serde_json::Value> = reports
+                .iter()
+                .map(|r| {
+                    let drift: Vec<String> = r.drift.iter().map(|d| d.to_string()).collect();
+                    serde_json::json!({"collection": r.collection, "field": r.field, "drift": drift})
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        }
+        OutputFormat::Table => {
+            for report in &reports {
+                println!("{}.{}:", report.collection, report.field);
+                for drift in &report.drift {
+                    println!("  - {}", drift);
+                }
+            }
+            if reports.is_empty() {
+                println!("Indexes match their documents.");
+            }
+        }
+        OutputFormat::Minimal => {
+            for report in &reports {
+                println!("{}.{}", report.collection, report.field);
+            }
+        }
+    }
+
+    if !reports.is_empty() {
+        anyhow::bail!("{} index(es) disagree with their documents; run `mdby reindex` to rebuild them", reports.len());
+    }
+
+    Ok(())
+}
+
+async fn toggle_field(path: &Path, collection: &str, id: &str, field: &str, format: OutputFormat) -> anyhow::Result<()> {
+    let mut db = Database::open(path).await?;
+    let value = db.toggle(collection, id, field).await?;
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::json!({"id": id, field: value})),
+        OutputFormat::Table => println!("{}/{}: {} = {}", collection, id, field, value),
+        OutputFormat::Minimal => println!("{}", value),
+    }
+    Ok(())
+}
+
+async fn tag_document(
+    path: &Path,
+    collection: &str,
+    id: &str,
+    field: &str,
+    changes: &[String],
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    let (mut add, mut remove) = (Vec::new(), Vec::new());
+    for change in changes {
+        // Everything after the first change is taken as a change, options included
+        if change.starts_with("--") {
+            anyhow::bail!("'{}' looks like an option; put options before the tag changes", change);
+        }
+        match change.strip_prefix('-') {
+            Some(tag) => remove.push(tag.to_string()),
+            None => add.push(change.strip_prefix('+').unwrap_or(change).to_string()),
+        }
+    }
+    if add.iter().chain(&remove).any(String::is_empty) {
+        anyhow::bail!("Empty tag; write changes as +tag or -tag");
+    }
+
+    let mut db = Database::open(path).await?;
+    let tags = db.tag(collection, id, field, &add, &remove).await?;
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::json!({"id": id, field: tags})),
+        OutputFormat::Table => println!("{}/{}: {} = [{}]", collection, id, field, tags.join(", ")),
+        OutputFormat::Minimal => {
+            for tag in &tags {
+                println!("{}", tag);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn find_documents(path: &Path, text: &str, limit: usize, edit: bool, format: OutputFormat) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let matches = db.find(text, limit).await?;
+
+    if edit {
+        let Some(best) = matches.first() else {
+            anyhow::bail!("No documents match '{}'", text);
+        };
+        return edit_document(path, &best.collection, &best.id).await;
+    }
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&matches)?),
+        OutputFormat::Table => {
+            if matches.is_empty() {
+                println!("No matches");
+            }
+            let names: Vec<String> = matches.iter().map(|m| format!("{}/{}", m.collection, m.id)).collect();
+            let width = names.iter().map(String::len).max().unwrap_or(0);
+            for (name, m) in names.iter().zip(&matches) {
+                match &m.title {
+                    Some(title) => println!("{:width$}  {}", name, title, width = width),
+                    None => println!("{}", name),
+                }
+            }
+        }
+        OutputFormat::Minimal => {
+            for m in &matches {
+                println!("{}/{}", m.collection, m.id);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Edit a copy of the document's file, then write it back through
+/// [`Database::replace`] so the usual checks run and the change is committed
+async fn edit_document(path: &Path, collection: &str, id: &str) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    mdby::validation::validate_collection_name(collection)?;
+    mdby::validation::validate_document_id(id)?;
+    let file = db.root.join("collections").join(collection).join(format!("{}.md", id));
+    if !file.exists() {
+        return Err(mdby::error::Error::DocumentNotFound { collection: collection.to_string(), id: id.to_string() }.into());
+    }
+
+    let copy = std::env::temp_dir().join(format!("mdby-{}-{}.md", collection, id));
+    let body_file = file.with_file_name(mdby::storage::compression::body_file(&format!("{}.md", id)));
+    if body_file.exists() {
+        // A compressed body is edited along with the frontmatter
+        let doc = Collection::open(collection, &db.root)
+            .get(id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Cannot read document '{}'", id))?;
+        std::fs::write(&copy, doc.render())?;
+    } else {
+        std::fs::copy(&file, &copy)?;
+    }
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // Through the shell, so editors configured with arguments (`code --wait`) work
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("mdby-edit")
+        .arg(&copy)
+        .status()?;
+    if !status.success() {
+        anyhow::bail!("Editor exited with {}; your edits are in {}", status, copy.display());
+    }
+
+    let content = std::fs::read_to_string(&copy)?;
+    let updated = Document::parse(id, &content)
+        .map_err(|e| anyhow::anyhow!("{}; your edits are in {}", e, copy.display()))?;
+    let changed = db
+        .replace(collection, &updated)
+        .await
+        .map_err(|e| anyhow::anyhow!("{}; your edits are in {}", e, copy.display()))?;
+    std::fs::remove_file(&copy)?;
+
+    if changed {
+        println!("Updated {}/{}", collection, id);
+    } else {
+        println!("No changes to {}/{}", collection, id);
+    }
+    Ok(())
+}
+
+async fn apply_defaults(path: &Path, collection: &str, format: OutputFormat) -> anyhow::Result<()> {
+    let db = Database::open(path).await?;
+    let updated = db.apply_defaults(collection).await?;
+
+    match format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::json!({"affected": updated.len(), "ids": updated}));
+        }
+        OutputFormat::Table => {
+            for id in &updated {
+                println!("  {}/{}", collection, id);
+            }
+            println!("Applied defaults to {} document(s)", updated.len());
+        }
+        OutputFormat::Minimal => {
+            for id in &updated {
+                println!("{}", id);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn dedupe_documents(
+    path: &Path,
+    collection: Option<&str>,
+    threshold: f64,
+    merge: bool,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    let mut db = Database::open(path).await?;
+
+    let names = match collection {
+        Some(name) => vec![name.to_string()],
+        None => match db.execute("SHOW COLLECTIONS").await? {
+            QueryResult::Collections(names) => names,
+            _ => Vec::new(),
+        },
+    };
+
+    let mut pairs = Vec::new();
+    for name in &names {
+        pairs.extend(db.find_duplicates(name, threshold).await?);
+    }
+
+    if merge {
+        return merge_duplicates(&db, &pairs).await;
+    }
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&pairs)?),
+        OutputFormat::Table => {
+            for pair in &pairs {
+                println!("{}: {} ~ {} ({})", pair.collection, pair.first, pair.second, similarity_label(pair));
+            }
+            if pairs.is_empty() {
+                println!("No duplicates found.");
+            } else {
+                println!("{} candidate pair(s). Run with --merge to resolve them.", pairs.len());
+            }
+        }
+        OutputFormat::Minimal => {
+            for pair in &pairs {
+                println!("{}/{} {}/{}", pair.collection, pair.first, pair.collection, pair.second);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Ask which document of each pair to keep, and merge the other into it
+async fn merge_duplicates(db: &Database, pairs: &[mdby::dedupe::DuplicatePair]) -> anyhow::Result<()> {
+    use std::io::{self, BufRead, Write};
+
+    let stdin = io::stdin();
+    let mut removed: HashSet<(String, String)> = HashSet::new();
+    let mut merged = 0;
+
+    for pair in pairs {
+        let gone = |id: &str| removed.contains(&(pair.collection.clone(), id.to_string()));
+        if gone(&pair.first) || gone(&pair.second) {
+            continue;
+        }
+
+        println!();
+        println!("{}: {} ~ {} ({})", pair.collection, pair.first, pair.second, similarity_label(pair));
+        print_differences(db, pair).await?;
+
+        let choice = loop {
+            print!("Keep [1] {}, [2] {}, [s]kip, [q]uit: ", pair.first, pair.second);
+            io::stdout().flush()?;
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                break "q".to_string();
+            }
+            let line = line.trim().to_lowercase();
+            if ["1", "2", "s", "q"].contains(&line.as_str()) {
+                break line;
+            }
+        };
+
+        let (keep, remove) = match choice.as_str() {
+            "1" => (&pair.first, &pair.second),
+            "2" => (&pair.second, &pair.first),
+            "s" => continue,
+            _ => break,
+        };
+        db.merge_duplicates(&pair.collection, keep, remove).await?;
+        println!("Merged {} into {}.", remove, keep);
+        removed.insert((pair.collection.clone(), remove.clone()));
+        merged += 1;
+    }
+
+    println!();
+    println!("Merged {} pair(s).", merged);
+    Ok(())
+}
+
+/// Print the fields that differ between the two documents of a pair
+async fn print_differences(db: &Database, pair: &mdby::dedupe::DuplicatePair) -> anyhow::Result<()> {
+    let collection = mdby::Collection::open(&pair.collection, &db.root);
+    let (Some(first), Some(second)) = (collection.get(&pair.first).await?, collection.get(&pair.second).await?) else {
+        return Ok(());
+    };
+
+    let names: BTreeSet<&String> = first.fields.keys().chain(second.fields.keys()).collect();
+    for name in names {
+        let (a, b) = (first.fields.get(name), second.fields.get(name));
+        if a != b {
+            let show = |v: Option<&mdby::storage::document::Value>| {
+                v.map(|v| serde_json::to_string(v).unwrap_or_default()).unwrap_or_else(|| "-".into())
+            };
+            println!("  {}: {} | {}", name, show(a), show(b));
+        }
+    }
+    if first.body.trim() != second.body.trim() {
+        println!("  body: {} | {} characters", first.body.trim().len(), second.body.trim().len());
+    }
+    Ok(())
+}
+
+fn similarity_label(pair: &mdby::dedupe::DuplicatePair) -> String {
+    if pair.exact {
+        "exact".to_string()
+    } else {
+        format!("{:.0}% similar", pair.similarity * 100.0)
+    }
 }
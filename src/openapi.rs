@@ -0,0 +1,368 @@
+//! OpenAPI description of the HTTP API (`GET /openapi.json`)
+//!
+//! [`document`] describes the routes `mdby serve` answers for a particular
+//! database, so clients can be generated against it: `POST /query` and
+//! `POST /batch`, then the `/collections/{name}` routes of each collection,
+//! with the shape of its documents taken from the field definitions of its
+//! schema. Fields a schema doesn't declare are allowed, as they are in the
+//! documents themselves.
+//!
+//! The description follows the handle's execution policy: collections it
+//! denies are left out, and so are the methods of statements it doesn't
+//! allow (no `PUT`, `PATCH` or `DELETE` under a read-only policy).
+
+use serde_json::{json, Map, Value};
+
+use crate::policy::StatementKind;
+use crate::schema::{FieldDef, FieldType, Schema};
+use crate::Database;
+
+/// Version of the OpenAPI specification the document follows
+pub const OPENAPI_VERSION: &str = "3.0.3";
+
+/// Component schema of error responses; collection names can't have a `.`,
+/// so neither this nor [`fields_component`] can be a collection's
+const ERROR: &str = "mdby.Error";
+
+/// Component schema of the fields written to a collection's documents
+fn fields_component(name: &str) -> String {
+    format!("{}.fields", name)
+}
+
+/// The OpenAPI document of the HTTP API serving `db`
+pub async fn document(db: &Database) -> anyhow::Result<Value> {
+    let mut names = db.store.collections().await?;
+    names.extend(db.schema.list().map(|schema| schema.name.clone()));
+    names.sort();
+    names.dedup();
+    names.retain(|name| db.policy().allows_collection(name));
+
+    let mut paths = Map::new();
+    paths.insert("/query".to_string(), query_path());
+    paths.insert("/batch".to_string(), batch_path());
+    let mut schemas = Map::new();
+    schemas.insert(ERROR.to_string(), error_schema());
+    for name in &names {
+        let schema = db.schema.get(name);
+        schemas.insert(name.clone(), document_schema(name, schema, true));
+        schemas.insert(fields_component(name), document_schema(name, schema, false));
+        paths.insert(format!("/collections/{}", name), collection_path(name));
+        paths.insert(format!("/collections/{}/{{id}}", name), document_path(db, name));
+    }
+    paths.insert("/views/{name}".to_string(), view_path());
+
+    Ok(json!({
+        "openapi": OPENAPI_VERSION,
+        "info": {
+            "title": db.config.name.as_deref().unwrap_or("MDBY"),
+            "description": db.config.description,
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {"schemas": schemas},
+    }))
+}
+
+/// JSON Schema of a field's values
+pub fn field_schema(field: &FieldDef) -> Value {
+    let mut schema = type_schema(&field.field_type);
+    if let Some(description) = &field.description {
+        schema["description"] = json!(description);
+    }
+    if let Some(default) = field.default.as_ref().and_then(|default| serde_json::to_value(default).ok()) {
+        schema["default"] = default;
+    }
+    if !field.required {
+        schema["nullable"] = json!(true);
+    }
+    schema
+}
+
+fn type_schema(field_type: &FieldType) -> Value {
+    match field_type {
+        FieldType::String => json!({"type": "string"}),
+        FieldType::Int => json!({"type": "integer", "format": "int64"}),
+        FieldType::Float => json!({"type": "number", "format": "double"}),
+        FieldType::Bool => json!({"type": "boolean"}),
+        FieldType::Date => json!({"type": "string", "format": "date"}),
+        FieldType::DateTime => json!({"type": "string", "format": "date-time"}),
+        FieldType::Array(inner) => json!({"type": "array", "items": type_schema(inner)}),
+        FieldType::Object => json!({"type": "object"}),
+        FieldType::Ref(target) => json!({"type": "string", "description": format!("ID of a document in {}", target)}),
+    }
+}
+
+/// A collection's documents as returned (`id` and the required fields
+/// present), or as written (`{name}.fields`: any of the fields)
+fn document_schema(name: &str, schema: Option<&Schema>, returned: bool) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    if returned {
+        properties.insert("id".to_string(), json!({"type": "string"}));
+        required.push("id".to_string());
+    }
+    for (field, def) in schema.iter().flat_map(|schema| &schema.fields) {
+        properties.insert(field.clone(), field_schema(def));
+        if returned && def.required {
+            required.push(field.clone());
+        }
+    }
+    properties.insert("_body".to_string(), json!({"type": "string", "description": "Markdown body"}));
+
+    let description = match returned {
+        true => schema.and_then(|schema| schema.description.clone()).unwrap_or_else(|| format!("A document in {}", name)),
+        false => format!("Fields to write to a document in {}; PUT needs the required ones", name),
+    };
+    let mut object = json!({
+        "type": "object",
+        "description": description,
+        "properties": properties,
+        "additionalProperties": true,
+    });
+    if !required.is_empty() {
+        object["required"] = json!(required);
+    }
+    object
+}
+
+fn error_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "error": {"type": "string"},
+            "hint": {"type": "string", "nullable": true},
+        },
+        "required": ["error"],
+    })
+}
+
+/// A reference to a component schema
+fn component(name: &str) -> Value {
+    json!({"$ref": format!("#/components/schemas/{}", name)})
+}
+
+/// A JSON response with `schema`
+fn json_response(description: &str, schema: Value) -> Value {
+    json!({"description": description, "content": {"application/json": {"schema": schema}}})
+}
+
+/// `responses`, and those of the error `statuses`
+fn errors(mut responses: Map<String, Value>, statuses: &[(&str, &str)]) -> Value {
+    for (status, description) in statuses {
+        responses.insert(status.to_string(), json_response(description, component(ERROR)));
+    }
+    Value::Object(responses)
+}
+
+/// `200` with the results of `schema` as JSON, CSV or an HTML table
+fn negotiated(description: &str, schema: Value) -> Map<String, Value> {
+    let mut responses = Map::new();
+    responses.insert(
+        "200".to_string(),
+        json!({
+            "description": description,
+            "content": {
+                "application/json": {"schema": schema},
+                "text/csv": {"schema": {"type": "string"}},
+                "text/html": {"schema": {"type": "string"}},
+            },
+        }),
+    );
+    responses
+}
+
+/// The header a request running statements needs unless its body is JSON
+fn query_header() -> Value {
+    json!({
+        "name": "MDBY-Query",
+        "in": "header",
+        "required": false,
+        "description": "Any value; required for a body that isn't `application/json`",
+        "schema": {"type": "string"},
+    })
+}
+
+fn query_path() -> Value {
+    let mut result = negotiated("The statement's result", json!({}));
+    result["200"]["headers"] = json!({
+        "MDBY-Result": {
+            "description": "The kind of result (`documents`, `affected`, `collections`, ...)",
+            "schema": {"type": "string"},
+        },
+    });
+    json!({
+        "post": {
+            "summary": "Run an MDQL statement",
+            "parameters": [query_header()],
+            "requestBody": {
+                "required": true,
+                "content": {
+                    "text/plain": {"schema": {"type": "string"}},
+                    "application/json": {
+                        "schema": {"type": "object", "properties": {"query": {"type": "string"}}, "required": ["query"]},
+                    },
+                },
+            },
+            "responses": errors(result, &[
+                ("400", "Invalid statement"),
+                ("403", "Denied by the execution policy"),
+                ("404", "No such collection or document"),
+                ("415", "Neither JSON nor sent with an MDBY-Query header"),
+            ]),
+        },
+    })
+}
+
+fn batch_path() -> Value {
+    let mut responses = Map::new();
+    responses.insert("200".to_string(), json_response("Each statement's result", json!({"type": "object"})));
+    json!({
+        "post": {
+            "summary": "Run MDQL statements as one transaction",
+            "parameters": [query_header()],
+            "requestBody": {
+                "required": true,
+                "content": {"application/json": {"schema": {"oneOf": [
+                    {"type": "array", "items": {"type": "string"}},
+                    {"type": "object", "properties": {"statements": {"type": "array", "items": {"type": "string"}}}, "required": ["statements"]},
+                    {"type": "object", "properties": {"script": {"type": "string"}}, "required": ["script"]},
+                ]}}},
+            },
+            "responses": errors(responses, &[
+                ("400", "A statement failed, and none were kept"),
+                ("415", "Neither JSON nor sent with an MDBY-Query header"),
+            ]),
+        },
+    })
+}
+
+fn collection_path(name: &str) -> Value {
+    let parameter = |param: &str, description: &str, schema: Value| {
+        json!({"name": param, "in": "query", "required": false, "description": description, "schema": schema})
+    };
+    json!({
+        "get": {
+            "summary": format!("List documents in {}", name),
+            "parameters": [
+                parameter("where", "MDQL condition, as in a WHERE clause", json!({"type": "string"})),
+                parameter("order_by", "MDQL ORDER BY list (`priority DESC, title`)", json!({"type": "string"})),
+                parameter("limit", "Most documents to return", json!({"type": "integer", "minimum": 0})),
+                parameter("offset", "Documents to skip", json!({"type": "integer", "minimum": 0})),
+            ],
+            "responses": errors(
+                negotiated("The documents", json!({"type": "array", "items": component(name)})),
+                &[("400", "Invalid parameters"), ("404", "No such collection")],
+            ),
+        },
+    })
+}
+
+fn document_path(db: &Database, name: &str) -> Value {
+    let policy = db.policy();
+    let etag = json!({"ETag": {"description": "The document's version", "schema": {"type": "string"}}});
+    let if_match = json!({
+        "name": "If-Match",
+        "in": "header",
+        "required": false,
+        "description": "Only write if the document is still at one of these versions (`*`: any)",
+        "schema": {"type": "string"},
+    });
+    let written = |summary: String| {
+        let mut responses = Map::new();
+        responses.insert(
+            "200".to_string(),
+            json!({
+                "description": "The document as written",
+                "headers": etag,
+                "content": {"application/json": {"schema": component(name)}},
+            }),
+        );
+        json!({
+            "summary": summary,
+            "parameters": [if_match],
+            "requestBody": {
+                "required": true,
+                "content": {"application/json": {"schema": component(&fields_component(name))}},
+            },
+            "responses": errors(responses, &[
+                ("400", "Invalid fields"),
+                ("403", "Denied by the execution policy"),
+                ("404", "No such document"),
+                ("412", "The document changed since the version in If-Match"),
+            ]),
+        })
+    };
+
+    let mut read = negotiated("The document", component(name));
+    read["200"]["headers"] = etag.clone();
+    let mut operations = Map::new();
+    operations.insert(
+        "get".to_string(),
+        json!({
+            "summary": format!("Read a document in {}", name),
+            "responses": errors(read, &[("404", "No such document")]),
+        }),
+    );
+    if policy.allows(StatementKind::Insert) && policy.allows(StatementKind::Update) {
+        operations.insert("put".to_string(), written(format!("Create or update a document in {}", name)));
+    }
+    if policy.allows(StatementKind::Update) {
+        operations.insert("patch".to_string(), written(format!("Set fields of a document in {}", name)));
+    }
+    if policy.allows(StatementKind::Delete) {
+        let mut responses = Map::new();
+        responses.insert("200".to_string(), json_response("Deleted", json!({"type": "object"})));
+        operations.insert(
+            "delete".to_string(),
+            json!({
+                "summary": format!("Delete a document in {}", name),
+                "parameters": [if_match],
+                "responses": errors(responses, &[
+                    ("403", "Denied by the execution policy"),
+                    ("404", "No such document"),
+                    ("412", "The document changed since the version in If-Match"),
+                ]),
+            }),
+        );
+    }
+    operations.insert(
+        "parameters".to_string(),
+        json!([{"name": "id", "in": "path", "required": true, "schema": {"type": "string"}}]),
+    );
+    Value::Object(operations)
+}
+
+fn view_path() -> Value {
+    json!({
+        "get": {
+            "summary": "Read a view's rendered HTML",
+            "parameters": [{"name": "name", "in": "path", "required": true, "schema": {"type": "string"}}],
+            "responses": {
+                "200": {"description": "The view", "content": {"text/html": {"schema": {"type": "string"}}}},
+                "403": json_response("The view's query is denied by the execution policy", component(ERROR)),
+                "404": json_response("No such view", component(ERROR)),
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_schema() {
+        let field = |field_type: FieldType, required: bool| FieldDef { field_type, required, ..Default::default() };
+        assert_eq!(field_schema(&field(FieldType::Int, true)), json!({"type": "integer", "format": "int64"}));
+        assert_eq!(
+            field_schema(&field(FieldType::Array(Box::new(FieldType::DateTime)), false)),
+            json!({"type": "array", "items": {"type": "string", "format": "date-time"}, "nullable": true})
+        );
+        let mut priority = field(FieldType::Int, false);
+        priority.default = Some(serde_yaml::Value::from(3));
+        priority.description = Some("1 is most urgent".to_string());
+        let schema = field_schema(&priority);
+        assert_eq!((schema["default"].clone(), schema["description"].clone()), (json!(3), json!("1 is most urgent")));
+        assert_eq!(field_schema(&field(FieldType::Ref("users".into()), true))["type"], "string");
+    }
+}
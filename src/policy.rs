@@ -0,0 +1,264 @@
+//! Statement-level access control
+//!
+//! An [`ExecutionPolicy`] attached to a [`crate::Database`] is checked before
+//! each MDQL statement runs, so an application can pass user-supplied queries
+//! through without exposing writes or other collections. A statement must be
+//! of an allowed kind, and every collection it names (including JOINed ones,
+//! those `REVERSE_REF` reads and the source of a view) must be allowed.
+//!
+//! A policy also refuses `BODY FROM FILE`, which reads files on the machine
+//! running the statement, unless it [allows it](ExecutionPolicy::allow_body_files).
+//! A handle without a policy set ([`ExecutionPolicy::trusted`]) allows it.
+//!
+//! ```
+//! use mdby::policy::{ExecutionPolicy, StatementKind};
+//!
+//! // Queries only, and never the `secrets` collection
+//! let policy = ExecutionPolicy::read_only().deny_collection("secrets");
+//!
+//! // Everything except dropping things
+//! let policy = ExecutionPolicy::default()
+//!     .deny(StatementKind::DropCollection)
+//!     .deny(StatementKind::DropView);
+//! ```
+
+use std::collections::HashSet;
+
+use mdql::Statement;
+
+/// Kind of MDQL statement, for allowing or denying
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatementKind {
+    Select,
+    Insert,
+    Update,
+    Delete,
+    CreateCollection,
+    AlterCollection,
+    CreateView,
+    DropCollection,
+    DropView,
+    /// SHOW COLLECTIONS, SHOW VIEWS and SHOW CREATE
+    Show,
+    Describe,
+}
+
+impl StatementKind {
+    /// Kind of a parsed statement
+    pub fn of(stmt: &Statement) -> Self {
+        match stmt {
+            Statement::Select(_) | Statement::Explain(_) => StatementKind::Select,
+            Statement::Insert(_) => StatementKind::Insert,
+            Statement::Update(_) => StatementKind::Update,
+            Statement::Delete(_) => StatementKind::Delete,
+            Statement::CreateCollection(_) => StatementKind::CreateCollection,
+            Statement::AlterCollection(_) => StatementKind::AlterCollection,
+            Statement::CreateView(_) => StatementKind::CreateView,
+            Statement::DropCollection(_) => StatementKind::DropCollection,
+            Statement::DropView(_) => StatementKind::DropView,
+            Statement::ShowCollections
+            | Statement::ShowViews
+            | Statement::ShowCreateCollection(_)
+            | Statement::ShowCreateView(_) => StatementKind::Show,
+            Statement::Describe(_) => StatementKind::Describe,
+        }
+    }
+
+    /// Keyword used in error messages
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatementKind::Select => "SELECT",
+            StatementKind::Insert => "INSERT",
+            StatementKind::Update => "UPDATE",
+            StatementKind::Delete => "DELETE",
+            StatementKind::CreateCollection => "CREATE COLLECTION",
+            StatementKind::AlterCollection => "ALTER COLLECTION",
+            StatementKind::CreateView => "CREATE VIEW",
+            StatementKind::DropCollection => "DROP COLLECTION",
+            StatementKind::DropView => "DROP VIEW",
+            StatementKind::Show => "SHOW",
+            StatementKind::Describe => "DESCRIBE",
+        }
+    }
+}
+
+/// Which statements a database handle may execute
+///
+/// The default allows every statement and collection, but not `BODY FROM FILE`.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionPolicy {
+    /// Allowed statement kinds; `None` allows every kind not denied
+    kinds: Option<HashSet<StatementKind>>,
+    denied_kinds: HashSet<StatementKind>,
+    /// Allowed collections; `None` allows every collection not denied
+    collections: Option<HashSet<String>>,
+    denied_collections: HashSet<String>,
+    /// Whether `INSERT ... BODY FROM FILE` may read files
+    body_files: bool,
+}
+
+impl ExecutionPolicy {
+    /// Everything, including `BODY FROM FILE`: the policy of a handle none
+    /// was set on
+    pub fn trusted() -> Self {
+        Self::default().allow_body_files()
+    }
+
+    /// Only SELECT, SHOW and DESCRIBE
+    pub fn read_only() -> Self {
+        Self::default().only([StatementKind::Select, StatementKind::Show, StatementKind::Describe])
+    }
+
+    /// Allow only these statement kinds
+    pub fn only(mut self, kinds: impl IntoIterator<Item = StatementKind>) -> Self {
+        self.kinds = Some(kinds.into_iter().collect());
+        self
+    }
+
+    /// Deny a statement kind
+    pub fn deny(mut self, kind: StatementKind) -> Self {
+        self.denied_kinds.insert(kind);
+        self
+    }
+
+    /// Allow only these collections
+    pub fn only_collections<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.collections = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Deny a collection
+    pub fn deny_collection(mut self, name: impl Into<String>) -> Self {
+        self.denied_collections.insert(name.into());
+        self
+    }
+
+    /// Allow `INSERT ... BODY FROM FILE`, reading files under the database root
+    pub fn allow_body_files(mut self) -> Self {
+        self.body_files = true;
+        self
+    }
+
+    /// Deny `INSERT ... BODY FROM FILE`
+    pub fn deny_body_files(mut self) -> Self {
+        self.body_files = false;
+        self
+    }
+
+    /// Whether a statement kind may run
+    pub fn allows(&self, kind: StatementKind) -> bool {
+        !self.denied_kinds.contains(&kind) && self.kinds.as_ref().is_none_or(|kinds| kinds.contains(&kind))
+    }
+
+    /// Whether a collection may be used
+    pub fn allows_collection(&self, name: &str) -> bool {
+        !self.denied_collections.contains(name)
+            && self.collections.as_ref().is_none_or(|names| names.contains(name))
+    }
+
+    /// Check a statement against the policy
+    pub fn check(&self, stmt: &Statement) -> crate::Result<()> {
+        let kind = StatementKind::of(stmt);
+        if !self.allows(kind) {
+            return Err(crate::Error::StatementDenied { statement: kind.name() });
+        }
+        // ON CONFLICT UPDATE can change existing documents, so it needs UPDATE too
+        if matches!(stmt, Statement::Insert(i) if i.on_conflict == Some(mdql::OnConflict::Update))
+            && !self.allows(StatementKind::Update)
+        {
+            return Err(crate::Error::StatementDenied { statement: "INSERT ... ON CONFLICT UPDATE" });
+        }
+        if matches!(stmt, Statement::Insert(i) if matches!(i.body, Some(mdql::InsertBody::File(_)))) && !self.body_files {
+            return Err(crate::Error::StatementDenied { statement: "INSERT ... BODY FROM FILE" });
+        }
+        for collection in collections(stmt) {
+            if !self.allows_collection(&collection) {
+                return Err(crate::Error::CollectionDenied { statement: kind.name(), collection });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collections a statement reads or writes
+fn collections(stmt: &Statement) -> Vec<String> {
+    match stmt {
+        Statement::Select(s) => select_collections(s),
+        Statement::Explain(s) => select_collections(s),
+        Statement::CreateView(v) => select_collections(&v.query),
+        Statement::Insert(i) => vec![i.into.clone()],
+        Statement::Update(u) => vec![u.collection.clone()],
+        Statement::Delete(d) => vec![d.from.clone()],
+        Statement::CreateCollection(c) => vec![c.name.clone()],
+        Statement::AlterCollection(a) => vec![a.name.clone()],
+        Statement::DropCollection(name) | Statement::ShowCreateCollection(name) | Statement::Describe(name) => {
+            vec![name.clone()]
+        }
+        Statement::DropView(_) | Statement::ShowCollections | Statement::ShowViews | Statement::ShowCreateView(_) => {
+            Vec::new()
+        }
+    }
+}
+
+/// A view's own query is checked when it's read, so `view:name` sources
+/// aren't collections here
+fn select_collections(select: &mdql::SelectStmt) -> Vec<String> {
+    std::iter::once(&select.from)
+        .filter(|from| mdql::view_source(from).is_none())
+        .chain(select.joins.iter().map(|join| &join.collection))
+        .cloned()
+        .chain(crate::query::reverse_ref_calls(select).into_iter().map(|call| call.collection))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(policy: &ExecutionPolicy, query: &str) -> crate::Result<()> {
+        policy.check(&mdql::parse(query).unwrap())
+    }
+
+    #[test]
+    fn test_read_only_policy() {
+        let policy = ExecutionPolicy::read_only();
+        assert!(check(&policy, "SELECT * FROM todos").is_ok());
+        assert!(check(&policy, "SHOW COLLECTIONS").is_ok());
+        let err = check(&policy, "DROP COLLECTION todos").unwrap_err();
+        assert_eq!(err.to_string(), "DROP COLLECTION is not allowed by the execution policy");
+    }
+
+    #[test]
+    fn test_upsert_needs_update() {
+        let policy = ExecutionPolicy::default().only([StatementKind::Insert]);
+        assert!(check(&policy, "INSERT INTO todos (id) VALUES ('a') ON CONFLICT DO NOTHING").is_ok());
+        assert!(check(&policy, "UPSERT INTO todos (id) VALUES ('a')").is_err());
+    }
+
+    #[test]
+    fn test_body_files_need_allowing() {
+        let query = "INSERT INTO notes (id) VALUES ('a') BODY FROM FILE 'draft.md'";
+        let err = check(&ExecutionPolicy::default(), query).unwrap_err();
+        assert_eq!(err.to_string(), "INSERT ... BODY FROM FILE is not allowed by the execution policy");
+        assert!(check(&ExecutionPolicy::trusted(), query).is_ok());
+        assert!(check(&ExecutionPolicy::trusted().deny_body_files(), query).is_err());
+        assert!(check(&ExecutionPolicy::default(), "INSERT INTO notes (id) VALUES ('a') BODY 'inline'").is_ok());
+    }
+
+    #[test]
+    fn test_collection_rules_cover_joins_and_views() {
+        let policy = ExecutionPolicy::default().deny_collection("secrets");
+        assert!(check(&policy, "UPDATE todos SET done = true").is_ok());
+        assert!(check(&policy, "SELECT * FROM todos AS t JOIN secrets AS s ON t.id = s.id").is_err());
+        assert!(check(&policy, "CREATE VIEW leak AS SELECT * FROM secrets").is_err());
+        assert!(check(&policy, "SELECT REVERSE_REF('secrets', 'owner') AS leak FROM notes").is_err());
+        assert!(check(&policy, "SELECT * FROM notes WHERE LENGTH(REVERSE_REF('secrets', 'owner')) > 0").is_err());
+        assert!(check(&policy, "SELECT REVERSE_REF('tasks', 'owner') FROM notes").is_ok());
+
+        let policy = ExecutionPolicy::default().only_collections(["todos"]).deny(StatementKind::Delete);
+        assert!(check(&policy, "INSERT INTO todos (id) VALUES ('a')").is_ok());
+        assert!(check(&policy, "INSERT INTO notes (id) VALUES ('a')").is_err());
+        assert!(check(&policy, "DELETE FROM todos").is_err());
+        assert!(check(&policy, "SELECT * FROM view:open").is_ok());
+    }
+}
@@ -0,0 +1,252 @@
+//! Aggregates and grouping (`GROUP BY`, `HAVING`)
+//!
+//! Documents are grouped by the values of the GROUP BY columns, in the order
+//! each group is first seen; without GROUP BY, all documents form one group.
+//! Each group becomes a result row with the selected columns, aggregates
+//! being named by their alias or as written (`COUNT(*)`). A row's ID is its
+//! group's values joined with `, `, or the collection name when nothing is
+//! grouped.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use mdql::{AggregateFunction, Column, Expr, SelectStmt};
+
+use super::executor::{compare_values, expr_references};
+use super::{filter, join};
+use crate::storage::document::{Document, Value};
+
+/// Whether a SELECT groups or aggregates its documents
+pub(crate) fn is_aggregate(stmt: &SelectStmt) -> bool {
+    !stmt.group_by.is_empty()
+        || stmt.having.is_some()
+        || stmt.columns.iter().any(|column| matches!(column, Column::Expr { expr, .. } if contains_aggregate(expr)))
+}
+
+/// Name of the only column of a SELECT that counts rows and selects nothing
+/// else (`SELECT COUNT(*) AS n FROM todos`), which returns the count alone
+/// as a [`QueryResult::Scalar`](crate::QueryResult::Scalar)
+pub(crate) fn lone_count(stmt: &SelectStmt) -> Option<String> {
+    if !stmt.group_by.is_empty() {
+        return None;
+    }
+    match stmt.columns.as_slice() {
+        [Column::Expr { expr, alias }]
+            if matches!(expr.as_ref(), Expr::Aggregate { function: AggregateFunction::Count, arg: None }) =>
+        {
+            Some(alias.clone().unwrap_or_else(|| expr.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Whether an expression uses an aggregate function
+pub(crate) fn contains_aggregate(expr: &Expr) -> bool {
+    expr_references(expr, &|e| matches!(e, Expr::Aggregate { .. }))
+}
+
+/// Group documents and compute one row per group
+pub(crate) fn group(docs: Vec<Document>, stmt: &SelectStmt) -> anyhow::Result<Vec<Document>> {
+    for column in &stmt.columns {
+        let name = match column {
+            Column::Star => anyhow::bail!("SELECT * can't be used with GROUP BY or aggregates; list the columns"),
+            Column::Field(name) => name.clone(),
+            Column::Qualified { table, field } => format!("{}.{}", table, field),
+            Column::Special(special) => special.to_string(),
+            Column::Expr { expr, .. } if contains_aggregate(expr) => continue,
+            Column::Expr { expr, .. } => expr.to_string(),
+        };
+        if !stmt.group_by.contains(&name) {
+            anyhow::bail!("Column '{}' must appear in GROUP BY or be used in an aggregate function", name);
+        }
+    }
+
+    // Every aggregate the query computes, found once per query
+    let found = RefCell::new(Vec::new());
+    let expressions = stmt
+        .columns
+        .iter()
+        .filter_map(|column| match column {
+            Column::Expr { expr, .. } => Some(expr.as_ref()),
+            _ => None,
+        })
+        .chain(stmt.having.as_ref());
+    for expr in expressions {
+        expr_references(expr, &|e| {
+            if matches!(e, Expr::Aggregate { .. }) {
+                found.borrow_mut().push(e.clone());
+            }
+            false
+        });
+    }
+    let aggregates = found.into_inner();
+
+    let mut groups: Vec<(Vec<Value>, Vec<Document>)> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for doc in docs {
+        let values: Vec<Value> = stmt
+            .group_by
+            .iter()
+            .map(|column| column_value(&doc, column).unwrap_or(Value::Null))
+            .collect();
+        let key = serde_json::to_string(&values)?;
+        match index.get(&key) {
+            Some(&i) => groups[i].1.push(doc),
+            None => {
+                index.insert(key, groups.len());
+                groups.push((values, vec![doc]));
+            }
+        }
+    }
+    if stmt.group_by.is_empty() && groups.is_empty() {
+        groups.push((Vec::new(), Vec::new()));
+    }
+
+    let mut rows = Vec::new();
+    for (values, docs) in groups {
+        let id = if stmt.group_by.is_empty() {
+            stmt.from.clone()
+        } else {
+            values.iter().map(display).collect::<Vec<_>>().join(", ")
+        };
+
+        // The group's values and aggregates, which HAVING and the columns read;
+        // aggregates are stored under their MDQL text
+        let mut group = Document::new(&id);
+        for (column, value) in stmt.group_by.iter().zip(&values) {
+            group.fields.insert(column.clone(), value.clone());
+        }
+        for expr in &aggregates {
+            if let Expr::Aggregate { function, arg } = expr {
+                group.fields.insert(expr.to_string(), aggregate(*function, arg.as_deref(), &docs));
+            }
+        }
+
+        if let Some(having) = &stmt.having {
+            if !filter::evaluate(having, &group) {
+                continue;
+            }
+        }
+
+        let mut row = Document::new(&id);
+        for column in &stmt.columns {
+            let (name, value) = match column {
+                Column::Field(name) => (name.clone(), group.fields.get(name).cloned()),
+                Column::Qualified { table, field } => {
+                    let path = format!("{}.{}", table, field);
+                    let value = group.fields.get(&path).cloned();
+                    // A path into an object field is named as written
+                    (if join::is_table(stmt, table) { field.clone() } else { path }, value)
+                }
+                Column::Special(special) => (special.to_string(), group.fields.get(&special.to_string()).cloned()),
+                Column::Expr { expr, alias } => {
+                    (alias.clone().unwrap_or_else(|| expr.to_string()), filter::value(expr, &group))
+                }
+                Column::Star => continue,
+            };
+            row.fields.insert(name, value.unwrap_or(Value::Null));
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Value of a GROUP BY column (`field` or `table.field`)
+fn column_value(doc: &Document, column: &str) -> Option<Value> {
+    match column.split_once('.') {
+        Some((table, field)) => join::qualified_value(doc, table, field),
+        None => doc.get_field(column.strip_prefix('@').unwrap_or(column)),
+    }
+}
+
+/// Compute an aggregate over a group's documents; NULLs are skipped
+fn aggregate(function: AggregateFunction, arg: Option<&Expr>, docs: &[Document]) -> Value {
+    let Some(arg) = arg else {
+        return Value::Int(docs.len() as i64);
+    };
+    let values: Vec<Value> = docs.iter().filter_map(|doc| filter::value(arg, doc)).collect();
+    let numbers: Vec<f64> = values
+        .iter()
+        .filter_map(|value| match value {
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        })
+        .collect();
+
+    match function {
+        AggregateFunction::Count => Value::Int(values.len() as i64),
+        AggregateFunction::Sum if numbers.is_empty() => Value::Null,
+        AggregateFunction::Sum if values.iter().all(|value| matches!(value, Value::Int(_))) => {
+            Value::Int(values.iter().filter_map(Value::as_i64).sum())
+        }
+        AggregateFunction::Sum => Value::Float(numbers.iter().sum()),
+        AggregateFunction::Avg if numbers.is_empty() => Value::Null,
+        AggregateFunction::Avg => Value::Float(numbers.iter().sum::<f64>() / numbers.len() as f64),
+        AggregateFunction::Min => {
+            values.into_iter().min_by(|a, b| compare_values(Some(a), Some(b))).unwrap_or(Value::Null)
+        }
+        AggregateFunction::Max => {
+            values.into_iter().max_by(|a, b| compare_values(Some(a), Some(b))).unwrap_or(Value::Null)
+        }
+    }
+}
+
+/// A group value as part of a row ID
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => "null".to_string(),
+        other => serde_json::to_string(other).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(query: &str) -> SelectStmt {
+        match mdql::parse(query).unwrap() {
+            mdql::Statement::Select(select) => select,
+            _ => unreachable!(),
+        }
+    }
+
+    fn task(id: &str, status: &str, points: Option<i64>) -> Document {
+        let mut doc = Document::new(id);
+        doc.set("status", status);
+        if let Some(points) = points {
+            doc.set("points", points);
+        }
+        doc
+    }
+
+    #[test]
+    fn test_group_and_aggregate() {
+        let docs = vec![
+            task("a", "open", Some(3)),
+            task("b", "done", Some(5)),
+            task("c", "open", None),
+            task("d", "open", Some(1)),
+        ];
+
+        let stmt = select("SELECT status, COUNT(*) AS n, COUNT(points), SUM(points), MAX(points) FROM tasks GROUP BY status");
+        let rows = group(docs.clone(), &stmt).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].id, "open");
+        assert_eq!(rows[0].fields.get("n"), Some(&Value::Int(3)));
+        assert_eq!(rows[0].fields.get("COUNT(points)"), Some(&Value::Int(2)));
+        assert_eq!(rows[0].fields.get("SUM(points)"), Some(&Value::Int(4)));
+        assert_eq!(rows[0].fields.get("MAX(points)"), Some(&Value::Int(3)));
+
+        let rows = group(docs.clone(), &select("SELECT AVG(points) FROM tasks HAVING COUNT(*) > 3")).unwrap();
+        assert_eq!(rows[0].id, "tasks");
+        assert_eq!(rows[0].fields.get("AVG(points)"), Some(&Value::Float(3.0)));
+        assert!(group(docs.clone(), &select("SELECT COUNT(*) FROM tasks HAVING COUNT(*) > 4")).unwrap().is_empty());
+
+        assert!(group(docs, &select("SELECT status, points FROM tasks GROUP BY status")).is_err());
+        let rows = group(Vec::new(), &select("SELECT COUNT(*), SUM(points) FROM tasks")).unwrap();
+        assert_eq!(rows[0].fields.get("COUNT(*)"), Some(&Value::Int(0)));
+        assert_eq!(rows[0].fields.get("SUM(points)"), Some(&Value::Null));
+    }
+}
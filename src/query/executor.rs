@@ -1,55 +1,492 @@
 //! Query execution engine
 
-use crate::storage::collection::Collection;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use futures_util::StreamExt;
+
 use crate::storage::document::{Document, Value};
-use crate::validation::{validate_collection_name, validate_document_id, validate_view_name, validate_template_name};
+use crate::storage::index::IndexManager;
+use crate::storage::snapshot::Snapshot;
+use crate::storage::store::Layout;
+use crate::storage::tree::TreeReader;
+use crate::embeddings;
+use crate::schema::{ids, ValidationError};
+use crate::slug::{self, SlugOptions};
+use crate::system;
+use crate::views::delivery::Schedule;
+use crate::validation::{validate_collection_name, validate_document_id, validate_relative_path, validate_view_name, validate_template_name};
 use crate::{Database, QueryResult};
 use mdql::{
-    Column, CreateCollectionStmt, CreateViewStmt, DeleteStmt, InsertStmt,
-    Literal, OrderDirection, SelectStmt, Statement, UpdateStmt,
+    AlterAction, AlterCollectionStmt, Column, ColumnDef, CreateCollectionStmt, CreateViewStmt, DeleteStmt, Expr, InsertBody, InsertStmt,
+    Literal, OnConflict, OrderDirection, SelectStmt, SpecialField, Statement, UpdateStmt,
 };
 
-use super::filter;
+use super::plan::{self, Access};
+use super::{aggregate, filter, from_view, functions, join, params, related, reverse_ref};
 
 /// Execute an MDQL statement
 pub async fn execute(db: &mut Database, stmt: Statement) -> anyhow::Result<QueryResult> {
+    if db.is_read_only() && !stmt.is_read_only() {
+        anyhow::bail!("Database is read-only; only SELECT, SHOW, DESCRIBE and EXPLAIN are allowed");
+    }
+    db.policy().check(&stmt)?;
+    functions::check(&stmt)?;
+    params::check(&stmt)?;
+    reverse_ref::check(&stmt)?;
+
     match stmt {
-        Statement::Select(select) => execute_select(db, select).await,
+        Statement::Select(select) => match aggregate::lone_count(&select) {
+            Some(column) => scalar(execute_select(db, select).await?, &column),
+            None => execute_select(db, select).await,
+        },
         Statement::Insert(insert) => execute_insert(db, insert).await,
         Statement::Update(update) => execute_update(db, update).await,
         Statement::Delete(delete) => execute_delete(db, delete).await,
         Statement::CreateCollection(create) => execute_create_collection(db, create).await,
+        Statement::AlterCollection(alter) => execute_alter_collection(db, alter).await,
         Statement::CreateView(create) => execute_create_view(db, create).await,
         Statement::DropCollection(name) => execute_drop_collection(db, &name).await,
         Statement::DropView(name) => execute_drop_view(db, &name).await,
         Statement::ShowCollections => execute_show_collections(db).await,
         Statement::ShowViews => execute_show_views(db).await,
+        Statement::ShowCreateCollection(name) => execute_show_create_collection(db, &name).await,
+        Statement::ShowCreateView(name) => execute_show_create_view(db, &name).await,
+        Statement::Describe(name) => execute_describe(db, &name).await,
+        Statement::Explain(select) => Ok(QueryResult::Plan(plan::explain(db, *select).await?)),
     }
 }
 
-async fn execute_select(db: &Database, stmt: SelectStmt) -> anyhow::Result<QueryResult> {
-    validate_collection_name(&stmt.from)?;
-    let collection = Collection::open(&stmt.from, &db.root);
+/// The value of a SELECT's only column, from its one row; NULL if HAVING,
+/// OFFSET or LIMIT left none
+fn scalar(result: QueryResult, column: &str) -> anyhow::Result<QueryResult> {
+    let QueryResult::Documents(rows) = result else {
+        return Ok(result);
+    };
+    let value = rows.first().and_then(|row| row.fields.get(column)).cloned();
+    Ok(QueryResult::Scalar(value.unwrap_or(Value::Null)))
+}
 
+/// Files a statement would remove, relative to the database root
+///
+/// A DELETE removes its matching documents and those its ON DELETE CASCADE
+/// rules reach; DROP COLLECTION and DROP VIEW remove everything they own.
+/// Other statements remove nothing.
+pub async fn removals(db: &Database, stmt: &Statement) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    match stmt {
+        Statement::Delete(delete) => {
+            validate_collection_name(&delete.from)?;
+            if !db.store.exists(&delete.from).await? {
+                return Ok(paths);
+            }
+            let mut where_clause = delete.where_clause.clone();
+            if let Some(where_clause) = &mut where_clause {
+                join::unqualify_expr(where_clause, &[&delete.from]);
+            }
+            let ids: Vec<String> = db
+                .store
+                .list(&delete.from)
+                .await?
+                .into_iter()
+                .filter(|doc| where_clause.as_ref().is_none_or(|w| filter::evaluate(w, doc)))
+                .map(|doc| doc.id)
+                .collect();
+            for (name, ids) in follow_references(db, &delete.from, &ids).await?.deleted {
+                paths.extend(ids.iter().map(|id| PathBuf::from("collections").join(&name).join(format!("{}.md", id))));
+            }
+        }
+        Statement::DropCollection(name) => {
+            validate_collection_name(name)?;
+            paths.extend(files_under(&db.root, &PathBuf::from("collections").join(name)));
+        }
+        Statement::DropView(name) => {
+            validate_view_name(name)?;
+            let definition = PathBuf::from(".mdby").join("views").join(format!("{}.yaml", name));
+            if db.root.join(&definition).exists() {
+                paths.push(definition);
+            }
+            paths.extend(files_under(&db.root, &PathBuf::from("views").join(name)));
+        }
+        _ => {}
+    }
+    Ok(paths)
+}
+
+/// Files in a directory under `root`, relative to `root`
+fn files_under(root: &Path, dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = walkdir::WalkDir::new(root.join(dir))
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.path().strip_prefix(root).ok().map(Path::to_path_buf))
+        .collect();
+    files.sort();
+    files
+}
+
+/// The rows of a SELECT that can be answered a document at a time: matching
+/// WHERE as each is read, and reading no further once OFFSET and LIMIT are
+/// satisfied
+///
+/// Only for statements planned as [`Access::Stream`].
+async fn select_streaming(db: &Database, stmt: &SelectStmt, snapshot: &Snapshot) -> anyhow::Result<Vec<Document>> {
+    validate_collection_name(&stmt.from)?;
+    let markdown = db.store.markdown().ok_or_else(|| anyhow::anyhow!("Only the markdown store streams documents"))?;
+    let collection = markdown.collection(&stmt.from).with_snapshot(snapshot.clone());
     if !collection.exists().await {
         anyhow::bail!("Collection '{}' does not exist", stmt.from);
     }
 
-    let mut docs = collection.list().await?;
+    let offset = stmt.offset.unwrap_or(0);
+    let mut docs = Vec::new();
+    let (mut scanned, mut skipped) = (0, 0);
+    let mut stream = std::pin::pin!(collection.stream()?);
+    while stmt.limit.is_none_or(|limit| docs.len() < limit) {
+        let Some(doc) = stream.next().await else {
+            break;
+        };
+        scanned += 1;
+        let mut doc = doc;
+        normalize(db, &stmt.from, std::slice::from_mut(&mut doc))?;
+        if stmt.where_clause.as_ref().is_some_and(|w| !filter::evaluate(w, &doc)) {
+            continue;
+        }
+        if skipped < offset {
+            skipped += 1;
+            continue;
+        }
+        docs.push(doc);
+    }
+    db.record_scan(&stmt.from, scanned);
+    Ok(docs)
+}
+
+/// Every document of a collection or system collection, or those an index
+/// lookup narrows it down to, as of `snapshot`
+async fn load_documents(
+    db: &Database,
+    name: &str,
+    stmt: &SelectStmt,
+    access: &Access,
+    snapshot: &Snapshot,
+) -> anyhow::Result<Vec<Document>> {
+    if system::is_system(name) {
+        return system::list(&db.git, name);
+    }
+    if let Some(view) = mdql::view_source(name) {
+        return view_documents(db, view).await;
+    }
+
+    validate_collection_name(name)?;
+    let mut docs = match from_replica(db, |tree| tree.documents(name))? {
+        Some(docs) => docs,
+        None => {
+            if !db.store.exists(name).await? {
+                anyhow::bail!("Collection '{}' does not exist", name);
+            }
+            match db.store.markdown() {
+                Some(markdown) => {
+                    let collection = markdown.collection(name).with_snapshot(snapshot.clone());
+                    let ids = match (access, &stmt.where_clause) {
+                        (Access::IndexLookup { fields }, Some(where_clause)) => {
+                            plan::candidates(&IndexManager::new(&db.root), &collection, fields, where_clause).await?
+                        }
+                        _ => None,
+                    };
+                    match ids {
+                        Some(ids) => {
+                            let mut docs = Vec::new();
+                            for id in ids {
+                                docs.extend(collection.get(&id).await?);
+                            }
+                            docs
+                        }
+                        None => collection.list().await?,
+                    }
+                }
+                None => db.store.list(name).await?,
+            }
+        }
+    };
+    normalize(db, name, &mut docs)?;
+    if uses_history(stmt) {
+        attach_history(&db.git, name, &mut docs)?;
+    }
+    db.record_scan(name, docs.len());
+    Ok(docs)
+}
+
+/// Normalize documents read from a collection to its schema's types (see
+/// [`Schema::normalize`](crate::schema::Schema::normalize))
+fn normalize(db: &Database, collection: &str, docs: &mut [Document]) -> anyhow::Result<()> {
+    if let Some(schema) = db.schema.get(collection) {
+        schema.normalize_all(docs)?;
+    }
+    Ok(())
+}
+
+/// Rows of a saved view, for `FROM view:name`: what its query returns
+///
+/// The policy applies to the view's query as if it were run directly.
+pub(super) async fn view_documents(db: &Database, name: &str) -> anyhow::Result<Vec<Document>> {
+    let query = from_view::resolve(name, &[], |view| read_view_file(db, view))?.remove(0);
+    let stmt = Statement::Select(query.clone());
+    db.policy().check(&stmt)?;
+    functions::check(&stmt)?;
+    reverse_ref::check(&stmt)?;
+    match Box::pin(execute_select(db, query)).await? {
+        QueryResult::Documents(docs) => Ok(docs),
+        _ => anyhow::bail!("View '{}' didn't return documents", name),
+    }
+}
+
+/// Check that the policy allows reading view `name`, as it would running
+/// the query of the view and of each view it selects from
+pub(crate) fn check_view(db: &Database, name: &str) -> anyhow::Result<()> {
+    for query in from_view::resolve(name, &[], |view| read_view_file(db, view))? {
+        db.policy().check(&Statement::Select(query))?;
+    }
+    Ok(())
+}
+
+/// Fields of a collection with an index: those declared INDEXED or UNIQUE
+pub(crate) fn indexed_fields(db: &Database, collection: &str) -> Vec<String> {
+    db.schema
+        .get(collection)
+        .map(|schema| {
+            schema
+                .fields
+                .iter()
+                .filter(|(_, def)| def.indexed || def.unique)
+                .map(|(name, _)| name.clone())
+                // Composite UNIQUE constraints look candidates up by their first field
+                .chain(schema.unique.iter().filter_map(|fields| fields.first().cloned()))
+                .fold(Vec::new(), |mut fields, field| {
+                    if !fields.contains(&field) {
+                        fields.push(field);
+                    }
+                    fields
+                })
+        })
+        .unwrap_or_default()
+}
+
+/// Check that writing `docs` leaves no two documents sharing a value of a
+/// UNIQUE field, or a combination of values of a composite UNIQUE
+/// constraint, among themselves or with the rest of the collection
+///
+/// Missing and null values are never duplicates, nor are combinations with
+/// one.
+pub(crate) async fn check_unique(db: &Database, collection: &str, docs: &[Document]) -> anyhow::Result<()> {
+    let Some(schema) = db.schema.get(collection) else {
+        return Ok(());
+    };
+
+    for (field, _) in schema.fields.iter().filter(|(_, def)| def.unique) {
+        let mut values: Vec<&Value> = Vec::new();
+        for doc in docs {
+            let Some(value) = doc.fields.get(field).filter(|value| !matches!(value, Value::Null)) else {
+                continue;
+            };
+            if values.contains(&value) {
+                return Err(ValidationError::UniqueViolation(field.clone()).into());
+            }
+            values.push(value);
+        }
+        if values.is_empty() {
+            continue;
+        }
+
+        // The documents being written no longer hold their old values
+        let holders = holders(db, collection, field, |value| value.is_some_and(|v| values.contains(&v))).await?;
+        if holders.iter().any(|id| !docs.iter().any(|doc| &doc.id == id)) {
+            return Err(ValidationError::UniqueViolation(field.clone()).into());
+        }
+    }
+
+    for fields in schema.unique.iter().filter(|fields| !fields.is_empty()) {
+        let mut combinations: Vec<Vec<&Value>> = Vec::new();
+        for values in docs.iter().filter_map(|doc| unique_combination(doc, fields)) {
+            if combinations.contains(&values) {
+                return Err(ValidationError::UniqueCombinationViolation(fields.clone()).into());
+            }
+            combinations.push(values);
+        }
+        if combinations.is_empty() {
+            continue;
+        }
+
+        // Documents sharing the first value are the only ones that can clash
+        let firsts: Vec<&Value> = combinations.iter().map(|values| values[0]).collect();
+        let holders = holders(db, collection, &fields[0], |value| value.is_some_and(|v| firsts.contains(&v))).await?;
+        for id in holders.iter().filter(|id| !docs.iter().any(|doc| &doc.id == *id)) {
+            let Some(holder) = db.store.get(collection, id).await? else {
+                continue;
+            };
+            if unique_combination(&holder, fields).is_some_and(|values| combinations.contains(&values)) {
+                return Err(ValidationError::UniqueCombinationViolation(fields.clone()).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// IDs of a collection's documents whose value of `field` satisfies
+/// `matches`: from the field's index in a markdown store, otherwise by
+/// reading every document
+async fn holders(
+    db: &Database,
+    collection: &str,
+    field: &str,
+    matches: impl Fn(Option<&Value>) -> bool,
+) -> anyhow::Result<BTreeSet<String>> {
+    match db.store.markdown() {
+        Some(markdown) => IndexManager::new(&db.root).lookup(&markdown.collection(collection), field, matches).await,
+        None => Ok(db
+            .store
+            .list(collection)
+            .await?
+            .into_iter()
+            .filter(|doc| matches(doc.fields.get(field)))
+            .map(|doc| doc.id)
+            .collect()),
+    }
+}
+
+/// A document's values of a composite UNIQUE constraint's fields, unless
+/// one is missing or null
+fn unique_combination<'a>(doc: &'a Document, fields: &[String]) -> Option<Vec<&'a Value>> {
+    fields
+        .iter()
+        .map(|field| doc.fields.get(field).filter(|value| !matches!(value, Value::Null)))
+        .collect()
+}
+
+/// Check that every REF value in `docs` names an existing document
+///
+/// Documents in `docs` count as existing, so a batch may refer to itself.
+pub(crate) async fn check_refs(db: &Database, collection: &str, docs: &[Document]) -> anyhow::Result<()> {
+    let Some(schema) = db.schema.get(collection) else {
+        return Ok(());
+    };
+    for doc in docs {
+        for (field, target, id) in crate::links::ref_values(schema, doc) {
+            if target == collection && docs.iter().any(|d| d.id == id) {
+                continue;
+            }
+            let exists = validate_collection_name(&target).is_ok()
+                && validate_document_id(&id).is_ok()
+                && db.store.get(&target, &id).await?.is_some();
+            if !exists {
+                return Err(ValidationError::MissingReference { field, collection: target, id }.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Keep a collection's indexes in step with documents written or deleted
+///
+/// Only markdown stores have indexes.
+pub(crate) fn update_indexes(db: &Database, collection: &str, written: &[Document], deleted: &[String]) -> anyhow::Result<()> {
+    let Some(markdown) = db.store.markdown() else {
+        return Ok(());
+    };
+    let indexed = indexed_fields(db, collection);
+    if indexed.is_empty() {
+        return Ok(());
+    }
+    IndexManager::new(&db.root).update(&markdown.collection(collection), &indexed, written, deleted)
+}
+
+async fn execute_select(db: &Database, mut stmt: SelectStmt) -> anyhow::Result<QueryResult> {
+    join::unqualify(&mut stmt);
+    if stmt.where_clause.as_ref().is_some_and(aggregate::contains_aggregate) {
+        anyhow::bail!("Aggregate functions can't be used in WHERE; filter groups with HAVING");
+    }
+
+    // AS OF reads every collection, joined ones included, from one past commit
+    if let Some(revision) = stmt.as_of.take() {
+        let commit = db.git.resolve_revision(&revision)?;
+        let past = db.at_revision(&commit).await?;
+        return Box::pin(execute_select(&past, stmt)).await;
+    }
+
+    // Every collection is read as it was now, whatever is written meanwhile
+    let snapshot = Snapshot::begin(&db.git, &db.root);
+    let access = plan::access(db, &stmt);
+    if access == Access::Count {
+        let mut row = Document::new(&stmt.from);
+        let count = plan::count_documents(db, &stmt.from).await?;
+        row.fields.insert(aggregate::lone_count(&stmt).unwrap_or_default(), Value::Int(count as i64));
+        return Ok(QueryResult::Documents(vec![row]));
+    }
+    if access == Access::Stream {
+        let mut docs = select_streaming(db, &stmt, &snapshot).await?;
+        if !matches!(stmt.columns.as_slice(), [Column::Star]) {
+            docs = docs.into_iter().map(|doc| project_columns(&doc, &stmt)).collect();
+        }
+        return Ok(QueryResult::Documents(docs));
+    }
+
+    let mut docs = load_documents(db, &stmt.from, &stmt, &access, &snapshot).await?;
+
+    // Similarity is measured against the whole collection, before filtering
+    let related = match (&stmt.related_to, &stmt.semantic_search) {
+        (Some(id), _) => Some(related::related_scores(&docs, id)?),
+        (None, Some(text)) => {
+            let provider = db.embedding_provider().ok_or_else(|| {
+                anyhow::anyhow!("SEMANTIC_SEARCH needs an embedding provider; set `embeddings` in .mdby/config.yaml")
+            })?;
+            Some(embeddings::search(&db.root, provider.as_ref(), &stmt.from, &docs, text, !db.is_read_only())?)
+        }
+        (None, None) => None,
+    };
+
+    // Apply JOINs; joined rows name each field after its collection
+    if !stmt.joins.is_empty() {
+        let table = join::table_name(&stmt.from, stmt.from_alias.as_ref());
+        docs = docs.iter().map(|doc| join::qualify(doc, table)).collect();
+        for clause in &stmt.joins {
+            let others = load_documents(db, &clause.collection, &stmt, &Access::FullScan, &snapshot).await?;
+            docs = join::join(docs, &others, clause);
+        }
+    }
+
+    // Work out REVERSE_REF calls before anything evaluates them
+    let reverse_refs = reverse_ref::calls(&stmt);
+    for call in &reverse_refs {
+        let others = load_documents(db, &call.collection, &stmt, &Access::FullScan, &snapshot).await?;
+        reverse_ref::attach(&mut docs, call, &others);
+    }
 
     // Apply WHERE filter
     if let Some(ref where_clause) = stmt.where_clause {
         docs.retain(|doc| filter::evaluate(where_clause, doc));
     }
 
+    // Apply RELATED TO / SEMANTIC_SEARCH; ORDER BY below keeps relevance as the tiebreaker
+    if let Some(scores) = &related {
+        related::rank(&mut docs, scores);
+    }
+
+    // Apply GROUP BY, aggregates and HAVING; ORDER BY then sorts the groups
+    let aggregated = aggregate::is_aggregate(&stmt);
+    if aggregated {
+        docs = aggregate::group(docs, &stmt)?;
+    }
+
     // Apply ORDER BY
     if !stmt.order_by.is_empty() {
+        let computed = computed_columns(&stmt.columns);
         docs.sort_by(|a, b| {
             for order in &stmt.order_by {
-                let a_val = a.fields.get(&order.column);
-                let b_val = b.fields.get(&order.column);
+                let a_val = sort_value(a, &order.column, &computed);
+                let b_val = sort_value(b, &order.column, &computed);
 
-                let cmp = compare_values(a_val, b_val);
+                let cmp = compare_values(a_val.as_deref(), b_val.as_deref());
                 if cmp != std::cmp::Ordering::Equal {
                     return match order.direction {
                         OrderDirection::Asc => cmp,
@@ -75,9 +512,11 @@ async fn execute_select(db: &Database, stmt: SelectStmt) -> anyhow::Result<Query
         docs.truncate(limit);
     }
 
-    // Project columns (if not *)
-    if !stmt.columns.iter().any(|c| matches!(c, Column::Star)) {
-        docs = docs.into_iter().map(|doc| project_columns(&doc, &stmt.columns)).collect();
+    // Project columns (if not just *); groups already hold only the selected columns
+    if !aggregated && !matches!(stmt.columns.as_slice(), [Column::Star]) {
+        docs = docs.into_iter().map(|doc| project_columns(&doc, &stmt)).collect();
+    } else if !aggregated {
+        reverse_ref::detach(&mut docs, &stmt, &reverse_refs);
     }
 
     Ok(QueryResult::Documents(docs))
@@ -85,142 +524,567 @@ async fn execute_select(db: &Database, stmt: SelectStmt) -> anyhow::Result<Query
 
 async fn execute_insert(db: &Database, stmt: InsertStmt) -> anyhow::Result<QueryResult> {
     validate_collection_name(&stmt.into)?;
-    let collection = Collection::open(&stmt.into, &db.root);
-    collection.ensure_exists().await?;
-
-    // Build document from columns and values
-    let id_idx = stmt.columns.iter().position(|c| c == "id");
-    let id = id_idx
-        .and_then(|i| stmt.values.get(i))
-        .and_then(|v| match v {
+    let layout = layout(db, &stmt.into);
+    db.store.create_collection(&stmt.into).await?;
+
+    let body = match &stmt.body {
+        Some(InsertBody::Inline(body)) => Some(body.clone()),
+        Some(InsertBody::File(path)) => Some(read_body_file(db, path).await?),
+        None => None,
+    };
+
+    // Build every document before writing any, so a bad row leaves nothing behind
+    let mut docs: Vec<Document> = Vec::with_capacity(stmt.values.len());
+    let mut existing_ids = BTreeSet::new();
+    let mut stored_ids: Option<BTreeSet<String>> = None;
+    for (row, values) in stmt.values.iter().enumerate() {
+        if values.len() != stmt.columns.len() {
+            anyhow::bail!(
+                "VALUES row {} has {} value(s) for {} column(s)",
+                row + 1,
+                values.len(),
+                stmt.columns.len()
+            );
+        }
+
+        // Build document from columns and values
+        let id_idx = stmt.columns.iter().position(|c| c == "id");
+        let id = id_idx.and_then(|i| values.get(i)).and_then(|v| match v {
             Literal::String(s) => Some(s.clone()),
+            // IDs are stored as strings; accept `VALUES (123, ...)` as '123'
+            Literal::Int(i) => Some(i.to_string()),
             _ => None,
-        })
-        .ok_or_else(|| anyhow::anyhow!("INSERT requires an 'id' column"))?;
-
-    validate_document_id(&id)?;
-    let mut doc = Document::new(id);
+        });
+        let mut doc = Document::new(id.clone().unwrap_or_default());
 
-    for (i, col) in stmt.columns.iter().enumerate() {
-        if col != "id" {
-            if let Some(val) = stmt.values.get(i) {
+        for (col, val) in stmt.columns.iter().zip(values) {
+            if col != "id" {
                 doc.fields.insert(col.clone(), literal_to_value(val));
             }
         }
+
+        // Without an explicit ID, the schema's id_strategy generates one
+        doc.id = match id {
+            Some(id) => id,
+            None => {
+                let generated = match db.schema.get(&stmt.into) {
+                    Some(schema) => {
+                        let stored = match stored_ids {
+                            Some(ref ids) => ids,
+                            None => stored_ids.insert(db.store.ids(&stmt.into).await?.into_iter().collect()),
+                        };
+                        ids::generate(&schema.id_strategy, &schema.id_format, &db.root, &stmt.into, &doc, |id| stored.contains(id)).await?
+                    }
+                    None => None,
+                };
+                let base = generated.ok_or_else(|| anyhow::anyhow!("INSERT requires an 'id' column"))?;
+                // Derived IDs can repeat within one statement; suffix them like taken ones
+                slug::unique(&base, &SlugOptions::default(), |id| docs.iter().any(|other| other.id == id))
+            }
+        };
+        validate_document_id(&doc.id)?;
+        if docs.iter().any(|other| other.id == doc.id) {
+            anyhow::bail!("Document '{}' appears more than once in INSERT", doc.id);
+        }
+
+        if let Some(body) = &body {
+            doc.body = body.clone();
+        }
+        if let Some(schema) = db.schema.get(&stmt.into) {
+            schema.normalize_datetimes(&mut doc, db.config.timezone);
+        }
+
+        // ON CONFLICT applies the new columns (and body, if given) to the existing document
+        let existing = match stmt.on_conflict {
+            Some(on_conflict) => db.store.get(&stmt.into, &doc.id).await?.map(|existing| (on_conflict, existing)),
+            None => None,
+        };
+        match existing {
+            Some((OnConflict::Nothing, _)) => continue,
+            Some((OnConflict::Update, mut existing)) => {
+                existing.fields.append(&mut doc.fields);
+                if body.is_none() {
+                    doc.body = std::mem::take(&mut existing.body);
+                }
+                doc.fields = existing.fields;
+                existing_ids.insert(doc.id.clone());
+            }
+            None => check_insertable(db, &stmt.into, &doc.id).await?,
+        }
+
+        // Coerce and validate against schema if exists
+        if let Some(schema) = db.schema.get(&stmt.into) {
+            schema.coerce(&mut doc);
+            schema.validate(&doc)?;
+        }
+        db.config.limits.check(&doc)?;
+        db.lint.enforce(&stmt.into, &doc)?;
+        docs.push(doc);
+    }
+    check_unique(db, &stmt.into, &docs).await?;
+    check_refs(db, &stmt.into, &docs).await?;
+
+    // Conflicting rows that leave their document as it was aren't rewritten or counted
+    let mut written = Vec::with_capacity(docs.len());
+    for doc in docs {
+        if !db.store.put(&stmt.into, &doc, &layout).await? {
+            existing_ids.remove(&doc.id);
+            continue;
+        }
+        written.push(doc);
     }
+    let docs = written;
+    if docs.is_empty() {
+        return Ok(QueryResult::Affected(0));
+    }
+    update_indexes(db, &stmt.into, &docs, &[])?;
+
+    // Commit the change
+    let verb = if existing_ids.is_empty() { "INSERT" } else { "UPSERT" };
+    match docs.as_slice() {
+        [doc] => db.git.commit(&format!("{} into {}: {}", verb, stmt.into, doc.id))?,
+        _ => db.git.commit(&format!("{} into {}: {} document(s)", verb, stmt.into, docs.len()))?,
+    };
+
+    Ok(QueryResult::Affected(docs.len()))
+}
 
-    if let Some(body) = stmt.body {
-        doc.body = body;
+/// Special fields filled in from git history rather than the file
+const HISTORY_FIELDS: [&str; 3] = ["@author", "@created", "@modified"];
+
+/// Whether a SELECT selects, joins, filters or sorts on `@author`, `@created`
+/// or `@modified`
+pub(crate) fn uses_history(stmt: &SelectStmt) -> bool {
+    let special = |field: &SpecialField| {
+        matches!(field, SpecialField::Author | SpecialField::Created | SpecialField::Modified)
+    };
+    let history = |expr: &Expr| match expr {
+        Expr::Column(Column::Special(field)) => special(field),
+        Expr::Column(Column::Qualified { field, .. }) => HISTORY_FIELDS.contains(&field.as_str()),
+        _ => false,
+    };
+    stmt.columns.iter().any(|column| match column {
+        Column::Special(field) => special(field),
+        Column::Qualified { field, .. } => HISTORY_FIELDS.contains(&field.as_str()),
+        Column::Expr { expr, .. } => expr_references(expr, &history),
+        _ => false,
+    }) || stmt.where_clause.as_ref().is_some_and(|expr| expr_references(expr, &history))
+        || stmt.joins.iter().any(|join| expr_references(&join.on, &history))
+        || stmt.having.as_ref().is_some_and(|expr| expr_references(expr, &history))
+        || stmt.order_by.iter().any(|order| HISTORY_FIELDS.contains(&order.column.as_str()))
+}
+
+/// Whether `expr` or any expression inside it matches `pred`
+pub(super) fn expr_references(expr: &Expr, pred: &dyn Fn(&Expr) -> bool) -> bool {
+    if pred(expr) {
+        return true;
+    }
+    match expr {
+        Expr::BinaryOp { left, right, .. } => expr_references(left, pred) || expr_references(right, pred),
+        Expr::UnaryOp { expr, .. } | Expr::Like { expr, .. } | Expr::IsNull { expr, .. } => expr_references(expr, pred),
+        Expr::Function { args, .. } => args.iter().any(|arg| expr_references(arg, pred)),
+        Expr::Aggregate { arg, .. } => arg.as_ref().is_some_and(|arg| expr_references(arg, pred)),
+        Expr::In { expr, values, .. } => {
+            expr_references(expr, pred) || values.iter().any(|value| expr_references(value, pred))
+        }
+        Expr::Between { expr, low, high, .. } => {
+            expr_references(expr, pred) || expr_references(low, pred) || expr_references(high, pred)
+        }
+        Expr::Column(Column::Expr { expr, .. }) => expr_references(expr, pred),
+        Expr::Literal(_) | Expr::Param(_) | Expr::Column(_) | Expr::Contains { .. } | Expr::HasTag { .. } => false,
     }
+}
 
-    // Validate against schema if exists
-    if let Some(schema) = db.schema.get(&stmt.into) {
-        schema.validate(&doc)?;
+/// Fill in `meta.author`, `meta.created` and `meta.modified` from the first
+/// and last commits touching each document
+pub(crate) fn attach_history(git: &crate::git::Repository, collection: &str, docs: &mut [Document]) -> anyhow::Result<()> {
+    let dir = std::path::Path::new("collections").join(collection);
+    let timestamp = |secs: i64| {
+        chrono::DateTime::from_timestamp(secs, 0).map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+    };
+    for doc in docs {
+        let history = git.file_history(&dir.join(&doc.path))?;
+        doc.meta.created = history.as_ref().and_then(|history| timestamp(history.created));
+        doc.meta.modified = history.as_ref().and_then(|history| timestamp(history.modified));
+        doc.meta.author = history.map(|history| history.author);
     }
+    Ok(())
+}
 
-    collection.insert(&doc).await?;
+/// Selected expressions by the name their results get, e.g. `weighted` for
+/// `priority * 2 AS weighted`
+fn computed_columns(columns: &[Column]) -> Vec<(String, &Expr)> {
+    columns
+        .iter()
+        .filter_map(|column| match column {
+            Column::Expr { expr, alias } => Some((alias.clone().unwrap_or_else(|| expr.to_string()), expr.as_ref())),
+            _ => None,
+        })
+        .collect()
+}
 
-    // Commit the change
-    db.git.commit(&format!("INSERT into {}: {}", stmt.into, doc.id))?;
+/// Value ORDER BY sorts `doc` on: a field, a selected expression's name, or a
+/// special field such as `@modified`
+fn sort_value<'a>(doc: &'a Document, column: &str, computed: &[(String, &Expr)]) -> Option<Cow<'a, Value>> {
+    if let Some(value) = doc.fields.get(column) {
+        return Some(Cow::Borrowed(value));
+    }
+    if let Some((_, expr)) = computed.iter().find(|(name, _)| name == column) {
+        return filter::value(expr, doc).map(Cow::Owned);
+    }
+    if let Some(value) = doc.get_path(column) {
+        return Some(Cow::Owned(value));
+    }
+    let special = match column {
+        "@id" => SpecialField::Id,
+        "@path" => SpecialField::Path,
+        "@author" => SpecialField::Author,
+        "@created" => SpecialField::Created,
+        "@modified" => SpecialField::Modified,
+        _ => return None,
+    };
+    filter::special_value(doc, &special).map(Cow::Owned)
+}
 
-    Ok(QueryResult::Affected(1))
+/// Read from the tree of a read-only replica, or `None` for a working tree
+fn from_replica<T>(db: &Database, read: impl FnOnce(&TreeReader) -> anyhow::Result<T>) -> anyhow::Result<Option<T>> {
+    match db.git.snapshot()? {
+        Some(tree) => read(&tree).map(Some),
+        None => Ok(None),
+    }
 }
 
-async fn execute_update(db: &Database, stmt: UpdateStmt) -> anyhow::Result<QueryResult> {
-    validate_collection_name(&stmt.collection)?;
-    let collection = Collection::open(&stmt.collection, &db.root);
+/// A view's definition file, from a replica's tree or the working tree;
+/// `None` if there is no such view
+fn read_view_file(db: &Database, name: &str) -> anyhow::Result<Option<String>> {
+    let view_file = Path::new(".mdby").join("views").join(format!("{}.yaml", name));
+    match from_replica(db, |tree| tree.read_file(&view_file))? {
+        Some(content) => Ok(content),
+        None => {
+            let path = db.root.join(&view_file);
+            Ok(if path.exists() { Some(std::fs::read_to_string(&path)?) } else { None })
+        }
+    }
+}
 
-    if !collection.exists().await {
+/// How a collection's documents are written: its schema's fields first, in
+/// order, and its bodies compressed over its limit
+pub(crate) fn layout(db: &Database, name: &str) -> Layout {
+    Layout::of(db.schema.get(name))
+}
+
+/// Check that a new document can take `id`: no document has it, and in a
+/// markdown store no ignore pattern hides its file
+pub(crate) async fn check_insertable(db: &Database, collection: &str, id: &str) -> anyhow::Result<()> {
+    match db.store.markdown() {
+        Some(markdown) => markdown.collection(collection).check_insertable(id),
+        None if db.store.get(collection, id).await?.is_some() => {
+            anyhow::bail!("Document '{}' already exists in collection '{}'", id, collection)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Read the body for `BODY FROM FILE`
+///
+/// The path is relative to the database root and has to stay under it: it
+/// can't be a symlink, lead out of the root through one, or into `.git` or
+/// `.mdby`.
+async fn read_body_file(db: &Database, path: &str) -> anyhow::Result<String> {
+    validate_relative_path(path)?;
+    if Path::new(path).components().any(|c| matches!(c.as_os_str().to_str(), Some(".git" | ".mdby"))) {
+        anyhow::bail!("Body file '{}' is inside .git or .mdby", path);
+    }
+
+    let file = db.root.join(path);
+    match tokio::fs::symlink_metadata(&file).await {
+        Ok(metadata) if metadata.file_type().is_symlink() => anyhow::bail!("Body file '{}' is a symlink", path),
+        Ok(metadata) if metadata.is_file() => {}
+        _ => anyhow::bail!("Body file '{}' not found in the database root", path),
+    }
+    if !file.canonicalize()?.starts_with(db.root.canonicalize()?) {
+        anyhow::bail!("Body file '{}' is outside the database root", path);
+    }
+    Ok(tokio::fs::read_to_string(&file).await?)
+}
+
+async fn execute_update(db: &Database, mut stmt: UpdateStmt) -> anyhow::Result<QueryResult> {
+    validate_collection_name(&stmt.collection)?;
+    if let Some(where_clause) = &mut stmt.where_clause {
+        join::unqualify_expr(where_clause, &[&stmt.collection]);
+    }
+    if !db.store.exists(&stmt.collection).await? {
         anyhow::bail!("Collection '{}' does not exist", stmt.collection);
     }
 
-    let mut docs = collection.list().await?;
+    let mut docs = db.store.list(&stmt.collection).await?;
+    db.record_scan(&stmt.collection, docs.len());
+    normalize(db, &stmt.collection, &mut docs)?;
 
     // Filter documents to update
     if let Some(ref where_clause) = stmt.where_clause {
         docs.retain(|doc| filter::evaluate(where_clause, doc));
     }
 
-    let count = docs.len();
-
-    // Apply SET clauses
-    for mut doc in docs {
+    // Apply SET clauses, checking limits and lint rules before anything is written
+    for doc in &mut docs {
         for set_clause in &stmt.set {
-            let value = evaluate_set_value(&set_clause.value, &doc);
+            let value = filter::value(&set_clause.value, doc).unwrap_or(Value::Null);
             doc.fields.insert(set_clause.column.clone(), value);
+            if let Some(schema) = db.schema.get(&stmt.collection) {
+                schema.normalize_datetime(doc, &set_clause.column, db.config.timezone);
+            }
         }
-        collection.upsert(&doc).await?;
+        normalize(db, &stmt.collection, std::slice::from_mut(doc))?;
+        db.config.limits.check(doc)?;
+        db.lint.enforce(&stmt.collection, doc)?;
     }
+    check_unique(db, &stmt.collection, &docs).await?;
+    check_refs(db, &stmt.collection, &docs).await?;
+
+    // Documents the SET leaves as they were aren't rewritten or counted
+    let layout = layout(db, &stmt.collection);
+    let mut changed = Vec::with_capacity(docs.len());
+    for doc in docs {
+        if db.store.put(&stmt.collection, &doc, &layout).await? {
+            changed.push(doc);
+        }
+    }
+    update_indexes(db, &stmt.collection, &changed, &[])?;
 
-    if count > 0 {
-        db.git.commit(&format!("UPDATE {}: {} document(s)", stmt.collection, count))?;
+    if !changed.is_empty() {
+        db.git.commit(&format!("UPDATE {}: {} document(s)", stmt.collection, changed.len()))?;
     }
 
-    Ok(QueryResult::Affected(count))
+    Ok(QueryResult::Affected(changed.len()))
 }
 
-async fn execute_delete(db: &Database, stmt: DeleteStmt) -> anyhow::Result<QueryResult> {
+async fn execute_delete(db: &Database, mut stmt: DeleteStmt) -> anyhow::Result<QueryResult> {
     validate_collection_name(&stmt.from)?;
-    let collection = Collection::open(&stmt.from, &db.root);
-
-    if !collection.exists().await {
+    if let Some(where_clause) = &mut stmt.where_clause {
+        join::unqualify_expr(where_clause, &[&stmt.from]);
+    }
+    if !db.store.exists(&stmt.from).await? {
         anyhow::bail!("Collection '{}' does not exist", stmt.from);
     }
 
-    let mut docs = collection.list().await?;
+    let mut docs = db.store.list(&stmt.from).await?;
+    db.record_scan(&stmt.from, docs.len());
+    normalize(db, &stmt.from, &mut docs)?;
 
     // Filter documents to delete
     if let Some(ref where_clause) = stmt.where_clause {
         docs.retain(|doc| filter::evaluate(where_clause, doc));
     }
 
+    // ON DELETE rules read, and may change, the collections referring to
+    // this one, so the policy has to allow those too
+    for name in referring_collections(db, &stmt.from) {
+        if !db.policy().allows_collection(&name) {
+            return Err(crate::Error::CollectionDenied { statement: "DELETE", collection: name }.into());
+        }
+    }
+
     let count = docs.len();
     let ids: Vec<_> = docs.iter().map(|d| d.id.clone()).collect();
+    let cascade = follow_references(db, &stmt.from, &ids).await?;
 
     for id in &ids {
-        collection.delete(id).await?;
+        db.store.delete(&stmt.from, id).await?;
+    }
+    update_indexes(db, &stmt.from, &[], &ids)?;
+
+    // Documents reached through ON DELETE CASCADE and SET NULL
+    let mut cascaded = 0;
+    for (name, deleted) in &cascade.deleted {
+        let deleted: Vec<String> = deleted.iter().filter(|id| name != &stmt.from || !ids.contains(id)).cloned().collect();
+        for id in &deleted {
+            db.store.delete(name, id).await?;
+        }
+        update_indexes(db, name, &[], &deleted)?;
+        cascaded += deleted.len();
+    }
+    for (name, docs) in &cascade.updated {
+        let layout = layout(db, name);
+        for doc in docs {
+            db.store.put(name, doc, &layout).await?;
+        }
+        update_indexes(db, name, docs, &[])?;
     }
 
     if count > 0 {
-        db.git.commit(&format!("DELETE from {}: {} document(s)", stmt.from, count))?;
+        let mut message = format!("DELETE from {}: {} document(s)", stmt.from, count);
+        let updated: usize = cascade.updated.values().map(Vec::len).sum();
+        if cascaded + updated > 0 {
+            message.push_str(&format!(" ({} referencing document(s) deleted, {} updated)", cascaded, updated));
+        }
+        db.git.commit(&message)?;
     }
 
     Ok(QueryResult::Affected(count))
 }
 
+/// Documents deleted or rewritten by the ON DELETE rules of REF fields
+#[derive(Default)]
+struct Cascade {
+    /// IDs to delete by collection, including the documents deleted directly
+    deleted: BTreeMap<String, BTreeSet<String>>,
+    /// Documents with references cleared by SET NULL, by collection
+    updated: BTreeMap<String, Vec<Document>>,
+}
+
+/// Follow the ON DELETE rules of REF fields pointing at the documents `ids`
+/// of `collection`, and at the documents CASCADE deletes in turn
+///
+/// Fails on a RESTRICT reference from a document that isn't itself deleted,
+/// or when SET NULL would leave a document invalid.
+async fn follow_references(db: &Database, collection: &str, ids: &[String]) -> anyhow::Result<Cascade> {
+    use crate::schema::OnDelete;
+
+    // (collection, field, target, rule) for every REF field with a rule
+    let rules: Vec<(String, String, String, OnDelete)> = db
+        .schema
+        .list()
+        .flat_map(|schema| {
+            schema.fields.iter().filter_map(|(field, def)| {
+                Some((schema.name.clone(), field.clone(), def.field_type.ref_target()?.to_string(), def.on_delete?))
+            })
+        })
+        .collect();
+
+    let mut cascade = Cascade::default();
+    cascade.deleted.entry(collection.to_string()).or_default().extend(ids.iter().cloned());
+    if rules.is_empty() {
+        return Ok(cascade);
+    }
+
+    let mut loaded: BTreeMap<String, Vec<Document>> = BTreeMap::new();
+    let mut cleared: BTreeSet<(String, String)> = BTreeSet::new();
+    let mut restricted: Vec<(String, String, String, String)> = Vec::new();
+    let mut queue: Vec<(String, String)> = ids.iter().map(|id| (collection.to_string(), id.clone())).collect();
+
+    while let Some((target, id)) = queue.pop() {
+        for (referrer, field, _, rule) in rules.iter().filter(|(_, _, t, _)| *t == target) {
+            if !loaded.contains_key(referrer) {
+                let docs = if db.store.exists(referrer).await? { db.store.list(referrer).await? } else { Vec::new() };
+                loaded.insert(referrer.clone(), docs);
+            }
+            let docs = loaded.get_mut(referrer).into_iter().flatten();
+            for doc in docs.filter(|doc| refers_to(doc.fields.get(field), &id)) {
+                match rule {
+                    OnDelete::Restrict => restricted.push((referrer.clone(), doc.id.clone(), target.clone(), id.clone())),
+                    OnDelete::Cascade => {
+                        if cascade.deleted.entry(referrer.clone()).or_default().insert(doc.id.clone()) {
+                            queue.push((referrer.clone(), doc.id.clone()));
+                        }
+                    }
+                    OnDelete::SetNull => {
+                        let value = doc.fields.get_mut(field).expect("matched a reference");
+                        match value {
+                            Value::Array(items) => items.retain(|item| item.as_str() != Some(id.as_str())),
+                            other => *other = Value::Null,
+                        }
+                        cleared.insert((referrer.clone(), doc.id.clone()));
+                    }
+                }
+            }
+        }
+    }
+
+    let is_deleted = |name: &str, id: &str| cascade.deleted.get(name).is_some_and(|ids| ids.contains(id));
+    if let Some((referrer, referrer_id, target, id)) =
+        restricted.into_iter().find(|(referrer, referrer_id, ..)| !is_deleted(referrer, referrer_id))
+    {
+        return Err(crate::Error::DocumentReferenced {
+            collection: target,
+            id,
+            referrer: format!("{}/{}", referrer, referrer_id),
+        }
+        .into());
+    }
+
+    for (name, docs) in loaded {
+        let schema = db.schema.get(&name);
+        for doc in docs {
+            if !cleared.contains(&(name.clone(), doc.id.clone())) || is_deleted(&name, &doc.id) {
+                continue;
+            }
+            if let Some(schema) = schema {
+                schema.validate(&doc).map_err(|e| anyhow::anyhow!("ON DELETE SET NULL would leave {}/{} invalid: {}", name, doc.id, e))?;
+            }
+            cascade.updated.entry(name.clone()).or_default().push(doc);
+        }
+    }
+    Ok(cascade)
+}
+
+/// Collections whose ON DELETE rules a DELETE from `collection` follows:
+/// those with a rule on a REF to it, and to any collection CASCADE reaches
+fn referring_collections(db: &Database, collection: &str) -> BTreeSet<String> {
+    use crate::schema::OnDelete;
+
+    let mut found = BTreeSet::new();
+    let mut queue = vec![collection.to_string()];
+    while let Some(target) = queue.pop() {
+        for schema in db.schema.list() {
+            for def in schema.fields.values() {
+                let Some(rule) = def.on_delete else { continue };
+                if def.field_type.ref_target() != Some(target.as_str()) {
+                    continue;
+                }
+                if found.insert(schema.name.clone()) && rule == OnDelete::Cascade {
+                    queue.push(schema.name.clone());
+                }
+            }
+        }
+    }
+    found.remove(collection);
+    found
+}
+
+/// Whether a REF field's value (an ID or array of IDs) includes `id`
+fn refers_to(value: Option<&Value>, id: &str) -> bool {
+    match value {
+        Some(Value::String(s)) => s == id,
+        Some(Value::Array(items)) => items.iter().any(|item| item.as_str() == Some(id)),
+        _ => false,
+    }
+}
+
 async fn execute_create_collection(db: &mut Database, stmt: CreateCollectionStmt) -> anyhow::Result<QueryResult> {
     validate_collection_name(&stmt.name)?;
-    let collection = Collection::open(&stmt.name, &db.root);
-
-    if collection.exists().await {
+    if db.store.exists(&stmt.name).await? {
         if stmt.if_not_exists {
             return Ok(QueryResult::CollectionCreated(stmt.name));
         }
         anyhow::bail!("Collection '{}' already exists", stmt.name);
     }
 
-    collection.ensure_exists().await?;
-
     // Create schema from column definitions
-    if !stmt.columns.is_empty() {
-        let mut schema = crate::schema::Schema::new(&stmt.name);
-        for col in stmt.columns {
-            let field_def = crate::schema::FieldDef {
-                field_type: datatype_to_fieldtype(&col.data_type),
-                required: col.constraints.iter().any(|c| matches!(c, mdql::Constraint::Required)),
-                unique: col.constraints.iter().any(|c| matches!(c, mdql::Constraint::Unique)),
-                indexed: col.constraints.iter().any(|c| matches!(c, mdql::Constraint::Indexed)),
-                default: col.constraints.iter().find_map(|c| {
-                    if let mdql::Constraint::Default(lit) = c {
-                        Some(literal_to_yaml(&lit))
-                    } else {
-                        None
-                    }
-                }),
-                description: None,
-            };
-            schema.fields.insert(col.name, field_def);
+    let mut schema = crate::schema::Schema::new(&stmt.name);
+    for col in &stmt.columns {
+        schema.fields.insert(col.name.clone(), column_to_field(col)?);
+    }
+    for fields in &stmt.unique {
+        let constraint = format!("UNIQUE ({})", fields.join(", "));
+        for (i, field) in fields.iter().enumerate() {
+            if !schema.fields.contains_key(field) {
+                anyhow::bail!("{} names '{}', which is not a column of '{}'", constraint, field, stmt.name);
+            }
+            if fields[..i].contains(field) {
+                anyhow::bail!("{} names '{}' more than once", constraint, field);
+            }
+        }
+        // UNIQUE (slug) is the same as a UNIQUE column
+        match fields.as_slice() {
+            [field] => schema.fields[field].unique = true,
+            _ if !schema.unique.contains(fields) => schema.unique.push(fields.clone()),
+            _ => {}
         }
+    }
+
+    db.store.create_collection(&stmt.name).await?;
+    if !stmt.columns.is_empty() {
         db.schema.register(schema)?;
     }
 
@@ -229,14 +1093,242 @@ async fn execute_create_collection(db: &mut Database, stmt: CreateCollectionStmt
     Ok(QueryResult::CollectionCreated(stmt.name))
 }
 
+/// Schema field for a column definition
+fn column_to_field(col: &ColumnDef) -> anyhow::Result<crate::schema::FieldDef> {
+    let on_delete = col.constraints.iter().find_map(|c| match c {
+        mdql::Constraint::OnDelete(action) => Some(match action {
+            mdql::OnDelete::Cascade => crate::schema::OnDelete::Cascade,
+            mdql::OnDelete::SetNull => crate::schema::OnDelete::SetNull,
+            mdql::OnDelete::Restrict => crate::schema::OnDelete::Restrict,
+        }),
+        _ => None,
+    });
+    let field_type = datatype_to_fieldtype(&col.data_type);
+    if on_delete.is_some() && field_type.ref_target().is_none() {
+        anyhow::bail!("ON DELETE applies only to REF columns, but '{}' is {}", col.name, col.data_type);
+    }
+
+    Ok(crate::schema::FieldDef {
+        field_type,
+        required: col.constraints.iter().any(|c| matches!(c, mdql::Constraint::Required)),
+        unique: col.constraints.iter().any(|c| matches!(c, mdql::Constraint::Unique)),
+        indexed: col.constraints.iter().any(|c| matches!(c, mdql::Constraint::Indexed)),
+        default: col.constraints.iter().find_map(|c| {
+            if let mdql::Constraint::Default(lit) = c {
+                Some(literal_to_yaml(lit))
+            } else {
+                None
+            }
+        }),
+        description: None,
+        label: None,
+        labels: BTreeMap::new(),
+        scrub: None,
+        on_delete,
+        offset_field: None,
+    })
+}
+
+/// Change a collection's schema, and with MIGRATE rewrite its documents to
+/// match; returns the number of documents rewritten
+///
+/// A migration that would leave a document invalid (a REQUIRED column
+/// without a DEFAULT, a value that can't be converted to the new type, a
+/// rename onto a field the document already has) changes nothing.
+async fn execute_alter_collection(db: &mut Database, stmt: AlterCollectionStmt) -> anyhow::Result<QueryResult> {
+    validate_collection_name(&stmt.name)?;
+    if !db.store.exists(&stmt.name).await? {
+        anyhow::bail!("Collection '{}' does not exist", stmt.name);
+    }
+
+    let mut schema = db.schema.get(&stmt.name).cloned().unwrap_or_else(|| crate::schema::Schema::new(&stmt.name));
+    let require = |schema: &crate::schema::Schema, column: &str| {
+        if schema.fields.contains_key(column) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Collection '{}' has no column '{}'", stmt.name, column))
+        }
+    };
+
+    // The column whose values must be valid after migrating
+    let checked = match &stmt.action {
+        AlterAction::AddColumn(col) => {
+            if schema.fields.contains_key(&col.name) {
+                anyhow::bail!("Collection '{}' already has a column '{}'", stmt.name, col.name);
+            }
+            schema.fields.insert(col.name.clone(), column_to_field(col)?);
+            Some(col.name.clone())
+        }
+        AlterAction::DropColumn(name) => {
+            require(&schema, name)?;
+            schema.fields.shift_remove(name);
+            // As in SQL, a composite UNIQUE constraint goes with any of its columns
+            schema.unique.retain(|fields| !fields.contains(name));
+            None
+        }
+        AlterAction::RenameColumn { from, to } => {
+            require(&schema, from)?;
+            if schema.fields.contains_key(to) {
+                anyhow::bail!("Collection '{}' already has a column '{}'", stmt.name, to);
+            }
+            // Keep the column's place in the declaration order
+            schema.fields = std::mem::take(&mut schema.fields)
+                .into_iter()
+                .map(|(name, def)| if name == *from { (to.clone(), def) } else { (name, def) })
+                .collect();
+            for field in schema.unique.iter_mut().flatten().filter(|field| *field == from) {
+                *field = to.clone();
+            }
+            None
+        }
+        AlterAction::AlterType { column, data_type } => {
+            require(&schema, column)?;
+            let field = &mut schema.fields[column];
+            field.field_type = datatype_to_fieldtype(data_type);
+            if field.field_type.ref_target().is_none() {
+                field.on_delete = None;
+            }
+            Some(column.clone())
+        }
+    };
+
+    let mut migrated = Vec::new();
+    if stmt.migrate {
+        let mut failures = Vec::new();
+        let mut docs = db.store.list(&stmt.name).await?;
+        docs.sort_by(|a, b| a.id.cmp(&b.id));
+
+        for doc in &mut docs {
+            let before = doc.fields.clone();
+            match &stmt.action {
+                AlterAction::AddColumn(_) => {
+                    schema.apply_defaults(doc);
+                }
+                AlterAction::DropColumn(name) => {
+                    doc.fields.remove(name);
+                }
+                AlterAction::RenameColumn { from, to } => {
+                    if let Some(value) = doc.fields.remove(from) {
+                        if doc.fields.contains_key(to) {
+                            failures.push(format!("{} already has a '{}' field", doc.id, to));
+                        }
+                        doc.fields.insert(to.clone(), value);
+                    }
+                }
+                AlterAction::AlterType { column, .. } => {
+                    let field_type = &schema.fields[column].field_type;
+                    if let Some(value) = doc.fields.get_mut(column) {
+                        if let Some(coerced) = crate::schema::coerce_value(field_type, value) {
+                            *value = coerced;
+                        }
+                    }
+                }
+            }
+
+            if let Some(column) = &checked {
+                for error in schema.validate_all(doc) {
+                    let about_column = match &error {
+                        ValidationError::MissingRequired(field) => field == column,
+                        ValidationError::TypeMismatch { field, .. } | ValidationError::MissingReference { field, .. } => {
+                            field == column
+                        }
+                        ValidationError::UniqueViolation(_) | ValidationError::UniqueCombinationViolation(_) => false,
+                    };
+                    if about_column {
+                        failures.push(format!("{}: {}", doc.id, error));
+                    }
+                }
+            }
+            if doc.fields != before {
+                migrated.push(doc.id.clone());
+            }
+        }
+
+        // A new or retyped UNIQUE column must not start out with duplicates
+        if let Some(column) = checked.as_ref().filter(|column| schema.fields[*column].unique) {
+            let mut seen: Vec<&Value> = Vec::new();
+            for doc in &docs {
+                match doc.fields.get(column).filter(|value| **value != Value::Null) {
+                    Some(value) if seen.contains(&value) => {
+                        failures.push(format!("{}: {}", doc.id, ValidationError::UniqueViolation(column.clone())));
+                    }
+                    Some(value) => seen.push(value),
+                    None => {}
+                }
+            }
+        }
+
+        if !failures.is_empty() {
+            anyhow::bail!(
+                "Cannot migrate collection '{}'; nothing was changed:\n  {}",
+                stmt.name,
+                failures.join("\n  ")
+            );
+        }
+
+        let layout = Layout::of(Some(&schema));
+        for doc in docs.iter().filter(|doc| migrated.contains(&doc.id)) {
+            db.store.put(&stmt.name, doc, &layout).await?;
+        }
+    }
+
+    db.schema.register(schema)?;
+    // Indexed columns may have been renamed, dropped or retyped
+    IndexManager::new(&db.root).remove_collection(&stmt.name)?;
+
+    db.git.commit(&stmt.to_string())?;
+
+    Ok(QueryResult::Affected(migrated.len()))
+}
+
 async fn execute_create_view(db: &Database, stmt: CreateViewStmt) -> anyhow::Result<QueryResult> {
     validate_view_name(&stmt.name)?;
+    if stmt.query.as_of.is_some() {
+        anyhow::bail!("Views always show current documents; AS OF can't be used in a view");
+    }
     // Also validate the source collection
-    validate_collection_name(&stmt.query.from)?;
+    if system::is_system(&stmt.query.from) {
+        if !system::exists(&stmt.query.from) {
+            anyhow::bail!("Collection '{}' does not exist", stmt.query.from);
+        }
+    } else if let Some(view) = mdql::view_source(&stmt.query.from) {
+        // The view must exist and not read this one, directly or through others
+        from_view::resolve(view, std::slice::from_ref(&stmt.name), |view| read_view_file(db, view))?;
+    } else {
+        validate_collection_name(&stmt.query.from)?;
+    }
     // Validate template if provided
     if let Some(ref template) = stmt.template {
         validate_template_name(template)?;
     }
+    for (i, format) in stmt.formats.iter().enumerate() {
+        if stmt.formats[..i].contains(format) {
+            anyhow::bail!("Format '{}' is listed more than once", format.name());
+        }
+    }
+    // Both feeds would be written to feed.xml
+    if stmt.formats.iter().filter(|format| format.is_feed()).count() > 1 {
+        anyhow::bail!("A view has one feed; list 'rss' or 'atom' in FORMATS, not both");
+    }
+    if stmt.feed.is_some() && !stmt.formats.iter().any(|format| format.is_feed()) {
+        anyhow::bail!("FEED sets up a view's feed; list 'rss' or 'atom' in FORMATS too");
+    }
+    if let Some(ref hook) = stmt.render_hook {
+        if !db.config.render_hooks.contains_key(hook) {
+            anyhow::bail!("Render hook '{}' is not declared under render_hooks in .mdby/config.yaml", hook);
+        }
+    }
+    if let Some(ref delivery) = stmt.delivery {
+        if !db.config.delivery_targets.contains_key(&delivery.target) {
+            anyhow::bail!(
+                "Delivery target '{}' is not declared under delivery_targets in .mdby/config.yaml",
+                delivery.target
+            );
+        }
+        if let Some(ref schedule) = delivery.schedule {
+            schedule.parse::<Schedule>()?;
+        }
+    }
 
     // Views are stored in .mdby/views/{name}.yaml
     let view_path = db.root.join(".mdby").join("views");
@@ -251,10 +1343,17 @@ async fn execute_create_view(db: &Database, stmt: CreateViewStmt) -> anyhow::Res
     // Serialize view definition
     let view_def = serde_yaml::to_string(&ViewDefinition {
         name: stmt.name.clone(),
+        params: stmt.params,
         query: serde_json::to_value(&stmt.query)?,
         template: stmt.template,
+        formats: stmt.formats,
+        feed: stmt.feed,
+        features: stmt.features,
+        render_hook: stmt.render_hook,
+        delivery: stmt.delivery,
     })?;
 
+    crate::storage::journal::preserve(&db.root, &view_file)?;
     tokio::fs::write(&view_file, view_def).await?;
 
     db.git.commit(&format!("CREATE VIEW {}", stmt.name))?;
@@ -264,13 +1363,12 @@ async fn execute_create_view(db: &Database, stmt: CreateViewStmt) -> anyhow::Res
 
 async fn execute_drop_collection(db: &Database, name: &str) -> anyhow::Result<QueryResult> {
     validate_collection_name(name)?;
-    let collection_path = db.root.join("collections").join(name);
-
-    if !collection_path.exists() {
+    if !db.store.exists(name).await? {
         anyhow::bail!("Collection '{}' does not exist", name);
     }
 
-    tokio::fs::remove_dir_all(&collection_path).await?;
+    db.store.drop_collection(name).await?;
+    IndexManager::new(&db.root).remove_collection(name)?;
 
     db.git.commit(&format!("DROP COLLECTION {}", name))?;
 
@@ -285,6 +1383,7 @@ async fn execute_drop_view(db: &Database, name: &str) -> anyhow::Result<QueryRes
         anyhow::bail!("View '{}' does not exist", name);
     }
 
+    crate::storage::journal::preserve(&db.root, &view_file)?;
     tokio::fs::remove_file(&view_file).await?;
 
     // Also remove generated view output
@@ -299,25 +1398,18 @@ async fn execute_drop_view(db: &Database, name: &str) -> anyhow::Result<QueryRes
 }
 
 async fn execute_show_collections(db: &Database) -> anyhow::Result<QueryResult> {
-    let collections_path = db.root.join("collections");
-    let mut collections = Vec::new();
-
-    if collections_path.exists() {
-        let mut entries = tokio::fs::read_dir(&collections_path).await?;
-        while let Some(entry) = entries.next_entry().await? {
-            if entry.file_type().await?.is_dir() {
-                if let Some(name) = entry.file_name().to_str() {
-                    collections.push(name.to_string());
-                }
-            }
-        }
+    if let Some(collections) = from_replica(db, |tree| tree.collections())? {
+        return Ok(QueryResult::Collections(collections));
     }
 
-    collections.sort();
-    Ok(QueryResult::Collections(collections))
+    Ok(QueryResult::Collections(db.store.collections().await?))
 }
 
 async fn execute_show_views(db: &Database) -> anyhow::Result<QueryResult> {
+    if let Some(views) = from_replica(db, |tree| tree.views())? {
+        return Ok(QueryResult::Views(views));
+    }
+
     let views_path = db.root.join(".mdby").join("views");
     let mut views = Vec::new();
 
@@ -325,7 +1417,7 @@ async fn execute_show_views(db: &Database) -> anyhow::Result<QueryResult> {
         let mut entries = tokio::fs::read_dir(&views_path).await?;
         while let Some(entry) = entries.next_entry().await? {
             let path = entry.path();
-            if path.extension().map(|e| e == "yaml").unwrap_or(false) {
+            if crate::views::is_view_definition(&path) {
                 if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
                     views.push(stem.to_string());
                 }
@@ -337,35 +1429,185 @@ async fn execute_show_views(db: &Database) -> anyhow::Result<QueryResult> {
     Ok(QueryResult::Views(views))
 }
 
+/// Reconstruct the CREATE COLLECTION statement from the stored schema
+///
+/// Fields are listed in declaration order. Schema settings with no MDQL syntax
+/// (descriptions, ID strategy, strict mode) are not included.
+async fn execute_show_create_collection(db: &Database, name: &str) -> anyhow::Result<QueryResult> {
+    validate_collection_name(name)?;
+    let exists = match from_replica(db, |tree| tree.collection_exists(name))? {
+        Some(exists) => exists,
+        None => db.store.exists(name).await?,
+    };
+    if !exists {
+        anyhow::bail!("Collection '{}' does not exist", name);
+    }
+
+    let mut columns = Vec::new();
+    let mut unique = Vec::new();
+    if let Some(schema) = db.schema.get(name) {
+        unique = schema.unique.clone();
+        for (field_name, field_def) in &schema.fields {
+            columns.push(mdql::ColumnDef {
+                name: field_name.clone(),
+                data_type: fieldtype_to_datatype(&field_def.field_type),
+                constraints: field_constraints(field_def),
+            });
+        }
+    }
+
+    let stmt = CreateCollectionStmt {
+        name: name.to_string(),
+        columns,
+        unique,
+        if_not_exists: false,
+    };
+    Ok(QueryResult::Definition(stmt.to_string()))
+}
+
+/// MDQL constraints for a schema field, in the order CREATE COLLECTION lists them
+fn field_constraints(field_def: &crate::schema::FieldDef) -> Vec<mdql::Constraint> {
+    let mut constraints = Vec::new();
+    if field_def.required {
+        constraints.push(mdql::Constraint::Required);
+    }
+    if field_def.unique {
+        constraints.push(mdql::Constraint::Unique);
+    }
+    if field_def.indexed {
+        constraints.push(mdql::Constraint::Indexed);
+    }
+    if let Some(default) = &field_def.default {
+        constraints.push(mdql::Constraint::Default(yaml_to_literal(default)));
+    }
+    if let Some(on_delete) = field_def.on_delete {
+        constraints.push(mdql::Constraint::OnDelete(match on_delete {
+            crate::schema::OnDelete::Cascade => mdql::OnDelete::Cascade,
+            crate::schema::OnDelete::SetNull => mdql::OnDelete::SetNull,
+            crate::schema::OnDelete::Restrict => mdql::OnDelete::Restrict,
+        }));
+    }
+    constraints
+}
+
+/// Describe a collection: document count, schema and `_meta.md`/`README.md`
+async fn execute_describe(db: &Database, name: &str) -> anyhow::Result<QueryResult> {
+    validate_collection_name(name)?;
+    let (documents, meta) = match from_replica(db, |tree| Ok((tree.documents(name)?.len(), tree.meta(name)?)))? {
+        Some(read) => read,
+        None => {
+            if !db.store.exists(name).await? {
+                anyhow::bail!("Collection '{}' does not exist", name);
+            }
+            // README.md and _meta.md are files beside the documents
+            let meta = match db.store.markdown() {
+                Some(markdown) => markdown.collection(name).meta().await?,
+                None => None,
+            };
+            (db.store.ids(name).await?.len(), meta)
+        }
+    };
+
+    let schema = db.schema.get(name);
+    let fields = schema
+        .map(|schema| {
+            schema
+                .fields
+                .iter()
+                .map(|(field_name, field_def)| crate::FieldDescription {
+                    name: field_name.clone(),
+                    field_type: fieldtype_to_datatype(&field_def.field_type).to_string(),
+                    constraints: field_constraints(field_def).iter().map(|c| c.to_string()).collect(),
+                    description: field_def.description.clone(),
+                    label: field_def.display_name(db.config.locale.as_deref()).map(str::to_string),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(QueryResult::Description(crate::CollectionDescription {
+        name: name.to_string(),
+        documents,
+        description: schema.and_then(|s| s.description.clone()),
+        meta,
+        fields,
+    }))
+}
+
+/// Reconstruct the CREATE VIEW statement from the stored view definition
+async fn execute_show_create_view(db: &Database, name: &str) -> anyhow::Result<QueryResult> {
+    validate_view_name(name)?;
+    let Some(content) = read_view_file(db, name)? else {
+        anyhow::bail!("View '{}' does not exist", name);
+    };
+    let view_def: ViewDefinition = serde_yaml::from_str(&content)?;
+    let query: SelectStmt = serde_json::from_value(view_def.query)?;
+
+    let stmt = CreateViewStmt {
+        name: view_def.name,
+        params: view_def.params,
+        query: Box::new(query),
+        template: view_def.template,
+        formats: view_def.formats,
+        feed: view_def.feed,
+        features: view_def.features,
+        render_hook: view_def.render_hook,
+        delivery: view_def.delivery,
+        if_not_exists: false,
+    };
+    Ok(QueryResult::Definition(stmt.to_string()))
+}
+
 // Helper functions
 
-fn project_columns(doc: &Document, columns: &[Column]) -> Document {
+fn project_columns(doc: &Document, stmt: &SelectStmt) -> Document {
+    // Qualified columns are named after their field, unless two share a field
+    // name; paths into object fields are named as written
+    let qualified_fields: Vec<&String> = stmt
+        .columns
+        .iter()
+        .filter_map(|col| match col {
+            Column::Qualified { field, .. } => Some(field),
+            _ => None,
+        })
+        .collect();
+
     let mut result = Document::new(&doc.id);
     result.body = doc.body.clone();
     result.path = doc.path.clone();
     result.meta = doc.meta.clone();
 
-    for col in columns {
+    for col in &stmt.columns {
         match col {
             Column::Star => {
-                result.fields = doc.fields.clone();
+                result.fields.extend(doc.fields.clone());
             }
             Column::Field(name) => {
                 if let Some(val) = doc.fields.get(name) {
                     result.fields.insert(name.clone(), val.clone());
                 }
             }
-            Column::Qualified { table: _, field } => {
-                // For non-join queries, just use the field name
-                if let Some(val) = doc.fields.get(field) {
-                    result.fields.insert(field.clone(), val.clone());
+            Column::Qualified { table, field } => {
+                if let Some(val) = join::qualified_value(doc, table, field) {
+                    let name = if !join::is_table(stmt, table) || qualified_fields.iter().filter(|f| *f == &field).count() > 1 {
+                        format!("{}.{}", table, field)
+                    } else {
+                        field.clone()
+                    };
+                    result.fields.insert(name, val);
                 }
             }
+            Column::Special(field @ (SpecialField::Author | SpecialField::Created | SpecialField::Modified)) => {
+                // Git metadata isn't stored on the document, so selecting it adds a field
+                let value = filter::special_value(doc, field).unwrap_or(Value::Null);
+                result.fields.insert(field.to_string(), value);
+            }
             Column::Special(_) => {
                 // Special fields are always available via the doc structure
             }
-            Column::Expr { alias: _, .. } => {
-                // TODO: Evaluate expression and add as alias
+            Column::Expr { expr, alias } => {
+                let name = alias.clone().unwrap_or_else(|| expr.to_string());
+                result.fields.insert(name, filter::value(expr, doc).unwrap_or(Value::Null));
             }
         }
     }
@@ -373,7 +1615,7 @@ fn project_columns(doc: &Document, columns: &[Column]) -> Document {
     result
 }
 
-fn compare_values(a: Option<&Value>, b: Option<&Value>) -> std::cmp::Ordering {
+pub(super) fn compare_values(a: Option<&Value>, b: Option<&Value>) -> std::cmp::Ordering {
     match (a, b) {
         (None, None) => std::cmp::Ordering::Equal,
         (None, Some(_)) => std::cmp::Ordering::Less,
@@ -426,14 +1668,34 @@ fn datatype_to_fieldtype(dt: &mdql::DataType) -> crate::schema::FieldType {
     }
 }
 
-fn evaluate_set_value(expr: &mdql::Expr, doc: &Document) -> Value {
-    match expr {
-        mdql::Expr::Literal(lit) => literal_to_value(lit),
-        mdql::Expr::Column(mdql::Column::Field(name)) => {
-            doc.fields.get(name).cloned().unwrap_or(Value::Null)
+pub(crate) fn fieldtype_to_datatype(ft: &crate::schema::FieldType) -> mdql::DataType {
+    match ft {
+        crate::schema::FieldType::String => mdql::DataType::String,
+        crate::schema::FieldType::Int => mdql::DataType::Int,
+        crate::schema::FieldType::Float => mdql::DataType::Float,
+        crate::schema::FieldType::Bool => mdql::DataType::Bool,
+        crate::schema::FieldType::Date => mdql::DataType::Date,
+        crate::schema::FieldType::DateTime => mdql::DataType::DateTime,
+        crate::schema::FieldType::Object => mdql::DataType::Object,
+        crate::schema::FieldType::Array(inner) => {
+            mdql::DataType::Array(Box::new(fieldtype_to_datatype(inner)))
         }
-        // TODO: Handle more complex expressions
-        _ => Value::Null,
+        crate::schema::FieldType::Ref(name) => mdql::DataType::Ref(name.clone()),
+    }
+}
+
+fn yaml_to_literal(value: &serde_yaml::Value) -> Literal {
+    match value {
+        serde_yaml::Value::Null => Literal::Null,
+        serde_yaml::Value::Bool(b) => Literal::Bool(*b),
+        serde_yaml::Value::Number(n) => match n.as_i64() {
+            Some(i) => Literal::Int(i),
+            None => Literal::Float(n.as_f64().unwrap_or(0.0)),
+        },
+        serde_yaml::Value::String(s) => Literal::String(s.clone()),
+        serde_yaml::Value::Sequence(seq) => Literal::Array(seq.iter().map(yaml_to_literal).collect()),
+        // MDQL has no object literal; fall back to the YAML text
+        other => Literal::String(serde_yaml::to_string(other).unwrap_or_default().trim_end().to_string()),
     }
 }
 
@@ -441,6 +1703,18 @@ fn evaluate_set_value(expr: &mdql::Expr, doc: &Document) -> Value {
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 struct ViewDefinition {
     name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    params: Vec<mdql::ViewParam>,
     query: serde_json::Value,
     template: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    formats: Vec<mdql::ViewFormat>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    feed: Option<mdql::FeedFields>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    features: Vec<mdql::ViewFeature>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    render_hook: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delivery: Option<mdql::Delivery>,
 }
@@ -12,6 +12,27 @@ pub fn evaluate(expr: &Expr, doc: &Document) -> bool {
     }
 }
 
+/// Value of an expression for a document; `None` for NULL
+pub fn value(expr: &Expr, doc: &Document) -> Option<Value> {
+    match evaluate_expr(expr, doc) {
+        ExprResult::Value(Value::Null) | ExprResult::Null => None,
+        ExprResult::Value(v) => Some(v),
+        ExprResult::Bool(b) => Some(Value::Bool(b)),
+    }
+}
+
+/// Value of a special field; git-backed ones are `None` unless attached first
+pub(crate) fn special_value(doc: &Document, field: &SpecialField) -> Option<Value> {
+    match field {
+        SpecialField::Id => Some(Value::String(doc.id.clone())),
+        SpecialField::Body => Some(Value::String(doc.body.clone())),
+        SpecialField::Path => Some(Value::String(doc.path.display().to_string())),
+        SpecialField::Author => doc.meta.author.clone().map(Value::String),
+        SpecialField::Created => doc.meta.created.clone().map(Value::String),
+        SpecialField::Modified => doc.meta.modified.clone().map(Value::String),
+    }
+}
+
 /// Result of expression evaluation
 #[derive(Debug, Clone)]
 enum ExprResult {
@@ -52,25 +73,26 @@ fn evaluate_expr(expr: &Expr, doc: &Document) -> ExprResult {
                         .map(ExprResult::Value)
                         .unwrap_or(ExprResult::Null)
                 }
-                Column::Qualified { table: _, field } => {
-                    // For non-join queries, just use the field name
-                    doc.get_field(field)
+                Column::Qualified { table, field } => {
+                    super::join::qualified_value(doc, table, field)
                         .map(ExprResult::Value)
                         .unwrap_or(ExprResult::Null)
                 }
-                Column::Special(sf) => match sf {
-                    SpecialField::Id => ExprResult::Value(Value::String(doc.id.clone())),
-                    SpecialField::Body => ExprResult::Value(Value::String(doc.body.clone())),
-                    SpecialField::Path => ExprResult::Value(Value::String(doc.path.display().to_string())),
-                    SpecialField::Modified | SpecialField::Created => ExprResult::Null, // TODO
-                },
+                Column::Special(sf) => special_value(doc, sf).map(ExprResult::Value).unwrap_or(ExprResult::Null),
                 Column::Expr { expr, .. } => evaluate_expr(expr, doc),
             }
         }
 
         Expr::BinaryOp { left, op, right } => {
-            let left_val = evaluate_expr(left, doc);
-            let right_val = evaluate_expr(right, doc);
+            let mut left_val = evaluate_expr(left, doc);
+            let mut right_val = evaluate_expr(right, doc);
+            let ordering = matches!(op, BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge);
+            if is_id_column(left) {
+                (left_val, right_val) = id_operands(left_val, right_val, ordering);
+            }
+            if is_id_column(right) {
+                (right_val, left_val) = id_operands(right_val, left_val, ordering);
+            }
             evaluate_binary_op(&left_val, *op, &right_val)
         }
 
@@ -93,7 +115,7 @@ fn evaluate_expr(expr: &Expr, doc: &Document) -> ExprResult {
             ExprResult::Bool(contains)
         }
 
-        Expr::HasTag { tag, column } => {
+        Expr::HasTag { tag, column, .. } => {
             let field_name = column.as_deref().unwrap_or("tags");
             let has_tag = doc.fields.get(field_name)
                 .and_then(|v| v.as_array())
@@ -115,8 +137,12 @@ fn evaluate_expr(expr: &Expr, doc: &Document) -> ExprResult {
 
         Expr::In { expr, values, negated } => {
             let val = evaluate_expr(expr, doc);
+            let id_column = is_id_column(expr);
             let in_list = values.iter().any(|v| {
-                let v_result = evaluate_expr(v, doc);
+                let mut v_result = evaluate_expr(v, doc);
+                if id_column {
+                    v_result = as_id_operand(v_result);
+                }
                 values_equal(&val, &v_result)
             });
             ExprResult::Bool(if *negated { !in_list } else { in_list })
@@ -129,22 +155,94 @@ fn evaluate_expr(expr: &Expr, doc: &Document) -> ExprResult {
         }
 
         Expr::Between { expr, low, high, negated } => {
-            let val = evaluate_expr(expr, doc);
-            let low_val = evaluate_expr(low, doc);
-            let high_val = evaluate_expr(high, doc);
+            let mut val = evaluate_expr(expr, doc);
+            let mut low_val = evaluate_expr(low, doc);
+            let mut high_val = evaluate_expr(high, doc);
+            if is_id_column(expr) {
+                let numeric_bounds = [&low_val, &high_val].iter().all(|v| matches!(v, ExprResult::Value(Value::Int(_))));
+                match numeric_id(&val).filter(|_| numeric_bounds) {
+                    Some(number) => val = number,
+                    None => {
+                        low_val = as_id_operand(low_val);
+                        high_val = as_id_operand(high_val);
+                    }
+                }
+            }
 
             let in_range = compare_values(&val, &low_val) >= 0 &&
                            compare_values(&val, &high_val) <= 0;
             ExprResult::Bool(if *negated { !in_range } else { in_range })
         }
 
-        Expr::Function { name: _, args: _ } => {
-            // TODO: Implement built-in functions
-            ExprResult::Null
+        // Worked out for each row beforehand and stored under its MDQL text
+        Expr::Function { name, .. } if super::reverse_ref::is_reverse_ref(name) => doc
+            .fields
+            .get(&expr.to_string())
+            .cloned()
+            .map(ExprResult::Value)
+            .unwrap_or(ExprResult::Null),
+
+        Expr::Function { name, args } => {
+            let args: Vec<Value> = args
+                .iter()
+                .map(|arg| value(arg, doc).unwrap_or(Value::Null))
+                .collect();
+            ExprResult::Value(super::functions::call(name, &args))
         }
+
+        // A group's aggregates are stored in its row under their MDQL text
+        Expr::Aggregate { .. } => doc
+            .fields
+            .get(&expr.to_string())
+            .cloned()
+            .map(ExprResult::Value)
+            .unwrap_or(ExprResult::Null),
+
+        // Parameters are bound before a view's query runs
+        Expr::Param(_) => ExprResult::Null,
     }
 }
 
+/// Whether an expression refers to the document ID (`id`, `t.id` or `@id`)
+fn is_id_column(expr: &Expr) -> bool {
+    match expr {
+        Expr::Column(Column::Field(name)) => name == "id",
+        Expr::Column(Column::Qualified { field, .. }) => field == "id" || field == "@id",
+        Expr::Column(Column::Special(SpecialField::Id)) => true,
+        _ => false,
+    }
+}
+
+/// Treat a bare integer compared against an ID as the equivalent string
+fn as_id_operand(val: ExprResult) -> ExprResult {
+    match val {
+        ExprResult::Value(Value::Int(i)) => ExprResult::Value(Value::String(i.to_string())),
+        other => other,
+    }
+}
+
+/// An ID that is an integer, as one
+fn numeric_id(id: &ExprResult) -> Option<ExprResult> {
+    match id {
+        ExprResult::Value(Value::String(s)) => s.parse::<i64>().ok().map(|n| ExprResult::Value(Value::Int(n))),
+        _ => None,
+    }
+}
+
+/// An ID and the value it's compared with
+///
+/// Document IDs are always strings, so `id = 123` means `id = '123'`. An
+/// ordering compares an integer ID with an integer as numbers, though, so
+/// `id < 10` holds for '9' and not for '100'.
+fn id_operands(id: ExprResult, other: ExprResult, ordering: bool) -> (ExprResult, ExprResult) {
+    if ordering && matches!(other, ExprResult::Value(Value::Int(_))) {
+        if let Some(number) = numeric_id(&id) {
+            return (number, other);
+        }
+    }
+    (id, as_id_operand(other))
+}
+
 fn evaluate_binary_op(left: &ExprResult, op: BinaryOp, right: &ExprResult) -> ExprResult {
     match op {
         // Logical operators
@@ -181,6 +279,9 @@ fn values_equal(a: &ExprResult, b: &ExprResult) -> bool {
         (ExprResult::Value(Value::Null), ExprResult::Null) => true,
         (ExprResult::Null, ExprResult::Value(Value::Null)) => true,
         (ExprResult::Bool(a), ExprResult::Bool(b)) => a == b,
+        // Numbers compare by value regardless of Int/Float representation
+        (ExprResult::Value(Value::Int(a)), ExprResult::Value(Value::Float(b))) => (*a as f64) == *b,
+        (ExprResult::Value(Value::Float(a)), ExprResult::Value(Value::Int(b))) => *a == (*b as f64),
         (ExprResult::Value(a), ExprResult::Value(b)) => a == b,
         (ExprResult::Bool(a), ExprResult::Value(Value::Bool(b))) => a == b,
         (ExprResult::Value(Value::Bool(a)), ExprResult::Bool(b)) => a == b,
@@ -303,10 +404,10 @@ mod tests {
     #[test]
     fn test_has_tag() {
         let doc = make_doc();
-        let expr = Expr::HasTag { tag: "rust".into(), column: None };
+        let expr = Expr::HasTag { tag: "rust".into(), column: None, param: None };
         assert!(evaluate(&expr, &doc));
 
-        let expr2 = Expr::HasTag { tag: "python".into(), column: None };
+        let expr2 = Expr::HasTag { tag: "python".into(), column: None, param: None };
         assert!(!evaluate(&expr2, &doc));
     }
 
@@ -328,4 +429,64 @@ mod tests {
         };
         assert!(evaluate(&expr, &doc));
     }
+
+    fn parse_where(clause: &str) -> Expr {
+        match mdql::parse(&format!("SELECT * FROM t WHERE {}", clause)).unwrap() {
+            mdql::Statement::Select(s) => s.where_clause.unwrap(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_numeric_literal_comparisons() {
+        let mut doc = Document::new("test-1");
+        doc.set("delta", -3i64);
+        doc.set("ratio", Value::Float(0.5));
+        doc.set("big", 2_000_000i64);
+
+        for clause in [
+            "delta BETWEEN -5 AND -1",
+            "delta NOT BETWEEN -2 AND 2",
+            "delta IN (-3, 7)",
+            "delta = -3.0",
+            "delta < +0",
+            "ratio = .5",
+            "ratio BETWEEN -.5 AND +1",
+            "ratio IN (.25, .5)",
+            "big > 1e6",
+            "big = 2E6",
+        ] {
+            assert!(evaluate(&parse_where(clause), &doc), "expected match for {}", clause);
+        }
+
+        for clause in ["delta BETWEEN -2 AND 2", "delta IN (3, -4)", "ratio > 5e-1", "big < 1e6"] {
+            assert!(!evaluate(&parse_where(clause), &doc), "expected no match for {}", clause);
+        }
+    }
+
+    #[test]
+    fn test_numeric_id_comparisons() {
+        // A joined row, where `t.id` is the qualified ID
+        let doc = super::super::join::qualify(&Document::new("123"), "t");
+
+        for clause in ["id = 123", "@id = 123", "123 = id", "t.id = 123", "id IN (7, 123)", "id = '123'", "id != 124"] {
+            assert!(evaluate(&parse_where(clause), &doc), "expected match for {}", clause);
+        }
+        for clause in ["id = 124", "@id IN (1, 2)", "id != 123"] {
+            assert!(!evaluate(&parse_where(clause), &doc), "expected no match for {}", clause);
+        }
+
+        // Orderings compare numeric IDs as numbers, not text
+        let nine = Document::new("9");
+        let hundred = Document::new("100");
+        for clause in ["id < 10", "10 > id", "id <= 9", "@id BETWEEN 1 AND 20"] {
+            assert!(evaluate(&parse_where(clause), &nine), "expected match for {}", clause);
+            assert!(!evaluate(&parse_where(clause), &hundred), "expected no match for {}", clause);
+        }
+        for clause in ["id > 10", "20 < id", "id >= 100", "id NOT BETWEEN 1 AND 20"] {
+            assert!(evaluate(&parse_where(clause), &hundred), "expected match for {}", clause);
+            assert!(!evaluate(&parse_where(clause), &nine), "expected no match for {}", clause);
+        }
+        assert!(evaluate(&parse_where("id < 'a'"), &hundred), "strings still compare as text");
+    }
 }
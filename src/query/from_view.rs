@@ -0,0 +1,95 @@
+//! Selecting from saved views (`SELECT * FROM view:active_todos`)
+//!
+//! A `view:name` source reads the rows of the view's query, so a saved query
+//! can be narrowed further instead of copying its WHERE clause. The rows are
+//! those the view's SELECT returns: filtered, sorted, limited and projected
+//! to its columns, before the outer query applies its own clauses.
+//!
+//! A view may select from another view in turn. [`resolve`] follows the
+//! chain, failing on one that comes back to a view already in it, and on a
+//! parameterized view, which has nothing to bind its parameters.
+
+use mdql::{view_source, SelectStmt, ViewParam};
+
+use crate::validation::validate_view_name;
+
+/// The parts of a view definition (`/.mdby/views/{name}.yaml`) a SELECT needs
+#[derive(serde::Deserialize)]
+struct Definition {
+    #[serde(default)]
+    params: Vec<ViewParam>,
+    query: serde_json::Value,
+}
+
+/// The query of view `name`, then that of each view it selects from in turn
+///
+/// `chain` holds the views already being resolved, outermost first, whose
+/// reading `name` would be a cycle. `read` returns a view's definition file,
+/// or `None` if there is no such view.
+pub(crate) fn resolve(
+    name: &str,
+    chain: &[String],
+    read: impl Fn(&str) -> anyhow::Result<Option<String>>,
+) -> anyhow::Result<Vec<SelectStmt>> {
+    let mut chain = chain.to_vec();
+    let mut queries = Vec::new();
+    let mut name = name.to_string();
+    loop {
+        validate_view_name(&name)?;
+        if chain.contains(&name) {
+            chain.push(name);
+            anyhow::bail!("Views select from each other in a cycle: {}", chain.join(" -> "));
+        }
+        let Some(content) = read(&name)? else {
+            anyhow::bail!("View '{}' does not exist", name);
+        };
+        let definition: Definition = serde_yaml::from_str(&content)?;
+        if !definition.params.is_empty() {
+            anyhow::bail!("View '{}' has parameters, so it can't be selected from", name);
+        }
+        let query: SelectStmt = serde_json::from_value(definition.query)?;
+        let next = view_source(&query.from).map(str::to_string);
+        chain.push(name);
+        queries.push(query);
+        match next {
+            Some(next) => name = next,
+            None => return Ok(queries),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn definition(create: &str) -> String {
+        let mdql::Statement::CreateView(view) = mdql::parse(create).unwrap() else {
+            panic!("not a view");
+        };
+        serde_yaml::to_string(&serde_json::json!({ "params": view.params, "query": view.query })).unwrap()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let views = HashMap::from([
+            ("open", definition("CREATE VIEW open AS SELECT * FROM todos WHERE done = false")),
+            ("urgent", definition("CREATE VIEW urgent AS SELECT * FROM view:open WHERE priority > 3")),
+            ("a", definition("CREATE VIEW a AS SELECT * FROM view:b")),
+            ("b", definition("CREATE VIEW b AS SELECT * FROM view:a")),
+            ("by_tag", definition("CREATE VIEW by_tag(tag STRING) AS SELECT * FROM todos WHERE HAS TAG :tag")),
+        ]);
+        let read = |name: &str| Ok(views.get(name).cloned());
+
+        let queries = resolve("urgent", &[], read).unwrap();
+        assert_eq!(queries.iter().map(|query| query.from.as_str()).collect::<Vec<_>>(), ["view:open", "todos"]);
+
+        let err = resolve("a", &[], read).unwrap_err().to_string();
+        assert!(err.contains("a -> b -> a"), "{}", err);
+        let err = resolve("open", &["open".to_string()], read).unwrap_err().to_string();
+        assert!(err.contains("open -> open"), "{}", err);
+
+        assert!(resolve("by_tag", &[], read).unwrap_err().to_string().contains("has parameters"));
+        assert!(resolve("missing", &[], read).unwrap_err().to_string().contains("does not exist"));
+    }
+}
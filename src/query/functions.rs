@@ -0,0 +1,222 @@
+//! Built-in functions for expressions (`LOWER(title)`, `NOW()`)
+//!
+//! Functions work anywhere an expression does: in WHERE and HAVING, in
+//! selected columns, in JOIN conditions and as UPDATE SET values. Names are
+//! case-insensitive. A NULL argument gives NULL, except to COALESCE, whose
+//! job is replacing it. Unknown names and wrong argument counts are rejected
+//! before a statement runs ([`check`]).
+//!
+//! Dates and datetimes are strings, as stored in frontmatter: `NOW()` is an
+//! RFC 3339 timestamp in UTC and `DATE()` an ISO date, so both compare with
+//! stored values as text.
+
+use std::cell::RefCell;
+
+use mdql::{Column, Expr, SelectStmt, Statement};
+
+use super::executor::expr_references;
+use crate::storage::document::Value;
+
+/// A built-in function
+struct Function {
+    name: &'static str,
+    /// Fewest and most arguments; `None` for no upper limit
+    arity: (usize, Option<usize>),
+    /// Whether NULL arguments are passed through rather than giving NULL
+    takes_null: bool,
+    call: fn(&[Value]) -> Value,
+}
+
+const FUNCTIONS: &[Function] = &[
+    Function { name: "LOWER", arity: (1, Some(1)), takes_null: false, call: lower },
+    Function { name: "UPPER", arity: (1, Some(1)), takes_null: false, call: upper },
+    Function { name: "LENGTH", arity: (1, Some(1)), takes_null: false, call: length },
+    Function { name: "NOW", arity: (0, Some(0)), takes_null: false, call: now },
+    Function { name: "DATE", arity: (0, Some(1)), takes_null: false, call: date },
+    Function { name: "COALESCE", arity: (1, None), takes_null: true, call: coalesce },
+    Function { name: super::reverse_ref::NAME, arity: (2, Some(2)), takes_null: false, call: reverse_ref },
+];
+
+fn lookup(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|function| function.name.eq_ignore_ascii_case(name))
+}
+
+/// Call a function on its argument values; NULL for an unknown function
+pub(crate) fn call(name: &str, args: &[Value]) -> Value {
+    match lookup(name) {
+        Some(function) if function.takes_null || !args.iter().any(|arg| matches!(arg, Value::Null)) => {
+            (function.call)(args)
+        }
+        _ => Value::Null,
+    }
+}
+
+/// Check that every function a statement calls exists and gets a number of
+/// arguments it takes
+pub(crate) fn check(stmt: &Statement) -> anyhow::Result<()> {
+    let error = RefCell::new(None);
+    let invalid = |expr: &Expr| match expr {
+        Expr::Function { name, args } => match check_call(name, args.len()) {
+            Ok(()) => false,
+            Err(e) => {
+                error.replace(Some(e));
+                true
+            }
+        },
+        _ => false,
+    };
+
+    // Stops at the first invalid call, which `invalid` kept the error for
+    statement_exprs(stmt).into_iter().any(|expr| expr_references(expr, &invalid));
+    error.into_inner().map_or(Ok(()), Err)
+}
+
+fn check_call(name: &str, count: usize) -> anyhow::Result<()> {
+    let Some(function) = lookup(name) else {
+        let names: Vec<&str> = FUNCTIONS.iter().map(|function| function.name).collect();
+        anyhow::bail!("Unknown function '{}'. Available functions: {}", name, names.join(", "));
+    };
+    let (min, max) = function.arity;
+    if count < min || max.is_some_and(|max| count > max) {
+        let expected = match max {
+            Some(max) if max == min => min.to_string(),
+            Some(max) => format!("{} to {}", min, max),
+            None => format!("at least {}", min),
+        };
+        anyhow::bail!("{} takes {} argument(s), got {}", function.name, expected, count);
+    }
+    Ok(())
+}
+
+/// Whether a SELECT calls `NOW()` or `DATE()`, whose results change with
+/// the time it runs
+pub(crate) fn reads_clock(select: &SelectStmt) -> bool {
+    let clock = |expr: &Expr| match expr {
+        Expr::Function { name, args } => args.is_empty() && ["NOW", "DATE"].iter().any(|f| f.eq_ignore_ascii_case(name)),
+        _ => false,
+    };
+    select_exprs(select).into_iter().any(|expr| expr_references(expr, &clock))
+}
+
+/// Every expression written in a statement
+pub(super) fn statement_exprs(stmt: &Statement) -> Vec<&Expr> {
+    match stmt {
+        Statement::Select(select) => select_exprs(select),
+        Statement::Explain(select) => select_exprs(select),
+        Statement::CreateView(create) => select_exprs(&create.query),
+        Statement::Update(update) => update
+            .set
+            .iter()
+            .map(|set| &set.value)
+            .chain(update.where_clause.as_ref())
+            .collect(),
+        Statement::Delete(delete) => delete.where_clause.iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Every expression written in a SELECT
+pub(super) fn select_exprs(select: &SelectStmt) -> Vec<&Expr> {
+    select
+        .columns
+        .iter()
+        .filter_map(|column| match column {
+            Column::Expr { expr, .. } => Some(expr.as_ref()),
+            _ => None,
+        })
+        .chain(select.where_clause.as_ref())
+        .chain(select.having.as_ref())
+        .chain(select.joins.iter().map(|join| &join.on))
+        .collect()
+}
+
+fn lower(args: &[Value]) -> Value {
+    args[0].as_str().map_or(Value::Null, |s| Value::String(s.to_lowercase()))
+}
+
+fn upper(args: &[Value]) -> Value {
+    args[0].as_str().map_or(Value::Null, |s| Value::String(s.to_uppercase()))
+}
+
+/// Characters in a string, or items in an array or object
+fn length(args: &[Value]) -> Value {
+    match &args[0] {
+        Value::String(s) => Value::Int(s.chars().count() as i64),
+        Value::Array(items) => Value::Int(items.len() as i64),
+        Value::Object(fields) => Value::Int(fields.len() as i64),
+        _ => Value::Null,
+    }
+}
+
+fn now(_: &[Value]) -> Value {
+    Value::String(chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+}
+
+/// Today's date in UTC, or the date a date or datetime starts with
+fn date(args: &[Value]) -> Value {
+    let Some(arg) = args.first() else {
+        return Value::String(chrono::Utc::now().format("%Y-%m-%d").to_string());
+    };
+    arg.as_str()
+        .and_then(|s| s.get(..10))
+        .and_then(|s| chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok())
+        .map_or(Value::Null, |date| Value::String(date.to_string()))
+}
+
+/// Worked out per row before evaluation (see `reverse_ref.rs`); NULL where
+/// it wasn't
+fn reverse_ref(_: &[Value]) -> Value {
+    Value::Null
+}
+
+fn coalesce(args: &[Value]) -> Value {
+    args.iter().find(|arg| !matches!(arg, Value::Null)).cloned().unwrap_or(Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.into())
+    }
+
+    #[test]
+    fn test_call() {
+        assert_eq!(call("lower", &[string("Rust Notes")]), string("rust notes"));
+        assert_eq!(call("UPPER", &[string("straße")]), string("STRASSE"));
+        assert_eq!(call("LENGTH", &[string("héllo")]), Value::Int(5));
+        assert_eq!(call("LENGTH", &[Value::Array(vec![Value::Int(1), Value::Int(2)])]), Value::Int(2));
+        assert_eq!(call("DATE", &[string("2024-03-18T09:30:00Z")]), string("2024-03-18"));
+        assert_eq!(call("DATE", &[string("soon")]), Value::Null);
+        assert_eq!(call("LOWER", &[Value::Null]), Value::Null);
+        assert_eq!(call("COALESCE", &[Value::Null, string("a"), string("b")]), string("a"));
+        assert_eq!(call("NOPE", &[]), Value::Null);
+        let Value::String(now) = call("NOW", &[]) else {
+            panic!("Expected a string");
+        };
+        assert!(chrono::DateTime::parse_from_rfc3339(&now).is_ok());
+    }
+
+    #[test]
+    fn test_check() {
+        let parse = |q: &str| mdql::parse(q).unwrap();
+        assert!(check(&parse("SELECT UPPER(title) FROM todos WHERE LENGTH(tags) > 1")).is_ok());
+        let unknown = check(&parse("SELECT * FROM todos WHERE SHOUT(title) = 'A'")).unwrap_err();
+        assert!(unknown.to_string().contains("Unknown function 'SHOUT'"));
+        let arity = check(&parse("UPDATE todos SET title = LOWER(title, 'x')")).unwrap_err();
+        assert!(arity.to_string().contains("LOWER takes 1 argument(s), got 2"));
+        assert!(check(&parse("DELETE FROM todos WHERE COALESCE() = 1")).is_err());
+    }
+
+    #[test]
+    fn test_reads_clock() {
+        let select = |q: &str| match mdql::parse(q).unwrap() {
+            Statement::Select(select) => select,
+            _ => panic!("Expected Select"),
+        };
+        assert!(reads_clock(&select("SELECT * FROM todos WHERE due < date()")));
+        assert!(reads_clock(&select("SELECT title, NOW() AS at FROM todos")));
+        assert!(!reads_clock(&select("SELECT * FROM todos WHERE DATE(due) = '2024-03-18'")));
+    }
+}
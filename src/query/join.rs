@@ -0,0 +1,191 @@
+//! JOIN execution
+//!
+//! A row of a joined query is a document whose fields are named after the
+//! collection (or alias) they came from: `orders.total`, `customers.name`,
+//! and `customers.@id` for the ID. A row keeps the ID, path and body of its
+//! FROM document, or of the joined document for the unmatched rows of a
+//! RIGHT JOIN. Joins are applied left to right.
+
+use mdql::{Column, Expr, JoinClause, JoinType, SelectStmt, SpecialField};
+
+use super::filter;
+use crate::storage::document::{Document, Value};
+
+/// Name a collection's fields go by in joined rows
+pub(crate) fn table_name<'a>(collection: &'a str, alias: Option<&'a String>) -> &'a str {
+    alias.map(String::as_str).unwrap_or(collection)
+}
+
+/// A document as a row of `table`, with its fields qualified
+pub(crate) fn qualify(doc: &Document, table: &str) -> Document {
+    let mut row = Document::new(&doc.id);
+    row.path = doc.path.clone();
+    row.body = doc.body.clone();
+    row.meta = doc.meta.clone();
+    row.fields.insert(format!("{}.@id", table), Value::String(doc.id.clone()));
+    for (name, value) in [("@author", &doc.meta.author), ("@created", &doc.meta.created), ("@modified", &doc.meta.modified)] {
+        if let Some(value) = value {
+            row.fields.insert(format!("{}.{}", table, name), Value::String(value.clone()));
+        }
+    }
+    for (key, value) in &doc.fields {
+        row.fields.insert(format!("{}.{}", table, key), value.clone());
+    }
+    row
+}
+
+/// Value of `table.field` in a joined row (`table.id` being `table.@id`), or
+/// of a path into an object field (`metadata.author`)
+pub(crate) fn qualified_value(doc: &Document, table: &str, field: &str) -> Option<Value> {
+    doc.get_path(&format!("{}.{}", table, field))
+        .or_else(|| doc.fields.get(&format!("{}.@{}", table, field)).cloned())
+}
+
+/// Drop the qualifier from columns naming the only collection of a query
+/// without joins, so `todos.title` is `title` and `todos.@id` is `@id`
+///
+/// Whatever is still qualified afterwards is a path into an object field.
+pub(crate) fn unqualify(stmt: &mut SelectStmt) {
+    if !stmt.joins.is_empty() {
+        return;
+    }
+    let from = stmt.from.clone();
+    let tables: Vec<&str> = std::iter::once(from.as_str()).chain(stmt.from_alias.as_deref()).collect();
+    for column in &mut stmt.columns {
+        unqualify_column(column, &tables);
+    }
+    for expr in stmt.where_clause.iter_mut().chain(stmt.having.as_mut()) {
+        unqualify_expr(expr, &tables);
+    }
+    let names = stmt.order_by.iter_mut().map(|order| &mut order.column).chain(stmt.group_by.iter_mut());
+    for name in names {
+        if let Some((_, field)) = name.split_once('.').filter(|(table, _)| tables.contains(table)) {
+            *name = field.to_string();
+        }
+    }
+}
+
+/// [`unqualify`] for the columns of one expression
+pub(crate) fn unqualify_expr(expr: &mut Expr, tables: &[&str]) {
+    match expr {
+        Expr::Column(column) => unqualify_column(column, tables),
+        Expr::BinaryOp { left, right, .. } => {
+            unqualify_expr(left, tables);
+            unqualify_expr(right, tables);
+        }
+        Expr::UnaryOp { expr, .. } | Expr::Like { expr, .. } | Expr::IsNull { expr, .. } => unqualify_expr(expr, tables),
+        Expr::Function { args, .. } => args.iter_mut().for_each(|arg| unqualify_expr(arg, tables)),
+        Expr::Aggregate { arg, .. } => arg.iter_mut().for_each(|arg| unqualify_expr(arg, tables)),
+        Expr::In { expr, values, .. } => {
+            unqualify_expr(expr, tables);
+            values.iter_mut().for_each(|value| unqualify_expr(value, tables));
+        }
+        Expr::Between { expr, low, high, .. } => {
+            for expr in [expr, low, high] {
+                unqualify_expr(expr, tables);
+            }
+        }
+        Expr::Literal(_) | Expr::Param(_) | Expr::Contains { .. } | Expr::HasTag { .. } => {}
+    }
+}
+
+fn unqualify_column(column: &mut Column, tables: &[&str]) {
+    match column {
+        Column::Qualified { table, field } if tables.contains(&table.as_str()) => {
+            let special = [
+                SpecialField::Id,
+                SpecialField::Body,
+                SpecialField::Path,
+                SpecialField::Modified,
+                SpecialField::Created,
+                SpecialField::Author,
+            ]
+            .into_iter()
+            .find(|special| special.to_string() == *field);
+            *column = match (special, field.split_once('.')) {
+                (Some(special), _) => Column::Special(special),
+                (None, Some((object, path))) => Column::Qualified { table: object.to_string(), field: path.to_string() },
+                (None, None) => Column::Field(field.clone()),
+            };
+        }
+        Column::Expr { expr, .. } => unqualify_expr(expr, tables),
+        _ => {}
+    }
+}
+
+/// Whether `table` names the FROM collection or a joined one, rather than
+/// the object field a dotted path starts with
+pub(crate) fn is_table(stmt: &SelectStmt, table: &str) -> bool {
+    table == stmt.from
+        || stmt.from_alias.as_deref() == Some(table)
+        || stmt.joins.iter().any(|join| table == join.collection || join.alias.as_deref() == Some(table))
+}
+
+/// Join `rows` with `docs`, the documents of the joined collection
+pub(crate) fn join(rows: Vec<Document>, docs: &[Document], join: &JoinClause) -> Vec<Document> {
+    let table = table_name(&join.collection, join.alias.as_ref());
+    let others: Vec<Document> = docs.iter().map(|doc| qualify(doc, table)).collect();
+    let mut matched = vec![false; others.len()];
+
+    let mut joined = Vec::new();
+    for row in rows {
+        let mut found = false;
+        for (other, matched) in others.iter().zip(matched.iter_mut()) {
+            let mut combined = row.clone();
+            combined.fields.extend(other.fields.iter().map(|(k, v)| (k.clone(), v.clone())));
+            if filter::evaluate(&join.on, &combined) {
+                found = true;
+                *matched = true;
+                joined.push(combined);
+            }
+        }
+        if !found && join.join_type == JoinType::Left {
+            joined.push(row);
+        }
+    }
+
+    if join.join_type == JoinType::Right {
+        joined.extend(others.into_iter().zip(matched).filter(|(_, matched)| !matched).map(|(other, _)| other));
+    }
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn join_clause(join_type: JoinType) -> JoinClause {
+        match mdql::parse("SELECT * FROM orders AS o JOIN customers AS c ON o.customer = c.@id").unwrap() {
+            mdql::Statement::Select(select) => JoinClause { join_type, ..select.joins[0].clone() },
+            _ => unreachable!(),
+        }
+    }
+
+    fn order(id: &str, customer: &str) -> Document {
+        let mut doc = Document::new(id);
+        doc.set("customer", customer);
+        qualify(&doc, "o")
+    }
+
+    #[test]
+    fn test_join_types() {
+        let orders = vec![order("o1", "ada"), order("o2", "nobody"), order("o3", "ada")];
+        let mut ada = Document::new("ada");
+        ada.set("name", "Ada");
+        let customers = vec![ada, Document::new("grace")];
+
+        let inner = join(orders.clone(), &customers, &join_clause(JoinType::Inner));
+        let ids: Vec<&str> = inner.iter().map(|row| row.id.as_str()).collect();
+        assert_eq!(ids, vec!["o1", "o3"]);
+        assert_eq!(inner[0].fields.get("c.name"), Some(&Value::String("Ada".into())));
+        assert_eq!(qualified_value(&inner[0], "c", "@id"), Some(Value::String("ada".into())));
+
+        let left = join(orders.clone(), &customers, &join_clause(JoinType::Left));
+        assert_eq!(left.len(), 3);
+        assert_eq!(left[1].fields.get("c.name"), None);
+
+        let right = join(orders, &customers, &join_clause(JoinType::Right));
+        let ids: Vec<&str> = right.iter().map(|row| row.id.as_str()).collect();
+        assert_eq!(ids, vec!["o1", "o3", "grace"]);
+    }
+}
@@ -2,7 +2,24 @@
 //!
 //! Executes MDQL statements against the database.
 
+mod aggregate;
 mod executor;
 pub mod filter;
+mod from_view;
+mod functions;
+mod join;
+mod params;
+pub mod plan;
+pub mod related;
+mod reverse_ref;
 
-pub use executor::execute;
+pub use executor::{execute, removals};
+pub(crate) use executor::{
+    attach_history, check_insertable, check_view, check_refs, check_unique, fieldtype_to_datatype, indexed_fields, layout, update_indexes, uses_history,
+};
+pub(crate) use aggregate::{group, is_aggregate};
+pub(crate) use join::{join, qualify, table_name, unqualify};
+pub(crate) use from_view::resolve as resolve_view;
+pub(crate) use functions::reads_clock;
+pub(crate) use params::bind as bind_params;
+pub(crate) use reverse_ref::{attach as attach_reverse_refs, calls as reverse_ref_calls, detach as detach_reverse_refs};
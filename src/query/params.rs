@@ -0,0 +1,201 @@
+//! View parameters (`CREATE VIEW by_tag(tag STRING) AS ... HAS TAG :tag`)
+//!
+//! A parameterized view's query refers to its parameters as `:name`, as a
+//! value anywhere an expression goes or as the tag of HAS TAG. Parameters
+//! are checked when the view is created and bound when it is rendered: each
+//! value, given as text, is converted to the parameter's type and put in
+//! place of every `:name` before the query runs. `:name` outside a view
+//! definition is an error.
+
+use std::collections::BTreeMap;
+
+use mdql::{Column, DataType, Expr, Literal, SelectStmt, Statement, ViewParam};
+
+use super::executor::expr_references;
+use super::functions::statement_exprs;
+
+/// Check a statement's parameters: a view's must be declared once each with
+/// a scalar type and used only if declared, and other statements can't have
+/// any
+pub(crate) fn check(stmt: &Statement) -> anyhow::Result<()> {
+    let used = used(stmt);
+    let Statement::CreateView(create) = stmt else {
+        return match used.first() {
+            Some(name) => anyhow::bail!("Parameters like :{} can only be used in a view definition", name),
+            None => Ok(()),
+        };
+    };
+
+    for (i, param) in create.params.iter().enumerate() {
+        if create.params[..i].iter().any(|other| other.name == param.name) {
+            anyhow::bail!("Parameter '{}' is declared more than once", param.name);
+        }
+        if matches!(param.data_type, DataType::Array(_) | DataType::Object | DataType::Ref(_)) {
+            anyhow::bail!(
+                "Parameter '{}' has type {}; parameters must be STRING, INT, FLOAT, BOOL, DATE or DATETIME",
+                param.name,
+                param.data_type
+            );
+        }
+    }
+    if let Some(name) = used.iter().find(|name| !create.params.iter().any(|param| &param.name == *name)) {
+        anyhow::bail!("Parameter :{} is not declared; declare it as CREATE VIEW {}({} STRING)", name, create.name, name);
+    }
+    if !create.params.is_empty() && create.delivery.is_some() {
+        anyhow::bail!("A view with parameters is rendered on demand and can't be delivered");
+    }
+    Ok(())
+}
+
+/// Names of the parameters a statement refers to, in order of use
+fn used(stmt: &Statement) -> Vec<String> {
+    let names = std::cell::RefCell::new(Vec::new());
+    let collect = |expr: &Expr| {
+        match expr {
+            Expr::Param(name) | Expr::HasTag { param: Some(name), .. } => names.borrow_mut().push(name.clone()),
+            _ => {}
+        }
+        false
+    };
+    for expr in statement_exprs(stmt) {
+        expr_references(expr, &collect);
+    }
+    names.into_inner()
+}
+
+/// Put the values of a view's parameters in place of its `:name`s
+///
+/// Every parameter needs a value, given as text and converted to the
+/// parameter's type; a value for a parameter the view doesn't have is an
+/// error too.
+pub(crate) fn bind(query: &mut SelectStmt, params: &[ViewParam], values: &BTreeMap<String, String>) -> anyhow::Result<()> {
+    if let Some(name) = values.keys().find(|name| !params.iter().any(|param| &param.name == *name)) {
+        let declared: Vec<&str> = params.iter().map(|param| param.name.as_str()).collect();
+        anyhow::bail!("The view has no parameter '{}'; its parameters are: {}", name, declared.join(", "));
+    }
+
+    let mut bound = BTreeMap::new();
+    for param in params {
+        let text = values
+            .get(&param.name)
+            .ok_or_else(|| anyhow::anyhow!("Missing a value for parameter '{}' ({})", param.name, param.data_type))?;
+        bound.insert(param.name.as_str(), (text.as_str(), literal(&param.data_type, text).ok_or_else(|| {
+            anyhow::anyhow!("Parameter '{}' must be {}, got '{}'", param.name, param.data_type, text)
+        })?));
+    }
+
+    let exprs = query
+        .columns
+        .iter_mut()
+        .filter_map(|column| match column {
+            Column::Expr { expr, .. } => Some(expr.as_mut()),
+            _ => None,
+        })
+        .chain(query.where_clause.as_mut())
+        .chain(query.having.as_mut())
+        .chain(query.joins.iter_mut().map(|join| &mut join.on));
+    for expr in exprs {
+        bind_expr(expr, &bound);
+    }
+    Ok(())
+}
+
+/// [`bind`] for one expression, given each parameter's text and value
+fn bind_expr(expr: &mut Expr, bound: &BTreeMap<&str, (&str, Literal)>) {
+    match expr {
+        Expr::Param(name) => {
+            if let Some((_, value)) = bound.get(name.as_str()) {
+                *expr = Expr::Literal(value.clone());
+            }
+        }
+        Expr::HasTag { tag, param, .. } => {
+            if let Some((text, _)) = param.as_deref().and_then(|name| bound.get(name)) {
+                *tag = text.to_string();
+                *param = None;
+            }
+        }
+        Expr::BinaryOp { left, right, .. } => {
+            bind_expr(left, bound);
+            bind_expr(right, bound);
+        }
+        Expr::UnaryOp { expr, .. } | Expr::Like { expr, .. } | Expr::IsNull { expr, .. } => bind_expr(expr, bound),
+        Expr::Column(Column::Expr { expr, .. }) => bind_expr(expr, bound),
+        Expr::Function { args, .. } => args.iter_mut().for_each(|arg| bind_expr(arg, bound)),
+        Expr::Aggregate { arg, .. } => arg.iter_mut().for_each(|arg| bind_expr(arg, bound)),
+        Expr::In { expr, values, .. } => {
+            bind_expr(expr, bound);
+            values.iter_mut().for_each(|value| bind_expr(value, bound));
+        }
+        Expr::Between { expr, low, high, .. } => {
+            for expr in [expr, low, high] {
+                bind_expr(expr, bound);
+            }
+        }
+        Expr::Literal(_) | Expr::Column(_) | Expr::Contains { .. } => {}
+    }
+}
+
+/// A parameter value as a literal of its type; dates and datetimes stay
+/// strings, as stored in frontmatter
+fn literal(data_type: &DataType, text: &str) -> Option<Literal> {
+    let text = text.trim();
+    match data_type {
+        DataType::String => Some(Literal::String(text.to_string())),
+        DataType::Int => text.parse().ok().map(Literal::Int),
+        DataType::Float => text.parse::<f64>().ok().filter(|f| f.is_finite()).map(Literal::Float),
+        DataType::Bool => match text.to_lowercase().as_str() {
+            "true" => Some(Literal::Bool(true)),
+            "false" => Some(Literal::Bool(false)),
+            _ => None,
+        },
+        DataType::Date => chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .ok()
+            .map(|date| Literal::String(date.to_string())),
+        DataType::DateTime => chrono::DateTime::parse_from_rfc3339(text)
+            .ok()
+            .map(|_| Literal::String(text.to_string())),
+        DataType::Array(_) | DataType::Object | DataType::Ref(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_view(query: &str) -> Statement {
+        mdql::parse(query).unwrap()
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check(&create_view("CREATE VIEW by_tag(tag STRING) AS SELECT * FROM notes WHERE HAS TAG :tag")).is_ok());
+        let undeclared = check(&create_view("CREATE VIEW v(tag STRING) AS SELECT * FROM notes WHERE priority > :min"));
+        assert!(undeclared.unwrap_err().to_string().contains("Parameter :min is not declared"));
+        assert!(check(&create_view("CREATE VIEW v(a INT, a INT) AS SELECT * FROM notes")).is_err());
+        assert!(check(&create_view("CREATE VIEW v(a ARRAY<STRING>) AS SELECT * FROM notes")).is_err());
+        let outside = check(&mdql::parse("SELECT * FROM notes WHERE priority > :min").unwrap()).unwrap_err();
+        assert!(outside.to_string().contains("can only be used in a view"));
+    }
+
+    #[test]
+    fn test_bind() {
+        let Statement::CreateView(create) =
+            create_view("CREATE VIEW v(tag STRING, min INT) AS SELECT * FROM notes WHERE HAS TAG :tag AND priority >= :min")
+        else {
+            panic!("Expected CreateView");
+        };
+        let values = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+
+        let mut query = *create.query.clone();
+        bind(&mut query, &create.params, &values(&[("tag", "rust"), ("min", "2")])).unwrap();
+        assert_eq!(query.where_clause.unwrap().to_string(), "HAS TAG 'rust' AND priority >= 2");
+
+        let mut query = *create.query.clone();
+        let missing = bind(&mut query, &create.params, &values(&[("tag", "rust")])).unwrap_err();
+        assert!(missing.to_string().contains("Missing a value for parameter 'min'"));
+        let wrong = bind(&mut query, &create.params, &values(&[("tag", "rust"), ("min", "lots")])).unwrap_err();
+        assert!(wrong.to_string().contains("Parameter 'min' must be INT, got 'lots'"));
+        let unknown = bind(&mut query, &create.params, &values(&[("tag", "a"), ("min", "1"), ("max", "2")])).unwrap_err();
+        assert!(unknown.to_string().contains("no parameter 'max'"));
+    }
+}
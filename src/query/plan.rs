@@ -0,0 +1,353 @@
+//! Query planning: how a SELECT reads its documents
+//!
+//! [`access`] picks how the FROM collection is read; the executor follows
+//! it, and EXPLAIN reports it with the rest of the plan ([`explain`]). A new
+//! way of narrowing down documents plugs in as another [`Access`], chosen
+//! there.
+//!
+//! A WHERE clause can use an index when one of its AND-ed conditions refers
+//! to a single indexed field and nothing else (`status = 'open'`,
+//! `priority BETWEEN 1 AND 3`, `HAS TAG 'x' IN tags`). The condition is
+//! evaluated once per distinct value in the index, exactly as the filter
+//! would evaluate it per document, and only the documents holding a matching
+//! value are read. The whole WHERE clause is still applied to them afterwards.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+
+use mdql::{Column, Expr, SelectStmt};
+use serde::{Deserialize, Serialize};
+
+use super::executor::{expr_references, indexed_fields, uses_history, view_documents};
+use super::{aggregate, filter};
+use crate::storage::collection::Collection;
+use crate::storage::document::Document;
+use crate::storage::index::IndexManager;
+use crate::validation::validate_collection_name;
+use crate::{system, Database};
+
+/// How a SELECT reads its FROM collection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Access {
+    /// Every document, before filtering
+    FullScan,
+    /// One document at a time, stopping once LIMIT rows match
+    Stream,
+    /// Only the documents the indexes on these fields point to
+    IndexLookup { fields: Vec<String> },
+    /// Every document, from a git tree (a replica, or `AS OF`)
+    Snapshot,
+    /// A system collection, built from git history
+    System,
+    /// The rows of a saved view's query (`FROM view:name`)
+    View,
+    /// No documents, only how many there are, from the IDs the store lists
+    /// (`SELECT COUNT(*)` without WHERE)
+    Count,
+}
+
+/// How the rows of a SELECT are ordered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SortStrategy {
+    /// The order documents are read in
+    None,
+    /// Most similar first, for RELATED TO and SEMANTIC_SEARCH
+    Relevance,
+    /// Every row sorted in memory by the ORDER BY keys
+    InMemory { keys: Vec<String> },
+}
+
+/// The plan of a SELECT (from EXPLAIN)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryPlan {
+    pub collection: String,
+    pub access: Access,
+    /// Documents in the collection
+    pub documents: usize,
+    /// AND-ed WHERE conditions in the order they narrow the documents down
+    pub filters: Vec<PlannedFilter>,
+    pub joins: Vec<PlannedJoin>,
+    /// GROUP BY columns; empty with aggregates alone, which make one group
+    pub group_by: Vec<String>,
+    /// Whether rows are grouped and aggregated
+    pub aggregate: bool,
+    pub sort: SortStrategy,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+    /// Most documents the query reads, joined collections included
+    pub estimated_scanned: usize,
+}
+
+/// One WHERE condition of a [`QueryPlan`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedFilter {
+    /// The condition, as MDQL
+    pub condition: String,
+    /// Field whose index evaluates the condition, if any
+    pub index: Option<String>,
+}
+
+/// One JOIN of a [`QueryPlan`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedJoin {
+    /// The JOIN clause, as MDQL
+    pub join: String,
+    /// Documents in the joined collection, all of which are read
+    pub documents: usize,
+}
+
+/// How to read the FROM collection of a SELECT
+pub(crate) fn access(db: &Database, stmt: &SelectStmt) -> Access {
+    if system::is_system(&stmt.from) {
+        return Access::System;
+    }
+    if mdql::view_source(&stmt.from).is_some() {
+        return Access::View;
+    }
+    if db.git.is_read_only() {
+        return Access::Snapshot;
+    }
+    if counts_only(stmt) {
+        return Access::Count;
+    }
+    // Indexes and streaming work on markdown files
+    if db.store.markdown().is_none() {
+        return Access::FullScan;
+    }
+
+    let fields = index_fields(db, stmt);
+    if !fields.is_empty() {
+        return Access::IndexLookup { fields };
+    }
+
+    // Sorting, joining, grouping, ranking and reverse references need every
+    // document first
+    if !stmt.order_by.is_empty()
+        || !stmt.joins.is_empty()
+        || aggregate::is_aggregate(stmt)
+        || stmt.related_to.is_some()
+        || stmt.semantic_search.is_some()
+        || uses_history(stmt)
+        || !super::reverse_ref::calls(stmt).is_empty()
+    {
+        Access::FullScan
+    } else {
+        Access::Stream
+    }
+}
+
+/// Whether a SELECT asks only how many documents its collection holds, which
+/// the store knows from their file names without reading them
+fn counts_only(stmt: &SelectStmt) -> bool {
+    aggregate::lone_count(stmt).is_some()
+        && stmt.where_clause.is_none()
+        && stmt.having.is_none()
+        && stmt.joins.is_empty()
+        && stmt.order_by.is_empty()
+        && stmt.offset.is_none()
+        && stmt.limit.is_none()
+        && stmt.related_to.is_none()
+        && stmt.semantic_search.is_none()
+}
+
+/// Indexed fields a SELECT's WHERE clause can be narrowed down by
+fn index_fields(db: &Database, stmt: &SelectStmt) -> Vec<String> {
+    let Some(where_clause) = &stmt.where_clause else {
+        return Vec::new();
+    };
+    // Joined and ranked queries read the whole collection
+    if !stmt.joins.is_empty() || stmt.related_to.is_some() || stmt.semantic_search.is_some() {
+        return Vec::new();
+    }
+
+    let indexed = indexed_fields(db, &stmt.from);
+    let mut fields: Vec<String> = Vec::new();
+    for field in conditions(where_clause).into_iter().filter_map(single_field) {
+        if indexed.contains(&field) && !fields.contains(&field) {
+            fields.push(field);
+        }
+    }
+    fields
+}
+
+/// Plan a SELECT without running it
+pub(crate) async fn explain(db: &Database, mut stmt: SelectStmt) -> anyhow::Result<QueryPlan> {
+    super::join::unqualify(&mut stmt);
+    // AS OF reads from a snapshot, like the SELECT would
+    if let Some(revision) = stmt.as_of.take() {
+        let commit = db.git.resolve_revision(&revision)?;
+        let past = db.at_revision(&commit).await?;
+        return Box::pin(explain(&past, stmt)).await;
+    }
+
+    let access = access(db, &stmt);
+    let documents = count_documents(db, &stmt.from).await?;
+    let offset = stmt.offset.unwrap_or(0);
+    let mut estimated_scanned = match (&access, &stmt.where_clause) {
+        (Access::IndexLookup { fields }, Some(where_clause)) => match db.store.markdown() {
+            Some(markdown) => candidates(&IndexManager::new(&db.root), &markdown.collection(&stmt.from), fields, where_clause)
+                .await?
+                .map_or(documents, |ids| ids.len()),
+            None => documents,
+        },
+        // Without a filter, every document read is a row
+        (Access::Stream, None) => stmt.limit.map_or(documents, |limit| documents.min(offset + limit)),
+        (Access::Count, _) => 0,
+        _ => documents,
+    };
+
+    let indexed = match &access {
+        Access::IndexLookup { fields } => fields.clone(),
+        _ => Vec::new(),
+    };
+    let mut filters: Vec<PlannedFilter> = stmt
+        .where_clause
+        .iter()
+        .flat_map(conditions)
+        .map(|condition| PlannedFilter {
+            condition: condition.to_string(),
+            index: single_field(condition).filter(|field| indexed.contains(field)),
+        })
+        .collect();
+    // Indexed conditions narrow the documents down before the rest are checked
+    filters.sort_by_key(|filter| filter.index.is_none());
+
+    let mut joins = Vec::new();
+    for clause in &stmt.joins {
+        let documents = count_documents(db, &clause.collection).await?;
+        estimated_scanned += documents;
+        joins.push(PlannedJoin { join: clause.to_string(), documents });
+    }
+
+    let sort = if !stmt.order_by.is_empty() {
+        SortStrategy::InMemory { keys: stmt.order_by.iter().map(ToString::to_string).collect() }
+    } else if stmt.related_to.is_some() || stmt.semantic_search.is_some() {
+        SortStrategy::Relevance
+    } else {
+        SortStrategy::None
+    };
+
+    Ok(QueryPlan {
+        collection: stmt.from.clone(),
+        access,
+        documents,
+        filters,
+        joins,
+        group_by: stmt.group_by.clone(),
+        aggregate: aggregate::is_aggregate(&stmt),
+        sort,
+        offset: stmt.offset,
+        limit: stmt.limit,
+        estimated_scanned,
+    })
+}
+
+/// Number of documents in a collection or system collection, or of rows in
+/// a view, without reading them where that can be avoided
+pub(super) async fn count_documents(db: &Database, name: &str) -> anyhow::Result<usize> {
+    if system::is_system(name) {
+        return Ok(system::list(&db.git, name)?.len());
+    }
+    if let Some(view) = mdql::view_source(name) {
+        return Ok(view_documents(db, view).await?.len());
+    }
+    validate_collection_name(name)?;
+    if let Some(tree) = db.git.snapshot()? {
+        return Ok(tree.documents(name)?.len());
+    }
+    if !db.store.exists(name).await? {
+        anyhow::bail!("Collection '{}' does not exist", name);
+    }
+    Ok(db.store.ids(name).await?.len())
+}
+
+/// IDs of the documents that can match `where_clause`, or `None` if no
+/// indexed field narrows it down
+pub(crate) async fn candidates(
+    indexes: &IndexManager,
+    collection: &Collection,
+    indexed: &[String],
+    where_clause: &Expr,
+) -> anyhow::Result<Option<BTreeSet<String>>> {
+    let mut candidates: Option<BTreeSet<String>> = None;
+    for condition in conditions(where_clause) {
+        let Some(field) = single_field(condition).filter(|field| indexed.contains(field)) else {
+            continue;
+        };
+        let ids = indexes
+            .lookup(collection, &field, |value| {
+                let mut doc = Document::new("");
+                if let Some(value) = value {
+                    doc.fields.insert(field.clone(), value.clone());
+                }
+                filter::evaluate(condition, &doc)
+            })
+            .await?;
+        candidates = Some(match candidates {
+            Some(previous) => previous.intersection(&ids).cloned().collect(),
+            None => ids,
+        });
+    }
+    Ok(candidates)
+}
+
+/// The conditions of a WHERE clause that must all hold
+fn conditions(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::BinaryOp { left, op: mdql::BinaryOp::And, right } => {
+            let mut all = conditions(left);
+            all.extend(conditions(right));
+            all
+        }
+        other => vec![other],
+    }
+}
+
+/// The only field a condition reads, if it reads exactly one frontmatter
+/// field and nothing else of the document
+fn single_field(expr: &Expr) -> Option<String> {
+    let fields = RefCell::new(BTreeSet::new());
+    let other = expr_references(expr, &|e| match e {
+        Expr::Column(Column::Field(name)) => {
+            fields.borrow_mut().insert(name.clone());
+            false
+        }
+        Expr::HasTag { column, .. } => {
+            fields.borrow_mut().insert(column.clone().unwrap_or_else(|| "tags".to_string()));
+            false
+        }
+        Expr::Column(_) | Expr::Contains { .. } | Expr::Function { .. } | Expr::Aggregate { .. } => true,
+        _ => false,
+    });
+
+    let fields = fields.into_inner();
+    // `id`, `body` and `path` read the document itself rather than a field
+    match (other, fields.len()) {
+        (false, 1) => fields.into_iter().next().filter(|f| !matches!(f.as_str(), "id" | "body" | "path")),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn where_clause(query: &str) -> Expr {
+        match mdql::parse(query).unwrap() {
+            mdql::Statement::Select(select) => select.where_clause.unwrap(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_single_field_conditions() {
+        let expr = where_clause("SELECT * FROM t WHERE status = 'open' AND (p > 1 OR q = 2) AND HAS TAG 'x' AND id = 'a'");
+        let fields: Vec<Option<String>> = conditions(&expr).into_iter().map(single_field).collect();
+        assert_eq!(fields, vec![Some("status".into()), None, Some("tags".into()), None]);
+
+        let expr = where_clause("SELECT * FROM t WHERE CONTAINS('x') AND @author = 'Ada'");
+        assert!(conditions(&expr).into_iter().all(|c| single_field(c).is_none()));
+    }
+}
@@ -0,0 +1,169 @@
+//! Related documents (`RELATED TO 'id'`)
+//!
+//! Each document's string fields and body are split into lowercase words and
+//! weighted by TF-IDF, so words that are frequent in a document but rare in
+//! the collection count the most. Two documents are related by the cosine
+//! similarity of their weight vectors; documents sharing no weighted words
+//! score zero and are left out.
+
+use std::collections::HashMap;
+
+use crate::storage::document::{Document, Value};
+
+/// Words too common to say anything about a document
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "from", "has", "have", "in", "is", "it",
+    "its", "of", "on", "or", "that", "the", "this", "to", "was", "were", "will", "with",
+];
+
+/// TF-IDF vectors of a collection's documents
+pub struct RelatedIndex {
+    /// Unit-length word weights, by document ID
+    vectors: HashMap<String, HashMap<String, f64>>,
+}
+
+impl RelatedIndex {
+    /// Index every document of a collection
+    pub fn new(docs: &[Document]) -> Self {
+        let counts: Vec<(&str, HashMap<String, usize>)> = docs
+            .iter()
+            .map(|doc| {
+                let mut words = HashMap::new();
+                for word in words_of(doc) {
+                    *words.entry(word).or_insert(0) += 1;
+                }
+                (doc.id.as_str(), words)
+            })
+            .collect();
+
+        let mut frequency: HashMap<&str, usize> = HashMap::new();
+        for (_, words) in &counts {
+            for word in words.keys() {
+                *frequency.entry(word).or_insert(0) += 1;
+            }
+        }
+
+        let total = docs.len() as f64;
+        let mut vectors = HashMap::new();
+        for (id, words) in &counts {
+            let mut vector: HashMap<String, f64> = words
+                .iter()
+                .map(|(word, &count)| {
+                    let idf = ((total + 1.0) / (frequency[word.as_str()] as f64 + 1.0)).ln() + 1.0;
+                    (word.clone(), (1.0 + (count as f64).ln()) * idf)
+                })
+                .collect();
+            let norm = vector.values().map(|w| w * w).sum::<f64>().sqrt();
+            if norm > 0.0 {
+                vector.values_mut().for_each(|w| *w /= norm);
+            }
+            vectors.insert(id.to_string(), vector);
+        }
+
+        Self { vectors }
+    }
+
+    /// Similarity of every other document to `id`, from 0 to 1, leaving out
+    /// unrelated documents; `None` if `id` isn't indexed
+    pub fn scores(&self, id: &str) -> Option<HashMap<String, f64>> {
+        let target = self.vectors.get(id)?;
+        Some(
+            self.vectors
+                .iter()
+                .filter(|(other, _)| other.as_str() != id)
+                .map(|(other, vector)| {
+                    let dot: f64 = target.iter().filter_map(|(word, w)| vector.get(word).map(|v| w * v)).sum();
+                    (other.clone(), dot)
+                })
+                .filter(|(_, score)| *score > 0.0)
+                .collect(),
+        )
+    }
+
+    /// The `limit` documents most related to `id`, most related first
+    pub fn top(&self, id: &str, limit: usize) -> Vec<(String, f64)> {
+        let mut scores: Vec<(String, f64)> = self.scores(id).unwrap_or_default().into_iter().collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scores.truncate(limit);
+        scores
+    }
+}
+
+/// Similarity of each document of `docs` to document `id` of the same collection
+pub(crate) fn related_scores(docs: &[Document], id: &str) -> anyhow::Result<HashMap<String, f64>> {
+    RelatedIndex::new(docs)
+        .scores(id)
+        .ok_or_else(|| anyhow::anyhow!("Document '{}' not found in collection", id))
+}
+
+/// Keep the documents that have a score, most related first
+pub(crate) fn rank(docs: &mut Vec<Document>, scores: &HashMap<String, f64>) {
+    docs.retain(|doc| scores.contains_key(&doc.id));
+    docs.sort_by(|a, b| scores[&b.id].total_cmp(&scores[&a.id]).then_with(|| a.id.cmp(&b.id)));
+}
+
+/// A document's string fields, one per line, followed by its body
+pub(crate) fn document_text(doc: &Document) -> String {
+    let mut text = String::new();
+    for value in doc.fields.values() {
+        push_strings(value, &mut text);
+    }
+    text.push_str(&doc.body);
+    text
+}
+
+/// Lowercase words of a document's string fields and body
+fn words_of(doc: &Document) -> Vec<String> {
+    document_text(doc)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .map(str::to_lowercase)
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+fn push_strings(value: &Value, text: &mut String) {
+    match value {
+        Value::String(s) => {
+            text.push_str(s);
+            text.push('\n');
+        }
+        Value::Array(items) => items.iter().for_each(|item| push_strings(item, text)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(id: &str, title: &str, body: &str) -> Document {
+        let mut doc = Document::new(id);
+        doc.set("title", title);
+        doc.body = body.to_string();
+        doc
+    }
+
+    #[test]
+    fn test_related_ranking() {
+        let docs = vec![
+            doc("rust-intro", "Intro to Rust", "Ownership and borrowing in Rust"),
+            doc("rust-borrow", "The borrow checker", "Rust borrowing rules and lifetimes"),
+            doc("rust-cargo", "Cargo", "Building Rust crates"),
+            doc("baking", "Sourdough", "Flour, water and a starter"),
+        ];
+
+        let index = RelatedIndex::new(&docs);
+        let top = index.top("rust-intro", 5);
+        assert_eq!(top[0].0, "rust-borrow");
+        assert_eq!(top.len(), 2);
+        assert!(top.iter().all(|(id, score)| id != "baking" && *score > 0.0 && *score <= 1.0 + 1e-9));
+        assert!(index.scores("missing").is_none());
+
+        let mut ranked = docs.clone();
+        rank(&mut ranked, &related_scores(&docs, "rust-intro").unwrap());
+        let ids: Vec<&str> = ranked.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["rust-borrow", "rust-cargo"]);
+        assert!(related_scores(&docs, "missing").is_err());
+    }
+}
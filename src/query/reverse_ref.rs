@@ -0,0 +1,191 @@
+//! Reverse references (`REVERSE_REF('tasks', 'project')`)
+//!
+//! `REVERSE_REF(collection, field)` is, for each document, the IDs of the
+//! documents in `collection` whose `field` refers to it: a REF holding its ID,
+//! or an array of IDs including it. A project page can list its tasks this
+//! way without a JOIN. The IDs are sorted.
+//!
+//! A call's result depends on another collection, so it isn't computed when
+//! the call is evaluated: SELECT and view rendering work every call out for
+//! each row first ([`attach`]) and store it under the call's MDQL text, where
+//! evaluating the call finds it, as with aggregates. Both arguments must be
+//! string literals, and only SELECT and views can call it ([`check`]).
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use mdql::{Column, Expr, Literal, SelectStmt, Statement};
+
+use super::executor::expr_references;
+use super::functions::{select_exprs, statement_exprs};
+use crate::storage::document::{Document, Value};
+
+pub(crate) const NAME: &str = "REVERSE_REF";
+
+/// A call of REVERSE_REF in a statement
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Call {
+    /// The call's MDQL text, which rows store its result under
+    pub(crate) text: String,
+    pub(crate) collection: String,
+    pub(crate) field: String,
+}
+
+/// Whether a function name is REVERSE_REF, case-insensitively
+pub(crate) fn is_reverse_ref(name: &str) -> bool {
+    name.eq_ignore_ascii_case(NAME)
+}
+
+/// Check that a statement calls REVERSE_REF only where it can be worked
+/// out, and with string literals
+pub(crate) fn check(stmt: &Statement) -> anyhow::Result<()> {
+    let exprs = statement_exprs(stmt);
+    let called = exprs.iter().any(|expr| expr_references(expr, &|expr| call(expr).is_some()));
+    if called && !matches!(stmt, Statement::Select(_) | Statement::Explain(_) | Statement::CreateView(_)) {
+        anyhow::bail!("{} can only be used in SELECT and views", NAME);
+    }
+    let invalid = |expr: &Expr| match expr {
+        Expr::Function { name, .. } => is_reverse_ref(name) && call(expr).is_none(),
+        _ => false,
+    };
+    if exprs.iter().any(|expr| expr_references(expr, &invalid)) {
+        anyhow::bail!("{} takes a collection and a field as strings: {}('tasks', 'project')", NAME, NAME);
+    }
+    Ok(())
+}
+
+/// Every REVERSE_REF call in a SELECT, once each
+pub(crate) fn calls(stmt: &SelectStmt) -> Vec<Call> {
+    let calls = RefCell::new(Vec::new());
+    let collect = |expr: &Expr| {
+        if let Some(found) = call(expr) {
+            let mut calls = calls.borrow_mut();
+            if !calls.contains(&found) {
+                calls.push(found);
+            }
+        }
+        false
+    };
+    for expr in select_exprs(stmt) {
+        expr_references(expr, &collect);
+    }
+    calls.into_inner()
+}
+
+/// An expression as a REVERSE_REF call, if it is a valid one
+fn call(expr: &Expr) -> Option<Call> {
+    let Expr::Function { name, args } = expr else {
+        return None;
+    };
+    match args.as_slice() {
+        [Expr::Literal(Literal::String(collection)), Expr::Literal(Literal::String(field))] if is_reverse_ref(name) => {
+            Some(Call { text: expr.to_string(), collection: collection.clone(), field: field.clone() })
+        }
+        _ => None,
+    }
+}
+
+/// Store a call's result in every row, given the documents of the
+/// collection it reads
+pub(crate) fn attach(docs: &mut [Document], call: &Call, others: &[Document]) {
+    let mut referrers: HashMap<&str, Vec<&str>> = HashMap::new();
+    for other in others {
+        let ids = match other.fields.get(&call.field) {
+            Some(Value::String(id)) => vec![id.as_str()],
+            Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        for id in ids {
+            referrers.entry(id).or_default().push(other.id.as_str());
+        }
+    }
+    for ids in referrers.values_mut() {
+        ids.sort_unstable();
+        ids.dedup();
+    }
+
+    for doc in docs {
+        let ids = referrers.get(doc.id.as_str()).map(Vec::as_slice).unwrap_or_default();
+        let ids = ids.iter().map(|id| Value::String(id.to_string())).collect();
+        doc.fields.insert(call.text.clone(), Value::Array(ids));
+    }
+}
+
+/// Remove the results [`attach`] stored, keeping those of the calls a
+/// SELECT lists as columns under the columns' names; returns each such
+/// column's name and call
+pub(crate) fn detach(docs: &mut [Document], stmt: &SelectStmt, calls: &[Call]) -> Vec<(String, Call)> {
+    let selected: Vec<(String, Call)> = stmt
+        .columns
+        .iter()
+        .filter_map(|column| match column {
+            Column::Expr { expr, alias } => {
+                call(expr).map(|found| (alias.clone().unwrap_or_else(|| found.text.clone()), found))
+            }
+            _ => None,
+        })
+        .collect();
+
+    for doc in docs.iter_mut() {
+        let results: HashMap<&str, Value> = calls
+            .iter()
+            .filter_map(|call| doc.fields.remove(&call.text).map(|value| (call.text.as_str(), value)))
+            .collect();
+        for (name, call) in &selected {
+            if let Some(value) = results.get(call.text.as_str()) {
+                doc.fields.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(id: &str, field: Option<Value>) -> Document {
+        let mut doc = Document::new(id);
+        if let Some(value) = field {
+            doc.fields.insert("project".to_string(), value);
+        }
+        doc
+    }
+
+    #[test]
+    fn test_attach() {
+        let Statement::Select(stmt) =
+            mdql::parse("SELECT title, reverse_ref('tasks', 'project') AS tasks FROM projects").unwrap()
+        else {
+            panic!("Expected Select");
+        };
+        let calls = calls(&stmt);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].text, "REVERSE_REF('tasks', 'project')");
+
+        let tasks = vec![
+            doc("t2", Some(Value::String("alpha".into()))),
+            doc("t1", Some(Value::Array(vec![Value::String("alpha".into()), Value::String("beta".into())]))),
+            doc("t3", None),
+        ];
+        let mut projects = vec![doc("alpha", None), doc("beta", None), doc("gamma", None)];
+        attach(&mut projects, &calls[0], &tasks);
+        let ids = |doc: &Document, key: &str| doc.fields[key].clone();
+        let strings = |ids: &[&str]| Value::Array(ids.iter().map(|id| Value::String(id.to_string())).collect());
+        assert_eq!(ids(&projects[0], &calls[0].text), strings(&["t1", "t2"]));
+        assert_eq!(ids(&projects[2], &calls[0].text), strings(&[]));
+
+        assert_eq!(detach(&mut projects, &stmt, &calls), [("tasks".to_string(), calls[0].clone())]);
+        assert_eq!(ids(&projects[1], "tasks"), strings(&["t1"]));
+        assert!(!projects[1].fields.contains_key(&calls[0].text));
+    }
+
+    #[test]
+    fn test_check() {
+        let parse = |q: &str| mdql::parse(q).unwrap();
+        assert!(check(&parse("SELECT * FROM projects WHERE LENGTH(REVERSE_REF('tasks', 'project')) > 0")).is_ok());
+        assert!(check(&parse("SELECT REVERSE_REF(title, 'project') FROM projects")).is_err());
+        let outside = check(&parse("DELETE FROM projects WHERE LENGTH(REVERSE_REF('tasks', 'project')) = 0")).unwrap_err();
+        assert!(outside.to_string().contains("only be used in SELECT and views"));
+    }
+}
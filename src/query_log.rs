@@ -0,0 +1,169 @@
+//! Query log and slow query report
+//!
+//! With `query_log: true` in `.mdby/config.yaml`, every statement run through
+//! [`crate::Database::execute`] is appended to `/.mdby/logs/queries.log` as a
+//! line of JSON: when it ran, how long it took, and how many documents it read
+//! from each collection. `mdby slowlog` summarizes the log into the slowest
+//! statements and the collections read the most, which are the places an
+//! `INDEXED` field or a narrower WHERE clause pays off. The log is local to
+//! each checkout and ignored by git.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// One executed statement
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryLogEntry {
+    /// When the statement started, RFC 3339
+    pub at: String,
+    pub statement: String,
+    /// Wall-clock time in milliseconds
+    pub ms: f64,
+    /// Documents read per collection
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub scanned: BTreeMap<String, usize>,
+    /// Error message, if the statement failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl QueryLogEntry {
+    pub fn new(statement: &str, started: SystemTime, ms: f64) -> Self {
+        let secs = started.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+        let at = chrono::DateTime::from_timestamp(secs, 0)
+            .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+            .unwrap_or_default();
+        Self { at, statement: statement.trim().to_string(), ms, scanned: BTreeMap::new(), error: None }
+    }
+}
+
+/// Timings of one distinct statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementStats {
+    pub statement: String,
+    pub count: usize,
+    pub total_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Reads of one collection
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectionStats {
+    pub collection: String,
+    /// Statements that read the collection
+    pub queries: usize,
+    /// Documents read, over all those statements
+    pub documents: usize,
+}
+
+/// Summary of the query log (`mdby slowlog`)
+#[derive(Debug, Clone, Default)]
+pub struct SlowLogReport {
+    /// Number of logged statements
+    pub entries: usize,
+    /// Statements by slowest single run, slowest first
+    pub slowest: Vec<StatementStats>,
+    /// Collections by documents read, most first
+    pub most_scanned: Vec<CollectionStats>,
+}
+
+/// Path of a database's query log
+pub fn path(root: &Path) -> PathBuf {
+    root.join(".mdby").join("logs").join("queries.log")
+}
+
+/// Append an entry to the query log
+pub fn append(root: &Path, entry: &QueryLogEntry) -> anyhow::Result<()> {
+    let path = path(root);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+        let gitignore = dir.join(".gitignore");
+        if !gitignore.exists() {
+            std::fs::write(&gitignore, "*\n")?;
+        }
+    }
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+/// Every entry of the query log; lines that can't be read are skipped
+pub fn read(root: &Path) -> anyhow::Result<Vec<QueryLogEntry>> {
+    let path = path(root);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(&path)?;
+    Ok(content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+/// Summarize log entries, keeping the top `limit` statements and collections
+pub fn report(entries: &[QueryLogEntry], limit: usize) -> SlowLogReport {
+    let mut statements: BTreeMap<&str, StatementStats> = BTreeMap::new();
+    let mut collections: BTreeMap<&str, CollectionStats> = BTreeMap::new();
+
+    for entry in entries {
+        let stats = statements.entry(&entry.statement).or_insert_with(|| StatementStats {
+            statement: entry.statement.clone(),
+            count: 0,
+            total_ms: 0.0,
+            max_ms: 0.0,
+        });
+        stats.count += 1;
+        stats.total_ms += entry.ms;
+        stats.max_ms = stats.max_ms.max(entry.ms);
+
+        for (collection, documents) in &entry.scanned {
+            let stats = collections.entry(collection).or_insert_with(|| CollectionStats {
+                collection: collection.clone(),
+                queries: 0,
+                documents: 0,
+            });
+            stats.queries += 1;
+            stats.documents += documents;
+        }
+    }
+
+    let mut slowest: Vec<StatementStats> = statements.into_values().collect();
+    slowest.sort_by(|a, b| b.max_ms.total_cmp(&a.max_ms));
+    slowest.truncate(limit);
+
+    let mut most_scanned: Vec<CollectionStats> = collections.into_values().collect();
+    most_scanned.sort_by_key(|stats| std::cmp::Reverse(stats.documents));
+    most_scanned.truncate(limit);
+
+    SlowLogReport { entries: entries.len(), slowest, most_scanned }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(statement: &str, ms: f64, scanned: &[(&str, usize)]) -> QueryLogEntry {
+        let mut entry = QueryLogEntry::new(statement, SystemTime::UNIX_EPOCH, ms);
+        entry.scanned = scanned.iter().map(|(c, n)| (c.to_string(), *n)).collect();
+        entry
+    }
+
+    #[test]
+    fn test_report() {
+        let entries = vec![
+            entry("SELECT * FROM todos", 4.0, &[("todos", 100)]),
+            entry("SELECT * FROM notes", 9.0, &[("notes", 10)]),
+            entry("SELECT * FROM todos", 12.0, &[("todos", 100)]),
+            entry("SHOW COLLECTIONS", 0.5, &[]),
+        ];
+        assert_eq!(entries[0].at, "1970-01-01T00:00:00Z");
+
+        let report = report(&entries, 2);
+        assert_eq!(report.entries, 4);
+        assert_eq!(report.slowest.len(), 2);
+        assert_eq!(report.slowest[0].statement, "SELECT * FROM todos");
+        assert_eq!((report.slowest[0].count, report.slowest[0].total_ms, report.slowest[0].max_ms), (2, 16.0, 12.0));
+        assert_eq!(report.slowest[1].statement, "SELECT * FROM notes");
+        assert_eq!(report.most_scanned[0], CollectionStats { collection: "todos".into(), queries: 2, documents: 200 });
+    }
+}
@@ -0,0 +1,137 @@
+//! Databases served by `mdby serve`, for `mdby --url`
+//!
+//! [`Remote`] runs statements on a server through [`mdby_client::Client`]
+//! and turns the results back into [`QueryResult`]s, so the CLI and the REPL
+//! print a server's results the way they print a local database's. The JSON
+//! the server sends for values, DESCRIBE and EXPLAIN is read back into the
+//! database's own types.
+
+use mdby_client::Client;
+use serde_json::Value as Json;
+
+use crate::storage::collection::CollectionMeta;
+use crate::storage::document::Document;
+use crate::{BatchResult, CollectionDescription, FieldDescription, QueryResult, StatementOutcome};
+
+/// A database served over HTTP
+pub struct Remote {
+    client: Client,
+}
+
+impl Remote {
+    /// The database served at `url`
+    pub fn new(url: &str) -> Self {
+        Remote { client: Client::new(url) }
+    }
+
+    /// The server's URL
+    pub fn url(&self) -> &str {
+        self.client.url()
+    }
+
+    /// Execute an MDQL query on the server, as [`crate::Database::execute`]
+    pub async fn execute(&self, query: &str) -> anyhow::Result<QueryResult> {
+        query_result(self.client.execute(query).await?)
+    }
+
+    /// Execute a script as one transaction, as [`crate::Database::execute_script`]
+    pub async fn execute_script(&self, script: &str) -> anyhow::Result<BatchResult> {
+        batch_result(self.client.execute_script(script).await?)
+    }
+
+    /// Names of the collections and their schema fields, for completion
+    pub async fn names(&self) -> anyhow::Result<Vec<String>> {
+        let QueryResult::Collections(collections) = self.execute("SHOW COLLECTIONS").await? else {
+            anyhow::bail!("SHOW COLLECTIONS returned something else");
+        };
+        let mut names = collections.clone();
+        for collection in &collections {
+            if let Ok(QueryResult::Description(description)) = self.execute(&format!("DESCRIBE {}", collection)).await {
+                names.extend(description.fields.into_iter().map(|field| field.name));
+            }
+        }
+        Ok(names)
+    }
+}
+
+/// A client result as the database would have returned it
+pub fn query_result(result: mdby_client::QueryResult) -> anyhow::Result<QueryResult> {
+    use mdby_client::QueryResult as Remote;
+
+    Ok(match result {
+        Remote::Documents(docs) => QueryResult::Documents(docs.into_iter().map(document).collect::<anyhow::Result<_>>()?),
+        Remote::Scalar(value) => QueryResult::Scalar(serde_json::from_value(value)?),
+        Remote::Affected(count) => QueryResult::Affected(count),
+        Remote::ViewCreated(name) => QueryResult::ViewCreated(name),
+        Remote::CollectionCreated(name) => QueryResult::CollectionCreated(name),
+        Remote::Collections(names) => QueryResult::Collections(names),
+        Remote::Views(names) => QueryResult::Views(names),
+        Remote::Definition(statement) => QueryResult::Definition(statement),
+        Remote::Description(json) => QueryResult::Description(description(json)?),
+        Remote::Plan(json) => QueryResult::Plan(serde_json::from_value(json)?),
+        Remote::Batch(batch) => QueryResult::Batch(batch_result(batch)?),
+    })
+}
+
+/// A client batch as the database would have returned it
+pub fn batch_result(batch: mdby_client::BatchResult) -> anyhow::Result<BatchResult> {
+    let statements = batch
+        .statements
+        .into_iter()
+        .map(|outcome| {
+            Ok(StatementOutcome {
+                line: outcome.line,
+                statement: outcome.statement,
+                result: match outcome.result {
+                    Ok(result) => Ok(query_result(result)?),
+                    Err(e) => Err(e),
+                },
+            })
+        })
+        .collect::<anyhow::Result<_>>()?;
+    Ok(BatchResult { statements })
+}
+
+fn document(doc: mdby_client::Document) -> anyhow::Result<Document> {
+    let mut document = Document::new(doc.id).with_body(doc.body);
+    for (field, value) in doc.fields {
+        document.fields.insert(field, serde_json::from_value(value)?);
+    }
+    Ok(document)
+}
+
+/// A DESCRIBE result from its JSON ([`crate::server::description_json`])
+fn description(json: Json) -> anyhow::Result<CollectionDescription> {
+    let string = |json: &Json, key: &str| json.get(key).and_then(Json::as_str).map(str::to_string);
+    let Some(name) = string(&json, "name") else {
+        anyhow::bail!("DESCRIBE returned a collection without a name");
+    };
+    let meta = match json.get("meta") {
+        Some(meta @ Json::Object(_)) => Some(CollectionMeta {
+            file: string(meta, "file").unwrap_or_default(),
+            fields: serde_json::from_value(meta.get("fields").cloned().unwrap_or_default()).unwrap_or_default(),
+            body: string(meta, "body").unwrap_or_default(),
+        }),
+        _ => None,
+    };
+    let fields = json
+        .get("fields")
+        .and_then(Json::as_array)
+        .into_iter()
+        .flatten()
+        .map(|field| FieldDescription {
+            name: string(field, "name").unwrap_or_default(),
+            field_type: string(field, "type").unwrap_or_default(),
+            constraints: serde_json::from_value(field.get("constraints").cloned().unwrap_or_default()).unwrap_or_default(),
+            description: string(field, "description"),
+            label: string(field, "label"),
+        })
+        .collect();
+    Ok(CollectionDescription {
+        name,
+        documents: json.get("documents").and_then(Json::as_u64).unwrap_or_default() as usize,
+        description: string(&json, "description"),
+        meta,
+        fields,
+    })
+}
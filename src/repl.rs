@@ -0,0 +1,310 @@
+//! Line editing for the interactive shell (`mdby repl`)
+//!
+//! [`ReplHelper`] plugs into rustyline: a statement may span several lines
+//! and is only run once it ends with `;` ([`is_complete`]), and Tab completes
+//! MDQL keywords, collection names and the fields their schemas declare.
+//! Shell commands ([`MetaCommand`]: `help`, `exit` and psql-style backslash
+//! commands like `\dt`) need no `;`.
+
+use std::collections::BTreeSet;
+
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Helper};
+
+use crate::Database;
+
+/// Keywords offered by completion
+const KEYWORDS: &[&str] = &[
+    "ADD", "ALTER", "AND", "ARRAY", "AS", "ASC", "AVG", "BETWEEN", "BODY", "BOOL", "BY", "CASCADE", "COLLECTION",
+    "COLLECTIONS", "COLUMN", "CONFLICT", "CONTAINS", "COUNT", "CREATE", "DATE", "DATETIME", "DEFAULT", "DELETE",
+    "DELIVER", "DESC", "DESCRIBE", "DO", "DROP", "EXISTS", "EXPLAIN", "FEED", "FILE", "FLOAT", "FORMATS", "FROM",
+    "GROUP", "HAS", "HAVING", "IF", "IN", "INDEXED", "INNER", "INSERT", "INT", "INTO", "IS", "JOIN", "LEFT", "LIKE",
+    "LIMIT", "MAX", "MIGRATE", "MIN", "NOT", "NOTHING", "NULL", "OBJECT", "OF", "OFFSET", "ON", "OR", "ORDER",
+    "OUTER", "REF", "RELATED", "RENAME", "REQUIRED", "RESTRICT", "RIGHT", "SCHEDULE", "SELECT", "SEMANTIC_SEARCH",
+    "SET", "SHOW", "STRING", "SUM", "TAG", "TEMPLATE", "TO", "TYPE", "UNIQUE", "UPDATE", "UPSERT", "VALUES", "VIEW",
+    "VIEWS", "WHERE", "WITH",
+];
+
+/// A shell command rather than a statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetaCommand {
+    /// `help`, `\h`, `\?`
+    Help,
+    /// `exit`, `quit`, `\q`
+    Quit,
+    /// `\dt`, or `\d` alone: list the collections
+    ListCollections,
+    /// `\d todos`: describe a collection and its schema
+    Describe(String),
+    /// `\timing [on|off]`: time each statement; without an argument, toggle
+    Timing(Option<bool>),
+    /// `\format [json|table|minimal]`: set the output format, or show it
+    Format(Option<String>),
+    /// `\o [file]`: write results to a file, or back to the terminal
+    Output(Option<String>),
+}
+
+impl MetaCommand {
+    /// The shell command `line` is, if it is one
+    ///
+    /// A line starting with `\` that isn't a known command is an error,
+    /// rather than an MDQL statement.
+    pub fn parse(line: &str) -> Result<Option<Self>, String> {
+        let line = line.trim().trim_end_matches(';').trim_end();
+        match line.to_lowercase().as_str() {
+            "help" => return Ok(Some(Self::Help)),
+            "exit" | "quit" => return Ok(Some(Self::Quit)),
+            _ => {}
+        }
+        let Some(command) = line.strip_prefix('\\') else {
+            return Ok(None);
+        };
+
+        let (name, argument) = match command.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, Some(argument.trim().to_string())),
+            None => (command, None),
+        };
+        Ok(Some(match (name, argument) {
+            ("h" | "?", None) => Self::Help,
+            ("q", None) => Self::Quit,
+            ("dt", None) | ("d", None) => Self::ListCollections,
+            ("d", Some(name)) => Self::Describe(name),
+            ("timing", None) => Self::Timing(None),
+            ("timing", Some(value)) => match value.to_lowercase().as_str() {
+                "on" => Self::Timing(Some(true)),
+                "off" => Self::Timing(Some(false)),
+                _ => return Err(format!("Expected \\timing on or \\timing off, not '{}'", value)),
+            },
+            ("format", argument) => Self::Format(argument),
+            ("o", argument) => Self::Output(argument),
+            _ => return Err(format!("Unknown command: \\{}. Type \\h for help.", command)),
+        }))
+    }
+}
+
+/// Completion and multi-line input for the shell
+#[derive(Debug, Default)]
+pub struct ReplHelper {
+    /// Collection and field names, sorted
+    names: BTreeSet<String>,
+}
+
+impl ReplHelper {
+    /// A helper completing the names in `db`
+    pub async fn new(db: &Database) -> Self {
+        let mut helper = Self::default();
+        helper.refresh(db).await;
+        helper
+    }
+
+    /// Reload the collection and field names, e.g. after a statement that
+    /// may have created or altered a collection
+    pub async fn refresh(&mut self, db: &Database) {
+        self.names = db.store.collections().await.unwrap_or_default().into_iter().collect();
+        for schema in db.schema.list() {
+            self.names.insert(schema.name.clone());
+            self.names.extend(schema.fields.keys().cloned());
+        }
+    }
+
+    /// Complete these collection and field names, e.g. a server's
+    pub fn set_names(&mut self, names: impl IntoIterator<Item = String>) {
+        self.names = names.into_iter().collect();
+    }
+
+    /// Start of the word ending at `pos` in `line`, and what it could be
+    ///
+    /// Keywords keep the case the word was typed in; names match regardless
+    /// of case and keep their own.
+    pub fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == '-')
+            .last()
+            .map_or(pos, |(i, _)| i);
+        let word = &line[start..pos];
+        if word.is_empty() {
+            return (pos, Vec::new());
+        }
+
+        let upper = word.to_uppercase();
+        let lower = word.chars().all(|c| !c.is_alphabetic() || c.is_lowercase());
+        let keywords = KEYWORDS
+            .iter()
+            .filter(|keyword| keyword.starts_with(&upper))
+            .map(|keyword| if lower { keyword.to_lowercase() } else { keyword.to_string() });
+        let names = self
+            .names
+            .iter()
+            .filter(|name| name.to_uppercase().starts_with(&upper))
+            .cloned();
+        let mut candidates: Vec<String> = names.chain(keywords).collect();
+        candidates.dedup();
+        (start, candidates)
+    }
+}
+
+/// Whether `input` is ready to run: statements once they end with `;`
+/// outside a string, heredoc or comment, shell commands as soon as they're
+/// typed
+///
+/// Strings, `$$ ... $$` heredocs and comments follow the MDQL parser's
+/// rules, so a `;` or quote inside one neither ends the statement nor leaves
+/// it waiting for more.
+pub fn is_complete(input: &str) -> bool {
+    let trimmed = input.trim();
+    if trimmed.is_empty() || !matches!(MetaCommand::parse(trimmed), Ok(None)) {
+        return true;
+    }
+
+    let mut ends_statement = false;
+    let mut rest = trimmed;
+    while let Some(c) = rest.chars().next() {
+        let len = if rest.starts_with("--") {
+            // A comment runs to the end of the line
+            rest.find('\n').unwrap_or(rest.len())
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            match comment.find("*/") {
+                Some(end) => end + 4,
+                None => return false,
+            }
+        } else {
+            let len = match c {
+                '\'' | '"' => quoted_len(rest, c),
+                '$' => match heredoc_delimiter(rest) {
+                    Some(delimiter) => rest[delimiter.len()..].find(delimiter).map(|end| end + 2 * delimiter.len()),
+                    None => Some(1),
+                },
+                _ => Some(c.len_utf8()),
+            };
+            let Some(len) = len else {
+                return false;
+            };
+            if !c.is_whitespace() {
+                ends_statement = c == ';';
+            }
+            len
+        };
+        rest = &rest[len..];
+    }
+    ends_statement
+}
+
+/// Length of the string `input` starts with, opened by `quote`; None if it
+/// isn't closed
+fn quoted_len(input: &str, quote: char) -> Option<usize> {
+    let mut chars = input.char_indices().skip(1).peekable();
+    while let Some((i, c)) = chars.next() {
+        // A doubled or backslashed quote is an escaped one, and stays in
+        // the string
+        if c == '\\' {
+            chars.next();
+        } else if c == quote {
+            if chars.peek().is_none_or(|&(_, next)| next != quote) {
+                return Some(i + 1);
+            }
+            chars.next();
+        }
+    }
+    None
+}
+
+/// The delimiter (`$$`, `$tag$`) of the heredoc `input` starts with, if it
+/// starts one
+fn heredoc_delimiter(input: &str) -> Option<&str> {
+    let label = input[1..].find(|c: char| !(c.is_alphanumeric() || c == '_'))?;
+    input[1 + label..].starts_with('$').then(|| &input[..label + 2])
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.candidates(line, pos))
+    }
+}
+
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        Ok(match is_complete(ctx.input()) {
+            true => ValidationResult::Valid(None),
+            false => ValidationResult::Incomplete,
+        })
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_complete() {
+        assert!(is_complete("SELECT * FROM todos;"));
+        assert!(is_complete("SELECT *\nFROM todos\nWHERE done = false;  "));
+        assert!(!is_complete("SELECT * FROM todos"));
+        assert!(!is_complete("SELECT * FROM todos WHERE title = 'a;"));
+        assert!(is_complete("SELECT * FROM todos WHERE title = 'it''s;';"));
+        assert!(!is_complete("SELECT * FROM todos WHERE title = 'it\\';"));
+        assert!(!is_complete("SELECT * FROM todos -- all of them;"));
+        assert!(is_complete("SELECT * FROM todos; -- all of them"));
+        assert!(is_complete("SELECT * FROM todos; -- it's all of them"));
+        assert!(is_complete("help"));
+        assert!(is_complete("\\q"));
+        assert!(is_complete(""));
+    }
+
+    #[test]
+    fn test_is_complete_with_heredocs_and_block_comments() {
+        // An apostrophe in a heredoc doesn't open a string
+        assert!(is_complete("INSERT INTO notes (id) VALUES ('n') BODY $$\nIt's done\n$$;"));
+        // Nor does a `;` at the end of one of its lines end the statement
+        assert!(!is_complete("INSERT INTO notes (id) VALUES ('n') BODY $$\nFirst;"));
+        assert!(!is_complete("INSERT INTO notes (id) VALUES ('n') BODY $md$\nCosts $$5;"));
+        assert!(is_complete("INSERT INTO notes (id) VALUES ('n') BODY $md$\nCosts $$5;\n$md$;"));
+        assert!(!is_complete("INSERT INTO notes (id) VALUES ('n') BODY $$ a $$"));
+
+        assert!(!is_complete("SELECT * FROM todos /* all of them;"));
+        assert!(!is_complete("SELECT * FROM todos /* it's\nall of them;"));
+        assert!(is_complete("SELECT * FROM todos /* it's\nall of them; */;"));
+        assert!(is_complete("SELECT * FROM todos; /* done */"));
+        assert!(!is_complete("SELECT * FROM todos /* done */"));
+    }
+
+    #[test]
+    fn test_meta_command() {
+        assert_eq!(MetaCommand::parse("\\dt"), Ok(Some(MetaCommand::ListCollections)));
+        assert_eq!(MetaCommand::parse("\\d  todos "), Ok(Some(MetaCommand::Describe("todos".into()))));
+        assert_eq!(MetaCommand::parse("\\timing"), Ok(Some(MetaCommand::Timing(None))));
+        assert_eq!(MetaCommand::parse("\\timing OFF"), Ok(Some(MetaCommand::Timing(Some(false)))));
+        assert_eq!(MetaCommand::parse("\\format json"), Ok(Some(MetaCommand::Format(Some("json".into())))));
+        assert_eq!(MetaCommand::parse("\\o out.txt"), Ok(Some(MetaCommand::Output(Some("out.txt".into())))));
+        assert_eq!(MetaCommand::parse("\\o"), Ok(Some(MetaCommand::Output(None))));
+        assert_eq!(MetaCommand::parse("exit;"), Ok(Some(MetaCommand::Quit)));
+        assert_eq!(MetaCommand::parse("SELECT * FROM todos;"), Ok(None));
+        assert!(MetaCommand::parse("\\timing maybe").is_err());
+        assert!(MetaCommand::parse("\\x").unwrap_err().contains("Unknown command"));
+    }
+
+    #[test]
+    fn test_candidates() {
+        let helper = ReplHelper { names: ["priority", "todos", "title"].map(String::from).into() };
+        assert_eq!(helper.candidates("SEL", 3), (0, vec!["SELECT".to_string()]));
+        assert_eq!(helper.candidates("sel", 3).1, ["select"]);
+        assert_eq!(helper.candidates("SELECT * FROM to", 16), (14, vec!["todos".to_string(), "to".to_string()]));
+        assert_eq!(helper.candidates("SELECT T", 8).1, ["title", "todos", "TAG", "TEMPLATE", "TO", "TYPE"]);
+        assert_eq!(helper.candidates("SELECT pri", 10).1, ["priority"]);
+        assert!(helper.candidates("SELECT ", 7).1.is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+//! Value coercion against schema field types
+//!
+//! MDQL literals don't always arrive with the type the schema expects -
+//! `'5'` for an INT field, `'true'` for a BOOL. Coercion converts values
+//! where the conversion is unambiguous and leaves everything else untouched
+//! so that validation can report the mismatch.
+
+use super::FieldType;
+use crate::storage::document::Value;
+
+/// Coerce a value towards the given field type
+///
+/// Returns `None` if the value already matches or cannot be converted
+/// without guessing.
+pub fn coerce_value(field_type: &FieldType, value: &Value) -> Option<Value> {
+    match (field_type, value) {
+        (FieldType::Int, Value::String(s)) => s.trim().parse::<i64>().ok().map(Value::Int),
+        (FieldType::Float, Value::String(s)) => {
+            s.trim().parse::<f64>().ok().filter(|f| f.is_finite()).map(Value::Float)
+        }
+        (FieldType::Bool, Value::String(s)) => match s.trim().to_lowercase().as_str() {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        (FieldType::Date, Value::String(s)) => coerce_date(s.trim()).map(Value::String),
+        (FieldType::String, Value::Int(i)) => Some(Value::String(i.to_string())),
+        (FieldType::String, Value::Float(f)) => Some(Value::String(f.to_string())),
+        (FieldType::String, Value::Bool(b)) => Some(Value::String(b.to_string())),
+        (FieldType::Array(inner), Value::Array(items)) => {
+            let coerced: Vec<Option<Value>> = items.iter().map(|item| coerce_value(inner, item)).collect();
+            if coerced.iter().all(Option::is_none) {
+                return None;
+            }
+            Some(Value::Array(
+                coerced
+                    .into_iter()
+                    .zip(items)
+                    .map(|(new, old)| new.unwrap_or_else(|| old.clone()))
+                    .collect(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Normalize `YYYY/MM/DD` and `YYYY.MM.DD` to ISO `YYYY-MM-DD`
+fn coerce_date(s: &str) -> Option<String> {
+    let sep = s.chars().find(|c| *c == '/' || *c == '.')?;
+    let parts: Vec<&str> = s.split(sep).collect();
+    if parts.len() != 3 || parts[0].len() != 4 {
+        return None;
+    }
+
+    let year = parts[0].parse::<i32>().ok()?;
+    let month = parts[1].parse::<u32>().ok()?;
+    let day = parts[2].parse::<u32>().ok()?;
+    let date = chrono::NaiveDate::from_ymd_opt(year, month, day)?;
+
+    Some(date.format("%Y-%m-%d").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coerce_scalars() {
+        assert_eq!(coerce_value(&FieldType::Int, &Value::String("5".into())), Some(Value::Int(5)));
+        assert_eq!(coerce_value(&FieldType::Float, &Value::String("2.5".into())), Some(Value::Float(2.5)));
+        assert_eq!(coerce_value(&FieldType::Bool, &Value::String("TRUE".into())), Some(Value::Bool(true)));
+        assert_eq!(coerce_value(&FieldType::String, &Value::Int(42)), Some(Value::String("42".into())));
+        assert_eq!(
+            coerce_value(&FieldType::Date, &Value::String("2024/1/15".into())),
+            Some(Value::String("2024-01-15".into()))
+        );
+    }
+
+    #[test]
+    fn test_ambiguous_values_left_alone() {
+        assert_eq!(coerce_value(&FieldType::Int, &Value::String("five".into())), None);
+        assert_eq!(coerce_value(&FieldType::Int, &Value::String("5.5".into())), None);
+        assert_eq!(coerce_value(&FieldType::Bool, &Value::String("yes".into())), None);
+        assert_eq!(coerce_value(&FieldType::Date, &Value::String("01/15/2024".into())), None);
+        assert_eq!(coerce_value(&FieldType::Int, &Value::Int(5)), None);
+    }
+
+    #[test]
+    fn test_impossible_dates_left_alone() {
+        let date = |s: &str| coerce_value(&FieldType::Date, &Value::String(s.into()));
+        assert_eq!(date("2024/02/30"), None);
+        assert_eq!(date("2024/02/31"), None);
+        assert_eq!(date("2023/04/31"), None);
+        assert_eq!(date("2023/02/29"), None);
+        assert_eq!(date("2024/02/29"), Some(Value::String("2024-02-29".into())));
+    }
+
+    #[test]
+    fn test_coerce_array_items() {
+        let value = Value::Array(vec![Value::String("1".into()), Value::Int(2)]);
+        assert_eq!(
+            coerce_value(&FieldType::Array(Box::new(FieldType::Int)), &value),
+            Some(Value::Array(vec![Value::Int(1), Value::Int(2)]))
+        );
+    }
+}
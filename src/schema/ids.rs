@@ -0,0 +1,71 @@
+//! Document ID generation (`id_strategy` in the schema)
+//!
+//! An INSERT without an `id` column gets its ID from the collection's
+//! strategy: a random UUID, the next number of a counter kept in
+//! `/.mdby/counters/{collection}` (committed with the document, so clones
+//! and branches continue from it), or a slug of another field (see
+//! [`crate::slug`]). Numbers are written by the schema's `id_format`, with a
+//! prefix and zero-padding (`task-0001`). Numbers and
+//! slugs that are already taken are skipped, so generated IDs never collide
+//! with existing documents.
+
+use std::path::Path;
+
+use super::{IdFormat, IdStrategy};
+use crate::slug::{slugify_with, unique, SlugOptions, DEFAULT_MAX_LENGTH};
+use crate::storage::document::{Document, Value};
+
+/// An ID for `doc` in `collection`, or `None` if the strategy is `manual`;
+/// `taken` tells which IDs the collection's documents already have
+pub(crate) async fn generate(
+    strategy: &IdStrategy,
+    format: &IdFormat,
+    root: &Path,
+    collection: &str,
+    doc: &Document,
+    taken: impl Fn(&str) -> bool,
+) -> anyhow::Result<Option<String>> {
+    match strategy {
+        IdStrategy::Manual => Ok(None),
+        IdStrategy::Uuid => Ok(Some(uuid::Uuid::new_v4().to_string())),
+        IdStrategy::AutoIncrement => {
+            let path = root.join(".mdby").join("counters").join(collection);
+            let mut next = match tokio::fs::read_to_string(&path).await {
+                Ok(content) => content.trim().parse::<u64>().map_err(|_| {
+                    anyhow::anyhow!("Invalid ID counter in {:?}: expected a number", path)
+                })?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+                Err(e) => return Err(e.into()),
+            } + 1;
+            while taken(&format.id(next)) {
+                next += 1;
+            }
+
+            if let Some(dir) = path.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            crate::storage::journal::preserve(root, &path)?;
+            tokio::fs::write(&path, format!("{}\n", next)).await?;
+            Ok(Some(format.id(next)))
+        }
+        IdStrategy::Derived { from, transform, max_length } => {
+            let text = match doc.fields.get(from) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Int(i)) => i.to_string(),
+                Some(Value::Float(f)) => f.to_string(),
+                Some(Value::Bool(b)) => b.to_string(),
+                _ => anyhow::bail!("INSERT requires an 'id' column or a value for '{}', which the ID is derived from", from),
+            };
+            let options = SlugOptions::default().with_max_length(Some(max_length.unwrap_or(DEFAULT_MAX_LENGTH)));
+            let base = match transform.as_str() {
+                "slug" => slugify_with(&text, &options),
+                "none" => text.clone(),
+                other => anyhow::bail!("Unknown ID transform '{}': expected 'slug' or 'none'", other),
+            };
+            if base.is_empty() {
+                anyhow::bail!("Cannot derive an ID from '{}' = {:?}", from, text);
+            }
+            Ok(Some(unique(&base, &options, taken)))
+        }
+    }
+}
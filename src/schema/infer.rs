@@ -0,0 +1,235 @@
+//! Schemas inferred from existing documents (`mdby schema infer`)
+//!
+//! Each frontmatter field gets the type its values have in common: INT and
+//! FLOAT values make a FLOAT field, dates and datetimes a DATETIME, and
+//! dates among other strings a STRING. Values with nothing in common are a
+//! conflict: the field takes the type most of them fit, and the documents
+//! whose values don't fit it are reported. A field is proposed as required when
+//! enough of the documents have it.
+//!
+//! Inferring for a collection that already has a schema keeps the rest of
+//! each field's definition (defaults, indexes, descriptions) and every
+//! setting of the schema itself; only types and required flags change.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::{check_type_match, is_valid_date, is_valid_datetime, FieldDef, FieldType, Schema};
+use crate::storage::document::{Document, Value};
+
+/// Share of documents a field must appear in to be proposed as required
+pub const DEFAULT_REQUIRED_RATIO: f64 = 1.0;
+
+/// A schema proposed for a collection, with how each field was inferred
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaInference {
+    /// Documents scanned
+    pub documents: usize,
+    pub fields: Vec<FieldInference>,
+    pub schema: Schema,
+}
+
+/// How one field's type and required flag were inferred
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldInference {
+    pub name: String,
+    /// Inferred type, as MDQL writes it (`INT`, `ARRAY<STRING>`)
+    pub field_type: String,
+    /// Documents with the field
+    pub present: usize,
+    pub required: bool,
+    /// Documents per type of value seen, for fields whose values disagree
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub conflicts: BTreeMap<String, usize>,
+    /// Documents whose value doesn't fit the inferred type
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mismatched: Vec<String>,
+}
+
+impl SchemaInference {
+    /// Whether any field's values disagree on a type
+    pub fn has_conflicts(&self) -> bool {
+        self.fields.iter().any(|field| !field.mismatched.is_empty())
+    }
+}
+
+/// Infer a schema for collection `name` from its documents, on top of
+/// `existing` if it has one
+///
+/// New fields are ordered from the most to the least common, then by name.
+pub fn infer(name: &str, docs: &[Document], existing: Option<&Schema>, required_ratio: f64) -> SchemaInference {
+    let mut docs: Vec<&Document> = docs.iter().collect();
+    docs.sort_by(|a, b| a.id.cmp(&b.id));
+
+    // Field name -> (document ID, value) for every document that has it
+    let mut values: BTreeMap<&str, Vec<(&str, &Value)>> = BTreeMap::new();
+    for doc in &docs {
+        for (field, value) in &doc.fields {
+            values.entry(field.as_str()).or_default().push((doc.id.as_str(), value));
+        }
+    }
+    let mut values: Vec<_> = values.into_iter().collect();
+    values.sort_by_key(|(_, seen)| std::cmp::Reverse(seen.len()));
+
+    let mut schema = existing.cloned().unwrap_or_else(|| Schema::new(name));
+    let mut fields = Vec::new();
+    for (field, seen) in values {
+        let field_type = common_type(seen.iter().filter_map(|(_, value)| value_type(value)))
+            .or_else(|| seen.iter().any(|(_, value)| matches!(value, Value::Array(_))).then(|| array_of(FieldType::String)))
+            .unwrap_or_default();
+        let required = seen.len() as f64 >= required_ratio * docs.len() as f64;
+        let mismatched: Vec<String> = seen
+            .iter()
+            .filter(|(_, value)| !check_type_match(&field_type, value))
+            .map(|(id, _)| id.to_string())
+            .collect();
+        let conflicts = if mismatched.is_empty() {
+            BTreeMap::new()
+        } else {
+            let mut counts = BTreeMap::new();
+            for value_type in seen.iter().filter_map(|(_, value)| value_type(value)) {
+                *counts.entry(type_name(&value_type)).or_insert(0) += 1;
+            }
+            counts
+        };
+
+        fields.push(FieldInference {
+            name: field.to_string(),
+            field_type: type_name(&field_type),
+            present: seen.len(),
+            required,
+            conflicts,
+            mismatched,
+        });
+        let def = schema.fields.entry(field.to_string()).or_insert_with(FieldDef::default);
+        def.field_type = field_type;
+        def.required = required;
+    }
+
+    SchemaInference { schema, documents: docs.len(), fields }
+}
+
+/// Type of a single value; `None` for NULL and empty arrays, which fit any
+fn value_type(value: &Value) -> Option<FieldType> {
+    match value {
+        Value::Null => None,
+        Value::Bool(_) => Some(FieldType::Bool),
+        Value::Int(_) => Some(FieldType::Int),
+        Value::Float(_) => Some(FieldType::Float),
+        Value::String(s) if is_valid_date(s) => Some(FieldType::Date),
+        Value::String(s) if is_valid_datetime(s) => Some(FieldType::DateTime),
+        Value::String(_) => Some(FieldType::String),
+        Value::Array(items) => common_type(items.iter().filter_map(value_type)).map(array_of),
+        Value::Object(_) => Some(FieldType::Object),
+    }
+}
+
+/// The type all of `types` fit, or else the type of the largest group of
+/// them that fit one
+fn common_type(types: impl Iterator<Item = FieldType>) -> Option<FieldType> {
+    let mut groups: Vec<(FieldType, usize)> = Vec::new();
+    for field_type in types {
+        let unified = groups
+            .iter()
+            .enumerate()
+            .find_map(|(i, (common, _))| unify(common, &field_type).map(|unified| (i, unified)));
+        match unified {
+            Some((i, unified)) => {
+                groups[i].0 = unified;
+                groups[i].1 += 1;
+            }
+            None => groups.push((field_type, 1)),
+        }
+    }
+
+    // The earliest seen wins a tie
+    let most = groups.iter().map(|(_, count)| *count).max()?;
+    groups.into_iter().find(|(_, count)| *count == most).map(|(field_type, _)| field_type)
+}
+
+/// A type both `a` and `b` values fit, if there is one
+fn unify(a: &FieldType, b: &FieldType) -> Option<FieldType> {
+    use FieldType::*;
+    match (a, b) {
+        _ if a == b => Some(a.clone()),
+        (Int, Float) | (Float, Int) => Some(Float),
+        (Date, DateTime) | (DateTime, Date) => Some(DateTime),
+        (Date | DateTime, String) | (String, Date | DateTime) => Some(String),
+        (Array(a), Array(b)) => unify(a, b).map(array_of),
+        _ => None,
+    }
+}
+
+fn array_of(inner: FieldType) -> FieldType {
+    FieldType::Array(Box::new(inner))
+}
+
+fn type_name(field_type: &FieldType) -> String {
+    crate::query::fieldtype_to_datatype(field_type).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(id: &str, fields: &[(&str, Value)]) -> Document {
+        let mut doc = Document::new(id);
+        for (name, value) in fields {
+            doc.fields.insert(name.to_string(), value.clone());
+        }
+        doc
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.into())
+    }
+
+    #[test]
+    fn test_infer() {
+        let docs = vec![
+            doc("b", &[("title", string("Two")), ("score", Value::Float(1.5)), ("due", string("2024-03-01T09:00:00Z"))]),
+            doc("a", &[("title", string("One")), ("score", Value::Int(3)), ("due", string("2024-03-01"))]),
+            doc("c", &[("title", string("Three")), ("tags", Value::Array(vec![string("x")]))]),
+        ];
+        let inference = infer("notes", &docs, None, DEFAULT_REQUIRED_RATIO);
+        let field = |name: &str| inference.fields.iter().find(|field| field.name == name).unwrap();
+
+        assert_eq!(inference.documents, 3);
+        assert_eq!(inference.schema.field_order(), ["title", "due", "score", "tags"]);
+        assert_eq!(field("score").field_type, "FLOAT");
+        assert_eq!(field("due").field_type, "DATETIME");
+        assert_eq!(field("tags").field_type, "ARRAY<STRING>");
+        assert!(field("title").required);
+        assert!(!field("score").required);
+        assert!(!inference.has_conflicts());
+
+        let lenient = infer("notes", &docs, None, 0.6);
+        assert!(lenient.schema.fields["score"].required);
+    }
+
+    #[test]
+    fn test_infer_conflicts() {
+        let docs = vec![
+            doc("a", &[("priority", Value::Int(1))]),
+            doc("b", &[("priority", Value::Int(2))]),
+            doc("c", &[("priority", string("high"))]),
+            doc("d", &[("priority", Value::Null)]),
+        ];
+        let inference = infer("todos", &docs, None, DEFAULT_REQUIRED_RATIO);
+        let field = &inference.fields[0];
+        assert_eq!(field.field_type, "INT");
+        assert_eq!(field.mismatched, ["c"]);
+        assert_eq!(field.conflicts, BTreeMap::from([("INT".to_string(), 2), ("STRING".to_string(), 1)]));
+        assert!(inference.has_conflicts());
+    }
+
+    #[test]
+    fn test_infer_keeps_existing_definitions() {
+        let existing = Schema::new("todos").field("title", FieldDef { indexed: true, ..FieldDef::default() });
+        let docs = vec![doc("a", &[("title", Value::Int(7))])];
+        let inference = infer("todos", &docs, Some(&existing), DEFAULT_REQUIRED_RATIO);
+        let title = &inference.schema.fields["title"];
+        assert_eq!(title.field_type, FieldType::Int);
+        assert!(title.indexed && title.required);
+    }
+}
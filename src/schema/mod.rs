@@ -8,14 +8,24 @@
 //!
 //! Schemas are stored in `/.mdby/schemas/{collection}.yaml`
 
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
+mod coerce;
+pub(crate) mod ids;
+pub mod infer;
+pub mod timezone;
+
+pub use coerce::coerce_value;
+pub use timezone::TimeZone;
+
 /// A field type in the schema
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum FieldType {
+    #[default]
     String,
     Int,
     Float,
@@ -28,9 +38,14 @@ pub enum FieldType {
     Ref(String),
 }
 
-impl Default for FieldType {
-    fn default() -> Self {
-        Self::String
+impl FieldType {
+    /// Collection referenced by a `REF` or array of `REF`s
+    pub fn ref_target(&self) -> Option<&str> {
+        match self {
+            FieldType::Ref(target) => Some(target),
+            FieldType::Array(inner) => inner.ref_target(),
+            _ => None,
+        }
     }
 }
 
@@ -49,12 +64,60 @@ pub struct FieldDef {
     /// Human-readable description
     #[serde(default)]
     pub description: Option<String>,
+    /// Display name in table headers and generated pages (default: the
+    /// field's name)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Display names by locale (`fr`, `pt-BR`), used over `label` when the
+    /// database's `locale` matches
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
     /// Index this field for faster queries
     #[serde(default)]
     pub indexed: bool,
     /// Unique constraint
     #[serde(default)]
     pub unique: bool,
+    /// How `mdby export --scrub` anonymizes the field
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_yaml::with::singleton_map")]
+    pub scrub: Option<Scrub>,
+    /// What deleting the referenced document does, for a REF field
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_delete: Option<OnDelete>,
+    /// Field keeping the offset a DATETIME value was written with, before it
+    /// was stored in the database's `timezone` (`+02:00`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset_field: Option<String>,
+}
+
+impl FieldDef {
+    /// Display name in `locale`: its entry in `labels`, else that of its
+    /// language (`fr` for `fr-CA`), else `label`
+    pub fn display_name(&self, locale: Option<&str>) -> Option<&str> {
+        let localized = locale.and_then(|locale| {
+            let language = locale.split(['-', '_']).next().unwrap_or(locale);
+            self.labels.get(locale).or_else(|| self.labels.get(language))
+        });
+        localized.or(self.label.as_ref()).map(String::as_str)
+    }
+}
+
+impl Default for FieldDef {
+    fn default() -> Self {
+        Self {
+            field_type: FieldType::String,
+            required: false,
+            default: None,
+            description: None,
+            label: None,
+            labels: BTreeMap::new(),
+            indexed: false,
+            unique: false,
+            scrub: None,
+            on_delete: None,
+            offset_field: None,
+        }
+    }
 }
 
 /// Schema for a collection
@@ -65,12 +128,57 @@ pub struct Schema {
     /// Human-readable description
     #[serde(default)]
     pub description: Option<String>,
-    /// Field definitions
+    /// Field definitions, in declaration order
     #[serde(default)]
-    pub fields: HashMap<String, FieldDef>,
+    pub fields: IndexMap<String, FieldDef>,
+    /// Combinations of fields no two documents may share (`[project, slug]`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unique: Vec<Vec<String>>,
     /// ID generation strategy
-    #[serde(default)]
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
     pub id_strategy: IdStrategy,
+    /// How `auto_increment` IDs are written (`task-0001`)
+    #[serde(default, skip_serializing_if = "IdFormat::is_empty")]
+    pub id_format: IdFormat,
+    /// Reject mistyped values instead of coercing them (e.g. `'5'` for an INT)
+    #[serde(default)]
+    pub strict: bool,
+    /// Store bodies longer than this many bytes compressed, in `{id}.md.gz`
+    /// beside the frontmatter (see [`crate::storage::compression`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compress_bodies_over: Option<usize>,
+    /// How `mdby export --scrub` anonymizes document bodies
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_yaml::with::singleton_map")]
+    pub scrub_body: Option<Scrub>,
+    /// How generated pages such as the `@dashboard` view present documents
+    #[serde(default, skip_serializing_if = "DisplayHints::is_empty")]
+    pub display: DisplayHints,
+}
+
+/// Fields with a role in generated pages
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisplayHints {
+    /// Field holding a document's title (default `title`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// DATE or DATETIME field; documents past it are overdue
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due: Option<String>,
+    /// BOOL field; documents where it is true are never overdue
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub done: Option<String>,
+    /// Array field counted in the tag distribution (default `tags`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<String>,
+    /// Leave the collection off the dashboard
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+}
+
+impl DisplayHints {
+    fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
 }
 
 /// Strategy for generating document IDs
@@ -85,7 +193,63 @@ pub enum IdStrategy {
     /// UUID v4
     Uuid,
     /// Derived from a field (e.g., slug from title)
-    Derived { from: String, transform: String },
+    Derived {
+        from: String,
+        transform: String,
+        /// Longest slug; [`crate::slug::DEFAULT_MAX_LENGTH`] if not set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_length: Option<usize>,
+    },
+}
+
+/// How `auto_increment` IDs are written
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdFormat {
+    /// Text before the number (`task-`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// Zero-pad numbers to this many digits (`4` gives `0001`), so IDs sort
+    /// in numeric order; numbers too big for it get more digits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<usize>,
+}
+
+impl IdFormat {
+    fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// The ID of counter value `n`
+    pub fn id(&self, n: u64) -> String {
+        format!("{}{:0width$}", self.prefix.as_deref().unwrap_or(""), n, width = self.width.unwrap_or(0))
+    }
+}
+
+/// What deleting a document does to the REF fields that point at it
+///
+/// Without a rule, the references are left dangling (`mdby validate --links`
+/// reports them).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnDelete {
+    /// Delete the referencing documents too
+    Cascade,
+    /// Set the field to null, or remove the ID from an array of REFs
+    SetNull,
+    /// Refuse to delete a document that is still referenced
+    Restrict,
+}
+
+/// Anonymization rule for `mdby export --scrub`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scrub {
+    /// Leave the field out (or the body empty)
+    Drop,
+    /// Replace with a salted hash; equal values stay equal within one export
+    Hash,
+    /// Keep the first N characters
+    Truncate(usize),
 }
 
 impl Schema {
@@ -94,8 +258,14 @@ impl Schema {
         Self {
             name: name.into(),
             description: None,
-            fields: HashMap::new(),
+            fields: IndexMap::new(),
+            unique: Vec::new(),
             id_strategy: IdStrategy::default(),
+            id_format: IdFormat::default(),
+            strict: false,
+            compress_bodies_over: None,
+            scrub_body: None,
+            display: DisplayHints::default(),
         }
     }
 
@@ -105,16 +275,47 @@ impl Schema {
         self
     }
 
-    /// Validate a document against this schema
-    pub fn validate(&self, doc: &crate::Document) -> Result<(), ValidationError> {
-        // Check required fields
+    /// Field names in declaration order
+    pub fn field_order(&self) -> Vec<String> {
+        self.fields.keys().cloned().collect()
+    }
+
+    /// Display names in `locale` of the fields that have one
+    pub fn labels(&self, locale: Option<&str>) -> BTreeMap<String, String> {
+        self.fields
+            .iter()
+            .filter_map(|(name, def)| def.display_name(locale).map(|label| (name.clone(), label.to_string())))
+            .collect()
+    }
+
+    /// Coerce field values towards their declared types
+    ///
+    /// Only unambiguous conversions are applied (see [`coerce_value`]); anything
+    /// else is left for validation to reject. Does nothing in strict mode.
+    pub fn coerce(&self, doc: &mut crate::Document) {
+        if self.strict {
+            return;
+        }
+
         for (field_name, field_def) in &self.fields {
-            if field_def.required && !doc.fields.contains_key(field_name) {
-                return Err(ValidationError::MissingRequired(field_name.clone()));
+            if let Some(value) = doc.fields.get_mut(field_name) {
+                if let Some(coerced) = coerce_value(&field_def.field_type, value) {
+                    *value = coerced;
+                }
             }
         }
+    }
 
-        // Type checking for fields that exist
+    /// Normalize a stored document's values to their declared types
+    ///
+    /// Frontmatter written by hand (`priority: "5"`) is coerced like an
+    /// inserted value, so comparisons see the declared type. A strict schema
+    /// coerces nothing and fails on the first mistyped field instead.
+    pub fn normalize(&self, doc: &mut crate::Document) -> Result<(), ValidationError> {
+        if !self.strict {
+            self.coerce(doc);
+            return Ok(());
+        }
         for (field_name, field_def) in &self.fields {
             if let Some(value) = doc.fields.get(field_name) {
                 if !check_type_match(&field_def.field_type, value) {
@@ -126,9 +327,116 @@ impl Schema {
                 }
             }
         }
+        Ok(())
+    }
+
+    /// Store the DATETIME values of a document being written in `zone` (see
+    /// [`timezone`])
+    pub fn normalize_datetimes(&self, doc: &mut crate::Document, zone: TimeZone) {
+        for field_name in self.fields.keys() {
+            self.normalize_datetime(doc, field_name, zone);
+        }
+    }
+
+    /// Store `field` in `zone` if it's a DATETIME, recording the offset it
+    /// was written with in its `offset_field`
+    pub fn normalize_datetime(&self, doc: &mut crate::Document, field: &str, zone: TimeZone) {
+        use crate::storage::document::Value;
+        let Some(field_def) = self.fields.get(field).filter(|def| def.field_type == FieldType::DateTime) else {
+            return;
+        };
+        let Some(Value::String(text)) = doc.fields.get(field) else {
+            return;
+        };
+        let Some((stored, offset)) = zone.normalize(text) else {
+            return;
+        };
+        doc.fields.insert(field.to_string(), Value::String(stored));
+        if let Some(offset_field) = &field_def.offset_field {
+            doc.fields.insert(offset_field.clone(), Value::String(offset.to_string()));
+        }
+    }
 
+    /// [`normalize`](Self::normalize) documents read from the collection,
+    /// naming the document that fails
+    pub fn normalize_all(&self, docs: &mut [crate::Document]) -> crate::Result<()> {
+        for doc in docs {
+            self.normalize(doc).map_err(|e| crate::Error::SchemaValidation {
+                collection: self.name.clone(),
+                message: format!("document '{}': {}", doc.id, e),
+            })?;
+        }
         Ok(())
     }
+
+    /// Fill in default values for fields the document doesn't have
+    ///
+    /// Returns the names of the fields that were added.
+    pub fn apply_defaults(&self, doc: &mut crate::Document) -> Vec<String> {
+        let mut added = Vec::new();
+        for (field_name, field_def) in &self.fields {
+            if let Some(default) = &field_def.default {
+                if !doc.fields.contains_key(field_name) {
+                    let value = crate::storage::frontmatter::yaml_value_to_value(default.clone());
+                    doc.fields.insert(field_name.clone(), value);
+                    added.push(field_name.clone());
+                }
+            }
+        }
+        added
+    }
+
+    /// Validate a document against this schema, stopping at the first error
+    pub fn validate(&self, doc: &crate::Document) -> Result<(), ValidationError> {
+        match self.validate_all(doc).into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Validate a document against this schema, collecting every violation
+    ///
+    /// Missing required fields are reported first, then type mismatches.
+    /// Fields are visited in name order so the report is stable between runs.
+    pub fn validate_all(&self, doc: &crate::Document) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let mut field_names: Vec<&String> = self.fields.keys().collect();
+        field_names.sort();
+
+        // Check required fields
+        for field_name in &field_names {
+            if self.fields[*field_name].required && !doc.fields.contains_key(*field_name) {
+                errors.push(ValidationError::MissingRequired((*field_name).clone()));
+            }
+        }
+
+        // Type checking for fields that exist
+        for field_name in &field_names {
+            let field_def = &self.fields[*field_name];
+            if let Some(value) = doc.fields.get(*field_name) {
+                if !check_type_match(&field_def.field_type, value) {
+                    errors.push(ValidationError::TypeMismatch {
+                        field: (*field_name).clone(),
+                        expected: format!("{:?}", field_def.field_type),
+                        actual: describe_value_type(value),
+                    });
+                }
+            }
+        }
+
+        errors
+    }
+}
+
+/// Every validation error found for a single document
+#[derive(Debug)]
+pub struct ValidationReport {
+    /// Collection the document belongs to
+    pub collection: String,
+    /// Document ID
+    pub id: String,
+    /// All violations, in the order returned by [`Schema::validate_all`]
+    pub errors: Vec<ValidationError>,
 }
 
 /// Check if a Value matches the expected FieldType
@@ -212,7 +520,7 @@ fn is_valid_date(s: &str) -> bool {
 
     match (year, month, day) {
         (Some(_y), Some(m), Some(d)) => {
-            m >= 1 && m <= 12 && d >= 1 && d <= 31
+            (1..=12).contains(&m) && (1..=31).contains(&d)
         }
         _ => false,
     }
@@ -251,7 +559,7 @@ fn is_valid_datetime(s: &str) -> bool {
         let time_part = &s[11..];
         let time_base: &str = if time_part.contains('Z') || time_part.contains('+') || time_part.contains('-') {
             // Has timezone, extract time portion
-            time_part.split(|c| c == 'Z' || c == '+').next().unwrap_or("")
+            time_part.split(['Z', '+']).next().unwrap_or("")
         } else {
             time_part
         };
@@ -288,6 +596,14 @@ pub enum ValidationError {
     },
     #[error("Unique constraint violated for field: {0}")]
     UniqueViolation(String),
+    #[error("Unique constraint violated for fields: {}", .0.join(", "))]
+    UniqueCombinationViolation(Vec<String>),
+    #[error("Field {field} references missing document {collection}/{id}")]
+    MissingReference {
+        field: String,
+        collection: String,
+        id: String,
+    },
 }
 
 /// Registry of all schemas in the database
@@ -298,6 +614,15 @@ pub struct SchemaRegistry {
 }
 
 impl SchemaRegistry {
+    /// Load one collection's schema from the database directory, if it has one
+    pub fn load_one(db_path: &Path, name: &str) -> anyhow::Result<Option<Schema>> {
+        let path = db_path.join(".mdby").join("schemas").join(format!("{}.yaml", name));
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_yaml::from_str(&std::fs::read_to_string(&path)?)?))
+    }
+
     /// Load schemas from the database directory
     pub fn load(db_path: &Path) -> anyhow::Result<Self> {
         let schema_path = db_path.join(".mdby").join("schemas");
@@ -321,6 +646,14 @@ impl SchemaRegistry {
         Ok(registry)
     }
 
+    /// A registry of already-parsed schemas for the database at `db_path`
+    pub fn from_schemas(db_path: &Path, schemas: impl IntoIterator<Item = Schema>) -> Self {
+        Self {
+            schemas: schemas.into_iter().map(|schema| (schema.name.clone(), schema)).collect(),
+            path: db_path.join(".mdby").join("schemas"),
+        }
+    }
+
     /// Get a schema by collection name
     pub fn get(&self, name: &str) -> Option<&Schema> {
         self.schemas.get(name)
@@ -332,6 +665,10 @@ impl SchemaRegistry {
         std::fs::create_dir_all(&self.path)?;
         let file_path = self.path.join(format!("{}.yaml", schema.name));
         let content = serde_yaml::to_string(&schema)?;
+        // `path` is `/.mdby/schemas` under the database root
+        if let Some(root) = self.path.parent().and_then(Path::parent) {
+            crate::storage::journal::preserve(root, &file_path)?;
+        }
         std::fs::write(file_path, content)?;
 
         self.schemas.insert(schema.name.clone(), schema);
@@ -377,6 +714,64 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_apply_defaults_only_fills_missing_fields() {
+        let schema = Schema::new("todos")
+            .field("done", FieldDef {
+                field_type: FieldType::Bool,
+                default: Some(serde_yaml::Value::Bool(false)),
+                ..Default::default()
+            })
+            .field("priority", FieldDef {
+                field_type: FieldType::Int,
+                default: Some(serde_yaml::Value::Number(3.into())),
+                ..Default::default()
+            })
+            .field("title", FieldDef::default());
+
+        let mut doc = crate::Document::new("task-1");
+        doc.set("done", true);
+
+        assert_eq!(schema.apply_defaults(&mut doc), vec!["priority".to_string()]);
+        assert_eq!(doc.get("done"), Some(&crate::storage::document::Value::Bool(true)));
+        assert_eq!(doc.get("priority"), Some(&crate::storage::document::Value::Int(3)));
+        assert!(doc.get("title").is_none());
+    }
+
+    #[test]
+    fn test_validate_all_collects_every_error() {
+        let schema = Schema::new("todos")
+            .field("title", FieldDef {
+                field_type: FieldType::String,
+                required: true,
+                ..Default::default()
+            })
+            .field("owner", FieldDef {
+                field_type: FieldType::String,
+                required: true,
+                ..Default::default()
+            })
+            .field("priority", FieldDef {
+                field_type: FieldType::Int,
+                ..Default::default()
+            });
+
+        let mut doc = crate::Document::new("task-1");
+        doc.set("priority", "high");
+
+        let errors = schema.validate_all(&doc);
+        assert_eq!(errors.len(), 3);
+        assert!(matches!(&errors[0], ValidationError::MissingRequired(f) if f == "owner"));
+        assert!(matches!(&errors[1], ValidationError::MissingRequired(f) if f == "title"));
+        assert!(matches!(&errors[2], ValidationError::TypeMismatch { field, .. } if field == "priority"));
+
+        // validate() reports the first of those
+        assert!(matches!(
+            schema.validate(&doc),
+            Err(ValidationError::MissingRequired(f)) if f == "owner"
+        ));
+    }
+
     #[test]
     fn test_type_validation_string() {
         let schema = Schema::new("test")
@@ -539,6 +934,51 @@ mod tests {
         assert!(schema.validate(&doc).is_ok());
     }
 
+    #[test]
+    fn test_normalize() {
+        let mut schema = Schema::new("todos")
+            .field("priority", FieldDef { field_type: FieldType::Int, ..Default::default() })
+            .field("done", FieldDef { field_type: FieldType::Bool, ..Default::default() });
+
+        let mut doc = crate::Document::new("task-1");
+        doc.set("priority", "5");
+        doc.set("done", "true");
+        schema.normalize(&mut doc).unwrap();
+        assert_eq!(doc.get("priority"), Some(&Value::Int(5)));
+        assert_eq!(doc.get("done"), Some(&Value::Bool(true)));
+
+        schema.strict = true;
+        assert!(schema.normalize(&mut doc).is_ok());
+        doc.set("priority", "5");
+        assert!(matches!(
+            schema.normalize(&mut doc),
+            Err(ValidationError::TypeMismatch { field, .. }) if field == "priority"
+        ));
+    }
+
+    #[test]
+    fn test_id_format() {
+        assert_eq!(IdFormat::default().id(7), "7");
+        let format = IdFormat { prefix: Some("task-".into()), width: Some(4) };
+        assert_eq!(format.id(7), "task-0007");
+        assert_eq!(format.id(12345), "task-12345");
+        assert!(format.id(9) < format.id(10));
+    }
+
+    #[test]
+    fn test_labels() {
+        let yaml = "name: todos\nfields:\n  priority:\n    type: int\n    label: Priority\n    labels:\n      fr: Priorité\n      pt-BR: Prioridade\n  done:\n    type: bool\n";
+        let schema: Schema = serde_yaml::from_str(yaml).unwrap();
+        let priority = &schema.fields["priority"];
+        assert_eq!(priority.display_name(None), Some("Priority"));
+        assert_eq!(priority.display_name(Some("fr")), Some("Priorité"));
+        assert_eq!(priority.display_name(Some("fr-CA")), Some("Priorité"));
+        assert_eq!(priority.display_name(Some("pt-BR")), Some("Prioridade"));
+        assert_eq!(priority.display_name(Some("de")), Some("Priority"));
+        assert_eq!(schema.fields["done"].display_name(Some("fr")), None);
+        assert_eq!(schema.labels(Some("fr")), BTreeMap::from([("priority".to_string(), "Priorité".to_string())]));
+    }
+
     #[test]
     fn test_date_validation_helpers() {
         assert!(is_valid_date("2024-01-15"));
@@ -553,16 +993,3 @@ mod tests {
         assert!(!is_valid_datetime("not-a-datetime"));
     }
 }
-
-impl Default for FieldDef {
-    fn default() -> Self {
-        Self {
-            field_type: FieldType::String,
-            required: false,
-            default: None,
-            description: None,
-            indexed: false,
-            unique: false,
-        }
-    }
-}
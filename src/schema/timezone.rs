@@ -0,0 +1,150 @@
+//! Time zone normalization of DATETIME values
+//!
+//! Collaborators in different zones write datetimes with different offsets
+//! (`2024-03-01T09:00:00+01:00` is `2024-03-01T08:00:00Z`), and as strings
+//! those sort out of order. Datetimes written to a DATETIME field are stored
+//! in one zone instead - UTC, or the fixed offset set as `timezone` in
+//! `.mdby/config.yaml` - so ORDER BY and range filters see moments in order.
+//! A datetime without an offset is taken to be in that zone already. A
+//! field's `offset_field` keeps the offset each value was written with.
+//!
+//! Zones are fixed offsets rather than names like `Europe/Paris`: a named
+//! zone's offset changes with daylight saving time, and stored values would
+//! stop sorting in order across the change.
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, SecondsFormat};
+use std::fmt;
+use std::str::FromStr;
+
+/// Formats of datetimes with an offset, after RFC 3339
+const OFFSET_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S%.f%#z",
+    "%Y-%m-%d %H:%M:%S%.f%#z",
+    "%Y-%m-%dT%H:%M%#z",
+    "%Y-%m-%d %H:%M%#z",
+];
+
+/// Formats of datetimes without one
+const LOCAL_FORMATS: [&str; 4] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"];
+
+/// The zone DATETIME values are stored in (`UTC` or an offset like `+02:00`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeZone(FixedOffset);
+
+impl TimeZone {
+    pub fn utc() -> Self {
+        Self(FixedOffset::east_opt(0).expect("zero offset"))
+    }
+
+    pub fn is_utc(&self) -> bool {
+        self.0.local_minus_utc() == 0
+    }
+
+    /// `text` in this zone, with the offset it was written with; `None` if
+    /// it isn't a datetime with a time of day
+    pub fn normalize(&self, text: &str) -> Option<(String, FixedOffset)> {
+        let text = text.trim();
+        let written = DateTime::parse_from_rfc3339(text)
+            .ok()
+            .or_else(|| OFFSET_FORMATS.iter().find_map(|format| DateTime::parse_from_str(text, format).ok()))
+            .or_else(|| {
+                let local = LOCAL_FORMATS.iter().find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())?;
+                local.and_local_timezone(self.0).single()
+            })?;
+        let stored = written.with_timezone(&self.0);
+        Some((stored.to_rfc3339_opts(SecondsFormat::AutoSi, true), *written.offset()))
+    }
+}
+
+impl Default for TimeZone {
+    fn default() -> Self {
+        Self::utc()
+    }
+}
+
+impl fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_utc() {
+            write!(f, "UTC")
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+impl FromStr for TimeZone {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid timezone '{}': expected UTC or an offset like +02:00", s);
+        let text = s.trim();
+        if text.eq_ignore_ascii_case("utc") || text == "Z" {
+            return Ok(Self::utc());
+        }
+        let (sign, rest) = match text.as_bytes().first() {
+            Some(b'+') => (1, &text[1..]),
+            Some(b'-') => (-1, &text[1..]),
+            _ => return Err(invalid()),
+        };
+        let (hours, minutes) = match rest.split_once(':') {
+            Some((hours, minutes)) => (hours, minutes),
+            None if rest.len() == 4 => rest.split_at(2),
+            None => (rest, "0"),
+        };
+        let hours: i32 = hours.parse().map_err(|_| invalid())?;
+        let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+        if hours > 23 || minutes > 59 {
+            return Err(invalid());
+        }
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).map(Self).ok_or_else(invalid)
+    }
+}
+
+impl TryFrom<String> for TimeZone {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> anyhow::Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<TimeZone> for String {
+    fn from(zone: TimeZone) -> Self {
+        zone.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let utc = TimeZone::utc();
+        let (stored, offset) = utc.normalize("2024-03-01T09:00:00+01:00").unwrap();
+        assert_eq!(stored, "2024-03-01T08:00:00Z");
+        assert_eq!(offset.to_string(), "+01:00");
+        assert_eq!(utc.normalize("2024-03-01 09:00-0500").unwrap().0, "2024-03-01T14:00:00Z");
+        assert_eq!(utc.normalize("2024-03-01T09:00:00.250Z").unwrap().0, "2024-03-01T09:00:00.250Z");
+        assert_eq!(utc.normalize("2024-03-01 09:00:00").unwrap().0, "2024-03-01T09:00:00Z");
+        assert!(utc.normalize("2024-03-01").is_none());
+        assert!(utc.normalize("next tuesday").is_none());
+
+        let tokyo: TimeZone = "+09:00".parse().unwrap();
+        assert_eq!(tokyo.normalize("2024-03-01T08:00:00Z").unwrap().0, "2024-03-01T17:00:00+09:00");
+        let (stored, offset) = tokyo.normalize("2024-03-01T08:00").unwrap();
+        assert_eq!(stored, "2024-03-01T08:00:00+09:00");
+        assert_eq!(offset.to_string(), "+09:00");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!("utc".parse::<TimeZone>().unwrap().is_utc());
+        assert_eq!("-0330".parse::<TimeZone>().unwrap().to_string(), "-03:30");
+        assert_eq!("+2".parse::<TimeZone>().unwrap().to_string(), "+02:00");
+        assert_eq!(TimeZone::utc().to_string(), "UTC");
+        assert!("Europe/Paris".parse::<TimeZone>().is_err());
+        assert!("+25:00".parse::<TimeZone>().is_err());
+    }
+}
@@ -0,0 +1,405 @@
+//! Fake document generation (`mdby seed`)
+//!
+//! Seeding adds documents with plausible values to a collection, for demos and
+//! performance tests. Each field gets a value of its schema type, guessed from
+//! its name where that helps (`email`, `name`, `title`, `url`, ...):
+//!
+//! - `REF<collection>` fields point at existing documents of that collection
+//! - UNIQUE fields get distinct values
+//! - the lint rules' `allowed_tags` are used for the tags field, and bodies
+//!   contain the `required_headings`
+//! - optional fields are left out of some documents
+//!
+//! Unless values come from the schema alone (`from_schema`), fields that
+//! existing documents already use take their values from those documents, so
+//! status-like fields keep their real set of values. IDs follow the schema's
+//! `id_strategy`; with `manual`, they are `{collection}-{n}`.
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::schema::{FieldDef, FieldType};
+use crate::slug::SlugOptions;
+use crate::storage::document::{Document, Value};
+use crate::Database;
+
+const WORDS: &[&str] = &[
+    "apple", "river", "garden", "engine", "window", "harbor", "signal", "forest", "ledger", "market",
+    "planet", "canvas", "bridge", "summit", "meadow", "orbit", "lantern", "anchor", "voyage", "quartz",
+    "timber", "falcon", "cedar", "copper", "pixel", "thunder", "velvet", "beacon", "compass", "glacier",
+];
+
+const FIRST_NAMES: &[&str] = &[
+    "Ada", "Alan", "Grace", "Linus", "Barbara", "Dennis", "Margaret", "Ken", "Frances", "Edsger",
+    "Radia", "Donald", "Sophie", "Tim", "Hedy", "Guido",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Lovelace", "Turing", "Hopper", "Torvalds", "Liskov", "Ritchie", "Hamilton", "Thompson", "Allen",
+    "Dijkstra", "Perlman", "Knuth", "Wilson", "Berners-Lee", "Lamarr", "van Rossum",
+];
+
+/// Attempts at a fresh value for a UNIQUE field before giving up
+const UNIQUE_ATTEMPTS: usize = 100;
+
+/// Add `count` generated documents to a collection in one commit, returning
+/// their IDs
+///
+/// `seed` makes the output repeatable; without one, each run differs.
+pub async fn seed(db: &Database, name: &str, count: usize, from_schema: bool, seed: Option<u64>) -> anyhow::Result<Vec<String>> {
+    let schema = db.schema.get(name);
+    if from_schema && schema.is_none() {
+        anyhow::bail!("Collection '{}' has no schema to generate documents from", name);
+    }
+    let existing = if db.store.exists(name).await? { db.store.list(name).await? } else { Vec::new() };
+    if schema.is_none() && existing.is_empty() {
+        anyhow::bail!("Collection '{}' has no schema or documents to generate documents from", name);
+    }
+
+    let mut generator = Generator {
+        rng: Rng::new(seed.unwrap_or_else(|| uuid::Uuid::new_v4().as_u64_pair().0)),
+        rules: db.lint.rules(name),
+        samples: BTreeMap::new(),
+        presence: BTreeMap::new(),
+        taken: BTreeMap::new(),
+        sample_bodies: Vec::new(),
+    };
+    if !from_schema {
+        generator.learn(&existing);
+    }
+
+    // REF targets and UNIQUE values already in use
+    let mut targets: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut fields: Vec<(String, FieldDef)> = schema
+        .map(|s| s.fields.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+        .unwrap_or_default();
+    for field in generator.samples.keys() {
+        if !fields.iter().any(|(name, _)| name == field) {
+            fields.push((field.clone(), FieldDef::default()));
+        }
+    }
+    for (field, def) in &fields {
+        if let Some(target) = def.field_type.ref_target() {
+            if !targets.contains_key(target) {
+                let ids = match db.store.exists(target).await? {
+                    true => db.store.ids(target).await?,
+                    false => Vec::new(),
+                };
+                targets.insert(target.to_string(), ids);
+            }
+        }
+        if def.unique {
+            let values = existing.iter().filter_map(|doc| doc.fields.get(field)).cloned().collect();
+            generator.taken.insert(field.clone(), values);
+        }
+    }
+
+    let mut docs = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut doc = Document::new(String::new());
+        for (field, def) in &fields {
+            if let Some(value) = generator.field(field, def, &targets)? {
+                doc.fields.insert(field.clone(), value);
+            }
+        }
+        doc.body = generator.body();
+        docs.push(doc);
+    }
+
+    // IDs, distinct from existing documents and from each other
+    let strategy = schema.map(|s| s.id_strategy.clone()).unwrap_or_default();
+    let format = schema.map(|s| s.id_format.clone()).unwrap_or_default();
+    let stored: HashSet<String> = existing.iter().map(|doc| doc.id.clone()).collect();
+    let mut batch: HashSet<String> = HashSet::new();
+    let mut next = 1;
+    for doc in &mut docs {
+        let base = match crate::schema::ids::generate(&strategy, &format, &db.root, name, doc, |id| stored.contains(id)).await? {
+            Some(id) => id,
+            None => loop {
+                let id = format!("{}-{}", name, next);
+                next += 1;
+                if !stored.contains(&id) {
+                    break id;
+                }
+            },
+        };
+        let id = crate::slug::unique(&base, &SlugOptions::default(), |id| batch.contains(id));
+        batch.insert(id.clone());
+        doc.id = id;
+    }
+
+    for doc in &mut docs {
+        if let Some(schema) = schema {
+            schema.coerce(doc);
+            schema.validate(doc)?;
+        }
+        db.config.limits.check(doc)?;
+        db.lint.enforce(name, doc)?;
+    }
+    crate::query::check_unique(db, name, &docs).await?;
+
+    let layout = crate::query::layout(db, name);
+    db.store.create_collection(name).await?;
+    for doc in &docs {
+        db.store.put(name, doc, &layout).await?;
+    }
+    crate::query::update_indexes(db, name, &docs, &[])?;
+    if !docs.is_empty() {
+        db.git.commit(&format!("SEED {}: {} document(s)", name, docs.len()))?;
+    }
+
+    Ok(docs.into_iter().map(|doc| doc.id).collect())
+}
+
+struct Generator<'a> {
+    rng: Rng,
+    rules: Option<&'a crate::lint::CollectionRules>,
+    /// Values existing documents hold, by field
+    samples: BTreeMap<String, Vec<Value>>,
+    /// Share of existing documents that have each field
+    presence: BTreeMap<String, f64>,
+    /// Values of UNIQUE fields already used
+    taken: BTreeMap<String, Vec<Value>>,
+    sample_bodies: Vec<String>,
+}
+
+impl Generator<'_> {
+    fn learn(&mut self, docs: &[Document]) {
+        for doc in docs {
+            for (field, value) in &doc.fields {
+                if !matches!(value, Value::Null) {
+                    self.samples.entry(field.clone()).or_default().push(value.clone());
+                }
+            }
+            if !doc.body.is_empty() {
+                self.sample_bodies.push(doc.body.clone());
+            }
+        }
+        for (field, values) in &self.samples {
+            self.presence.insert(field.clone(), values.len() as f64 / docs.len() as f64);
+        }
+    }
+
+    /// A value for a field, or `None` to leave it out
+    fn field(&mut self, name: &str, def: &FieldDef, targets: &BTreeMap<String, Vec<String>>) -> anyhow::Result<Option<Value>> {
+        let presence = self.presence.get(name).copied().unwrap_or(0.8);
+        if !def.required && !self.rng.chance(presence) {
+            return Ok(None);
+        }
+
+        if !def.unique {
+            return Ok(self.value(name, &def.field_type, targets));
+        }
+        for attempt in 0..UNIQUE_ATTEMPTS {
+            let Some(mut value) = self.value(name, &def.field_type, targets) else {
+                return Ok(None);
+            };
+            if attempt > 0 {
+                value = numbered(value, attempt + 1);
+            }
+            let taken = self.taken.entry(name.to_string()).or_default();
+            if !taken.contains(&value) {
+                taken.push(value.clone());
+                return Ok(Some(value));
+            }
+        }
+        anyhow::bail!("Cannot generate enough distinct values for UNIQUE field '{}'", name)
+    }
+
+    fn value(&mut self, name: &str, field_type: &FieldType, targets: &BTreeMap<String, Vec<String>>) -> Option<Value> {
+        if let Some(values) = self.samples.get(name).filter(|v| !v.is_empty()) {
+            return Some(self.rng.pick(values).clone());
+        }
+
+        let lower = name.to_ascii_lowercase();
+        let tags_field = self.rules.and_then(|r| r.tags_field.as_deref()).unwrap_or("tags");
+        let value = match field_type {
+            FieldType::String => Value::String(self.string(&lower)),
+            FieldType::Int => Value::Int(self.rng.below(1000) as i64 + 1),
+            FieldType::Float => Value::Float((self.rng.below(100_000) as f64) / 100.0),
+            FieldType::Bool => Value::Bool(self.rng.chance(0.5)),
+            FieldType::Date => Value::String(self.date().format("%Y-%m-%d").to_string()),
+            FieldType::DateTime => {
+                let time = chrono::NaiveTime::from_num_seconds_from_midnight_opt(self.rng.below(86_400) as u32, 0)?;
+                Value::String(self.date().and_time(time).format("%Y-%m-%dT%H:%M:%SZ").to_string())
+            }
+            FieldType::Object => Value::Object(BTreeMap::new()),
+            FieldType::Ref(target) => Value::String(self.rng.pick(targets.get(target).filter(|ids| !ids.is_empty())?).clone()),
+            FieldType::Array(inner) => {
+                if name == tags_field {
+                    if let Some(allowed) = self.rules.and_then(|r| r.allowed_tags.as_ref()).filter(|a| !a.is_empty()) {
+                        let mut tags: Vec<Value> = Vec::new();
+                        for _ in 0..=self.rng.below(3) {
+                            let tag = Value::String(self.rng.pick(allowed).clone());
+                            if !tags.contains(&tag) {
+                                tags.push(tag);
+                            }
+                        }
+                        return Some(Value::Array(tags));
+                    }
+                }
+                let len = self.rng.below(3) + 1;
+                let item = match **inner {
+                    FieldType::String => "tag".to_string(),
+                    _ => name.to_string(),
+                };
+                Value::Array((0..len).filter_map(|_| self.value(&item, inner, targets)).collect())
+            }
+        };
+        Some(value)
+    }
+
+    fn string(&mut self, name: &str) -> String {
+        let first = *self.rng.pick(FIRST_NAMES);
+        let last = *self.rng.pick(LAST_NAMES);
+        if name.contains("email") {
+            format!("{}.{}@example.com", first.to_lowercase(), last.to_lowercase().replace(' ', ""))
+        } else if name.contains("url") || name.contains("link") || name.contains("website") {
+            format!("https://example.com/{}", self.words(2).replace(' ', "-"))
+        } else if name.contains("phone") {
+            format!("555-01{:02}", self.rng.below(100))
+        } else if name.contains("name") || name == "author" || name == "owner" {
+            format!("{} {}", first, last)
+        } else if name.contains("title") || name.contains("subject") || name.contains("summary") {
+            let n = 2 + self.rng.below(4);
+            let mut title = capitalize(&self.words(n));
+            if let Some(max) = self.rules.and_then(|r| r.max_title_length) {
+                title = title.chars().take(max).collect::<String>().trim_end().to_string();
+            }
+            title
+        } else if name == "tag" {
+            self.rng.pick(WORDS).to_string()
+        } else {
+            let n = 1 + self.rng.below(3);
+            self.words(n)
+        }
+    }
+
+    fn body(&mut self) -> String {
+        if !self.sample_bodies.is_empty() {
+            return self.rng.pick(&self.sample_bodies).clone();
+        }
+        let mut sections: Vec<String> = self
+            .rules
+            .map(|r| r.required_headings.clone())
+            .unwrap_or_default()
+            .iter()
+            .map(|heading| {
+                let heading = if heading.starts_with('#') { heading.clone() } else { format!("## {}", heading) };
+                format!("{}\n\n{}", heading, self.paragraph())
+            })
+            .collect();
+        if sections.is_empty() {
+            sections.push(self.paragraph());
+        }
+        sections.join("\n\n")
+    }
+
+    fn paragraph(&mut self) -> String {
+        let sentences: Vec<String> = (0..2 + self.rng.below(3))
+            .map(|_| {
+                let n = 5 + self.rng.below(8);
+                format!("{}.", capitalize(&self.words(n)))
+            })
+            .collect();
+        sentences.join(" ")
+    }
+
+    fn words(&mut self, n: usize) -> String {
+        (0..n).map(|_| *self.rng.pick(WORDS)).collect::<Vec<_>>().join(" ")
+    }
+
+    /// A day between 2020-01-01 and 2026-12-31
+    fn date(&mut self) -> chrono::NaiveDate {
+        let start = chrono::NaiveDate::from_ymd_opt(2020, 1, 1).unwrap_or_default();
+        start + chrono::Days::new(self.rng.below(2557) as u64)
+    }
+}
+
+/// A variant of a value for another attempt at a UNIQUE field
+fn numbered(value: Value, n: usize) -> Value {
+    match value {
+        Value::String(s) => Value::String(match s.split_once('@') {
+            Some((user, domain)) => format!("{}{}@{}", user, n, domain),
+            None if s.contains(' ') => format!("{} {}", s, n),
+            None => format!("{}-{}", s, n),
+        }),
+        Value::Int(i) => Value::Int(i + 1000 * n as i64),
+        Value::Float(f) => Value::Float(f + 1000.0 * n as f64),
+        other => other,
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// SplitMix64; small, seedable and good enough for fake data
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+
+    fn chance(&mut self, p: f64) -> bool {
+        ((self.next() >> 11) as f64) < p * (1u64 << 53) as f64
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Schema;
+
+    #[test]
+    fn test_generated_values_match_types() {
+        let schema = Schema::new("people")
+            .field("email", FieldDef { unique: true, required: true, ..Default::default() })
+            .field("born", FieldDef { field_type: FieldType::Date, required: true, ..Default::default() })
+            .field("score", FieldDef { field_type: FieldType::Float, required: true, ..Default::default() })
+            .field("tags", FieldDef { field_type: FieldType::Array(Box::new(FieldType::String)), ..Default::default() });
+        let mut generator = Generator {
+            rng: Rng::new(7),
+            rules: None,
+            samples: BTreeMap::new(),
+            presence: BTreeMap::new(),
+            taken: BTreeMap::new(),
+            sample_bodies: Vec::new(),
+        };
+
+        let mut emails = HashSet::new();
+        for _ in 0..200 {
+            let mut doc = Document::new("x");
+            for (name, def) in &schema.fields {
+                if let Some(value) = generator.field(name, def, &BTreeMap::new()).unwrap() {
+                    doc.fields.insert(name.clone(), value);
+                }
+            }
+            schema.validate(&doc).unwrap();
+            let email = doc.fields["email"].as_str().unwrap().to_string();
+            assert!(email.ends_with("@example.com"));
+            assert!(emails.insert(email));
+        }
+    }
+}
@@ -0,0 +1,869 @@
+//! HTTP API (`mdby serve`)
+//!
+//! Exposes a database over HTTP:
+//!
+//! - `POST /query` runs the MDQL statement in the request body (JSON
+//!   `{"query": "..."}`, or plain text with an `MDBY-Query` header) and
+//!   returns its result as JSON, in the same shape as
+//!   `mdby --format json query`
+//! - `POST /batch` runs a JSON array of MDQL statements (or
+//!   `{"statements": [...]}`, or a script as `{"script": "..."}`) as one
+//!   transaction: one commit if they all succeed, nothing kept if one fails.
+//!   The response lists each statement's result and its `kind`, and has a 4xx
+//!   status and an `error` if one failed
+//! - `GET /collections/{name}` returns a collection's documents,
+//!   filtered and sorted by the `where` and `order_by` query parameters (an
+//!   MDQL condition and ORDER BY list), and paged by `limit` and `offset`
+//! - `GET /collections/{name}/{id}` returns one document as JSON, with its
+//!   version as an `ETag`
+//! - `PUT /collections/{name}/{id}` writes a document from a JSON object of
+//!   fields (`_body` for the body), creating it or setting the fields given
+//! - `PATCH /collections/{name}/{id}` sets the fields given on a document
+//! - `DELETE /collections/{name}/{id}` deletes a document
+//! - `GET /views/{name}` returns a view's rendered HTML, if the execution
+//!   policy allows running the view's query
+//! - `GET /openapi.json` describes these routes for this database
+//!   ([`crate::openapi`]), with each collection's document shape
+//!
+//! `POST /query` and the two `GET /collections` routes send results as JSON,
+//! CSV or an HTML table, whichever the `Accept` header prefers (JSON without
+//! one, 406 if none of them is acceptable). Other responses, and errors, are
+//! always JSON. Browser pages on the origins in `serve.cors_origins`
+//! ([`ServeSettings`](crate::config::ServeSettings)) may call the API, and
+//! read the `ETag` of its responses.
+//!
+//! Results of different kinds can have the same JSON (a list of documents,
+//! collections or views), so `POST /query` names the kind of its result in an
+//! `MDBY-Result` header ([`result_kind`]), which is how the `mdby-client`
+//! crate rebuilds a [`QueryResult`].
+//!
+//! A document's version is the last commit that wrote it
+//! ([`Database::document_version`]). Writes with an `If-Match` header only
+//! happen if the document is still at one of the versions listed (`*`: any),
+//! and otherwise fail with 412, so two clients editing the same document
+//! can't silently overwrite each other.
+//!
+//! Requests run one at a time on a thread that owns the database (a git
+//! repository handle can't be shared between threads), so nothing writes
+//! between a version check and the write it guards. Statements run through
+//! the database's [`ExecutionPolicy`](crate::policy::ExecutionPolicy), so a
+//! server started with a read-only policy only answers queries. `BODY FROM
+//! FILE` is always refused, since it would read files on the server. Errors are
+//! returned as `{"error": "...", "hint": "..."}` with a 4xx status.
+//!
+//! Browsers let any page post a form or plain text to another origin, with
+//! CORS only hiding the response, so a request that runs statements must be
+//! one they would check with the server first: JSON, or with an `MDBY-Query`
+//! header ([`QUERY_HEADER`]). Others get 415 and run nothing.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures_util::future::LocalBoxFuture;
+use futures_util::FutureExt;
+use serde_json::json;
+use tokio::sync::{mpsc, oneshot};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::storage::document::Document;
+use crate::validation::{validate_collection_name, validate_document_id, validate_view_name};
+use crate::{BatchResult, CollectionDescription, Database, QueryResult};
+
+/// Work for the database thread, which sends its own result back
+type Job = Box<dyn for<'a> FnOnce(&'a mut Database) -> LocalBoxFuture<'a, ()> + Send>;
+
+#[derive(Clone)]
+struct Shared {
+    root: PathBuf,
+    jobs: mpsc::Sender<Job>,
+}
+
+impl Shared {
+    /// Run `f` on the database thread and return its result
+    async fn run<T, F>(&self, f: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: for<'a> FnOnce(&'a mut Database) -> LocalBoxFuture<'a, anyhow::Result<T>> + Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let job: Job = Box::new(move |db| {
+            async move {
+                let _ = reply.send(f(db).await);
+            }
+            .boxed_local()
+        });
+        self.jobs
+            .send(job)
+            .await
+            .map_err(|_| anyhow::anyhow!("The database thread has stopped"))?;
+        result.await.map_err(|_| anyhow::anyhow!("The database thread has stopped"))?
+    }
+
+    /// Run a statement on the database thread
+    async fn execute(&self, statement: String) -> anyhow::Result<QueryResult> {
+        self.run(move |db| async move { db.execute(&statement).await }.boxed_local()).await
+    }
+}
+
+/// Routes of the HTTP API, serving `db`
+///
+/// The database moves to its own thread, which stops when the router is dropped.
+pub fn router(mut db: Database) -> Router {
+    db.set_policy(db.policy().clone().deny_body_files());
+    let cors = cors(&db.config.serve.cors_origins);
+    let root = db.root.clone();
+    let (jobs, mut received) = mpsc::channel::<Job>(64);
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to start the database thread");
+        runtime.block_on(async {
+            while let Some(job) = received.recv().await {
+                job(&mut db).await;
+            }
+        });
+    });
+
+    let router = Router::new()
+        .route("/query", post(query))
+        .route("/batch", post(batch))
+        .route("/collections/{name}", get(documents))
+        .route(
+            "/collections/{name}/{id}",
+            get(document).put(put_document).patch(patch_document).delete(delete_document),
+        )
+        .route("/views/{name}", get(view))
+        .route("/openapi.json", get(openapi))
+        .with_state(Shared { root, jobs });
+    match cors {
+        Some(cors) => router.layer(cors),
+        None => router,
+    }
+}
+
+/// CORS headers letting pages on `origins` call the API; `None` without any
+fn cors(origins: &[String]) -> Option<CorsLayer> {
+    if origins.is_empty() {
+        return None;
+    }
+    let allowed = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        // Browsers send the origin without a trailing slash
+        AllowOrigin::list(origins.iter().filter_map(|origin| {
+            let value = HeaderValue::from_str(origin.trim_end_matches('/'));
+            if value.is_err() {
+                tracing::warn!("Ignoring invalid CORS origin '{}'", origin);
+            }
+            value.ok()
+        }))
+    };
+    Some(
+        CorsLayer::new()
+            .allow_origin(allowed)
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+            .allow_headers([header::ACCEPT, header::CONTENT_TYPE, header::IF_MATCH, HeaderName::from_static(QUERY_HEADER)])
+            .expose_headers([header::ETAG]),
+    )
+}
+
+/// Serve the HTTP API on `addr` until the process is stopped
+pub async fn serve(db: Database, addr: SocketAddr) -> anyhow::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!("Serving {:?} on http://{}", db.root, listener.local_addr()?);
+    axum::serve(listener, router(db)).await?;
+    Ok(())
+}
+
+async fn query(State(db): State<Shared>, headers: HeaderMap, body: String) -> Response {
+    let Some(representation) = Representation::accepted(&headers) else {
+        return not_acceptable();
+    };
+    if let Some(refused) = refuse_simple_request(&headers) {
+        return refused;
+    }
+    let statement = if is_json(&headers) {
+        match serde_json::from_str::<serde_json::Value>(&body) {
+            Ok(json) => match json.get("query").and_then(|q| q.as_str()) {
+                Some(statement) => statement.to_string(),
+                None => return error(StatusCode::BAD_REQUEST, "Expected a JSON object with a \"query\" string", None),
+            },
+            Err(e) => return error(StatusCode::BAD_REQUEST, &format!("Invalid JSON: {}", e), None),
+        }
+    } else {
+        body
+    };
+
+    match db.execute(statement).await {
+        Ok(result) => {
+            let mut response = representation.respond(result_json(&result));
+            response
+                .headers_mut()
+                .insert(RESULT_KIND, HeaderValue::from_static(result_kind(&result)));
+            response
+        }
+        Err(e) => anyhow_error(e),
+    }
+}
+
+async fn batch(State(db): State<Shared>, headers: HeaderMap, body: String) -> Response {
+    if let Some(refused) = refuse_simple_request(&headers) {
+        return refused;
+    }
+    let json = match serde_json::from_str::<serde_json::Value>(&body) {
+        Ok(json) => json,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &format!("Invalid JSON: {}", e), None),
+    };
+    if let Some(script) = json.get("script").and_then(|script| script.as_str()) {
+        let script = script.to_string();
+        return match db.run(move |db| async move { db.execute_script(&script).await }.boxed_local()).await {
+            Ok(batch) => batch_response(&batch, "line"),
+            Err(e) => anyhow_error(e),
+        };
+    }
+    let list = if json.is_array() { Some(&json) } else { json.get("statements") };
+    let statements: Option<Vec<String>> = list
+        .and_then(|list| list.as_array())
+        .and_then(|list| list.iter().map(|s| s.as_str().map(str::to_string)).collect());
+    let Some(statements) = statements.filter(|statements| !statements.is_empty()) else {
+        return error(StatusCode::BAD_REQUEST, "Expected a JSON array of statement strings", None);
+    };
+
+    match db.run(move |db| async move { db.execute_batch(&statements).await }.boxed_local()).await {
+        Ok(batch) => batch_response(&batch, "index"),
+        Err(e) => anyhow_error(e),
+    }
+}
+
+/// A batch's outcomes, with the status and error of the statement that
+/// failed if one did
+fn batch_response(batch: &BatchResult, position: &str) -> Response {
+    let mut body = batch_json(batch, position);
+    let failure = batch.statements.iter().find_map(|outcome| Some((outcome.line, outcome.result.as_ref().err()?)));
+    match failure {
+        None => Json(body).into_response(),
+        Some((at, e)) => {
+            let (status, message, hint) = describe_error(e);
+            let statement = match position {
+                "line" => format!("The statement on line {}", at),
+                _ => format!("Statement {}", at),
+            };
+            body["error"] = json!(format!("{} failed, so none were kept: {}", statement, message));
+            body["hint"] = json!(hint);
+            (status, Json(body)).into_response()
+        }
+    }
+}
+
+/// Whether a request's body is JSON
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.to_ascii_lowercase().starts_with("application/json"))
+}
+
+/// Refuse a request running statements that a page on any site could have
+/// sent: a browser posts forms and plain text to other origins without
+/// asking first, but not JSON or a request with an `MDBY-Query` header
+fn refuse_simple_request(headers: &HeaderMap) -> Option<Response> {
+    if is_json(headers) || headers.contains_key(QUERY_HEADER) {
+        return None;
+    }
+    Some(error(
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "Statements must be sent as JSON (Content-Type: application/json) or with an MDBY-Query header",
+        Some("Add -H 'MDBY-Query: true' to send a statement as plain text"),
+    ))
+}
+
+/// Query parameters of `GET /collections/{name}`
+#[derive(serde::Deserialize)]
+struct DocumentsQuery {
+    r#where: Option<String>,
+    order_by: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+async fn documents(
+    State(db): State<Shared>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    params: Result<Query<DocumentsQuery>, QueryRejection>,
+) -> Response {
+    let Some(representation) = Representation::accepted(&headers) else {
+        return not_acceptable();
+    };
+    let Query(params) = match params {
+        Ok(params) => params,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &e.body_text(), None),
+    };
+    if let Err(e) = validate_collection_name(&name) {
+        return error(StatusCode::BAD_REQUEST, &e.to_string(), None);
+    }
+
+    let Ok(mdql::Statement::Select(mut select)) = mdql::parse(&format!("SELECT * FROM {}", name)) else {
+        return error(StatusCode::INTERNAL_SERVER_ERROR, "Unexpected query", None);
+    };
+    if let Some(condition) = params.r#where.as_deref().filter(|condition| !condition.trim().is_empty()) {
+        match mdql::parse_expression(condition) {
+            Ok(condition) => select.where_clause = Some(condition),
+            Err(e) => return error(StatusCode::BAD_REQUEST, &format!("Invalid where: {}", e), None),
+        }
+    }
+    if let Some(order_by) = params.order_by.as_deref().filter(|order_by| !order_by.trim().is_empty()) {
+        match mdql::parse_order_by(order_by) {
+            Ok(order_by) => select.order_by = order_by,
+            Err(e) => return error(StatusCode::BAD_REQUEST, &format!("Invalid order_by: {}", e), None),
+        }
+    }
+    select.limit = params.limit;
+    select.offset = params.offset;
+
+    // As MDQL, so it runs and is logged like any other statement
+    let statement = mdql::Statement::Select(select).to_string();
+    let result = db
+        .run(move |db| {
+            async move {
+                // A replica's collections are in its commit, and the SELECT finds them there
+                if db.git.snapshot()?.is_none() && !db.store.exists(&name).await? {
+                    return Err(crate::Error::CollectionNotFound { name }.into());
+                }
+                db.execute(&statement).await
+            }
+            .boxed_local()
+        })
+        .await;
+    match result {
+        Ok(result) => representation.respond(result_json(&result)),
+        Err(e) => anyhow_error(e),
+    }
+}
+
+async fn document(State(db): State<Shared>, Path((name, id)): Path<(String, String)>, headers: HeaderMap) -> Response {
+    let Some(representation) = Representation::accepted(&headers) else {
+        return not_acceptable();
+    };
+    if let Err(e) = validate_collection_name(&name).and_then(|_| validate_document_id(&id)) {
+        return error(StatusCode::BAD_REQUEST, &e.to_string(), None);
+    }
+
+    match db.run(move |db| read_document(db, name, id).boxed_local()).await {
+        Ok((doc, version)) => versioned(representation.respond(document_json(&doc)), version),
+        Err(e) => anyhow_error(e),
+    }
+}
+
+async fn put_document(
+    State(db): State<Shared>,
+    Path((name, id)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let fields = match document_fields(&id, &body, true) {
+        Ok(fields) => fields,
+        Err(message) => return error(StatusCode::BAD_REQUEST, &message, None),
+    };
+    let statement = mdql::Statement::Insert(mdql::InsertStmt {
+        into: name.clone(),
+        columns: fields.columns,
+        values: vec![fields.values],
+        body: fields.body.map(mdql::InsertBody::Inline),
+        on_conflict: Some(mdql::OnConflict::Update),
+    });
+    write_document(db, name, id, &headers, statement, true).await
+}
+
+async fn patch_document(
+    State(db): State<Shared>,
+    Path((name, id)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let fields = match document_fields(&id, &body, false) {
+        Ok(fields) => fields,
+        Err(message) => return error(StatusCode::BAD_REQUEST, &message, None),
+    };
+    let set: Vec<mdql::SetClause> = fields
+        .columns
+        .into_iter()
+        .zip(fields.values)
+        .filter(|(column, _)| column != "id")
+        .map(|(column, value)| mdql::SetClause { column, value: mdql::Expr::Literal(value) })
+        .collect();
+    if set.is_empty() {
+        return error(StatusCode::BAD_REQUEST, "Expected a JSON object with fields to set", None);
+    }
+    let statement = mdql::Statement::Update(mdql::UpdateStmt {
+        collection: name.clone(),
+        set,
+        where_clause: Some(id_is(&id)),
+    });
+    write_document(db, name, id, &headers, statement, true).await
+}
+
+async fn delete_document(State(db): State<Shared>, Path((name, id)): Path<(String, String)>, headers: HeaderMap) -> Response {
+    if let Err(e) = validate_document_id(&id) {
+        return error(StatusCode::BAD_REQUEST, &e.to_string(), None);
+    }
+    let statement = mdql::Statement::Delete(mdql::DeleteStmt { from: name.clone(), where_clause: Some(id_is(&id)) });
+    write_document(db, name, id, &headers, statement, false).await
+}
+
+/// Run a statement writing document `name/id`, if `If-Match` allows, and
+/// respond with the document as written (`{"affected": n}` for a delete)
+async fn write_document(
+    db: Shared,
+    name: String,
+    id: String,
+    headers: &HeaderMap,
+    statement: mdql::Statement,
+    read_back: bool,
+) -> Response {
+    if let Err(e) = validate_collection_name(&name).and_then(|_| validate_document_id(&id)) {
+        return error(StatusCode::BAD_REQUEST, &e.to_string(), None);
+    }
+    let if_match = headers
+        .get(header::IF_MATCH)
+        .map(|value| value.to_str().unwrap_or_default().split(',').map(|tag| tag.trim().to_string()).collect::<Vec<_>>());
+
+    let written = db
+        .run(move |db| {
+            async move {
+                if let Some(tags) = if_match {
+                    let version = db.document_version(&name, &id)?;
+                    let matches = version.is_some_and(|version| {
+                        tags.iter().any(|tag| tag == "*" || tag.trim_matches('"') == version)
+                    });
+                    if !matches {
+                        return Err(crate::Error::DocumentChanged { collection: name, id }.into());
+                    }
+                }
+                let result = db.execute(&statement.to_string()).await?;
+                if matches!(result, QueryResult::Affected(0)) {
+                    return Err(crate::Error::DocumentNotFound { collection: name, id }.into());
+                }
+                match read_back {
+                    true => read_document(db, name, id).await.map(|(doc, version)| (Some(doc), version)),
+                    false => Ok((None, None)),
+                }
+            }
+            .boxed_local()
+        })
+        .await;
+    match written {
+        Ok((Some(doc), version)) => versioned(Json(document_json(&doc)).into_response(), version),
+        Ok((None, _)) => Json(json!({"affected": 1})).into_response(),
+        Err(e) => anyhow_error(e),
+    }
+}
+
+/// A document and its version
+async fn read_document(db: &mut Database, name: String, id: String) -> anyhow::Result<(Document, Option<String>)> {
+    // Through the executor, so the policy and read-only replicas apply
+    let statement = format!("SELECT * FROM {} WHERE id = '{}'", name, id);
+    let QueryResult::Documents(docs) = db.execute(&statement).await? else {
+        anyhow::bail!("Unexpected query result");
+    };
+    let Some(doc) = docs.into_iter().next() else {
+        return Err(crate::Error::DocumentNotFound { collection: name, id }.into());
+    };
+    let version = db.document_version(&name, &id)?;
+    Ok((doc, version))
+}
+
+/// `response` with `version` as its `ETag`
+fn versioned(mut response: Response, version: Option<String>) -> Response {
+    if let Some(value) = version.and_then(|version| HeaderValue::from_str(&format!("\"{}\"", version)).ok()) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+}
+
+/// `id = '{id}'`
+fn id_is(id: &str) -> mdql::Expr {
+    mdql::Expr::BinaryOp {
+        left: Box::new(mdql::Expr::Column(mdql::Column::Special(mdql::SpecialField::Id))),
+        op: mdql::BinaryOp::Eq,
+        right: Box::new(mdql::Expr::Literal(mdql::Literal::String(id.to_string()))),
+    }
+}
+
+/// A document written through the API, as INSERT columns and values
+struct Fields {
+    /// Field names, `id` first
+    columns: Vec<String>,
+    values: Vec<mdql::Literal>,
+    body: Option<String>,
+}
+
+/// The fields of the JSON object in a request body, or why it isn't one
+fn document_fields(id: &str, body: &str, allow_body: bool) -> Result<Fields, String> {
+    let json: serde_json::Value = serde_json::from_str(body).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let Some(object) = json.as_object() else {
+        return Err("Expected a JSON object of fields".to_string());
+    };
+
+    let mut columns = vec!["id".to_string()];
+    let mut values = vec![mdql::Literal::String(id.to_string())];
+    let mut text = None;
+    for (key, value) in object {
+        match key.as_str() {
+            "_body" if allow_body => match value.as_str() {
+                Some(body) => text = Some(body.to_string()),
+                None => return Err("Expected _body to be a string".to_string()),
+            },
+            "_body" => return Err("The body can only be written with PUT".to_string()),
+            "id" if value.as_str() == Some(id) => {}
+            "id" => return Err(format!("The document's id must be '{}'", id)),
+            _ if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') => {
+                return Err(format!("Invalid field name '{}'", key));
+            }
+            _ => match json_literal(value) {
+                Some(literal) => {
+                    columns.push(key.clone());
+                    values.push(literal);
+                }
+                None => return Err(format!("Field '{}' is an object, which MDQL can't write", key)),
+            },
+        }
+    }
+    Ok(Fields { columns, values, body: text })
+}
+
+/// A JSON value as an MDQL literal; `None` for objects
+fn json_literal(value: &serde_json::Value) -> Option<mdql::Literal> {
+    Some(match value {
+        serde_json::Value::Null => mdql::Literal::Null,
+        serde_json::Value::Bool(b) => mdql::Literal::Bool(*b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => mdql::Literal::Int(i),
+            None => mdql::Literal::Float(n.as_f64().unwrap_or(0.0)),
+        },
+        serde_json::Value::String(s) => mdql::Literal::String(s.clone()),
+        serde_json::Value::Array(items) => mdql::Literal::Array(items.iter().map(json_literal).collect::<Option<_>>()?),
+        serde_json::Value::Object(_) => return None,
+    })
+}
+
+async fn openapi(State(db): State<Shared>) -> Response {
+    match db.run(|db| crate::openapi::document(db).boxed_local()).await {
+        Ok(document) => Json(document).into_response(),
+        Err(e) => anyhow_error(e),
+    }
+}
+
+async fn view(State(db): State<Shared>, Path(name): Path<String>) -> Response {
+    if let Err(e) = validate_view_name(&name) {
+        return error(StatusCode::BAD_REQUEST, &e.to_string(), None);
+    }
+
+    let path = db.root.join("views").join(&name).join("index.html");
+    let Ok(html) = tokio::fs::read_to_string(&path).await else {
+        return anyhow_error(crate::Error::ViewNotFound { name }.into());
+    };
+    // The page holds what the view's query read, so the policy has to allow
+    // running it
+    match db.run(move |db| async move { crate::query::check_view(db, &name) }.boxed_local()).await {
+        Ok(()) => Html(html).into_response(),
+        Err(e) => anyhow_error(e),
+    }
+}
+
+/// How a result is sent, chosen by the request's `Accept` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Representation {
+    Json,
+    Csv,
+    Html,
+}
+
+impl Representation {
+    /// The representation of the most preferred media type in `Accept`
+    /// that results can be sent as (JSON without an `Accept`), or `None`
+    /// if none of them can
+    fn accepted(headers: &HeaderMap) -> Option<Self> {
+        let Some(accept) = headers.get(header::ACCEPT) else {
+            return Some(Self::Json);
+        };
+        let accept = accept.to_str().ok()?;
+        if accept.trim().is_empty() {
+            return Some(Self::Json);
+        }
+        // (quality, position, media type), best first; q=0 means "not this"
+        let mut types: Vec<(f32, usize, String)> = accept
+            .split(',')
+            .enumerate()
+            .filter_map(|(position, item)| {
+                let mut parts = item.split(';');
+                let media = parts.next()?.trim().to_ascii_lowercase();
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q=")?.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                Some((quality, position, media))
+            })
+            .filter(|(quality, ..)| *quality > 0.0)
+            .collect();
+        types.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+        types.into_iter().find_map(|(_, _, media)| match media.as_str() {
+            "application/json" | "application/*" | "*/*" => Some(Self::Json),
+            "text/csv" => Some(Self::Csv),
+            "text/html" | "text/*" => Some(Self::Html),
+            _ => None,
+        })
+    }
+
+    /// A result's JSON in this representation
+    fn respond(self, json: serde_json::Value) -> Response {
+        let mut response = match self {
+            Self::Json => Json(json).into_response(),
+            Self::Csv => match csv_table(&json) {
+                Ok(csv) => ([(header::CONTENT_TYPE, "text/csv; charset=utf-8")], csv).into_response(),
+                Err(e) => anyhow_error(e),
+            },
+            Self::Html => Html(html_table(&json)).into_response(),
+        };
+        response.headers_mut().insert(header::VARY, HeaderValue::from_static("accept"));
+        response
+    }
+}
+
+fn not_acceptable() -> Response {
+    error(
+        StatusCode::NOT_ACCEPTABLE,
+        "Results can only be sent as application/json, text/csv or text/html",
+        None,
+    )
+}
+
+/// A result's JSON as a table: its columns, in the order they first appear,
+/// and the cells of each row
+///
+/// Arrays are rows, other values one row; rows that are objects have a
+/// column per key, and others a single `value`.
+fn table(json: &serde_json::Value) -> (Vec<String>, Vec<Vec<String>>) {
+    let rows: Vec<&serde_json::Value> = match json {
+        serde_json::Value::Array(items) => items.iter().collect(),
+        other => vec![other],
+    };
+    let mut columns: Vec<String> = Vec::new();
+    for row in &rows {
+        let keys: Vec<&String> = match row {
+            serde_json::Value::Object(object) => object.keys().collect(),
+            _ => Vec::new(),
+        };
+        for key in keys {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+        if !row.is_object() && !columns.iter().any(|column| column == "value") {
+            columns.push("value".to_string());
+        }
+    }
+    let cells = rows
+        .iter()
+        .map(|row| {
+            columns
+                .iter()
+                .map(|column| match row {
+                    serde_json::Value::Object(object) => object.get(column).map(cell).unwrap_or_default(),
+                    other if column == "value" => cell(other),
+                    _ => String::new(),
+                })
+                .collect()
+        })
+        .collect();
+    (columns, cells)
+}
+
+/// A JSON value as table cell text: arrays of strings and numbers joined
+/// with `;` as `mdby export csv` writes them, objects as JSON
+fn cell(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Array(items) if items.iter().all(|item| !item.is_array() && !item.is_object()) => items
+            .iter()
+            .map(cell)
+            .collect::<Vec<_>>()
+            .join(&crate::import::CSV_ARRAY_SEPARATOR.to_string()),
+        other => other.to_string(),
+    }
+}
+
+fn csv_table(json: &serde_json::Value) -> anyhow::Result<String> {
+    let (columns, rows) = table(json);
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    if !columns.is_empty() {
+        writer.write_record(&columns)?;
+    }
+    for row in rows {
+        writer.write_record(&row)?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+fn html_table(json: &serde_json::Value) -> String {
+    let (columns, rows) = table(json);
+    let row = |cells: &[String], tag: &str| {
+        let cells: String = cells.iter().map(|cell| format!("<{tag}>{}</{tag}>", tera::escape_html(cell))).collect();
+        format!("<tr>{}</tr>\n", cells)
+    };
+    let body: String = rows.iter().map(|cells| row(cells, "td")).collect();
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"></head>\n<body>\n<table>\n<thead>\n{}</thead>\n<tbody>\n{}</tbody>\n</table>\n</body>\n</html>\n",
+        row(&columns, "th"),
+        body
+    )
+}
+
+/// A query result as JSON, as printed by `mdby --format json query`
+pub fn result_json(result: &QueryResult) -> serde_json::Value {
+    match result {
+        QueryResult::Documents(docs) => docs.iter().map(document_json).collect(),
+        QueryResult::Scalar(value) => json!(value),
+        QueryResult::Affected(count) => json!({"affected": count}),
+        QueryResult::CollectionCreated(name) => json!({"created": "collection", "name": name}),
+        QueryResult::ViewCreated(name) => json!({"created": "view", "name": name}),
+        QueryResult::Collections(names) | QueryResult::Views(names) => json!(names),
+        QueryResult::Definition(statement) => json!({"statement": statement}),
+        QueryResult::Description(description) => description_json(description),
+        QueryResult::Plan(plan) => json!(plan),
+        QueryResult::Batch(batch) => batch_json(batch, "line"),
+    }
+}
+
+/// Response header naming the kind of a `POST /query` result
+pub const RESULT_KIND: &str = "mdby-result";
+
+/// Request header letting `POST /query` take a plain-text statement, and
+/// `POST /batch` JSON sent without a JSON `Content-Type`
+///
+/// Browsers only send it to another origin after a preflight request the
+/// CORS settings answer, so web pages can't use it to run statements.
+pub const QUERY_HEADER: &str = "mdby-query";
+
+/// Name of the kind of a result, as sent in [`RESULT_KIND`]
+pub fn result_kind(result: &QueryResult) -> &'static str {
+    match result {
+        QueryResult::Documents(_) => "documents",
+        QueryResult::Scalar(_) => "scalar",
+        QueryResult::Affected(_) => "affected",
+        QueryResult::ViewCreated(_) => "view-created",
+        QueryResult::CollectionCreated(_) => "collection-created",
+        QueryResult::Collections(_) => "collections",
+        QueryResult::Views(_) => "views",
+        QueryResult::Definition(_) => "definition",
+        QueryResult::Description(_) => "description",
+        QueryResult::Plan(_) => "plan",
+        QueryResult::Batch(_) => "batch",
+    }
+}
+
+/// A batch's outcomes as JSON, each placed by `position` (its `line`)
+fn batch_json(batch: &BatchResult, position: &str) -> serde_json::Value {
+    let statements: Vec<serde_json::Value> = batch
+        .statements
+        .iter()
+        .map(|outcome| {
+            let mut json = json!({position: outcome.line, "statement": outcome.statement});
+            match &outcome.result {
+                Ok(result) => {
+                    json["kind"] = json!(result_kind(result));
+                    json["result"] = result_json(result);
+                }
+                Err(e) => json["error"] = json!(e.to_string()),
+            }
+            json
+        })
+        .collect();
+    json!({
+        "statements": statements,
+        "succeeded": batch.succeeded(),
+        "failed": batch.failed(),
+        "affected": batch.affected(),
+    })
+}
+
+/// A document as JSON: its ID, frontmatter fields, and body as `_body`
+pub fn document_json(doc: &Document) -> serde_json::Value {
+    let mut obj = serde_json::Map::new();
+    obj.insert("id".to_string(), json!(doc.id));
+    for (key, value) in &doc.fields {
+        obj.insert(key.clone(), serde_json::to_value(value).unwrap_or_default());
+    }
+    if !doc.body.is_empty() {
+        obj.insert("_body".to_string(), json!(doc.body));
+    }
+    serde_json::Value::Object(obj)
+}
+
+/// A DESCRIBE result as JSON
+pub fn description_json(description: &CollectionDescription) -> serde_json::Value {
+    let fields: Vec<serde_json::Value> = description
+        .fields
+        .iter()
+        .map(|f| {
+            json!({
+                "name": f.name,
+                "type": f.field_type,
+                "constraints": f.constraints,
+                "description": f.description,
+                "label": f.label,
+            })
+        })
+        .collect();
+    let meta = description.meta.as_ref().map(|m| {
+        let fields: serde_json::Map<String, serde_json::Value> = m
+            .fields
+            .iter()
+            .map(|(k, v)| (k.clone(), serde_json::to_value(v).unwrap_or_default()))
+            .collect();
+        json!({"file": m.file, "fields": fields, "body": m.body})
+    });
+    json!({
+        "name": description.name,
+        "documents": description.documents,
+        "description": description.description,
+        "meta": meta,
+        "fields": fields,
+    })
+}
+
+/// An error response, with a status from the kind of MDBY error
+fn anyhow_error(e: anyhow::Error) -> Response {
+    let (status, message, hint) = describe_error(&e);
+    error(status, &message, hint)
+}
+
+/// The status, message and hint of an error response
+fn describe_error(e: &anyhow::Error) -> (StatusCode, String, Option<&str>) {
+    let Some(mdby_err) = e.downcast_ref::<crate::Error>() else {
+        return (StatusCode::BAD_REQUEST, e.to_string(), None);
+    };
+    let status = match mdby_err {
+        crate::Error::CollectionNotFound { .. }
+        | crate::Error::DocumentNotFound { .. }
+        | crate::Error::ViewNotFound { .. } => StatusCode::NOT_FOUND,
+        crate::Error::StatementDenied { .. } | crate::Error::CollectionDenied { .. } => StatusCode::FORBIDDEN,
+        crate::Error::DocumentChanged { .. } => StatusCode::PRECONDITION_FAILED,
+        crate::Error::CollectionAlreadyExists { .. }
+        | crate::Error::DocumentAlreadyExists { .. }
+        | crate::Error::ViewAlreadyExists { .. } => StatusCode::CONFLICT,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    (status, mdby_err.to_string(), mdby_err.suggestion())
+}
+
+fn error(status: StatusCode, message: &str, hint: Option<&str>) -> Response {
+    (status, Json(json!({"error": message, "hint": hint}))).into_response()
+}
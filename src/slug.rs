@@ -0,0 +1,119 @@
+//! Slugs: IDs and names made from free text
+//!
+//! A slug is the text transliterated to ASCII (`Crème brûlée` becomes
+//! `creme-brulee`, `北京` becomes `bei-jing`), lowercased, with each run of
+//! anything but letters and digits turned into one separator. A slug longer
+//! than the maximum is cut at a word boundary. Derived document IDs and
+//! proposal branch names are all made here, so the same text always gives
+//! the same slug.
+//!
+//! [`unique`] resolves collisions the same way everywhere too: `-2`, `-3`,
+//! and so on, shortening the slug if the suffix wouldn't fit.
+
+/// Longest slug by default, in characters
+pub const DEFAULT_MAX_LENGTH: usize = 80;
+
+/// How slugs are made
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlugOptions {
+    /// Longest slug, in characters; `None` for no limit
+    pub max_length: Option<usize>,
+    /// Between words, and before a collision suffix
+    pub separator: char,
+}
+
+impl Default for SlugOptions {
+    fn default() -> Self {
+        Self { max_length: Some(DEFAULT_MAX_LENGTH), separator: '-' }
+    }
+}
+
+impl SlugOptions {
+    /// Limit slugs to `max_length` characters, or not at all with `None`
+    pub fn with_max_length(mut self, max_length: Option<usize>) -> Self {
+        self.max_length = max_length;
+        self
+    }
+}
+
+/// Slug of `text` with the default options
+pub fn slugify(text: &str) -> String {
+    slugify_with(text, &SlugOptions::default())
+}
+
+/// Slug of `text`; empty if it has no letters or digits
+pub fn slugify_with(text: &str, options: &SlugOptions) -> String {
+    let ascii = unidecode::unidecode(text);
+    let mut slug = String::new();
+    for word in ascii.split(|c: char| !c.is_ascii_alphanumeric()).filter(|word| !word.is_empty()) {
+        let separator = if slug.is_empty() { 0 } else { options.separator.len_utf8() };
+        if let Some(max_length) = options.max_length {
+            if slug.len() + separator + word.len() > max_length {
+                // A single word longer than the limit is cut instead
+                if slug.is_empty() {
+                    slug.push_str(&word[..max_length].to_ascii_lowercase());
+                }
+                break;
+            }
+        }
+        if separator > 0 {
+            slug.push(options.separator);
+        }
+        slug.push_str(&word.to_ascii_lowercase());
+    }
+    slug
+}
+
+/// `base` if it isn't taken, or else the first of `base-2`, `base-3`, ...
+/// that isn't, shortening `base` to keep within the maximum length
+pub fn unique(base: &str, options: &SlugOptions, taken: impl Fn(&str) -> bool) -> String {
+    if !taken(base) {
+        return base.to_string();
+    }
+    (2..)
+        .map(|n| {
+            let suffix = format!("{}{}", options.separator, n);
+            let mut keep = options
+                .max_length
+                .map_or(base.len(), |max_length| max_length.saturating_sub(suffix.len()).min(base.len()));
+            while !base.is_char_boundary(keep) {
+                keep -= 1;
+            }
+            format!("{}{}", base[..keep].trim_end_matches(options.separator), suffix)
+        })
+        .find(|candidate| !taken(candidate))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  Rust 2024 -- notes "), "rust-2024-notes");
+        assert_eq!(slugify("Crème brûlée"), "creme-brulee");
+        assert_eq!(slugify("北京"), "bei-jing");
+        assert_eq!(slugify("???"), "");
+    }
+
+    #[test]
+    fn test_max_length_cuts_at_words() {
+        let options = SlugOptions::default().with_max_length(Some(12));
+        assert_eq!(slugify_with("Fix typo in the README", &options), "fix-typo-in");
+        assert_eq!(slugify_with("Supercalifragilistic", &options), "supercalifra");
+        let options = SlugOptions { max_length: None, separator: '_' };
+        assert_eq!(slugify_with(&"word ".repeat(30), &options).len(), 149);
+    }
+
+    #[test]
+    fn test_unique() {
+        let taken = ["notes", "notes-2", "abcdefgh"];
+        let options = SlugOptions::default();
+        assert_eq!(unique("ideas", &options, |s| taken.contains(&s)), "ideas");
+        assert_eq!(unique("notes", &options, |s| taken.contains(&s)), "notes-3");
+        let options = options.with_max_length(Some(8));
+        assert_eq!(unique("abcdefgh", &options, |s| taken.contains(&s)), "abcdef-2");
+    }
+}
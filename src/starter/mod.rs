@@ -0,0 +1,170 @@
+//! Starter templates for `mdby init --template`
+//!
+//! Each starter is an MDQL script (collections, example documents and views)
+//! plus any view templates it references. Applying a starter runs the script
+//! against a fresh database, writes the templates to `/.mdby/templates/`,
+//! records the starter in `/.mdby/config.yaml` and renders the views.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::config::Config;
+use crate::storage::collection::Collection;
+use crate::views::TemplateEngine;
+use crate::Database;
+
+/// A bundled starter database layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Starter {
+    /// Task list with priorities and an open-todos view
+    Todo,
+    /// Blog posts with a published-posts view
+    Blog,
+    /// Companies, contacts and deals
+    Crm,
+    /// Linked atomic notes
+    Zettelkasten,
+}
+
+impl Starter {
+    /// All available starters
+    pub const ALL: [Starter; 4] = [Starter::Todo, Starter::Blog, Starter::Crm, Starter::Zettelkasten];
+
+    /// Name used on the command line and in the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Starter::Todo => "todo",
+            Starter::Blog => "blog",
+            Starter::Crm => "crm",
+            Starter::Zettelkasten => "zettelkasten",
+        }
+    }
+
+    /// One-line description written to the config file
+    pub fn description(&self) -> &'static str {
+        match self {
+            Starter::Todo => "Task list with priorities, due dates and tags",
+            Starter::Blog => "Blog posts with authors, publish dates and tags",
+            Starter::Crm => "Companies, contacts and deals",
+            Starter::Zettelkasten => "Small atomic notes linked by ID",
+        }
+    }
+
+    /// A query to suggest once the starter has been applied
+    pub fn example_query(&self) -> &'static str {
+        match self {
+            Starter::Todo => "SELECT title, priority, due FROM todos WHERE done = false ORDER BY due",
+            Starter::Blog => "SELECT title, date FROM posts WHERE published = true ORDER BY date DESC",
+            Starter::Crm => "SELECT title, value, stage FROM deals ORDER BY value DESC",
+            Starter::Zettelkasten => "SELECT title, links FROM notes ORDER BY title",
+        }
+    }
+
+    /// MDQL script that creates the collections, example documents and views
+    pub fn script(&self) -> &'static str {
+        match self {
+            Starter::Todo => include_str!("todo.mdql"),
+            Starter::Blog => include_str!("blog.mdql"),
+            Starter::Crm => include_str!("crm.mdql"),
+            Starter::Zettelkasten => include_str!("zettelkasten.mdql"),
+        }
+    }
+
+    /// View templates referenced by the script, as (file name, content)
+    pub fn templates(&self) -> Vec<(&'static str, &'static str)> {
+        match self {
+            Starter::Todo => vec![("todo-list.html", TemplateEngine::todo_list_template())],
+            Starter::Blog => vec![("blog.html", include_str!("blog.html"))],
+            Starter::Crm | Starter::Zettelkasten => vec![],
+        }
+    }
+}
+
+impl fmt::Display for Starter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Starter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|starter| starter.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(|s| s.name()).collect();
+                anyhow::anyhow!("Unknown template '{}' (available: {})", s, names.join(", "))
+            })
+    }
+}
+
+/// Apply a starter to a database
+///
+/// Fails without changing anything if a collection the starter creates
+/// already exists.
+pub async fn apply(db: &mut Database, starter: Starter) -> anyhow::Result<()> {
+    let statements = mdql::parse_multi(starter.script())?;
+
+    for stmt in &statements {
+        if let mdql::Statement::CreateCollection(create) = stmt {
+            if Collection::open(&create.name, &db.root).exists().await {
+                anyhow::bail!(
+                    "Collection '{}' already exists; the '{}' template needs an empty database",
+                    create.name,
+                    starter
+                );
+            }
+        }
+    }
+
+    let templates_dir = db.root.join(".mdby").join("templates");
+    tokio::fs::create_dir_all(&templates_dir).await?;
+    for (name, content) in starter.templates() {
+        let path = templates_dir.join(name);
+        if !path.exists() {
+            tokio::fs::write(&path, content).await?;
+        }
+    }
+
+    for stmt in statements {
+        db.execute_ast(stmt).await?;
+    }
+
+    let mut config = Config::load(&db.root)?;
+    config.template = Some(starter.name().to_string());
+    if config.description.is_none() {
+        config.description = Some(starter.description().to_string());
+    }
+    config.save(&db.root)?;
+    db.config = config;
+
+    db.regenerate_views().await?;
+
+    if db.git.has_changes()? {
+        db.git.commit(&format!("Apply '{}' starter template", starter))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_starter_scripts_parse() {
+        for starter in Starter::ALL {
+            let statements = mdql::parse_multi(starter.script())
+                .unwrap_or_else(|e| panic!("{} script failed to parse: {}", starter, e));
+            assert!(statements.iter().any(|s| matches!(s, mdql::Statement::CreateView(_))));
+        }
+    }
+
+    #[test]
+    fn test_starter_from_str() {
+        assert_eq!("Blog".parse::<Starter>().unwrap(), Starter::Blog);
+        assert!("wiki".parse::<Starter>().is_err());
+    }
+}
@@ -0,0 +1,494 @@
+//! Reading a database from a `.zip` or `.tar.gz` snapshot
+//!
+//! A backup or CI artifact of a database directory can be queried without
+//! extracting it: [`ArchiveStore`] reads the whole archive into memory once
+//! and serves its documents through the [`Store`] trait. Tar archives may be
+//! gzipped or not; zip entries may be stored or deflated. The format is told
+//! from the file's first bytes, not its name.
+//!
+//! The layout and rules are those of a working tree (see
+//! [`TreeReader`](super::tree::TreeReader)): documents are the `.md` files
+//! directly inside `collections/{name}/`, minus metadata files and anything
+//! matched by `.mdbyignore`, with compressed bodies put back. An archive made from outside the database
+//! directory (`tar czf backup.tgz notes/`) has its top-level directory
+//! skipped. Archives are read-only: every write fails.
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::collection::META_FILES;
+use super::compression;
+use super::document::Document;
+use super::ignore::{IgnoreRules, IGNORE_FILE};
+use super::store::{Layout, Store};
+
+/// Documents and settings of a database, read from an archive
+#[derive(Debug)]
+pub struct ArchiveStore {
+    path: PathBuf,
+    /// File contents by path relative to the database root
+    files: BTreeMap<String, Vec<u8>>,
+    /// Directories, including those holding no files
+    dirs: BTreeSet<String>,
+    /// The archive's modification time, used as every document's
+    modified: Option<SystemTime>,
+}
+
+/// A file (with its contents) or directory in an archive
+type Entry = (String, Option<Vec<u8>>);
+
+impl ArchiveStore {
+    /// Read the archive at `path`
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path).map_err(|e| anyhow::anyhow!("Cannot read archive {:?}: {}", path, e))?;
+        let entries = read_entries(&bytes).map_err(|e| anyhow::anyhow!("Cannot read archive {:?}: {}", path, e))?;
+        let mut store = Self::from_entries(entries);
+        store.path = path.to_path_buf();
+        store.modified = std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        Ok(store)
+    }
+
+    fn from_entries(entries: Vec<Entry>) -> Self {
+        let mut entries: Vec<Entry> = entries
+            .into_iter()
+            .filter_map(|(path, content)| {
+                let parts: Vec<&str> = path.split('/').filter(|part| !part.is_empty() && *part != ".").collect();
+                (!parts.is_empty()).then(|| (parts.join("/"), content))
+            })
+            .collect();
+
+        // Skip top-level directories until the database root is reached
+        let at_root = |entries: &[Entry]| {
+            entries.iter().any(|(path, _)| {
+                let first = path.split('/').next().unwrap_or_default();
+                first == "collections" || first == ".mdby"
+            })
+        };
+        while !at_root(&entries) {
+            let Some(top) = entries.iter().find(|(_, content)| content.is_some()).and_then(|(path, _)| path.split_once('/')) else {
+                break;
+            };
+            let prefix = format!("{}/", top.0);
+            if !entries.iter().all(|(path, _)| path.starts_with(&prefix) || prefix == format!("{}/", path)) {
+                break;
+            }
+            entries = entries
+                .into_iter()
+                .filter_map(|(path, content)| path.strip_prefix(&prefix).map(|path| (path.to_string(), content)))
+                .collect();
+        }
+
+        let mut files = BTreeMap::new();
+        let mut dirs = BTreeSet::new();
+        for (path, content) in entries {
+            let mut dir = path.as_str();
+            while let Some((parent, _)) = dir.rsplit_once('/') {
+                dirs.insert(parent.to_string());
+                dir = parent;
+            }
+            match content {
+                Some(content) => {
+                    files.insert(path, content);
+                }
+                None => {
+                    dirs.insert(path);
+                }
+            }
+        }
+        Self { path: PathBuf::new(), files, dirs, modified: None }
+    }
+
+    /// Contents of a file, relative to the database root
+    pub fn read_file(&self, path: &str) -> Option<String> {
+        self.files.get(path).map(|content| String::from_utf8_lossy(content).into_owned())
+    }
+
+    /// Names of the files directly in a directory, sorted
+    pub fn files(&self, dir: &str) -> Vec<String> {
+        let prefix = format!("{}/", dir);
+        self.files
+            .keys()
+            .filter_map(|path| path.strip_prefix(&prefix))
+            .filter(|name| !name.contains('/'))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Collection names, sorted
+    fn collection_names(&self) -> Vec<String> {
+        self.dirs
+            .iter()
+            .filter_map(|dir| dir.strip_prefix("collections/"))
+            .filter(|name| !name.contains('/'))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// IDs and file names of a collection's documents
+    fn documents(&self, collection: &str) -> anyhow::Result<Vec<(String, String)>> {
+        let dir = format!("collections/{}", collection);
+        let ignore_files: Vec<String> = [IGNORE_FILE.to_string(), format!("{}/{}", dir, IGNORE_FILE)]
+            .iter()
+            .filter_map(|path| self.read_file(path))
+            .collect();
+        let rules = IgnoreRules::from_files(&ignore_files)?;
+        Ok(self
+            .files(&dir)
+            .into_iter()
+            .filter(|file| !rules.is_ignored(file) && !META_FILES.contains(&file.as_str()))
+            .filter_map(|file| file.strip_suffix(".md").map(|id| (id.to_string(), file.clone())))
+            .collect())
+    }
+
+    /// Parse one document; `None` if it doesn't parse, as unparseable files
+    /// are skipped on disk too
+    fn parse(&self, collection: &str, id: &str, file: &str) -> Option<Document> {
+        let path = format!("collections/{}/{}", collection, file);
+        let mut doc = Document::parse(id, &self.read_file(&path)?).ok()?;
+        if let Some(body) = self.files.get(&compression::body_file(&path)) {
+            doc.body = compression::decompress(body).ok()?;
+        }
+        doc.path = PathBuf::from(file);
+        doc.meta.modified_at = self.modified;
+        Some(doc)
+    }
+
+    fn read_only(&self) -> anyhow::Error {
+        anyhow::anyhow!("Archive {:?} is read-only", self.path)
+    }
+}
+
+impl Store for ArchiveStore {
+    fn collections(&self) -> BoxFuture<'_, anyhow::Result<Vec<String>>> {
+        async move { Ok(self.collection_names()) }.boxed()
+    }
+
+    fn exists<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<bool>> {
+        async move { Ok(self.dirs.contains(&format!("collections/{}", collection))) }.boxed()
+    }
+
+    fn create_collection<'a>(&'a self, _collection: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move { Err(self.read_only()) }.boxed()
+    }
+
+    fn drop_collection<'a>(&'a self, _collection: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move { Err(self.read_only()) }.boxed()
+    }
+
+    fn ids<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<String>>> {
+        async move { Ok(self.documents(collection)?.into_iter().map(|(id, _)| id).collect()) }.boxed()
+    }
+
+    fn list<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<Document>>> {
+        async move {
+            Ok(self
+                .documents(collection)?
+                .iter()
+                .filter_map(|(id, file)| self.parse(collection, id, file))
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn get<'a>(&'a self, collection: &'a str, id: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Document>>> {
+        async move {
+            Ok(self
+                .documents(collection)?
+                .iter()
+                .find(|(found, _)| found == id)
+                .and_then(|(id, file)| self.parse(collection, id, file)))
+        }
+        .boxed()
+    }
+
+    fn put<'a>(&'a self, _collection: &'a str, _doc: &'a Document, _layout: &'a Layout) -> BoxFuture<'a, anyhow::Result<bool>> {
+        async move { Err(self.read_only()) }.boxed()
+    }
+
+    fn delete<'a>(&'a self, _collection: &'a str, _id: &'a str) -> BoxFuture<'a, anyhow::Result<bool>> {
+        async move { Err(self.read_only()) }.boxed()
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+}
+
+/// Every entry of a zip, tar or gzipped tar archive
+fn read_entries(bytes: &[u8]) -> anyhow::Result<Vec<Entry>> {
+    if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+        read_zip(bytes)
+    } else if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut tar = Vec::new();
+        GzDecoder::new(bytes).read_to_end(&mut tar)?;
+        read_tar(&tar)
+    } else if bytes.get(257..262) == Some(b"ustar".as_slice()) {
+        read_tar(bytes)
+    } else {
+        anyhow::bail!("not a zip, tar or gzipped tar archive")
+    }
+}
+
+const TAR_BLOCK: usize = 512;
+
+/// Entries of an uncompressed tar archive (ustar, with GNU and PAX long names)
+fn read_tar(bytes: &[u8]) -> anyhow::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut long_name: Option<String> = None;
+    let mut offset = 0;
+    while let Some(header) = bytes.get(offset..offset + TAR_BLOCK) {
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size = tar_number(&header[124..136])?;
+        let start = offset + TAR_BLOCK;
+        let data = bytes
+            .get(start..start + size)
+            .ok_or_else(|| anyhow::anyhow!("tar entry runs past the end of the archive"))?;
+        offset = start + size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+
+        let name = long_name.take().unwrap_or_else(|| {
+            let name = tar_string(&header[0..100]);
+            let prefix = tar_string(&header[345..500]);
+            match header.get(257..262) == Some(b"ustar".as_slice()) && !prefix.is_empty() {
+                true => format!("{}/{}", prefix, name),
+                false => name,
+            }
+        });
+        match header[156] {
+            b'0' | 0 | b'7' => entries.push((name, Some(data.to_vec()))),
+            b'5' => entries.push((name, None)),
+            // GNU long name of the next entry
+            b'L' => long_name = Some(tar_string(data)),
+            // PAX extended header; only the path matters here
+            b'x' => long_name = pax_path(data),
+            // Links, devices and global headers hold no documents
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+/// A NUL-terminated string field of a tar header
+fn tar_string(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// A number field of a tar header: octal text, or base-256 when its first
+/// byte has the high bit set
+fn tar_number(field: &[u8]) -> anyhow::Result<usize> {
+    if field.first().is_some_and(|&b| b & 0x80 != 0) {
+        let value = field[1..].iter().fold(0u64, |value, &b| (value << 8) | u64::from(b));
+        return Ok(usize::try_from(value)?);
+    }
+    let text = tar_string(field);
+    let text = text.trim_matches(|c: char| c == ' ' || c == '\0');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    usize::from_str_radix(text, 8).map_err(|_| anyhow::anyhow!("invalid tar header size '{}'", text))
+}
+
+/// The `path` record of a PAX extended header (`{length} path={value}\n`)
+fn pax_path(data: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .find_map(|line| line.split_once(' ').and_then(|(_, record)| record.strip_prefix("path=")).map(str::to_string))
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> anyhow::Result<usize> {
+    bytes
+        .get(at..at + 2)
+        .map(|b| usize::from(u16::from_le_bytes([b[0], b[1]])))
+        .ok_or_else(|| anyhow::anyhow!("zip archive is truncated"))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> anyhow::Result<usize> {
+    let value = bytes
+        .get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| anyhow::anyhow!("zip archive is truncated"))?;
+    if value == u32::MAX {
+        anyhow::bail!("zip64 archives are not supported");
+    }
+    Ok(value as usize)
+}
+
+/// Entries of a zip archive, found through its central directory
+fn read_zip(bytes: &[u8]) -> anyhow::Result<Vec<Entry>> {
+    // The end of central directory record is last, before a comment of up
+    // to 64 KiB
+    let end = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .take(65536 + 22)
+        .find(|&at| bytes[at..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(|| anyhow::anyhow!("zip archive has no central directory"))?;
+    let count = u16_at(bytes, end + 10)?;
+    let mut at = u32_at(bytes, end + 16)?;
+
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if !bytes.get(at..).is_some_and(|rest| rest.starts_with(b"PK\x01\x02")) {
+            anyhow::bail!("zip central directory is corrupt");
+        }
+        let method = u16_at(bytes, at + 10)?;
+        let compressed = u32_at(bytes, at + 20)?;
+        let name_len = u16_at(bytes, at + 28)?;
+        let extra_len = u16_at(bytes, at + 30)?;
+        let comment_len = u16_at(bytes, at + 32)?;
+        let local = u32_at(bytes, at + 42)?;
+        let name = bytes
+            .get(at + 46..at + 46 + name_len)
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .ok_or_else(|| anyhow::anyhow!("zip archive is truncated"))?;
+        at += 46 + name_len + extra_len + comment_len;
+
+        if name.ends_with('/') {
+            entries.push((name, None));
+            continue;
+        }
+        if !bytes.get(local..).is_some_and(|rest| rest.starts_with(b"PK\x03\x04")) {
+            anyhow::bail!("zip entry '{}' is corrupt", name);
+        }
+        let start = local + 30 + u16_at(bytes, local + 26)? + u16_at(bytes, local + 28)?;
+        let data = bytes
+            .get(start..start + compressed)
+            .ok_or_else(|| anyhow::anyhow!("zip entry '{}' runs past the end of the archive", name))?;
+        let content = match method {
+            0 => data.to_vec(),
+            8 => {
+                let mut content = Vec::new();
+                DeflateDecoder::new(data).read_to_end(&mut content)?;
+                content
+            }
+            _ => anyhow::bail!("zip entry '{}' uses unsupported compression method {}", name, method),
+        };
+        entries.push((name, Some(content)));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{DeflateEncoder, GzEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+
+    const FILES: &[(&str, &str)] = &[
+        ("notes/.mdby/schemas/todos.yaml", "name: todos\nfields: []\n"),
+        ("notes/collections/todos/a.md", "---\ntitle: A\n---\nFirst\n"),
+        ("notes/collections/todos/b.md", "---\ntitle: B\n---\n"),
+        ("notes/collections/todos/README.md", "About todos\n"),
+        ("notes/collections/todos/.mdbyignore", "b.md\n"),
+    ];
+
+    fn tar(files: &[(&str, &str)]) -> Vec<u8> {
+        let header = |tar: &mut Vec<u8>, name: &str, size: usize, kind: u8| {
+            let mut block = [0u8; TAR_BLOCK];
+            block[..name.len()].copy_from_slice(name.as_bytes());
+            block[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+            block[156] = kind;
+            block[257..263].copy_from_slice(b"ustar\0");
+            tar.extend_from_slice(&block);
+        };
+        let mut tar = Vec::new();
+        header(&mut tar, "notes/collections/empty/", 0, b'5');
+        for (name, content) in files {
+            header(&mut tar, name, content.len(), b'0');
+            tar.extend_from_slice(content.as_bytes());
+            tar.resize(tar.len().div_ceil(TAR_BLOCK) * TAR_BLOCK, 0);
+        }
+        tar.resize(tar.len() + 2 * TAR_BLOCK, 0);
+        tar
+    }
+
+    fn zip(files: &[(&str, &str)]) -> Vec<u8> {
+        let (mut zip, mut central) = (Vec::new(), Vec::new());
+        for (i, (name, content)) in files.iter().enumerate() {
+            // Alternate stored and deflated entries
+            let (method, data) = match i % 2 {
+                0 => (0u16, content.as_bytes().to_vec()),
+                _ => {
+                    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                    encoder.write_all(content.as_bytes()).unwrap();
+                    (8, encoder.finish().unwrap())
+                }
+            };
+            let offset = zip.len() as u32;
+            zip.extend_from_slice(b"PK\x03\x04");
+            zip.extend_from_slice(&[0; 4]);
+            zip.extend_from_slice(&method.to_le_bytes());
+            zip.extend_from_slice(&[0; 8]);
+            zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            zip.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            zip.extend_from_slice(&[0; 2]);
+            zip.extend_from_slice(name.as_bytes());
+            zip.extend_from_slice(&data);
+
+            central.extend_from_slice(b"PK\x01\x02");
+            central.extend_from_slice(&[0; 6]);
+            central.extend_from_slice(&method.to_le_bytes());
+            central.extend_from_slice(&[0; 8]);
+            central.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            central.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            central.extend_from_slice(&[0; 12]);
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+        }
+        let start = zip.len() as u32;
+        zip.extend_from_slice(&central);
+        zip.extend_from_slice(b"PK\x05\x06");
+        zip.extend_from_slice(&[0; 4]);
+        zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&start.to_le_bytes());
+        zip.extend_from_slice(&[0; 2]);
+        zip
+    }
+
+    async fn check(store: &ArchiveStore) {
+        assert!(store.is_read_only());
+        assert!(store.read_file(".mdby/schemas/todos.yaml").is_some());
+        assert_eq!(store.files(".mdby/schemas"), ["todos.yaml"]);
+        assert_eq!(store.ids("todos").await.unwrap(), ["a"]);
+        let docs = store.list("todos").await.unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].body.trim(), "First");
+        assert!(store.get("todos", "b").await.unwrap().is_none());
+        assert!(store.put("todos", &docs[0], &Layout::default()).await.unwrap_err().to_string().contains("read-only"));
+    }
+
+    #[tokio::test]
+    async fn test_tar() {
+        let store = ArchiveStore::from_entries(read_entries(&tar(FILES)).unwrap());
+        check(&store).await;
+        assert_eq!(store.collections().await.unwrap(), ["empty", "todos"]);
+
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&tar(FILES)).unwrap();
+        check(&ArchiveStore::from_entries(read_entries(&gz.finish().unwrap()).unwrap())).await;
+    }
+
+    #[tokio::test]
+    async fn test_zip() {
+        let store = ArchiveStore::from_entries(read_entries(&zip(FILES)).unwrap());
+        check(&store).await;
+        assert_eq!(store.collections().await.unwrap(), ["todos"]);
+    }
+
+    #[test]
+    fn test_not_an_archive() {
+        let error = read_entries(b"---\ntitle: A\n---\n").unwrap_err();
+        assert!(error.to_string().contains("not a zip, tar or gzipped tar archive"));
+    }
+}
@@ -13,12 +13,48 @@
 //!     2024-01-15-meeting.md
 //!     2024-01-16-ideas.md
 //! ```
-
-use super::document::Document;
+//!
+//! Documents are written atomically: to a temporary `.{id}.md.tmp` beside
+//! the document, flushed to disk and renamed over it, so a crash leaves
+//! either the old file or the new one, never part of one.
+//!
+//! Files matching `.mdbyignore` patterns (see [`super::ignore`]) are not
+//! treated as documents. Neither is an optional `_meta.md` or `README.md`,
+//! which describes the collection itself (see [`Collection::meta`]).
+//!
+//! A collection opened [`Collection::with_snapshot`] reads documents as they
+//! were when the snapshot began (see [`super::snapshot`]).
+//!
+//! Bodies over the schema's `compress_bodies_over` limit are written to a
+//! gzipped `{id}.md.gz` beside the document and read back from it (see
+//! [`super::compression`]).
+
+use super::compression;
+use super::document::{Document, Fields};
+use super::ignore::IgnoreRules;
+use super::journal;
+use super::snapshot::Snapshot;
+use super::store::Layout;
+use futures_util::stream::{self, Stream, StreamExt};
 use std::path::{Path, PathBuf};
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
 use walkdir::WalkDir;
 
+/// Files that hold collection-level metadata, in order of precedence
+pub const META_FILES: &[&str] = &["_meta.md", "README.md"];
+
+/// Collection-level metadata from `_meta.md` or `README.md`
+#[derive(Debug, Clone)]
+pub struct CollectionMeta {
+    /// File the metadata was read from
+    pub file: String,
+    /// Frontmatter fields
+    pub fields: Fields,
+    /// Markdown body
+    pub body: String,
+}
+
 /// A collection of documents
 #[derive(Debug)]
 pub struct Collection {
@@ -26,6 +62,14 @@ pub struct Collection {
     pub name: String,
     /// Path to the collection directory
     pub path: PathBuf,
+    /// Fields written first in frontmatter (usually the schema's order)
+    pub field_order: Vec<String>,
+    /// Bodies longer than this many bytes are written compressed
+    pub compress_bodies_over: Option<usize>,
+    /// Database root, for the root `.mdbyignore`
+    root: PathBuf,
+    /// Reads are pinned to this, if set
+    snapshot: Option<Snapshot>,
 }
 
 impl Collection {
@@ -33,11 +77,34 @@ impl Collection {
     pub fn open(name: impl Into<String>, base_path: &Path) -> Self {
         let name = name.into();
         let path = base_path.join("collections").join(&name);
-        Self { name, path }
+        Self { name, path, field_order: Vec::new(), compress_bodies_over: None, root: base_path.to_path_buf(), snapshot: None }
+    }
+
+    /// Read documents as they were when `snapshot` began
+    pub fn with_snapshot(mut self, snapshot: Snapshot) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    /// Write these fields first in frontmatter, before the rest alphabetically
+    pub fn with_field_order(mut self, field_order: Vec<String>) -> Self {
+        self.field_order = field_order;
+        self
+    }
+
+    /// Write documents as `layout` asks: its fields first in frontmatter, and
+    /// bodies over its limit compressed
+    pub fn with_layout(mut self, layout: &Layout) -> Self {
+        self.field_order = layout.field_order.clone();
+        self.compress_bodies_over = layout.compress_bodies_over;
+        self
     }
 
     /// Create the collection directory if it doesn't exist
     pub async fn ensure_exists(&self) -> anyhow::Result<()> {
+        if !self.path.exists() {
+            journal::preserve(&self.root, &self.path)?;
+        }
         fs::create_dir_all(&self.path).await?;
         Ok(())
     }
@@ -49,49 +116,85 @@ impl Collection {
 
     /// List all documents in the collection
     pub async fn list(&self) -> anyhow::Result<Vec<Document>> {
-        let mut documents = Vec::new();
+        Ok(self.stream()?.collect().await)
+    }
+
+    /// The documents in the collection, each file read only when the stream
+    /// gets to it
+    ///
+    /// Like [`Collection::list`], files that don't parse are skipped.
+    pub fn stream(&self) -> anyhow::Result<impl Stream<Item = Document> + '_> {
+        let mut paths = self.document_paths()?;
+        // Documents removed since a pinned snapshot began are still in it
+        if let Some(snapshot) = self.snapshot.as_ref().filter(|snapshot| snapshot.is_pinned()) {
+            let rules = self.ignore_rules()?;
+            let removed: Vec<PathBuf> = snapshot
+                .files(&self.path)?
+                .into_iter()
+                .filter(|path| !path.exists() && is_document(&rules, path))
+                .collect();
+            paths.extend(removed);
+        }
+        Ok(stream::iter(paths).filter_map(move |path| async move { self.read_document(&path).await.ok().flatten() }))
+    }
+
+    /// Paths of the collection's document files, without reading them
+    pub fn document_paths(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
 
         if !self.path.exists() {
-            return Ok(documents);
+            return Ok(paths);
         }
 
+        let rules = self.ignore_rules()?;
         for entry in WalkDir::new(&self.path)
             .min_depth(1)
             .max_depth(1)
             .into_iter()
             .filter_map(|e| e.ok())
         {
-            let path = entry.path();
-            if path.extension().map(|e| e == "md").unwrap_or(false) {
-                if let Ok(doc) = self.read_document(path).await {
-                    documents.push(doc);
-                }
+            if is_document(&rules, entry.path()) {
+                paths.push(entry.path().to_path_buf());
             }
         }
 
-        Ok(documents)
+        Ok(paths)
     }
 
     /// Read a single document by ID
     pub async fn get(&self, id: &str) -> anyhow::Result<Option<Document>> {
-        let path = self.path.join(format!("{}.md", id));
-        if !path.exists() {
+        let file_name = format!("{}.md", id);
+        let path = self.path.join(&file_name);
+        // A pinned read may find a document removed since the snapshot began
+        let pinned = self.snapshot.as_ref().is_some_and(Snapshot::is_pinned);
+        if (!pinned && !path.exists()) || self.is_hidden(&file_name)? {
             return Ok(None);
         }
-        self.read_document(&path).await.map(Some)
+        self.read_document(&path).await
     }
 
     /// Insert a new document
     pub async fn insert(&self, doc: &Document) -> anyhow::Result<()> {
         self.ensure_exists().await?;
+        self.check_insertable(&doc.id)?;
+
         let path = self.path.join(format!("{}.md", doc.id));
+        self.write(&path, self.render(doc)?).await
+    }
 
-        if path.exists() {
-            anyhow::bail!("Document '{}' already exists in collection '{}'", doc.id, self.name);
+    /// Check that a new document could be inserted with this ID: no document
+    /// has it, and it isn't reserved or ignored
+    pub fn check_insertable(&self, id: &str) -> anyhow::Result<()> {
+        if self.path.join(format!("{}.md", id)).exists() {
+            anyhow::bail!("Document '{}' already exists in collection '{}'", id, self.name);
+        }
+        if self.is_hidden(&format!("{}.md", id))? {
+            anyhow::bail!(
+                "Document ID '{}' is reserved or matches an ignore pattern in collection '{}'",
+                id,
+                self.name
+            );
         }
-
-        let content = doc.render();
-        fs::write(&path, content).await?;
         Ok(())
     }
 
@@ -103,25 +206,46 @@ impl Collection {
             anyhow::bail!("Document '{}' not found in collection '{}'", doc.id, self.name);
         }
 
-        let content = doc.render();
-        fs::write(&path, content).await?;
-        Ok(())
+        self.write(&path, self.render(doc)?).await
+    }
+
+    /// Update an existing document unless its files already hold exactly
+    /// what would be written; returns whether it was written
+    pub async fn update_if_changed(&self, doc: &Document) -> anyhow::Result<bool> {
+        let path = self.path.join(format!("{}.md", doc.id));
+        let (content, body) = self.render(doc)?;
+        match fs::read_to_string(&path).await {
+            Ok(current) if current == content && fs::read(body_path(&path)).await.ok() == body => return Ok(false),
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                anyhow::bail!("Document '{}' not found in collection '{}'", doc.id, self.name);
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        self.write(&path, (content, body)).await?;
+        Ok(true)
     }
 
     /// Upsert a document (insert or update)
     pub async fn upsert(&self, doc: &Document) -> anyhow::Result<()> {
         self.ensure_exists().await?;
         let path = self.path.join(format!("{}.md", doc.id));
-        let content = doc.render();
-        fs::write(&path, content).await?;
-        Ok(())
+        self.write(&path, self.render(doc)?).await
     }
 
     /// Delete a document by ID
     pub async fn delete(&self, id: &str) -> anyhow::Result<bool> {
         let path = self.path.join(format!("{}.md", id));
         if path.exists() {
+            let body = body_path(&path);
+            if body.exists() {
+                journal::preserve(&self.root, &body)?;
+                fs::remove_file(&body).await?;
+            }
+            journal::preserve(&self.root, &path)?;
             fs::remove_file(&path).await?;
+            sync_dir(&self.path).await?;
             Ok(true)
         } else {
             Ok(false)
@@ -134,28 +258,158 @@ impl Collection {
         Ok(docs.len())
     }
 
+    /// Ignore patterns that apply to this collection
+    pub fn ignore_rules(&self) -> anyhow::Result<IgnoreRules> {
+        IgnoreRules::load(&self.root, &self.path)
+    }
+
+    /// Whether a file in the collection directory is not a document
+    fn is_hidden(&self, file_name: &str) -> anyhow::Result<bool> {
+        Ok(META_FILES.contains(&file_name) || self.ignore_rules()?.is_ignored(file_name))
+    }
+
+    /// Read the collection's `_meta.md` (or, failing that, `README.md`)
+    pub async fn meta(&self) -> anyhow::Result<Option<CollectionMeta>> {
+        for file in META_FILES {
+            let path = self.path.join(file);
+            if path.is_file() {
+                let content = fs::read_to_string(&path).await?;
+                let (fields, body) = super::frontmatter::parse(&content)?;
+                return Ok(Some(CollectionMeta {
+                    file: file.to_string(),
+                    fields,
+                    body,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// A document's file contents, and its body compressed if it is over the
+    /// collection's limit, in which case the file's body is left empty
+    fn render(&self, doc: &Document) -> anyhow::Result<(String, Option<Vec<u8>>)> {
+        if !compression::should_compress(&doc.body, self.compress_bodies_over) {
+            return Ok((doc.render_ordered(&self.field_order), None));
+        }
+        let mut frontmatter = Document::new(doc.id.clone());
+        frontmatter.fields = doc.fields.clone();
+        Ok((frontmatter.render_ordered(&self.field_order), Some(compression::compress(&doc.body)?)))
+    }
+
+    /// Write what [`Collection::render`] gave for the document at `path`,
+    /// removing a compressed body it no longer has
+    async fn write(&self, path: &Path, (content, body): (String, Option<Vec<u8>>)) -> anyhow::Result<()> {
+        let body_path = body_path(path);
+        match body {
+            Some(body) => {
+                journal::preserve(&self.root, &body_path)?;
+                write_atomic(&body_path, &body).await?;
+            }
+            None if body_path.exists() => {
+                journal::preserve(&self.root, &body_path)?;
+                fs::remove_file(&body_path).await?;
+            }
+            None => {}
+        }
+        journal::preserve(&self.root, path)?;
+        write_atomic(path, content.as_bytes()).await
+    }
+
     /// Read a document from a path
-    async fn read_document(&self, path: &Path) -> anyhow::Result<Document> {
+    ///
+    /// `None` for a file a snapshot doesn't have: one added since it began.
+    async fn read_document(&self, path: &Path) -> anyhow::Result<Option<Document>> {
         let id = path
             .file_stem()
             .and_then(|s| s.to_str())
             .ok_or_else(|| anyhow::anyhow!("Invalid document path"))?;
 
-        let content = fs::read_to_string(path).await?;
+        let snapshot = self.snapshot.as_ref().filter(|snapshot| snapshot.is_pinned() && snapshot.is_stale(path));
+        let content = match snapshot {
+            Some(snapshot) => match snapshot.read(path)? {
+                Some(content) => content,
+                None => return Ok(None),
+            },
+            None => fs::read_to_string(path).await?,
+        };
         let mut doc = Document::parse(id, &content)?;
 
+        // The body file is read from the same place as the document's
+        let body = match snapshot {
+            Some(snapshot) => snapshot.read_bytes(&body_path(path))?,
+            None => fs::read(body_path(path)).await.ok(),
+        };
+        if let Some(body) = body {
+            doc.body = compression::decompress(&body)?;
+        }
+
         // Set relative path within collection
         doc.path = path.strip_prefix(&self.path)?.to_path_buf();
 
-        // Set metadata
-        if let Ok(metadata) = path.metadata() {
-            doc.meta.modified_at = metadata.modified().ok();
+        // Set metadata; the file's modification time is only the document's own when read from it
+        if snapshot.is_none() {
+            if let Ok(metadata) = path.metadata() {
+                doc.meta.modified_at = metadata.modified().ok();
+            }
         }
 
-        Ok(doc)
+        Ok(Some(doc))
     }
 }
 
+/// Replace a file's contents without ever leaving it half-written
+///
+/// The content goes to a temporary file in the same directory, which is
+/// flushed to disk and renamed over `path`. The directory is flushed too, so
+/// the rename survives a crash.
+async fn write_atomic(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        anyhow::bail!("Invalid document path {:?}", path);
+    };
+    let temporary = dir.join(format!(".{}.tmp", name.to_string_lossy()));
+
+    let written = async {
+        let mut file = fs::File::create(&temporary).await?;
+        file.write_all(content).await?;
+        file.sync_all().await?;
+        drop(file);
+        fs::rename(&temporary, path).await
+    }
+    .await;
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temporary).await;
+        return Err(e.into());
+    }
+    sync_dir(dir).await
+}
+
+/// Whether a file in a collection directory is a document: a `.md` file
+/// that isn't ignored or the collection's metadata
+fn is_document(rules: &IgnoreRules, path: &Path) -> bool {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    !rules.is_ignored(&file_name)
+        && !META_FILES.contains(&file_name.as_ref())
+        && path.extension().map(|e| e == "md").unwrap_or(false)
+}
+
+/// The compressed body file beside a document's file
+fn body_path(path: &Path) -> PathBuf {
+    let file = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(compression::body_file(&file))
+}
+
+/// Flush a directory's entries to disk, after files in it are renamed or
+/// removed
+async fn sync_dir(dir: &Path) -> anyhow::Result<()> {
+    // Directories can't be opened as files on Windows, where renames are
+    // durable without this
+    #[cfg(unix)]
+    fs::File::open(dir).await?.sync_all().await?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -197,4 +451,59 @@ mod tests {
         let gone = collection.get("task-1").await.unwrap();
         assert!(gone.is_none());
     }
+
+    #[tokio::test]
+    async fn test_snapshot_reads() {
+        let tmp = TempDir::new().unwrap();
+        let repo = crate::git::Repository::open_or_init(tmp.path()).unwrap();
+        let collection = Collection::open("todos", tmp.path());
+        for id in ["a", "b"] {
+            let mut doc = Document::new(id);
+            doc.set("title", "Before");
+            collection.insert(&doc).await.unwrap();
+        }
+        repo.commit("Add todos").unwrap();
+
+        let pinned = Collection::open("todos", tmp.path()).with_snapshot(Snapshot::begin(&repo, tmp.path()));
+        // Past the file system clock's granularity, so the writes below are stamped after the start
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let mut doc = Document::new("a");
+        doc.set("title", "After");
+        collection.update(&doc).await.unwrap();
+        collection.delete("b").await.unwrap();
+        collection.insert(&Document::new("c")).await.unwrap();
+
+        let mut docs = pinned.list().await.unwrap();
+        docs.sort_by(|x, y| x.id.cmp(&y.id));
+        let ids: Vec<&str> = docs.iter().map(|doc| doc.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+        assert_eq!(docs[0].get("title").and_then(|v| v.as_str()), Some("Before"));
+        assert_eq!(docs[1].get("title").and_then(|v| v.as_str()), Some("Before"));
+        assert!(pinned.get("b").await.unwrap().is_some());
+        assert!(pinned.get("c").await.unwrap().is_none());
+        assert_eq!(collection.list().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_writes_replace_files_whole() {
+        let tmp = TempDir::new().unwrap();
+        let collection = Collection::open("todos", tmp.path());
+        let mut doc = Document::new("task-1");
+        doc.set("title", "First");
+        collection.insert(&doc).await.unwrap();
+
+        // A temporary file left by a crash is neither a document nor in the way
+        std::fs::write(collection.path.join(".task-1.md.tmp"), "---\ntitle: Ha").unwrap();
+        assert_eq!(collection.list().await.unwrap().len(), 1);
+        doc.set("title", "Second");
+        collection.upsert(&doc).await.unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&collection.path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(files, vec!["task-1.md"]);
+        let fetched = collection.get("task-1").await.unwrap().unwrap();
+        assert_eq!(fetched.get("title").unwrap().as_str(), Some("Second"));
+    }
 }
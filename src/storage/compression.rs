@@ -0,0 +1,58 @@
+//! Compressed document bodies (`{id}.md.gz`)
+//!
+//! A collection whose schema sets `compress_bodies_over` keeps bodies longer
+//! than that many bytes gzipped in `{id}.md.gz`, beside the document. The
+//! `.md` file stays plain markdown holding the frontmatter and an empty
+//! body, so fields can still be read and grepped. Wherever a document is
+//! read from (the working tree, a commit or an archive) its body is put back
+//! from the compressed file, so nothing above the storage layer sees the
+//! difference. Writing a body under the limit again removes the file.
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{Read, Write};
+
+/// Name of the file holding the compressed body of the document in `file`
+/// (`task-1.md` → `task-1.md.gz`)
+pub fn body_file(file: &str) -> String {
+    format!("{}.gz", file)
+}
+
+/// Whether a body is stored compressed under a collection's limit
+pub fn should_compress(body: &str, limit: Option<usize>) -> bool {
+    limit.is_some_and(|limit| body.len() > limit)
+}
+
+pub fn compress(body: &str) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body.as_bytes())?;
+    Ok(encoder.finish()?)
+}
+
+pub fn decompress(bytes: &[u8]) -> anyhow::Result<String> {
+    let mut body = String::new();
+    GzDecoder::new(bytes)
+        .read_to_string(&mut body)
+        .map_err(|e| anyhow::anyhow!("Invalid compressed body: {}", e))?;
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let body = "# Notes\n\n".to_string() + &"All work and no play. ".repeat(1000);
+        let compressed = compress(&body).unwrap();
+        assert!(compressed.len() < body.len() / 10);
+        assert_eq!(decompress(&compressed).unwrap(), body);
+        assert!(decompress(b"plain text").is_err());
+
+        assert!(should_compress(&body, Some(1024)));
+        assert!(!should_compress(&body, None));
+        assert!(!should_compress("short", Some(1024)));
+        assert_eq!(body_file("task-1.md"), "task-1.md.gz");
+    }
+}
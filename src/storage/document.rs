@@ -5,7 +5,7 @@
 //! contains the markdown content.
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 /// A document in the database
@@ -38,7 +38,7 @@ pub enum Value {
     Float(f64),
     String(String),
     Array(Vec<Value>),
-    Object(HashMap<String, Value>),
+    Object(BTreeMap<String, Value>),
 }
 
 impl Value {
@@ -112,13 +112,34 @@ impl From<bool> for Value {
 }
 
 /// A map of field names to values
-pub type Fields = HashMap<String, Value>;
+///
+/// Kept sorted so that anything serialized from it is deterministic.
+pub type Fields = BTreeMap<String, Value>;
+
+/// Field names in output order
+///
+/// Names listed in `order` (typically a schema's declaration order) come
+/// first, followed by the remaining fields alphabetically.
+pub fn ordered_keys<'a>(fields: &'a Fields, order: &[String]) -> Vec<&'a String> {
+    let mut keys: Vec<&String> = order
+        .iter()
+        .filter_map(|name| fields.get_key_value(name).map(|(k, _)| k))
+        .collect();
+    keys.extend(fields.keys().filter(|k| !order.contains(k)));
+    keys
+}
 
 /// Metadata about a document (not persisted in the file)
 #[derive(Debug, Clone, Default)]
 pub struct DocumentMeta {
     /// Git commit hash when last read
     pub git_hash: Option<String>,
+    /// Author of the last commit touching the file, when a query asked for `@author`
+    pub author: Option<String>,
+    /// Time of the first commit adding the file (RFC 3339), when a query asked for `@created`
+    pub created: Option<String>,
+    /// Time of the last commit touching the file (RFC 3339), when a query asked for `@modified`
+    pub modified: Option<String>,
     /// File modification time
     pub modified_at: Option<std::time::SystemTime>,
 }
@@ -158,6 +179,27 @@ impl Document {
         }
     }
 
+    /// Get a value by dotted path: a field, then keys of nested objects
+    /// (`metadata.author`)
+    ///
+    /// Field names may hold dots themselves, as the fields of joined rows do
+    /// (`t.metadata`); the longest field name the path starts with is used.
+    pub fn get_path(&self, path: &str) -> Option<Value> {
+        if let Some(value) = self.get_field(path) {
+            return Some(value);
+        }
+        let (name, keys) = path
+            .rmatch_indices('.')
+            .map(|(i, _)| (&path[..i], &path[i + 1..]))
+            .find(|(name, _)| self.fields.contains_key(*name))?;
+        keys.split('.')
+            .try_fold(self.fields.get(name)?, |value, key| match value {
+                Value::Object(map) => map.get(key),
+                _ => None,
+            })
+            .cloned()
+    }
+
     /// Set the body content
     pub fn with_body(mut self, body: impl Into<String>) -> Self {
         self.body = body.into();
@@ -182,6 +224,11 @@ impl Document {
     pub fn render(&self) -> String {
         super::frontmatter::render(&self.fields, &self.body)
     }
+
+    /// Render document back to markdown with `order` fields first
+    pub fn render_ordered(&self, order: &[String]) -> String {
+        super::frontmatter::render_ordered(&self.fields, &self.body, order)
+    }
 }
 
 #[cfg(test)]
@@ -199,6 +246,17 @@ mod tests {
         assert_eq!(doc.get("title"), Some(&Value::String("Hello World".into())));
     }
 
+    #[test]
+    fn test_get_path() {
+        let doc = Document::parse("note", "---\nmeta:\n  author:\n    name: Marie\nt.meta:\n  year: 1903\n---\n").unwrap();
+        assert_eq!(doc.get_path("meta.author.name"), Some(Value::String("Marie".into())));
+        assert!(matches!(doc.get_path("meta.author"), Some(Value::Object(_))));
+        assert_eq!(doc.get_path("t.meta.year"), Some(Value::Int(1903)));
+        assert_eq!(doc.get_path("meta.author.name.first"), None);
+        assert_eq!(doc.get_path("meta.missing"), None);
+        assert_eq!(doc.get_path("id"), Some(Value::String("note".into())));
+    }
+
     #[test]
     fn test_roundtrip() {
         let mut doc = Document::new("test");
@@ -13,7 +13,7 @@
 //! ```
 
 use super::document::{Fields, Value};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 /// Parse YAML frontmatter from markdown content
 pub fn parse(content: &str) -> anyhow::Result<(Fields, String)> {
@@ -27,8 +27,7 @@ pub fn parse(content: &str) -> anyhow::Result<(Fields, String)> {
 
     // Find the closing delimiter
     let rest = &content[3..];
-    let end_pos = rest
-        .find("\n---")
+    let end_pos = find_closing_delimiter(rest)
         .ok_or_else(|| anyhow::anyhow!("Unclosed frontmatter: missing closing ---"))?;
 
     let yaml_content = &rest[..end_pos].trim();
@@ -42,6 +41,17 @@ pub fn parse(content: &str) -> anyhow::Result<(Fields, String)> {
     Ok((fields, body))
 }
 
+/// Find the `\n---` that closes the frontmatter
+///
+/// The delimiter must sit on a line of its own, so values such as
+/// `--- draft` in a block scalar don't end the frontmatter early.
+fn find_closing_delimiter(rest: &str) -> Option<usize> {
+    rest.match_indices("\n---").map(|(pos, _)| pos).find(|&pos| {
+        let after = &rest[pos + 4..];
+        after.is_empty() || after.starts_with('\n') || after.starts_with("\r\n")
+    })
+}
+
 /// Convert serde_yaml::Value to our Fields type
 fn yaml_to_fields(value: serde_yaml::Value) -> anyhow::Result<Fields> {
     match value {
@@ -62,7 +72,7 @@ fn yaml_to_fields(value: serde_yaml::Value) -> anyhow::Result<Fields> {
 }
 
 /// Convert a serde_yaml::Value to our Value type
-fn yaml_value_to_value(v: serde_yaml::Value) -> Value {
+pub(crate) fn yaml_value_to_value(v: serde_yaml::Value) -> Value {
     match v {
         serde_yaml::Value::Null => Value::Null,
         serde_yaml::Value::Bool(b) => Value::Bool(b),
@@ -80,7 +90,7 @@ fn yaml_value_to_value(v: serde_yaml::Value) -> Value {
             Value::Array(seq.into_iter().map(yaml_value_to_value).collect())
         }
         serde_yaml::Value::Mapping(map) => {
-            let obj: HashMap<String, Value> = map
+            let obj: BTreeMap<String, Value> = map
                 .into_iter()
                 .filter_map(|(k, v)| {
                     k.as_str().map(|key| (key.to_string(), yaml_value_to_value(v)))
@@ -114,15 +124,26 @@ fn value_to_yaml(v: &Value) -> serde_yaml::Value {
 }
 
 /// Render fields and body back to markdown with frontmatter
+///
+/// Fields are written alphabetically.
 pub fn render(fields: &Fields, body: &str) -> String {
+    render_ordered(fields, body, &[])
+}
+
+/// Render with the fields named in `order` first, then the rest alphabetically
+pub fn render_ordered(fields: &Fields, body: &str, order: &[String]) -> String {
     if fields.is_empty() {
+        // A body that itself starts with `---` would be read back as frontmatter
+        if body.trim_start().starts_with("---") {
+            return format!("---\n---\n\n{}", body);
+        }
         return body.to_string();
     }
 
     // Convert fields to YAML mapping
-    let yaml_map: serde_yaml::Mapping = fields
-        .iter()
-        .map(|(k, v)| (serde_yaml::Value::String(k.clone()), value_to_yaml(v)))
+    let yaml_map: serde_yaml::Mapping = super::document::ordered_keys(fields, order)
+        .into_iter()
+        .map(|k| (serde_yaml::Value::String(k.clone()), value_to_yaml(&fields[k])))
         .collect();
 
     let yaml_str = serde_yaml::to_string(&serde_yaml::Value::Mapping(yaml_map))
@@ -183,4 +204,54 @@ Some content here.
         assert_eq!(parsed_fields.get("priority"), fields.get("priority"));
         assert!(parsed_body.contains("# Content"));
     }
+
+    #[test]
+    fn test_roundtrip_dash_values() {
+        let tricky = [
+            "---",
+            "--- not a delimiter",
+            "- looks like a list",
+            "-5",
+            "line one\n---\nline three",
+            "key: value",
+            "true",
+            "caf\u{e9} \u{1F600}",
+            "",
+        ];
+
+        for value in tricky {
+            let mut fields = Fields::new();
+            fields.insert("title".into(), Value::String(value.into()));
+            fields.insert("after".into(), Value::Int(1));
+
+            let rendered = render(&fields, "Body");
+            let (parsed_fields, parsed_body) = parse(&rendered).unwrap();
+            assert_eq!(parsed_fields.get("title"), Some(&Value::String(value.into())), "value {:?}", value);
+            assert_eq!(parsed_fields.get("after"), Some(&Value::Int(1)));
+            assert_eq!(parsed_body, "Body");
+        }
+    }
+
+    #[test]
+    fn test_render_field_order() {
+        let mut fields = Fields::new();
+        fields.insert("zeta".into(), Value::Int(1));
+        fields.insert("title".into(), Value::String("Test".into()));
+        fields.insert("alpha".into(), Value::Int(2));
+
+        assert_eq!(render(&fields, ""), "---\nalpha: 2\ntitle: Test\nzeta: 1\n---\n\n");
+        assert_eq!(
+            render_ordered(&fields, "", &["title".into(), "missing".into()]),
+            "---\ntitle: Test\nalpha: 2\nzeta: 1\n---\n\n"
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_body_starting_with_delimiter() {
+        let body = "---\nnot: frontmatter\n---\n\nText";
+        let rendered = render(&Fields::new(), body);
+        let (parsed_fields, parsed_body) = parse(&rendered).unwrap();
+        assert!(parsed_fields.is_empty());
+        assert_eq!(parsed_body, body);
+    }
 }
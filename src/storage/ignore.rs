@@ -0,0 +1,121 @@
+//! Ignore patterns for collection scanning
+//!
+//! Files matching these patterns are skipped when a collection is scanned,
+//! so editor temp files and drafts aren't read as documents. (Collection
+//! metadata files are skipped separately, see [`super::collection::META_FILES`].)
+//!
+//! Patterns come from three places:
+//! - Built-in defaults ([`DEFAULT_PATTERNS`])
+//! - `/.mdbyignore` at the database root (all collections)
+//! - `/collections/{name}/.mdbyignore` (that collection only)
+//!
+//! Each line is a glob matched against the file name. Blank lines and lines
+//! starting with `#` are skipped (write `\#` for a literal leading `#`). A
+//! leading `!` re-includes matching files, overriding every ignore pattern.
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::path::Path;
+
+/// Name of the ignore file
+pub const IGNORE_FILE: &str = ".mdbyignore";
+
+/// Patterns that are always ignored unless re-included with `!`
+pub const DEFAULT_PATTERNS: &[&str] = &[
+    // Backup and lock files left by editors
+    "*~",
+    ".#*",
+    "\\#*#",
+    // Hidden files
+    ".*",
+];
+
+/// Compiled ignore patterns
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+    ignore: GlobSet,
+    include: GlobSet,
+}
+
+impl IgnoreRules {
+    /// Load the defaults plus the root and collection `.mdbyignore` files
+    pub fn load(root: &Path, collection_dir: &Path) -> anyhow::Result<Self> {
+        let mut files = Vec::new();
+        for dir in [root, collection_dir] {
+            let path = dir.join(IGNORE_FILE);
+            if path.is_file() {
+                files.push(std::fs::read_to_string(&path)?);
+            }
+        }
+
+        Self::from_files(&files)
+    }
+
+    /// The defaults plus the contents of `.mdbyignore` files, root first
+    pub fn from_files<S: AsRef<str>>(files: &[S]) -> anyhow::Result<Self> {
+        let mut lines: Vec<&str> = DEFAULT_PATTERNS.to_vec();
+        for content in files {
+            lines.extend(content.as_ref().lines());
+        }
+
+        Self::from_patterns(&lines)
+    }
+
+    /// Compile a list of pattern lines
+    pub fn from_patterns<S: AsRef<str>>(lines: &[S]) -> anyhow::Result<Self> {
+        let mut ignore = GlobSetBuilder::new();
+        let mut include = GlobSetBuilder::new();
+
+        for line in lines {
+            let line = line.as_ref().trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (builder, pattern) = match line.strip_prefix('!') {
+                Some(pattern) => (&mut include, pattern),
+                None => (&mut ignore, line),
+            };
+            let glob = Glob::new(pattern)
+                .map_err(|e| anyhow::anyhow!("Invalid pattern '{}' in {}: {}", line, IGNORE_FILE, e))?;
+            builder.add(glob);
+        }
+
+        Ok(Self {
+            ignore: ignore.build()?,
+            include: include.build()?,
+        })
+    }
+
+    /// Whether a file in the collection directory should be skipped
+    pub fn is_ignored(&self, file_name: &str) -> bool {
+        self.ignore.is_match(file_name) && !self.include.is_match(file_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_patterns() {
+        let rules = IgnoreRules::from_patterns(DEFAULT_PATTERNS).unwrap();
+        assert!(rules.is_ignored("task.md~"));
+        assert!(rules.is_ignored(".#task.md"));
+        assert!(rules.is_ignored("#task.md#"));
+        assert!(rules.is_ignored(".hidden.md"));
+        assert!(!rules.is_ignored("task-1.md"));
+    }
+
+    #[test]
+    fn test_comments_and_negation() {
+        let rules = IgnoreRules::from_patterns(&["# drafts", "", "draft-*.md", "!draft-keep.md"]).unwrap();
+        assert!(rules.is_ignored("draft-one.md"));
+        assert!(!rules.is_ignored("draft-keep.md"));
+        assert!(!rules.is_ignored("final.md"));
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert!(IgnoreRules::from_patterns(&["[unclosed"]).is_err());
+    }
+}
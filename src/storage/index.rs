@@ -0,0 +1,348 @@
+//! Secondary indexes for `INDEXED` fields
+//!
+//! Each indexed field has an index file at
+//! `/.mdby/indexes/{collection}/{field}.idx` listing the field's distinct
+//! values, each with the IDs of the documents holding it. The file also
+//! records the modification time and size of every document it indexed, so
+//! documents changed outside MDBY (by hand, or by a sync) are noticed and
+//! re-read before the index is used. Index files are derived data and ignored
+//! by git; deleting them only costs a rebuild.
+//!
+//! An edit that keeps a file's modification time and size slips past that
+//! check, as does a damaged index file. [`IndexManager::check`] compares an
+//! index with the documents themselves, and [`IndexManager::rebuild`] starts
+//! one over (`mdby reindex`).
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use super::collection::Collection;
+use super::document::{Document, Value};
+
+/// Reads and maintains the index files of a database
+pub struct IndexManager {
+    /// `/.mdby/indexes`
+    dir: PathBuf,
+}
+
+/// One distinct value of an indexed field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// The value; `None` for documents without the field
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+    pub document_ids: Vec<String>,
+}
+
+/// A way an index disagrees with the documents it indexes
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum IndexDrift {
+    #[error("Document '{0}' is missing from the index")]
+    Missing(String),
+    #[error("Document '{0}' is indexed under a value it no longer holds")]
+    Stale(String),
+    #[error("Document '{0}' is in the index but no longer exists")]
+    Orphaned(String),
+}
+
+/// How one field's index disagrees with its collection
+#[derive(Debug, Clone)]
+pub struct IndexReport {
+    pub collection: String,
+    pub field: String,
+    /// By document ID
+    pub drift: Vec<IndexDrift>,
+}
+
+/// Modification time (nanoseconds since the epoch) and size of a document file
+type Stamp = (u64, u64);
+
+/// Contents of an index file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FieldIndex {
+    entries: Vec<IndexEntry>,
+    /// Stamp of each document when it was indexed
+    documents: BTreeMap<String, Stamp>,
+}
+
+impl FieldIndex {
+    fn remove(&mut self, id: &str) {
+        self.documents.remove(id);
+        for entry in &mut self.entries {
+            entry.document_ids.retain(|other| other != id);
+        }
+        self.entries.retain(|entry| !entry.document_ids.is_empty());
+    }
+
+    fn insert(&mut self, doc: &Document, field: &str, stamp: Stamp) {
+        self.remove(&doc.id);
+        self.documents.insert(doc.id.clone(), stamp);
+        let value = doc.fields.get(field).cloned();
+        match self.entries.iter_mut().find(|entry| entry.value == value) {
+            Some(entry) => {
+                let at = entry.document_ids.partition_point(|other| other < &doc.id);
+                entry.document_ids.insert(at, doc.id.clone());
+            }
+            None => self.entries.push(IndexEntry { value, document_ids: vec![doc.id.clone()] }),
+        }
+    }
+}
+
+impl IndexManager {
+    pub fn new(root: &Path) -> Self {
+        Self { dir: root.join(".mdby").join("indexes") }
+    }
+
+    /// Path of a field's index file
+    pub fn path(&self, collection: &str, field: &str) -> PathBuf {
+        self.dir.join(collection).join(format!("{}.idx", field))
+    }
+
+    /// IDs of the documents whose value of `field` satisfies `matches`
+    ///
+    /// The index is built on first use and brought up to date with the
+    /// collection's files before it's read.
+    pub async fn lookup(
+        &self,
+        collection: &Collection,
+        field: &str,
+        matches: impl Fn(Option<&Value>) -> bool,
+    ) -> anyhow::Result<BTreeSet<String>> {
+        let index = self.refresh(collection, field).await?;
+        Ok(index
+            .entries
+            .iter()
+            .filter(|entry| matches(entry.value.as_ref()))
+            .flat_map(|entry| entry.document_ids.iter().cloned())
+            .collect())
+    }
+
+    /// Record written and deleted documents in the collection's indexes
+    ///
+    /// Indexes that haven't been built yet are left for their first lookup.
+    pub fn update(
+        &self,
+        collection: &Collection,
+        fields: &[String],
+        written: &[Document],
+        deleted: &[String],
+    ) -> anyhow::Result<()> {
+        for field in fields {
+            let path = self.path(&collection.name, field);
+            if !path.exists() {
+                continue;
+            }
+            let mut index = self.load(&path);
+            for id in deleted {
+                index.remove(id);
+            }
+            for doc in written {
+                match stamp(&collection.path.join(&doc.path)) {
+                    Some(stamp) => index.insert(doc, field, stamp),
+                    None => index.remove(&doc.id),
+                }
+            }
+            self.save(&path, &mut index)?;
+        }
+        Ok(())
+    }
+
+    /// Build a field's index from scratch, returning the number of documents
+    /// indexed
+    pub async fn rebuild(&self, collection: &Collection, field: &str) -> anyhow::Result<usize> {
+        let path = self.path(&collection.name, field);
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        Ok(self.refresh(collection, field).await?.documents.len())
+    }
+
+    /// Compare a field's index, as stored, with the collection's documents
+    ///
+    /// Returns `None` if the index hasn't been built yet.
+    pub async fn check(&self, collection: &Collection, field: &str) -> anyhow::Result<Option<Vec<IndexDrift>>> {
+        let path = self.path(&collection.name, field);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let index = self.load(&path);
+        let mut indexed: BTreeMap<&str, Option<&Value>> = BTreeMap::new();
+        for entry in &index.entries {
+            for id in &entry.document_ids {
+                indexed.insert(id, entry.value.as_ref());
+            }
+        }
+
+        let mut drift = Vec::new();
+        let mut docs = collection.list().await?;
+        docs.sort_by(|a, b| a.id.cmp(&b.id));
+        for doc in &docs {
+            match indexed.remove(doc.id.as_str()) {
+                None => drift.push(IndexDrift::Missing(doc.id.clone())),
+                Some(value) if value != doc.fields.get(field) => drift.push(IndexDrift::Stale(doc.id.clone())),
+                Some(_) => {}
+            }
+        }
+        drift.extend(indexed.into_keys().map(|id| IndexDrift::Orphaned(id.to_string())));
+        drift.sort_by(|a, b| drift_id(a).cmp(drift_id(b)));
+        Ok(Some(drift))
+    }
+
+    /// Remove every index of a collection
+    pub fn remove_collection(&self, collection: &str) -> anyhow::Result<()> {
+        let dir = self.dir.join(collection);
+        if dir.exists() {
+            std::fs::remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+
+    /// Load a field's index, re-reading documents added, changed or removed
+    /// since they were indexed
+    async fn refresh(&self, collection: &Collection, field: &str) -> anyhow::Result<FieldIndex> {
+        let path = self.path(&collection.name, field);
+        let mut index = self.load(&path);
+        let mut changed = false;
+
+        let mut on_disk = BTreeMap::new();
+        for file in collection.document_paths()? {
+            if let (Some(id), Some(stamp)) = (file.file_stem().and_then(|s| s.to_str()), stamp(&file)) {
+                on_disk.insert(id.to_string(), stamp);
+            }
+        }
+
+        let removed: Vec<String> = index.documents.keys().filter(|id| !on_disk.contains_key(*id)).cloned().collect();
+        for id in removed {
+            index.remove(&id);
+            changed = true;
+        }
+        for (id, stamp) in on_disk {
+            if index.documents.get(&id) == Some(&stamp) {
+                continue;
+            }
+            match collection.get(&id).await? {
+                Some(doc) => index.insert(&doc, field, stamp),
+                None => index.remove(&id),
+            }
+            changed = true;
+        }
+
+        if changed || !path.exists() {
+            self.save(&path, &mut index)?;
+        }
+        Ok(index)
+    }
+
+    /// A field's index, or an empty one if it's missing or unreadable
+    fn load(&self, path: &Path) -> FieldIndex {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, path: &Path, index: &mut FieldIndex) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let gitignore = self.dir.join(".gitignore");
+        if !gitignore.exists() {
+            std::fs::write(&gitignore, "*\n")?;
+        }
+
+        // Sorted by value, so rebuilding an index gives the same file
+        index.entries.sort_by_cached_key(|entry| serde_json::to_string(&entry.value).unwrap_or_default());
+        std::fs::write(path, serde_json::to_string(index)?)?;
+        Ok(())
+    }
+}
+
+fn drift_id(drift: &IndexDrift) -> &str {
+    match drift {
+        IndexDrift::Missing(id) | IndexDrift::Stale(id) | IndexDrift::Orphaned(id) => id,
+    }
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((modified.as_nanos() as u64, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn task(id: &str, status: &str) -> Document {
+        let mut doc = Document::new(id);
+        doc.set("status", status);
+        doc
+    }
+
+    async fn ids_with(manager: &IndexManager, collection: &Collection, status: &str) -> Vec<String> {
+        let status = Value::from(status);
+        let ids = manager.lookup(collection, "status", |value| value == Some(&status)).await.unwrap();
+        ids.into_iter().collect()
+    }
+
+    #[tokio::test]
+    async fn test_index_follows_writes_and_outside_edits() {
+        let tmp = TempDir::new().unwrap();
+        let collection = Collection::open("todos", tmp.path());
+        let manager = IndexManager::new(tmp.path());
+        for doc in [task("a", "open"), task("b", "done"), task("c", "open")] {
+            collection.insert(&doc).await.unwrap();
+        }
+
+        assert_eq!(ids_with(&manager, &collection, "open").await, vec!["a", "c"]);
+        assert!(manager.path("todos", "status").exists());
+
+        // Written through MDBY
+        let fields = vec!["status".to_string()];
+        let b = task("b", "open");
+        collection.update(&b).await.unwrap();
+        collection.delete("c").await.unwrap();
+        manager.update(&collection, &fields, &[b], &["c".to_string()]).unwrap();
+        assert_eq!(ids_with(&manager, &collection, "open").await, vec!["a", "b"]);
+
+        // Edited by hand, and a document without the field
+        std::fs::write(collection.path.join("a.md"), "---\nstatus: closed\n---\n").unwrap();
+        std::fs::write(collection.path.join("d.md"), "No frontmatter\n").unwrap();
+        assert_eq!(ids_with(&manager, &collection, "open").await, vec!["b"]);
+        let missing = manager.lookup(&collection, "status", |value| value.is_none()).await.unwrap();
+        assert_eq!(missing.into_iter().collect::<Vec<_>>(), vec!["d"]);
+    }
+
+    #[tokio::test]
+    async fn test_check_and_rebuild() {
+        let tmp = TempDir::new().unwrap();
+        let collection = Collection::open("todos", tmp.path());
+        let manager = IndexManager::new(tmp.path());
+        for doc in [task("a", "open"), task("b", "done"), task("c", "open")] {
+            collection.insert(&doc).await.unwrap();
+        }
+        assert_eq!(manager.check(&collection, "status").await.unwrap(), None);
+        assert_eq!(manager.rebuild(&collection, "status").await.unwrap(), 3);
+        assert_eq!(manager.check(&collection, "status").await.unwrap(), Some(vec![]));
+
+        // Changes the index hasn't seen: written without updating it
+        collection.update(&task("a", "done")).await.unwrap();
+        collection.delete("b").await.unwrap();
+        collection.insert(&task("d", "open")).await.unwrap();
+        let drift = manager.check(&collection, "status").await.unwrap().unwrap();
+        assert_eq!(
+            drift,
+            vec![
+                IndexDrift::Stale("a".into()),
+                IndexDrift::Orphaned("b".into()),
+                IndexDrift::Missing("d".into()),
+            ]
+        );
+
+        assert_eq!(manager.rebuild(&collection, "status").await.unwrap(), 3);
+        assert_eq!(manager.check(&collection, "status").await.unwrap(), Some(vec![]));
+    }
+}
@@ -0,0 +1,290 @@
+//! Write-ahead journal for statements (`/.mdby/journal/`)
+//!
+//! A statement writes its files and then commits them, so a crash in between
+//! would leave half a statement on disk. Before a statement that writes
+//! starts, [`begin`] records it in `pending.json` along with the commit HEAD
+//! is at. While that file exists, every file about to be written or removed
+//! is recorded first, with a copy of what it held ([`preserve`]).
+//!
+//! Opening the database finishes whatever a crash left behind ([`recover`]):
+//! if HEAD has moved, the statement's commit landed and the journal is
+//! cleared; otherwise every recorded file is put back as it was. A statement
+//! that fails before committing is rolled back the same way.
+//!
+//! A script run by [`Database::execute_script`](crate::Database::execute_script)
+//! is journaled as one statement, so its statements are undone together.
+//!
+//! Journal files are flushed to disk, and so are the directory entries
+//! naming them, before the write they guard happens, as
+//! [`Collection`](super::collection::Collection) does for documents; a crash
+//! can't leave a write on disk without its record.
+//!
+//! Documents, schemas, view definitions and ID counters are journaled.
+//! Derived files (indexes, caches, generated view output) aren't: they are
+//! refreshed on their next use. Like the rest of the database, the journal
+//! assumes one writer at a time.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// The statement being journaled
+const PENDING: &str = "pending.json";
+
+/// Copies of recorded files, at their paths relative to the database root
+const BACKUPS: &str = "files";
+
+/// Empty markers for recorded paths that didn't exist, laid out the same way
+const CREATED: &str = "created";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Pending {
+    statement: String,
+    /// HEAD when the statement started; `None` before the first commit
+    head: Option<String>,
+}
+
+/// What [`recover`] found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recovery {
+    /// The statement committed before the crash; its changes stay
+    Completed(String),
+    /// The statement didn't commit; its changes were undone
+    RolledBack(String),
+}
+
+/// `/.mdby/journal`
+pub fn journal_dir(root: &Path) -> PathBuf {
+    root.join(".mdby").join("journal")
+}
+
+/// Start journaling a statement
+pub fn begin(root: &Path, statement: &str, head: Option<String>) -> anyhow::Result<()> {
+    let dir = journal_dir(root);
+    clear(root)?;
+    create_dirs(&dir)?;
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        std::fs::write(&gitignore, "*\n")?;
+    }
+
+    // Through a temporary file, so a crash never leaves half of it
+    let pending = Pending { statement: statement.to_string(), head };
+    let temporary = dir.join(format!("{}.tmp", PENDING));
+    std::fs::write(&temporary, serde_json::to_string(&pending)?)?;
+    std::fs::File::open(&temporary)?.sync_all()?;
+    std::fs::rename(temporary, dir.join(PENDING))?;
+    sync_dir(&dir)
+}
+
+/// Record a file before it is written or removed, if a statement is being
+/// journaled
+///
+/// Only the first call for a path counts, so the journal keeps the file as
+/// it was before the statement. A path that doesn't exist yet is removed
+/// again on rollback, along with anything created under it.
+pub fn preserve(root: &Path, path: &Path) -> anyhow::Result<()> {
+    let dir = journal_dir(root);
+    if !dir.join(PENDING).exists() {
+        return Ok(());
+    }
+    let Ok(relative) = path.strip_prefix(root) else {
+        return Ok(());
+    };
+
+    let backup = dir.join(BACKUPS).join(relative);
+    let created = dir.join(CREATED);
+    // Already recorded, or under a directory the statement created
+    if backup.exists() || relative.ancestors().any(|ancestor| created.join(ancestor).is_file()) {
+        return Ok(());
+    }
+
+    if path.is_file() {
+        let Some(parent) = backup.parent() else {
+            return Ok(());
+        };
+        create_dirs(parent)?;
+        // Complete copies only: a crash mid-copy leaves just the temporary file
+        let temporary = backup.with_file_name(format!(
+            "{}.tmp",
+            backup.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        std::fs::copy(path, &temporary)?;
+        std::fs::File::open(&temporary)?.sync_all()?;
+        std::fs::rename(temporary, &backup)?;
+        sync_dir(parent)
+    } else {
+        let marker = created.join(relative);
+        let Some(parent) = marker.parent() else {
+            return Ok(());
+        };
+        create_dirs(parent)?;
+        std::fs::File::create(&marker)?.sync_all()?;
+        sync_dir(parent)
+    }
+}
+
+/// Create a directory and any missing parents, flushing each new entry to
+/// disk
+fn create_dirs(dir: &Path) -> anyhow::Result<()> {
+    if dir.is_dir() {
+        return Ok(());
+    }
+    let Some(parent) = dir.parent() else {
+        return Ok(());
+    };
+    create_dirs(parent)?;
+    match std::fs::create_dir(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Ok(()),
+        Err(e) => return Err(e.into()),
+    }
+    sync_dir(parent)
+}
+
+/// Flush a directory's entries to disk, after files in it are created or
+/// renamed
+fn sync_dir(dir: &Path) -> anyhow::Result<()> {
+    // Directories can't be opened as files on Windows, where renames are
+    // durable without this
+    #[cfg(unix)]
+    std::fs::File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// Record every file under a directory before it is removed
+pub fn preserve_dir(root: &Path, dir: &Path) -> anyhow::Result<()> {
+    if !journal_dir(root).join(PENDING).exists() {
+        return Ok(());
+    }
+    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        if entry.file_type().is_file() {
+            preserve(root, entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Put every recorded file back as it was and clear the journal, returning
+/// the number of paths restored or removed
+pub fn rollback(root: &Path) -> anyhow::Result<usize> {
+    let dir = journal_dir(root);
+    let mut count = 0;
+
+    for (tree, restore) in [(dir.join(BACKUPS), true), (dir.join(CREATED), false)] {
+        for entry in WalkDir::new(&tree).min_depth(1).into_iter().filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy();
+            if !entry.file_type().is_file() || (restore && name.ends_with(".tmp")) {
+                continue;
+            }
+            let Ok(relative) = entry.path().strip_prefix(&tree) else {
+                continue;
+            };
+            let path = root.join(relative);
+            if restore {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::copy(entry.path(), &path)?;
+            } else if path.is_dir() {
+                std::fs::remove_dir_all(&path)?;
+            } else if path.exists() {
+                std::fs::remove_file(&path)?;
+            }
+            count += 1;
+        }
+    }
+
+    clear(root)?;
+    Ok(count)
+}
+
+/// Finish a statement left in the journal by a crash, given the current HEAD
+pub fn recover(root: &Path, head: Option<String>) -> anyhow::Result<Option<Recovery>> {
+    let path = journal_dir(root).join(PENDING);
+    let pending: Pending = match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| anyhow::anyhow!("Invalid journal {:?}: {}", path, e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    if pending.head != head {
+        clear(root)?;
+        tracing::warn!("Found a journal for a statement that committed: {}", pending.statement);
+        return Ok(Some(Recovery::Completed(pending.statement)));
+    }
+
+    let count = rollback(root)?;
+    tracing::warn!("Rolled back {} file(s) of an interrupted statement: {}", count, pending.statement);
+    Ok(Some(Recovery::RolledBack(pending.statement)))
+}
+
+/// Remove the journal, keeping the statement's changes
+pub fn clear(root: &Path) -> anyhow::Result<()> {
+    let dir = journal_dir(root);
+    // The statement first, so a crash part way leaves no journal rather than half of one
+    let pending = dir.join(PENDING);
+    if pending.exists() {
+        std::fs::remove_file(pending)?;
+    }
+    for tree in [BACKUPS, CREATED] {
+        if dir.join(tree).exists() {
+            std::fs::remove_dir_all(dir.join(tree))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback_restores_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let docs = root.join("collections").join("todos");
+        std::fs::create_dir_all(&docs).unwrap();
+        std::fs::write(docs.join("a.md"), "old a").unwrap();
+        std::fs::write(docs.join("b.md"), "old b").unwrap();
+
+        // Nothing is recorded outside a statement
+        preserve(root, &docs.join("a.md")).unwrap();
+        assert!(!journal_dir(root).join(BACKUPS).exists());
+
+        begin(root, "UPDATE todos SET done = true", Some("abc".into())).unwrap();
+        preserve(root, &docs.join("a.md")).unwrap();
+        std::fs::write(docs.join("a.md"), "new a").unwrap();
+        preserve(root, &docs.join("a.md")).unwrap();
+        preserve(root, &docs.join("b.md")).unwrap();
+        std::fs::remove_file(docs.join("b.md")).unwrap();
+        let notes = root.join("collections").join("notes");
+        preserve(root, &notes).unwrap();
+        std::fs::create_dir_all(&notes).unwrap();
+        preserve(root, &notes.join("c.md")).unwrap();
+        std::fs::write(notes.join("c.md"), "new c").unwrap();
+
+        assert_eq!(recover(root, Some("abc".into())).unwrap(), Some(Recovery::RolledBack("UPDATE todos SET done = true".into())));
+        assert_eq!(std::fs::read_to_string(docs.join("a.md")).unwrap(), "old a");
+        assert_eq!(std::fs::read_to_string(docs.join("b.md")).unwrap(), "old b");
+        assert!(!notes.exists());
+        assert!(!journal_dir(root).join(PENDING).exists());
+        assert_eq!(recover(root, Some("abc".into())).unwrap(), None);
+    }
+
+    #[test]
+    fn test_recover_keeps_committed_statement() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        std::fs::write(root.join("a.md"), "old").unwrap();
+
+        begin(root, "DELETE FROM todos", Some("abc".into())).unwrap();
+        preserve(root, &root.join("a.md")).unwrap();
+        std::fs::write(root.join("a.md"), "new").unwrap();
+
+        assert_eq!(recover(root, Some("def".into())).unwrap(), Some(Recovery::Completed("DELETE FROM todos".into())));
+        assert_eq!(std::fs::read_to_string(root.join("a.md")).unwrap(), "new");
+    }
+}
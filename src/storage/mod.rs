@@ -4,4 +4,12 @@
 
 pub mod document;
 pub mod collection;
+pub mod compression;
 pub mod frontmatter;
+pub mod archive;
+pub mod index;
+pub mod ignore;
+pub mod journal;
+pub mod snapshot;
+pub mod store;
+pub mod tree;
@@ -0,0 +1,82 @@
+//! Consistent reads for a query (snapshot isolation)
+//!
+//! A SELECT reads its documents one file at a time, so another process
+//! writing the same collection meanwhile could show it half of a statement.
+//! A [`Snapshot`] pins a query to the moment it started and to the commit
+//! HEAD was at then. Files untouched since the start are read from disk as
+//! usual; a file that changed or disappeared underneath the query is read
+//! from the pinned commit instead, and a file that wasn't in it is left out.
+//! A document removed from disk since the start is listed from the commit's
+//! tree, so a listing shows the same documents a read by ID finds.
+//!
+//! Every write commits, so the commit holds what a file contained before a
+//! write that started after the query did. Edits made before the query
+//! started are read from disk whether committed or not. File times come from
+//! a coarser clock than the query's start, so a write in the first few
+//! milliseconds of a query may still be read from disk.
+
+use git2::Oid;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::tree::TreeReader;
+
+/// When a query started, and the commit its reads are pinned to
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    root: PathBuf,
+    /// HEAD when the query started; `None` if it couldn't be read, in which
+    /// case reads fall back to the working tree
+    commit: Option<Oid>,
+    started: SystemTime,
+}
+
+impl Snapshot {
+    /// Pin reads to now and to the repository's current HEAD
+    pub fn begin(git: &crate::git::Repository, root: &Path) -> Self {
+        let commit = git.head_commit().ok().map(|commit| commit.id());
+        Self { root: root.to_path_buf(), commit, started: SystemTime::now() }
+    }
+
+    /// Whether a file changed or was removed since the query started
+    pub fn is_stale(&self, path: &Path) -> bool {
+        match path.metadata().and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified > self.started,
+            Err(_) => true,
+        }
+    }
+
+    /// A file as of the pinned commit, if it was in it
+    pub fn read(&self, path: &Path) -> anyhow::Result<Option<String>> {
+        Ok(self.read_bytes(path)?.map(|content| String::from_utf8_lossy(&content).into_owned()))
+    }
+
+    /// A file's bytes as of the pinned commit, if it was in it
+    pub fn read_bytes(&self, path: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some(commit) = self.commit else {
+            return Ok(None);
+        };
+        let relative = path.strip_prefix(&self.root)?;
+        let repo = git2::Repository::open(&self.root)?;
+        let commit = repo.find_commit(commit)?;
+        let content = TreeReader::new(&repo, &commit)?.read_bytes(relative)?;
+        Ok(content)
+    }
+
+    /// Paths of the files directly in `dir` as of the pinned commit
+    pub fn files(&self, dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let Some(commit) = self.commit else {
+            return Ok(Vec::new());
+        };
+        let relative = dir.strip_prefix(&self.root)?;
+        let repo = git2::Repository::open(&self.root)?;
+        let commit = repo.find_commit(commit)?;
+        let files = TreeReader::new(&repo, &commit)?.files(relative)?;
+        Ok(files.into_iter().map(|file| dir.join(file)).collect())
+    }
+
+    /// Whether reads have a commit to fall back to
+    pub fn is_pinned(&self) -> bool {
+        self.commit.is_some()
+    }
+}
@@ -0,0 +1,310 @@
+//! Document storage backends
+//!
+//! The executor reads and writes documents through a [`Store`]: list, get,
+//! put and delete by collection, plus creating and dropping collections.
+//! [`MarkdownStore`], a directory of markdown files per collection, is the
+//! default; [`MemoryStore`] keeps documents in memory, for tests and
+//! throwaway databases, and [`ArchiveStore`](super::archive::ArchiveStore)
+//! reads them from a `.zip` or `.tar.gz` snapshot. Another backend (an object
+//! store mirror) implements the trait and is installed with
+//! [`Database::set_store`](crate::Database::set_store).
+//!
+//! Some features work on the markdown files themselves: indexes, reads
+//! pinned to a snapshot, streaming SELECTs and the write journal. They apply
+//! when the store is a markdown one ([`Store::markdown`]); with another
+//! store, queries scan the documents it lists instead. Schemas, views and
+//! the rest of `/.mdby/` stay on disk, committed to git as before.
+
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::collection::Collection;
+use super::document::Document;
+use super::journal;
+use crate::schema::Schema;
+
+/// Where a database's documents are kept
+pub trait Store: Send + Sync {
+    /// Names of every collection, sorted
+    fn collections(&self) -> BoxFuture<'_, anyhow::Result<Vec<String>>>;
+
+    /// Whether a collection exists
+    fn exists<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<bool>>;
+
+    /// Create a collection, if it doesn't exist
+    fn create_collection<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// Remove a collection and every document in it
+    fn drop_collection<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// IDs of a collection's documents, without reading them
+    fn ids<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<String>>>;
+
+    /// Every document in a collection
+    fn list<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<Document>>>;
+
+    /// One document by ID
+    fn get<'a>(&'a self, collection: &'a str, id: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Document>>>;
+
+    /// Write a document, adding it or replacing the one with its ID; returns
+    /// false, writing nothing, if the stored document is the same
+    ///
+    /// `layout` is how the collection's schema asks for documents to be
+    /// written, for formats that have a choice.
+    fn put<'a>(&'a self, collection: &'a str, doc: &'a Document, layout: &'a Layout) -> BoxFuture<'a, anyhow::Result<bool>>;
+
+    /// Delete a document; returns whether there was one
+    fn delete<'a>(&'a self, collection: &'a str, id: &'a str) -> BoxFuture<'a, anyhow::Result<bool>>;
+
+    /// The markdown files behind the store, if that's what it is
+    fn markdown(&self) -> Option<&MarkdownStore> {
+        None
+    }
+
+    /// Whether every write fails, making the database read-only
+    fn is_read_only(&self) -> bool {
+        false
+    }
+}
+
+/// How a collection's documents are written, from its schema
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    /// Fields to write first, for formats that keep an order
+    pub field_order: Vec<String>,
+    /// Bodies longer than this many bytes are kept compressed, by stores
+    /// that can (see [`super::compression`])
+    pub compress_bodies_over: Option<usize>,
+}
+
+impl Layout {
+    /// The layout a schema asks for; the default without one
+    pub fn of(schema: Option<&Schema>) -> Self {
+        match schema {
+            Some(schema) => Self { field_order: schema.field_order(), compress_bodies_over: schema.compress_bodies_over },
+            None => Self::default(),
+        }
+    }
+}
+
+/// Documents as markdown files in `/collections/{name}/` (see [`Collection`])
+#[derive(Debug, Clone)]
+pub struct MarkdownStore {
+    root: PathBuf,
+}
+
+impl MarkdownStore {
+    /// The store of the database at `root`
+    pub fn new(root: &Path) -> Self {
+        Self { root: root.to_path_buf() }
+    }
+
+    /// A collection's directory
+    pub fn collection(&self, name: &str) -> Collection {
+        Collection::open(name, &self.root)
+    }
+}
+
+impl Store for MarkdownStore {
+    fn collections(&self) -> BoxFuture<'_, anyhow::Result<Vec<String>>> {
+        async move {
+            let dir = self.root.join("collections");
+            let mut names = Vec::new();
+            if dir.exists() {
+                let mut entries = tokio::fs::read_dir(&dir).await?;
+                while let Some(entry) = entries.next_entry().await? {
+                    if entry.file_type().await?.is_dir() {
+                        names.extend(entry.file_name().to_str().map(str::to_string));
+                    }
+                }
+            }
+            names.sort();
+            Ok(names)
+        }
+        .boxed()
+    }
+
+    fn exists<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<bool>> {
+        async move { Ok(self.collection(collection).exists().await) }.boxed()
+    }
+
+    fn create_collection<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move { self.collection(collection).ensure_exists().await }.boxed()
+    }
+
+    fn drop_collection<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            let path = self.collection(collection).path;
+            journal::preserve_dir(&self.root, &path)?;
+            tokio::fs::remove_dir_all(&path).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn ids<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<String>>> {
+        async move {
+            let paths = self.collection(collection).document_paths()?;
+            Ok(paths
+                .iter()
+                .filter_map(|path| path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string))
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn list<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<Document>>> {
+        async move { self.collection(collection).list().await }.boxed()
+    }
+
+    fn get<'a>(&'a self, collection: &'a str, id: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Document>>> {
+        async move { self.collection(collection).get(id).await }.boxed()
+    }
+
+    fn put<'a>(&'a self, collection: &'a str, doc: &'a Document, layout: &'a Layout) -> BoxFuture<'a, anyhow::Result<bool>> {
+        async move {
+            let collection = self.collection(collection).with_layout(layout);
+            if collection.path.join(format!("{}.md", doc.id)).exists() {
+                collection.update_if_changed(doc).await
+            } else {
+                collection.insert(doc).await?;
+                Ok(true)
+            }
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, collection: &'a str, id: &'a str) -> BoxFuture<'a, anyhow::Result<bool>> {
+        async move { self.collection(collection).delete(id).await }.boxed()
+    }
+
+    fn markdown(&self) -> Option<&MarkdownStore> {
+        Some(self)
+    }
+}
+
+/// Documents kept in memory, lost when the store is dropped
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    collections: Mutex<BTreeMap<String, BTreeMap<String, Document>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `f` on the collections, with the lock held
+    fn with<T>(&self, f: impl FnOnce(&mut BTreeMap<String, BTreeMap<String, Document>>) -> T) -> anyhow::Result<T> {
+        let mut collections = self.collections.lock().map_err(|_| anyhow::anyhow!("Memory store lock poisoned"))?;
+        Ok(f(&mut collections))
+    }
+}
+
+impl Store for MemoryStore {
+    fn collections(&self) -> BoxFuture<'_, anyhow::Result<Vec<String>>> {
+        async move { self.with(|collections| collections.keys().cloned().collect()) }.boxed()
+    }
+
+    fn exists<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<bool>> {
+        async move { self.with(|collections| collections.contains_key(collection)) }.boxed()
+    }
+
+    fn create_collection<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            self.with(|collections| {
+                collections.entry(collection.to_string()).or_default();
+            })
+        }
+        .boxed()
+    }
+
+    fn drop_collection<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            self.with(|collections| {
+                collections.remove(collection);
+            })
+        }
+        .boxed()
+    }
+
+    fn ids<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<String>>> {
+        async move { Ok(self.with(|collections| collections.get(collection).map(|docs| docs.keys().cloned().collect()))?.unwrap_or_default()) }
+            .boxed()
+    }
+
+    fn list<'a>(&'a self, collection: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<Document>>> {
+        async move { Ok(self.with(|collections| collections.get(collection).map(|docs| docs.values().cloned().collect()))?.unwrap_or_default()) }
+            .boxed()
+    }
+
+    fn get<'a>(&'a self, collection: &'a str, id: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Document>>> {
+        async move { self.with(|collections| collections.get(collection).and_then(|docs| docs.get(id).cloned())) }.boxed()
+    }
+
+    fn put<'a>(&'a self, collection: &'a str, doc: &'a Document, _layout: &'a Layout) -> BoxFuture<'a, anyhow::Result<bool>> {
+        async move {
+            self.with(|collections| {
+                let docs = collections.entry(collection.to_string()).or_default();
+                let unchanged = docs.get(&doc.id).is_some_and(|stored| stored.fields == doc.fields && stored.body == doc.body);
+                if !unchanged {
+                    docs.insert(doc.id.clone(), doc.clone());
+                }
+                !unchanged
+            })
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, collection: &'a str, id: &'a str) -> BoxFuture<'a, anyhow::Result<bool>> {
+        async move { self.with(|collections| collections.get_mut(collection).is_some_and(|docs| docs.remove(id).is_some())) }
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::document::Value;
+
+    /// The same round trip through any store
+    async fn round_trip(store: &dyn Store) {
+        assert!(!store.exists("notes").await.unwrap());
+        store.create_collection("notes").await.unwrap();
+        assert_eq!(store.collections().await.unwrap(), ["notes"]);
+
+        let mut doc = Document::new("a").with_body("Hello");
+        doc.fields.insert("title".into(), Value::String("A".into()));
+        assert!(store.put("notes", &doc, &Layout::default()).await.unwrap());
+        assert!(!store.put("notes", &doc, &Layout::default()).await.unwrap());
+        assert_eq!(store.get("notes", "a").await.unwrap().unwrap().body, "Hello");
+        assert_eq!(store.ids("notes").await.unwrap(), ["a"]);
+
+        doc.body = "Changed".into();
+        assert!(store.put("notes", &doc, &Layout::default()).await.unwrap());
+        assert_eq!(store.list("notes").await.unwrap()[0].body, "Changed");
+
+        assert!(store.delete("notes", "a").await.unwrap());
+        assert!(!store.delete("notes", "a").await.unwrap());
+        assert!(store.get("notes", "a").await.unwrap().is_none());
+
+        store.drop_collection("notes").await.unwrap();
+        assert!(!store.exists("notes").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_markdown_store() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        round_trip(&MarkdownStore::new(tmp.path())).await;
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        let store = MemoryStore::new();
+        round_trip(&store).await;
+        assert!(store.markdown().is_none());
+    }
+}
@@ -0,0 +1,214 @@
+//! Reading a database from a git tree
+//!
+//! A read-only replica opened from a bare repository has no working tree, so
+//! its collections, documents and settings are read straight from the blobs
+//! of one commit. The layout and rules are the same as on disk: documents are
+//! the `.md` files directly inside `collections/{name}/`, minus metadata files
+//! and anything matched by `.mdbyignore`, with bodies in a `.md.gz` beside
+//! them decompressed.
+
+use git2::{ObjectType, Repository, Tree};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use super::collection::{CollectionMeta, META_FILES};
+use super::compression;
+use super::document::Document;
+use super::ignore::{IgnoreRules, IGNORE_FILE};
+
+/// Files and collections as of one commit
+pub struct TreeReader<'r> {
+    repo: &'r Repository,
+    tree: Tree<'r>,
+    /// Commit time, used as every document's modification time
+    time: SystemTime,
+}
+
+impl<'r> TreeReader<'r> {
+    /// Read the tree of a commit
+    pub fn new(repo: &'r Repository, commit: &git2::Commit<'r>) -> anyhow::Result<Self> {
+        let seconds = u64::try_from(commit.time().seconds()).unwrap_or_default();
+        Ok(Self {
+            repo,
+            tree: commit.tree()?,
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(seconds),
+        })
+    }
+
+    /// Contents of a file, relative to the database root
+    pub fn read_file(&self, path: impl AsRef<Path>) -> anyhow::Result<Option<String>> {
+        Ok(self.read_bytes(path)?.map(|content| String::from_utf8_lossy(&content).into_owned()))
+    }
+
+    /// Bytes of a file, relative to the database root
+    pub fn read_bytes(&self, path: impl AsRef<Path>) -> anyhow::Result<Option<Vec<u8>>> {
+        let entry = match self.tree.get_path(path.as_ref()) {
+            Ok(entry) if entry.kind() == Some(ObjectType::Blob) => entry,
+            _ => return Ok(None),
+        };
+        let blob = self.repo.find_blob(entry.id())?;
+        Ok(Some(blob.content().to_vec()))
+    }
+
+    /// Names of the entries of one kind in a directory, sorted
+    fn entries(&self, dir: &Path, kind: ObjectType) -> anyhow::Result<Vec<String>> {
+        let entry = match self.tree.get_path(dir) {
+            Ok(entry) if entry.kind() == Some(ObjectType::Tree) => entry,
+            _ => return Ok(Vec::new()),
+        };
+        let tree = self.repo.find_tree(entry.id())?;
+        let mut names: Vec<String> = tree
+            .iter()
+            .filter(|entry| entry.kind() == Some(kind))
+            .filter_map(|entry| entry.name().map(str::to_string))
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Total size in bytes of the files under a directory, at any depth
+    pub fn size(&self, dir: impl AsRef<Path>) -> anyhow::Result<u64> {
+        let entry = match self.tree.get_path(dir.as_ref()) {
+            Ok(entry) if entry.kind() == Some(ObjectType::Tree) => entry,
+            _ => return Ok(0),
+        };
+        let odb = self.repo.odb()?;
+        let mut bytes = 0;
+        let mut error = None;
+        self.repo.find_tree(entry.id())?.walk(git2::TreeWalkMode::PreOrder, |_, entry| {
+            if entry.kind() == Some(ObjectType::Blob) {
+                match odb.read_header(entry.id()) {
+                    Ok((size, _)) => bytes += size as u64,
+                    Err(err) => {
+                        error = Some(err);
+                        return git2::TreeWalkResult::Abort;
+                    }
+                }
+            }
+            git2::TreeWalkResult::Ok
+        })?;
+        match error {
+            Some(err) => Err(err.into()),
+            None => Ok(bytes),
+        }
+    }
+
+    /// Names of the files in a directory, sorted
+    pub fn files(&self, dir: impl AsRef<Path>) -> anyhow::Result<Vec<String>> {
+        self.entries(dir.as_ref(), ObjectType::Blob)
+    }
+
+    /// Collection names, sorted
+    pub fn collections(&self) -> anyhow::Result<Vec<String>> {
+        self.entries(Path::new("collections"), ObjectType::Tree)
+    }
+
+    /// Whether a collection exists
+    pub fn collection_exists(&self, name: &str) -> anyhow::Result<bool> {
+        Ok(self.collections()?.iter().any(|c| c == name))
+    }
+
+    /// View names, sorted
+    pub fn views(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .files(Path::new(".mdby").join("views"))?
+            .into_iter()
+            .filter_map(|file| file.strip_suffix(".yaml").map(str::to_string))
+            .collect())
+    }
+
+    /// Every document in a collection
+    pub fn documents(&self, collection: &str) -> anyhow::Result<Vec<Document>> {
+        if !self.collection_exists(collection)? {
+            anyhow::bail!("Collection '{}' does not exist", collection);
+        }
+
+        let dir = Path::new("collections").join(collection);
+        let ignore_files: Vec<String> = [PathBuf::from(IGNORE_FILE), dir.join(IGNORE_FILE)]
+            .iter()
+            .filter_map(|path| self.read_file(path).transpose())
+            .collect::<anyhow::Result<_>>()?;
+        let rules = IgnoreRules::from_files(&ignore_files)?;
+
+        let mut documents = Vec::new();
+        for file in self.files(&dir)? {
+            let Some(id) = file.strip_suffix(".md") else {
+                continue;
+            };
+            if rules.is_ignored(&file) || META_FILES.contains(&file.as_str()) {
+                continue;
+            }
+            let Some(content) = self.read_file(dir.join(&file))? else {
+                continue;
+            };
+            // Unparseable files are skipped, as they are on disk
+            if let Ok(mut doc) = Document::parse(id, &content) {
+                if let Some(body) = self.read_bytes(dir.join(compression::body_file(&file)))? {
+                    doc.body = compression::decompress(&body)?;
+                }
+                doc.path = PathBuf::from(&file);
+                doc.meta.modified_at = Some(self.time);
+                documents.push(doc);
+            }
+        }
+
+        Ok(documents)
+    }
+
+    /// The collection's `_meta.md` (or, failing that, `README.md`)
+    pub fn meta(&self, collection: &str) -> anyhow::Result<Option<CollectionMeta>> {
+        let dir = Path::new("collections").join(collection);
+        for file in META_FILES {
+            if let Some(content) = self.read_file(dir.join(file))? {
+                let (fields, body) = super::frontmatter::parse(&content)?;
+                return Ok(Some(CollectionMeta {
+                    file: file.to_string(),
+                    fields,
+                    body,
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let full = root.join(path);
+        std::fs::create_dir_all(full.parent().unwrap()).unwrap();
+        std::fs::write(full, content).unwrap();
+    }
+
+    #[test]
+    fn test_reads_collections_from_commit() {
+        let tmp = TempDir::new().unwrap();
+        let repo = crate::git::Repository::open_or_init(tmp.path()).unwrap();
+        write(tmp.path(), "collections/todos/task-1.md", "---\ntitle: One\n---\nBody\n");
+        write(tmp.path(), "collections/todos/draft.md", "---\ntitle: Draft\n---\n");
+        write(tmp.path(), "collections/todos/.mdbyignore", "draft.md\n");
+        write(tmp.path(), "collections/todos/README.md", "---\nowner: ada\n---\nAbout todos\n");
+        write(tmp.path(), ".mdby/views/open.yaml", "name: open\n");
+        repo.commit("Add todos").unwrap();
+
+        // Later working tree changes aren't part of the commit
+        write(tmp.path(), "collections/todos/task-2.md", "---\ntitle: Two\n---\n");
+
+        let commit = repo.inner().head().unwrap().peel_to_commit().unwrap();
+        let reader = TreeReader::new(repo.inner(), &commit).unwrap();
+        assert_eq!(reader.collections().unwrap(), vec!["todos"]);
+        assert_eq!(reader.views().unwrap(), vec!["open"]);
+
+        let docs = reader.documents("todos").unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].id, "task-1");
+        assert_eq!(docs[0].body.trim(), "Body");
+        assert_eq!(reader.meta("todos").unwrap().unwrap().body.trim(), "About todos");
+
+        assert!(reader.documents("missing").is_err());
+        assert_eq!(reader.read_file("collections/todos/nope.md").unwrap(), None);
+    }
+}
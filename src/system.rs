@@ -0,0 +1,174 @@
+//! System collections
+//!
+//! Read-only collections whose names start with `@`. Their documents are
+//! generated on each query rather than stored as files, and they can be used
+//! anywhere a SELECT reads a collection, including views.
+//!
+//! - `@commits`: the git history, newest first. Each document's ID is the
+//!   commit hash, with fields `hash`, `author`, `email`, `date` (UTC, RFC 3339),
+//!   `message` (first line) and `files` (paths touched). The body is the full
+//!   commit message.
+//! - `@collections`: one document per collection, with the collection name as
+//!   its ID and fields `name`, `documents`, `bytes` (every file in the
+//!   collection's directory), `schema` (whether one is defined), `modified`
+//!   (time of the last commit touching the collection, RFC 3339) and
+//!   `activity` (commits touching it in each of the last
+//!   [`ACTIVITY_WEEKS`] weeks, oldest first).
+
+use std::path::Path;
+
+use crate::git::{CommitDetails, Repository};
+use crate::storage::collection::Collection;
+use crate::storage::document::{Document, Value};
+
+/// The git history
+pub const COMMITS: &str = "@commits";
+
+/// Collection sizes and activity
+pub const COLLECTIONS: &str = "@collections";
+
+/// Weeks of commit counts in each `@collections` document's `activity`
+pub const ACTIVITY_WEEKS: usize = 8;
+
+/// Whether a name refers to a system collection
+pub fn is_system(name: &str) -> bool {
+    name.starts_with('@')
+}
+
+/// Whether a system collection with this name exists
+pub fn exists(name: &str) -> bool {
+    name == COMMITS || name == COLLECTIONS
+}
+
+/// Documents of a system collection
+pub fn list(git: &Repository, name: &str) -> anyhow::Result<Vec<Document>> {
+    match name {
+        COMMITS => Ok(git.history()?.into_iter().map(commit_document).collect()),
+        COLLECTIONS => collection_documents(git),
+        _ => anyhow::bail!("Collection '{}' does not exist", name),
+    }
+}
+
+fn commit_document(commit: CommitDetails) -> Document {
+    let mut doc = Document::new(&commit.id);
+    let summary = commit.message.lines().next().unwrap_or_default().to_string();
+    let date = chrono::DateTime::from_timestamp(commit.time, 0)
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_default();
+
+    doc.fields.insert("hash".into(), Value::String(commit.id));
+    doc.fields.insert("author".into(), Value::String(commit.author));
+    doc.fields.insert("email".into(), Value::String(commit.email));
+    doc.fields.insert("date".into(), Value::String(date));
+    doc.fields.insert("message".into(), Value::String(summary));
+    doc.fields.insert(
+        "files".into(),
+        Value::Array(commit.files.into_iter().map(Value::String).collect()),
+    );
+    doc.body = commit.message;
+    doc
+}
+
+/// Document count, size and schema presence of one collection
+struct CollectionSize {
+    name: String,
+    documents: usize,
+    bytes: u64,
+    schema: bool,
+}
+
+fn collection_documents(git: &Repository) -> anyhow::Result<Vec<Document>> {
+    let sizes = match git.snapshot()? {
+        Some(tree) => tree
+            .collections()?
+            .into_iter()
+            .map(|name| {
+                let dir = Path::new("collections").join(&name);
+                Ok(CollectionSize {
+                    documents: tree.documents(&name)?.len(),
+                    bytes: tree.size(&dir)?,
+                    schema: tree.read_file(schema_path(&name))?.is_some(),
+                    name,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        None => {
+            let root = git
+                .inner()
+                .workdir()
+                .ok_or_else(|| anyhow::anyhow!("Repository has no working tree"))?;
+            working_tree_sizes(root)?
+        }
+    };
+
+    let history = git.history()?;
+    let now = chrono::Utc::now().timestamp();
+    Ok(sizes
+        .into_iter()
+        .map(|size| {
+            let prefix = format!("collections/{}/", size.name);
+            let touching: Vec<&CommitDetails> = history
+                .iter()
+                .filter(|commit| commit.files.iter().any(|file| file.starts_with(&prefix)))
+                .collect();
+
+            let mut activity = [0i64; ACTIVITY_WEEKS];
+            for commit in &touching {
+                let week = usize::try_from((now - commit.time).max(0) / (7 * 24 * 60 * 60)).unwrap_or(usize::MAX);
+                if week < ACTIVITY_WEEKS {
+                    activity[ACTIVITY_WEEKS - 1 - week] += 1;
+                }
+            }
+            // History is newest first
+            let modified = touching
+                .first()
+                .and_then(|commit| chrono::DateTime::from_timestamp(commit.time, 0))
+                .map(|t| Value::String(t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)))
+                .unwrap_or(Value::Null);
+
+            let mut doc = Document::new(&size.name);
+            doc.fields.insert("name".into(), Value::String(size.name));
+            doc.fields.insert("documents".into(), Value::Int(size.documents as i64));
+            doc.fields.insert("bytes".into(), Value::Int(i64::try_from(size.bytes).unwrap_or(i64::MAX)));
+            doc.fields.insert("schema".into(), Value::Bool(size.schema));
+            doc.fields.insert("modified".into(), modified);
+            doc.fields.insert("activity".into(), Value::Array(activity.into_iter().map(Value::Int).collect()));
+            doc
+        })
+        .collect())
+}
+
+fn working_tree_sizes(root: &Path) -> anyhow::Result<Vec<CollectionSize>> {
+    let dir = root.join("collections");
+    let mut sizes = Vec::new();
+    if !dir.exists() {
+        return Ok(sizes);
+    }
+
+    for entry in std::fs::read_dir(&dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let bytes = walkdir::WalkDir::new(entry.path())
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum();
+        sizes.push(CollectionSize {
+            documents: Collection::open(&name, root).document_paths()?.len(),
+            bytes,
+            schema: root.join(schema_path(&name)).exists(),
+            name,
+        });
+    }
+    sizes.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(sizes)
+}
+
+fn schema_path(collection: &str) -> std::path::PathBuf {
+    Path::new(".mdby").join("schemas").join(format!("{}.yaml", collection))
+}
@@ -101,6 +101,32 @@ pub fn validate_template_name(name: &str) -> Result<(), ValidationError> {
     Ok(())
 }
 
+/// Validate a file path given in a query (e.g. `BODY FROM FILE`)
+///
+/// The path must be relative and may not climb out of its base directory
+pub fn validate_relative_path(path: &str) -> Result<(), ValidationError> {
+    if path.is_empty() {
+        return Err(ValidationError::Empty);
+    }
+
+    let path_ref = std::path::Path::new(path);
+    if path_ref.has_root() || path.starts_with('~') {
+        return Err(ValidationError::InvalidIdentifier(
+            path.to_string(),
+            "must be a relative path",
+        ));
+    }
+
+    if path_ref.components().any(|c| matches!(c, std::path::Component::ParentDir)) {
+        return Err(ValidationError::InvalidIdentifier(
+            path.to_string(),
+            "contains path traversal characters",
+        ));
+    }
+
+    Ok(())
+}
+
 /// Core identifier validation
 fn validate_identifier(name: &str, _kind: &'static str) -> Result<(), ValidationError> {
     if name.is_empty() {
@@ -146,11 +172,9 @@ pub fn sanitize_identifier(input: &str) -> Option<String> {
     let mut result = String::with_capacity(input.len());
 
     for (i, c) in input.chars().enumerate() {
-        if c.is_ascii_alphanumeric() {
-            result.push(c);
-        } else if (c == '_' || c == '-') && i > 0 {
+        if c.is_ascii_alphanumeric() || ((c == '_' || c == '-') && i > 0) {
             result.push(c);
-        } else if !result.is_empty() && result.chars().last() != Some('_') {
+        } else if !result.is_empty() && !result.ends_with('_') {
             // Replace invalid chars with underscore (avoiding duplicates)
             result.push('_');
         }
@@ -226,6 +250,16 @@ mod tests {
         assert!(validate_template_name(".hidden").is_err());
     }
 
+    #[test]
+    fn test_relative_paths() {
+        assert!(validate_relative_path("draft.md").is_ok());
+        assert!(validate_relative_path("./notes/draft.md").is_ok());
+        assert!(validate_relative_path("/etc/passwd").is_err());
+        assert!(validate_relative_path("~/draft.md").is_err());
+        assert!(validate_relative_path("notes/../../secret.md").is_err());
+        assert!(validate_relative_path("").is_err());
+    }
+
     #[test]
     fn test_sanitize() {
         assert_eq!(sanitize_identifier("hello world"), Some("hello_world".to_string()));
@@ -0,0 +1,246 @@
+//! Built-in `@dashboard` view (`mdby dashboard`)
+//!
+//! Renders an overview of the database to `/views/@dashboard/index.html`, with
+//! the same data as JSON in `index.json`: document counts per collection, the
+//! most recently changed documents, overdue documents, and how often each tag
+//! is used. View names can't start with `@`, so the output never collides
+//! with a user view. Once generated, the dashboard is refreshed along with
+//! the other views.
+//!
+//! Each collection's schema can say which fields play which role through its
+//! `display` hints; see [`DisplayHints`]. Only collections with a `due` field
+//! have overdue documents.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::Serialize;
+use tera::Context;
+use tokio::fs;
+
+use super::TemplateEngine;
+use crate::schema::DisplayHints;
+use crate::storage::document::{Document, Value};
+use crate::Database;
+
+/// Name of the dashboard view
+pub const DASHBOARD_VIEW: &str = "@dashboard";
+
+/// Number of documents listed under recent changes
+const RECENT_DOCUMENTS: usize = 10;
+
+/// Number of tags listed per collection
+const TOP_TAGS: usize = 10;
+
+/// Path of the generated dashboard page
+pub fn dashboard_path(db: &Database) -> PathBuf {
+    db.root.join("views").join(DASHBOARD_VIEW).join("index.html")
+}
+
+#[derive(Debug, Serialize)]
+struct Dashboard {
+    documents: usize,
+    overdue: usize,
+    collections: Vec<CollectionSummary>,
+    recent: Vec<DocumentSummary>,
+}
+
+#[derive(Debug, Serialize)]
+struct CollectionSummary {
+    name: String,
+    description: Option<String>,
+    count: usize,
+    /// Field the overdue list is based on
+    due_field: Option<String>,
+    overdue: Vec<DocumentSummary>,
+    /// Most used first
+    tags: Vec<TagCount>,
+}
+
+#[derive(Debug, Serialize)]
+struct DocumentSummary {
+    collection: String,
+    id: String,
+    title: String,
+    /// The due date, for overdue documents
+    due: Option<String>,
+    modified: Option<String>,
+    author: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct TagCount {
+    tag: String,
+    count: usize,
+}
+
+/// Generate the dashboard, returning the path of its HTML page
+pub async fn generate_dashboard(db: &Database) -> anyhow::Result<PathBuf> {
+    let dashboard = build(db).await?;
+
+    let mut engine = TemplateEngine::empty();
+    engine.add_template("dashboard.html", DASHBOARD_TEMPLATE)?;
+    let html = engine.render_context("dashboard.html", &Context::from_serialize(&dashboard)?)?;
+
+    let path = dashboard_path(db);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+        fs::write(parent.join("index.json"), serde_json::to_string_pretty(&dashboard)?).await?;
+    }
+    fs::write(&path, html).await?;
+
+    tracing::info!("Generated dashboard: {:?}", path);
+
+    Ok(path)
+}
+
+async fn build(db: &Database) -> anyhow::Result<Dashboard> {
+    let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    let mut collections = Vec::new();
+    let mut recent = Vec::new();
+
+    for (name, mut docs) in db.all_documents().await? {
+        let schema = db.schema.get(&name);
+        let hints = schema.map(|schema| schema.display.clone()).unwrap_or_default();
+        if hints.hidden {
+            continue;
+        }
+        crate::query::attach_history(&db.git, &name, &mut docs)?;
+        docs.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut overdue: Vec<DocumentSummary> = Vec::new();
+        if let Some(due_field) = &hints.due {
+            for doc in &docs {
+                let done = hints.done.as_ref().is_some_and(|done| doc.get(done) == Some(&Value::Bool(true)));
+                match doc.get(due_field).and_then(Value::as_str) {
+                    // Dates and datetimes both start with the ISO date
+                    Some(due) if !done && due.get(..10).is_some_and(|date| *date < *today) => {
+                        let mut summary = summarize(&name, doc, &hints);
+                        summary.due = Some(due.to_string());
+                        overdue.push(summary);
+                    }
+                    _ => {}
+                }
+            }
+            overdue.sort_by(|a, b| a.due.cmp(&b.due));
+        }
+
+        let tags_field = hints.tags.as_deref().unwrap_or("tags");
+        let mut tag_counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for doc in &docs {
+            if let Some(Value::Array(tags)) = doc.get(tags_field) {
+                for tag in tags.iter().filter_map(Value::as_str) {
+                    *tag_counts.entry(tag).or_default() += 1;
+                }
+            }
+        }
+        let mut tags: Vec<TagCount> = tag_counts
+            .into_iter()
+            .map(|(tag, count)| TagCount { tag: tag.to_string(), count })
+            .collect();
+        tags.sort_by_key(|tag| std::cmp::Reverse(tag.count));
+        tags.truncate(TOP_TAGS);
+
+        recent.extend(docs.iter().map(|doc| summarize(&name, doc, &hints)));
+        collections.push(CollectionSummary {
+            name: name.clone(),
+            description: schema.and_then(|schema| schema.description.clone()),
+            count: docs.len(),
+            due_field: hints.due.clone(),
+            overdue,
+            tags,
+        });
+    }
+
+    // RFC 3339 timestamps in UTC sort as text
+    recent.retain(|doc| doc.modified.is_some());
+    recent.sort_by(|a, b| b.modified.cmp(&a.modified));
+    recent.truncate(RECENT_DOCUMENTS);
+
+    Ok(Dashboard {
+        documents: collections.iter().map(|c| c.count).sum(),
+        overdue: collections.iter().map(|c| c.overdue.len()).sum(),
+        collections,
+        recent,
+    })
+}
+
+fn summarize(collection: &str, doc: &Document, hints: &DisplayHints) -> DocumentSummary {
+    let title_field = hints.title.as_deref().unwrap_or("title");
+    DocumentSummary {
+        collection: collection.to_string(),
+        id: doc.id.clone(),
+        title: doc.get(title_field).and_then(Value::as_str).unwrap_or(&doc.id).to_string(),
+        due: None,
+        modified: doc.meta.modified.clone(),
+        author: doc.meta.author.clone(),
+    }
+}
+
+const DASHBOARD_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>Dashboard</title>
+    <style>
+        body { font-family: system-ui, sans-serif; max-width: 960px; margin: 2rem auto; padding: 0 1rem; }
+        section { border-bottom: 1px solid #eee; padding: 1rem 0; }
+        table { border-collapse: collapse; width: 100%; margin-top: 0.5rem; }
+        th, td { text-align: left; padding: 0.3rem 0.6rem; border-bottom: 1px solid #eee; vertical-align: top; }
+        .meta { color: #666; font-size: 0.9rem; }
+        .overdue { color: #b00020; }
+        .tag { display: inline-block; background: #f6f8fa; border-radius: 4px; padding: 0.1rem 0.4rem; margin: 0.1rem; }
+    </style>
+</head>
+<body>
+    <h1>Dashboard</h1>
+    <p class="meta">{{ documents }} document(s) in {{ collections | length }} collection(s){% if overdue %} &middot; <span class="overdue">{{ overdue }} overdue</span>{% endif %}</p>
+
+    <h2>Collections</h2>
+    <table>
+        <tr><th>Collection</th><th>Documents</th><th>Overdue</th></tr>
+        {% for collection in collections %}
+        <tr>
+            <td><a href="#collection-{{ collection.name }}">{{ collection.name }}</a></td>
+            <td>{{ collection.count }}</td>
+            <td>{% if collection.due_field %}{{ collection.overdue | length }}{% else %}<span class="meta">&ndash;</span>{% endif %}</td>
+        </tr>
+        {% else %}
+        <tr><td colspan="3" class="meta">No collections</td></tr>
+        {% endfor %}
+    </table>
+
+    <h2>Recently changed</h2>
+    <table>
+        {% for doc in recent %}
+        <tr>
+            <td>{{ doc.title }}</td>
+            <td class="meta">{{ doc.collection }}/{{ doc.id }}</td>
+            <td class="meta">{{ doc.author | default(value="") }}</td>
+            <td class="meta">{{ doc.modified | date(format="%Y-%m-%d %H:%M") }}</td>
+        </tr>
+        {% else %}
+        <tr><td class="meta">No committed documents</td></tr>
+        {% endfor %}
+    </table>
+
+    {% for collection in collections %}
+    <section id="collection-{{ collection.name }}">
+        <h3>{{ collection.name }}</h3>
+        {% if collection.description %}<p>{{ collection.description }}</p>{% endif %}
+        {% if collection.overdue %}
+        <h4 class="overdue">Overdue</h4>
+        <table>
+            {% for doc in collection.overdue %}
+            <tr><td>{{ doc.title }}</td><td class="meta">{{ doc.id }}</td><td class="overdue">{{ doc.due }}</td></tr>
+            {% endfor %}
+        </table>
+        {% endif %}
+        {% if collection.tags %}
+        <h4>Tags</h4>
+        <p>{% for tag in collection.tags %}<span class="tag">{{ tag.tag }} ({{ tag.count }})</span>{% endfor %}</p>
+        {% endif %}
+    </section>
+    {% endfor %}
+</body>
+</html>"##;
@@ -0,0 +1,304 @@
+//! View delivery (`DELIVER TO target SCHEDULE '...'`)
+//!
+//! A view can name a delivery target from `delivery_targets` in
+//! `/.mdby/config.yaml`: a webhook, or a command such as `sendmail -t`.
+//! Delivering a view regenerates it and sends its HTML to the target.
+//! `mdby deliver <view>` delivers one view on demand; `mdby deliver --daemon`
+//! keeps running and delivers each scheduled view when its schedule comes
+//! round.
+//!
+//! Schedules are written as `hourly`, `every 15m` (or `2h`), `daily 07:00`
+//! or `weekly mon 07:00`, in UTC. The last scheduled delivery of each view is
+//! recorded in `/.mdby/logs/deliveries.json`, so a restarted daemon doesn't
+//! send a view twice for the same slot. A view is first delivered at the
+//! first slot after the daemon sees it.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::str::FromStr;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use super::regenerate::ViewDefinition;
+use crate::config::DeliveryTarget;
+use crate::Database;
+
+const MINUTE: i64 = 60;
+const DAY: i64 = 24 * 60 * MINUTE;
+const WEEK: i64 = 7 * DAY;
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// When a view is delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// Every so many seconds, counted from the epoch
+    Every(i64),
+    /// Every day at a minute of the day
+    Daily(i64),
+    /// Every week on a weekday (0 = Monday) at a minute of the day
+    Weekly(i64, i64),
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let invalid = || {
+            anyhow::anyhow!(
+                "Invalid schedule '{}': expected 'hourly', 'every 15m', 'daily 07:00' or 'weekly mon 07:00'",
+                s
+            )
+        };
+        let words: Vec<String> = s.split_whitespace().map(str::to_lowercase).collect();
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+
+        match words.as_slice() {
+            ["hourly"] => Ok(Schedule::Every(60 * MINUTE)),
+            ["every", interval] => {
+                let (number, unit) = match (interval.strip_suffix('m'), interval.strip_suffix('h')) {
+                    (Some(number), _) => (number, MINUTE),
+                    (_, Some(number)) => (number, 60 * MINUTE),
+                    _ => return Err(invalid()),
+                };
+                let number: i64 = number.parse().map_err(|_| invalid())?;
+                if number <= 0 {
+                    return Err(invalid());
+                }
+                let seconds = number
+                    .checked_mul(unit)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid schedule '{}': the interval is too long", s))?;
+                Ok(Schedule::Every(seconds))
+            }
+            ["daily", time] => Ok(Schedule::Daily(minute_of_day(time).ok_or_else(invalid)?)),
+            ["weekly", day, time] => {
+                let day = WEEKDAYS.iter().position(|d| day.starts_with(d)).ok_or_else(invalid)?;
+                Ok(Schedule::Weekly(day as i64, minute_of_day(time).ok_or_else(invalid)?))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// `HH:MM` as minutes since midnight
+fn minute_of_day(time: &str) -> Option<i64> {
+    let (hours, minutes) = time.split_once(':')?;
+    let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+    ((0..24).contains(&hours) && (0..60).contains(&minutes)).then_some(hours * 60 + minutes)
+}
+
+impl Schedule {
+    /// The latest slot at or before `now` (seconds since the epoch)
+    pub fn last_slot(&self, now: i64) -> i64 {
+        match *self {
+            Schedule::Every(seconds) => now - now.rem_euclid(seconds),
+            Schedule::Daily(minute) => {
+                let slot = now - now.rem_euclid(DAY) + minute * MINUTE;
+                if slot > now { slot - DAY } else { slot }
+            }
+            Schedule::Weekly(weekday, minute) => {
+                let day = now.div_euclid(DAY);
+                // 1970-01-01 was a Thursday
+                let today = (day + 3).rem_euclid(7);
+                let slot = (day - (today - weekday).rem_euclid(7)) * DAY + minute * MINUTE;
+                if slot > now { slot - WEEK } else { slot }
+            }
+        }
+    }
+}
+
+/// Regenerate a view and send it to its delivery target
+pub async fn deliver(db: &Database, name: &str) -> anyhow::Result<()> {
+    let definition = read_definition(&db.root, name).await?;
+    let delivery = definition
+        .delivery
+        .ok_or_else(|| anyhow::anyhow!("View '{}' has no DELIVER TO target", name))?;
+    let target = db.config.delivery_targets.get(&delivery.target).ok_or_else(|| {
+        anyhow::anyhow!("Delivery target '{}' is not declared under delivery_targets in .mdby/config.yaml", delivery.target)
+    })?;
+
+    super::regenerate_one(db, name).await?;
+    let html = tokio::fs::read_to_string(db.root.join("views").join(name).join("index.html")).await?;
+    send(&db.root, target, name, html)
+        .await
+        .map_err(|e| anyhow::anyhow!("Delivering view '{}' to '{}' failed: {}", name, delivery.target, e))
+}
+
+/// Deliver every scheduled view whose schedule has come round since its last
+/// delivery, as of `now` (seconds since the epoch)
+///
+/// Returns the views attempted, with the outcome of each.
+pub async fn deliver_due(db: &Database, now: i64) -> anyhow::Result<Vec<(String, anyhow::Result<()>)>> {
+    let mut state = load_state(&db.root);
+    let mut attempted = Vec::new();
+
+    for (name, schedule) in scheduled_views(&db.root).await? {
+        let slot = schedule.last_slot(now);
+        match state.get(&name) {
+            // Seen for the first time: wait for the next slot
+            None => {
+                state.insert(name, slot);
+            }
+            Some(&last) if slot > last => {
+                // Recorded even on failure, so a broken target isn't retried until the next slot
+                state.insert(name.clone(), slot);
+                let result = deliver(db, &name).await;
+                attempted.push((name, result));
+            }
+            Some(_) => {}
+        }
+    }
+
+    save_state(&db.root, &state)?;
+    Ok(attempted)
+}
+
+/// Views with a delivery schedule
+async fn scheduled_views(root: &Path) -> anyhow::Result<Vec<(String, Schedule)>> {
+    let dir = root.join(".mdby").join("views");
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut views = Vec::new();
+    let mut entries = tokio::fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if !super::is_view_definition(&path) {
+            continue;
+        }
+        let definition: ViewDefinition = serde_yaml::from_str(&tokio::fs::read_to_string(&path).await?)?;
+        let Some(schedule) = definition.delivery.and_then(|d| d.schedule) else {
+            continue;
+        };
+        match schedule.parse() {
+            Ok(schedule) => views.push((definition.name, schedule)),
+            Err(e) => tracing::error!("View '{}': {}", definition.name, e),
+        }
+    }
+    views.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(views)
+}
+
+async fn read_definition(root: &Path, name: &str) -> anyhow::Result<ViewDefinition> {
+    let path = root.join(".mdby").join("views").join(format!("{}.yaml", name));
+    if !path.exists() {
+        anyhow::bail!("View '{}' does not exist", name);
+    }
+    Ok(serde_yaml::from_str(&tokio::fs::read_to_string(&path).await?)?)
+}
+
+/// Send a view's HTML to a target
+async fn send(root: &Path, target: &DeliveryTarget, view: &str, html: String) -> anyhow::Result<()> {
+    match target {
+        DeliveryTarget::Webhook { webhook } => {
+            let url = webhook.clone();
+            let view = view.to_string();
+            tokio::task::spawn_blocking(move || {
+                ureq::post(&url)
+                    .set("Content-Type", "text/html; charset=utf-8")
+                    .set("X-Mdby-View", &view)
+                    .send_string(&html)
+                    .map(|_| ())
+                    .map_err(|e| anyhow::anyhow!(e))
+            })
+            .await??;
+            Ok(())
+        }
+        DeliveryTarget::Command { command, args, to } => {
+            let mut input = String::new();
+            if let Some(to) = to {
+                input.push_str(&format!(
+                    "To: {}\nSubject: {}\nMIME-Version: 1.0\nContent-Type: text/html; charset=utf-8\n\n",
+                    to, view
+                ));
+            }
+            input.push_str(&html);
+
+            let mut child = Command::new(command)
+                .args(args)
+                .current_dir(root)
+                .env("MDBY_VIEW", view)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()?;
+            let mut stdin = child.stdin.take().ok_or_else(|| anyhow::anyhow!("stdin unavailable"))?;
+            let writer = tokio::spawn(async move {
+                stdin.write_all(input.as_bytes()).await?;
+                stdin.shutdown().await
+            });
+            let output = child.wait_with_output().await?;
+            writer.await??;
+
+            if !output.status.success() {
+                anyhow::bail!("{} ({})", String::from_utf8_lossy(&output.stderr).trim(), output.status);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn state_path(root: &Path) -> PathBuf {
+    root.join(".mdby").join("logs").join("deliveries.json")
+}
+
+/// Last delivered slot of each scheduled view
+fn load_state(root: &Path) -> BTreeMap<String, i64> {
+    std::fs::read_to_string(state_path(root))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_state(root: &Path, state: &BTreeMap<String, i64>) -> anyhow::Result<()> {
+    let path = state_path(root);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+        let gitignore = dir.join(".gitignore");
+        if !gitignore.exists() {
+            std::fs::write(&gitignore, "*\n")?;
+        }
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(state)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-01-15 (a Monday) at 06:30 UTC
+    const MONDAY_0630: i64 = 1_705_300_200;
+
+    #[test]
+    fn test_parse_schedules() {
+        assert_eq!("hourly".parse::<Schedule>().unwrap(), Schedule::Every(3600));
+        assert_eq!("every 15m".parse::<Schedule>().unwrap(), Schedule::Every(900));
+        assert_eq!("Every 2h".parse::<Schedule>().unwrap(), Schedule::Every(7200));
+        assert_eq!("daily 07:00".parse::<Schedule>().unwrap(), Schedule::Daily(420));
+        assert_eq!("weekly Friday 17:30".parse::<Schedule>().unwrap(), Schedule::Weekly(4, 1050));
+        for invalid in ["", "daily", "daily 25:00", "every 0m", "every 5d", "every 5é", "weekly someday 07:00"] {
+            assert!(invalid.parse::<Schedule>().is_err(), "{}", invalid);
+        }
+
+        // Intervals too long to count in seconds are errors, not overflows
+        let too_long = format!("every {}h", i64::MAX / 60);
+        assert!(too_long.parse::<Schedule>().unwrap_err().to_string().contains("too long"));
+        let too_long = format!("every {}m", i64::MAX);
+        assert!(too_long.parse::<Schedule>().unwrap_err().to_string().contains("too long"));
+    }
+
+    #[test]
+    fn test_last_slot() {
+        let midnight = MONDAY_0630 - 390 * MINUTE;
+        assert_eq!(Schedule::Every(3600).last_slot(MONDAY_0630), MONDAY_0630 - 30 * MINUTE);
+        // Today's 07:00 hasn't come yet, so the last slot was yesterday's
+        assert_eq!(Schedule::Daily(420).last_slot(MONDAY_0630), midnight - DAY + 420 * MINUTE);
+        assert_eq!(Schedule::Daily(360).last_slot(MONDAY_0630), midnight + 360 * MINUTE);
+        assert_eq!(Schedule::Weekly(0, 360).last_slot(MONDAY_0630), midnight + 360 * MINUTE);
+        assert_eq!(Schedule::Weekly(0, 420).last_slot(MONDAY_0630), midnight - WEEK + 420 * MINUTE);
+        assert_eq!(Schedule::Weekly(4, 0).last_slot(MONDAY_0630), midnight - 3 * DAY);
+    }
+}
@@ -0,0 +1,225 @@
+//! Generated database documentation (`mdby docs`)
+//!
+//! Renders a single HTML page describing the database — collections and their
+//! schemas, views and their queries, and recent commits — to
+//! `/views/_docs/index.html`. View names can't start with an underscore, so the
+//! output never collides with a user view. Once generated, the page is
+//! refreshed along with the other views.
+
+use std::path::PathBuf;
+use tera::Context;
+use tokio::fs;
+
+use super::regenerate::ViewDefinition;
+use super::TemplateEngine;
+use crate::storage::collection::Collection;
+use crate::Database;
+
+/// Number of commits listed under recent activity
+const RECENT_COMMITS: usize = 20;
+
+/// Directory (under `/views/`) that holds the generated docs
+pub const DOCS_DIR: &str = "_docs";
+
+/// Path of the generated docs page
+pub fn docs_path(db: &Database) -> PathBuf {
+    db.root.join("views").join(DOCS_DIR).join("index.html")
+}
+
+/// Generate the documentation page, returning its path
+pub async fn generate_docs(db: &Database) -> anyhow::Result<PathBuf> {
+    let mut context = Context::new();
+    context.insert("name", &db.config.name);
+    context.insert("description", &db.config.description);
+    context.insert("template", &db.config.template);
+    context.insert("collections", &collections_context(db).await?);
+    context.insert("views", &views_context(db).await?);
+    context.insert("commits", &db.git.log(RECENT_COMMITS)?);
+
+    let mut engine = TemplateEngine::empty();
+    engine.add_template("docs.html", DOCS_TEMPLATE)?;
+    let html = engine.render_context("docs.html", &context)?;
+
+    let path = docs_path(db);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(&path, html).await?;
+
+    tracing::info!("Generated docs: {:?}", path);
+
+    Ok(path)
+}
+
+async fn collections_context(db: &Database) -> anyhow::Result<Vec<serde_json::Value>> {
+    let collections_path = db.root.join("collections");
+    let mut names = Vec::new();
+
+    if collections_path.exists() {
+        let mut entries = fs::read_dir(&collections_path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                if let Some(name) = entry.file_name().to_str() {
+                    names.push(name.to_string());
+                }
+            }
+        }
+    }
+    names.sort();
+
+    let mut collections = Vec::new();
+    for name in names {
+        let collection = Collection::open(&name, &db.root);
+        let count = collection.count().await?;
+        let readme = collection.meta().await?.map(|meta| meta.body);
+        let schema = db.schema.get(&name);
+
+        let fields: Vec<serde_json::Value> = schema
+            .map(|schema| {
+                schema
+                    .fields
+                    .iter()
+                    .map(|(field_name, def)| {
+                        serde_json::json!({
+                            "name": field_name,
+                            "type": crate::query::fieldtype_to_datatype(&def.field_type).to_string(),
+                            "required": def.required,
+                            "unique": def.unique,
+                            "indexed": def.indexed,
+                            "default": def.default.as_ref().map(|v| {
+                                serde_yaml::to_string(v).unwrap_or_default().trim_end().to_string()
+                            }),
+                            "description": def.description,
+                            "label": def.display_name(db.config.locale.as_deref()),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        collections.push(serde_json::json!({
+            "name": name,
+            "count": count,
+            "description": schema.and_then(|s| s.description.clone()),
+            "strict": schema.map(|s| s.strict).unwrap_or(false),
+            "readme": readme,
+            "fields": fields,
+            "unique": schema.map(|s| s.unique.clone()).unwrap_or_default(),
+        }));
+    }
+
+    Ok(collections)
+}
+
+async fn views_context(db: &Database) -> anyhow::Result<Vec<serde_json::Value>> {
+    let views_path = db.root.join(".mdby").join("views");
+    let mut views = Vec::new();
+
+    if views_path.exists() {
+        let mut entries = fs::read_dir(&views_path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if super::is_view_definition(&path) {
+                let content = fs::read_to_string(&path).await?;
+                let view_def: ViewDefinition = serde_yaml::from_str(&content)?;
+                let query: mdql::SelectStmt = serde_json::from_value(view_def.query)?;
+
+                views.push(serde_json::json!({
+                    "name": view_def.name,
+                    "query": query.to_string(),
+                    "template": view_def.template,
+                }));
+            }
+        }
+    }
+
+    views.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+    Ok(views)
+}
+
+const DOCS_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>{{ name | default(value="MDBY database") }}</title>
+    <style>
+        body { font-family: system-ui, sans-serif; max-width: 960px; margin: 2rem auto; padding: 0 1rem; }
+        nav a { margin-right: 1rem; }
+        section { border-bottom: 1px solid #eee; padding: 1rem 0; }
+        table { border-collapse: collapse; width: 100%; margin-top: 0.5rem; }
+        th, td { text-align: left; padding: 0.3rem 0.6rem; border-bottom: 1px solid #eee; vertical-align: top; }
+        code, pre { background: #f6f8fa; border-radius: 4px; }
+        pre { padding: 0.75rem; overflow-x: auto; }
+        .meta { color: #666; font-size: 0.9rem; }
+    </style>
+</head>
+<body>
+    <h1>{{ name | default(value="MDBY database") }}</h1>
+    {% if description %}<p>{{ description }}</p>{% endif %}
+    {% if template %}<p class="meta">Created from the <code>{{ template }}</code> template</p>{% endif %}
+
+    <nav>
+        <a href="#collections">Collections ({{ collections | length }})</a>
+        <a href="#views">Views ({{ views | length }})</a>
+        <a href="#activity">Recent activity</a>
+    </nav>
+
+    <h2 id="collections">Collections</h2>
+    {% for collection in collections %}
+    <section id="collection-{{ collection.name }}">
+        <h3>{{ collection.name }}</h3>
+        <p class="meta">{{ collection.count }} document(s){% if collection.strict %} &middot; strict{% endif %}</p>
+        {% if collection.description %}<p>{{ collection.description }}</p>{% endif %}
+        {% if collection.readme %}<div>{{ collection.readme | markdown | safe }}</div>{% endif %}
+        {% if collection.fields %}
+        <table>
+            <tr><th>Field</th><th>Type</th><th>Constraints</th><th>Description</th></tr>
+            {% for field in collection.fields %}
+            <tr>
+                <td><code>{{ field.name }}</code>{% if field.label %} {{ field.label }}{% endif %}</td>
+                <td><code>{{ field.type }}</code></td>
+                <td>
+                    {% if field.required %}REQUIRED {% endif %}
+                    {% if field.unique %}UNIQUE {% endif %}
+                    {% if field.indexed %}INDEXED {% endif %}
+                    {% if field.default %}DEFAULT <code>{{ field.default }}</code>{% endif %}
+                </td>
+                <td>{{ field.description | default(value="") }}</td>
+            </tr>
+            {% endfor %}
+        </table>
+        {% for fields in collection.unique %}
+        <p class="meta">UNIQUE (<code>{{ fields | join(sep=", ") }}</code>)</p>
+        {% endfor %}
+        {% else %}
+        <p class="meta">No schema</p>
+        {% endif %}
+    </section>
+    {% else %}
+    <p class="meta">No collections</p>
+    {% endfor %}
+
+    <h2 id="views">Views</h2>
+    {% for view in views %}
+    <section id="view-{{ view.name }}">
+        <h3><a href="../{{ view.name }}/index.html">{{ view.name }}</a></h3>
+        <pre><code>{{ view.query }}</code></pre>
+        {% if view.template %}<p class="meta">Template: <code>{{ view.template }}</code></p>{% endif %}
+    </section>
+    {% else %}
+    <p class="meta">No views</p>
+    {% endfor %}
+
+    <h2 id="activity">Recent activity</h2>
+    <table>
+        {% for commit in commits %}
+        <tr>
+            <td><code>{{ commit.id | truncate(length=7, end="") }}</code></td>
+            <td>{{ commit.summary }}</td>
+            <td class="meta">{{ commit.author }}</td>
+            <td class="meta">{{ commit.time | date(format="%Y-%m-%d %H:%M") }}</td>
+        </tr>
+        {% endfor %}
+    </table>
+</body>
+</html>"##;
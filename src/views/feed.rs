@@ -0,0 +1,195 @@
+//! RSS and Atom feeds of a view (`FORMATS ('html', 'rss')`)
+//!
+//! A feed has an entry per document the view's query returns, in its order.
+//! Each entry takes its title from `title` (else the document ID), its date
+//! from `date` and its content from the body rendered as HTML; a view's
+//! `FEED (title = headline, date = published, body = summary, link = url)`
+//! takes them from other fields instead. Dates may be DATEs or DATETIMEs;
+//! entries without one have no publication date (RSS) or the feed's latest
+//! one (Atom).
+//!
+//! Links are made absolute with `site_url` from the database config, the
+//! URL `views/` is published at: a view's feed links to
+//! `{site_url}/{view}/`, and an entry without a `link` field to its
+//! document's anchor there.
+
+use chrono::{DateTime, FixedOffset, NaiveDate};
+
+use super::markdown::{self, MarkdownOptions};
+use super::regenerate::markdown_value;
+use super::OutputFormat;
+use crate::config::Config;
+use crate::storage::document::{Document, Value};
+use mdql::FeedFields;
+
+/// One entry of a feed
+struct Entry {
+    id: String,
+    title: String,
+    link: String,
+    date: Option<DateTime<FixedOffset>>,
+    /// Content as HTML
+    content: String,
+}
+
+/// A view's documents as an RSS 2.0 or Atom feed
+pub(super) fn generate(
+    format: OutputFormat,
+    view: &str,
+    docs: &[Document],
+    fields: &FeedFields,
+    config: &Config,
+    options: MarkdownOptions,
+) -> String {
+    let site = config.site_url.as_deref().map(|url| url.trim_end_matches('/'));
+    let link = format!("{}/{}/", site.unwrap_or_default(), view);
+    let title = match &config.name {
+        Some(name) => format!("{}: {}", name, view),
+        None => view.to_string(),
+    };
+    let entries: Vec<Entry> = docs.iter().map(|doc| entry(doc, fields, &link, site, options)).collect();
+
+    match format {
+        OutputFormat::Atom => atom(view, &title, &link, &entries),
+        _ => {
+            let description = config.description.clone().unwrap_or_else(|| format!("Documents in the {} view", view));
+            rss(&title, &description, &link, &entries)
+        }
+    }
+}
+
+fn entry(doc: &Document, fields: &FeedFields, view_link: &str, site: Option<&str>, options: MarkdownOptions) -> Entry {
+    let text = |field: &str| {
+        doc.get_path(field)
+            .filter(|value| !matches!(value, Value::Null))
+            .map(|value| markdown_value(&value))
+    };
+    let title = text(fields.title.as_deref().unwrap_or("title")).unwrap_or_else(|| doc.id.clone());
+    let date = text(fields.date.as_deref().unwrap_or("date")).and_then(|date| parse_date(&date));
+    let body = match &fields.body {
+        Some(field) => text(field).unwrap_or_default(),
+        None => doc.body.clone(),
+    };
+    let link = match fields.link.as_deref().and_then(text) {
+        Some(link) if link.contains("://") => link,
+        Some(link) => format!("{}/{}", site.unwrap_or_default(), link.trim_start_matches('/')),
+        None => format!("{}#{}", view_link, doc.id),
+    };
+
+    Entry { id: doc.id.clone(), title, link, date, content: markdown::render(&body, options) }
+}
+
+/// A DATETIME, or a DATE as midnight UTC
+fn parse_date(text: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(text).ok().or_else(|| {
+        let midnight = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0)?;
+        Some(midnight.and_utc().fixed_offset())
+    })
+}
+
+fn rss(title: &str, description: &str, link: &str, entries: &[Entry]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rss version=\"2.0\">\n<channel>\n");
+    out.push_str(&format!("  <title>{}</title>\n", escape(title)));
+    out.push_str(&format!("  <link>{}</link>\n", escape(link)));
+    out.push_str(&format!("  <description>{}</description>\n", escape(description)));
+    if let Some(latest) = entries.iter().filter_map(|entry| entry.date).max() {
+        out.push_str(&format!("  <lastBuildDate>{}</lastBuildDate>\n", latest.to_rfc2822()));
+    }
+    for entry in entries {
+        out.push_str("  <item>\n");
+        out.push_str(&format!("    <title>{}</title>\n", escape(&entry.title)));
+        out.push_str(&format!("    <link>{}</link>\n", escape(&entry.link)));
+        out.push_str(&format!("    <guid isPermaLink=\"false\">{}</guid>\n", escape(&entry.id)));
+        if let Some(date) = entry.date {
+            out.push_str(&format!("    <pubDate>{}</pubDate>\n", date.to_rfc2822()));
+        }
+        out.push_str(&format!("    <description>{}</description>\n", escape(&entry.content)));
+        out.push_str("  </item>\n");
+    }
+    out.push_str("</channel>\n</rss>\n");
+    out
+}
+
+fn atom(view: &str, title: &str, link: &str, entries: &[Entry]) -> String {
+    // Atom needs IRIs for IDs, which relative links are not
+    let absolute = link.contains("://");
+    let feed_id = if absolute { link.to_string() } else { format!("urn:mdby:view:{}", view) };
+    let updated = entries
+        .iter()
+        .filter_map(|entry| entry.date)
+        .max()
+        .unwrap_or_else(|| DateTime::UNIX_EPOCH.fixed_offset());
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    out.push_str(&format!("  <id>{}</id>\n", escape(&feed_id)));
+    out.push_str(&format!("  <title>{}</title>\n", escape(title)));
+    out.push_str(&format!("  <link href=\"{}\"/>\n", escape(link)));
+    out.push_str(&format!("  <updated>{}</updated>\n", updated.to_rfc3339()));
+    out.push_str("  <author><name>mdby</name></author>\n");
+    for entry in entries {
+        let id = if entry.link.contains("://") {
+            entry.link.clone()
+        } else {
+            format!("urn:mdby:view:{}:{}", view, entry.id)
+        };
+        out.push_str("  <entry>\n");
+        out.push_str(&format!("    <id>{}</id>\n", escape(&id)));
+        out.push_str(&format!("    <title>{}</title>\n", escape(&entry.title)));
+        out.push_str(&format!("    <link href=\"{}\"/>\n", escape(&entry.link)));
+        out.push_str(&format!("    <updated>{}</updated>\n", entry.date.unwrap_or(updated).to_rfc3339()));
+        out.push_str(&format!("    <content type=\"html\">{}</content>\n", escape(&entry.content)));
+        out.push_str("  </entry>\n");
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+/// Text escaped for XML content and attribute values
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(id: &str, fields: &[(&str, &str)], body: &str) -> Document {
+        let mut doc = Document::new(id).with_body(body);
+        for (name, value) in fields {
+            doc.fields.insert(name.to_string(), Value::String(value.to_string()));
+        }
+        doc
+    }
+
+    #[test]
+    fn test_rss() {
+        let docs = vec![post("hello", &[("title", "Hello & welcome"), ("date", "2024-03-01")], "Some *text*")];
+        let config = Config { site_url: Some("https://blog.example.com/".into()), ..Config::default() };
+        let rss = generate(OutputFormat::Rss, "posts", &docs, &FeedFields::default(), &config, MarkdownOptions::default());
+        assert!(rss.contains("<link>https://blog.example.com/posts/</link>"));
+        assert!(rss.contains("<title>Hello &amp; welcome</title>"));
+        assert!(rss.contains("<link>https://blog.example.com/posts/#hello</link>"));
+        assert!(rss.contains("<pubDate>Fri, 1 Mar 2024 00:00:00 +0000</pubDate>"));
+        assert!(rss.contains("<description>&lt;p&gt;Some &lt;em&gt;text&lt;/em&gt;&lt;/p&gt;\n</description>"));
+    }
+
+    #[test]
+    fn test_atom_mapped_fields() {
+        let docs = vec![
+            post("a", &[("headline", "First"), ("published", "2024-03-01T09:00:00+01:00"), ("url", "/p/a")], ""),
+            post("b", &[("headline", "Second")], ""),
+        ];
+        let fields = FeedFields {
+            title: Some("headline".into()),
+            date: Some("published".into()),
+            link: Some("url".into()),
+            ..FeedFields::default()
+        };
+        let atom = generate(OutputFormat::Atom, "posts", &docs, &fields, &Config::default(), MarkdownOptions::default());
+        assert!(atom.contains("<id>urn:mdby:view:posts</id>"));
+        assert!(atom.contains("<updated>2024-03-01T09:00:00+01:00</updated>\n  <author>"));
+        assert!(atom.contains("<title>First</title>\n    <link href=\"/p/a\"/>"));
+        assert!(atom.contains("<id>urn:mdby:view:posts:b</id>"));
+        assert_eq!(atom.matches("<updated>2024-03-01T09:00:00+01:00</updated>").count(), 3);
+    }
+}
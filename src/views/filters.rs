@@ -0,0 +1,135 @@
+//! Filters for view templates, besides `markdown` and Tera's own
+//!
+//! - `date(format="%B %-d, %Y")` formats a date, a datetime or a Unix
+//!   timestamp with chrono's strftime syntax, `%Y-%m-%d` by default. Values
+//!   that aren't dates are left as they are, so one odd frontmatter value
+//!   doesn't fail the whole view.
+//! - `excerpt(words=50)` is the start of a markdown body as plain text, with
+//!   `…` where it was cut.
+//! - `slugify` makes the same slugs as derived document IDs (see
+//!   [`crate::slug`]).
+//! - `wordcount` counts the words of a markdown body's text, without its
+//!   markup.
+
+use std::collections::HashMap;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime};
+use tera::{Tera, Value};
+
+use super::markdown;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+const DEFAULT_EXCERPT_WORDS: usize = 50;
+
+/// Register the filters, replacing Tera's filters of the same names
+pub(super) fn register(tera: &mut Tera) {
+    tera.register_filter("date", date);
+    tera.register_filter("excerpt", excerpt);
+    tera.register_filter("slugify", slugify);
+    tera.register_filter("wordcount", wordcount);
+}
+
+fn date(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let format = match args.get("format") {
+        Some(format) => format.as_str().ok_or_else(|| tera::Error::msg("Filter `date`: `format` must be a string"))?,
+        None => DEFAULT_DATE_FORMAT,
+    };
+    let items: Vec<Item> = StrftimeItems::new(format).collect();
+    if items.contains(&Item::Error) {
+        return Err(tera::Error::msg(format!("Filter `date`: invalid format '{}'", format)));
+    }
+    Ok(match parse_datetime(value) {
+        Some(datetime) => Value::String(datetime.format_with_items(items.into_iter()).to_string()),
+        None => value.clone(),
+    })
+}
+
+/// A date, datetime or Unix timestamp; datetimes without an offset are UTC
+fn parse_datetime(value: &Value) -> Option<DateTime<FixedOffset>> {
+    let utc = |naive: NaiveDateTime| naive.and_utc().fixed_offset();
+    match value {
+        Value::Number(secs) => DateTime::from_timestamp(secs.as_i64()?, 0).map(|datetime| datetime.fixed_offset()),
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .ok()
+            .or_else(|| {
+                ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+                    .iter()
+                    .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+                    .map(utc)
+            })
+            .or_else(|| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().and_then(|day| day.and_hms_opt(0, 0, 0)).map(utc)),
+        _ => None,
+    }
+}
+
+fn excerpt(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let limit = match args.get("words") {
+        Some(words) => words
+            .as_u64()
+            .ok_or_else(|| tera::Error::msg("Filter `excerpt`: `words` must be a positive integer"))? as usize,
+        None => DEFAULT_EXCERPT_WORDS,
+    };
+    let text = markdown::plain_text(value.as_str().unwrap_or(""));
+    let words: Vec<&str> = text.split_whitespace().collect();
+    Ok(Value::String(if words.len() > limit {
+        format!("{}…", words[..limit].join(" "))
+    } else {
+        text
+    }))
+}
+
+fn slugify(value: &Value, _args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    };
+    Ok(Value::String(crate::slug::slugify(&text)))
+}
+
+fn wordcount(value: &Value, _args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = markdown::plain_text(value.as_str().unwrap_or(""));
+    Ok(Value::from(text.split_whitespace().count()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, context: serde_json::Value) -> tera::Result<String> {
+        let mut tera = Tera::default();
+        register(&mut tera);
+        tera.render_str(template, &tera::Context::from_value(context).unwrap())
+    }
+
+    #[test]
+    fn test_date() {
+        let context = serde_json::json!({
+            "day": "2024-03-18",
+            "at": "2024-03-18T09:30:00+02:00",
+            "stamp": 1710754200,
+            "odd": "soon",
+        });
+        assert_eq!(render("{{ day | date }}", context.clone()).unwrap(), "2024-03-18");
+        assert_eq!(render(r#"{{ day | date(format="%B %-d, %Y") }}"#, context.clone()).unwrap(), "March 18, 2024");
+        assert_eq!(render(r#"{{ at | date(format="%H:%M %z") }}"#, context.clone()).unwrap(), "09:30 +0200");
+        assert_eq!(render(r#"{{ stamp | date(format="%Y-%m-%d %H:%M") }}"#, context.clone()).unwrap(), "2024-03-18 09:30");
+        assert_eq!(render("{{ odd | date }}", context.clone()).unwrap(), "soon");
+        assert!(render(r#"{{ day | date(format="%Q") }}"#, context).is_err());
+    }
+
+    #[test]
+    fn test_excerpt_and_wordcount() {
+        let context = serde_json::json!({ "body": "# Title\n\nSome **bold** text and a [link](https://example.com).\n\n- one\n- two" });
+        assert_eq!(render("{{ body | wordcount }}", context.clone()).unwrap(), "9");
+        assert_eq!(render("{{ body | excerpt(words=4) }}", context.clone()).unwrap(), "Title Some bold text…");
+        assert_eq!(render("{{ body | excerpt }}", context).unwrap(), "Title Some bold text and a link. one two");
+    }
+
+    #[test]
+    fn test_slugify() {
+        let context = serde_json::json!({ "title": "Crème brûlée, again!" });
+        assert_eq!(render("{{ title | slugify }}", context).unwrap(), "creme-brulee-again");
+    }
+}
@@ -0,0 +1,134 @@
+//! External render hooks (`RENDER WITH name`)
+//!
+//! A view can pipe each document body through a command declared under
+//! `render_hooks` in `/.mdby/config.yaml` (pandoc, mermaid-cli, ...). The
+//! command's output is exposed to templates as `doc.rendered`.
+//!
+//! Output is cached in `/.mdby/cache/render/`, keyed on a hash of the
+//! command, its arguments and the body, so unchanged documents aren't
+//! re-rendered. The cache directory ignores itself in git.
+
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::config::RenderHook;
+use crate::storage::document::Document;
+
+/// Render each document body with a hook, in document order
+pub async fn render_bodies(root: &Path, hook: &RenderHook, docs: &[Document]) -> anyhow::Result<Vec<String>> {
+    let cache_dir = cache_dir(root);
+    fs::create_dir_all(&cache_dir).await?;
+    let gitignore = cache_dir.join(".gitignore");
+    if !gitignore.exists() {
+        fs::write(&gitignore, "*\n").await?;
+    }
+
+    let mut rendered = Vec::with_capacity(docs.len());
+    for doc in docs {
+        let cached = cache_dir.join(format!("{}.html", cache_key(hook, &doc.body)));
+        if cached.exists() {
+            rendered.push(fs::read_to_string(&cached).await?);
+            continue;
+        }
+
+        let html = run(hook, &doc.body)
+            .await
+            .map_err(|e| anyhow::anyhow!("Render hook '{}' failed for '{}': {}", hook.command, doc.id, e))?;
+        fs::write(&cached, &html).await?;
+        rendered.push(html);
+    }
+
+    Ok(rendered)
+}
+
+/// Directory holding cached hook output
+pub fn cache_dir(root: &Path) -> PathBuf {
+    root.join(".mdby").join("cache").join("render")
+}
+
+/// Hex SHA-256 of the command line and body
+fn cache_key(hook: &RenderHook, body: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(hook.command.as_bytes());
+    for arg in &hook.args {
+        hasher.update([0]);
+        hasher.update(arg.as_bytes());
+    }
+    hasher.update([0, 0]);
+    hasher.update(body.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Run the hook with the body on stdin, returning its stdout
+async fn run(hook: &RenderHook, body: &str) -> anyhow::Result<String> {
+    let mut child = Command::new(&hook.command)
+        .args(&hook.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().ok_or_else(|| anyhow::anyhow!("stdin unavailable"))?;
+    let body = body.to_string();
+    let writer = tokio::spawn(async move {
+        stdin.write_all(body.as_bytes()).await?;
+        stdin.shutdown().await
+    });
+
+    let output = child.wait_with_output().await?;
+    writer.await??;
+
+    if !output.status.success() {
+        anyhow::bail!("{} ({})", String::from_utf8_lossy(&output.stderr).trim(), output.status);
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(command: &str, args: &[&str]) -> RenderHook {
+        RenderHook {
+            command: command.into(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_cache_key_covers_command_and_body() {
+        let tr = hook("tr", &["a-z", "A-Z"]);
+        assert_eq!(cache_key(&tr, "x"), cache_key(&tr, "x"));
+        assert_ne!(cache_key(&tr, "x"), cache_key(&tr, "y"));
+        assert_ne!(cache_key(&tr, "x"), cache_key(&hook("tr", &["a-z"]), "x"));
+    }
+
+    #[tokio::test]
+    async fn test_render_and_cache() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let upper = hook("tr", &["a-z", "A-Z"]);
+        let mut doc = Document::new("a");
+        doc.body = "hello".into();
+
+        let rendered = render_bodies(tmp.path(), &upper, std::slice::from_ref(&doc)).await.unwrap();
+        assert_eq!(rendered, ["HELLO"]);
+
+        // A cached result is used without running the command again
+        let cached = cache_dir(tmp.path()).join(format!("{}.html", cache_key(&upper, "hello")));
+        std::fs::write(&cached, "from cache").unwrap();
+        let rendered = render_bodies(tmp.path(), &upper, &[doc]).await.unwrap();
+        assert_eq!(rendered, ["from cache"]);
+    }
+
+    #[tokio::test]
+    async fn test_failing_hook() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let err = render_bodies(tmp.path(), &hook("false", &[]), &[Document::new("a")]).await.unwrap_err();
+        assert!(err.to_string().contains("Render hook 'false' failed for 'a'"));
+    }
+}
@@ -0,0 +1,248 @@
+//! Markdown rendering for view templates (the `markdown` filter)
+//!
+//! Views created `WITH MERMAID` turn ```` ```mermaid ```` fences into
+//! `<pre class="mermaid">` blocks. Views created `WITH MATH` turn `$...$` and
+//! `$$...$$` outside code into KaTeX `\(...\)` / `\[...\]` spans, encoded so
+//! markdown doesn't treat `_` or `*` inside them as emphasis. [`scripts`]
+//! returns the client-side scripts that render both, which regeneration
+//! injects into the page.
+
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Parser, Tag, TagEnd};
+use std::ops::Range;
+
+use mdql::ViewFeature;
+
+/// Which rendering extensions are enabled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MarkdownOptions {
+    /// Render ```mermaid fences as diagrams
+    pub mermaid: bool,
+    /// Render `$...$` and `$$...$$` as math
+    pub math: bool,
+}
+
+impl MarkdownOptions {
+    /// Options for a view's features
+    pub fn from_features(features: &[ViewFeature]) -> Self {
+        Self {
+            mermaid: features.contains(&ViewFeature::Mermaid),
+            math: features.contains(&ViewFeature::Math),
+        }
+    }
+}
+
+/// Render markdown to HTML
+pub fn render(text: &str, options: MarkdownOptions) -> String {
+    let source = if options.math { protect_math(text) } else { text.to_string() };
+
+    let mut in_mermaid = false;
+    let events = Parser::new(&source).map(|event| match event {
+        Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang)))
+            if options.mermaid && lang.split_whitespace().next() == Some("mermaid") =>
+        {
+            in_mermaid = true;
+            Event::Html(CowStr::Borrowed("<pre class=\"mermaid\">"))
+        }
+        Event::End(TagEnd::CodeBlock) if in_mermaid => {
+            in_mermaid = false;
+            Event::Html(CowStr::Borrowed("</pre>\n"))
+        }
+        Event::Text(text) if in_mermaid => Event::Html(tera::escape_html(&text).into()),
+        other => other,
+    });
+
+    let mut out = String::new();
+    html::push_html(&mut out, events);
+    out
+}
+
+/// Script tags that render the enabled extensions in the browser
+pub fn scripts(options: MarkdownOptions) -> String {
+    let mut out = String::new();
+    if options.mermaid {
+        out.push_str(MERMAID_SCRIPT);
+    }
+    if options.math {
+        out.push_str(KATEX_SCRIPT);
+    }
+    out
+}
+
+/// Insert scripts before `</body>`, or append them if there is none
+pub fn inject_scripts(html: String, scripts: &str) -> String {
+    if scripts.is_empty() {
+        return html;
+    }
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(pos) => format!("{}{}{}", &html[..pos], scripts, &html[pos..]),
+        None => html + scripts,
+    }
+}
+
+/// The text of markdown without its markup, for excerpts and word counts;
+/// blocks and line breaks become single spaces
+pub fn plain_text(text: &str) -> String {
+    let mut out = String::new();
+    for event in Parser::new(text) {
+        match event {
+            Event::Text(text) | Event::Code(text) => out.push_str(&text),
+            Event::End(TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Link | TagEnd::Image) => {}
+            Event::SoftBreak | Event::HardBreak | Event::End(_) => out.push(' '),
+            _ => {}
+        }
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+const MERMAID_SCRIPT: &str = r#"<script type="module">
+import mermaid from "https://cdn.jsdelivr.net/npm/mermaid@10/dist/mermaid.esm.min.mjs";
+mermaid.initialize({ startOnLoad: true });
+</script>
+"#;
+
+const KATEX_SCRIPT: &str = r#"<link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/katex@0.16/dist/katex.min.css">
+<script defer src="https://cdn.jsdelivr.net/npm/katex@0.16/dist/katex.min.js"></script>
+<script defer src="https://cdn.jsdelivr.net/npm/katex@0.16/dist/contrib/auto-render.min.js"
+    onload="renderMathInElement(document.body, { delimiters: [
+        { left: '\\[', right: '\\]', display: true },
+        { left: '\\(', right: '\\)', display: false }
+    ] })"></script>
+"#;
+
+/// Replace math outside code with encoded `\(...\)` / `\[...\]` spans
+fn protect_math(text: &str) -> String {
+    let code = code_ranges(text);
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < text.len() {
+        if let Some(range) = code.iter().find(|r| r.contains(&i)) {
+            out.push_str(&text[i..range.end]);
+            i = range.end;
+            continue;
+        }
+
+        let rest = &text[i..];
+        if rest.starts_with("\\$") {
+            out.push_str("\\$");
+            i += 2;
+        } else if let Some(inner) = rest.strip_prefix("$$") {
+            match inner.find("$$").filter(|end| !inner[..*end].trim().is_empty()) {
+                Some(end) => {
+                    out.push_str(&math_span(&inner[..end], true));
+                    i += end + 4;
+                }
+                None => {
+                    out.push_str("$$");
+                    i += 2;
+                }
+            }
+        } else if let Some(inner) = rest.strip_prefix('$') {
+            match inline_math_end(inner) {
+                Some(end) => {
+                    out.push_str(&math_span(&inner[..end], false));
+                    i += end + 2;
+                }
+                None => {
+                    out.push('$');
+                    i += 1;
+                }
+            }
+        } else {
+            let ch = rest.chars().next().unwrap_or_default();
+            out.push(ch);
+            i += ch.len_utf8();
+        }
+    }
+
+    out
+}
+
+/// End of `$...$` content: no space just inside either `$`, one line,
+/// and the closing `$` isn't followed by a digit (so `$5 and $10` is text)
+fn inline_math_end(inner: &str) -> Option<usize> {
+    if inner.starts_with(char::is_whitespace) || inner.starts_with('$') {
+        return None;
+    }
+
+    let line = &inner[..inner.find('\n').unwrap_or(inner.len())];
+    let mut prev = None;
+    for (pos, ch) in line.char_indices() {
+        if ch == '$' && pos > 0 && prev != Some('\\') && !prev.is_some_and(char::is_whitespace) {
+            let next = line[pos + 1..].chars().next();
+            return (!next.is_some_and(|c| c.is_ascii_digit())).then_some(pos);
+        }
+        prev = Some(ch);
+    }
+    None
+}
+
+/// A math span with every ASCII punctuation character entity-encoded
+fn math_span(tex: &str, display: bool) -> String {
+    let (class, open, close) = if display {
+        ("math math-display", "\\[", "\\]")
+    } else {
+        ("math math-inline", "\\(", "\\)")
+    };
+
+    let mut span = format!("<span class=\"{}\">", class);
+    for ch in open.chars().chain(tex.trim().chars()).chain(close.chars()) {
+        match ch {
+            '\n' | '\r' => span.push(' '),
+            c if c.is_ascii_punctuation() => span.push_str(&format!("&#{};", c as u32)),
+            c => span.push(c),
+        }
+    }
+    span.push_str("</span>");
+    span
+}
+
+/// Byte ranges of code spans and code blocks
+pub(super) fn code_ranges(text: &str) -> Vec<Range<usize>> {
+    Parser::new(text)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Code(_) | Event::Start(Tag::CodeBlock(_)) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: MarkdownOptions = MarkdownOptions { mermaid: true, math: true };
+
+    #[test]
+    fn test_mermaid_fence() {
+        let html = render("```mermaid\ngraph TD; A-->B\n```\n", ALL);
+        assert_eq!(html, "<pre class=\"mermaid\">graph TD; A--&gt;B\n</pre>\n");
+
+        let plain = render("```mermaid\ngraph\n```\n", MarkdownOptions::default());
+        assert!(plain.contains("<code class=\"language-mermaid\">"));
+    }
+
+    #[test]
+    fn test_inline_and_display_math() {
+        let html = render("Area $a_1 * b_2$ and\n\n$$x^2$$", ALL);
+        assert!(html.contains("<span class=\"math math-inline\">\\(a_1 * b_2\\)</span>"), "{}", html);
+        assert!(html.contains("<span class=\"math math-display\">\\[x^2\\]</span>"), "{}", html);
+        assert!(!html.contains("<em>"));
+    }
+
+    #[test]
+    fn test_math_not_detected() {
+        for text in ["Costs $5 and $10", "`$a$` in code", "a $ b $ c", "escaped \\$x$"] {
+            let html = render(text, ALL);
+            assert!(!html.contains("math"), "{} -> {}", text, html);
+        }
+    }
+
+    #[test]
+    fn test_inject_scripts() {
+        let html = inject_scripts("<html><body>x</body></html>".into(), "<script></script>");
+        assert_eq!(html, "<html><body>x<script></script></body></html>");
+        assert_eq!(inject_scripts("x".into(), "<s>"), "x<s>");
+    }
+}
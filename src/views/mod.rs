@@ -12,6 +12,16 @@
 //!     index.json       # JSON export
 //!   /daily-notes/
 //!     index.html
+//!     index.md         # With FORMATS ('html', 'markdown', 'csv')
+//!     index.csv
+//!   /blog/
+//!     index.html
+//!     feed.xml         # With FORMATS ('html', 'rss') or ('html', 'atom')
+//!   /_docs/
+//!     index.html       # Database reference (`mdby docs`)
+//!   /@dashboard/
+//!     index.html       # Built-in overview (`mdby dashboard`)
+//!     index.json
 //! ```
 //!
 //! # Templates
@@ -26,12 +36,34 @@
 //! </article>
 //! {% endfor %}
 //! ```
+//!
+//! Views created with `RENDER WITH name` also get `doc.rendered`, the body
+//! as rendered by an external command (see [`hooks`]). Views created
+//! `WITH MERMAID` or `WITH MATH` render diagrams and formulas in the
+//! `markdown` filter (see [`markdown`]), and every template has `date`,
+//! `excerpt`, `slugify` and `wordcount` filters too (see `filters.rs`).
+//! `![[other-doc]]` in a body embeds that document's body in the HTML
+//! output (see `transclude.rs`). Templates can extend and include the
+//! others in `/.mdby/templates/` (see `templates.rs`).
 
+mod dashboard;
+pub mod delivery;
+mod docs;
+mod feed;
+mod filters;
+pub mod hooks;
+pub mod markdown;
 mod regenerate;
+mod state;
 mod templates;
+mod transclude;
 
-pub use regenerate::regenerate_all;
-pub use templates::TemplateEngine;
+pub use dashboard::{dashboard_path, generate_dashboard, DASHBOARD_VIEW};
+pub use docs::{docs_path, generate_docs};
+pub use regenerate::{preview_template, rebuild_all, regenerate_all, regenerate_one, regenerate_reading, render_view, RenderedView};
+#[allow(deprecated)]
+pub use regenerate::regenerate_view;
+pub use templates::{add_template, list_templates, remove_template, TemplateEngine, TemplateInfo};
 
 use serde::{Deserialize, Serialize};
 use mdql::SelectStmt;
@@ -50,21 +82,8 @@ pub struct View {
     pub formats: Vec<OutputFormat>,
 }
 
-/// Output format for a view
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum OutputFormat {
-    Html,
-    Json,
-    Markdown,
-    Csv,
-}
-
-impl Default for OutputFormat {
-    fn default() -> Self {
-        Self::Html
-    }
-}
+/// Output format for a view (`FORMATS ('html', 'csv')`)
+pub use mdql::ViewFormat as OutputFormat;
 
 impl View {
     pub fn new(name: impl Into<String>, query: SelectStmt) -> Self {
@@ -81,3 +100,8 @@ impl View {
         self
     }
 }
+
+/// Whether a file in `/.mdby/views/` is a view definition
+pub(crate) fn is_view_definition(path: &std::path::Path) -> bool {
+    path.extension().is_some_and(|e| e == "yaml")
+}
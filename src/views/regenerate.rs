@@ -1,59 +1,561 @@
 //! View regeneration
+//!
+//! Views are regenerated concurrently, bounded by the configured parallelism
+//! (`view_parallelism` in `/.mdby/config.yaml`). Views that read the same
+//! collection share one load of its documents.
+//!
+//! With `auto_regenerate_views: true`, a statement that changes a collection
+//! regenerates just the views reading it ([`regenerate_reading`]).
+//!
+//! [`regenerate_all`] skips views that are up to date with the documents and
+//! templates they were rendered from (see `state.rs`); [`rebuild_all`]
+//! regenerates every view regardless.
 
-use std::path::Path;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tokio::fs;
+use tokio::sync::{OnceCell, Semaphore};
+use tokio::task::JoinSet;
 
-use super::TemplateEngine;
-use crate::storage::collection::Collection;
-use crate::storage::document::Document;
+use super::markdown::{self, MarkdownOptions};
+use super::state;
+use super::{OutputFormat, TemplateEngine};
+use crate::config::{Config, RenderHook};
+use crate::embeddings::{self, EmbeddingProvider};
+use crate::storage::collection::{Collection, CollectionMeta};
+use crate::storage::document::{ordered_keys, Document, Value};
+use crate::schema::SchemaRegistry;
+use crate::system;
 use crate::Database;
-use crate::query::filter;
+use crate::git::Repository;
+use crate::query::related::{self, RelatedIndex};
+use crate::query::{self, filter};
+use mdql::{FeedFields, ViewFeature};
 
-/// Regenerate all views in the database
+/// Related documents listed for each document of a `WITH RELATED` view
+const RELATED_IN_VIEW: usize = 5;
+
+/// Regenerate the views in the database that are out of date
+///
+/// Also refreshes the generated docs page and the dashboard if `mdby docs`
+/// and `mdby dashboard` have been run.
 pub async fn regenerate_all(db: &Database) -> anyhow::Result<()> {
-    let views_def_path = db.root.join(".mdby").join("views");
+    regenerate_every(db, true).await
+}
 
-    if !views_def_path.exists() {
-        return Ok(());
+/// Regenerate all views in the database, whether or not they are out of date
+pub async fn rebuild_all(db: &Database) -> anyhow::Result<()> {
+    regenerate_every(db, false).await
+}
+
+async fn regenerate_every(db: &Database, only_stale: bool) -> anyhow::Result<()> {
+    if super::docs_path(db).exists() {
+        if let Err(e) = super::generate_docs(db).await {
+            tracing::error!("Failed to regenerate docs: {}", e);
+        }
+    }
+    if super::dashboard_path(db).exists() {
+        if let Err(e) = super::generate_dashboard(db).await {
+            tracing::error!("Failed to regenerate dashboard: {}", e);
+        }
+    }
+
+    regenerate_paths(db, view_definitions(db).await?, only_stale).await
+}
+
+/// Regenerate the views that read any of `collections`, in their FROM or a
+/// JOIN or through a view they select from, returning their names
+///
+/// Also refreshes the dashboard if `mdby dashboard` has been run.
+pub async fn regenerate_reading(db: &Database, collections: &BTreeSet<String>) -> anyhow::Result<Vec<String>> {
+    if collections.is_empty() {
+        return Ok(Vec::new());
+    }
+    if super::dashboard_path(db).exists() {
+        if let Err(e) = super::generate_dashboard(db).await {
+            tracing::error!("Failed to regenerate dashboard: {}", e);
+        }
+    }
+
+    let mut names = Vec::new();
+    let mut paths = Vec::new();
+    for path in view_definitions(db).await? {
+        let view_def = read_definition(&path).await?;
+        let query: mdql::SelectStmt = serde_json::from_value(view_def.query)?;
+        if sources(&db.root, query).iter().any(|name| collections.contains(name)) {
+            names.push(view_def.name);
+            paths.push(path);
+        }
+    }
+    regenerate_paths(db, paths, false).await?;
+    names.sort();
+    Ok(names)
+}
+
+/// Collections a view's query reads in its FROM or a JOIN, and those read by
+/// the views it selects from
+fn sources(root: &Path, query: mdql::SelectStmt) -> Vec<String> {
+    let mut queries = vec![query];
+    if let Some(view) = mdql::view_source(&queries[0].from) {
+        queries.extend(query::resolve_view(view, &[], |view| read_view_file(root, view)).unwrap_or_default());
+    }
+    queries
+        .iter()
+        .flat_map(|query| std::iter::once(&query.from).chain(query.joins.iter().map(|join| &join.collection)))
+        .cloned()
+        .collect()
+}
+
+/// What a view's fingerprint covers: its definition file, then those of the
+/// views it selects from, as their changes change its output too
+fn definition_inputs(root: &Path, path: &Path) -> anyhow::Result<Vec<u8>> {
+    let inputs = RefCell::new(std::fs::read(path)?);
+    let query = serde_yaml::from_slice::<ViewDefinition>(&inputs.borrow())
+        .ok()
+        .and_then(|view_def| serde_json::from_value::<mdql::SelectStmt>(view_def.query).ok());
+    if let Some(view) = query.as_ref().and_then(|query| mdql::view_source(&query.from)) {
+        // A broken chain fails when the view is regenerated
+        let _ = query::resolve_view(view, &[], |view| {
+            let content = read_view_file(root, view)?;
+            inputs.borrow_mut().extend(content.iter().flat_map(|content| content.bytes()));
+            Ok(content)
+        });
     }
+    Ok(inputs.into_inner())
+}
 
+/// Definition files of every view (`/.mdby/views/*.yaml`)
+async fn view_definitions(db: &Database) -> anyhow::Result<Vec<PathBuf>> {
+    let views_def_path = db.root.join(".mdby").join("views");
+    let mut paths = Vec::new();
+    if !views_def_path.exists() {
+        return Ok(paths);
+    }
     let mut entries = fs::read_dir(&views_def_path).await?;
     while let Some(entry) = entries.next_entry().await? {
         let path = entry.path();
-        if path.extension().map(|e| e == "yaml").unwrap_or(false) {
-            if let Err(e) = regenerate_view(db, &path).await {
+        if super::is_view_definition(&path) {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+/// Regenerate the views defined in `paths` concurrently, logging failures;
+/// with `only_stale`, just those out of date
+///
+/// Records what each view was regenerated from in `/.mdby/state/views.yaml`.
+async fn regenerate_paths(db: &Database, paths: Vec<PathBuf>, only_stale: bool) -> anyhow::Result<()> {
+    let mut states = state::load(&db.root);
+    let changes = state::Changes::new(&db.git, &states)?;
+    let shared = state::shared_inputs(&db.root, &db.config)?;
+    let cache = DocumentCache::default();
+    let displays = Arc::new(field_displays(db));
+    let config = Arc::new(db.config.clone());
+    let embedder = db.embedding_provider();
+    let semaphore = Arc::new(Semaphore::new(db.config.view_parallelism()));
+    let mut tasks = JoinSet::new();
+
+    for path in paths {
+        let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let fingerprint = state::fingerprint(&shared, &definition_inputs(&db.root, &path)?);
+        let output = db.root.join("views").join(&name);
+        if only_stale && output.exists() && !changes.is_stale(states.get(&name), &fingerprint) {
+            continue;
+        }
+
+        let permit = semaphore.clone().acquire_owned().await?;
+        let root = db.root.clone();
+        let cache = cache.share();
+        let displays = displays.clone();
+        let config = config.clone();
+        let embedder = embedder.clone();
+        tasks.spawn(async move {
+            let result = regenerate_definition(&root, &path, &cache, &displays, &config, embedder.as_deref()).await;
+            drop(permit);
+            (path, name, fingerprint, result, cache.read())
+        });
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        let (path, name, fingerprint, result, reads) = joined?;
+        let recorded = match result {
+            Ok(Some(view_def)) => serde_json::from_value::<mdql::SelectStmt>(view_def.query)
+                .ok()
+                .filter(|query| !query::reads_clock(query))
+                .and_then(|_| changes.record(fingerprint, reads)),
+            Ok(None) => None,
+            Err(e) => {
                 tracing::error!("Failed to regenerate view {:?}: {}", path, e);
+                None
             }
-        }
+        };
+        match recorded {
+            Some(recorded) => states.insert(name, recorded),
+            None => states.remove(&name),
+        };
     }
 
-    Ok(())
+    let definitions = db.root.join(".mdby").join("views");
+    states.retain(|name, _| definitions.join(format!("{}.yaml", name)).exists());
+    state::save(&db.root, &states)
+}
+
+/// Regenerate one view
+pub async fn regenerate_one(db: &Database, name: &str) -> anyhow::Result<()> {
+    if name == super::DASHBOARD_VIEW {
+        return super::generate_dashboard(db).await.map(|_| ());
+    }
+    let path = definition_path(db, name)?;
+    let view_def = read_definition(&path).await?;
+    if !view_def.params.is_empty() {
+        anyhow::bail!(
+            "View '{}' has parameters and is rendered on demand: mdby view render {} --param name=value",
+            name,
+            name
+        );
+    }
+    let embedder = db.embedding_provider();
+    regenerate_definition(
+        &db.root,
+        &path,
+        &DocumentCache::default(),
+        &field_displays(db),
+        &db.config,
+        embedder.as_deref(),
+    )
+    .await
+    .map(|_| ())
 }
 
-/// Regenerate a single view
+/// Regenerate the view defined in `view_def_path`
+///
+/// Views with parameters are skipped, as they are only rendered on demand.
+#[deprecated(note = "use regenerate_one, which takes the view's name and refuses views with parameters")]
 pub async fn regenerate_view(db: &Database, view_def_path: &Path) -> anyhow::Result<()> {
-    let content = fs::read_to_string(view_def_path).await?;
-    let view_def: ViewDefinition = serde_yaml::from_str(&content)?;
+    let embedder = db.embedding_provider();
+    regenerate_definition(
+        &db.root,
+        view_def_path,
+        &DocumentCache::default(),
+        &field_displays(db),
+        &db.config,
+        embedder.as_deref(),
+    )
+    .await
+    .map(|_| ())
+}
+
+/// A view rendered to each of its formats
+#[derive(Debug, Clone)]
+pub struct RenderedView {
+    /// Output of each format, in the order of the view's `FORMATS`
+    pub outputs: Vec<(OutputFormat, String)>,
+}
+
+impl RenderedView {
+    /// The output in one format, if the view is rendered to it
+    pub fn get(&self, format: OutputFormat) -> Option<&str> {
+        self.outputs.iter().find(|(f, _)| *f == format).map(|(_, output)| output.as_str())
+    }
+
+    /// Write each output to `dir` as `index.{extension}` (feeds as
+    /// `feed.xml`), removing outputs of other formats left from an earlier
+    /// definition
+    pub async fn write_to(&self, dir: &Path) -> anyhow::Result<()> {
+        fs::create_dir_all(dir).await?;
+        for (format, output) in &self.outputs {
+            fs::write(dir.join(format.file_name()), output).await?;
+        }
+        for format in OutputFormat::ALL {
+            let path = dir.join(format.file_name());
+            let written = self.outputs.iter().any(|(f, _)| f.file_name() == format.file_name());
+            if !written && path.exists() {
+                fs::remove_file(&path).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Render a view with values for its parameters, without writing it to
+/// `/views/`
+pub async fn render_view(db: &Database, name: &str, params: &BTreeMap<String, String>) -> anyhow::Result<RenderedView> {
+    let view_def = read_definition(&definition_path(db, name)?).await?;
+    let embedder = db.embedding_provider();
+    render(
+        &db.root,
+        &view_def,
+        params,
+        &DocumentCache::default(),
+        &field_displays(db),
+        &db.config,
+        embedder.as_deref(),
+    )
+    .await
+}
+
+/// Render a template from `/.mdby/templates/` with the results of a SELECT,
+/// as a view using it would be rendered, without writing anything
+pub async fn preview_template(db: &Database, template: &str, query: &str) -> anyhow::Result<String> {
+    let mdql::Statement::Select(select) = mdql::parse(query)? else {
+        anyhow::bail!("Preview a template with a SELECT query");
+    };
+    for part in template.split('/') {
+        crate::validation::validate_template_name(part)?;
+    }
+    if !db.root.join(".mdby").join("templates").join(template).is_file() {
+        anyhow::bail!("Template '{}' does not exist", template);
+    }
+    let view_def = ViewDefinition {
+        name: template.to_string(),
+        params: Vec::new(),
+        query: serde_json::to_value(&select)?,
+        template: Some(template.to_string()),
+        formats: vec![OutputFormat::Html],
+        feed: None,
+        features: Vec::new(),
+        render_hook: None,
+        delivery: None,
+    };
+    let embedder = db.embedding_provider();
+    let rendered = render(
+        &db.root,
+        &view_def,
+        &BTreeMap::new(),
+        &DocumentCache::default(),
+        &field_displays(db),
+        &db.config,
+        embedder.as_deref(),
+    )
+    .await?;
+    Ok(rendered.outputs.into_iter().map(|(_, html)| html).collect())
+}
+
+/// Definition file of an existing view
+fn definition_path(db: &Database, name: &str) -> anyhow::Result<PathBuf> {
+    let path = db.root.join(".mdby").join("views").join(format!("{}.yaml", name));
+    if !path.exists() {
+        anyhow::bail!("View '{}' does not exist", name);
+    }
+    Ok(path)
+}
+
+/// A view's definition file; `None` if there is no such view
+fn read_view_file(root: &Path, name: &str) -> anyhow::Result<Option<String>> {
+    let path = root.join(".mdby").join("views").join(format!("{}.yaml", name));
+    Ok(if path.exists() { Some(std::fs::read_to_string(&path)?) } else { None })
+}
+
+async fn read_definition(path: &Path) -> anyhow::Result<ViewDefinition> {
+    Ok(serde_yaml::from_str(&fs::read_to_string(path).await?)?)
+}
+
+/// How a collection's fields are shown in views, from its schema
+#[derive(Debug, Default)]
+struct FieldDisplay {
+    /// Schema field order, for JSON, Markdown and CSV output
+    order: Vec<String>,
+    /// Display names in the configured locale (`collection.labels` in templates)
+    labels: BTreeMap<String, String>,
+}
+
+/// How each collection with a schema shows its fields
+fn field_displays(db: &Database) -> HashMap<String, FieldDisplay> {
+    db.schema
+        .list()
+        .map(|schema| {
+            let display = FieldDisplay { order: schema.field_order(), labels: schema.labels(db.config.locale.as_deref()) };
+            (schema.name.clone(), display)
+        })
+        .collect()
+}
+
+/// A collection's documents, loaded on first use
+type CachedCollection = Arc<OnceCell<Arc<Vec<Document>>>>;
+
+/// Documents loaded during one regeneration pass, keyed by collection
+///
+/// Each collection is read at most once, however many views use it.
+#[derive(Default)]
+pub(super) struct DocumentCache {
+    collections: Arc<Mutex<HashMap<String, CachedCollection>>>,
+    /// Collections read through this handle
+    read: Mutex<BTreeSet<String>>,
+}
+
+impl DocumentCache {
+    /// A handle on the same documents that records its own reads
+    pub(super) fn share(&self) -> Self {
+        Self { collections: self.collections.clone(), read: Mutex::default() }
+    }
+
+    /// Collections read through this handle so far
+    pub(super) fn read(&self) -> BTreeSet<String> {
+        self.read.lock().map(|read| read.clone()).unwrap_or_default()
+    }
 
+    pub(super) async fn get(&self, root: &Path, name: &str) -> anyhow::Result<Arc<Vec<Document>>> {
+        if let Ok(mut read) = self.read.lock() {
+            read.insert(name.to_string());
+        }
+        let cell = self
+            .collections
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Document cache lock poisoned"))?
+            .entry(name.to_string())
+            .or_default()
+            .clone();
+
+        cell.get_or_try_init(|| async {
+            let docs = if system::is_system(name) {
+                system::list(&Repository::open(root)?, name)?
+            } else {
+                let mut docs = Collection::open(name, root).list().await?;
+                if let Some(schema) = SchemaRegistry::load_one(root, name)? {
+                    schema.normalize_all(&mut docs)?;
+                }
+                docs
+            };
+            Ok::<_, anyhow::Error>(Arc::new(docs))
+        })
+        .await
+        .cloned()
+    }
+}
+
+/// Regenerate a single view, returning its definition
+///
+/// Views with parameters are skipped: they are only rendered on demand.
+async fn regenerate_definition(
+    root: &Path,
+    view_def_path: &Path,
+    cache: &DocumentCache,
+    displays: &HashMap<String, FieldDisplay>,
+    config: &Config,
+    embedder: Option<&dyn EmbeddingProvider>,
+) -> anyhow::Result<Option<ViewDefinition>> {
+    let view_def = read_definition(view_def_path).await?;
+    if !view_def.params.is_empty() {
+        return Ok(None);
+    }
+    let rendered = render(root, &view_def, &BTreeMap::new(), cache, displays, config, embedder).await?;
+    rendered.write_to(&root.join("views").join(&view_def.name)).await?;
+
+    tracing::info!("Regenerated view: {}", view_def.name);
+
+    Ok(Some(view_def))
+}
+
+/// Run a view's query, with `params` bound, and render the result as HTML
+/// and JSON
+async fn render(
+    root: &Path,
+    view_def: &ViewDefinition,
+    params: &BTreeMap<String, String>,
+    cache: &DocumentCache,
+    displays: &HashMap<String, FieldDisplay>,
+    config: &Config,
+    embedder: Option<&dyn EmbeddingProvider>,
+) -> anyhow::Result<RenderedView> {
     // Parse the stored query
-    let query: mdql::SelectStmt = serde_json::from_value(view_def.query.clone())?;
+    let mut query: mdql::SelectStmt = serde_json::from_value(view_def.query.clone())?;
+    query::bind_params(&mut query, &view_def.params, params)?;
+    let rows = run_query(root, query, cache, embedder).await?;
+
+    let no_schema = FieldDisplay::default();
+    let display = displays.get(&rows.collection).unwrap_or(&no_schema);
+    let mut outputs = Vec::new();
+    for format in view_def.output_formats() {
+        let output = match format {
+            OutputFormat::Html => render_html(root, view_def, &rows, &display.labels, cache, &config.render_hooks).await?,
+            OutputFormat::Json => generate_json(&rows.docs, &display.order)?,
+            OutputFormat::Markdown => generate_markdown(&view_def.name, &rows.docs, display),
+            OutputFormat::Csv => generate_csv(&rows.docs, &display.order)?,
+            OutputFormat::Rss | OutputFormat::Atom => super::feed::generate(
+                format,
+                &view_def.name,
+                &rows.docs,
+                view_def.feed.as_ref().unwrap_or(&FeedFields::default()),
+                config,
+                MarkdownOptions::from_features(&view_def.features),
+            ),
+        };
+        outputs.push((format, output));
+    }
+    Ok(RenderedView { outputs })
+}
+
+/// The rows of a view's query, with what rendering them needs
+struct QueryRows {
+    /// Collection the rows come from, through any views selected from
+    collection: String,
+    /// Every row of the FROM source, for ranking and `WITH RELATED`
+    source: Arc<Vec<Document>>,
+    docs: Vec<Document>,
+    /// Selected REVERSE_REF columns, with the documents they refer to
+    reverse_ref_columns: Vec<(String, Arc<Vec<Document>>)>,
+}
 
-    // Execute the query
-    let collection = Collection::open(&query.from, &db.root);
-    let mut docs = collection.list().await?;
+/// Run a view's query, applying it to the shared documents
+async fn run_query(
+    root: &Path,
+    mut query: mdql::SelectStmt,
+    cache: &DocumentCache,
+    embedder: Option<&dyn EmbeddingProvider>,
+) -> anyhow::Result<QueryRows> {
+    query::unqualify(&mut query);
 
-    // Apply WHERE filter
-    if let Some(ref where_clause) = query.where_clause {
+    // Execute the query, applying the WHERE filter to the shared documents
+    let (collection, mut source) = match mdql::view_source(&query.from) {
+        Some(view) => view_rows(root, view, cache, embedder).await?,
+        None => (query.from.clone(), cache.get(root, &query.from).await?),
+    };
+    if !system::is_system(&collection) && query::uses_history(&query) {
+        let mut all = source.to_vec();
+        query::attach_history(&Repository::open(root)?, &collection, &mut all)?;
+        source = Arc::new(all);
+    }
+    let mut docs: Vec<Document> = if query.joins.is_empty() {
+        source.to_vec()
+    } else {
+        let table = query::table_name(&query.from, query.from_alias.as_ref());
+        let mut rows: Vec<Document> = source.iter().map(|doc| query::qualify(doc, table)).collect();
+        for clause in &query.joins {
+            rows = query::join(rows, &cache.get(root, &clause.collection).await?, clause);
+        }
+        rows
+    };
+    let reverse_refs = query::reverse_ref_calls(&query);
+    for call in &reverse_refs {
+        query::attach_reverse_refs(&mut docs, call, &cache.get(root, &call.collection).await?);
+    }
+    if let Some(where_clause) = &query.where_clause {
         docs.retain(|doc| filter::evaluate(where_clause, doc));
     }
 
+    // Apply RELATED TO / SEMANTIC_SEARCH, scoring against the whole collection
+    if let Some(id) = &query.related_to {
+        related::rank(&mut docs, &related::related_scores(&source, id)?);
+    } else if let Some(text) = &query.semantic_search {
+        let embedder = embedder.ok_or_else(|| anyhow::anyhow!("SEMANTIC_SEARCH needs an embedding provider"))?;
+        related::rank(&mut docs, &embeddings::search(root, embedder, &collection, &source, text, true)?);
+    }
+
+    // Apply GROUP BY and aggregates
+    if query::is_aggregate(&query) {
+        docs = query::group(docs, &query)?;
+    }
+
     // Apply ORDER BY
     if !query.order_by.is_empty() {
         docs.sort_by(|a, b| {
             for order in &query.order_by {
-                let a_val = a.fields.get(&order.column);
-                let b_val = b.fields.get(&order.column);
-                let cmp = compare_opt_values(a_val, b_val);
+                let a_val = a.get_path(&order.column);
+                let b_val = b.get_path(&order.column);
+                let cmp = compare_opt_values(a_val.as_ref(), b_val.as_ref());
                 if cmp != std::cmp::Ordering::Equal {
                     return match order.direction {
                         mdql::OrderDirection::Asc => cmp,
@@ -73,39 +575,130 @@ pub async fn regenerate_view(db: &Database, view_def_path: &Path) -> anyhow::Res
         docs.truncate(limit);
     }
 
-    // Create output directory
-    let output_dir = db.root.join("views").join(&view_def.name);
-    fs::create_dir_all(&output_dir).await?;
+    // Selected REVERSE_REF columns hold referencing IDs; templates get the documents
+    let mut reverse_ref_columns = Vec::new();
+    for (column, call) in query::detach_reverse_refs(&mut docs, &query, &reverse_refs) {
+        reverse_ref_columns.push((column, cache.get(root, &call.collection).await?));
+    }
+    Ok(QueryRows { collection, source, docs, reverse_ref_columns })
+}
 
-    // Generate HTML output
-    let html = generate_html(&view_def, &docs, db).await?;
-    fs::write(output_dir.join("index.html"), html).await?;
+/// Rows of a saved view, for a query `FROM view:name`, with the collection
+/// they come from
+async fn view_rows(
+    root: &Path,
+    name: &str,
+    cache: &DocumentCache,
+    embedder: Option<&dyn EmbeddingProvider>,
+) -> anyhow::Result<(String, Arc<Vec<Document>>)> {
+    let query = query::resolve_view(name, &[], |view| read_view_file(root, view))?.remove(0);
+    let rows = Box::pin(run_query(root, query, cache, embedder)).await?;
+    Ok((rows.collection, Arc::new(rows.docs)))
+}
 
-    // Generate JSON output
-    let json = generate_json(&docs)?;
-    fs::write(output_dir.join("index.json"), json).await?;
+/// HTML output, with the collection's `_meta.md`/`README.md` and field
+/// labels in context and `![[...]]` embeds expanded
+async fn render_html(
+    root: &Path,
+    view_def: &ViewDefinition,
+    rows: &QueryRows,
+    labels: &BTreeMap<String, String>,
+    cache: &DocumentCache,
+    hooks: &BTreeMap<String, RenderHook>,
+) -> anyhow::Result<String> {
+    let from = rows.collection.as_str();
+    let source = rows.source.as_slice();
+    let docs = referencing_documents(&rows.docs, &rows.reverse_ref_columns);
+    let meta = if system::is_system(from) {
+        None
+    } else {
+        Collection::open(from, root).meta().await?
+    };
+    let mut context = tera::Context::new();
+    context.insert("collection", &collection_context(from, meta.as_ref(), labels));
+    let page_docs = super::transclude::expand_documents(root, from, &docs, cache).await?;
 
-    tracing::info!("Regenerated view: {}", view_def.name);
+    // Per-document template keys: `rendered` from a render hook, `related` with WITH RELATED
+    let mut extras = vec![serde_json::Map::new(); page_docs.len()];
+    if let Some(name) = &view_def.render_hook {
+        let hook = hooks
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Render hook '{}' is not declared in .mdby/config.yaml", name))?;
+        let rendered = super::hooks::render_bodies(root, hook, &page_docs).await?;
+        for (extra, html) in extras.iter_mut().zip(rendered) {
+            extra.insert("rendered".to_string(), serde_json::Value::String(html));
+        }
+    }
+    if view_def.features.contains(&ViewFeature::Related) {
+        let index = RelatedIndex::new(source);
+        for (extra, doc) in extras.iter_mut().zip(&page_docs) {
+            extra.insert("related".to_string(), related_context(&index, source, &doc.id));
+        }
+    }
+    generate_html(view_def, &page_docs, extras, root, context).await
+}
 
-    Ok(())
+/// Documents with each REVERSE_REF column's IDs replaced by the documents
+/// they name, as objects with `id`, `body` and their fields
+fn referencing_documents(docs: &[Document], columns: &[(String, Arc<Vec<Document>>)]) -> Vec<Document> {
+    let mut docs = docs.to_vec();
+    for (column, others) in columns {
+        let others: HashMap<&str, &Document> = others.iter().map(|other| (other.id.as_str(), other)).collect();
+        for doc in &mut docs {
+            let Some(Value::Array(ids)) = doc.fields.get_mut(column) else {
+                continue;
+            };
+            for id in ids.iter_mut() {
+                let Some(other) = id.as_str().and_then(|id| others.get(id)) else {
+                    continue;
+                };
+                let mut object = other.fields.clone();
+                object.insert("id".to_string(), Value::String(other.id.clone()));
+                object.insert("body".to_string(), Value::String(other.body.clone()));
+                *id = Value::Object(object.into_iter().collect());
+            }
+        }
+    }
+    docs
 }
 
-async fn generate_html(view_def: &ViewDefinition, docs: &[Document], db: &Database) -> anyhow::Result<String> {
-    let mut engine = if let Some(ref template_name) = view_def.template {
-        // Load from templates directory
-        let templates_dir = db.root.join(".mdby").join("templates");
-        let mut engine = TemplateEngine::new(&templates_dir)?;
+/// Template variable `collection`: `name`, `readme` (markdown body) and `meta` (frontmatter)
+fn collection_context(name: &str, meta: Option<&CollectionMeta>, labels: &BTreeMap<String, String>) -> serde_json::Value {
+    let fields: serde_json::Map<String, serde_json::Value> = meta
+        .map(|m| m.fields.iter().map(|(k, v)| (k.clone(), value_to_json(v))).collect())
+        .unwrap_or_default();
+    serde_json::json!({
+        "name": name,
+        "readme": meta.map(|m| m.body.as_str()),
+        "meta": fields,
+        "labels": labels,
+    })
+}
 
-        // Also try to load the specific template file
-        let template_path = templates_dir.join(template_name);
-        if template_path.exists() {
-            let content = fs::read_to_string(&template_path).await?;
-            engine.add_template(template_name, &content)?;
-        }
+/// Template variable `related` of a document: `id`, `title` and `score` of
+/// its most similar documents in the collection
+fn related_context(index: &RelatedIndex, docs: &[Document], id: &str) -> serde_json::Value {
+    index
+        .top(id, RELATED_IN_VIEW)
+        .into_iter()
+        .map(|(other, score)| {
+            let title = docs.iter().find(|doc| doc.id == other).and_then(|doc| doc.get("title")).and_then(|v| v.as_str());
+            serde_json::json!({ "id": other, "title": title, "score": score })
+        })
+        .collect()
+}
 
-        engine
-    } else {
-        TemplateEngine::empty()
+async fn generate_html(
+    view_def: &ViewDefinition,
+    docs: &[Document],
+    extras: Vec<serde_json::Map<String, serde_json::Value>>,
+    root: &Path,
+    context: tera::Context,
+) -> anyhow::Result<String> {
+    // Named templates come with the rest, which they may extend or include
+    let mut engine = match view_def.template {
+        Some(_) => TemplateEngine::new(&root.join(".mdby").join("templates"))?,
+        None => TemplateEngine::empty(),
     };
 
     let template = if let Some(ref name) = view_def.template {
@@ -116,19 +709,62 @@ async fn generate_html(view_def: &ViewDefinition, docs: &[Document], db: &Databa
         "default"
     };
 
-    engine.render(template, docs)
+    let options = MarkdownOptions::from_features(&view_def.features);
+    engine.set_markdown_options(options);
+
+    let html = if extras.iter().all(serde_json::Map::is_empty) {
+        engine.render_with(template, docs, context)?
+    } else {
+        engine.render_extended(template, docs, extras, context)?
+    };
+    Ok(markdown::inject_scripts(html, &markdown::scripts(options)))
+}
+
+/// Documents as markdown: a section per document, headed by its title (or
+/// ID), listing its fields by label and then its body
+fn generate_markdown(name: &str, docs: &[Document], display: &FieldDisplay) -> String {
+    let mut out = format!("# {}\n", name);
+    for doc in docs {
+        let heading = doc.fields.get("title").and_then(|title| title.as_str()).unwrap_or(&doc.id);
+        out.push_str(&format!("\n## {}\n\n", heading));
+        for key in ordered_keys(&doc.fields, &display.order) {
+            let label = display.labels.get(key).unwrap_or(key);
+            out.push_str(&format!("- **{}**: {}\n", label, markdown_value(&doc.fields[key])));
+        }
+        if !doc.body.trim().is_empty() {
+            out.push_str(&format!("\n{}\n", doc.body.trim()));
+        }
+    }
+    out
+}
+
+/// A field value as markdown text: strings as they are, arrays as a list
+pub(super) fn markdown_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(markdown_value).collect::<Vec<_>>().join(", "),
+        other => value_to_json(other).to_string(),
+    }
+}
+
+/// Documents as CSV, in the columns `mdby export csv` writes
+fn generate_csv(docs: &[Document], field_order: &[String]) -> anyhow::Result<String> {
+    let mut out = Vec::new();
+    crate::export::write_csv(docs, field_order.to_vec(), &mut out)?;
+    Ok(String::from_utf8(out)?)
 }
 
-fn generate_json(docs: &[Document]) -> anyhow::Result<String> {
+/// Serialize documents as `id`, schema fields, remaining fields alphabetically, then `body`
+fn generate_json(docs: &[Document], field_order: &[String]) -> anyhow::Result<String> {
     let items: Vec<serde_json::Value> = docs.iter().map(|doc| {
         let mut obj = serde_json::Map::new();
         obj.insert("id".to_string(), serde_json::Value::String(doc.id.clone()));
-        obj.insert("body".to_string(), serde_json::Value::String(doc.body.clone()));
 
-        for (key, value) in &doc.fields {
-            obj.insert(key.clone(), value_to_json(value));
+        for key in ordered_keys(&doc.fields, field_order) {
+            obj.insert(key.clone(), value_to_json(&doc.fields[key]));
         }
 
+        obj.insert("body".to_string(), serde_json::Value::String(doc.body.clone()));
         serde_json::Value::Object(obj)
     }).collect();
 
@@ -176,8 +812,52 @@ fn compare_opt_values(
 
 /// View definition stored in YAML
 #[derive(Debug, serde::Deserialize)]
-struct ViewDefinition {
-    name: String,
-    query: serde_json::Value,
-    template: Option<String>,
+pub(super) struct ViewDefinition {
+    pub(super) name: String,
+    #[serde(default)]
+    pub(super) params: Vec<mdql::ViewParam>,
+    pub(super) query: serde_json::Value,
+    pub(super) template: Option<String>,
+    #[serde(default)]
+    pub(super) formats: Vec<OutputFormat>,
+    #[serde(default)]
+    pub(super) feed: Option<FeedFields>,
+    #[serde(default)]
+    pub(super) features: Vec<mdql::ViewFeature>,
+    #[serde(default)]
+    pub(super) render_hook: Option<String>,
+    #[serde(default)]
+    pub(super) delivery: Option<mdql::Delivery>,
+}
+
+impl ViewDefinition {
+    /// Formats the view is rendered to: its `FORMATS`, or HTML and JSON
+    pub(super) fn output_formats(&self) -> Vec<OutputFormat> {
+        if self.formats.is_empty() {
+            vec![OutputFormat::Html, OutputFormat::Json]
+        } else {
+            self.formats.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_document_cache_loads_collection_once() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let collection = Collection::open("notes", tmp.path());
+        collection.ensure_exists().await.unwrap();
+        collection.insert(&Document::new("a")).await.unwrap();
+
+        let cache = DocumentCache::default();
+        let first = cache.get(tmp.path(), "notes").await.unwrap();
+        collection.insert(&Document::new("b")).await.unwrap();
+        let second = cache.get(tmp.path(), "notes").await.unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(second.len(), 1);
+    }
 }
@@ -0,0 +1,215 @@
+//! Which views are out of date (`/.mdby/state/views.yaml`)
+//!
+//! Regenerating a view records the HEAD commit it was rendered at, the
+//! collections it read (its FROM, JOINs and `REVERSE_REF`s, those of any
+//! view it selects from, and any whose documents it embeds) and a
+//! fingerprint of its definition, those of the views it selects from, the
+//! templates and the database config. [`regenerate_all`](super::regenerate_all) then
+//! rebuilds only the views that are stale:
+//!
+//! - with no record or no output in `/views/`
+//! - whose fingerprint differs
+//! - that read a collection whose documents or schema changed since the
+//!   recorded commit, or have uncommitted changes
+//! - that read a system collection (`@commits`), once HEAD has moved
+//!
+//! Views whose query calls `NOW()` or `DATE()`, and views rendered while a
+//! collection they read had uncommitted changes, aren't recorded, so they are
+//! always stale. Without any commit, every view is. The file is local to the
+//! checkout and ignored by git, and kept apart from the view definitions in
+//! `/.mdby/views/` so it can't be mistaken for one.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::Config;
+use crate::git::Repository;
+use crate::storage::ignore::IGNORE_FILE;
+use crate::system;
+
+/// What a view was last regenerated from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct ViewState {
+    pub(super) commit: String,
+    pub(super) fingerprint: String,
+    pub(super) reads: BTreeSet<String>,
+}
+
+fn state_path(root: &Path) -> PathBuf {
+    root.join(".mdby").join("state").join("views.yaml")
+}
+
+/// Recorded state of each view; empty if there is none or it can't be read
+pub(super) fn load(root: &Path) -> BTreeMap<String, ViewState> {
+    std::fs::read_to_string(state_path(root))
+        .ok()
+        .and_then(|content| serde_yaml::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub(super) fn save(root: &Path, states: &BTreeMap<String, ViewState>) -> anyhow::Result<()> {
+    let path = state_path(root);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+        let gitignore = dir.join(".gitignore");
+        if !gitignore.exists() {
+            std::fs::write(&gitignore, "*\n")?;
+        }
+    }
+    std::fs::write(&path, serde_yaml::to_string(states)?)?;
+    Ok(())
+}
+
+/// Hash of what every view's output depends on besides its own definition
+/// and documents: the templates, the database config and `/.mdbyignore`
+pub(super) fn shared_inputs(root: &Path, config: &Config) -> anyhow::Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_yaml::to_string(config)?.as_bytes());
+    let templates = root.join(".mdby").join("templates");
+    let mut files: Vec<PathBuf> = walkdir::WalkDir::new(&templates)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .collect();
+    files.sort();
+    files.push(root.join(IGNORE_FILE));
+    for file in files {
+        if let Ok(content) = std::fs::read(&file) {
+            hasher.update([0]);
+            hasher.update(file.strip_prefix(root).unwrap_or(&file).to_string_lossy().as_bytes());
+            hasher.update([0]);
+            hasher.update(content);
+        }
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Hex fingerprint of a view from its definition file's content
+pub(super) fn fingerprint(shared: &[u8], definition: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(shared);
+    hasher.update(definition);
+    format!("{:x}", hasher.finalize())
+}
+
+/// What changed in the repository, for telling which views are stale
+pub(super) struct Changes {
+    head: Option<String>,
+    /// Collections with uncommitted changes
+    uncommitted: BTreeSet<String>,
+    /// Collections changed since each recorded commit; `None` for commits
+    /// no longer in the history
+    since: HashMap<String, Option<BTreeSet<String>>>,
+}
+
+impl Changes {
+    pub(super) fn new(repo: &Repository, states: &BTreeMap<String, ViewState>) -> anyhow::Result<Self> {
+        let head = repo.head_hash().ok();
+        let mut since = HashMap::new();
+        if head.is_some() {
+            for state in states.values() {
+                since
+                    .entry(state.commit.clone())
+                    .or_insert_with(|| repo.changed_since(&state.commit).ok().map(collections_in));
+            }
+        }
+        Ok(Self { head, uncommitted: collections_in(repo.uncommitted()?), since })
+    }
+
+    /// Whether a view with this recorded state and fingerprint must be
+    /// regenerated
+    pub(super) fn is_stale(&self, state: Option<&ViewState>, fingerprint: &str) -> bool {
+        let (Some(head), Some(state)) = (&self.head, state) else {
+            return true;
+        };
+        let Some(Some(changed)) = self.since.get(&state.commit) else {
+            return true;
+        };
+        state.fingerprint != fingerprint
+            || state.reads.iter().any(|name| {
+                if system::is_system(name) {
+                    state.commit != *head
+                } else {
+                    changed.contains(name) || self.uncommitted.contains(name)
+                }
+            })
+    }
+
+    /// State to record for a view just regenerated from `reads`; `None` if
+    /// it has to be regenerated next time anyway
+    pub(super) fn record(&self, fingerprint: String, reads: BTreeSet<String>) -> Option<ViewState> {
+        let commit = self.head.clone()?;
+        if reads.iter().any(|name| self.uncommitted.contains(name)) {
+            return None;
+        }
+        Some(ViewState { commit, fingerprint, reads })
+    }
+}
+
+/// Collections whose documents (`collections/{name}/...`) or schema
+/// (`.mdby/schemas/{name}.yaml`) are among `paths`
+fn collections_in(paths: Vec<PathBuf>) -> BTreeSet<String> {
+    paths
+        .iter()
+        .filter_map(|path| {
+            let parts: Vec<&str> = path
+                .components()
+                .filter_map(|part| match part {
+                    Component::Normal(part) => part.to_str(),
+                    _ => None,
+                })
+                .collect();
+            match parts.as_slice() {
+                ["collections", name, _, ..] => Some(name.to_string()),
+                [".mdby", "schemas", file] => file.strip_suffix(".yaml").map(str::to_string),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(commit: &str, reads: &[&str]) -> ViewState {
+        ViewState {
+            commit: commit.into(),
+            fingerprint: "f".into(),
+            reads: reads.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_collections_in() {
+        let paths = ["collections/todos/a.md", ".mdby/schemas/notes.yaml", ".mdby/views/open.yaml", "collections/README.md"];
+        let names = collections_in(paths.iter().map(PathBuf::from).collect());
+        assert_eq!(names, BTreeSet::from(["notes".to_string(), "todos".to_string()]));
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let changes = Changes {
+            head: Some("c2".into()),
+            uncommitted: BTreeSet::from(["drafts".to_string()]),
+            since: HashMap::from([
+                ("c1".to_string(), Some(BTreeSet::from(["todos".to_string()]))),
+                ("c2".to_string(), Some(BTreeSet::new())),
+            ]),
+        };
+        assert!(!changes.is_stale(Some(&state("c1", &["notes"])), "f"));
+        assert!(changes.is_stale(Some(&state("c1", &["notes"])), "g"));
+        assert!(changes.is_stale(Some(&state("c1", &["notes", "todos"])), "f"));
+        assert!(changes.is_stale(Some(&state("c1", &["@commits"])), "f"));
+        assert!(!changes.is_stale(Some(&state("c2", &["@commits"])), "f"));
+        assert!(changes.is_stale(Some(&state("c2", &["drafts"])), "f"));
+        assert!(changes.is_stale(Some(&state("c0", &["notes"])), "f"));
+        assert!(changes.is_stale(None, "f"));
+
+        assert!(changes.record("f".into(), BTreeSet::from(["drafts".to_string()])).is_none());
+        assert_eq!(changes.record("f".into(), BTreeSet::from(["notes".to_string()])), Some(state("c2", &["notes"])));
+    }
+}
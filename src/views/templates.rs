@@ -1,10 +1,25 @@
-//! Template engine for views
+//! Template engine for views, and the templates in `/.mdby/templates/`
+//!
+//! Every file in `/.mdby/templates/` is loaded, named by its path there
+//! (`page.html`, `partials/header.html`), so templates can extend one another
+//! (`{% extends "base.html" %}` with `{% block %}`s), include partials
+//! (`{% include "partials/header.html" %}`) and import macros. Files whose
+//! names start with `.` are skipped. A template that doesn't parse, or
+//! extends one that doesn't exist, fails every view rendered with a
+//! template; [`add_template`] checks a template against the others before
+//! writing it.
 
+use regex::Regex;
+use serde::Serialize;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use tera::{Context, Tera};
 
+use super::markdown::{self, MarkdownOptions};
 use crate::storage::document::{Document, Value};
+use crate::validation::validate_template_name;
+use crate::Database;
 
 /// Template engine wrapper
 pub struct TemplateEngine {
@@ -12,24 +27,41 @@ pub struct TemplateEngine {
 }
 
 impl TemplateEngine {
-    /// Create a new template engine loading templates from a directory
+    /// Create a new template engine loading every template in a directory
     pub fn new(templates_dir: &Path) -> anyhow::Result<Self> {
-        let pattern = templates_dir.join("**/*.html").display().to_string();
-        let mut tera = Tera::new(&pattern).unwrap_or_else(|_| Tera::default());
-
-        // Register custom filters
-        tera.register_filter("markdown", markdown_filter);
+        Self::with_sources(read_sources(templates_dir)?)
+    }
 
-        Ok(Self { tera })
+    /// Create a template engine from `(name, content)` pairs, resolving
+    /// inheritance between them
+    fn with_sources(sources: Vec<(String, String)>) -> anyhow::Result<Self> {
+        let mut engine = Self::empty();
+        engine
+            .tera
+            .add_raw_templates(sources)
+            .map_err(|e| anyhow::anyhow!("Invalid template in .mdby/templates: {}", describe(&e)))?;
+        Ok(engine)
     }
 
     /// Create an empty template engine
     pub fn empty() -> Self {
         let mut tera = Tera::default();
         tera.register_filter("markdown", markdown_filter);
+        super::filters::register(&mut tera);
         Self { tera }
     }
 
+    /// Enable Mermaid and math rendering in the `markdown` filter
+    pub fn set_markdown_options(&mut self, options: MarkdownOptions) {
+        self.tera.register_filter(
+            "markdown",
+            move |value: &tera::Value, _args: &HashMap<String, tera::Value>| {
+                let text = value.as_str().unwrap_or("");
+                Ok(tera::Value::String(markdown::render(text, options)))
+            },
+        );
+    }
+
     /// Add a template from a string
     pub fn add_template(&mut self, name: &str, content: &str) -> anyhow::Result<()> {
         self.tera.add_raw_template(name, content)?;
@@ -38,7 +70,11 @@ impl TemplateEngine {
 
     /// Render a template with documents
     pub fn render(&self, template_name: &str, documents: &[Document]) -> anyhow::Result<String> {
-        let mut context = Context::new();
+        self.render_with(template_name, documents, Context::new())
+    }
+
+    /// Render a template with documents and additional context variables
+    pub fn render_with(&self, template_name: &str, documents: &[Document], mut context: Context) -> anyhow::Result<String> {
         context.insert("documents", &documents_to_json(documents));
         context.insert("count", &documents.len());
 
@@ -46,6 +82,34 @@ impl TemplateEngine {
         Ok(result)
     }
 
+    /// Render a template with extra keys on each document
+    ///
+    /// The keys of each `extras` entry (such as `rendered` from a render hook)
+    /// are added to the matching document.
+    pub fn render_extended(
+        &self,
+        template_name: &str,
+        documents: &[Document],
+        extras: Vec<serde_json::Map<String, serde_json::Value>>,
+        mut context: Context,
+    ) -> anyhow::Result<String> {
+        let mut docs = documents_to_json(documents);
+        for (doc, extra) in docs.iter_mut().zip(extras) {
+            if let serde_json::Value::Object(obj) = doc {
+                obj.extend(extra);
+            }
+        }
+        context.insert("documents", &docs);
+        context.insert("count", &documents.len());
+
+        Ok(self.tera.render(template_name, &context)?)
+    }
+
+    /// Render a template with an arbitrary context
+    pub fn render_context(&self, template_name: &str, context: &Context) -> anyhow::Result<String> {
+        Ok(self.tera.render(template_name, context)?)
+    }
+
     /// Render an inline template string
     pub fn render_inline(&mut self, template: &str, documents: &[Document]) -> anyhow::Result<String> {
         self.add_template("__inline__", template)?;
@@ -69,15 +133,18 @@ impl TemplateEngine {
 </head>
 <body>
     <h1>{{ view_name | default(value="View") }}</h1>
+    {% if collection.readme %}<div class="readme">{{ collection.readme | markdown | safe }}</div>{% endif %}
     <p>{{ count }} document(s)</p>
 
     {% for doc in documents %}
     <article>
         <h2>{{ doc.title | default(value=doc.id) }}</h2>
         <div class="meta">
-            {% if doc.tags %}<span>Tags: {{ doc.tags | join(sep=", ") }}</span>{% endif %}
+            {% if doc.tags %}<span>{{ collection.labels.tags | default(value="Tags") }}: {{ doc.tags | join(sep=", ") }}</span>{% endif %}
         </div>
-        {% if doc.body %}
+        {% if doc.rendered %}
+        <div class="body">{{ doc.rendered | safe }}</div>
+        {% elif doc.body %}
         <div class="body">{{ doc.body | markdown | safe }}</div>
         {% endif %}
     </article>
@@ -129,6 +196,185 @@ impl TemplateEngine {
     }
 }
 
+/// A template in `/.mdby/templates/` and what uses it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TemplateInfo {
+    /// Path in `/.mdby/templates/`, which views and other templates refer to
+    pub name: String,
+    /// Template it extends
+    pub extends: Option<String>,
+    /// Templates it includes or imports macros from
+    pub includes: Vec<String>,
+    /// Views rendered with it
+    pub views: Vec<String>,
+    /// Templates extending, including or importing it
+    pub used_by: Vec<String>,
+}
+
+/// Every template in `/.mdby/templates/`, sorted by name
+pub fn list_templates(db: &Database) -> anyhow::Result<Vec<TemplateInfo>> {
+    let sources = read_sources(&templates_dir(&db.root))?;
+    let views = view_templates(&db.root)?;
+    let mut templates: Vec<TemplateInfo> = sources
+        .iter()
+        .map(|(name, content)| {
+            let (extends, includes) = references(content);
+            TemplateInfo {
+                name: name.clone(),
+                extends,
+                includes,
+                views: views.iter().filter(|(_, template)| template == name).map(|(view, _)| view.clone()).collect(),
+                used_by: Vec::new(),
+            }
+        })
+        .collect();
+    for i in 0..templates.len() {
+        let name = templates[i].name.clone();
+        templates[i].used_by = templates
+            .iter()
+            .filter(|other| other.extends.as_ref() == Some(&name) || other.includes.contains(&name))
+            .map(|other| other.name.clone())
+            .collect();
+    }
+    Ok(templates)
+}
+
+/// Write a template to `/.mdby/templates/` and commit it, after checking
+/// that it parses and loads with the other templates
+///
+/// Fails if the template exists, unless `replace` is set.
+pub fn add_template(db: &Database, name: &str, content: &str, replace: bool) -> anyhow::Result<()> {
+    let path = template_path(db, name)?;
+    if path.exists() && !replace {
+        anyhow::bail!("Template '{}' already exists; replace it with --force", name);
+    }
+    let mut sources = read_sources(&templates_dir(&db.root))?;
+    sources.retain(|(other, _)| other != name);
+    sources.push((name.to_string(), content.to_string()));
+    TemplateEngine::with_sources(sources)?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    crate::storage::journal::preserve(&db.root, &path)?;
+    std::fs::write(&path, content)?;
+    db.git.commit(&format!("ADD TEMPLATE {}", name))?;
+    Ok(())
+}
+
+/// Remove a template from `/.mdby/templates/` and commit it
+///
+/// Fails if a view is rendered with it or another template uses it.
+pub fn remove_template(db: &Database, name: &str) -> anyhow::Result<()> {
+    let path = template_path(db, name)?;
+    let template = list_templates(db)?
+        .into_iter()
+        .find(|template| template.name == name)
+        .ok_or_else(|| anyhow::anyhow!("Template '{}' does not exist", name))?;
+    if !template.views.is_empty() {
+        anyhow::bail!("Template '{}' is used by view(s): {}", name, template.views.join(", "));
+    }
+    if !template.used_by.is_empty() {
+        anyhow::bail!("Template '{}' is used by template(s): {}", name, template.used_by.join(", "));
+    }
+
+    crate::storage::journal::preserve(&db.root, &path)?;
+    std::fs::remove_file(&path)?;
+    db.git.commit(&format!("REMOVE TEMPLATE {}", name))?;
+    Ok(())
+}
+
+fn templates_dir(root: &Path) -> PathBuf {
+    root.join(".mdby").join("templates")
+}
+
+/// Path of a template by name, after checking each part of the name
+fn template_path(db: &Database, name: &str) -> anyhow::Result<PathBuf> {
+    if db.is_read_only() {
+        anyhow::bail!("Database is a read-only replica");
+    }
+    for part in name.split('/') {
+        validate_template_name(part)?;
+    }
+    Ok(templates_dir(&db.root).join(name))
+}
+
+/// Name and content of every template in a directory, sorted by name
+fn read_sources(dir: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let mut sources = Vec::new();
+    let entries = walkdir::WalkDir::new(dir)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| !entry.file_name().to_string_lossy().starts_with('.'));
+    for entry in entries.filter_map(Result::ok).filter(|entry| entry.file_type().is_file()) {
+        let Ok(relative) = entry.path().strip_prefix(dir) else {
+            continue;
+        };
+        let name = relative.iter().map(|part| part.to_string_lossy()).collect::<Vec<_>>().join("/");
+        sources.push((name, std::fs::read_to_string(entry.path())?));
+    }
+    Ok(sources)
+}
+
+/// The template a template extends, and those it includes or imports
+fn references(content: &str) -> (Option<String>, Vec<String>) {
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    static NAME: OnceLock<Regex> = OnceLock::new();
+    let tag_pattern = TAG.get_or_init(|| Regex::new(r"\{%-?\s*(extends|include|import)\s+(.*?)-?%\}").unwrap());
+    let name_pattern = NAME.get_or_init(|| Regex::new(r#""([^"]+)"|'([^']+)'"#).unwrap());
+
+    let mut extends = None;
+    let mut includes: Vec<String> = Vec::new();
+    for tag in tag_pattern.captures_iter(content) {
+        let mut names = name_pattern.captures_iter(&tag[2]).filter_map(|name| name.get(1).or(name.get(2)).map(|m| m.as_str().to_string()));
+        match &tag[1] {
+            "extends" => extends = names.next(),
+            // `import "macros.html" as m` names one file
+            "import" => includes.extend(names.take(1)),
+            _ => includes.extend(names),
+        }
+    }
+    includes.sort();
+    includes.dedup();
+    (extends, includes)
+}
+
+/// `(view, template)` for each view rendered with a template
+fn view_templates(root: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let dir = root.join(".mdby").join("views");
+    let mut views = Vec::new();
+    if !dir.exists() {
+        return Ok(views);
+    }
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if !super::is_view_definition(&path) {
+            continue;
+        }
+        let definition: serde_yaml::Value = serde_yaml::from_str(&std::fs::read_to_string(&path)?)?;
+        if let (Some(name), Some(template)) = (
+            path.file_stem().and_then(|stem| stem.to_str()),
+            definition.get("template").and_then(|template| template.as_str()),
+        ) {
+            views.push((name.to_string(), template.to_string()));
+        }
+    }
+    views.sort();
+    Ok(views)
+}
+
+/// A Tera error with the errors that caused it, which hold the details
+fn describe(error: &tera::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
+}
+
 /// Convert documents to JSON-serializable format
 fn documents_to_json(documents: &[Document]) -> Vec<serde_json::Value> {
     documents.iter().map(|doc| {
@@ -167,10 +413,7 @@ fn value_to_json(value: &Value) -> serde_json::Value {
 /// Tera filter to convert markdown to HTML
 fn markdown_filter(value: &tera::Value, _args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
     let text = value.as_str().unwrap_or("");
-    let parser = pulldown_cmark::Parser::new(text);
-    let mut html = String::new();
-    pulldown_cmark::html::push_html(&mut html, parser);
-    Ok(tera::Value::String(html))
+    Ok(tera::Value::String(markdown::render(text, MarkdownOptions::default())))
 }
 
 #[cfg(test)]
@@ -190,4 +433,31 @@ mod tests {
 
         assert_eq!(result, "Hello World");
     }
+
+    #[test]
+    fn test_inheritance_and_includes() {
+        let sources = vec![
+            ("base.html".to_string(), "<h1>{% block title %}{% endblock %}</h1>{% block content %}{% endblock %}".to_string()),
+            ("partials/item.html".to_string(), "<li>{{ doc.title }}</li>".to_string()),
+            (
+                "list.html".to_string(),
+                r#"{% extends "base.html" %}{% block title %}{{ count }} item(s){% endblock %}{% block content %}{% for doc in documents %}{% include "partials/item.html" %}{% endfor %}{% endblock %}"#.to_string(),
+            ),
+        ];
+        let engine = TemplateEngine::with_sources(sources).unwrap();
+        let mut doc = Document::new("a");
+        doc.set("title", "First");
+        assert_eq!(engine.render("list.html", &[doc]).unwrap(), "<h1>1 item(s)</h1><li>First</li>");
+
+        let missing = TemplateEngine::with_sources(vec![("page.html".to_string(), r#"{% extends "nope.html" %}"#.to_string())]);
+        assert!(missing.err().unwrap().to_string().contains("nope.html"));
+    }
+
+    #[test]
+    fn test_references() {
+        let content = r#"{%- extends 'base.html' -%}{% import "macros.html" as m %}{% include ["a.html", "b.html"] ignore missing %}"#;
+        let (extends, includes) = references(content);
+        assert_eq!(extends.as_deref(), Some("base.html"));
+        assert_eq!(includes, ["a.html", "b.html", "macros.html"]);
+    }
 }
@@ -0,0 +1,171 @@
+//! Document transclusion (`![[other-doc]]`)
+//!
+//! When a view is rendered, `![[id]]` (same collection) or
+//! `![[collection/id]]` in a body is replaced with the referenced document's
+//! body, recursively up to [`MAX_DEPTH`] levels. An embed that would form a
+//! cycle, goes too deep or names a missing document is left as written.
+//! Embeds inside code are ignored. The JSON output keeps the original bodies.
+
+use regex::Regex;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::OnceLock;
+
+use super::markdown::code_ranges;
+use super::regenerate::DocumentCache;
+use crate::storage::document::Document;
+use crate::validation::{validate_collection_name, validate_document_id};
+
+/// Maximum nesting of embedded documents
+pub const MAX_DEPTH: usize = 5;
+
+type Expansion<'a> = Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + 'a>>;
+
+/// Expand the embeds in each document's body
+pub(super) async fn expand_documents(
+    root: &Path,
+    collection: &str,
+    docs: &[Document],
+    cache: &DocumentCache,
+) -> anyhow::Result<Vec<Document>> {
+    let mut expanded = Vec::with_capacity(docs.len());
+    for doc in docs {
+        let mut doc = doc.clone();
+        if doc.body.contains("![[") {
+            let mut stack = vec![key(collection, &doc.id)];
+            doc.body = expand(root, collection, &doc.body, cache, &mut stack).await?;
+        }
+        expanded.push(doc);
+    }
+    Ok(expanded)
+}
+
+/// Replace embeds in `body`, which belongs to `collection`
+///
+/// `stack` holds the documents currently being expanded, for cycle detection.
+fn expand<'a>(
+    root: &'a Path,
+    collection: &'a str,
+    body: &'a str,
+    cache: &'a DocumentCache,
+    stack: &'a mut Vec<String>,
+) -> Expansion<'a> {
+    Box::pin(async move {
+        static EMBED: OnceLock<Regex> = OnceLock::new();
+        let embed = EMBED.get_or_init(|| Regex::new(r"!\[\[([^\[\]|#]+)(?:#[^\[\]|]*)?(?:\|[^\[\]]*)?\]\]").unwrap());
+
+        let code = code_ranges(body);
+        let mut out = String::with_capacity(body.len());
+        let mut last = 0;
+
+        for caps in embed.captures_iter(body) {
+            let whole = caps.get(0).expect("match");
+            if code.iter().any(|r| r.contains(&whole.start())) {
+                continue;
+            }
+
+            let target = caps[1].trim();
+            let (target_collection, id) = target.split_once('/').unwrap_or((collection, target));
+            let target_key = key(target_collection, id);
+
+            let replacement = if stack.contains(&target_key) {
+                tracing::warn!("Transclusion cycle at ![[{}]]", target);
+                None
+            } else if stack.len() > MAX_DEPTH {
+                tracing::warn!("Transclusion of ![[{}]] exceeds depth {}", target, MAX_DEPTH);
+                None
+            } else {
+                match lookup(root, target_collection, id, cache).await? {
+                    Some(embedded) => {
+                        stack.push(target_key);
+                        let expanded = expand(root, target_collection, &embedded, cache, stack).await?;
+                        stack.pop();
+                        Some(expanded)
+                    }
+                    None => None,
+                }
+            };
+
+            out.push_str(&body[last..whole.start()]);
+            match replacement {
+                Some(text) => out.push_str(text.trim_end()),
+                None => out.push_str(whole.as_str()),
+            }
+            last = whole.end();
+        }
+
+        out.push_str(&body[last..]);
+        Ok(out)
+    })
+}
+
+/// Body of a document, if it exists
+async fn lookup(root: &Path, collection: &str, id: &str, cache: &DocumentCache) -> anyhow::Result<Option<String>> {
+    if validate_collection_name(collection).is_err() || validate_document_id(id).is_err() {
+        return Ok(None);
+    }
+    if !root.join("collections").join(collection).is_dir() {
+        return Ok(None);
+    }
+
+    let docs = cache.get(root, collection).await?;
+    Ok(docs.iter().find(|d| d.id == id).map(|d| d.body.clone()))
+}
+
+fn key(collection: &str, id: &str) -> String {
+    format!("{}/{}", collection, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::collection::Collection;
+
+    async fn insert(root: &Path, collection: &str, id: &str, body: &str) {
+        let collection = Collection::open(collection, root);
+        collection.ensure_exists().await.unwrap();
+        let mut doc = Document::new(id);
+        doc.body = body.into();
+        collection.insert(&doc).await.unwrap();
+    }
+
+    async fn expand_one(root: &Path, collection: &str, id: &str) -> String {
+        let doc = Collection::open(collection, root).get(id).await.unwrap().unwrap();
+        let docs = expand_documents(root, collection, &[doc], &DocumentCache::default()).await.unwrap();
+        docs[0].body.clone()
+    }
+
+    #[tokio::test]
+    async fn test_nested_and_cross_collection() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        insert(tmp.path(), "notes", "week", "# Week\n\n![[mon]]\n\n![[people/ada|Ada]]\n").await;
+        insert(tmp.path(), "notes", "mon", "Monday ![[tue]]\n").await;
+        insert(tmp.path(), "notes", "tue", "Tuesday\n").await;
+        insert(tmp.path(), "people", "ada", "Ada Lovelace\n").await;
+
+        let body = expand_one(tmp.path(), "notes", "week").await;
+        assert_eq!(body, "# Week\n\nMonday Tuesday\n\nAda Lovelace\n");
+    }
+
+    #[tokio::test]
+    async fn test_cycles_missing_and_code_left_alone() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        insert(tmp.path(), "notes", "a", "A ![[b]] ![[missing]] `![[b]]`").await;
+        insert(tmp.path(), "notes", "b", "B ![[a]]").await;
+
+        let body = expand_one(tmp.path(), "notes", "a").await;
+        assert_eq!(body, "A B ![[a]] ![[missing]] `![[b]]`");
+    }
+
+    #[tokio::test]
+    async fn test_depth_limit() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        for i in 0..10 {
+            insert(tmp.path(), "chain", &format!("d{}", i), &format!("{} ![[d{}]]", i, i + 1)).await;
+        }
+
+        let body = expand_one(tmp.path(), "chain", "d0").await;
+        assert_eq!(body, format!("0 1 2 3 4 5 ![[d{}]]", MAX_DEPTH + 1));
+    }
+}
@@ -2,6 +2,11 @@
 //!
 //! Tests full query execution flows from parsing through to file system changes.
 
+use std::collections::BTreeMap;
+
+use mdby::embeddings::EmbeddingProvider;
+use mdby::policy::{ExecutionPolicy, StatementKind};
+use mdby::views::OutputFormat;
 use mdby::{Database, QueryResult};
 use tempfile::TempDir;
 
@@ -13,6 +18,7 @@ async fn setup_test_db() -> (TempDir, Database) {
 }
 
 /// Helper to execute a query and unwrap the result
+#[allow(clippy::expect_fun_call)]
 async fn exec(db: &mut Database, query: &str) -> QueryResult {
     db.execute(query).await.expect(&format!("Query failed: {}", query))
 }
@@ -210,6 +216,36 @@ async fn test_select_where_by_id() {
     }
 }
 
+#[tokio::test]
+async fn test_select_numeric_id() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION tickets").await;
+    exec(&mut db, "INSERT INTO tickets (id, title) VALUES (123, 'Numeric')").await;
+    exec(&mut db, "INSERT INTO tickets (id, title) VALUES ('456', 'Quoted')").await;
+
+    for query in [
+        "SELECT * FROM tickets WHERE id = 123",
+        "SELECT * FROM tickets WHERE @id = 123",
+        "SELECT * FROM tickets WHERE id = '123'",
+    ] {
+        let result = exec(&mut db, query).await;
+        if let QueryResult::Documents(docs) = result {
+            assert_eq!(docs.len(), 1, "{}", query);
+            assert_eq!(docs[0].id, "123");
+        } else {
+            panic!("Expected Documents");
+        }
+    }
+
+    let result = exec(&mut db, "SELECT * FROM tickets WHERE id IN (123, 456)").await;
+    assert!(matches!(result, QueryResult::Documents(docs) if docs.len() == 2));
+
+    // As numbers, where text would put '99' after both
+    let result = exec(&mut db, "SELECT * FROM tickets WHERE id > 99").await;
+    assert!(matches!(result, QueryResult::Documents(docs) if docs.len() == 2));
+}
+
 #[tokio::test]
 async fn test_select_with_order_by() {
     let (_tmp, mut db) = setup_test_db().await;
@@ -283,6 +319,322 @@ async fn test_select_with_and_condition() {
     }
 }
 
+#[tokio::test]
+async fn test_select_join() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION customers (name STRING REQUIRED)").await;
+    exec(&mut db, "CREATE COLLECTION orders (customer REF<customers>, total INT)").await;
+    exec(&mut db, "INSERT INTO customers (id, name) VALUES ('ada', 'Ada')").await;
+    exec(&mut db, "INSERT INTO customers (id, name) VALUES ('grace', 'Grace')").await;
+    exec(&mut db, "INSERT INTO orders (id, customer, total) VALUES ('o1', 'ada', 30)").await;
+    exec(&mut db, "INSERT INTO orders (id, customer, total) VALUES ('o2', 'grace', 10)").await;
+    exec(&mut db, "INSERT INTO orders (id, customer, total) VALUES ('o3', 'ada', 20)").await;
+
+    let result = exec(
+        &mut db,
+        "SELECT o.total, c.name FROM orders AS o JOIN customers AS c ON o.customer = c.@id WHERE o.total > 15 ORDER BY o.total",
+    )
+    .await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected documents") };
+    let rows: Vec<(&str, Option<i64>, Option<&str>)> = docs
+        .iter()
+        .map(|d| (d.id.as_str(), d.fields["total"].as_i64(), d.fields["name"].as_str()))
+        .collect();
+    assert_eq!(rows, vec![("o3", Some(20), Some("Ada")), ("o1", Some(30), Some("Ada"))]);
+
+    // Unmatched customers are kept by a LEFT JOIN from their side
+    exec(&mut db, "INSERT INTO customers (id, name) VALUES ('linus', 'Linus')").await;
+    let result = exec(&mut db, "SELECT * FROM customers LEFT JOIN orders ON orders.customer = customers.@id").await;
+    assert!(matches!(result, QueryResult::Documents(ref docs)
+        if docs.len() == 4 && docs.iter().any(|d| d.id == "linus" && !d.fields.contains_key("orders.total"))));
+
+    assert!(db.execute("SELECT * FROM orders JOIN missing ON orders.customer = missing.@id").await.is_err());
+}
+
+#[tokio::test]
+async fn test_select_group_by_with_aggregates() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, status, points) VALUES ('a', 'open', 3)").await;
+    exec(&mut db, "INSERT INTO todos (id, status, points) VALUES ('b', 'done', 5)").await;
+    exec(&mut db, "INSERT INTO todos (id, status, points) VALUES ('c', 'open', 2)").await;
+    exec(&mut db, "INSERT INTO todos (id, status, points) VALUES ('d', 'blocked', 8)").await;
+
+    let result = exec(&mut db, "SELECT status, COUNT(*) AS n, SUM(points) AS total, MIN(points) FROM todos GROUP BY status ORDER BY total DESC, n DESC").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected documents") };
+    let rows: Vec<(&str, Option<i64>, Option<i64>)> = docs
+        .iter()
+        .map(|d| (d.id.as_str(), d.fields["n"].as_i64(), d.fields["total"].as_i64()))
+        .collect();
+    assert_eq!(rows, vec![("blocked", Some(1), Some(8)), ("open", Some(2), Some(5)), ("done", Some(1), Some(5))]);
+    assert_eq!(docs[1].fields["MIN(points)"].as_i64(), Some(2));
+
+    let result = exec(&mut db, "SELECT status FROM todos WHERE points < 8 GROUP BY status HAVING COUNT(*) > 1").await;
+    assert!(matches!(result, QueryResult::Documents(ref docs) if docs.len() == 1 && docs[0].id == "open"));
+
+    let result = exec(&mut db, "SELECT COUNT(*), AVG(points) FROM todos").await;
+    assert!(matches!(result, QueryResult::Documents(ref docs)
+        if docs.len() == 1 && docs[0].fields["COUNT(*)"].as_i64() == Some(4)));
+
+    assert!(db.execute("SELECT status, points FROM todos GROUP BY status").await.is_err());
+    assert!(db.execute("SELECT * FROM todos WHERE COUNT(*) > 1").await.is_err());
+}
+
+#[tokio::test]
+async fn test_select_count_returns_scalar() {
+    use mdby::query::plan::Access;
+    use mdby::storage::document::Value;
+
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING, done BOOL)").await;
+    exec(&mut db, "INSERT INTO todos (id, title, done) VALUES ('a', 'A', true), ('b', 'B', false), ('c', 'C', false)").await;
+
+    let result = exec(&mut db, "SELECT COUNT(*) FROM todos").await;
+    assert!(matches!(result, QueryResult::Scalar(Value::Int(3))), "{:?}", result);
+    let result = exec(&mut db, "SELECT COUNT(*) AS n FROM todos WHERE done = false").await;
+    assert!(matches!(result, QueryResult::Scalar(Value::Int(2))), "{:?}", result);
+    let result = exec(&mut db, "SELECT COUNT(*) FROM todos HAVING COUNT(*) > 10").await;
+    assert!(matches!(result, QueryResult::Scalar(Value::Null)), "{:?}", result);
+
+    // Counting every document reads none of them, so a file that doesn't parse still counts
+    let QueryResult::Plan(plan) = exec(&mut db, "EXPLAIN SELECT COUNT(*) FROM todos").await else { panic!() };
+    assert_eq!(plan.access, Access::Count);
+    assert_eq!((plan.documents, plan.estimated_scanned), (3, 0));
+    std::fs::write(tmp.path().join("collections/todos/broken.md"), "---\ntitle: [unclosed\n---\n").unwrap();
+    assert!(matches!(exec(&mut db, "SELECT * FROM todos").await, QueryResult::Documents(docs) if docs.len() == 3));
+    let result = exec(&mut db, "SELECT COUNT(*) FROM todos").await;
+    assert!(matches!(result, QueryResult::Scalar(Value::Int(4))), "{:?}", result);
+
+    // Views still get a row with the count
+    exec(&mut db, "CREATE VIEW stats AS SELECT COUNT(*) AS n FROM todos").await;
+}
+
+#[tokio::test]
+async fn test_select_nested_fields() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION papers").await;
+    exec(&mut db, "CREATE COLLECTION people").await;
+    let dir = tmp.path().join("collections/papers");
+    std::fs::write(dir.join("radium.md"), "---\nmetadata:\n  author: marie\n  venue:\n    year: 1898\n---\n").unwrap();
+    std::fs::write(dir.join("x-rays.md"), "---\nmetadata:\n  author: wilhelm\n  venue:\n    year: 1895\n---\n").unwrap();
+    std::fs::write(dir.join("draft.md"), "---\nauthor: marie\n---\n").unwrap();
+    exec(&mut db, "INSERT INTO people (id, name) VALUES ('marie', 'Marie Curie')").await;
+
+    let QueryResult::Documents(docs) =
+        exec(&mut db, "SELECT metadata.author, metadata.venue.year FROM papers WHERE metadata.author = 'marie'").await
+    else {
+        panic!()
+    };
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].id, "radium");
+    assert_eq!(docs[0].fields["metadata.author"].as_str(), Some("marie"));
+    assert_eq!(docs[0].fields["metadata.venue.year"].as_i64(), Some(1898));
+
+    let QueryResult::Documents(docs) =
+        exec(&mut db, "SELECT * FROM papers WHERE metadata.venue.year > 1890 ORDER BY metadata.venue.year").await
+    else {
+        panic!()
+    };
+    let ids: Vec<&str> = docs.iter().map(|doc| doc.id.as_str()).collect();
+    assert_eq!(ids, vec!["x-rays", "radium"]);
+
+    // Table-qualified names still mean the table, in plain and joined queries
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT papers.author FROM papers WHERE papers.author = 'marie'").await
+    else {
+        panic!()
+    };
+    assert_eq!(docs[0].fields["author"].as_str(), Some("marie"));
+    let QueryResult::Documents(docs) = exec(
+        &mut db,
+        "SELECT p.@id, u.name FROM papers AS p JOIN people AS u ON p.metadata.author = u.@id",
+    )
+    .await
+    else {
+        panic!()
+    };
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].fields["name"].as_str(), Some("Marie Curie"));
+
+    // Views read paths and qualified names the same way
+    exec(&mut db, "CREATE VIEW dated AS SELECT * FROM papers WHERE papers.metadata.venue.year > 0 ORDER BY metadata.venue.year").await;
+    db.regenerate_views().await.unwrap();
+    let json = std::fs::read_to_string(tmp.path().join("views/dated/index.json")).unwrap();
+    let (x_rays, radium) = (json.find("x-rays").unwrap(), json.find("radium").unwrap());
+    assert!(x_rays < radium);
+    assert!(!json.contains("draft"));
+}
+
+#[tokio::test]
+async fn test_reindex_and_check_indexes() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (status STRING INDEXED)").await;
+    exec(&mut db, "INSERT INTO todos (id, status) VALUES ('a', 'open'), ('b', 'done')").await;
+    exec(&mut db, "SELECT * FROM todos WHERE status = 'open'").await;
+    assert!(db.check_indexes("todos").await.unwrap().is_empty());
+
+    // An edit keeping the file's size and modification time goes unnoticed
+    let path = tmp.path().join("collections/todos/b.md");
+    let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+    let content = std::fs::read_to_string(&path).unwrap().replace("done", "open");
+    std::fs::write(&path, content).unwrap();
+    std::fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM todos WHERE status = 'open'").await else {
+        panic!()
+    };
+    assert_eq!(docs.len(), 1);
+
+    let reports = db.check_indexes("todos").await.unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].field, "status");
+    assert_eq!(reports[0].drift, vec![mdby::storage::index::IndexDrift::Stale("b".into())]);
+
+    assert_eq!(db.reindex("todos").await.unwrap(), vec!["status"]);
+    assert!(db.check_indexes("todos").await.unwrap().is_empty());
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM todos WHERE status = 'open'").await else {
+        panic!()
+    };
+    assert_eq!(docs.len(), 2);
+}
+
+#[tokio::test]
+async fn test_functions_in_expressions() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "INSERT INTO notes (id, title, tags) VALUES ('a', 'Learning RUST', ['lang', 'systems'])").await;
+    exec(&mut db, "INSERT INTO notes (id, title, tags, due) VALUES ('b', 'Gardening', ['outdoors'], '2024-03-18T09:30:00Z')").await;
+
+    let QueryResult::Documents(docs) = exec(
+        &mut db,
+        "SELECT UPPER(title) AS loud, LENGTH(tags) AS n, COALESCE(due, 'never') AS due FROM notes WHERE lower(title) LIKE '%rust%'",
+    )
+    .await
+    else {
+        panic!()
+    };
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].fields["loud"].as_str(), Some("LEARNING RUST"));
+    assert_eq!(docs[0].fields["n"].as_i64(), Some(2));
+    assert_eq!(docs[0].fields["due"].as_str(), Some("never"));
+
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM notes WHERE DATE(due) = '2024-03-18'").await else {
+        panic!()
+    };
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].id, "b");
+
+    exec(&mut db, "UPDATE notes SET updated_at = NOW(), title = UPPER(title) WHERE id = 'a'").await;
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM notes WHERE id = 'a'").await else {
+        panic!()
+    };
+    let doc = &docs[0];
+    assert_eq!(doc.fields["title"].as_str(), Some("LEARNING RUST"));
+    let updated_at = doc.fields["updated_at"].as_str().unwrap();
+    assert!(updated_at.starts_with(&chrono::Utc::now().format("%Y-%m-%d").to_string()));
+
+    // Unknown functions and wrong argument counts are errors, not NULLs
+    let err = db.execute("SELECT * FROM notes WHERE SHOUT(title) = 'A'").await.unwrap_err();
+    assert!(err.to_string().contains("Unknown function 'SHOUT'"));
+    assert!(db.execute("UPDATE notes SET title = LOWER()").await.is_err());
+}
+
+#[tokio::test]
+async fn test_reverse_ref() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION projects").await;
+    exec(&mut db, "CREATE COLLECTION tasks").await;
+    exec(&mut db, "INSERT INTO projects (id, title) VALUES ('alpha', 'Alpha'), ('beta', 'Beta'), ('gamma', 'Gamma')").await;
+    exec(&mut db, "INSERT INTO tasks (id, title, project) VALUES ('write-docs', 'Write docs', 'alpha')").await;
+    exec(&mut db, "INSERT INTO tasks (id, title, project) VALUES ('fix-bug', 'Fix bug', ['alpha', 'beta'])").await;
+
+    let QueryResult::Documents(docs) =
+        exec(&mut db, "SELECT title, REVERSE_REF('tasks', 'project') AS tasks FROM projects ORDER BY title").await
+    else {
+        panic!()
+    };
+    let tasks = |doc: &mdby::Document| -> Vec<String> {
+        let ids = doc.fields["tasks"].as_array().unwrap();
+        ids.iter().map(|id| id.as_str().unwrap().to_string()).collect()
+    };
+    assert_eq!(tasks(&docs[0]), ["fix-bug", "write-docs"]);
+    assert_eq!(tasks(&docs[1]), ["fix-bug"]);
+    assert!(tasks(&docs[2]).is_empty());
+
+    // Usable in WHERE, without leaking into SELECT *
+    let QueryResult::Documents(docs) =
+        exec(&mut db, "SELECT * FROM projects WHERE LENGTH(REVERSE_REF('tasks', 'project')) = 0").await
+    else {
+        panic!()
+    };
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].id, "gamma");
+    assert!(docs[0].fields.keys().all(|key| key == "title"));
+
+    let err = db.execute("DELETE FROM projects WHERE LENGTH(REVERSE_REF('tasks', 'project')) = 0").await.unwrap_err();
+    assert!(err.to_string().contains("only be used in SELECT and views"));
+    assert!(db.execute("SELECT REVERSE_REF(title, 'project') FROM projects").await.is_err());
+
+    // Templates get the referencing documents themselves
+    std::fs::create_dir_all(tmp.path().join(".mdby/templates")).unwrap();
+    std::fs::write(
+        tmp.path().join(".mdby/templates/projects.html"),
+        "{% for doc in documents %}{{ doc.id }}:{% for task in doc.tasks %}{{ task.title }};{% endfor %}\n{% endfor %}",
+    )
+    .unwrap();
+    exec(
+        &mut db,
+        "CREATE VIEW board AS SELECT title, REVERSE_REF('tasks', 'project') AS tasks FROM projects ORDER BY title \
+         TEMPLATE 'projects.html'",
+    )
+    .await;
+    db.regenerate_views().await.unwrap();
+    let html = std::fs::read_to_string(tmp.path().join("views/board/index.html")).unwrap();
+    assert_eq!(html.trim(), "alpha:Fix bug;Write docs;\nbeta:Fix bug;\ngamma:");
+}
+
+#[tokio::test]
+async fn test_select_uses_indexes() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos (status STRING INDEXED, points INT INDEXED)").await;
+    exec(&mut db, "INSERT INTO todos (id, status, points) VALUES ('a', 'open', 3)").await;
+    exec(&mut db, "INSERT INTO todos (id, status, points) VALUES ('b', 'done', 5)").await;
+    exec(&mut db, "INSERT INTO todos (id, status, points) VALUES ('c', 'open', 8)").await;
+
+    async fn ids(db: &mut Database, query: &str) -> Vec<String> {
+        let QueryResult::Documents(docs) = exec(db, query).await else { panic!("Expected documents") };
+        let mut ids: Vec<String> = docs.into_iter().map(|d| d.id).collect();
+        ids.sort();
+        ids
+    }
+
+    assert_eq!(ids(&mut db, "SELECT * FROM todos WHERE status = 'open'").await, vec!["a", "c"]);
+    assert_eq!(ids(&mut db, "SELECT * FROM todos WHERE status = 'open' AND points > 5").await, vec!["c"]);
+    let index = tmp.path().join(".mdby/indexes/todos/status.idx");
+    assert!(index.exists());
+
+    // Writes keep the index current
+    exec(&mut db, "UPDATE todos SET status = 'open' WHERE @id = 'b'").await;
+    exec(&mut db, "DELETE FROM todos WHERE @id = 'a'").await;
+    assert_eq!(ids(&mut db, "SELECT * FROM todos WHERE status IN ('open')").await, vec!["b", "c"]);
+
+    // So do edits made outside MDBY
+    std::fs::write(tmp.path().join("collections/todos/c.md"), "---\nstatus: closed\npoints: 8\n---\n").unwrap();
+    assert_eq!(ids(&mut db, "SELECT * FROM todos WHERE status = 'open'").await, vec!["b"]);
+
+    // Index files aren't committed
+    let status = std::process::Command::new("git")
+        .args(["status", "--porcelain", "--ignored", ".mdby/indexes"])
+        .current_dir(tmp.path())
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&status.stdout).starts_with("!!"));
+
+    exec(&mut db, "DROP COLLECTION todos").await;
+    assert!(!index.exists());
+}
+
 // =============================================================================
 // UPDATE Tests
 // =============================================================================
@@ -398,6 +750,45 @@ async fn test_drop_collection() {
     assert!(result.is_err());
 }
 
+/// Files a statement would remove
+async fn removals(db: &Database, query: &str) -> Vec<std::path::PathBuf> {
+    db.removals(&mdql::parse(query).unwrap()).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_removals_preview() {
+    let (_tmp, mut db) = setup_test_db().await;
+    let paths = |list: &[&str]| list.iter().map(std::path::PathBuf::from).collect::<Vec<_>>();
+
+    exec(&mut db, "CREATE COLLECTION posts").await;
+    exec(&mut db, "INSERT INTO posts (id, draft) VALUES ('p1', true)").await;
+    exec(&mut db, "INSERT INTO posts (id, draft) VALUES ('p2', false)").await;
+    assert_eq!(removals(&db, "DELETE FROM posts").await, paths(&["collections/posts/p1.md", "collections/posts/p2.md"]));
+
+    exec(&mut db, "CREATE COLLECTION comments (post REF<posts> ON DELETE CASCADE)").await;
+    exec(&mut db, "INSERT INTO comments (id, post) VALUES ('c1', 'p1')").await;
+    exec(&mut db, "CREATE VIEW drafts AS SELECT * FROM posts WHERE draft = true").await;
+
+    // DELETE lists matching documents and those reached by ON DELETE CASCADE
+    assert_eq!(
+        removals(&db, "DELETE FROM posts WHERE draft = true").await,
+        paths(&["collections/comments/c1.md", "collections/posts/p1.md"])
+    );
+    assert_eq!(removals(&db, "DELETE FROM posts").await.len(), 3);
+    assert_eq!(
+        removals(&db, "DROP COLLECTION posts").await,
+        paths(&["collections/posts/p1.md", "collections/posts/p2.md"])
+    );
+    let view = removals(&db, "DROP VIEW drafts").await;
+    assert_eq!(view[0], std::path::PathBuf::from(".mdby/views/drafts.yaml"));
+    assert!(view[1..].iter().all(|p| p.starts_with("views/drafts")));
+    assert!(removals(&db, "SELECT * FROM posts").await.is_empty());
+
+    // Previewing changes nothing
+    assert!(_tmp.path().join("collections/posts/p1.md").exists());
+    assert!(!db.git.has_changes().unwrap());
+}
+
 // =============================================================================
 // VIEW Tests
 // =============================================================================
@@ -438,6 +829,571 @@ async fn test_drop_view() {
     assert!(!_tmp.path().join(".mdby/views/active.yaml").exists());
 }
 
+#[tokio::test]
+async fn test_regenerate_views_in_parallel() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "INSERT INTO todos (id, done) VALUES ('a', false)").await;
+    exec(&mut db, "INSERT INTO todos (id, done) VALUES ('b', true)").await;
+    exec(&mut db, "INSERT INTO notes (id) VALUES ('n')").await;
+    exec(&mut db, "CREATE VIEW open AS SELECT * FROM todos WHERE done = false").await;
+    exec(&mut db, "CREATE VIEW closed AS SELECT * FROM todos WHERE done = true").await;
+    exec(&mut db, "CREATE VIEW all_todos AS SELECT * FROM todos").await;
+    exec(&mut db, "CREATE VIEW all_notes AS SELECT * FROM notes").await;
+
+    db.config.view_parallelism = Some(2);
+    db.regenerate_views().await.unwrap();
+
+    let count = |view: &str| {
+        let json = std::fs::read_to_string(tmp.path().join("views").join(view).join("index.json")).unwrap();
+        serde_json::from_str::<Vec<serde_json::Value>>(&json).unwrap().len()
+    };
+    assert_eq!(count("open"), 1);
+    assert_eq!(count("closed"), 1);
+    assert_eq!(count("all_todos"), 2);
+    assert_eq!(count("all_notes"), 1);
+}
+
+#[tokio::test]
+#[allow(deprecated)]
+async fn test_regenerate_view_from_its_definition() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, done) VALUES ('a', false)").await;
+    exec(&mut db, "CREATE VIEW open AS SELECT * FROM todos WHERE done = false").await;
+    exec(&mut db, "INSERT INTO todos (id, done) VALUES ('b', false)").await;
+
+    mdby::views::regenerate_view(&db, &tmp.path().join(".mdby/views/open.yaml")).await.unwrap();
+
+    let json = std::fs::read_to_string(tmp.path().join("views/open/index.json")).unwrap();
+    assert_eq!(serde_json::from_str::<Vec<serde_json::Value>>(&json).unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_auto_regenerate_views() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "CREATE VIEW open AS SELECT * FROM todos WHERE done = false").await;
+    exec(&mut db, "CREATE VIEW all_notes AS SELECT * FROM notes").await;
+
+    let view = |name: &str| tmp.path().join("views").join(name).join("index.json");
+    let count = |name: &str| {
+        let json = std::fs::read_to_string(view(name)).unwrap();
+        serde_json::from_str::<Vec<serde_json::Value>>(&json).unwrap().len()
+    };
+
+    // Off by default
+    exec(&mut db, "INSERT INTO todos (id, done) VALUES ('a', false)").await;
+    assert!(!view("open").exists());
+
+    db.config.auto_regenerate_views = true;
+    exec(&mut db, "INSERT INTO todos (id, done) VALUES ('b', false)").await;
+    assert_eq!(count("open"), 2);
+    // Only views reading the changed collection
+    assert!(!view("all_notes").exists());
+
+    exec(&mut db, "UPDATE todos SET done = true WHERE id = 'a'").await;
+    assert_eq!(count("open"), 1);
+    exec(&mut db, "DELETE FROM todos WHERE id = 'b'").await;
+    assert_eq!(count("open"), 0);
+    exec(&mut db, "INSERT INTO notes (id) VALUES ('n')").await;
+    assert_eq!(count("all_notes"), 1);
+}
+
+#[tokio::test]
+async fn test_incremental_view_regeneration() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "CREATE COLLECTION snippets").await;
+    exec(&mut db, "INSERT INTO snippets (id) VALUES ('greeting') BODY 'Hello'").await;
+    exec(&mut db, "INSERT INTO notes (id) VALUES ('n') BODY '![[snippets/greeting]]'").await;
+    exec(&mut db, "CREATE VIEW open AS SELECT * FROM todos").await;
+    exec(&mut db, "CREATE VIEW all_notes AS SELECT * FROM notes").await;
+    db.regenerate_views().await.unwrap();
+
+    let state = std::fs::read_to_string(tmp.path().join(".mdby/state/views.yaml")).unwrap();
+    assert!(state.contains("all_notes:") && state.contains("- snippets"));
+    let QueryResult::Views(views) = exec(&mut db, "SHOW VIEWS").await else { panic!() };
+    assert_eq!(views, ["all_notes", "open"]);
+
+    // Mark both outputs to see which are rewritten
+    let output = |name: &str| tmp.path().join("views").join(name).join("index.html");
+    let mark = || {
+        for name in ["open", "all_notes"] {
+            std::fs::write(output(name), "stale").unwrap();
+        }
+    };
+    let rewritten = |name: &str| std::fs::read_to_string(output(name)).unwrap() != "stale";
+
+    mark();
+    db.regenerate_views().await.unwrap();
+    assert!(!rewritten("open") && !rewritten("all_notes"));
+
+    exec(&mut db, "INSERT INTO todos (id) VALUES ('a')").await;
+    db.regenerate_views().await.unwrap();
+    assert!(rewritten("open") && !rewritten("all_notes"));
+
+    // Collections read through embeds count, as do uncommitted edits
+    mark();
+    exec(&mut db, "UPDATE snippets SET body = 'Hi' WHERE id = 'greeting'").await;
+    db.regenerate_views().await.unwrap();
+    assert!(!rewritten("open") && rewritten("all_notes"));
+    mark();
+    std::fs::write(tmp.path().join("collections/todos/b.md"), "---\ntitle: B\n---\n").unwrap();
+    db.regenerate_views().await.unwrap();
+    assert!(rewritten("open") && !rewritten("all_notes"));
+
+    mark();
+    db.rebuild_views().await.unwrap();
+    assert!(rewritten("open") && rewritten("all_notes"));
+
+    // The state isn't among the view definitions, so it isn't listed as a
+    // view and doesn't take a name
+    let listed = std::process::Command::new(env!("CARGO_BIN_EXE_mdby"))
+        .args(["--format", "json", "views"])
+        .current_dir(tmp.path())
+        .output()
+        .unwrap();
+    let listed: serde_json::Value = serde_json::from_slice(&listed.stdout).unwrap();
+    let mut names: Vec<&str> = listed.as_array().unwrap().iter().filter_map(|view| view["name"].as_str()).collect();
+    names.sort();
+    assert_eq!(names, ["all_notes", "open"]);
+    exec(&mut db, "CREATE VIEW state AS SELECT * FROM todos").await;
+    let QueryResult::Definition(definition) = exec(&mut db, "SHOW CREATE VIEW state").await else { panic!() };
+    assert!(definition.starts_with("CREATE VIEW state AS"), "{}", definition);
+    db.regenerate_views().await.unwrap();
+    assert!(output("state").exists());
+}
+
+#[tokio::test]
+async fn test_view_formats() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING, priority INT)").await;
+    exec(&mut db, "INSERT INTO todos (id, title, priority, tags) VALUES ('a', 'Buy milk', 2, ['home', 'errand']) BODY 'Oat, please'").await;
+    exec(&mut db, "INSERT INTO todos (id, title, priority) VALUES ('b', 'File taxes', 1)").await;
+    exec(&mut db, "CREATE VIEW report AS SELECT * FROM todos ORDER BY id FORMATS ('markdown', 'csv')").await;
+    db.regenerate_views().await.unwrap();
+
+    let dir = tmp.path().join("views/report");
+    let markdown = std::fs::read_to_string(dir.join("index.md")).unwrap();
+    assert!(markdown.starts_with("# report\n\n## Buy milk\n"));
+    assert!(markdown.contains("- **priority**: 2\n- **tags**: home, errand\n\nOat, please\n"));
+    assert!(markdown.contains("## File taxes"));
+    let csv = std::fs::read_to_string(dir.join("index.csv")).unwrap();
+    assert_eq!(csv, "id,title,priority,tags,body\na,Buy milk,2,home;errand,\"Oat, please\"\nb,File taxes,1,,\n");
+    assert!(!dir.join("index.html").exists());
+    assert!(!dir.join("index.json").exists());
+
+    let QueryResult::Definition(definition) = exec(&mut db, "SHOW CREATE VIEW report").await else {
+        panic!("Expected a definition");
+    };
+    assert!(definition.ends_with("FORMATS ('markdown', 'csv')"));
+
+    // Outputs of formats no longer listed are removed
+    exec(&mut db, "DROP VIEW report").await;
+    exec(&mut db, "CREATE VIEW report AS SELECT * FROM todos FORMATS ('html')").await;
+    db.regenerate_views().await.unwrap();
+    assert!(dir.join("index.html").exists());
+    assert!(!dir.join("index.csv").exists());
+    assert!(!dir.join("index.md").exists());
+
+    let duplicate = db.execute("CREATE VIEW twice AS SELECT * FROM todos FORMATS ('csv', 'CSV')").await.unwrap_err();
+    assert!(duplicate.to_string().contains("listed more than once"));
+}
+
+#[tokio::test]
+async fn test_view_feeds() {
+    let (tmp, db) = setup_test_db().await;
+    drop(db);
+    std::fs::create_dir_all(tmp.path().join(".mdby")).unwrap();
+    std::fs::write(tmp.path().join(".mdby/config.yaml"), "name: Notebook\nsite_url: https://blog.example.com\n").unwrap();
+    let mut db = Database::open(tmp.path()).await.unwrap();
+    exec(&mut db, "CREATE COLLECTION posts (headline STRING, published DATE, draft BOOL)").await;
+    exec(&mut db, "INSERT INTO posts (id, headline, published, draft) VALUES ('hello', 'Hello <world>', '2024-03-01', false) BODY 'First *post*'").await;
+    exec(&mut db, "INSERT INTO posts (id, headline, published, draft) VALUES ('later', 'Later', '2024-04-01', false)").await;
+    exec(&mut db, "INSERT INTO posts (id, headline, draft) VALUES ('wip', 'WIP', true)").await;
+    exec(
+        &mut db,
+        "CREATE VIEW blog AS SELECT * FROM posts WHERE draft = false ORDER BY published DESC \
+         FORMATS ('html', 'rss') FEED (title = headline, date = published)",
+    )
+    .await;
+    db.regenerate_views().await.unwrap();
+
+    let dir = tmp.path().join("views/blog");
+    assert!(dir.join("index.html").exists());
+    let rss = std::fs::read_to_string(dir.join("feed.xml")).unwrap();
+    assert!(rss.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rss version=\"2.0\">"));
+    assert!(rss.contains("<title>Notebook: blog</title>"));
+    assert!(rss.contains("<link>https://blog.example.com/blog/#hello</link>"));
+    assert!(rss.contains("<title>Hello &lt;world&gt;</title>"));
+    assert!(rss.contains("<pubDate>Fri, 1 Mar 2024 00:00:00 +0000</pubDate>"));
+    assert!(rss.contains("First &lt;em&gt;post&lt;/em&gt;"));
+    assert!(rss.find("<title>Later</title>").unwrap() < rss.find("<title>Hello").unwrap());
+    assert!(!rss.contains("WIP"));
+
+    let QueryResult::Definition(definition) = exec(&mut db, "SHOW CREATE VIEW blog").await else {
+        panic!("Expected a definition");
+    };
+    assert!(definition.ends_with("FORMATS ('html', 'rss') FEED (title = headline, date = published)"));
+
+    exec(&mut db, "DROP VIEW blog").await;
+    exec(&mut db, "CREATE VIEW blog AS SELECT * FROM posts FORMATS ('atom') FEED (title = headline, date = published)").await;
+    db.regenerate_views().await.unwrap();
+    let atom = std::fs::read_to_string(dir.join("feed.xml")).unwrap();
+    assert!(atom.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\">"));
+    assert!(atom.contains("<id>https://blog.example.com/blog/#later</id>"));
+    assert!(!dir.join("index.html").exists());
+
+    let both = db.execute("CREATE VIEW both AS SELECT * FROM posts FORMATS ('rss', 'atom')").await.unwrap_err();
+    assert!(both.to_string().contains("not both"));
+    let no_feed = db.execute("CREATE VIEW plain AS SELECT * FROM posts FEED (date = published)").await.unwrap_err();
+    assert!(no_feed.to_string().contains("list 'rss' or 'atom' in FORMATS"));
+}
+
+#[tokio::test]
+async fn test_view_parameters() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "INSERT INTO notes (id, tags, priority) VALUES ('a', ['rust', 'db'], 1)").await;
+    exec(&mut db, "INSERT INTO notes (id, tags, priority) VALUES ('b', ['rust'], 3)").await;
+    exec(&mut db, "INSERT INTO notes (id, tags, priority) VALUES ('c', ['go'], 3)").await;
+    exec(&mut db, "CREATE VIEW by_tag(tag STRING, min INT) AS SELECT * FROM notes WHERE HAS TAG :tag AND priority >= :min ORDER BY id").await;
+
+    let params = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    };
+    let ids = |json: &str| -> Vec<String> {
+        let docs: Vec<serde_json::Value> = serde_json::from_str(json).unwrap();
+        docs.iter().map(|doc| doc["id"].as_str().unwrap().to_string()).collect()
+    };
+    let rendered = db.render_view("by_tag", &params(&[("tag", "rust"), ("min", "1")])).await.unwrap();
+    assert_eq!(ids(rendered.get(OutputFormat::Json).unwrap()), ["a", "b"]);
+    assert!(rendered.get(OutputFormat::Html).unwrap().contains("<html"));
+    let rendered = db.render_view("by_tag", &params(&[("tag", "rust"), ("min", "2")])).await.unwrap();
+    assert_eq!(ids(rendered.get(OutputFormat::Json).unwrap()), ["b"]);
+    let rendered = db.render_view("by_tag", &params(&[("tag", "go"), ("min", "0")])).await.unwrap();
+    assert_eq!(ids(rendered.get(OutputFormat::Json).unwrap()), ["c"]);
+    let wrong = db.render_view("by_tag", &params(&[("tag", "rust"), ("min", "lots")])).await.unwrap_err();
+    assert!(wrong.to_string().contains("must be INT"));
+    let missing = db.render_view("by_tag", &params(&[("tag", "rust")])).await.unwrap_err();
+    assert!(missing.to_string().contains("Missing a value for parameter 'min'"));
+
+    // Only rendered on demand
+    db.regenerate_views().await.unwrap();
+    assert!(!tmp.path().join("views/by_tag").exists());
+
+    let QueryResult::Definition(definition) = exec(&mut db, "SHOW CREATE VIEW by_tag").await else {
+        panic!("Expected a definition");
+    };
+    assert!(definition.starts_with("CREATE VIEW by_tag(tag STRING, min INT) AS"));
+
+    let undeclared = db.execute("CREATE VIEW v(tag STRING) AS SELECT * FROM notes WHERE priority > :min").await.unwrap_err();
+    assert!(undeclared.to_string().contains("Parameter :min is not declared"));
+    let outside = db.execute("SELECT * FROM notes WHERE HAS TAG :tag").await.unwrap_err();
+    assert!(outside.to_string().contains("can only be used in a view"));
+}
+
+#[tokio::test]
+async fn test_select_from_view() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, done, priority) VALUES ('a', false, 5), ('b', false, 1), ('c', true, 5), ('d', false, 4)").await;
+    exec(&mut db, "CREATE VIEW active_todos AS SELECT * FROM todos WHERE done = false ORDER BY id").await;
+
+    let ids = |result: QueryResult| -> Vec<String> {
+        let QueryResult::Documents(docs) = result else { panic!("Expected documents") };
+        docs.into_iter().map(|doc| doc.id).collect()
+    };
+    assert_eq!(ids(exec(&mut db, "SELECT * FROM view:active_todos WHERE priority > 3").await), ["a", "d"]);
+    assert_eq!(ids(exec(&mut db, "SELECT * FROM view:active_todos ORDER BY priority LIMIT 1").await), ["b"]);
+
+    // Views over views
+    exec(&mut db, "CREATE VIEW urgent AS SELECT * FROM view:active_todos WHERE priority > 3").await;
+    assert_eq!(ids(exec(&mut db, "SELECT * FROM view:urgent WHERE priority = 4").await), ["d"]);
+    let QueryResult::Plan(plan) = exec(&mut db, "EXPLAIN SELECT * FROM view:urgent").await else {
+        panic!("Expected a plan");
+    };
+    assert_eq!(plan.access, mdby::query::plan::Access::View);
+    assert_eq!(plan.documents, 2);
+
+    db.regenerate_views().await.unwrap();
+    let json = std::fs::read_to_string(tmp.path().join("views/urgent/index.json")).unwrap();
+    assert!(json.contains("\"d\"") && !json.contains("\"b\""), "{}", json);
+
+    // A view reading the documents it's selected over shows their changes
+    exec(&mut db, "UPDATE todos SET done = true WHERE id = 'd'").await;
+    db.regenerate_views().await.unwrap();
+    let json = std::fs::read_to_string(tmp.path().join("views/urgent/index.json")).unwrap();
+    assert!(!json.contains("\"d\""), "{}", json);
+
+    // Cycles are refused, however long
+    let err = db.execute("CREATE VIEW loop AS SELECT * FROM view:loop").await.unwrap_err();
+    assert!(err.to_string().contains("cycle"), "{}", err);
+    exec(&mut db, "DROP VIEW active_todos").await;
+    let err = db.execute("CREATE VIEW active_todos AS SELECT * FROM view:urgent").await.unwrap_err();
+    assert!(err.to_string().contains("active_todos -> urgent -> active_todos"), "{}", err);
+
+    let missing = db.execute("SELECT * FROM view:urgent").await.unwrap_err();
+    assert!(missing.to_string().contains("View 'active_todos' does not exist"), "{}", missing);
+
+    exec(&mut db, "CREATE VIEW by_priority(min INT) AS SELECT * FROM todos WHERE priority >= :min").await;
+    let err = db.execute("SELECT * FROM view:by_priority").await.unwrap_err();
+    assert!(err.to_string().contains("has parameters"), "{}", err);
+
+    // The policy applies to the view's own query
+    exec(&mut db, "CREATE VIEW everything AS SELECT * FROM todos").await;
+    db.set_policy(ExecutionPolicy::default().deny_collection("todos"));
+    assert!(db.execute("SELECT * FROM view:everything").await.is_err());
+}
+
+#[tokio::test]
+async fn test_field_labels() {
+    use mdby::schema::{FieldDef, FieldType, Schema};
+
+    let (tmp, mut db) = setup_test_db().await;
+    let labelled = |label: &str, french: &str, field_type: FieldType| FieldDef {
+        field_type,
+        label: Some(label.into()),
+        labels: BTreeMap::from([("fr".to_string(), french.to_string())]),
+        ..FieldDef::default()
+    };
+    let schema = Schema::new("todos")
+        .field("title", FieldDef::default())
+        .field("priority", labelled("Priority", "Priorité", FieldType::Int))
+        .field("tags", labelled("Tags", "Étiquettes", FieldType::Array(Box::new(FieldType::String))));
+    db.save_schema(schema).unwrap();
+    exec(&mut db, "INSERT INTO todos (id, title, priority, tags) VALUES ('a', 'Write docs', 3, ['docs'])").await;
+
+    let QueryResult::Description(description) = exec(&mut db, "DESCRIBE todos").await else {
+        panic!("Expected a description");
+    };
+    assert_eq!(description.fields[0].label, None);
+    assert_eq!(description.fields[1].label.as_deref(), Some("Priority"));
+    assert_eq!(db.field_labels("todos")["tags"], "Tags");
+
+    // The configured locale picks the label, falling back to `label`
+    db.config.locale = Some("fr-CA".into());
+    assert_eq!(db.field_labels("todos")["priority"], "Priorité");
+    let QueryResult::Description(description) = exec(&mut db, "DESCRIBE todos").await else {
+        panic!("Expected a description");
+    };
+    assert_eq!(description.fields[2].label.as_deref(), Some("Étiquettes"));
+
+    exec(&mut db, "CREATE VIEW board AS SELECT * FROM todos FORMATS ('html', 'markdown')").await;
+    db.regenerate_views().await.unwrap();
+    let html = std::fs::read_to_string(tmp.path().join("views/board/index.html")).unwrap();
+    assert!(html.contains("Étiquettes: docs"), "{}", html);
+    let markdown = std::fs::read_to_string(tmp.path().join("views/board/index.md")).unwrap();
+    assert!(markdown.contains("- **Priorité**: 3") && markdown.contains("- **title**: Write docs"), "{}", markdown);
+
+    // Labels are for display; queries and data keep the field names
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM todos WHERE priority = 3").await else {
+        panic!("Expected documents");
+    };
+    assert!(docs[0].fields.contains_key("priority"));
+}
+
+#[tokio::test]
+async fn test_view_render_hook() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "INSERT INTO notes (id) VALUES ('n') BODY 'graph'").await;
+
+    // Hooks must be declared in the config
+    assert!(db.execute("CREATE VIEW diagrams AS SELECT * FROM notes RENDER WITH upper").await.is_err());
+
+    db.config.render_hooks.insert(
+        "upper".into(),
+        mdby::config::RenderHook { command: "tr".into(), args: vec!["a-z".into(), "A-Z".into()] },
+    );
+    exec(&mut db, "CREATE VIEW diagrams AS SELECT * FROM notes RENDER WITH upper").await;
+    db.regenerate_views().await.unwrap();
+
+    let html = std::fs::read_to_string(tmp.path().join("views/diagrams/index.html")).unwrap();
+    assert!(html.contains("GRAPH"), "{}", html);
+    // Cached output stays out of the database's git history
+    let ignore = std::fs::read_to_string(tmp.path().join(".mdby/cache/render/.gitignore")).unwrap();
+    assert_eq!(ignore.trim(), "*");
+
+    let result = exec(&mut db, "SHOW CREATE VIEW diagrams").await;
+    assert!(matches!(result, QueryResult::Definition(def) if def.ends_with("RENDER WITH upper")));
+}
+
+#[tokio::test]
+async fn test_view_delivery() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, title, done) VALUES ('t1', 'File taxes', false)").await;
+
+    // Targets must be declared in the config, and schedules must parse
+    let create = "CREATE VIEW overdue AS SELECT * FROM todos WHERE done = false DELIVER TO digest SCHEDULE 'daily 07:00'";
+    assert!(db.execute(create).await.is_err());
+    db.config.delivery_targets.insert(
+        "digest".into(),
+        mdby::config::DeliveryTarget::Command {
+            command: "sh".into(),
+            args: vec!["-c".into(), "cat >> delivered.html".into()],
+            to: Some("me@example.com".into()),
+        },
+    );
+    assert!(db.execute("CREATE VIEW v AS SELECT * FROM todos DELIVER TO digest SCHEDULE 'sometimes'").await.is_err());
+    exec(&mut db, create).await;
+
+    let result = exec(&mut db, "SHOW CREATE VIEW overdue").await;
+    assert!(matches!(result, QueryResult::Definition(def) if def.ends_with("DELIVER TO digest SCHEDULE 'daily 07:00'")));
+
+    db.deliver_view("overdue").await.unwrap();
+    let delivered = std::fs::read_to_string(tmp.path().join("delivered.html")).unwrap();
+    assert!(delivered.starts_with("To: me@example.com\nSubject: overdue\n"), "{}", delivered);
+    assert!(delivered.contains("File taxes"));
+
+    // 2024-01-15 06:30 UTC: first seen, so only armed
+    let monday = 1_705_300_200;
+    assert!(db.deliver_due_views(monday).await.unwrap().is_empty());
+    // 07:00 passes
+    let delivered = db.deliver_due_views(monday + 3600).await.unwrap();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].0, "overdue");
+    assert!(delivered[0].1.is_ok());
+    // Not again until the next morning
+    assert!(db.deliver_due_views(monday + 7200).await.unwrap().is_empty());
+    let delivered = std::fs::read_to_string(tmp.path().join("delivered.html")).unwrap();
+    assert_eq!(delivered.matches("Subject: overdue").count(), 2);
+}
+
+#[tokio::test]
+async fn test_view_mermaid_and_math() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "INSERT INTO notes (id) VALUES ('n') BODY '```mermaid\ngraph TD\n```\n\nEnergy $E = mc^2$'").await;
+    exec(&mut db, "CREATE VIEW technical AS SELECT * FROM notes WITH MERMAID, MATH").await;
+    exec(&mut db, "CREATE VIEW plain AS SELECT * FROM notes").await;
+    db.regenerate_views().await.unwrap();
+
+    let html = std::fs::read_to_string(tmp.path().join("views/technical/index.html")).unwrap();
+    assert!(html.contains("<pre class=\"mermaid\">graph TD"), "{}", html);
+    assert!(html.contains("\\(E = mc^2\\)"));
+    assert!(html.contains("mermaid.esm.min.mjs") && html.contains("katex.min.js"));
+
+    let plain = std::fs::read_to_string(tmp.path().join("views/plain/index.html")).unwrap();
+    assert!(plain.contains("language-mermaid") && !plain.contains("<script"));
+
+    let result = exec(&mut db, "SHOW CREATE VIEW technical").await;
+    assert!(matches!(result, QueryResult::Definition(def) if def.ends_with("WITH MERMAID, MATH")));
+}
+
+#[tokio::test]
+async fn test_view_transclusion() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION daily").await;
+    exec(&mut db, "CREATE COLLECTION reviews").await;
+    exec(&mut db, "INSERT INTO daily (id) VALUES ('mon') BODY 'Shipped the **parser**'").await;
+    exec(&mut db, "INSERT INTO reviews (id) VALUES ('week-1') BODY '# Week 1
+
+![[daily/mon]]
+
+![[week-1]]'").await;
+    exec(&mut db, "CREATE VIEW weekly AS SELECT * FROM reviews").await;
+    db.regenerate_views().await.unwrap();
+
+    let html = std::fs::read_to_string(tmp.path().join("views/weekly/index.html")).unwrap();
+    assert!(html.contains("Shipped the <strong>parser</strong>"), "{}", html);
+    // A self-embed is a cycle and stays as written
+    assert!(html.contains("![[week-1]]"));
+
+    // JSON output keeps the original body
+    let json = std::fs::read_to_string(tmp.path().join("views/weekly/index.json")).unwrap();
+    assert!(json.contains("![[daily/mon]]"));
+}
+
+#[tokio::test]
+async fn test_select_related_to() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "INSERT INTO notes (id, title, draft) VALUES ('rust-intro', 'Intro to Rust', false) BODY 'Ownership and borrowing in Rust'").await;
+    exec(&mut db, "INSERT INTO notes (id, title, draft) VALUES ('rust-borrow', 'The borrow checker', false) BODY 'Rust borrowing rules and lifetimes'").await;
+    exec(&mut db, "INSERT INTO notes (id, title, draft) VALUES ('rust-cargo', 'Cargo', true) BODY 'Building Rust crates'").await;
+    exec(&mut db, "INSERT INTO notes (id, title, draft) VALUES ('baking', 'Sourdough', false) BODY 'Flour, water and a starter'").await;
+
+    let result = exec(&mut db, "SELECT * FROM notes RELATED TO 'rust-intro' LIMIT 5").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected documents") };
+    let ids: Vec<&str> = docs.iter().map(|d| d.id.as_str()).collect();
+    assert_eq!(ids, vec!["rust-borrow", "rust-cargo"]);
+
+    let result = exec(&mut db, "SELECT * FROM notes RELATED TO 'rust-intro' WHERE draft = false").await;
+    assert!(matches!(result, QueryResult::Documents(ref docs) if docs.len() == 1 && docs[0].id == "rust-borrow"));
+    assert!(db.execute("SELECT * FROM notes RELATED TO 'missing'").await.is_err());
+
+    // Views can list each document's related documents
+    std::fs::create_dir_all(tmp.path().join(".mdby/templates")).unwrap();
+    std::fs::write(
+        tmp.path().join(".mdby/templates/related.html"),
+        "{% for doc in documents %}{{ doc.id }}:{% for r in doc.related %}{{ r.title }};{% endfor %}\n{% endfor %}",
+    ).unwrap();
+    exec(&mut db, "CREATE VIEW linked AS SELECT * FROM notes WHERE @id = 'rust-intro' TEMPLATE 'related.html' WITH RELATED").await;
+    db.regenerate_views().await.unwrap();
+
+    let html = std::fs::read_to_string(tmp.path().join("views/linked/index.html")).unwrap();
+    assert_eq!(html.trim(), "rust-intro:The borrow checker;Cargo;");
+}
+
+/// Counts of a few topic words
+struct TopicEmbedder;
+
+impl EmbeddingProvider for TopicEmbedder {
+    fn model(&self) -> String {
+        "topics".to_string()
+    }
+
+    fn embed(&self, text: &str) -> anyhow::Result<Vec<f32>> {
+        let text = text.to_lowercase();
+        Ok(["branch", "merge", "bread"].iter().map(|w| text.matches(w).count() as f32).collect())
+    }
+}
+
+#[tokio::test]
+async fn test_semantic_search() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "INSERT INTO notes (id, title) VALUES ('branching', 'Branches') BODY 'Create a branch, then merge the branch'").await;
+    exec(&mut db, "INSERT INTO notes (id, title) VALUES ('merging', 'Merging') BODY 'Resolve merge conflicts'").await;
+    exec(&mut db, "INSERT INTO notes (id, title) VALUES ('baking', 'Baking') BODY 'Knead the bread'").await;
+
+    // No provider configured
+    assert!(db.execute("SELECT * FROM notes SEMANTIC_SEARCH('branches')").await.is_err());
+
+    db.set_embedding_provider(TopicEmbedder);
+    let result = exec(&mut db, "SELECT * FROM notes SEMANTIC_SEARCH('branch and merge') LIMIT 2").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected documents") };
+    let ids: Vec<&str> = docs.iter().map(|d| d.id.as_str()).collect();
+    assert_eq!(ids, vec!["branching", "merging"]);
+    assert!(tmp.path().join(".mdby/embeddings/notes/baking.json").exists());
+
+    // Stored embeddings stay out of git
+    assert!(!db.git.has_changes().unwrap());
+
+    // A command declared in the config works as a provider
+    let mut db = Database::open(tmp.path()).await.unwrap();
+    db.config.embeddings = Some(mdby::config::EmbeddingCommand {
+        command: "sh".to_string(),
+        args: vec!["-c".to_string(), "cat > /dev/null; echo '[1, 0]'".to_string()],
+        model: None,
+    });
+    let result = exec(&mut db, "SELECT * FROM notes SEMANTIC_SEARCH('anything')").await;
+    assert!(matches!(result, QueryResult::Documents(ref docs) if docs.len() == 3));
+}
+
 // =============================================================================
 // Security Tests
 // =============================================================================
@@ -475,6 +1431,39 @@ async fn test_path_traversal_in_collection_name_blocked() {
     assert!(result.is_err());
 }
 
+#[tokio::test]
+async fn test_execution_policy_restricts_statements() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "CREATE COLLECTION secrets").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('task-1', 'Visible')").await;
+
+    db.set_policy(ExecutionPolicy::read_only().deny_collection("secrets"));
+    let result = exec(&mut db, "SELECT * FROM todos").await;
+    assert!(matches!(result, QueryResult::Documents(ref docs) if docs.len() == 1));
+
+    let err = db.execute("DROP COLLECTION todos").await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<mdby::Error>(),
+        Some(mdby::Error::StatementDenied { statement: "DROP COLLECTION" })
+    ));
+    assert!(tmp.path().join("collections/todos").exists());
+
+    let err = db.execute("SELECT * FROM secrets").await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<mdby::Error>(), Some(mdby::Error::CollectionDenied { .. })));
+
+    // REVERSE_REF reads the collection it names
+    let err = db.execute("SELECT REVERSE_REF('secrets', 'owner') AS keys FROM todos").await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<mdby::Error>(),
+        Some(mdby::Error::CollectionDenied { collection, .. }) if collection == "secrets"
+    ));
+
+    db.set_policy(ExecutionPolicy::default().deny(StatementKind::Delete));
+    exec(&mut db, "INSERT INTO secrets (id) VALUES ('key')").await;
+    assert!(db.execute("DELETE FROM secrets").await.is_err());
+}
+
 // =============================================================================
 // Git Integration Tests
 // =============================================================================
@@ -495,151 +1484,3112 @@ async fn test_operations_create_git_commits() {
     assert_ne!(after_create, after_insert);
 }
 
-// =============================================================================
-// Edge Cases
-// =============================================================================
-
 #[tokio::test]
-async fn test_special_characters_in_string_values() {
-    let (_tmp, mut db) = setup_test_db().await;
+async fn test_select_commits_system_collection() {
+    let (tmp, mut db) = setup_test_db().await;
 
     exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('task-1', 'Test')").await;
 
-    // Test various special characters in values (not IDs)
-    exec(&mut db, r#"INSERT INTO todos (id, title) VALUES ('task-1', 'Test with "quotes"')"#).await;
+    let result = exec(&mut db, "SELECT * FROM @commits LIMIT 1").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents") };
+    assert_eq!(docs[0].id, db.git.head_hash().unwrap());
+    assert_eq!(docs[0].get("message").and_then(|v| v.as_str()), Some("INSERT into todos: task-1"));
+    assert!(docs[0].get("date").and_then(|v| v.as_str()).is_some_and(|d| d.ends_with('Z')));
+    assert!(matches!(docs[0].get("files"), Some(mdby::storage::document::Value::Array(files)) if files.len() == 1));
 
-    let result = exec(&mut db, "SELECT * FROM todos").await;
-    if let QueryResult::Documents(docs) = result {
-        assert_eq!(docs.len(), 1);
-    } else {
-        panic!("Expected Documents");
-    }
+    let result = exec(&mut db, "SELECT * FROM @commits WHERE message LIKE 'CREATE%'").await;
+    assert!(matches!(result, QueryResult::Documents(docs) if docs.len() == 1));
+
+    // Views can read system collections
+    exec(&mut db, "CREATE VIEW activity AS SELECT * FROM @commits").await;
+    db.regenerate_views().await.unwrap();
+    let html = std::fs::read_to_string(tmp.path().join("views/activity/index.html")).unwrap();
+    assert!(html.contains("Initialize MDBY database"));
+
+    assert!(db.execute("SELECT * FROM @unknown").await.is_err());
+    assert!(db.execute("DELETE FROM @commits").await.is_err());
 }
 
 #[tokio::test]
-async fn test_numeric_string_id() {
+async fn test_select_collections_system_collection() {
+    use mdby::storage::document::Value;
     let (_tmp, mut db) = setup_test_db().await;
 
-    exec(&mut db, "CREATE COLLECTION todos").await;
-    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('123', 'Numeric ID')").await;
-
-    let result = exec(&mut db, "SELECT * FROM todos WHERE id = '123'").await;
-    if let QueryResult::Documents(docs) = result {
-        assert_eq!(docs.len(), 1);
-    } else {
-        panic!("Expected Documents");
-    }
+    exec(&mut db, "CREATE COLLECTION todos (title STRING)").await;
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('a', 'A')").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('b', 'B')").await;
+
+    let result = exec(&mut db, "SELECT * FROM @collections ORDER BY name").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents") };
+    assert_eq!(docs.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(), vec!["notes", "todos"]);
+
+    let (notes, todos) = (&docs[0], &docs[1]);
+    assert_eq!(todos.get("documents"), Some(&Value::Int(2)));
+    assert!(todos.get("bytes").and_then(|v| v.as_i64()).is_some_and(|bytes| bytes > 0));
+    assert_eq!(todos.get("schema"), Some(&Value::Bool(true)));
+    assert!(todos.get("modified").and_then(|v| v.as_str()).is_some_and(|d| d.ends_with('Z')));
+    let Some(Value::Array(activity)) = todos.get("activity") else { panic!("Expected activity") };
+    assert_eq!(activity.len(), mdby::system::ACTIVITY_WEEKS);
+    assert_eq!(activity.last(), Some(&Value::Int(2)));
+
+    assert_eq!(notes.get("documents"), Some(&Value::Int(0)));
+    assert_eq!(notes.get("schema"), Some(&Value::Bool(false)));
+    assert_eq!(notes.get("modified"), Some(&Value::Null));
+
+    let result = exec(&mut db, "SELECT name FROM @collections WHERE documents > 1").await;
+    assert!(matches!(result, QueryResult::Documents(docs) if docs.len() == 1));
 }
 
-// =============================================================================
-// Schema Type Validation Tests
-// =============================================================================
-
 #[tokio::test]
-async fn test_schema_type_validation_int_field() {
+async fn test_sync_unreachable_remote_queues_commits() {
     let (_tmp, mut db) = setup_test_db().await;
+    db.git.inner().remote("origin", "http://127.0.0.1:9/remote.git").unwrap();
 
-    // Create collection with INT field
-    exec(&mut db, "CREATE COLLECTION items (name STRING, count INT)").await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('task-1', 'Offline')").await;
 
-    // Valid: insert with correct types
-    let result = db.execute("INSERT INTO items (id, name, count) VALUES ('item-1', 'Widget', 42)").await;
-    assert!(result.is_ok());
+    let result = db.sync("origin", None).await.unwrap();
+    assert!(result.offline);
+    assert_eq!(result.queued, db.git.pending("origin").unwrap());
+    assert!(result.queued >= 2);
+    assert_eq!(result.pushed, 0);
+}
 
-    // Invalid: string value for INT field
-    let result = db.execute("INSERT INTO items (id, name, count) VALUES ('item-2', 'Gadget', 'not-a-number')").await;
-    assert!(result.is_err());
-    let err_msg = result.unwrap_err().to_string();
-    assert!(err_msg.contains("TypeMismatch") || err_msg.contains("type") || err_msg.contains("expected"));
+#[tokio::test]
+async fn test_sync_merges_conflicting_documents() {
+    let tmp = TempDir::new().unwrap();
+    let bare = tmp.path().join("remote.git");
+    git2::Repository::init_bare(&bare).unwrap();
+
+    let mut a = Database::open(tmp.path().join("a")).await.unwrap();
+    a.git.inner().remote("origin", bare.to_str().unwrap()).unwrap();
+    exec(&mut a, "CREATE COLLECTION todos").await;
+    exec(&mut a, "INSERT INTO todos (id, title, priority) VALUES ('task-1', 'Draft', 1) BODY 'Notes'").await;
+    a.sync("origin", None).await.unwrap();
+
+    git2::Repository::clone(bare.to_str().unwrap(), tmp.path().join("b")).unwrap();
+    let mut b = Database::open(tmp.path().join("b")).await.unwrap();
+
+    // Both sides edit the same document
+    exec(&mut a, "UPDATE todos SET title = 'Final' WHERE id = 'task-1'").await;
+    a.sync("origin", None).await.unwrap();
+    exec(&mut b, "UPDATE todos SET priority = 5 WHERE id = 'task-1'").await;
+
+    let result = b.sync("origin", None).await.unwrap();
+    assert_eq!(result.conflicts.len(), 1);
+    assert_eq!(result.conflicts_resolved, vec!["collections/todos/task-1.md"]);
+    assert_eq!(result.pushed, 1);
+    assert!(tmp.path().join("b").join(&result.conflicts[0].path).exists());
+    assert!(!b.git.has_changes().unwrap());
+
+    let result = exec(&mut b, "SELECT * FROM todos").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected documents") };
+    assert_eq!(docs[0].get("title").and_then(|v| v.as_str()), Some("Final"));
+    assert_eq!(docs[0].get("priority").and_then(|v| v.as_i64()), Some(5));
+
+    // The other side receives the merge, including the conflict record
+    let result = a.sync("origin", None).await.unwrap();
+    assert_eq!(result.pulled, 1);
+    let result = exec(&mut a, "SELECT * FROM todos").await;
+    assert!(matches!(result, QueryResult::Documents(ref docs) if docs[0].get("priority").and_then(|v| v.as_i64()) == Some(5)));
+    assert!(std::fs::read_dir(tmp.path().join("a/.mdby/conflicts")).unwrap().count() == 1);
 }
 
 #[tokio::test]
-async fn test_schema_type_validation_bool_field() {
-    let (_tmp, mut db) = setup_test_db().await;
+async fn test_proposals_flow() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
 
-    // Create collection with BOOL field
-    exec(&mut db, "CREATE COLLECTION flags (name STRING, enabled BOOL)").await;
+    std::fs::write(
+        tmp.path().join("collections/todos/task-1.md"),
+        "---\ntitle: Proposed\n---\n",
+    )
+    .unwrap();
+    let proposal = db.propose("Add task 1", "origin").unwrap();
+    assert!(!proposal.pushed);
+    let result = exec(&mut db, "SELECT * FROM todos").await;
+    assert!(matches!(result, QueryResult::Documents(ref docs) if docs.is_empty()));
 
-    // Valid: insert with correct types
-    let result = db.execute("INSERT INTO flags (id, name, enabled) VALUES ('flag-1', 'Feature', true)").await;
-    assert!(result.is_ok());
+    let listed = db.proposals("origin").unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].summary, "Add task 1");
 
-    // Invalid: string value for BOOL field
-    let result = db.execute("INSERT INTO flags (id, name, enabled) VALUES ('flag-2', 'Other', 'yes')").await;
-    assert!(result.is_err());
+    let outcome = db.apply_proposal(&proposal.name, "origin").unwrap();
+    assert!(outcome.is_some());
+    let result = exec(&mut db, "SELECT * FROM todos").await;
+    assert!(matches!(result, QueryResult::Documents(ref docs) if docs.len() == 1));
+    assert!(db.proposals("origin").unwrap().is_empty());
 }
 
 #[tokio::test]
-async fn test_schema_type_validation_date_field() {
-    let (_tmp, mut db) = setup_test_db().await;
+async fn test_signed_commits_verify() {
+    let tmp = TempDir::new().unwrap();
+    let key = tmp.path().join("signing-key");
+    let status = std::process::Command::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+        .arg(&key)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let db_path = tmp.path().join("db");
+    let db = Database::open(&db_path).await.unwrap();
+    let initial = db.git.head_hash().unwrap();
+    std::fs::create_dir_all(db_path.join(".mdby")).unwrap();
+    std::fs::write(
+        db_path.join(".mdby/config.yaml"),
+        format!("signing:\n  format: ssh\n  key: {}\n", key.display()),
+    )
+    .unwrap();
+
+    let mut db = Database::open(&db_path).await.unwrap();
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('task-1', 'Signed')").await;
 
-    // Create collection with DATE field
-    exec(&mut db, "CREATE COLLECTION events (title STRING, event_date DATE)").await;
+    let results = db.verify(Some(&initial)).unwrap();
+    assert!(!results.is_empty());
+    assert!(results.iter().all(|r| !r.flagged()), "{:?}", results);
+    // The initial commit predates the signing config
+    assert!(db.verify(None).unwrap().last().unwrap().flagged());
+}
 
-    // Valid: ISO 8601 date
-    let result = db.execute("INSERT INTO events (id, title, event_date) VALUES ('event-1', 'Meeting', '2024-01-15')").await;
-    assert!(result.is_ok());
+#[tokio::test]
+async fn test_bare_replica_serves_reads() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED)").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('task-1', 'First')").await;
 
-    // Invalid: non-date string
-    let result = db.execute("INSERT INTO events (id, title, event_date) VALUES ('event-2', 'Party', 'next tuesday')").await;
-    assert!(result.is_err());
+    let bare = tmp.path().join("replica.git");
+    git2::Repository::init_bare(&bare).unwrap();
+    let branch = db.git.inner().head().unwrap().shorthand().unwrap().to_string();
+    let refspec = format!("refs/heads/{0}:refs/heads/{0}", branch);
+    let push = |db: &Database| db.git.inner().find_remote("replica").unwrap().push(&[&refspec], None).unwrap();
+    db.git.inner().remote("replica", bare.to_str().unwrap()).unwrap();
+    push(&db);
+
+    let mut replica = Database::open_bare(&bare, &branch).await.unwrap();
+    assert!(replica.is_read_only());
+    let result = exec(&mut replica, "SELECT * FROM todos WHERE title = 'First'").await;
+    assert!(matches!(result, QueryResult::Documents(ref docs) if docs.len() == 1));
+    let result = exec(&mut replica, "SHOW COLLECTIONS").await;
+    assert!(matches!(result, QueryResult::Collections(ref names) if names == &["todos"]));
+    let result = exec(&mut replica, "SHOW CREATE COLLECTION todos").await;
+    assert!(matches!(result, QueryResult::Definition(ref sql) if sql.contains("REQUIRED")));
+
+    let err = replica.execute("INSERT INTO todos (id, title) VALUES ('task-2', 'Second')").await.unwrap_err();
+    assert!(err.to_string().contains("read-only"));
+
+    // The replica follows the branch as it's pushed to
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('task-2', 'Second')").await;
+    push(&db);
+    let result = exec(&mut replica, "SELECT * FROM todos").await;
+    assert!(matches!(result, QueryResult::Documents(ref docs) if docs.len() == 2));
 }
 
-// =============================================================================
-// SHOW Commands Tests
-// =============================================================================
+#[tokio::test]
+async fn test_conflict_resolution_is_recorded() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('task-1', 'Base')").await;
+
+    let base = mdby::storage::document::Document::new("task-1").with_body("");
+    let mut ours = base.clone();
+    ours.set("title", "Local edit");
+    let mut theirs = base.clone();
+    theirs.set("title", "Remote edit");
+
+    let (merged, record) = mdby::git::resolve_recorded(
+        tmp.path(),
+        "todos",
+        Some(&base),
+        &ours,
+        &theirs,
+        mdby::git::ConflictResolution::MergeFields,
+    )
+    .unwrap();
+    assert_eq!(merged.get("title").and_then(|v| v.as_str()), Some("Remote edit"));
+
+    // The losing local edit can still be recovered from the record
+    let saved = std::fs::read_to_string(tmp.path().join(&record.path)).unwrap();
+    assert!(saved.contains("title: Local edit"));
+    assert!(saved.contains("strategy: merge_fields"));
+}
 
 #[tokio::test]
-async fn test_show_collections() {
+async fn test_select_expressions_and_aliases() {
+    use mdby::storage::document::Value;
     let (_tmp, mut db) = setup_test_db().await;
 
-    // Initially empty
-    let result = exec(&mut db, "SHOW COLLECTIONS").await;
-    if let QueryResult::Collections(names) = result {
-        assert!(names.is_empty());
-    } else {
-        panic!("Expected Collections result");
-    }
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, title, priority) VALUES ('a', 'Alpha', 2)").await;
+    exec(&mut db, "INSERT INTO todos (id, title, priority) VALUES ('b', 'Beta', 5)").await;
+
+    let result = exec(&mut db, "SELECT title AS name, priority * 2 AS weighted, priority + 0.5, @id FROM todos ORDER BY weighted DESC").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents") };
+    assert_eq!(docs.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(), vec!["b", "a"]);
+    assert_eq!(docs[0].get("name"), Some(&Value::String("Beta".into())));
+    assert_eq!(docs[0].get("weighted"), Some(&Value::Int(10)));
+    assert_eq!(docs[0].get("priority + 0.5"), Some(&Value::Float(5.5)));
+    assert!(docs[0].get("title").is_none());
+
+    // Computed columns alongside every field, and in WHERE
+    let result = exec(&mut db, "SELECT *, title || '!' AS shout FROM todos WHERE priority * 2 > 5").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents") };
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].get("shout"), Some(&Value::String("Beta!".into())));
+    assert_eq!(docs[0].get("priority"), Some(&Value::Int(5)));
+}
 
-    // Create some collections
+#[tokio::test]
+async fn test_toggle_and_tag() {
+    use mdby::storage::document::Value;
+    let (_tmp, mut db) = setup_test_db().await;
     exec(&mut db, "CREATE COLLECTION todos").await;
-    exec(&mut db, "CREATE COLLECTION users").await;
-    exec(&mut db, "CREATE COLLECTION projects").await;
+    exec(&mut db, "INSERT INTO todos (id, title, tags) VALUES ('t1', 'It''s here', ['a', 'someday'])").await;
+
+    assert!(db.toggle("todos", "t1", "done").await.unwrap());
+    assert!(!db.toggle("todos", "t1", "done").await.unwrap());
+    assert!(db.toggle("todos", "t1", "title").await.is_err());
+    assert!(db.toggle("todos", "missing", "done").await.is_err());
+
+    let tags = db.tag("todos", "t1", "tags", &["urgent".into(), "a".into()], &["someday".into()]).await.unwrap();
+    assert_eq!(tags, vec!["a", "urgent"]);
+
+    // Both ran as ordinary UPDATE commits
+    assert_eq!(db.git.log(1).unwrap()[0].summary, "UPDATE todos: 1 document(s)");
+    let result = exec(&mut db, "SELECT * FROM todos WHERE done = false AND HAS TAG 'urgent'").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents") };
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].get("title"), Some(&Value::String("It's here".into())));
+}
 
-    // Should list all collections
-    let result = exec(&mut db, "SHOW COLLECTIONS").await;
-    if let QueryResult::Collections(names) = result {
-        assert_eq!(names.len(), 3);
-        assert!(names.contains(&"todos".to_string()));
-        assert!(names.contains(&"users".to_string()));
-        assert!(names.contains(&"projects".to_string()));
-    } else {
-        panic!("Expected Collections result");
+#[tokio::test]
+async fn test_insert_many_rows() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED UNIQUE)").await;
+    let commits = db.git.log(100).unwrap().len();
+
+    let result = exec(&mut db, "INSERT INTO todos (id, title) VALUES ('a', 'A'), ('b', 'B'), ('c', 'C')").await;
+    assert!(matches!(result, QueryResult::Affected(3)));
+    let log = db.git.log(100).unwrap();
+    assert_eq!(log.len(), commits + 1);
+    assert_eq!(log[0].summary, "INSERT into todos: 3 document(s)");
+
+    // A bad row anywhere writes nothing
+    for query in [
+        "INSERT INTO todos (id, title) VALUES ('d', 'D'), ('a', 'Again')",
+        "INSERT INTO todos (id, title) VALUES ('d', 'D'), ('e', 'D')",
+        "INSERT INTO todos (id, title) VALUES ('d', 'D'), ('d', 'E')",
+        "INSERT INTO todos (id, title) VALUES ('d', 'D'), ('e')",
+    ] {
+        assert!(db.execute(query).await.is_err(), "{}", query);
     }
+    let result = exec(&mut db, "SELECT * FROM todos").await;
+    assert!(matches!(result, QueryResult::Documents(docs) if docs.len() == 3));
+
+    // Rows mix inserts and upserts
+    let result = exec(&mut db, "UPSERT INTO todos (id, title) VALUES ('a', 'A2'), ('d', 'D')").await;
+    assert!(matches!(result, QueryResult::Affected(2)));
+    assert_eq!(db.git.log(1).unwrap()[0].summary, "UPSERT into todos: 2 document(s)");
+    let result = exec(&mut db, "INSERT INTO todos (id, title) VALUES ('a', 'x'), ('b', 'y') ON CONFLICT DO NOTHING").await;
+    assert!(matches!(result, QueryResult::Affected(0)));
 }
 
 #[tokio::test]
-async fn test_show_views() {
+async fn test_insert_on_conflict() {
+    use mdby::storage::document::Value;
     let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, done BOOL)").await;
+    exec(&mut db, "INSERT INTO todos (id, title, done) VALUES ('t1', 'First', false) BODY 'Notes'").await;
 
-    // Initially empty
-    let result = exec(&mut db, "SHOW VIEWS").await;
-    if let QueryResult::Views(names) = result {
-        assert!(names.is_empty());
-    } else {
-        panic!("Expected Views result");
-    }
+    // Plain INSERT still refuses a taken ID
+    assert!(db.execute("INSERT INTO todos (id, title) VALUES ('t1', 'Again')").await.is_err());
 
-    // Create a collection and view
-    exec(&mut db, "CREATE COLLECTION todos").await;
-    exec(&mut db, "CREATE VIEW active AS SELECT * FROM todos").await;
+    let result = exec(&mut db, "INSERT INTO todos (id, title) VALUES ('t1', 'Again') ON CONFLICT DO NOTHING").await;
+    assert!(matches!(result, QueryResult::Affected(0)));
 
-    // Should list the view
-    let result = exec(&mut db, "SHOW VIEWS").await;
-    if let QueryResult::Views(names) = result {
-        assert_eq!(names.len(), 1);
-        assert!(names.contains(&"active".to_string()));
-    } else {
-        panic!("Expected Views result");
-    }
+    // Given columns are replaced; other fields and the body are kept
+    exec(&mut db, "INSERT INTO todos (id, done) VALUES ('t1', true) ON CONFLICT UPDATE").await;
+    assert_eq!(db.git.log(1).unwrap()[0].summary, "UPSERT into todos: t1");
+    exec(&mut db, "UPSERT INTO todos (id, title) VALUES ('t2', 'Second')").await;
+    assert_eq!(db.git.log(1).unwrap()[0].summary, "INSERT into todos: t2");
+
+    let result = exec(&mut db, "SELECT * FROM todos ORDER BY @id").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents") };
+    assert_eq!(docs.len(), 2);
+    assert_eq!(docs[0].get("title"), Some(&Value::String("First".into())));
+    assert_eq!(docs[0].get("done"), Some(&Value::Bool(true)));
+    assert_eq!(docs[0].body.trim(), "Notes");
+
+    // The merged document is still validated
+    assert!(db.execute("UPSERT INTO todos (id, done) VALUES ('t1', 'yes please')").await.is_err());
+}
+
+#[tokio::test]
+async fn test_csv_import_and_export() {
+    use mdby::storage::document::Value;
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, priority INT, done BOOL, tags ARRAY<STRING>)").await;
+    let commits = db.git.log(100).unwrap().len();
+
+    let csv = "key,title,priority,done,tags,body\n\
+               t1,Buy milk,2,TRUE,home; errands,\"Semi-skimmed,\nnot oat\"\n\
+               t2,Write report,,false,,\n";
+    let ids = db.import_csv("todos", csv.as_bytes(), "key").await.unwrap();
+    assert_eq!(ids, vec!["t1", "t2"]);
+    assert_eq!(db.git.log(100).unwrap().len(), commits + 1);
+    assert_eq!(db.git.log(1).unwrap()[0].summary, "IMPORT into todos: 2 document(s)");
+
+    let result = exec(&mut db, "SELECT * FROM todos WHERE priority = 2 AND done = true AND HAS TAG 'errands'").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents") };
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].body.trim(), "Semi-skimmed,\nnot oat");
+    let result = exec(&mut db, "SELECT * FROM todos WHERE @id = 't2'").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents") };
+    assert_eq!(docs[0].get("priority"), None);
+    assert_eq!(docs[0].get("done"), Some(&Value::Bool(false)));
+
+    // A bad row, or an ID that's taken, imports nothing
+    for csv in ["id,title,priority\nt3,Ok,1\nt4,Bad,many\n", "id,title\nt3,Ok\nt1,Taken\n", "title\nNo IDs\n"] {
+        let err = db.import_csv("todos", csv.as_bytes(), "id").await.unwrap_err();
+        assert!(!err.to_string().is_empty());
+    }
+    let err = db.import_csv("todos", "id,title,priority\nt4,Bad,many\n".as_bytes(), "id").await.unwrap_err();
+    assert!(err.to_string().starts_with("Line 2:"), "{}", err);
+    assert_eq!(db.git.log(100).unwrap().len(), commits + 1);
+
+    let mut out = Vec::new();
+    assert_eq!(db.export_csv("todos", &mut out).await.unwrap(), 2);
+    let exported = String::from_utf8(out).unwrap();
+    assert!(exported.starts_with("id,title,priority,done,tags,body\nt1,Buy milk,2,true,home;errands,"), "{}", exported);
+
+    // The export reads back into the same documents
+    exec(&mut db, "CREATE COLLECTION copy (title STRING REQUIRED, priority INT, done BOOL, tags ARRAY<STRING>)").await;
+    db.import_csv("copy", exported.as_bytes(), "id").await.unwrap();
+    let QueryResult::Documents(original) = exec(&mut db, "SELECT * FROM todos ORDER BY @id").await else { panic!() };
+    let QueryResult::Documents(copy) = exec(&mut db, "SELECT * FROM copy ORDER BY @id").await else { panic!() };
+    for (a, b) in original.iter().zip(&copy) {
+        assert_eq!((&a.id, &a.fields, &a.body), (&b.id, &b.fields, &b.body));
+    }
+}
+
+#[tokio::test]
+async fn test_json_export_and_jsonl_import() {
+    use mdby::storage::document::Value;
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, priority INT, due DATE, tags ARRAY<STRING>)").await;
+    exec(
+        &mut db,
+        "INSERT INTO todos (id, title, priority, due, tags) VALUES ('t1', 'Buy milk', 2, '2026-01-05', ['home']) BODY 'Line one\n\n\"Quoted\"'",
+    )
+    .await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('t2', 'Write report')").await;
+
+    let mut out = Vec::new();
+    assert_eq!(db.export_json("todos", &mut out, false).await.unwrap(), 2);
+    let array: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(array[0]["id"], "t1");
+    assert_eq!(array[0]["priority"], 2);
+    assert_eq!(array[0]["tags"], serde_json::json!(["home"]));
+    assert!(array[0]["_body"].as_str().unwrap().contains("\"Quoted\""));
+    assert!(array[1].get("_body").is_none());
+
+    // JSON Lines reads back into the same documents, in one commit
+    let mut out = Vec::new();
+    db.export_json("todos", &mut out, true).await.unwrap();
+    let exported = String::from_utf8(out).unwrap();
+    assert_eq!(exported.lines().count(), 2);
+    exec(&mut db, "CREATE COLLECTION copy (title STRING REQUIRED, priority INT, due DATE, tags ARRAY<STRING>)").await;
+    let commits = db.git.log(100).unwrap().len();
+    assert_eq!(db.import_jsonl("copy", exported.as_bytes()).await.unwrap(), vec!["t1", "t2"]);
+    assert_eq!(db.git.log(100).unwrap().len(), commits + 1);
+    assert_eq!(db.git.log(1).unwrap()[0].summary, "IMPORT into copy: 2 document(s)");
+    let QueryResult::Documents(original) = exec(&mut db, "SELECT * FROM todos ORDER BY @id").await else { panic!() };
+    let QueryResult::Documents(copy) = exec(&mut db, "SELECT * FROM copy ORDER BY @id").await else { panic!() };
+    assert_eq!(copy.len(), 2);
+    for (a, b) in original.iter().zip(&copy) {
+        assert_eq!((&a.id, &a.fields, &a.body), (&b.id, &b.fields, &b.body));
+    }
+
+    // Values are coerced like an INSERT; blank lines are skipped
+    let jsonl = "{\"id\": \"t3\", \"title\": \"Call\", \"priority\": \"3\"}\n\n";
+    db.import_jsonl("copy", jsonl.as_bytes()).await.unwrap();
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM copy WHERE @id = 't3'").await else { panic!() };
+    assert_eq!(docs[0].get("priority"), Some(&Value::Int(3)));
+
+    // Any bad line imports nothing
+    for jsonl in [
+        "{\"id\": \"t4\", \"title\": \"Ok\"}\n{\"id\": \"t5\", \"priority\": 1}\n",
+        "{\"id\": \"t4\", \"title\": \"Ok\"}\n{\"id\": \"t1\", \"title\": \"Taken\"}\n",
+        "{\"id\": \"t4\", \"title\": \"Ok\"}\nnot json\n",
+    ] {
+        let err = db.import_jsonl("copy", jsonl.as_bytes()).await.unwrap_err();
+        assert!(err.to_string().starts_with("Line 2:"), "{}", err);
+    }
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM copy").await else { panic!() };
+    assert_eq!(docs.len(), 3);
+}
+
+#[tokio::test]
+async fn test_link_graph() {
+    use mdby::graph::{Edge, EdgeKind, GraphFilter};
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION users").await;
+    exec(&mut db, "CREATE COLLECTION posts (author REF<users>)").await;
+    exec(&mut db, "INSERT INTO users (id, title) VALUES ('ally', 'Ally'), ('bo', 'Bo')").await;
+    exec(&mut db, "INSERT INTO posts (id, title, author, tags) VALUES ('a', 'A', 'ally', ['rust'])").await;
+    exec(
+        &mut db,
+        "INSERT INTO posts (id, author, tags) VALUES ('b', 'bo', ['misc']) BODY 'See [[a]], [[users/ally|Ally]] and [[gone]]'",
+    )
+    .await;
+
+    let edge = |source: &str, target: &str, kind: EdgeKind| Edge { source: source.into(), target: target.into(), kind };
+    let author = || EdgeKind::Ref { field: "author".into() };
+
+    let graph = db.graph(&GraphFilter::default()).await.unwrap();
+    let nodes: Vec<_> = graph.nodes.iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(nodes, vec!["posts/a", "posts/b", "users/ally", "users/bo"]);
+    // Broken links aren't edges
+    assert_eq!(
+        graph.edges,
+        vec![
+            edge("posts/a", "users/ally", author()),
+            edge("posts/b", "posts/a", EdgeKind::Wikilink),
+            edge("posts/b", "users/ally", EdgeKind::Wikilink),
+            edge("posts/b", "users/bo", author()),
+        ]
+    );
+    assert!(graph.to_dot().contains("\"posts/b\" -> \"posts/a\" [style=dashed];"));
+
+    // Filters keep only edges between the documents left
+    let filter = GraphFilter { collections: vec!["posts".into()], tags: Vec::new() };
+    let graph = db.graph(&filter).await.unwrap();
+    assert_eq!(graph.nodes.len(), 2);
+    assert_eq!(graph.edges, vec![edge("posts/b", "posts/a", EdgeKind::Wikilink)]);
+    let filter = GraphFilter { collections: Vec::new(), tags: vec!["rust".into()] };
+    let graph = db.graph(&filter).await.unwrap();
+    assert_eq!(graph.nodes[0].title.as_deref(), Some("A"));
+    assert_eq!((graph.nodes.len(), graph.edges.len()), (1, 0));
+}
+
+#[tokio::test]
+async fn test_find_and_replace_documents() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION posts").await;
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "INSERT INTO posts (id, title) VALUES ('rust-intro', 'Getting started')").await;
+    exec(&mut db, "INSERT INTO posts (id, title) VALUES ('meeting', 'Rust in production')").await;
+    exec(&mut db, "INSERT INTO notes (id, title) VALUES ('groceries', 'Bread, eggs')").await;
+
+    // IDs and titles both match, across collections, with the tighter match first
+    let matches = db.find("rust", 10).await.unwrap();
+    let found: Vec<_> = matches.iter().map(|m| (m.collection.as_str(), m.id.as_str())).collect();
+    assert_eq!(found, vec![("posts", "rust-intro"), ("posts", "meeting")]);
+    // Every word has to match
+    let matches = db.find("rust prod", 10).await.unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].title.as_deref(), Some("Rust in production"));
+    assert!(db.find("grcrs", 10).await.unwrap()[0].id == "groceries");
+    assert!(db.find("zzz", 10).await.unwrap().is_empty());
+    assert!(db.find("  ", 10).await.is_err());
+
+    let mut doc = mdby::Document::new("groceries").with_body("Don't forget milk\n");
+    doc.set("title", "Bread, eggs");
+    assert!(db.replace("notes", &doc).await.unwrap());
+    assert_eq!(db.git.log(1).unwrap()[0].summary, "EDIT notes: groceries");
+    assert!(!db.replace("notes", &doc).await.unwrap());
+    assert!(db.replace("notes", &mdby::Document::new("missing")).await.is_err());
+}
+
+#[tokio::test]
+async fn test_author_special_field() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('task-1', 'Test')").await;
+    let author = db.git.log(1).unwrap()[0].author.clone();
+
+    let result = exec(&mut db, "SELECT title, @author FROM todos").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents") };
+    assert_eq!(docs[0].get("@author").and_then(|v| v.as_str()), Some(author.as_str()));
+
+    let query = format!("SELECT * FROM todos WHERE @author = '{}'", author);
+    assert!(matches!(exec(&mut db, &query).await, QueryResult::Documents(docs) if docs.len() == 1));
+    let result = exec(&mut db, "SELECT * FROM todos WHERE @author = 'nobody'").await;
+    assert!(matches!(result, QueryResult::Documents(docs) if docs.is_empty()));
+}
+
+#[tokio::test]
+async fn test_created_and_modified_special_fields() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "INSERT INTO notes (id, title) VALUES ('a', 'A')").await;
+    exec(&mut db, "INSERT INTO notes (id, title) VALUES ('b', 'B')").await;
+    // Commit times have one-second resolution
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    exec(&mut db, "UPDATE notes SET title = 'A2' WHERE id = 'a'").await;
+
+    let result = exec(&mut db, "SELECT @created, @modified FROM notes WHERE @id = 'a'").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents") };
+    let created = docs[0].get("@created").and_then(|v| v.as_str()).unwrap().to_string();
+    let modified = docs[0].get("@modified").and_then(|v| v.as_str()).unwrap().to_string();
+    assert!(created.ends_with('Z'));
+    assert!(created < modified);
+
+    let result = exec(&mut db, "SELECT * FROM notes WHERE @created > '2024-01-01' ORDER BY @modified DESC").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents") };
+    let ids: Vec<&str> = docs.iter().map(|d| d.id.as_str()).collect();
+    assert_eq!(ids, vec!["a", "b"]);
+
+    let query = format!("SELECT * FROM notes WHERE @modified > '{}'", created);
+    assert!(matches!(exec(&mut db, &query).await, QueryResult::Documents(docs) if docs.len() == 1));
+}
+
+// =============================================================================
+// Edge Cases
+// =============================================================================
+
+#[tokio::test]
+async fn test_special_characters_in_string_values() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos").await;
+
+    // Test various special characters in values (not IDs)
+    exec(&mut db, r#"INSERT INTO todos (id, title) VALUES ('task-1', 'Test with "quotes"')"#).await;
+
+    let result = exec(&mut db, "SELECT * FROM todos").await;
+    if let QueryResult::Documents(docs) = result {
+        assert_eq!(docs.len(), 1);
+    } else {
+        panic!("Expected Documents");
+    }
+}
+
+#[tokio::test]
+async fn test_numeric_string_id() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('123', 'Numeric ID')").await;
+
+    let result = exec(&mut db, "SELECT * FROM todos WHERE id = '123'").await;
+    if let QueryResult::Documents(docs) = result {
+        assert_eq!(docs.len(), 1);
+    } else {
+        panic!("Expected Documents");
+    }
+}
+
+#[tokio::test]
+async fn test_output_field_order_is_deterministic() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos (title STRING, priority INT, done BOOL)").await;
+    exec(&mut db, "INSERT INTO todos (id, zone, done, area, priority, title) VALUES ('a', 'z', false, 'x', 1, 'A')").await;
+    exec(&mut db, "CREATE VIEW all_todos AS SELECT * FROM todos").await;
+    db.regenerate_views().await.unwrap();
+
+    // Schema fields in declaration order, then the rest alphabetically
+    let content = std::fs::read_to_string(tmp.path().join("collections/todos/a.md")).unwrap();
+    assert!(content.starts_with("---\ntitle: A\npriority: 1\ndone: false\narea: x\nzone: z\n---"), "{}", content);
+
+    let json = std::fs::read_to_string(tmp.path().join("views/all_todos/index.json")).unwrap();
+    let items: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+    let keys: Vec<&String> = items[0].as_object().unwrap().keys().collect();
+    assert_eq!(keys, ["id", "title", "priority", "done", "area", "zone", "body"]);
+
+    // Rewriting produces identical output
+    exec(&mut db, "UPDATE todos SET done = false WHERE id = 'a'").await;
+    assert_eq!(std::fs::read_to_string(tmp.path().join("collections/todos/a.md")).unwrap(), content);
+}
+
+#[tokio::test]
+async fn test_document_limits_enforced_on_write() {
+    let (tmp, mut db) = setup_test_db().await;
+    db.config.limits = mdby::config::Limits { max_body_bytes: 10, max_fields: 2, max_array_len: 2 };
+
+    exec(&mut db, "CREATE COLLECTION notes").await;
+
+    let err = db.execute("INSERT INTO notes (id) VALUES ('big') BODY 'far more than ten bytes'").await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<mdby::Error>(),
+        Some(mdby::Error::LimitExceeded { limit: "body size (bytes)", .. })
+    ));
+    assert!(db.execute("INSERT INTO notes (id, tags) VALUES ('tags', [1, 2, 3])").await.is_err());
+    assert!(!tmp.path().join("collections/notes/big.md").exists());
+
+    // An UPDATE that would break the limit for any document writes nothing
+    exec(&mut db, "INSERT INTO notes (id, a) VALUES ('one', 1)").await;
+    exec(&mut db, "INSERT INTO notes (id, a, b) VALUES ('two', 1, 2)").await;
+    let before = std::fs::read_to_string(tmp.path().join("collections/notes/one.md")).unwrap();
+    assert!(db.execute("UPDATE notes SET c = 3").await.is_err());
+    assert_eq!(std::fs::read_to_string(tmp.path().join("collections/notes/one.md")).unwrap(), before);
+    assert!(!db.git.has_changes().unwrap());
+}
+
+#[tokio::test]
+async fn test_ignored_files_are_not_documents() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "INSERT INTO notes (id, title) VALUES ('real', 'Real')").await;
+    exec(&mut db, "INSERT INTO notes (id, title) VALUES ('draft-idea', 'Draft')").await;
+
+    let dir = tmp.path().join("collections/notes");
+    std::fs::write(dir.join("README.md"), "# About these notes").unwrap();
+    std::fs::write(dir.join(".#real.md"), "lock").unwrap();
+    std::fs::write(tmp.path().join(".mdbyignore"), "# Work in progress\ndraft-*.md\n").unwrap();
+
+    let result = exec(&mut db, "SELECT * FROM notes").await;
+    assert!(matches!(result, QueryResult::Documents(docs) if docs.len() == 1 && docs[0].id == "real"));
+    assert_eq!(mdby::Collection::open("notes", tmp.path()).count().await.unwrap(), 1);
+
+    // Writing a document that would be hidden is refused
+    assert!(db.execute("INSERT INTO notes (id) VALUES ('draft-two')").await.is_err());
+
+    // Collection-level file can re-include
+    std::fs::write(dir.join(".mdbyignore"), "!draft-idea.md\n").unwrap();
+    let result = exec(&mut db, "SELECT * FROM notes").await;
+    assert!(matches!(result, QueryResult::Documents(docs) if docs.len() == 2));
+}
+
+#[tokio::test]
+async fn test_lint_rules() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION posts").await;
+    exec(&mut db, "INSERT INTO posts (id, title, tags) VALUES ('good', 'Good', ['rust']) BODY '## Summary\n\nFine.'").await;
+    exec(&mut db, "INSERT INTO posts (id, title, tags) VALUES ('bad', 'A very long title', ['cobol']) BODY 'TODO'").await;
+
+    std::fs::create_dir_all(tmp.path().join(".mdby")).unwrap();
+    std::fs::write(
+        tmp.path().join(".mdby/lint.yaml"),
+        "collections:\n  posts:\n    required_headings: ['## Summary']\n    max_title_length: 10\n    forbidden_words: [todo]\n    allowed_tags: [rust, git]\n",
+    )
+    .unwrap();
+    let mut db = mdby::Database::open(tmp.path()).await.unwrap();
+
+    let reports = db.lint_collection("posts").await.unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].id, "bad");
+    assert_eq!(reports[0].violations.len(), 4);
+
+    // Not enforced: writes still succeed
+    exec(&mut db, "INSERT INTO posts (id, title) VALUES ('draft', 'TODO')").await;
+
+    db.lint.collections.get_mut("posts").unwrap().enforce = true;
+    let err = db.execute("INSERT INTO posts (id, title) VALUES ('draft-2', 'TODO')").await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<mdby::Error>(), Some(mdby::Error::LintFailed { .. })));
+    assert!(db.execute("UPDATE posts SET tags = ['java'] WHERE id = 'good'").await.is_err());
+    assert!(!db.git.has_changes().unwrap());
+}
+
+#[tokio::test]
+async fn test_check_links() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION people").await;
+    exec(&mut db, "CREATE COLLECTION notes (author REF<people>)").await;
+    exec(&mut db, "INSERT INTO people (id) VALUES ('ada')").await;
+    exec(&mut db, "INSERT INTO notes (id, author) VALUES ('ok', 'ada') BODY 'See [[other]], [[people/ada]] and ![img](img.png)'").await;
+    exec(&mut db, "INSERT INTO notes (id) VALUES ('other')").await;
+    exec(&mut db, "INSERT INTO people (id) VALUES ('bob')").await;
+    exec(&mut db, "INSERT INTO notes (id, author) VALUES ('bad', 'bob') BODY 'See [[gone]] and [file](missing.pdf)'").await;
+    // Without an ON DELETE rule, deleting a referenced document leaves the reference dangling
+    exec(&mut db, "DELETE FROM people WHERE id = 'bob'").await;
+    std::fs::write(tmp.path().join("collections/notes/img.png"), b"png").unwrap();
+
+    let reports = db.check_links("notes", false).await.unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].id, "bad");
+    assert_eq!(
+        reports[0].broken,
+        vec![
+            mdby::links::BrokenLink::Wikilink("gone".into()),
+            mdby::links::BrokenLink::Ref { field: "author".into(), collection: "people".into(), id: "bob".into() },
+            mdby::links::BrokenLink::Asset("missing.pdf".into()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_dedupe_finds_and_merges_duplicates() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION contacts").await;
+    exec(&mut db, "INSERT INTO contacts (id, name, email) VALUES ('ada-1', 'Ada Lovelace', 'ada@example.com')").await;
+    exec(&mut db, "INSERT INTO contacts (id, name, email, phone) VALUES ('ada-2', 'ada lovelace', 'ada@example.com', '555')").await;
+    exec(&mut db, "INSERT INTO contacts (id, name) VALUES ('grace', 'Grace Hopper')").await;
+
+    let pairs = db.find_duplicates("contacts", 0.5).await.unwrap();
+    assert_eq!(pairs.len(), 1);
+    assert_eq!((pairs[0].first.as_str(), pairs[0].second.as_str()), ("ada-1", "ada-2"));
+    assert!(!pairs[0].exact);
+    assert!(db.find_duplicates("contacts", 1.5).await.is_err());
+
+    let merged = db.merge_duplicates("contacts", "ada-1", "ada-2").await.unwrap();
+    assert_eq!(merged.get("phone").and_then(|v| v.as_str()), Some("555"));
+    let result = exec(&mut db, "SELECT * FROM contacts").await;
+    assert!(matches!(result, QueryResult::Documents(ref docs) if docs.len() == 2));
+    assert!(db.git.log(1).unwrap()[0].summary.starts_with("DEDUPE contacts"));
+}
+
+// =============================================================================
+// Schema Type Validation Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_unique_constraint() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION users (email STRING UNIQUE, team STRING)").await;
+    exec(&mut db, "INSERT INTO users (id, email, team) VALUES ('ada', 'ada@example.com', 'core')").await;
+    exec(&mut db, "INSERT INTO users (id, email, team) VALUES ('grace', 'grace@example.com', 'core')").await;
+    // Missing values are never duplicates
+    exec(&mut db, "INSERT INTO users (id, team) VALUES ('bot1', 'ops')").await;
+    exec(&mut db, "INSERT INTO users (id, team) VALUES ('bot2', 'ops')").await;
+
+    let err = db.execute("INSERT INTO users (id, email) VALUES ('ada2', 'ada@example.com')").await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<mdby::schema::ValidationError>(),
+        Some(mdby::schema::ValidationError::UniqueViolation(field)) if field == "email"
+    ));
+    assert!(!tmp.path().join("collections/users/ada2.md").exists());
+
+    // Updates are checked against other documents and against each other
+    assert!(db.execute("UPDATE users SET email = 'grace@example.com' WHERE @id = 'ada'").await.is_err());
+    assert!(db.execute("UPDATE users SET email = 'same@example.com' WHERE team = 'ops'").await.is_err());
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM users WHERE email = 'same@example.com'").await else {
+        panic!("Expected documents");
+    };
+    assert!(docs.is_empty());
+
+    // A document keeping its own value, or taking a freed one, is fine
+    exec(&mut db, "UPDATE users SET team = 'research' WHERE @id = 'ada'").await;
+    exec(&mut db, "UPDATE users SET email = 'ada@example.org' WHERE @id = 'ada'").await;
+    exec(&mut db, "INSERT INTO users (id, email) VALUES ('ada2', 'ada@example.com')").await;
+}
+
+#[tokio::test]
+async fn test_composite_unique_constraint() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION pages (project STRING, slug STRING, title STRING, UNIQUE (project, slug))").await;
+    exec(&mut db, "INSERT INTO pages (id, project, slug) VALUES ('a', 'docs', 'intro'), ('b', 'blog', 'intro')").await;
+    exec(&mut db, "INSERT INTO pages (id, project, slug) VALUES ('c', 'docs', 'setup')").await;
+    // A combination with a missing value is never a duplicate
+    exec(&mut db, "INSERT INTO pages (id, slug) VALUES ('d', 'intro'), ('e', 'intro')").await;
+
+    let err = db.execute("INSERT INTO pages (id, project, slug) VALUES ('f', 'docs', 'intro')").await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<mdby::schema::ValidationError>(),
+        Some(mdby::schema::ValidationError::UniqueCombinationViolation(fields)) if fields == &["project", "slug"]
+    ));
+    assert!(!tmp.path().join("collections/pages/f.md").exists());
+    assert!(db.execute("INSERT INTO pages (id, project, slug) VALUES ('f', 'ops', 'x'), ('g', 'ops', 'x')").await.is_err());
+    assert!(db.execute("UPDATE pages SET project = 'docs' WHERE @id = 'b'").await.is_err());
+    exec(&mut db, "UPDATE pages SET slug = 'intro' WHERE @id = 'e'").await;
+    exec(&mut db, "UPDATE pages SET project = 'docs', slug = 'start' WHERE @id = 'a'").await;
+    exec(&mut db, "UPDATE pages SET project = 'docs' WHERE @id = 'b'").await;
+
+    let QueryResult::Definition(definition) = exec(&mut db, "SHOW CREATE COLLECTION pages").await else {
+        panic!("Expected a definition");
+    };
+    assert!(definition.contains("UNIQUE (project, slug)"), "{}", definition);
+
+    // Renaming a column follows it into the constraint; dropping one drops the constraint
+    let schema = || std::fs::read_to_string(tmp.path().join(".mdby/schemas/pages.yaml")).unwrap();
+    exec(&mut db, "ALTER COLLECTION pages RENAME COLUMN slug TO path").await;
+    assert!(schema().contains("unique:\n- - project\n  - path\n"), "{}", schema());
+    exec(&mut db, "ALTER COLLECTION pages DROP COLUMN project").await;
+    assert!(!schema().contains("unique:\n"));
+
+    let err = db.execute("CREATE COLLECTION posts (slug STRING, UNIQUE (blog, slug))").await.unwrap_err();
+    assert!(err.to_string().contains("not a column of 'posts'"));
+    exec(&mut db, "CREATE COLLECTION posts (slug STRING, UNIQUE (slug))").await;
+    let QueryResult::Definition(definition) = exec(&mut db, "SHOW CREATE COLLECTION posts").await else {
+        panic!("Expected a definition");
+    };
+    assert!(definition.contains("slug STRING UNIQUE\n)"), "{}", definition);
+}
+
+#[tokio::test]
+async fn test_schema_type_validation_int_field() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    // Create collection with INT field
+    exec(&mut db, "CREATE COLLECTION items (name STRING, count INT)").await;
+
+    // Valid: insert with correct types
+    let result = db.execute("INSERT INTO items (id, name, count) VALUES ('item-1', 'Widget', 42)").await;
+    assert!(result.is_ok());
+
+    // Invalid: string value for INT field
+    let result = db.execute("INSERT INTO items (id, name, count) VALUES ('item-2', 'Gadget', 'not-a-number')").await;
+    assert!(result.is_err());
+    let err_msg = result.unwrap_err().to_string();
+    assert!(err_msg.contains("TypeMismatch") || err_msg.contains("type") || err_msg.contains("expected"));
+}
+
+#[tokio::test]
+async fn test_schema_type_validation_bool_field() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    // Create collection with BOOL field
+    exec(&mut db, "CREATE COLLECTION flags (name STRING, enabled BOOL)").await;
+
+    // Valid: insert with correct types
+    let result = db.execute("INSERT INTO flags (id, name, enabled) VALUES ('flag-1', 'Feature', true)").await;
+    assert!(result.is_ok());
+
+    // Invalid: string value for BOOL field
+    let result = db.execute("INSERT INTO flags (id, name, enabled) VALUES ('flag-2', 'Other', 'yes')").await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_schema_type_validation_date_field() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    // Create collection with DATE field
+    exec(&mut db, "CREATE COLLECTION events (title STRING, event_date DATE)").await;
+
+    // Valid: ISO 8601 date
+    let result = db.execute("INSERT INTO events (id, title, event_date) VALUES ('event-1', 'Meeting', '2024-01-15')").await;
+    assert!(result.is_ok());
+
+    // Invalid: non-date string
+    let result = db.execute("INSERT INTO events (id, title, event_date) VALUES ('event-2', 'Party', 'next tuesday')").await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_insert_coerces_values_to_schema_types() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION items (name STRING, count INT, active BOOL)").await;
+    exec(&mut db, "INSERT INTO items (id, name, count, active) VALUES ('item-1', 42, '5', 'true')").await;
+
+    let result = exec(&mut db, "SELECT * FROM items").await;
+    match result {
+        QueryResult::Documents(docs) => {
+            assert_eq!(docs[0].get("name"), Some(&mdby::storage::document::Value::String("42".into())));
+            assert_eq!(docs[0].get("count"), Some(&mdby::storage::document::Value::Int(5)));
+            assert_eq!(docs[0].get("active"), Some(&mdby::storage::document::Value::Bool(true)));
+        }
+        _ => panic!("Expected Documents result"),
+    }
+}
+
+#[tokio::test]
+async fn test_insert_strict_schema_rejects_coercible_values() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION items (count INT)").await;
+
+    // Opt into strict mode by editing the schema file, then reopen
+    let schema_path = tmp.path().join(".mdby/schemas/items.yaml");
+    let schema = std::fs::read_to_string(&schema_path).unwrap();
+    std::fs::write(&schema_path, schema.replace("strict: false", "strict: true")).unwrap();
+    let mut db = Database::open(tmp.path()).await.unwrap();
+
+    let result = db.execute("INSERT INTO items (id, count) VALUES ('item-1', '5')").await;
+    assert!(result.is_err());
+    exec(&mut db, "INSERT INTO items (id, count) VALUES ('item-2', 5)").await;
+}
+
+#[tokio::test]
+async fn test_read_coerces_hand_written_values() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos (title STRING, priority INT, done BOOL)").await;
+    std::fs::write(
+        tmp.path().join("collections/todos/task-1.md"),
+        "---\ntitle: Hand written\npriority: \"5\"\ndone: \"true\"\n---\n",
+    )
+    .unwrap();
+
+    let result = exec(&mut db, "SELECT * FROM todos WHERE priority > 3 AND done = true").await;
+    match result {
+        QueryResult::Documents(docs) => {
+            assert_eq!(docs.len(), 1);
+            assert_eq!(docs[0].get("priority"), Some(&mdby::storage::document::Value::Int(5)));
+        }
+        _ => panic!("Expected Documents result"),
+    }
+
+    // An UPDATE writes the coerced values back
+    exec(&mut db, "UPDATE todos SET title = 'Edited' WHERE id = 'task-1'").await;
+    let content = std::fs::read_to_string(tmp.path().join("collections/todos/task-1.md")).unwrap();
+    assert!(content.contains("priority: 5\n"), "{}", content);
+    assert!(content.contains("done: true\n"), "{}", content);
+
+    // A strict schema fails on the mistyped value instead
+    std::fs::write(
+        tmp.path().join("collections/todos/task-2.md"),
+        "---\ntitle: Also hand written\npriority: \"2\"\n---\n",
+    )
+    .unwrap();
+    let schema_path = tmp.path().join(".mdby/schemas/todos.yaml");
+    let schema = std::fs::read_to_string(&schema_path).unwrap();
+    std::fs::write(&schema_path, schema.replace("strict: false", "strict: true")).unwrap();
+    let mut db = Database::open(tmp.path()).await.unwrap();
+
+    let err = db.execute("SELECT * FROM todos WHERE priority > 3").await.unwrap_err().to_string();
+    assert!(err.contains("task-2"), "{}", err);
+    assert!(db.execute("UPDATE todos SET priority = '4' WHERE id = 'task-1'").await.is_err());
+}
+
+#[tokio::test]
+async fn test_insert_padded_auto_increment_ids() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION tasks (title STRING)").await;
+    let path = tmp.path().join(".mdby/schemas/tasks.yaml");
+    let schema = std::fs::read_to_string(&path).unwrap();
+    let strategy = "id_strategy: auto_increment\nid_format:\n  prefix: task-\n  width: 4";
+    std::fs::write(&path, schema.replace("id_strategy: manual", strategy)).unwrap();
+    let mut db = Database::open(tmp.path()).await.unwrap();
+
+    let rows: Vec<String> = (1..=10).map(|n| format!("('Task {}')", n)).collect();
+    exec(&mut db, &format!("INSERT INTO tasks (title) VALUES {}", rows.join(", "))).await;
+    exec(&mut db, "INSERT INTO tasks (id, title) VALUES ('task-0011', 'Manual')").await;
+    exec(&mut db, "INSERT INTO tasks (title) VALUES ('Task 12')").await;
+    assert!(tmp.path().join("collections/tasks/task-0001.md").exists());
+
+    // Sorting by ID is numeric order
+    let result = exec(&mut db, "SELECT * FROM tasks ORDER BY @id").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents result") };
+    let ids: Vec<&str> = docs.iter().map(|d| d.id.as_str()).collect();
+    assert_eq!(ids.len(), 12);
+    assert_eq!(ids[..2], ["task-0001", "task-0002"]);
+    assert_eq!(ids[9..], ["task-0010", "task-0011", "task-0012"]);
+    let counter = std::fs::read_to_string(tmp.path().join(".mdby/counters/tasks")).unwrap();
+    assert_eq!(counter.trim(), "12");
+}
+
+#[tokio::test]
+async fn test_datetimes_stored_in_one_zone() {
+    use mdby::storage::document::Value;
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION meetings (title STRING, starts DATETIME)").await;
+    let schema_path = tmp.path().join(".mdby/schemas/meetings.yaml");
+    let schema = std::fs::read_to_string(&schema_path).unwrap();
+    std::fs::write(&schema_path, schema.replace("type: datetime", "type: datetime\n    offset_field: starts_offset")).unwrap();
+    let mut db = Database::open(tmp.path()).await.unwrap();
+
+    // Paris at 09:00 is after New York at 03:30, though it sorts before as written
+    exec(&mut db, "INSERT INTO meetings (id, title, starts) VALUES ('paris', 'Paris', '2024-03-01T09:00:00+01:00'), ('new-york', 'New York', '2024-03-01T03:30:00-05:00')").await;
+    let result = exec(&mut db, "SELECT * FROM meetings ORDER BY starts").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents result") };
+    assert_eq!(docs.iter().map(|doc| doc.id.as_str()).collect::<Vec<_>>(), ["paris", "new-york"]);
+    assert_eq!(docs[0].get("starts"), Some(&Value::String("2024-03-01T08:00:00Z".into())));
+    assert_eq!(docs[0].get("starts_offset"), Some(&Value::String("+01:00".into())));
+    assert_eq!(docs[1].get("starts"), Some(&Value::String("2024-03-01T08:30:00Z".into())));
+
+    let result = exec(&mut db, "SELECT * FROM meetings WHERE starts < '2024-03-01T08:15:00Z'").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents result") };
+    assert_eq!(docs.len(), 1);
+
+    // A configured zone stores values with its offset; ones without an offset are taken to be in it
+    std::fs::write(tmp.path().join(".mdby/config.yaml"), "timezone: \"+09:00\"\n").unwrap();
+    let mut db = Database::open(tmp.path()).await.unwrap();
+    exec(&mut db, "UPDATE meetings SET starts = '2024-03-02 10:00' WHERE id = 'paris'").await;
+    let result = exec(&mut db, "SELECT * FROM meetings WHERE id = 'paris'").await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents result") };
+    let doc = &docs[0];
+    assert_eq!(doc.get("starts"), Some(&Value::String("2024-03-02T10:00:00+09:00".into())));
+    assert_eq!(doc.get("starts_offset"), Some(&Value::String("+09:00".into())));
+
+    std::fs::write(tmp.path().join(".mdby/config.yaml"), "timezone: Europe/Paris\n").unwrap();
+    assert!(Database::open(tmp.path()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_insert_generated_ids() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION posts (title STRING)").await;
+    exec(&mut db, "CREATE COLLECTION tickets (title STRING)").await;
+    exec(&mut db, "CREATE COLLECTION events (title STRING)").await;
+    assert!(db.execute("INSERT INTO posts (title) VALUES ('Hello')").await.is_err());
+
+    let set_strategy = |collection: &str, strategy: &str| {
+        let path = tmp.path().join(format!(".mdby/schemas/{}.yaml", collection));
+        let schema = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, schema.replace("id_strategy: manual", strategy)).unwrap();
+    };
+    set_strategy("posts", "id_strategy:\n  derived:\n    from: title\n    transform: slug");
+    set_strategy("tickets", "id_strategy: auto_increment");
+    set_strategy("events", "id_strategy: uuid");
+    let mut db = Database::open(tmp.path()).await.unwrap();
+
+    // Derived: slugs, with a suffix when taken; explicit IDs still win
+    exec(&mut db, "INSERT INTO posts (title) VALUES ('Hello, World!')").await;
+    exec(&mut db, "INSERT INTO posts (title) VALUES ('Hello world')").await;
+    exec(&mut db, "INSERT INTO posts (id, title) VALUES ('custom', 'Hello')").await;
+    assert!(tmp.path().join("collections/posts/hello-world.md").exists());
+    assert!(tmp.path().join("collections/posts/hello-world-2.md").exists());
+    assert!(tmp.path().join("collections/posts/custom.md").exists());
+    assert!(db.execute("INSERT INTO posts (title) VALUES ('???')").await.is_err());
+
+    // Auto increment: a committed counter, skipping IDs already taken
+    exec(&mut db, "INSERT INTO tickets (title) VALUES ('First')").await;
+    exec(&mut db, "INSERT INTO tickets (id, title) VALUES ('2', 'Manual')").await;
+    exec(&mut db, "INSERT INTO tickets (title) VALUES ('Third')").await;
+    let result = exec(&mut db, "SELECT * FROM tickets ORDER BY title").await;
+    match result {
+        QueryResult::Documents(docs) => {
+            let ids: Vec<&str> = docs.iter().map(|d| d.id.as_str()).collect();
+            assert_eq!(ids, vec!["1", "2", "3"]);
+        }
+        _ => panic!("Expected Documents result"),
+    }
+    let counter = std::fs::read_to_string(tmp.path().join(".mdby/counters/tickets")).unwrap();
+    assert_eq!(counter.trim(), "3");
+    assert!(!db.git.has_changes().unwrap());
+
+    // UUID
+    exec(&mut db, "INSERT INTO events (title) VALUES ('Launch')").await;
+    let result = exec(&mut db, "SELECT * FROM events").await;
+    match result {
+        QueryResult::Documents(docs) => {
+            assert_eq!(docs[0].id.len(), 36);
+            assert_eq!(docs[0].id.matches('-').count(), 4);
+        }
+        _ => panic!("Expected Documents result"),
+    }
+}
+
+#[tokio::test]
+async fn test_insert_body_from_file() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    std::fs::write(tmp.path().join("draft-body.md"), "# Draft\n\nLoaded from disk.").unwrap();
+
+    exec(&mut db, "INSERT INTO notes (id) VALUES ('n1') BODY FROM FILE 'draft-body.md'").await;
+    let content = std::fs::read_to_string(tmp.path().join("collections/notes/n1.md")).unwrap();
+    assert!(content.contains("Loaded from disk."));
+
+    // Paths must stay relative
+    let result = db.execute("INSERT INTO notes (id) VALUES ('n2') BODY FROM FILE '../outside.md'").await;
+    assert!(result.is_err());
+    let result = db.execute("INSERT INTO notes (id) VALUES ('n3') BODY FROM FILE 'missing.md'").await;
+    assert!(result.is_err());
+
+    // Only files under the root, outside .git and .mdby, and not symlinks
+    let err = db.execute("INSERT INTO notes (id) VALUES ('n4') BODY FROM FILE '.git/config'").await.unwrap_err();
+    assert!(err.to_string().contains("inside .git or .mdby"), "{}", err);
+    #[cfg(unix)]
+    {
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret.md"), "secret").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret.md"), tmp.path().join("link.md")).unwrap();
+        let err = db.execute("INSERT INTO notes (id) VALUES ('n5') BODY FROM FILE 'link.md'").await.unwrap_err();
+        assert!(err.to_string().contains("symlink"), "{}", err);
+        std::os::unix::fs::symlink(outside.path(), tmp.path().join("linked")).unwrap();
+        let err = db.execute("INSERT INTO notes (id) VALUES ('n6') BODY FROM FILE 'linked/secret.md'").await.unwrap_err();
+        assert!(err.to_string().contains("outside the database root"), "{}", err);
+    }
+
+    // A handle with a policy refuses it unless the policy allows it
+    db.set_policy(ExecutionPolicy::default());
+    let err = db.execute("INSERT INTO notes (id) VALUES ('n7') BODY FROM FILE 'draft-body.md'").await.unwrap_err();
+    assert!(err.to_string().contains("BODY FROM FILE is not allowed"), "{}", err);
+    db.set_policy(ExecutionPolicy::default().allow_body_files());
+    exec(&mut db, "INSERT INTO notes (id) VALUES ('n7') BODY FROM FILE 'draft-body.md'").await;
+}
+
+#[tokio::test]
+async fn test_insert_escaped_strings_roundtrip() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, r"INSERT INTO notes (id, title, note) VALUES ('n1', '---', '- first\n---\n- caf\u{e9}')").await;
+
+    let result = exec(&mut db, "SELECT * FROM notes WHERE id = 'n1'").await;
+    match result {
+        QueryResult::Documents(docs) => {
+            assert_eq!(docs[0].get("title"), Some(&mdby::storage::document::Value::String("---".into())));
+            assert_eq!(
+                docs[0].get("note"),
+                Some(&mdby::storage::document::Value::String("- first\n---\n- café".into()))
+            );
+        }
+        _ => panic!("Expected Documents result"),
+    }
+}
+
+#[tokio::test]
+async fn test_validate_collection_reports_all_errors() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, priority INT)").await;
+    exec(&mut db, "INSERT INTO todos (id, title, priority) VALUES ('good', 'Fine', 1)").await;
+
+    // Hand-edited document that breaks two rules at once
+    std::fs::write(
+        _tmp.path().join("collections/todos/bad.md"),
+        "---\npriority: high\n---\n\nEdited outside MDQL.",
+    ).unwrap();
+
+    let reports = db.validate_collection("todos").await.unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].id, "bad");
+    assert_eq!(reports[0].errors.len(), 2);
+}
+
+#[tokio::test]
+async fn test_apply_defaults() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos (title STRING, done BOOL DEFAULT false, tags ARRAY<STRING> DEFAULT ['inbox'])").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('a', 'Missing both')").await;
+    exec(&mut db, "INSERT INTO todos (id, title, done, tags) VALUES ('b', 'Has both', true, [])").await;
+
+    let updated = db.apply_defaults("todos").await.unwrap();
+    assert_eq!(updated, vec!["a".to_string()]);
+
+    // Defaults are now stored, so filters match without handling absence
+    let result = exec(&mut db, "SELECT * FROM todos WHERE done = false").await;
+    assert!(matches!(result, QueryResult::Documents(docs) if docs.len() == 1 && docs[0].id == "a"));
+    let content = std::fs::read_to_string(tmp.path().join("collections/todos/a.md")).unwrap();
+    assert!(content.contains("tags:\n- inbox"), "{}", content);
+    assert!(!db.git.has_changes().unwrap());
+
+    // Nothing left to fill in
+    assert!(db.apply_defaults("todos").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_apply_defaults_requires_schema() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    assert!(db.apply_defaults("notes").await.is_err());
+}
+
+#[tokio::test]
+async fn test_infer_schema() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "INSERT INTO notes (id, title, priority, created, tags) VALUES ('a', 'One', 1, '2024-03-01', ['x'])").await;
+    exec(&mut db, "INSERT INTO notes (id, title, priority, created) VALUES ('b', 'Two', 2.5, '2024-03-02')").await;
+    exec(&mut db, "INSERT INTO notes (id, title, priority) VALUES ('c', 'Three', 'high')").await;
+
+    let inference = db.infer_schema("notes", 1.0).await.unwrap();
+    assert_eq!(inference.documents, 3);
+    let field = |name: &str| inference.fields.iter().find(|field| field.name == name).unwrap();
+    assert_eq!(field("title").field_type, "STRING");
+    assert!(field("title").required);
+    assert_eq!(field("created").field_type, "DATE");
+    assert!(!field("created").required);
+    assert_eq!(field("tags").field_type, "ARRAY<STRING>");
+    assert_eq!(field("priority").field_type, "FLOAT");
+    assert_eq!(field("priority").mismatched, ["c"]);
+    assert!(inference.has_conflicts());
+    assert!(db.infer_schema("missing", 1.0).await.is_err());
+
+    // Nothing is written until the schema is saved
+    let schema_file = tmp.path().join(".mdby/schemas/notes.yaml");
+    assert!(!schema_file.exists());
+    let mut schema = inference.schema;
+    schema.fields["priority"].field_type = mdby::schema::FieldType::Int;
+    db.save_schema(schema).unwrap();
+    assert!(schema_file.exists());
+    assert!(!db.git.has_changes().unwrap());
+
+    let err = db.execute("INSERT INTO notes (id, priority) VALUES ('d', 1)").await.unwrap_err();
+    assert!(err.to_string().contains("title"));
+    exec(&mut db, "INSERT INTO notes (id, title, priority) VALUES ('d', 'Four', 1)").await;
+}
+
+// =============================================================================
+// Collection Metadata Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_collection_readme_is_metadata() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, done BOOL DEFAULT false)").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('a', 'First')").await;
+    std::fs::write(
+        tmp.path().join("collections/todos/README.md"),
+        "---\nowner: ops\n---\n\nThings the team needs to do.",
+    ).unwrap();
+
+    // Not a document
+    let result = exec(&mut db, "SELECT * FROM todos").await;
+    assert!(matches!(result, QueryResult::Documents(docs) if docs.len() == 1));
+    assert!(db.execute("INSERT INTO todos (id, title) VALUES ('README', 'x')").await.is_err());
+
+    // Shown by DESCRIBE
+    let description = match exec(&mut db, "DESCRIBE todos").await {
+        QueryResult::Description(description) => description,
+        other => panic!("Expected Description, got {:?}", other),
+    };
+    assert_eq!(description.documents, 1);
+    let meta = description.meta.unwrap();
+    assert_eq!(meta.file, "README.md");
+    assert_eq!(meta.body.trim(), "Things the team needs to do.");
+    assert_eq!(meta.fields.get("owner").and_then(|v| v.as_str()), Some("ops"));
+    assert_eq!(description.fields[0].name, "title");
+    assert_eq!(description.fields[0].constraints, vec!["REQUIRED"]);
+    assert_eq!(description.fields[1].constraints, vec!["DEFAULT false"]);
+
+    // _meta.md takes precedence
+    std::fs::write(tmp.path().join("collections/todos/_meta.md"), "Preferred description.").unwrap();
+    if let QueryResult::Description(description) = exec(&mut db, "DESCRIBE COLLECTION todos").await {
+        assert_eq!(description.meta.unwrap().file, "_meta.md");
+    }
+}
+
+#[tokio::test]
+async fn test_collection_readme_in_view_context() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    std::fs::write(tmp.path().join("collections/todos/_meta.md"), "---\nowner: ops\n---\n\nTeam tasks.").unwrap();
+    std::fs::create_dir_all(tmp.path().join(".mdby/templates")).unwrap();
+    std::fs::write(
+        tmp.path().join(".mdby/templates/about.html"),
+        "{{ collection.name }}|{{ collection.meta.owner }}|{{ collection.readme | trim }}",
+    ).unwrap();
+    exec(&mut db, "CREATE VIEW about AS SELECT * FROM todos TEMPLATE 'about.html'").await;
+    db.regenerate_views().await.unwrap();
+
+    let html = std::fs::read_to_string(tmp.path().join("views/about/index.html")).unwrap();
+    assert_eq!(html, "todos|ops|Team tasks.");
+}
+
+#[tokio::test]
+async fn test_describe_missing_collection() {
+    let (_tmp, mut db) = setup_test_db().await;
+    assert!(db.execute("DESCRIBE nope").await.is_err());
+}
+
+// =============================================================================
+// ALTER COLLECTION Tests
+// =============================================================================
+
+async fn definition(db: &mut Database, collection: &str) -> String {
+    match exec(db, &format!("SHOW CREATE COLLECTION {}", collection)).await {
+        QueryResult::Definition(statement) => statement,
+        other => panic!("Expected Definition, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_alter_collection_schema() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, priority STRING, notes STRING)").await;
+    exec(&mut db, "INSERT INTO todos (id, title, priority, notes) VALUES ('t1', 'Write docs', '3', 'soon')").await;
+
+    // Without MIGRATE, only the schema changes
+    let result = exec(&mut db, "ALTER COLLECTION todos ADD COLUMN due_date DATE").await;
+    assert!(matches!(result, QueryResult::Affected(0)));
+    exec(&mut db, "ALTER COLLECTION todos RENAME COLUMN notes TO details").await;
+    exec(&mut db, "ALTER COLLECTION todos ALTER COLUMN priority TYPE INT").await;
+    exec(&mut db, "ALTER COLLECTION todos DROP COLUMN details").await;
+    assert_eq!(
+        definition(&mut db, "todos").await,
+        "CREATE COLLECTION todos (\n    title STRING REQUIRED,\n    priority INT,\n    due_date DATE\n)"
+    );
+    let doc = std::fs::read_to_string(tmp.path().join("collections/todos/t1.md")).unwrap();
+    assert!(doc.contains("notes: soon") && doc.contains("priority: '3'"), "{}", doc);
+    assert!(!db.git.has_changes().unwrap());
+
+    assert!(db.execute("ALTER COLLECTION todos ADD COLUMN title STRING").await.is_err());
+    assert!(db.execute("ALTER COLLECTION todos DROP COLUMN missing").await.is_err());
+    assert!(db.execute("ALTER COLLECTION todos RENAME COLUMN title TO priority").await.is_err());
+    assert!(db.execute("ALTER COLLECTION missing ADD COLUMN x INT").await.is_err());
+
+    // Schema-less collections gain a schema
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    exec(&mut db, "ALTER COLLECTION notes ADD COLUMN pinned BOOL").await;
+    assert!(definition(&mut db, "notes").await.contains("pinned BOOL"));
+}
+
+#[tokio::test]
+async fn test_alter_collection_migrate() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos (title STRING, priority STRING INDEXED, notes STRING)").await;
+    exec(&mut db, "INSERT INTO todos (id, title, priority, notes) VALUES ('t1', 'A', '3', 'x')").await;
+    exec(&mut db, "INSERT INTO todos (id, title, priority) VALUES ('t2', 'B', '1')").await;
+    exec(&mut db, "SELECT * FROM todos WHERE priority = '1'").await;
+
+    let result = exec(&mut db, "ALTER COLLECTION todos ADD COLUMN done BOOL REQUIRED DEFAULT false MIGRATE").await;
+    assert!(matches!(result, QueryResult::Affected(2)));
+    let result = exec(&mut db, "ALTER COLLECTION todos RENAME COLUMN notes TO details MIGRATE").await;
+    assert!(matches!(result, QueryResult::Affected(1)));
+    exec(&mut db, "ALTER COLLECTION todos ALTER COLUMN priority TYPE INT MIGRATE").await;
+    let doc = std::fs::read_to_string(tmp.path().join("collections/todos/t1.md")).unwrap();
+    assert!(doc.starts_with("---\ntitle: A\npriority: 3\ndetails: x\ndone: false\n"), "{}", doc);
+
+    // The rebuilt index sees the new values
+    let result = exec(&mut db, "SELECT * FROM todos WHERE priority = 1").await;
+    assert!(matches!(result, QueryResult::Documents(docs) if docs.len() == 1 && docs[0].id == "t2"));
+
+    exec(&mut db, "ALTER COLLECTION todos DROP COLUMN details MIGRATE").await;
+    let doc = std::fs::read_to_string(tmp.path().join("collections/todos/t1.md")).unwrap();
+    assert!(!doc.contains("details"));
+    assert!(!db.git.has_changes().unwrap());
+
+    // Migrations that would leave documents invalid change nothing
+    exec(&mut db, "INSERT INTO todos (id, title, priority, done) VALUES ('t3', 'C', 2, true)").await;
+    let err = db.execute("ALTER COLLECTION todos ADD COLUMN owner STRING REQUIRED MIGRATE").await.unwrap_err();
+    assert!(err.to_string().contains("t1: Missing required field: owner"), "{}", err);
+    assert!(db.execute("ALTER COLLECTION todos ADD COLUMN code STRING UNIQUE DEFAULT 'x' MIGRATE").await.is_err());
+    assert!(db.execute("ALTER COLLECTION todos ALTER COLUMN title TYPE INT MIGRATE").await.is_err());
+    assert!(!definition(&mut db, "todos").await.contains("owner"));
+    assert!(definition(&mut db, "todos").await.contains("title STRING"));
+}
+
+// =============================================================================
+// REF Integrity Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_ref_must_exist() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION people").await;
+    exec(&mut db, "CREATE COLLECTION notes (author REF<people>)").await;
+    exec(&mut db, "INSERT INTO people (id) VALUES ('ada')").await;
+
+    exec(&mut db, "INSERT INTO notes (id, author) VALUES ('n1', 'ada')").await;
+    exec(&mut db, "INSERT INTO notes (id, author) VALUES ('n2', NULL)").await;
+
+    let err = db.execute("INSERT INTO notes (id, author) VALUES ('n3', 'bob')").await.unwrap_err();
+    assert!(err.to_string().contains("people/bob"), "{}", err);
+    assert!(db.execute("UPDATE notes SET author = 'bob' WHERE id = 'n1'").await.is_err());
+    assert!(!_tmp.path().join("collections/notes/n3.md").exists());
+}
+
+#[tokio::test]
+async fn test_on_delete_rules() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION users (name STRING)").await;
+    exec(&mut db, "CREATE COLLECTION posts (author REF<users> ON DELETE SET NULL)").await;
+    exec(&mut db, "CREATE COLLECTION comments (post REF<posts> REQUIRED ON DELETE CASCADE)").await;
+    exec(&mut db, "CREATE COLLECTION replies (comment REF<comments> ON DELETE CASCADE)").await;
+    exec(&mut db, "CREATE COLLECTION pins (post REF<posts> ON DELETE RESTRICT)").await;
+
+    exec(&mut db, "INSERT INTO users (id) VALUES ('ada')").await;
+    exec(&mut db, "INSERT INTO users (id) VALUES ('alan')").await;
+    exec(&mut db, "INSERT INTO posts (id, author) VALUES ('p1', 'ada')").await;
+    exec(&mut db, "INSERT INTO posts (id, author) VALUES ('p2', 'alan')").await;
+    exec(&mut db, "INSERT INTO comments (id, post) VALUES ('c1', 'p1')").await;
+    exec(&mut db, "INSERT INTO comments (id, post) VALUES ('c2', 'p2')").await;
+    exec(&mut db, "INSERT INTO replies (id, comment) VALUES ('r1', 'c1')").await;
+    exec(&mut db, "INSERT INTO pins (id, post) VALUES ('pin', 'p2')").await;
+
+    // SET NULL clears the reference
+    let result = exec(&mut db, "DELETE FROM users WHERE id = 'ada'").await;
+    assert!(matches!(result, QueryResult::Affected(1)));
+    let QueryResult::Documents(posts) = exec(&mut db, "SELECT * FROM posts WHERE id = 'p1'").await else {
+        panic!("Expected Documents result")
+    };
+    assert_eq!(posts[0].get("author"), Some(&mdby::storage::document::Value::Null));
+
+    // CASCADE follows references through several collections, in one commit
+    let before = db.git.head_hash().unwrap();
+    exec(&mut db, "DELETE FROM posts WHERE id = 'p1'").await;
+    assert!(!_tmp.path().join("collections/comments/c1.md").exists());
+    assert!(!_tmp.path().join("collections/replies/r1.md").exists());
+    assert!(_tmp.path().join("collections/comments/c2.md").exists());
+    let log = db.git.log(2).unwrap();
+    assert_eq!(log[1].id, before);
+    assert!(log[0].summary.contains("2 referencing document(s) deleted"), "{}", log[0].summary);
+
+    // RESTRICT refuses, and nothing is deleted
+    let err = db.execute("DELETE FROM posts WHERE id = 'p2'").await.unwrap_err();
+    assert!(err.to_string().contains("pins/pin"), "{}", err);
+    assert!(_tmp.path().join("collections/posts/p2.md").exists());
+    assert!(_tmp.path().join("collections/comments/c2.md").exists());
+
+    exec(&mut db, "DELETE FROM pins").await;
+    exec(&mut db, "DELETE FROM posts WHERE id = 'p2'").await;
+    assert!(!_tmp.path().join("collections/comments/c2.md").exists());
+
+    // The rule is stored in the schema and only applies to REF columns
+    let schema = std::fs::read_to_string(_tmp.path().join(".mdby/schemas/comments.yaml")).unwrap();
+    assert!(schema.contains("on_delete: cascade"), "{}", schema);
+    assert!(db.execute("CREATE COLLECTION bad (n INT ON DELETE CASCADE)").await.is_err());
+    assert!(!_tmp.path().join("collections/bad").exists());
+
+    // SHOW CREATE COLLECTION keeps the rule, so replaying it does too
+    let statement = definition(&mut db, "comments").await;
+    assert!(statement.contains("ON DELETE CASCADE"), "{}", statement);
+    assert!(definition(&mut db, "posts").await.contains("ON DELETE SET NULL"));
+    exec(&mut db, "DROP COLLECTION replies").await;
+    exec(&mut db, "DROP COLLECTION comments").await;
+    exec(&mut db, &statement).await;
+    assert_eq!(definition(&mut db, "comments").await, statement);
+}
+
+#[tokio::test]
+async fn test_on_delete_rules_need_the_policy_to_allow_referrers() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION posts").await;
+    exec(&mut db, "CREATE COLLECTION comments (post REF<posts> ON DELETE CASCADE)").await;
+    exec(&mut db, "CREATE COLLECTION audit (comment REF<comments> ON DELETE SET NULL)").await;
+    exec(&mut db, "INSERT INTO posts (id) VALUES ('p1')").await;
+    exec(&mut db, "INSERT INTO comments (id, post) VALUES ('c1', 'p1')").await;
+    exec(&mut db, "INSERT INTO audit (id, comment) VALUES ('a1', 'c1')").await;
+
+    // The cascade would reach comments, and through them audit
+    for denied in ["comments", "audit"] {
+        db.set_policy(ExecutionPolicy::default().deny_collection(denied));
+        let err = db.execute("DELETE FROM posts WHERE id = 'p1'").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<mdby::Error>(),
+            Some(mdby::Error::CollectionDenied { collection, .. }) if collection == denied
+        ));
+        assert!(tmp.path().join("collections/posts/p1.md").exists());
+        assert!(tmp.path().join("collections/comments/c1.md").exists());
+    }
+
+    db.set_policy(ExecutionPolicy::default().only_collections(["posts", "comments", "audit"]));
+    exec(&mut db, "DELETE FROM posts WHERE id = 'p1'").await;
+    assert!(!tmp.path().join("collections/comments/c1.md").exists());
+}
+
+// =============================================================================
+// SHOW Commands Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_show_create_collection_roundtrip() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, done BOOL DEFAULT false, due DATETIME, tags ARRAY<STRING>)").await;
+
+    let result = exec(&mut db, "SHOW CREATE COLLECTION todos").await;
+    let statement = match result {
+        QueryResult::Definition(statement) => statement,
+        other => panic!("Expected Definition, got {:?}", other),
+    };
+    assert!(statement.starts_with("CREATE COLLECTION todos ("));
+    assert!(statement.contains("title STRING REQUIRED"));
+    assert!(statement.contains("done BOOL DEFAULT false"));
+
+    // The output recreates the same schema in a fresh database
+    let (_tmp2, mut other) = setup_test_db().await;
+    exec(&mut other, &statement).await;
+    let result = exec(&mut other, "SHOW CREATE COLLECTION todos").await;
+    assert!(matches!(result, QueryResult::Definition(s) if s == statement));
+
+    // Schema-less collections and missing collections
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    let result = exec(&mut db, "SHOW CREATE COLLECTION notes").await;
+    assert!(matches!(result, QueryResult::Definition(s) if s == "CREATE COLLECTION notes"));
+    assert!(db.execute("SHOW CREATE COLLECTION missing").await.is_err());
+}
+
+#[tokio::test]
+async fn test_show_create_view() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "CREATE VIEW active AS SELECT title FROM todos WHERE done = false ORDER BY priority DESC LIMIT 5").await;
+
+    let result = exec(&mut db, "SHOW CREATE VIEW active").await;
+    assert!(matches!(
+        result,
+        QueryResult::Definition(s)
+            if s == "CREATE VIEW active AS SELECT title FROM todos WHERE done = false ORDER BY priority DESC LIMIT 5"
+    ));
+    assert!(db.execute("SHOW CREATE VIEW missing").await.is_err());
+}
+
+#[tokio::test]
+async fn test_show_collections() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    // Initially empty
+    let result = exec(&mut db, "SHOW COLLECTIONS").await;
+    if let QueryResult::Collections(names) = result {
+        assert!(names.is_empty());
+    } else {
+        panic!("Expected Collections result");
+    }
+
+    // Create some collections
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "CREATE COLLECTION users").await;
+    exec(&mut db, "CREATE COLLECTION projects").await;
+
+    // Should list all collections
+    let result = exec(&mut db, "SHOW COLLECTIONS").await;
+    if let QueryResult::Collections(names) = result {
+        assert_eq!(names.len(), 3);
+        assert!(names.contains(&"todos".to_string()));
+        assert!(names.contains(&"users".to_string()));
+        assert!(names.contains(&"projects".to_string()));
+    } else {
+        panic!("Expected Collections result");
+    }
+}
+
+#[tokio::test]
+async fn test_show_views() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    // Initially empty
+    let result = exec(&mut db, "SHOW VIEWS").await;
+    if let QueryResult::Views(names) = result {
+        assert!(names.is_empty());
+    } else {
+        panic!("Expected Views result");
+    }
+
+    // Create a collection and view
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "CREATE VIEW active AS SELECT * FROM todos").await;
+
+    // Should list the view
+    let result = exec(&mut db, "SHOW VIEWS").await;
+    if let QueryResult::Views(names) = result {
+        assert_eq!(names.len(), 1);
+        assert!(names.contains(&"active".to_string()));
+    } else {
+        panic!("Expected Views result");
+    }
+}
+
+// =============================================================================
+// Starter Template Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_starter_templates_apply_cleanly() {
+    for starter in mdby::starter::Starter::ALL {
+        let (tmp, mut db) = setup_test_db().await;
+
+        mdby::starter::apply(&mut db, starter).await
+            .unwrap_or_else(|e| panic!("{} starter failed: {}", starter, e));
+
+        // Example data is queryable and valid
+        let result = exec(&mut db, starter.example_query()).await;
+        assert!(matches!(result, QueryResult::Documents(docs) if !docs.is_empty()), "{}", starter);
+        if let QueryResult::Collections(names) = exec(&mut db, "SHOW COLLECTIONS").await {
+            for name in names {
+                assert!(db.validate_collection(&name).await.unwrap().is_empty(), "{}/{}", starter, name);
+            }
+        }
+
+        // Views are rendered and the starter is recorded in the config
+        if let QueryResult::Views(views) = exec(&mut db, "SHOW VIEWS").await {
+            assert!(!views.is_empty());
+            for view in views {
+                assert!(tmp.path().join("views").join(&view).join("index.html").exists(), "{}", view);
+            }
+        }
+        let config = mdby::config::Config::load(tmp.path()).unwrap();
+        assert_eq!(config.template.as_deref(), Some(starter.name()));
+        assert!(!db.git.has_changes().unwrap());
+    }
+}
+
+#[tokio::test]
+async fn test_starter_template_refuses_existing_collection() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+
+    let result = mdby::starter::apply(&mut db, mdby::starter::Starter::Todo).await;
+    assert!(result.is_err());
+    assert!(!tmp.path().join(".mdby/templates/todo-list.html").exists());
+}
+
+#[test]
+fn test_init_rejects_unknown_starter_template() {
+    let tmp = TempDir::new().unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_mdby"))
+        .args(["init", "--template", "nope"])
+        .current_dir(tmp.path())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("invalid value 'nope'") && !stderr.contains("panicked"), "{}", stderr);
+    assert!(!tmp.path().join(".mdby").exists());
+}
+
+// =============================================================================
+// Docs Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_generate_docs() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, priority INT DEFAULT 1)").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('a', 'First')").await;
+    exec(&mut db, "CREATE VIEW open_todos AS SELECT * FROM todos WHERE done = false").await;
+
+    let path = db.generate_docs().await.unwrap();
+    let html = std::fs::read_to_string(&path).unwrap();
+
+    assert!(path.ends_with("views/_docs/index.html"));
+    assert!(html.contains("todos"));
+    assert!(html.contains("REQUIRED"));
+    assert!(html.contains("1 document(s)"));
+    assert!(html.contains("SELECT * FROM todos WHERE done = false"));
+    assert!(html.contains("INSERT into todos: a"));
+}
+
+#[tokio::test]
+async fn test_docs_refresh_with_views() {
+    let (_tmp, mut db) = setup_test_db().await;
+    let path = db.generate_docs().await.unwrap();
+    assert!(!std::fs::read_to_string(&path).unwrap().contains("notes"));
+
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    db.regenerate_views().await.unwrap();
+
+    assert!(std::fs::read_to_string(&path).unwrap().contains("notes"));
+}
+
+#[tokio::test]
+async fn test_dashboard_view() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (name STRING, due DATE, finished BOOL, labels ARRAY<STRING>)").await;
+    exec(&mut db, "CREATE COLLECTION secrets").await;
+
+    // Display hints name the fields with a role on the dashboard
+    let schema_path = tmp.path().join(".mdby/schemas/todos.yaml");
+    let schema = std::fs::read_to_string(&schema_path).unwrap();
+    let hints = "display:\n  title: name\n  due: due\n  done: finished\n  tags: labels\n";
+    std::fs::write(&schema_path, format!("{}{}", schema, hints)).unwrap();
+    std::fs::write(tmp.path().join(".mdby/schemas/secrets.yaml"), "name: secrets\ndisplay:\n  hidden: true\n").unwrap();
+    let mut db = Database::open(tmp.path()).await.unwrap();
+
+    exec(
+        &mut db,
+        "INSERT INTO todos (id, name, due, finished, labels) VALUES \
+         ('late', 'Pay rent', '2020-01-01', false, ['home', 'money']), \
+         ('done', 'Old chore', '2020-01-01', true, ['home']), \
+         ('later', 'Someday', '2999-01-01', false, [])",
+    )
+    .await;
+    exec(&mut db, "INSERT INTO secrets (id) VALUES ('s')").await;
+
+    let path = db.generate_dashboard().await.unwrap();
+    assert!(path.ends_with("views/@dashboard/index.html"));
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path.with_file_name("index.json")).unwrap()).unwrap();
+    assert_eq!(json["documents"], 3);
+    let todos = &json["collections"][0];
+    assert_eq!(json["collections"].as_array().unwrap().len(), 1);
+    assert_eq!(todos["overdue"].as_array().unwrap().len(), 1);
+    assert_eq!(todos["overdue"][0]["title"], "Pay rent");
+    assert_eq!(todos["tags"][0], serde_json::json!({"tag": "home", "count": 2}));
+    assert_eq!(json["recent"].as_array().unwrap().len(), 3);
+    assert!(std::fs::read_to_string(&path).unwrap().contains("1 overdue"));
+
+    // Refreshed with the other views
+    exec(&mut db, "UPDATE todos SET finished = true WHERE @id = 'late'").await;
+    db.regenerate_views().await.unwrap();
+    assert!(!std::fs::read_to_string(&path).unwrap().contains("overdue</span>"));
+}
+
+// =============================================================================
+// Result Diff Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_diff_queries() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, status, points) VALUES ('a', 'open', 1)").await;
+    exec(&mut db, "INSERT INTO todos (id, status, points) VALUES ('b', 'open', 2)").await;
+    exec(&mut db, "INSERT INTO todos (id, status, points) VALUES ('c', 'done', 3)").await;
+
+    let diff = db
+        .diff_queries("SELECT * FROM todos WHERE status = 'open'", None, "SELECT * FROM todos WHERE points > 1", None)
+        .await
+        .unwrap();
+    assert_eq!(diff.added.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(), vec!["c"]);
+    assert_eq!(diff.removed.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(), vec!["a"]);
+    assert!(diff.changed.is_empty());
+
+    // The same query before and after some writes
+    let before = db.git.head_hash().unwrap();
+    exec(&mut db, "UPDATE todos SET points = 5 WHERE @id = 'b'").await;
+    exec(&mut db, "INSERT INTO todos (id, status) VALUES ('d', 'open')").await;
+    exec(&mut db, "DELETE FROM todos WHERE @id = 'a'").await;
+
+    let query = "SELECT * FROM todos WHERE status = 'open'";
+    let diff = db.diff_queries(query, Some(&before), query, None).await.unwrap();
+    assert_eq!(diff.added.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(), vec!["d"]);
+    assert_eq!(diff.removed.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(), vec!["a"]);
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.changed[0].after.id, "b");
+    assert_eq!(diff.changed[0].fields, vec!["points"]);
+
+    // Both sides at revisions
+    let diff = db.diff_queries(query, Some(&before), query, Some("HEAD")).await.unwrap();
+    assert_eq!((diff.added.len(), diff.removed.len(), diff.changed.len()), (1, 1, 1));
+
+    assert!(db.diff_queries("SHOW COLLECTIONS", None, query, None).await.is_err());
+    assert!(db.diff_queries(query, Some("no-such-revision"), query, None).await.is_err());
+}
+
+#[tokio::test]
+async fn test_select_as_of() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "CREATE COLLECTION users").await;
+    exec(&mut db, "INSERT INTO users (id, name) VALUES ('ada', 'Ada')").await;
+    exec(&mut db, "INSERT INTO todos (id, owner, done) VALUES ('a', 'ada', false)").await;
+    let before = db.git.head_hash().unwrap();
+    exec(&mut db, "UPDATE todos SET done = true WHERE @id = 'a'").await;
+    exec(&mut db, "UPDATE users SET name = 'Ada L.' WHERE @id = 'ada'").await;
+    exec(&mut db, "INSERT INTO todos (id, owner, done) VALUES ('b', 'ada', false)").await;
+
+    let query = format!("SELECT * FROM todos AS OF '{}' WHERE done = false", before);
+    let result = exec(&mut db, &query).await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents") };
+    assert_eq!(docs.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(), vec!["a"]);
+
+    // Joined collections are read at the same commit
+    let query = format!("SELECT t.@id, u.name FROM todos AS OF '{}' AS t JOIN users AS u ON t.owner = u.@id", before);
+    let result = exec(&mut db, &query).await;
+    let QueryResult::Documents(docs) = result else { panic!("Expected Documents") };
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].get("name").and_then(|v| v.as_str()), Some("Ada"));
+
+    // A time means the last commit at or before it
+    let result = exec(&mut db, "SELECT * FROM todos AS OF '2999-01-01'").await;
+    assert!(matches!(result, QueryResult::Documents(docs) if docs.len() == 2));
+    assert!(db.execute("SELECT * FROM todos AS OF '1999-01-01'").await.is_err());
+    assert!(db.execute("SELECT * FROM todos AS OF 'no-such-revision'").await.is_err());
+    assert!(db.execute(&format!("CREATE VIEW old AS SELECT * FROM todos AS OF '{}'", before)).await.is_err());
+}
+
+#[tokio::test]
+async fn test_document_history() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('a', 'First')").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('b', 'Other')").await;
+    exec(&mut db, "UPDATE todos SET title = 'Second' WHERE @id = 'a'").await;
+    exec(&mut db, "DELETE FROM todos WHERE @id = 'a'").await;
+
+    let versions = db.history("todos", "a").await.unwrap();
+    assert_eq!(versions.len(), 3);
+    assert!(versions[0].document.is_none());
+    assert_eq!(versions[0].commit.id, db.git.head_hash().unwrap());
+    let titles: Vec<_> = versions[1..]
+        .iter()
+        .map(|v| v.document.as_ref().unwrap().get("title").and_then(|t| t.as_str().map(String::from)))
+        .collect();
+    assert_eq!(titles, vec![Some("Second".to_string()), Some("First".to_string())]);
+    assert!(versions[2].commit.summary.starts_with("INSERT"));
+
+    assert!(db.history("todos", "missing").await.unwrap().is_empty());
+    assert!(db.history("todos", "../x").await.is_err());
+}
+
+// =============================================================================
+// Export Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_export_scrub() {
+    let (tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION people (name STRING, email STRING, phone STRING)").await;
+    let path = tmp.path().join(".mdby/schemas/people.yaml");
+    let schema = std::fs::read_to_string(&path).unwrap();
+    let schema = schema
+        .replace("  email:\n", "  email:\n    scrub: hash\n")
+        .replace("  phone:\n", "  phone:\n    scrub: drop\n");
+    std::fs::write(&path, format!("{}scrub_body:\n  truncate: 7\n", schema)).unwrap();
+    let mut db = Database::open(tmp.path()).await.unwrap();
+
+    exec(&mut db, "INSERT INTO people (id, name, email, phone) VALUES ('ada', 'Ada', 'ada@example.com', '555-0100') BODY 'Private notes'").await;
+    exec(&mut db, "INSERT INTO people (id, name, email) VALUES ('bob', 'Bob', 'ada@example.com')").await;
+
+    let dest = TempDir::new().unwrap();
+    let summary = db.export(&dest.path().join("plain"), false).await.unwrap();
+    assert_eq!((summary.collections, summary.documents, summary.scrubbed), (1, 2, 0));
+    let plain = std::fs::read_to_string(dest.path().join("plain/collections/people/ada.md")).unwrap();
+    assert!(plain.contains("555-0100") && plain.contains("Private notes"));
+    assert!(db.export(&dest.path().join("plain"), false).await.is_err());
+
+    let summary = db.export(&dest.path().join("scrubbed"), true).await.unwrap();
+    assert_eq!(summary.scrubbed, 4);
+    let mut copy = Database::open(dest.path().join("scrubbed")).await.unwrap();
+    let result = exec(&mut copy, "SELECT * FROM people ORDER BY id").await;
+    match result {
+        QueryResult::Documents(docs) => {
+            let ada = &docs[0];
+            assert_eq!(ada.get("name"), Some(&mdby::storage::document::Value::from("Ada")));
+            assert!(ada.get("phone").is_none());
+            let email = ada.get("email").and_then(|v| v.as_str()).unwrap();
+            assert!(email.ends_with("@example.invalid") && !email.starts_with("ada"));
+            // Equal values hash alike
+            assert_eq!(docs[1].get("email"), ada.get("email"));
+            assert_eq!(ada.body.trim(), "Private");
+        }
+        _ => panic!("Expected Documents result"),
+    }
+    // Schemas come along; the config doesn't
+    assert!(dest.path().join("scrubbed/.mdby/schemas/people.yaml").exists());
+}
+
+// =============================================================================
+// Batch Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_batch_results() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    let result = exec(
+        &mut db,
+        "CREATE COLLECTION todos (title STRING REQUIRED);
+         INSERT INTO todos (id, title) VALUES ('a', 'First');
+         INSERT INTO todos (id) VALUES ('b');  -- missing title
+         INSERT INTO todos (id, title) VALUES ('c', 'Third');
+         UPDATE todos SET done = true;
+         SELECT * FROM todos",
+    )
+    .await;
+    let QueryResult::Batch(batch) = result else { panic!("Expected Batch result") };
+    assert_eq!(batch.statements.len(), 6);
+    assert_eq!((batch.succeeded(), batch.failed(), batch.affected()), (5, 1, 4));
+
+    // Statements after a failure still run
+    assert!(batch.statements[2].result.is_err());
+    assert!(batch.statements[2].statement.starts_with("INSERT INTO todos (id) VALUES ('b')"));
+    assert!(matches!(batch.statements[4].result, Ok(QueryResult::Affected(2))));
+    match &batch.statements[5].result {
+        Ok(QueryResult::Documents(docs)) => assert_eq!(docs.len(), 2),
+        other => panic!("Expected Documents result, got {:?}", other),
+    }
+
+    // A single statement keeps its own result, trailing semicolon or not
+    assert!(matches!(exec(&mut db, "DELETE FROM todos WHERE id = 'a';").await, QueryResult::Affected(1)));
+
+    // A batch that doesn't parse runs nothing
+    assert!(db.execute("DELETE FROM todos; SELEC * FROM todos").await.is_err());
+    assert!(_tmp.path().join("collections/todos/c.md").exists());
+}
+
+#[tokio::test]
+async fn test_execute_script_is_one_commit() {
+    let (tmp, mut db) = setup_test_db().await;
+    let head = db.git.head_hash().unwrap();
+
+    let batch = db
+        .execute_script(
+            "-- Set up the todo list
+CREATE COLLECTION todos (title STRING REQUIRED);
+INSERT INTO todos (id, title) VALUES ('a', 'First'), ('b', 'Second');
+
+UPDATE todos SET done = true WHERE id = 'a';
+SELECT * FROM todos WHERE done = true;",
+        )
+        .await
+        .unwrap();
+    assert_eq!((batch.succeeded(), batch.failed()), (4, 0));
+    assert_eq!(batch.statements.iter().map(|s| s.line).collect::<Vec<_>>(), vec![2, 3, 5, 6]);
+    assert!(matches!(&batch.statements[3].result, Ok(QueryResult::Documents(docs)) if docs.len() == 1));
+
+    let log = db.git.log(2).unwrap();
+    assert_eq!(log[1].id, head);
+    assert_eq!(log[0].summary, "SCRIPT: 3 statement(s)");
+    assert!(!db.git.has_changes().unwrap());
+
+    // A failing statement undoes the whole script
+    let head = db.git.head_hash().unwrap();
+    let batch = db
+        .execute_script(
+            "INSERT INTO todos (id, title) VALUES ('c', 'Third');
+DELETE FROM todos WHERE id = 'a';
+INSERT INTO todos (id) VALUES ('d');
+INSERT INTO todos (id, title) VALUES ('e', 'Never run');",
+        )
+        .await
+        .unwrap();
+    assert_eq!(batch.statements.len(), 3);
+    assert_eq!(batch.statements[2].line, 3);
+    assert!(batch.statements[2].result.is_err());
+    assert_eq!(db.git.head_hash().unwrap(), head);
+    assert!(!db.git.has_changes().unwrap());
+    assert!(tmp.path().join("collections/todos/a.md").exists());
+    assert!(!tmp.path().join("collections/todos/c.md").exists());
+
+    // A script that doesn't parse runs nothing and names the line
+    let err = db.execute_script("DELETE FROM todos;\nSELEC * FROM todos").await.unwrap_err();
+    assert!(err.to_string().contains("line 2"), "{}", err);
+    assert!(tmp.path().join("collections/todos/b.md").exists());
+}
+
+// =============================================================================
+// Seed Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_seed_from_schema() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION authors (name STRING REQUIRED, email STRING UNIQUE)").await;
+    exec(&mut db, "INSERT INTO authors (id, name) VALUES ('ada', 'Ada')").await;
+    exec(&mut db, "INSERT INTO authors (id, name) VALUES ('alan', 'Alan')").await;
+    exec(
+        &mut db,
+        "CREATE COLLECTION posts (title STRING REQUIRED, author REF<authors> REQUIRED, views INT, published DATE)",
+    )
+    .await;
+
+    let ids = db.seed("posts", 50, true, Some(1)).await.unwrap();
+    assert_eq!(ids.len(), 50);
+    assert!(!db.git.has_changes().unwrap());
+
+    let result = exec(&mut db, "SELECT * FROM posts").await;
+    match result {
+        QueryResult::Documents(docs) => {
+            assert_eq!(docs.len(), 50);
+            for doc in &docs {
+                let author = doc.get("author").and_then(|v| v.as_str()).unwrap();
+                assert!(author == "ada" || author == "alan");
+                assert!(doc.get("title").and_then(|v| v.as_str()).is_some_and(|t| !t.is_empty()));
+            }
+        }
+        _ => panic!("Expected Documents result"),
+    }
+
+    // The same seed gives the same documents
+    let (_other_tmp, mut other) = setup_test_db().await;
+    exec(&mut other, "CREATE COLLECTION authors (name STRING REQUIRED, email STRING UNIQUE)").await;
+    exec(&mut other, "INSERT INTO authors (id, name) VALUES ('ada', 'Ada')").await;
+    exec(&mut other, "INSERT INTO authors (id, name) VALUES ('alan', 'Alan')").await;
+    exec(
+        &mut other,
+        "CREATE COLLECTION posts (title STRING REQUIRED, author REF<authors> REQUIRED, views INT, published DATE)",
+    )
+    .await;
+    assert_eq!(other.seed("posts", 50, true, Some(1)).await.unwrap(), ids);
+
+    // UNIQUE values stay distinct, also from existing documents
+    db.seed("authors", 300, true, None).await.unwrap();
+    let result = exec(&mut db, "SELECT email FROM authors WHERE email IS NOT NULL").await;
+    match result {
+        QueryResult::Documents(docs) => {
+            let emails: std::collections::HashSet<_> = docs.iter().filter_map(|d| d.get("email")?.as_str()).collect();
+            assert_eq!(emails.len(), docs.len());
+        }
+        _ => panic!("Expected Documents result"),
+    }
+
+    // A required REF needs something to point at
+    exec(&mut db, "CREATE COLLECTION comments (post REF<drafts> REQUIRED)").await;
+    assert!(db.seed("comments", 1, true, None).await.is_err());
+}
+
+#[tokio::test]
+async fn test_seed_reuses_existing_values() {
+    let (_tmp, mut db) = setup_test_db().await;
+
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, status STRING)").await;
+    exec(&mut db, "INSERT INTO todos (id, title, status) VALUES ('a', 'Write docs', 'open')").await;
+    exec(&mut db, "INSERT INTO todos (id, title, status) VALUES ('b', 'Fix bug', 'closed')").await;
+
+    db.seed("todos", 20, false, Some(3)).await.unwrap();
+    let result = exec(&mut db, "SELECT * FROM todos").await;
+    match result {
+        QueryResult::Documents(docs) => {
+            assert_eq!(docs.len(), 22);
+            for doc in &docs {
+                let status = doc.get("status").and_then(|v| v.as_str()).unwrap();
+                assert!(status == "open" || status == "closed");
+            }
+            assert!(docs.iter().any(|d| d.id == "todos-1"));
+        }
+        _ => panic!("Expected Documents result"),
+    }
+
+    // Without a schema, there is nothing to generate from
+    assert!(db.seed("missing", 1, false, None).await.is_err());
+    assert!(db.seed("missing", 1, true, None).await.is_err());
+}
+
+#[tokio::test]
+async fn test_select_stops_reading_at_limit() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION notes (n INT)").await;
+    let rows: Vec<String> = (0..20).map(|n| format!("('note-{}', {})", n, n)).collect();
+    exec(&mut db, &format!("INSERT INTO notes (id, n) VALUES {}", rows.join(", "))).await;
+
+    db.config.query_log = true;
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT n FROM notes LIMIT 3").await else { panic!() };
+    assert_eq!(docs.len(), 3);
+    assert!(docs.iter().all(|doc| doc.fields.len() == 1));
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM notes LIMIT 3 OFFSET 2").await else { panic!() };
+    assert_eq!(docs.len(), 3);
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM notes WHERE n >= 15 LIMIT 10 OFFSET 1").await else {
+        panic!()
+    };
+    assert_eq!(docs.len(), 4);
+    assert!(docs.iter().all(|doc| doc.get("n").and_then(|v| v.as_i64()).unwrap() >= 15));
+    // ORDER BY needs every document
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM notes ORDER BY n DESC LIMIT 1").await else { panic!() };
+    assert_eq!(docs[0].id, "note-19");
+
+    let entries = mdby::query_log::read(tmp.path()).unwrap();
+    let scanned: Vec<usize> = entries.iter().map(|entry| entry.scanned["notes"]).collect();
+    assert_eq!(scanned, vec![3, 5, 20, 20]);
+}
+
+#[tokio::test]
+async fn test_explain_select() {
+    use mdby::query::plan::{Access, SortStrategy};
+
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (status STRING INDEXED, priority INT)").await;
+    exec(&mut db, "CREATE COLLECTION users (name STRING)").await;
+    let rows: Vec<String> = (0..10)
+        .map(|n| format!("('t{}', '{}', {})", n, if n < 3 { "open" } else { "done" }, n))
+        .collect();
+    exec(&mut db, &format!("INSERT INTO todos (id, status, priority) VALUES {}", rows.join(", "))).await;
+    exec(&mut db, "INSERT INTO users (id, name) VALUES ('ada', 'Ada')").await;
+
+    let explain = |query: &str| format!("EXPLAIN {}", query);
+    let QueryResult::Plan(plan) = exec(&mut db, &explain("SELECT * FROM todos LIMIT 4")).await else { panic!() };
+    assert_eq!(plan.access, Access::Stream);
+    assert_eq!((plan.documents, plan.estimated_scanned), (10, 4));
+    assert_eq!(plan.sort, SortStrategy::None);
+
+    // The indexed condition narrows the documents down first
+    let query = "SELECT * FROM todos WHERE priority > 1 AND status = 'open' ORDER BY priority DESC";
+    let QueryResult::Plan(plan) = exec(&mut db, &explain(query)).await else { panic!() };
+    assert_eq!(plan.access, Access::IndexLookup { fields: vec!["status".into()] });
+    assert_eq!(plan.estimated_scanned, 3);
+    let filters: Vec<(&str, Option<&str>)> =
+        plan.filters.iter().map(|f| (f.condition.as_str(), f.index.as_deref())).collect();
+    assert_eq!(filters, vec![("status = 'open'", Some("status")), ("priority > 1", None)]);
+    assert_eq!(plan.sort, SortStrategy::InMemory { keys: vec!["priority DESC".into()] });
+
+    let query = "SELECT t.status, COUNT(*) FROM todos AS t JOIN users AS u ON t.owner = u.id GROUP BY t.status";
+    let QueryResult::Plan(plan) = exec(&mut db, &explain(query)).await else { panic!() };
+    assert_eq!(plan.access, Access::FullScan);
+    assert!(plan.aggregate);
+    assert_eq!(plan.joins.len(), 1);
+    assert_eq!(plan.estimated_scanned, 11);
+
+    let QueryResult::Plan(plan) = exec(&mut db, &explain("SELECT * FROM @commits")).await else { panic!() };
+    assert_eq!(plan.access, Access::System);
+
+    // Documents are counted, not read
+    db.config.query_log = true;
+    exec(&mut db, &explain("SELECT * FROM todos")).await;
+    let entries = mdby::query_log::read(db.root.as_path()).unwrap();
+    assert!(entries[0].scanned.is_empty());
+    assert!(db.execute("EXPLAIN SELECT * FROM missing").await.is_err());
+    assert!(db.execute("EXPLAIN DELETE FROM todos").await.is_err());
+}
+
+// =============================================================================
+// Query Log Tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_query_log() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (status STRING INDEXED)").await;
+    assert!(mdby::query_log::read(tmp.path()).unwrap().is_empty());
+
+    db.config.query_log = true;
+    exec(&mut db, "INSERT INTO todos (id, status) VALUES ('a', 'open')").await;
+    exec(&mut db, "INSERT INTO todos (id, status) VALUES ('b', 'done')").await;
+    exec(&mut db, "SELECT * FROM todos").await;
+    exec(&mut db, "SELECT * FROM todos WHERE status = 'open'").await;
+    exec(&mut db, "UPDATE todos SET status = 'open'").await;
+    assert!(db.execute("SELECT * FROM missing").await.is_err());
+
+    let entries = mdby::query_log::read(tmp.path()).unwrap();
+    assert_eq!(entries.len(), 6);
+    assert_eq!(entries[2].statement, "SELECT * FROM todos");
+    assert_eq!(entries[2].scanned.get("todos"), Some(&2));
+    // The index narrows the read down to one document
+    assert_eq!(entries[3].scanned.get("todos"), Some(&1));
+    assert!(entries[5].error.as_deref().unwrap().contains("does not exist"));
+
+    let report = mdby::query_log::report(&entries, 10);
+    assert_eq!(report.entries, 6);
+    assert_eq!(report.slowest.len(), 6);
+    assert_eq!(report.most_scanned[0].collection, "todos");
+    assert_eq!((report.most_scanned[0].queries, report.most_scanned[0].documents), (3, 5));
+
+    // The log isn't committed
+    let status = std::process::Command::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(tmp.path())
+        .output()
+        .unwrap();
+    assert!(!String::from_utf8_lossy(&status.stdout).contains("logs"));
+}
+
+// =============================================================================
+// HTTP API Tests
+// =============================================================================
+
+/// Serve `db` on a free local port and return its base URL
+async fn serve(db: Database) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let router = mdby::server::router(db);
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    url
+}
+
+/// Make a request with a blocking client, returning the status and body
+async fn request(method: &'static str, url: String, body: Option<&'static str>) -> (u16, String) {
+    let (status, _, body) = request_with(method, url, None, body.map(str::to_string)).await;
+    (status, body)
+}
+
+/// Make a request with the headers given, returning the status, response
+/// headers (names in lowercase) and body
+async fn request_headers(
+    method: &'static str,
+    url: String,
+    headers: &'static [(&'static str, &'static str)],
+    body: Option<&'static str>,
+) -> (u16, std::collections::BTreeMap<String, String>, String) {
+    tokio::task::spawn_blocking(move || {
+        let mut request = ureq::request(method, &url);
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        let response = match body {
+            Some(body) => request.send_string(body),
+            None => request.call(),
+        };
+        match response {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => {
+                let headers = response
+                    .headers_names()
+                    .into_iter()
+                    .filter_map(|name| Some((name.to_lowercase(), response.header(&name)?.to_string())))
+                    .collect();
+                (response.status(), headers, response.into_string().unwrap())
+            }
+            Err(e) => panic!("Request to {} failed: {}", url, e),
+        }
+    })
+    .await
+    .unwrap()
+}
+
+/// Make a request with an `If-Match` header, returning the status, `ETag`
+/// and body; it has the `MDBY-Query` header, as scripts send plain-text
+/// statements
+async fn request_with(
+    method: &'static str,
+    url: String,
+    if_match: Option<String>,
+    body: Option<String>,
+) -> (u16, Option<String>, String) {
+    tokio::task::spawn_blocking(move || {
+        let mut request = ureq::request(method, &url).set("MDBY-Query", "true");
+        if let Some(tag) = &if_match {
+            request = request.set("If-Match", tag);
+        }
+        let response = match body {
+            Some(body) => request.send_string(&body),
+            None => request.call(),
+        };
+        match response {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => {
+                let etag = response.header("ETag").map(str::to_string);
+                (response.status(), etag, response.into_string().unwrap())
+            }
+            Err(e) => panic!("Request to {} failed: {}", url, e),
+        }
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_http_api() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, title, done) VALUES ('a', 'First', false)").await;
+    exec(&mut db, "CREATE VIEW open_todos AS SELECT * FROM todos WHERE done = false").await;
+    db.regenerate_views().await.unwrap();
+    let url = serve(db).await;
+
+    let (status, body) = request("POST", format!("{}/query", url), Some("SELECT title FROM todos")).await;
+    assert_eq!(status, 200);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json, serde_json::json!([{"id": "a", "title": "First"}]));
+
+    let (status, body) = request("POST", format!("{}/query", url), Some("UPDATE todos SET done = true")).await;
+    assert_eq!(status, 200);
+    assert_eq!(body, r#"{"affected":1}"#);
+
+    let (status, body) = request("GET", format!("{}/collections/todos/a", url), None).await;
+    assert_eq!(status, 200);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["done"], serde_json::json!(true));
+
+    let (status, _) = request("GET", format!("{}/collections/todos/missing", url), None).await;
+    assert_eq!(status, 404);
+
+    let (status, body) = request("GET", format!("{}/views/open_todos", url), None).await;
+    assert_eq!(status, 200);
+    assert!(body.contains("<html") || body.contains("<!DOCTYPE"));
+    let (status, _) = request("GET", format!("{}/views/nope", url), None).await;
+    assert_eq!(status, 404);
+
+    let (status, body) = request("POST", format!("{}/query", url), Some("SELEC nonsense")).await;
+    assert_eq!(status, 400);
+    assert!(serde_json::from_str::<serde_json::Value>(&body).unwrap()["error"].is_string());
+}
+
+#[tokio::test]
+async fn test_http_api_applies_policy() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    db.set_policy(ExecutionPolicy::read_only());
+    let url = serve(db).await;
+
+    let (status, body) = request("POST", format!("{}/query", url), Some("DROP COLLECTION todos")).await;
+    assert_eq!(status, 403);
+    assert!(body.contains("not allowed by the execution policy"));
+
+    let (status, body) = request("POST", format!("{}/query", url), Some("SHOW COLLECTIONS")).await;
+    assert_eq!(status, 200);
+    assert_eq!(body, r#"["todos"]"#);
+}
+
+#[tokio::test]
+async fn test_http_refuses_statements_pages_could_send() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    let url = serve(db).await;
+    let query = format!("{}/query", url);
+
+    // What a form on another site can post without a preflight
+    let forms: [&'static [(&str, &str)]; 2] = [&[("Content-Type", "text/plain")], &[("Content-Type", "application/x-www-form-urlencoded")]];
+    for form in forms {
+        let (status, _, body) = request_headers("POST", query.clone(), form, Some("DROP COLLECTION todos")).await;
+        assert_eq!(status, 415, "{}", body);
+    }
+    let plain = &[("Content-Type", "text/plain"), ("MDBY-Query", "true")];
+    let (status, _, body) = request_headers("POST", query.clone(), plain, Some("SHOW COLLECTIONS")).await;
+    assert_eq!((status, body.as_str()), (200, r#"["todos"]"#));
+
+    let json = &[("Content-Type", "application/json")];
+    let (status, _, body) = request_headers("POST", query.clone(), json, Some(r#"{"query": "SHOW COLLECTIONS"}"#)).await;
+    assert_eq!((status, body.as_str()), (200, r#"["todos"]"#));
+}
+
+#[tokio::test]
+async fn test_http_refuses_views_over_denied_collections() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "CREATE COLLECTION secrets").await;
+    exec(&mut db, "INSERT INTO secrets (id, key) VALUES ('a', 'hunter2')").await;
+    exec(&mut db, "CREATE VIEW open AS SELECT * FROM todos").await;
+    exec(&mut db, "CREATE VIEW keys AS SELECT * FROM secrets").await;
+    exec(&mut db, "CREATE VIEW some_keys AS SELECT * FROM view:keys LIMIT 1").await;
+    db.regenerate_views().await.unwrap();
+    db.set_policy(ExecutionPolicy::read_only().deny_collection("secrets"));
+    let url = serve(db).await;
+
+    let (status, _) = request("GET", format!("{}/views/open", url), None).await;
+    assert_eq!(status, 200);
+    for view in ["keys", "some_keys"] {
+        let (status, body) = request("GET", format!("{}/views/{}", url, view), None).await;
+        assert_eq!(status, 403, "{}", view);
+        assert!(!body.contains("hunter2"), "{}", body);
+    }
+}
+
+#[tokio::test]
+async fn test_http_negotiates_formats_and_allows_cors_origins() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(&mut db, "INSERT INTO todos (id, title, tags) VALUES ('a', 'Milk, eggs', ['shop', 'food']), ('b', '<b>Bold</b>', ['x'])").await;
+    db.config.serve.cors_origins = vec!["https://app.example.com/".to_string()];
+    let url = serve(db).await;
+    let todos = format!("{}/collections/todos?order_by=id", url);
+
+    let (status, headers, body) = request_headers("GET", todos.clone(), &[("Accept", "text/csv")], None).await;
+    assert_eq!(status, 200, "{}", body);
+    assert!(headers["content-type"].starts_with("text/csv"));
+    assert_eq!(headers["vary"], "accept");
+    assert_eq!(body, "id,tags,title\na,shop;food,\"Milk, eggs\"\nb,x,<b>Bold</b>\n");
+
+    // The most preferred format the API has wins, so browsers get HTML
+    let browser = &[("Accept", "text/html,application/xhtml+xml,*/*;q=0.8")];
+    let (_, headers, body) = request_headers("GET", todos.clone(), browser, None).await;
+    assert!(headers["content-type"].starts_with("text/html"));
+    assert!(body.contains("<th>title</th>") && body.contains("<td>&lt;b&gt;Bold&lt;&#x2F;b&gt;</td>"), "{}", body);
+    let (_, headers, _) = request_headers("GET", todos.clone(), &[("Accept", "text/csv;q=0.5, application/json")], None).await;
+    assert!(headers["content-type"].starts_with("application/json"));
+
+    let (status, _, body) = request_headers("POST", format!("{}/query", url), &[("Accept", "text/csv"), ("MDBY-Query", "true")], Some("SHOW COLLECTIONS")).await;
+    assert_eq!((status, body.as_str()), (200, "value\ntodos\n"));
+    let (status, headers, body) = request_headers("GET", format!("{}/collections/todos/a", url), &[("Accept", "text/csv")], None).await;
+    assert_eq!(status, 200);
+    assert!(headers.contains_key("etag"));
+    assert!(body.starts_with("id,"), "{}", body);
+    let (status, _, _) = request_headers("GET", todos.clone(), &[("Accept", "image/png")], None).await;
+    assert_eq!(status, 406);
+
+    // Pages on the configured origin may call the API, and read ETags
+    let preflight = &[
+        ("Origin", "https://app.example.com"),
+        ("Access-Control-Request-Method", "PATCH"),
+        ("Access-Control-Request-Headers", "if-match, content-type"),
+    ];
+    let (status, headers, _) = request_headers("OPTIONS", format!("{}/collections/todos/a", url), preflight, None).await;
+    assert_eq!(status, 200);
+    assert_eq!(headers["access-control-allow-origin"], "https://app.example.com");
+    assert!(headers["access-control-allow-methods"].contains("PATCH"));
+    assert!(headers["access-control-allow-headers"].contains("if-match"));
+    let (_, headers, _) = request_headers("GET", todos.clone(), &[("Origin", "https://app.example.com")], None).await;
+    assert_eq!(headers["access-control-allow-origin"], "https://app.example.com");
+    assert_eq!(headers["access-control-expose-headers"], "etag");
+    let (_, headers, _) = request_headers("GET", todos, &[("Origin", "https://evil.example.com")], None).await;
+    assert!(!headers.contains_key("access-control-allow-origin"));
+}
+
+#[tokio::test]
+async fn test_http_describes_collections_in_openapi() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION users").await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, priority INT DEFAULT 3, due DATE, owner REF<users>)").await;
+    exec(&mut db, "CREATE COLLECTION secrets (key STRING)").await;
+    db.set_policy(ExecutionPolicy::read_only().deny_collection("secrets"));
+    let url = serve(db).await;
+
+    let (status, body) = request("GET", format!("{}/openapi.json", url), None).await;
+    assert_eq!(status, 200, "{}", body);
+    let spec: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(spec["openapi"], "3.0.3");
+
+    // Document shapes come from the schema's fields
+    let todo = &spec["components"]["schemas"]["todos"];
+    assert_eq!(todo["required"], serde_json::json!(["id", "title"]));
+    assert_eq!(
+        todo["properties"]["priority"],
+        serde_json::json!({"type": "integer", "format": "int64", "default": 3, "nullable": true})
+    );
+    assert_eq!(todo["properties"]["due"]["format"], "date");
+    assert_eq!(todo["properties"]["owner"]["type"], "string");
+    assert_eq!(todo["additionalProperties"], true);
+    assert_eq!(spec["components"]["schemas"]["users"]["required"], serde_json::json!(["id"]));
+
+    // Routes follow the policy: no writes, and nothing of denied collections
+    let paths = &spec["paths"];
+    assert!(paths["/query"]["post"].is_object());
+    assert_eq!(paths["/collections/todos"]["get"]["responses"]["200"]["content"]["application/json"]["schema"]["items"]["$ref"], "#/components/schemas/todos");
+    assert!(paths["/collections/todos/{id}"]["get"].is_object());
+    assert!(paths["/collections/todos/{id}"]["put"].is_null());
+    assert!(paths["/collections/todos/{id}"]["delete"].is_null());
+    assert!(paths["/collections/secrets"].is_null());
+    assert!(spec["components"]["schemas"]["secrets"].is_null());
+
+    // Every reference resolves
+    fn refs<'a>(value: &'a serde_json::Value, found: &mut Vec<&'a str>) {
+        match value {
+            serde_json::Value::Object(object) => {
+                found.extend(object.get("$ref").and_then(|r| r.as_str()));
+                object.values().for_each(|value| refs(value, found));
+            }
+            serde_json::Value::Array(items) => items.iter().for_each(|value| refs(value, found)),
+            _ => {}
+        }
+    }
+    let mut found = Vec::new();
+    refs(&spec, &mut found);
+    assert!(!found.is_empty());
+    for reference in found {
+        let name = reference.strip_prefix("#/components/schemas/").unwrap();
+        assert!(spec["components"]["schemas"][name].is_object(), "{}", reference);
+    }
+}
+
+#[tokio::test]
+async fn test_http_refuses_body_files() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION notes").await;
+    std::fs::write(tmp.path().join(".mdby/secret.txt"), "secret").unwrap();
+    std::fs::write(tmp.path().join("draft.md"), "draft").unwrap();
+    let url = serve(db).await;
+
+    for file in ["draft.md", ".mdby/secret.txt", ".git/config"] {
+        let query = format!("INSERT INTO notes (id) VALUES ('leak') BODY FROM FILE '{}'", file);
+        let (status, _, body) = request_with("POST", format!("{}/query", url), None, Some(query)).await;
+        assert_eq!(status, 403, "{}", body);
+        assert!(body.contains("BODY FROM FILE is not allowed"), "{}", body);
+    }
+    let (status, _) = request("GET", format!("{}/collections/notes/leak", url), None).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn test_repl_completes_collection_and_field_names() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING, priority INT)").await;
+    let mut helper = mdby::repl::ReplHelper::new(&db).await;
+    assert_eq!(helper.candidates("SELECT * FROM to", 16).1, ["todos", "to"]);
+    assert_eq!(helper.candidates("SELECT pr", 9).1, ["priority"]);
+
+    exec(&mut db, "CREATE COLLECTION projects (owner STRING)").await;
+    assert!(helper.candidates("SELECT ow", 9).1.is_empty());
+    helper.refresh(&db).await;
+    assert_eq!(helper.candidates("SELECT ow", 9).1, ["owner"]);
+    assert_eq!(helper.candidates("SELECT * FROM pro", 17).1, ["projects"]);
+
+    assert!(!mdby::repl::is_complete("SELECT *\nFROM todos"));
+    assert!(mdby::repl::is_complete("SELECT *\nFROM todos;"));
+}
+
+#[test]
+fn test_repl_backslash_commands() {
+    use mdby::repl::{is_complete, MetaCommand};
+
+    assert_eq!(MetaCommand::parse("\\dt"), Ok(Some(MetaCommand::ListCollections)));
+    assert_eq!(MetaCommand::parse("\\d todos"), Ok(Some(MetaCommand::Describe("todos".into()))));
+    assert_eq!(MetaCommand::parse("\\timing on"), Ok(Some(MetaCommand::Timing(Some(true)))));
+    assert_eq!(MetaCommand::parse("\\format"), Ok(Some(MetaCommand::Format(None))));
+    assert_eq!(MetaCommand::parse("\\o results.txt"), Ok(Some(MetaCommand::Output(Some("results.txt".into())))));
+    assert_eq!(MetaCommand::parse("SELECT * FROM todos WHERE title = '\\d'"), Ok(None));
+    assert!(MetaCommand::parse("\\nope").is_err());
+
+    // Backslash commands run without a ';'
+    assert!(is_complete("\\d todos"));
+    assert!(is_complete("\\nope"));
+}
+
+#[tokio::test]
+async fn test_http_lists_documents_by_query_parameters() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos").await;
+    exec(
+        &mut db,
+        "INSERT INTO todos (id, done, priority) VALUES ('a', false, 1), ('b', true, 5), ('c', false, 3), ('d', false, 2)",
+    )
+    .await;
+    let url = serve(db).await;
+    let ids = |body: &str| -> Vec<String> {
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        json.as_array().unwrap().iter().map(|doc| doc["id"].as_str().unwrap().to_string()).collect()
+    };
+
+    let (status, body) =
+        request("GET", format!("{}/collections/todos?where=done%3Dfalse&order_by=priority&limit=2", url), None).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(ids(&body), ["a", "d"]);
+
+    let query = "where=done%20%3D%20false%20AND%20priority%20%3E%201&order_by=priority%20DESC&offset=1";
+    let (status, body) = request("GET", format!("{}/collections/todos?{}", url, query), None).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(ids(&body), ["d"]);
+
+    let (_, body) = request("GET", format!("{}/collections/todos", url), None).await;
+    assert_eq!(ids(&body).len(), 4);
+
+    // Only a condition is accepted, never another statement
+    let (status, body) =
+        request("GET", format!("{}/collections/todos?where=done%3Dfalse%3B%20DROP%20COLLECTION%20todos", url), None).await;
+    assert_eq!(status, 400);
+    assert!(body.contains("Invalid where"), "{}", body);
+    let (status, _) = request("GET", format!("{}/collections/todos?limit=ten", url), None).await;
+    assert_eq!(status, 400);
+    let (status, _) = request("GET", format!("{}/collections/missing", url), None).await;
+    assert_eq!(status, 404);
+
+    // A document may be called `documents` like any other
+    let doc = format!("{}/collections/todos/documents", url);
+    let (status, _, body) = request_with("PUT", doc.clone(), None, Some(r#"{"done": false}"#.to_string())).await;
+    assert_eq!(status, 200, "{}", body);
+    let (status, body) = request("GET", doc.clone(), None).await;
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap()["id"], "documents");
+    let (status, _) = request("PATCH", doc.clone(), Some(r#"{"done": true}"#)).await;
+    assert_eq!(status, 200);
+    let (status, _) = request("DELETE", doc.clone(), None).await;
+    assert_eq!(status, 200);
+    let (status, _) = request("GET", doc, None).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn test_http_writes_check_if_match() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, done BOOL)").await;
+    let url = serve(db).await;
+    let doc = format!("{}/collections/todos/a", url);
+
+    let (status, etag, body) = request_with("PUT", doc.clone(), None, Some(r#"{"title": "First", "_body": "Notes"}"#.into())).await;
+    assert_eq!(status, 200, "{}", body);
+    let first = etag.unwrap();
+    let (_, etag, body) = request_with("GET", doc.clone(), None, None).await;
+    assert_eq!(etag.as_deref(), Some(first.as_str()));
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap()["_body"], "Notes");
+
+    // Two clients read version `first`; the second one to write loses
+    let (status, etag, body) = request_with("PATCH", doc.clone(), Some(first.clone()), Some(r#"{"done": true}"#.into())).await;
+    assert_eq!(status, 200, "{}", body);
+    let second = etag.unwrap();
+    assert_ne!(second, first);
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap()["done"], true);
+    let (status, _, body) = request_with("PATCH", doc.clone(), Some(first.clone()), Some(r#"{"title": "Clobbered"}"#.into())).await;
+    assert_eq!(status, 412, "{}", body);
+    let (status, _, _) = request_with("DELETE", doc.clone(), Some(first.clone()), None).await;
+    assert_eq!(status, 412);
+    let content = std::fs::read_to_string(tmp.path().join("collections/todos/a.md")).unwrap();
+    assert!(content.contains("title: First"), "{}", content);
+
+    // Without If-Match, or with the current version or `*`, writes go through
+    let (status, _, _) = request_with("PUT", doc.clone(), Some(format!("{}, {}", first, second)), Some(r#"{"title": "Renamed"}"#.into())).await;
+    assert_eq!(status, 200);
+    let (status, _, _) = request_with("PATCH", doc.clone(), Some("*".into()), Some(r#"{"done": false}"#.into())).await;
+    assert_eq!(status, 200);
+    let (status, _, body) = request_with("DELETE", doc.clone(), None, None).await;
+    assert_eq!((status, body.as_str()), (200, r#"{"affected":1}"#));
+    assert!(!tmp.path().join("collections/todos/a.md").exists());
+
+    let (status, _, _) = request_with("PATCH", doc.clone(), None, Some(r#"{"done": true}"#.into())).await;
+    assert_eq!(status, 404);
+    let (status, _, _) = request_with("PUT", doc.clone(), Some("*".into()), Some(r#"{"title": "Back"}"#.into())).await;
+    assert_eq!(status, 412);
+    let (status, _, _) = request_with("PUT", doc, None, Some(r#"{"title": "Back", "meta": {"a": 1}}"#.into())).await;
+    assert_eq!(status, 400);
+}
+
+#[tokio::test]
+async fn test_http_batch_is_all_or_nothing() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED)").await;
+    let url = serve(db).await;
+    let commits = || {
+        let output = std::process::Command::new("git")
+            .args(["rev-list", "--count", "HEAD"])
+            .current_dir(tmp.path())
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stdout).trim().parse::<usize>().unwrap()
+    };
+    let before = commits();
+
+    let body = r#"["INSERT INTO todos (id, title) VALUES ('a', 'First')", "UPDATE todos SET done = true WHERE id = 'a'", "SELECT id FROM todos"]"#;
+    let (status, body) = request("POST", format!("{}/batch", url), Some(body)).await;
+    assert_eq!(status, 200, "{}", body);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["succeeded"], 3);
+    assert_eq!(json["statements"][1]["index"], 2);
+    assert_eq!(json["statements"][1]["kind"], "affected");
+    assert_eq!(json["statements"][1]["result"], serde_json::json!({"affected": 1}));
+    assert_eq!(json["statements"][2]["result"], serde_json::json!([{"id": "a"}]));
+    assert_eq!(commits(), before + 1);
+
+    // The second statement fails, so the first isn't kept either
+    let body = r#"{"statements": ["DELETE FROM todos WHERE id = 'a'", "INSERT INTO todos (id) VALUES ('b')", "SELECT * FROM todos"]}"#;
+    let (status, body) = request("POST", format!("{}/batch", url), Some(body)).await;
+    assert_eq!(status, 400, "{}", body);
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["statements"].as_array().unwrap().len(), 2);
+    assert!(json["statements"][1]["error"].is_string());
+    assert!(json["error"].as_str().unwrap().starts_with("Statement 2 failed"), "{}", json["error"]);
+    assert!(tmp.path().join("collections/todos/a.md").exists());
+    assert_eq!(commits(), before + 1);
+
+    let (status, _) = request("POST", format!("{}/batch", url), Some(r#"["SELEC nonsense"]"#)).await;
+    assert_eq!(status, 400);
+    let (status, _) = request("POST", format!("{}/batch", url), Some(r#"{"query": "SELECT * FROM todos"}"#)).await;
+    assert_eq!(status, 400);
+
+    // A page on another site can post text without a preflight, but not JSON
+    let delete = Some(r#"["DELETE FROM todos"]"#);
+    let (status, _, body) = request_headers("POST", format!("{}/batch", url), &[("Content-Type", "text/plain")], delete).await;
+    assert_eq!(status, 415, "{}", body);
+    assert!(tmp.path().join("collections/todos/a.md").exists());
+    let json = &[("Content-Type", "application/json")];
+    let (status, _, body) = request_headers("POST", format!("{}/batch", url), json, delete).await;
+    assert_eq!(status, 200, "{}", body);
+    assert!(!tmp.path().join("collections/todos/a.md").exists());
+}
+
+#[tokio::test]
+async fn test_client_runs_statements_on_a_server() {
+    use mdby_client::{Client, Error, QueryResult};
+
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, done BOOL DEFAULT false)").await;
+    let client = Client::new(serve(db).await);
+
+    let result = client.execute("INSERT INTO todos (id, title) VALUES ('a', 'First')").await.unwrap();
+    assert!(matches!(result, QueryResult::Affected(1)), "{:?}", result);
+    let result = client.execute("SELECT * FROM todos").await.unwrap();
+    let QueryResult::Documents(docs) = result else { panic!("{:?}", result) };
+    assert_eq!((docs[0].id.as_str(), docs[0].get("title")), ("a", Some(&serde_json::json!("First"))));
+    // An empty list is still told apart from documents
+    let result = client.execute("SHOW VIEWS").await.unwrap();
+    assert!(matches!(&result, QueryResult::Views(views) if views.is_empty()), "{:?}", result);
+    let result = client.execute("SHOW CREATE COLLECTION todos").await.unwrap();
+    assert!(matches!(&result, QueryResult::Definition(mdql) if mdql.starts_with("CREATE COLLECTION todos")), "{:?}", result);
+    let result = client.execute("DESCRIBE todos").await.unwrap();
+    assert!(matches!(&result, QueryResult::Description(json) if json["documents"] == 1), "{:?}", result);
+
+    // Errors carry the server's status and hint
+    let e = client.execute("SELECT * FROM nope").await.unwrap_err();
+    assert!(matches!(e.downcast_ref::<Error>(), Some(Error::Server { status: 400..=499, .. })), "{:?}", e);
+
+    // Batches and scripts are one transaction; a failure lists the outcomes
+    let statements = vec!["UPDATE todos SET done = true WHERE id = 'a'".to_string(), "SELECT id FROM todos".to_string()];
+    let batch = client.execute_batch(&statements).await.unwrap();
+    assert_eq!((batch.succeeded(), batch.affected()), (2, 1));
+    let batch = client
+        .execute_script("DELETE FROM todos WHERE id = 'a';\nINSERT INTO todos (id) VALUES ('b');")
+        .await
+        .unwrap();
+    assert_eq!((batch.succeeded(), batch.failed()), (1, 1));
+    assert_eq!(batch.statements[1].line, 2);
+    assert!(tmp.path().join("collections/todos/a.md").exists());
+    assert!(client.execute_script("SELEC nonsense;").await.is_err());
+
+    let e = Client::new("http://127.0.0.1:9").execute("SHOW COLLECTIONS").await.unwrap_err();
+    assert!(matches!(e.downcast_ref::<Error>(), Some(Error::Unreachable { .. })), "{:?}", e);
+}
+
+#[tokio::test]
+async fn test_cli_runs_statements_on_a_server_with_url() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, done BOOL DEFAULT false)").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('a', 'First')").await;
+    let url = serve(db).await;
+    // The CLI runs somewhere that isn't a database
+    let elsewhere = TempDir::new().unwrap();
+    let cwd = elsewhere.path().to_path_buf();
+    let mdby = |args: Vec<&str>, stdin: &str| {
+        let (args, stdin, cwd) = (
+            args.into_iter().map(str::to_string).collect::<Vec<_>>(),
+            stdin.to_string(),
+            cwd.clone(),
+        );
+        tokio::task::spawn_blocking(move || {
+            use std::io::Write;
+            let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_mdby"))
+                .args(&args)
+                .current_dir(cwd)
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped())
+                .spawn()
+                .unwrap();
+            child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+            let output = child.wait_with_output().unwrap();
+            let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+            (output.status.success(), text(&output.stdout), text(&output.stderr))
+        })
+    };
+
+    let (ok, stdout, stderr) = mdby(vec!["--url", &url, "--format", "json", "query", "SELECT title FROM todos"], "").await.unwrap();
+    assert!(ok, "{}", stderr);
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(json, serde_json::json!([{"id": "a", "title": "First"}]));
+    let (ok, stdout, _) = mdby(vec!["query", "DESCRIBE todos", "--url", &url], "").await.unwrap();
+    assert!(ok && stdout.contains("Documents:  1") && stdout.contains("title"), "{}", stdout);
+    let (ok, stdout, _) = mdby(vec!["--url", &url, "query", "EXPLAIN SELECT * FROM todos LIMIT 1"], "").await.unwrap();
+    assert!(ok && stdout.contains("Limit:      1"), "{}", stdout);
+
+    // Scripts are one transaction on the server
+    let (ok, _, stderr) = mdby(vec!["--url", &url, "exec", "-"], "UPDATE todos SET done = true;\nINSERT INTO todos (id) VALUES ('b');").await.unwrap();
+    assert!(!ok && stderr.contains("line 2"), "{}", stderr);
+    assert!(!tmp.path().join("collections/todos/b.md").exists());
+    let (ok, stdout, _) = mdby(vec!["--url", &url, "--format", "minimal", "query", "SELECT COUNT(*) FROM todos WHERE done = true"], "").await.unwrap();
+    assert!(ok && stdout.trim() == "0", "{}", stdout);
+
+    // Removals still need --yes without a terminal
+    let (ok, _, stderr) = mdby(vec!["--url", &url, "query", "DELETE FROM todos"], "").await.unwrap();
+    assert!(!ok && stderr.contains("--yes"), "{}", stderr);
+    assert!(tmp.path().join("collections/todos/a.md").exists());
+
+    // The REPL reads statements from standard input
+    let (ok, stdout, stderr) = mdby(vec!["--url", &url, "repl"], "SELECT title\nFROM todos;\nSELECT * FROM nope;\n").await.unwrap();
+    assert!(ok && stdout.contains(&url) && stdout.contains("First"), "{}", stdout);
+    assert!(stderr.contains("nope"), "{}", stderr);
+
+    // Other commands need the database directory
+    let (ok, _, stderr) = mdby(vec!["--url", &url, "status"], "").await.unwrap();
+    assert!(!ok && stderr.contains("--url"), "{}", stderr);
+    let (ok, _, stderr) = mdby(vec!["--url", "http://127.0.0.1:9", "query", "SHOW COLLECTIONS"], "").await.unwrap();
+    assert!(!ok && stderr.contains("Hint:"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn test_journal_rolls_back_interrupted_statements() {
+    use mdby::storage::journal;
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION users").await;
+    exec(&mut db, "CREATE COLLECTION tickets (title STRING, owner REF<users>)").await;
+    let path = tmp.path().join(".mdby/schemas/tickets.yaml");
+    let schema = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, schema.replace("id_strategy: manual", "id_strategy: auto_increment")).unwrap();
+    let mut db = Database::open(tmp.path()).await.unwrap();
+    exec(&mut db, "INSERT INTO tickets (title) VALUES ('First')").await;
+
+    // A failing statement leaves nothing behind, not even the counter it bumped
+    assert!(db.execute("INSERT INTO tickets (title, owner) VALUES ('Second', 'nobody')").await.is_err());
+    let counter = std::fs::read_to_string(tmp.path().join(".mdby/counters/tickets")).unwrap();
+    assert_eq!(counter.trim(), "1");
+    assert!(!db.git.has_changes().unwrap());
+
+    // A crash between writing and committing is undone on the next open
+    let head = db.git.head_hash().ok();
+    journal::begin(tmp.path(), "UPDATE tickets SET title = 'Changed'", head.clone()).unwrap();
+    let doc = tmp.path().join("collections/tickets/1.md");
+    let original = std::fs::read_to_string(&doc).unwrap();
+    journal::preserve(tmp.path(), &doc).unwrap();
+    std::fs::write(&doc, original.replace("First", "Changed")).unwrap();
+    let new_doc = tmp.path().join("collections/tickets/2.md");
+    journal::preserve(tmp.path(), &new_doc).unwrap();
+    std::fs::write(&new_doc, "---\ntitle: Half\n---\n").unwrap();
+    drop(db);
+
+    let db = Database::open(tmp.path()).await.unwrap();
+    assert_eq!(std::fs::read_to_string(&doc).unwrap(), original);
+    assert!(!new_doc.exists());
+    assert!(!db.git.has_changes().unwrap());
+
+    // One that committed before the crash is kept
+    journal::begin(tmp.path(), "UPDATE tickets SET title = 'Changed'", db.git.head_hash().ok()).unwrap();
+    journal::preserve(tmp.path(), &doc).unwrap();
+    std::fs::write(&doc, original.replace("First", "Changed")).unwrap();
+    db.git.commit("UPDATE tickets: 1").unwrap();
+    drop(db);
+    let mut db = Database::open(tmp.path()).await.unwrap();
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM tickets").await else { panic!() };
+    assert_eq!(docs[0].get("title").and_then(|v| v.as_str()), Some("Changed"));
+    assert!(!journal::journal_dir(tmp.path()).join("pending.json").exists());
+}
+
+#[tokio::test]
+async fn test_document_writes_leave_no_temporary_files() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING)").await;
+    exec(&mut db, "INSERT INTO todos (id, title) VALUES ('a', 'A'), ('b', 'B')").await;
+    exec(&mut db, "UPDATE todos SET title = 'Changed' WHERE @id = 'a'").await;
+    exec(&mut db, "DELETE FROM todos WHERE @id = 'b'").await;
+
+    let files: Vec<String> = std::fs::read_dir(tmp.path().join("collections/todos"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(files, vec!["a.md"]);
+    assert!(!db.git.has_changes().unwrap());
+    let content = std::fs::read_to_string(tmp.path().join("collections/todos/a.md")).unwrap();
+    assert!(content.contains("title: Changed"));
+}
+
+#[tokio::test]
+async fn test_derived_ids_use_slug_rules() {
+    use mdby::slug::{slugify_with, SlugOptions};
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION posts (title STRING)").await;
+    let path = tmp.path().join(".mdby/schemas/posts.yaml");
+    let schema = std::fs::read_to_string(&path).unwrap();
+    let strategy = "id_strategy:\n  derived:\n    from: title\n    transform: slug\n    max_length: 16";
+    std::fs::write(&path, schema.replace("id_strategy: manual", strategy)).unwrap();
+    let mut db = Database::open(tmp.path()).await.unwrap();
+
+    exec(&mut db, "INSERT INTO posts (title) VALUES ('Crème brûlée recipes for beginners')").await;
+    exec(&mut db, "INSERT INTO posts (title) VALUES ('Crème brûlée recipes, again')").await;
+    exec(&mut db, "INSERT INTO posts (title) VALUES ('北京'), ('北京')").await;
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM posts ORDER BY @id").await else { panic!() };
+    let ids: Vec<&str> = docs.iter().map(|doc| doc.id.as_str()).collect();
+    assert_eq!(ids, vec!["bei-jing", "bei-jing-2", "creme-brulee", "creme-brulee-2"]);
+
+    // The same rules are available to applications
+    let options = SlugOptions::default().with_max_length(Some(16));
+    assert_eq!(slugify_with("Crème brûlée recipes for beginners", &options), "creme-brulee");
+}
+
+#[tokio::test]
+async fn test_noop_writes_are_skipped() {
+    let (_tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING, status STRING)").await;
+    exec(&mut db, "INSERT INTO todos (id, title, status) VALUES ('a', 'A', 'open'), ('b', 'B', 'done')").await;
+    let commits = db.git.log(100).unwrap().len();
+
+    // Nothing changes: no write, no commit
+    let result = exec(&mut db, "UPDATE todos SET status = 'open' WHERE @id = 'a'").await;
+    assert!(matches!(result, QueryResult::Affected(0)));
+    assert_eq!(db.git.log(100).unwrap().len(), commits);
+
+    // Only the documents that change are counted and committed
+    let result = exec(&mut db, "UPDATE todos SET status = 'open'").await;
+    assert!(matches!(result, QueryResult::Affected(1)));
+    assert_eq!(db.git.log(100).unwrap().len(), commits + 1);
+    assert_eq!(db.git.log(1).unwrap()[0].summary, "UPDATE todos: 1 document(s)");
+
+    // The same for upserts that match what's there
+    let result = exec(&mut db, "UPSERT INTO todos (id, title) VALUES ('a', 'A')").await;
+    assert!(matches!(result, QueryResult::Affected(0)));
+    let result = exec(&mut db, "UPSERT INTO todos (id, title) VALUES ('a', 'A'), ('c', 'C')").await;
+    assert!(matches!(result, QueryResult::Affected(1)));
+    assert_eq!(db.git.log(1).unwrap()[0].summary, "INSERT into todos: c");
+    assert!(!db.git.has_changes().unwrap());
+}
+
+#[tokio::test]
+async fn test_memory_store() {
+    use mdby::storage::store::MemoryStore;
+
+    let (tmp, mut db) = setup_test_db().await;
+    db.set_store(MemoryStore::new());
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, priority INT INDEXED, slug STRING UNIQUE)").await;
+    exec(
+        &mut db,
+        "INSERT INTO todos (id, title, priority, slug) VALUES ('a', 'Write docs', 2, 'docs'), ('b', 'Fix bug', 1, 'bug')",
+    )
+    .await;
+
+    // The executor reads and writes the store, the index included
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM todos WHERE priority < 3 ORDER BY priority").await else {
+        panic!("Expected documents");
+    };
+    let ids: Vec<&str> = docs.iter().map(|doc| doc.id.as_str()).collect();
+    assert_eq!(ids, ["b", "a"]);
+    assert!(db.execute("INSERT INTO todos (id, title, slug) VALUES ('c', 'Again', 'bug')").await.is_err());
+    assert!(db.execute("INSERT INTO todos (id, title) VALUES ('a', 'Twice')").await.is_err());
+
+    let result = exec(&mut db, "UPDATE todos SET priority = 5 WHERE @id = 'a'").await;
+    assert!(matches!(result, QueryResult::Affected(1)));
+    let result = exec(&mut db, "DELETE FROM todos WHERE priority = 1").await;
+    assert!(matches!(result, QueryResult::Affected(1)));
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM todos").await else {
+        panic!("Expected documents");
+    };
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].fields["priority"], mdby::storage::document::Value::Int(5));
+
+    let QueryResult::Collections(names) = exec(&mut db, "SHOW COLLECTIONS").await else {
+        panic!("Expected collections");
+    };
+    assert_eq!(names, ["todos"]);
+
+    // Nothing was written as markdown; the schema still is
+    assert!(!tmp.path().join("collections").join("todos").exists());
+    assert!(tmp.path().join(".mdby").join("schemas").join("todos.yaml").exists());
+}
+
+#[tokio::test]
+async fn test_template_inheritance_and_management() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING, done BOOL)").await;
+    exec(&mut db, "INSERT INTO todos (id, title, done) VALUES ('a', 'Write docs', false), ('b', 'Fix bug', true)").await;
+
+    db.add_template("base.html", "<h1>{% block title %}{% endblock %}</h1>\n{% block content %}{% endblock %}", false).unwrap();
+    db.add_template("partials/item.html", "<li>{{ doc.title }}</li>", false).unwrap();
+    let list = r#"{% extends "base.html" %}{% block title %}{{ count }} open{% endblock %}
+{% block content %}{% for doc in documents %}{% include "partials/item.html" %}{% endfor %}{% endblock %}"#;
+    db.add_template("list.html", list, false).unwrap();
+    assert_eq!(db.git.log(1).unwrap()[0].summary, "ADD TEMPLATE list.html");
+
+    // Names are checked, templates aren't silently replaced and must load with the rest
+    assert!(db.add_template("list.html", list, false).is_err());
+    assert!(db.add_template("../escape.html", "x", false).is_err());
+    let orphan = db.add_template("orphan.html", r#"{% extends "missing.html" %}"#, false).unwrap_err();
+    assert!(orphan.to_string().contains("missing.html"));
+    assert!(!tmp.path().join(".mdby/templates/orphan.html").exists());
+
+    exec(&mut db, "CREATE VIEW open AS SELECT * FROM todos WHERE done = false TEMPLATE 'list.html'").await;
+    db.regenerate_views().await.unwrap();
+    let html = std::fs::read_to_string(tmp.path().join("views/open/index.html")).unwrap();
+    assert_eq!(html.trim(), "<h1>1 open</h1>\n<li>Write docs</li>");
+
+    // Previews render live results without writing anything
+    let preview = db.preview_template("list.html", "SELECT * FROM todos ORDER BY title").await.unwrap();
+    assert_eq!(preview.trim(), "<h1>2 open</h1>\n<li>Fix bug</li><li>Write docs</li>");
+    assert!(db.preview_template("list.html", "DELETE FROM todos").await.is_err());
+
+    let templates = db.templates().unwrap();
+    let names: Vec<&str> = templates.iter().map(|template| template.name.as_str()).collect();
+    assert_eq!(names, ["base.html", "list.html", "partials/item.html"]);
+    assert_eq!(templates[0].used_by, ["list.html"]);
+    assert_eq!(templates[1].extends.as_deref(), Some("base.html"));
+    assert_eq!(templates[1].includes, ["partials/item.html"]);
+    assert_eq!(templates[1].views, ["open"]);
+
+    // Templates in use stay
+    let in_use = db.remove_template("base.html").unwrap_err();
+    assert!(in_use.to_string().contains("list.html"));
+    assert!(db.remove_template("list.html").unwrap_err().to_string().contains("open"));
+    exec(&mut db, "DROP VIEW open").await;
+    db.remove_template("list.html").unwrap();
+    db.remove_template("base.html").unwrap();
+    assert!(!tmp.path().join(".mdby/templates/base.html").exists());
+    assert!(db.remove_template("base.html").is_err());
+}
+
+#[tokio::test]
+async fn test_template_filters() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION posts (title STRING, published DATE)").await;
+    exec(
+        &mut db,
+        "INSERT INTO posts (id, title, published) VALUES ('hello', 'Hello, Wörld', '2024-03-18') \
+         BODY 'First **post** with a few words in it.'",
+    )
+    .await;
+    db.add_template(
+        "blog.html",
+        r#"{% for doc in documents %}<a href="{{ doc.title | slugify }}.html">{{ doc.published | date(format="%B %-d, %Y") }}</a> {{ doc.body | excerpt(words=3) }} ({{ doc.body | wordcount }}){% endfor %}"#,
+        false,
+    )
+    .unwrap();
+    exec(&mut db, "CREATE VIEW blog AS SELECT * FROM posts TEMPLATE 'blog.html'").await;
+    db.regenerate_views().await.unwrap();
+
+    let html = std::fs::read_to_string(tmp.path().join("views/blog/index.html")).unwrap();
+    assert_eq!(html.trim(), r#"<a href="hello-world.html">March 18, 2024</a> First post with… (8)"#);
+}
+
+/// A gzipped tar of a directory's files, under a top-level `name/`
+fn tar_gz(dir: &std::path::Path, name: &str) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut tar = Vec::new();
+    for entry in walkdir::WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry.unwrap();
+        let relative = entry.path().strip_prefix(dir).unwrap().to_string_lossy().to_string();
+        if !entry.file_type().is_file() || relative.starts_with(".git") {
+            continue;
+        }
+        let content = std::fs::read(entry.path()).unwrap();
+        let path = format!("{}/{}", name, relative);
+        let mut header = [0u8; 512];
+        header[..path.len()].copy_from_slice(path.as_bytes());
+        header[124..135].copy_from_slice(format!("{:011o}", content.len()).as_bytes());
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        tar.extend_from_slice(&header);
+        tar.extend_from_slice(&content);
+        tar.resize(tar.len().div_ceil(512) * 512, 0);
+    }
+    tar.resize(tar.len() + 1024, 0);
+
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(&tar).unwrap();
+    gz.finish().unwrap()
+}
+
+#[tokio::test]
+async fn test_archive_database() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION todos (title STRING REQUIRED, priority INT)").await;
+    exec(
+        &mut db,
+        "INSERT INTO todos (id, title, priority) VALUES ('a', 'Write docs', 2), ('b', 'Fix bug', 1), ('c', 'Ship', 3)",
+    )
+    .await;
+
+    let backups = TempDir::new().unwrap();
+    let archive = backups.path().join("backup.tgz");
+    std::fs::write(&archive, tar_gz(tmp.path(), "notes")).unwrap();
+
+    // Opening a file opens the archive, read-only
+    let mut backup = Database::open(&archive).await.unwrap();
+    assert!(backup.is_read_only());
+    let QueryResult::Documents(docs) = exec(&mut backup, "SELECT * FROM todos WHERE priority < 3 ORDER BY priority").await
+    else {
+        panic!("Expected documents");
+    };
+    let ids: Vec<&str> = docs.iter().map(|doc| doc.id.as_str()).collect();
+    assert_eq!(ids, ["b", "a"]);
+    let QueryResult::Collections(names) = exec(&mut backup, "SHOW COLLECTIONS").await else {
+        panic!("Expected collections");
+    };
+    assert_eq!(names, ["todos"]);
+
+    // The schema came from the archive's .mdby/
+    let QueryResult::Description(description) = exec(&mut backup, "DESCRIBE COLLECTION todos").await else {
+        panic!("Expected a description");
+    };
+    assert_eq!(description.documents, 3);
+    assert_eq!(description.fields.len(), 2);
+    let err = backup.execute("INSERT INTO todos (id, title) VALUES ('d', 'Nope')").await.unwrap_err();
+    assert!(err.to_string().contains("read-only"));
+
+    // Later changes to the database aren't in the backup
+    exec(&mut db, "DELETE FROM todos WHERE @id = 'c'").await;
+    let mut backup = Database::open(&archive).await.unwrap();
+    let QueryResult::Documents(docs) = exec(&mut backup, "SELECT * FROM todos").await else {
+        panic!("Expected documents");
+    };
+    assert_eq!(docs.len(), 3);
+
+    std::fs::write(backups.path().join("notes.txt"), "not an archive").unwrap();
+    assert!(Database::open(backups.path().join("notes.txt")).await.is_err());
+}
+
+#[tokio::test]
+async fn test_compressed_bodies() {
+    let (tmp, mut db) = setup_test_db().await;
+    exec(&mut db, "CREATE COLLECTION notes (title STRING)").await;
+    let mut schema = mdby::Schema::new("notes");
+    schema.compress_bodies_over = Some(1024);
+    db.save_schema(schema).unwrap();
+
+    let long = "All work and no play. ".repeat(200);
+    exec(&mut db, &format!("INSERT INTO notes (id, title) VALUES ('long', 'Long') BODY '{}'", long)).await;
+    exec(&mut db, "INSERT INTO notes (id, title) VALUES ('short', 'Short') BODY 'Brief'").await;
+
+    // The long body is gzipped beside plain frontmatter; the short one isn't
+    let dir = tmp.path().join("collections/notes");
+    let frontmatter = std::fs::read_to_string(dir.join("long.md")).unwrap();
+    assert!(frontmatter.contains("title: Long"));
+    assert!(!frontmatter.contains("All work"));
+    assert!(std::fs::metadata(dir.join("long.md.gz")).unwrap().len() < 1024);
+    assert!(!dir.join("short.md.gz").exists());
+
+    // Reads put the body back, from the working tree and from a commit
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM notes WHERE @id = 'long'").await else {
+        panic!("Expected documents");
+    };
+    assert_eq!(docs[0].body.trim(), long.trim());
+    let head = db.git.head_hash().unwrap();
+    let QueryResult::Documents(docs) = exec(&mut db, &format!("SELECT * FROM notes AS OF '{}' WHERE @id = 'long'", head)).await
+    else {
+        panic!("Expected documents");
+    };
+    assert_eq!(docs[0].body.trim(), long.trim());
+
+    // Updating fields keeps the body; a body under the limit is stored plainly again
+    exec(&mut db, "UPDATE notes SET title = 'Longer' WHERE @id = 'long'").await;
+    assert!(dir.join("long.md.gz").exists());
+    let QueryResult::Documents(docs) = exec(&mut db, "SELECT * FROM notes WHERE title = 'Longer'").await else {
+        panic!("Expected documents");
+    };
+    assert_eq!(docs[0].body.trim(), long.trim());
+    exec(&mut db, "UPSERT INTO notes (id) VALUES ('long') BODY 'Cut'").await;
+    assert!(!dir.join("long.md.gz").exists());
+    assert!(std::fs::read_to_string(dir.join("long.md")).unwrap().contains("Cut"));
+
+    exec(&mut db, &format!("UPSERT INTO notes (id) VALUES ('short') BODY '{}'", long)).await;
+    assert!(dir.join("short.md.gz").exists());
+    exec(&mut db, "DELETE FROM notes WHERE @id = 'short'").await;
+    assert!(!dir.join("short.md").exists());
+    assert!(!dir.join("short.md.gz").exists());
+    assert!(!db.git.has_changes().unwrap());
 }